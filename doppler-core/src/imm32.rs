@@ -0,0 +1,314 @@
+//! The imm32 segment test.
+//!
+//! A segment is imm32 compatible when bytes 4-7 are the sign extension of
+//! bytes 0-3. Read as a little-endian u64 `v`, that is exactly when adding
+//! 2^31 leaves the upper 32 bits clear, which lets the `simd` features test
+//! all four segments of a key with a handful of vector instructions.
+
+/// Chance that a uniformly random key has at least one imm32 compatible
+/// segment: each of the four segments matches with probability 2^-32.
+pub const MATCH_PROBABILITY: f64 = 4.0 / 4_294_967_296.0;
+
+/// Returns true if the given 8-byte segment of the public key forms a valid
+/// 32-bit immediate, i.e. bytes 4-7 are the sign extension of bytes 0-3.
+pub fn segment_is_imm32_compatible(pubkey_bytes: &[u8; 32], segment: usize) -> bool {
+    let offset = segment * 8;
+    let byte3 = pubkey_bytes[offset + 3];
+
+    if byte3 & 0x80 != 0 {
+        // Bit 31 is set - negative i32, bytes 4-7 of segment must be 0xFF
+        pubkey_bytes[offset + 4] == 0xFF &&
+        pubkey_bytes[offset + 5] == 0xFF &&
+        pubkey_bytes[offset + 6] == 0xFF &&
+        pubkey_bytes[offset + 7] == 0xFF
+    } else {
+        // Bit 31 is clear - positive i32, bytes 4-7 of segment must be 0x00
+        pubkey_bytes[offset + 4] == 0x00 &&
+        pubkey_bytes[offset + 5] == 0x00 &&
+        pubkey_bytes[offset + 6] == 0x00 &&
+        pubkey_bytes[offset + 7] == 0x00
+    }
+}
+
+/// Returns the lowest segment set in an imm32 mask.
+pub fn first_segment(mask: u8) -> Option<usize> {
+    (mask != 0).then(|| mask.trailing_zeros() as usize)
+}
+
+/// Computes a mask for every public key in `pubkeys`, with bit `n` set if
+/// segment `n` forms a valid 32-bit immediate, writing the results to the
+/// matching entries of `masks`.
+#[allow(unreachable_code)]
+pub fn imm32_masks(pubkeys: &[[u8; 32]], masks: &mut [u8]) {
+    assert_eq!(pubkeys.len(), masks.len(), "one mask is needed per public key");
+
+    #[cfg(all(feature = "simd-avx512", feature = "std", target_arch = "x86_64"))]
+    if std::arch::is_x86_feature_detected!("avx512f") && std::arch::is_x86_feature_detected!("avx512vl") {
+        // SAFETY: AVX-512F and AVX-512VL support was detected at runtime
+        unsafe { avx512::imm32_masks(pubkeys, masks) };
+        return;
+    }
+
+    #[cfg(all(
+        feature = "simd-avx512",
+        not(feature = "std"),
+        target_arch = "x86_64",
+        target_feature = "avx512f",
+        target_feature = "avx512vl"
+    ))]
+    {
+        // SAFETY: AVX-512F and AVX-512VL are enabled for the whole build
+        unsafe { avx512::imm32_masks(pubkeys, masks) };
+        return;
+    }
+
+    #[cfg(all(feature = "simd", feature = "std", target_arch = "x86_64"))]
+    if std::arch::is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 support was detected at runtime
+        unsafe { avx2::imm32_masks(pubkeys, masks) };
+        return;
+    }
+
+    // Without std there is no runtime detection, so AVX2 has to be enabled
+    // at compile time
+    #[cfg(all(feature = "simd", not(feature = "std"), target_arch = "x86_64", target_feature = "avx2"))]
+    {
+        // SAFETY: AVX2 is enabled for the whole build
+        unsafe { avx2::imm32_masks(pubkeys, masks) };
+        return;
+    }
+
+    #[cfg(all(feature = "simd-neon", target_arch = "aarch64"))]
+    {
+        // SAFETY: NEON is part of the aarch64 baseline
+        unsafe { neon::imm32_masks(pubkeys, masks) };
+        return;
+    }
+
+    scalar_imm32_masks(pubkeys, masks);
+}
+
+fn scalar_imm32_masks(pubkeys: &[[u8; 32]], masks: &mut [u8]) {
+    for (pubkey_bytes, mask) in pubkeys.iter().zip(masks.iter_mut()) {
+        *mask = (0..4)
+            .filter(|&segment| segment_is_imm32_compatible(pubkey_bytes, segment))
+            .fold(0, |mask, segment| mask | (1 << segment));
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", any(feature = "std", target_feature = "avx2")))]
+mod avx2 {
+    use core::arch::x86_64::*;
+
+    /// Tests all four segments of a key in one 256-bit register, two keys per
+    /// loop iteration.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn imm32_masks(pubkeys: &[[u8; 32]], masks: &mut [u8]) {
+        let bias = _mm256_set1_epi64x(0x8000_0000);
+        let zero = _mm256_setzero_si256();
+
+        let mask_of = |pubkey: &[u8; 32]| {
+            // SAFETY: the key is 32 bytes and the load is unaligned
+            let lanes = unsafe { _mm256_loadu_si256(pubkey.as_ptr().cast()) };
+            let high = _mm256_srli_epi64::<32>(_mm256_add_epi64(lanes, bias));
+            let compatible = _mm256_cmpeq_epi64(high, zero);
+            _mm256_movemask_pd(_mm256_castsi256_pd(compatible)) as u8
+        };
+
+        let mut pairs = pubkeys.chunks_exact(2);
+        let mut mask_pairs = masks.chunks_exact_mut(2);
+        for (pair, mask_pair) in (&mut pairs).zip(&mut mask_pairs) {
+            mask_pair[0] = mask_of(&pair[0]);
+            mask_pair[1] = mask_of(&pair[1]);
+        }
+        for (pubkey, mask) in pairs.remainder().iter().zip(mask_pairs.into_remainder()) {
+            *mask = mask_of(pubkey);
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "simd-avx512",
+    target_arch = "x86_64",
+    any(feature = "std", all(target_feature = "avx512f", target_feature = "avx512vl"))
+))]
+mod avx512 {
+    use core::arch::x86_64::*;
+
+    /// Tests two keys per 512-bit register. The biased lanes are tested
+    /// straight into a mask register, so there is no shift, compare or
+    /// movemask as in the AVX2 kernel.
+    #[target_feature(enable = "avx512f,avx512vl")]
+    pub unsafe fn imm32_masks(pubkeys: &[[u8; 32]], masks: &mut [u8]) {
+        let mut pairs = pubkeys.chunks_exact(2);
+        let mut mask_pairs = masks.chunks_exact_mut(2);
+        for (pair, mask_pair) in (&mut pairs).zip(&mut mask_pairs) {
+            // SAFETY: the pair is 64 contiguous bytes and the load is unaligned
+            let lanes = unsafe { _mm512_loadu_si512(pair.as_ptr().cast()) };
+            let biased = _mm512_add_epi64(lanes, _mm512_set1_epi64(0x8000_0000));
+            let compatible = _mm512_testn_epi64_mask(biased, _mm512_set1_epi64(0xFFFF_FFFF_0000_0000_u64 as i64));
+            mask_pair[0] = compatible & 0x0F;
+            mask_pair[1] = compatible >> 4;
+        }
+        for (pubkey, mask) in pairs.remainder().iter().zip(mask_pairs.into_remainder()) {
+            // SAFETY: the key is 32 bytes and the load is unaligned
+            let lanes = unsafe { _mm256_loadu_si256(pubkey.as_ptr().cast()) };
+            let biased = _mm256_add_epi64(lanes, _mm256_set1_epi64x(0x8000_0000));
+            *mask = _mm256_testn_epi64_mask(biased, _mm256_set1_epi64x(0xFFFF_FFFF_0000_0000_u64 as i64));
+        }
+    }
+}
+
+#[cfg(all(feature = "simd-neon", target_arch = "aarch64"))]
+mod neon {
+    use core::arch::aarch64::*;
+
+    /// Tests two segments per 128-bit register, so one key per two registers.
+    /// The upper halves of all four segments are narrowed into one register
+    /// so the mask comes out of a single horizontal add.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn imm32_masks(pubkeys: &[[u8; 32]], masks: &mut [u8]) {
+        let bias = vdupq_n_u64(0x8000_0000);
+        let segment_bits: [u32; 4] = [1, 2, 4, 8];
+        // SAFETY: the array holds four u32s
+        let segment_bits = unsafe { vld1q_u32(segment_bits.as_ptr()) };
+
+        for (pubkey, mask) in pubkeys.iter().zip(masks.iter_mut()) {
+            // SAFETY: the key is 32 bytes, read as two unaligned 16-byte halves
+            let (low, high) = unsafe {
+                (
+                    vreinterpretq_u64_u8(vld1q_u8(pubkey.as_ptr())),
+                    vreinterpretq_u64_u8(vld1q_u8(pubkey.as_ptr().add(16))),
+                )
+            };
+            let upper_halves = vcombine_u32(
+                vshrn_n_u64::<32>(vaddq_u64(low, bias)),
+                vshrn_n_u64::<32>(vaddq_u64(high, bias)),
+            );
+            *mask = vaddvq_u32(vandq_u32(vceqzq_u32(upper_halves), segment_bits)) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// Low and high halves around the sign-extension boundary.
+    const LOW_HALVES: [u32; 6] = [0, 1, 0x7fff_ffff, 0x8000_0000, 0x8000_0001, 0xffff_ffff];
+    const HIGH_HALVES: [u32; 6] = [0, 1, 0x0000_0080, 0x8000_0000, 0xffff_fffe, 0xffff_ffff];
+
+    fn expected_mask(pubkey: &[u8; 32]) -> u8 {
+        (0..4)
+            .filter(|&segment| segment_is_imm32_compatible(pubkey, segment))
+            .fold(0, |mask, segment| mask | (1 << segment))
+    }
+
+    /// Random keys from a fixed splitmix64 stream, followed by keys built
+    /// from every boundary segment, each in every segment position.
+    fn test_keys() -> Vec<[u8; 32]> {
+        let mut state = 0x1234_5678_9abc_def0_u64;
+        let mut next = move || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        let mut keys = Vec::new();
+        for _ in 0..1001 {
+            let mut key = [0u8; 32];
+            for chunk in key.chunks_exact_mut(8) {
+                chunk.copy_from_slice(&next().to_le_bytes());
+            }
+            keys.push(key);
+        }
+        for &low in &LOW_HALVES {
+            for &high in &HIGH_HALVES {
+                let segment = (u64::from(high) << 32 | u64::from(low)).to_le_bytes();
+                for position in 0..4 {
+                    let mut key = [0u8; 32];
+                    for chunk in key.chunks_exact_mut(8) {
+                        chunk.copy_from_slice(&next().to_le_bytes());
+                    }
+                    key[position * 8..position * 8 + 8].copy_from_slice(&segment);
+                    keys.push(key);
+                }
+                // The same segment in all four positions at once
+                let mut key = [0u8; 32];
+                for chunk in key.chunks_exact_mut(8) {
+                    chunk.copy_from_slice(&segment);
+                }
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    fn assert_matches_scalar(kernel: unsafe fn(&[[u8; 32]], &mut [u8])) {
+        let keys = test_keys();
+        let mut masks = std::vec![0xaa; keys.len()];
+        // SAFETY: callers check the kernel's CPU features first
+        unsafe { kernel(&keys, &mut masks) };
+        for (key, mask) in keys.iter().zip(&masks) {
+            assert_eq!(*mask, expected_mask(key), "key {key:02x?}");
+        }
+        // One key at a time, through the remainder path of the two-key kernels
+        for key in &keys {
+            let mut mask = [0xaa];
+            // SAFETY: as above
+            unsafe { kernel(core::slice::from_ref(key), &mut mask) };
+            assert_eq!(mask[0], expected_mask(key), "single key {key:02x?}");
+        }
+    }
+
+    #[test]
+    fn boundary_segments() {
+        let segment = |low: u32, high: u32| {
+            let mut key = [0u8; 32];
+            key[..8].copy_from_slice(&(u64::from(high) << 32 | u64::from(low)).to_le_bytes());
+            segment_is_imm32_compatible(&key, 0)
+        };
+        assert!(segment(0x7fff_ffff, 0));
+        assert!(!segment(0x7fff_ffff, 0xffff_ffff));
+        assert!(segment(0x8000_0000, 0xffff_ffff));
+        assert!(!segment(0x8000_0000, 0));
+    }
+
+    #[test]
+    fn dispatch_matches_scalar() {
+        assert_matches_scalar(imm32_masks);
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64", any(feature = "std", target_feature = "avx2")))]
+    #[test]
+    fn avx2_matches_scalar() {
+        if !std::arch::is_x86_feature_detected!("avx2") {
+            return;
+        }
+        assert_matches_scalar(avx2::imm32_masks);
+    }
+
+    #[cfg(all(
+        feature = "simd-avx512",
+        target_arch = "x86_64",
+        any(feature = "std", all(target_feature = "avx512f", target_feature = "avx512vl"))
+    ))]
+    #[test]
+    fn avx512_matches_scalar() {
+        if !std::arch::is_x86_feature_detected!("avx512f") || !std::arch::is_x86_feature_detected!("avx512vl") {
+            return;
+        }
+        assert_matches_scalar(avx512::imm32_masks);
+    }
+
+    #[cfg(all(feature = "simd-neon", target_arch = "aarch64"))]
+    #[test]
+    fn neon_matches_scalar() {
+        assert_matches_scalar(neon::imm32_masks);
+    }
+}
@@ -0,0 +1,11 @@
+//! The imm32 segment test and vanity pattern predicates used by
+//! doppler-keygen, without std so they can be embedded anywhere the CLI's
+//! exact matching rules are needed.
+
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod imm32;
+pub mod pattern;
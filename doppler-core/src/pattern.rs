@@ -0,0 +1,385 @@
+//! Allocation-free base58 address matching.
+
+use core::cmp::Ordering;
+
+/// The longest base58 encoding of a 32-byte public key.
+pub const MAX_ADDRESS_LEN: usize = 44;
+
+/// Encodes a public key as a base58 address into `buf`, returning the
+/// encoded part.
+pub fn encode_address<'a>(pubkey: &[u8; 32], buf: &'a mut [u8; MAX_ADDRESS_LEN]) -> &'a str {
+    let len = bs58::encode(pubkey)
+        .onto(&mut buf[..])
+        .expect("a 32-byte key always fits in MAX_ADDRESS_LEN characters");
+    // Base58 output is always ASCII
+    core::str::from_utf8(&buf[..len]).expect("base58 output is ASCII")
+}
+
+/// Returns true if `address` starts with `prefix` and ends with `suffix`,
+/// ignoring ASCII case if `ignore_case` is set. A missing prefix or suffix
+/// matches anything.
+pub fn address_matches(address: &str, prefix: Option<&str>, suffix: Option<&str>, ignore_case: bool) -> bool {
+    let eq = |a: &[u8], b: &[u8]| if ignore_case { a.eq_ignore_ascii_case(b) } else { a == b };
+    let address = address.as_bytes();
+
+    prefix.is_none_or(|prefix| {
+        let prefix = prefix.as_bytes();
+        address.len() >= prefix.len() && eq(&address[..prefix.len()], prefix)
+    }) && suffix.is_none_or(|suffix| {
+        let suffix = suffix.as_bytes();
+        address.len() >= suffix.len() && eq(&address[address.len() - suffix.len()..], suffix)
+    })
+}
+
+/// The base58 digits in order of value.
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// The smallest key whose address does not start with '1', i.e. the first
+/// key without a leading zero byte.
+const MIN_KEY: [u8; 32] = {
+    let mut key = [0; 32];
+    key[0] = 1;
+    key
+};
+
+/// An inclusive range of public keys, compared as big-endian integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRange {
+    pub start: [u8; 32],
+    pub end: [u8; 32],
+}
+
+/// The public keys whose addresses start with a case-sensitive prefix, as
+/// ranges of raw keys, so a key can be tested without base58 encoding it.
+///
+/// A key without leading zero bytes has a 43 or 44 digit address, and the
+/// keys whose address of a given length starts with the prefix form one
+/// contiguous range, so there are at most two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixRanges {
+    ranges: [KeyRange; 2],
+    len: usize,
+}
+
+impl PrefixRanges {
+    /// Computes the ranges for `prefix`. Returns `None` for prefixes that
+    /// cannot be expressed as ranges: empty ones, ones starting with '1'
+    /// (which stands for a leading zero byte) and ones with characters
+    /// outside the base58 alphabet.
+    pub fn new(prefix: &str) -> Option<PrefixRanges> {
+        let digits = prefix.as_bytes();
+        if digits.first().is_none_or(|&first| first == b'1') || !digits.iter().all(|digit| ALPHABET.contains(digit)) {
+            return None;
+        }
+
+        let mut ranges = PrefixRanges { ranges: [KeyRange { start: MIN_KEY, end: MIN_KEY }; 2], len: 0 };
+        let mut value = [0; 32];
+        for digit in digits {
+            let digit_value = ALPHABET.iter().position(|d| d == digit).unwrap_or(0) as u32;
+            if !mul_add(&mut value, 58, digit_value) {
+                // Longer than any key's address
+                return Some(ranges);
+            }
+        }
+
+        for address_len in [43, 44] {
+            let Some(padding) = usize::checked_sub(address_len, digits.len()) else { continue };
+
+            // Addresses of this length starting with the prefix run from the
+            // prefix padded with '1's to the prefix padded with 'z's
+            let mut start = value;
+            if !(0..padding).all(|_| mul_add(&mut start, 58, 0)) {
+                continue;
+            }
+            let mut end = value;
+            if mul_add(&mut end, 1, 1) && (0..padding).all(|_| mul_add(&mut end, 58, 0)) {
+                decrement(&mut end);
+            } else {
+                end = [0xFF; 32];
+            }
+
+            let start = start.max(MIN_KEY);
+            if start <= end {
+                ranges.ranges[ranges.len] = KeyRange { start, end };
+                ranges.len += 1;
+            }
+        }
+        Some(ranges)
+    }
+
+    /// The ranges, lowest first. Empty if no key's address starts with the
+    /// prefix.
+    pub fn ranges(&self) -> &[KeyRange] {
+        &self.ranges[..self.len]
+    }
+
+    /// Returns true if the address of `pubkey` starts with the prefix.
+    pub fn contains(&self, pubkey: &[u8; 32]) -> bool {
+        self.ranges()
+            .iter()
+            .any(|range| cmp_keys(pubkey, &range.start).is_ge() && cmp_keys(pubkey, &range.end).is_le())
+    }
+}
+
+/// Sets `value` to `value * factor + addend`, returning false if the result
+/// does not fit in 32 bytes.
+fn mul_add(value: &mut [u8; 32], factor: u32, addend: u32) -> bool {
+    let mut carry = addend;
+    for byte in value.iter_mut().rev() {
+        let product = u32::from(*byte) * factor + carry;
+        *byte = product as u8;
+        carry = product >> 8;
+    }
+    carry == 0
+}
+
+/// Subtracts one from a nonzero `value`.
+fn decrement(value: &mut [u8; 32]) {
+    for byte in value.iter_mut().rev() {
+        let (difference, borrow) = byte.overflowing_sub(1);
+        *byte = difference;
+        if !borrow {
+            break;
+        }
+    }
+}
+
+/// Compares two keys as big-endian integers.
+#[allow(unreachable_code)]
+fn cmp_keys(a: &[u8; 32], b: &[u8; 32]) -> Ordering {
+    #[cfg(all(feature = "simd-avx512", feature = "std", target_arch = "x86_64"))]
+    if std::arch::is_x86_feature_detected!("avx512bw") && std::arch::is_x86_feature_detected!("avx512vl") {
+        // SAFETY: AVX-512BW and AVX-512VL support was detected at runtime
+        return unsafe { avx512::cmp_keys(a, b) };
+    }
+
+    #[cfg(all(
+        feature = "simd-avx512",
+        not(feature = "std"),
+        target_arch = "x86_64",
+        target_feature = "avx512bw",
+        target_feature = "avx512vl"
+    ))]
+    {
+        // SAFETY: AVX-512BW and AVX-512VL are enabled for the whole build
+        return unsafe { avx512::cmp_keys(a, b) };
+    }
+
+    #[cfg(all(feature = "simd-neon", target_arch = "aarch64"))]
+    {
+        // SAFETY: NEON is part of the aarch64 baseline
+        return unsafe { neon::cmp_keys(a, b) };
+    }
+
+    a.cmp(b)
+}
+
+/// Decides a comparison from per-byte masks with one bit per byte, the most
+/// significant byte lowest: the first differing byte decides.
+#[cfg(any(
+    all(
+        feature = "simd-avx512",
+        target_arch = "x86_64",
+        any(feature = "std", all(target_feature = "avx512bw", target_feature = "avx512vl"))
+    ),
+    all(feature = "simd-neon", target_arch = "aarch64")
+))]
+fn first_difference(differs: u64, less: u64) -> Option<Ordering> {
+    (differs != 0).then(|| if less >> differs.trailing_zeros() & 1 != 0 { Ordering::Less } else { Ordering::Greater })
+}
+
+#[cfg(all(
+    feature = "simd-avx512",
+    target_arch = "x86_64",
+    any(feature = "std", all(target_feature = "avx512bw", target_feature = "avx512vl"))
+))]
+mod avx512 {
+    use core::arch::x86_64::*;
+    use core::cmp::Ordering;
+
+    /// Compares all 32 bytes at once into two mask registers.
+    #[target_feature(enable = "avx512bw,avx512vl")]
+    pub unsafe fn cmp_keys(a: &[u8; 32], b: &[u8; 32]) -> Ordering {
+        // SAFETY: both keys are 32 bytes and the loads are unaligned
+        let (a, b) = unsafe { (_mm256_loadu_si256(a.as_ptr().cast()), _mm256_loadu_si256(b.as_ptr().cast())) };
+        let differs = _mm256_cmpneq_epu8_mask(a, b);
+        let less = _mm256_cmplt_epu8_mask(a, b);
+        super::first_difference(u64::from(differs), u64::from(less)).unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(all(feature = "simd-neon", target_arch = "aarch64"))]
+mod neon {
+    use core::arch::aarch64::*;
+    use core::cmp::Ordering;
+
+    /// Compares 16 bytes at a time. NEON has no movemask, so each byte
+    /// lane of the comparison is narrowed to a nibble, which fits the 16
+    /// lanes in a u64.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn cmp_keys(a: &[u8; 32], b: &[u8; 32]) -> Ordering {
+        let nibbles = |lanes: uint8x16_t| {
+            vget_lane_u64::<0>(vreinterpret_u64_u8(vshrn_n_u16::<4>(vreinterpretq_u16_u8(lanes))))
+        };
+
+        for offset in [0, 16] {
+            // SAFETY: both keys are 32 bytes, read as unaligned 16-byte halves
+            let (a, b) = unsafe { (vld1q_u8(a.as_ptr().add(offset)), vld1q_u8(b.as_ptr().add(offset))) };
+            let differs = nibbles(vmvnq_u8(vceqq_u8(a, b)));
+            let less = nibbles(vcltq_u8(a, b));
+            if let Some(ordering) = super::first_difference(differs, less) {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fixed splitmix64 stream of keys.
+    struct Keys(u64);
+
+    impl Keys {
+        fn next_u64(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn next_key(&mut self) -> [u8; 32] {
+            let mut key = [0u8; 32];
+            for chunk in key.chunks_exact_mut(8) {
+                chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+            }
+            key
+        }
+
+        /// A key that is often between `start` and `end`: the bytes they
+        /// share, then a byte between theirs, then random bytes.
+        fn key_within(&mut self, range: &KeyRange) -> [u8; 32] {
+            let mut key = self.next_key();
+            let shared = range.start.iter().zip(&range.end).take_while(|(a, b)| a == b).count();
+            key[..shared].copy_from_slice(&range.start[..shared]);
+            if shared < 32 {
+                let (low, high) = (range.start[shared], range.end[shared]);
+                key[shared] = low + (self.next_u64() % (u64::from(high - low) + 1)) as u8;
+            }
+            key
+        }
+    }
+
+    fn matches(key: &[u8; 32], prefix: &str) -> bool {
+        let mut buf = [0; MAX_ADDRESS_LEN];
+        address_matches(encode_address(key, &mut buf), Some(prefix), None, false)
+    }
+
+    fn address_len(key: &[u8; 32]) -> usize {
+        encode_address(key, &mut [0; MAX_ADDRESS_LEN]).len()
+    }
+
+    fn step(key: &[u8; 32], up: bool) -> [u8; 32] {
+        let mut value = *key;
+        if up {
+            mul_add(&mut value, 1, 1);
+        } else {
+            decrement(&mut value);
+        }
+        value
+    }
+
+    /// Checks `contains` against `address_matches` on each range's ends,
+    /// the keys just outside them, keys inside them and random keys.
+    fn assert_agrees_with_address_matches(prefix: &str, keys: &mut Keys) {
+        let ranges = PrefixRanges::new(prefix).expect("prefix has ranges");
+        for range in ranges.ranges() {
+            for key in [range.start, range.end] {
+                assert!(ranges.contains(&key) && matches(&key, prefix), "{prefix}: end {key:02x?}");
+            }
+            for key in [step(&range.start, false), step(&range.end, true)] {
+                assert_eq!(ranges.contains(&key), matches(&key, prefix), "{prefix}: beyond {key:02x?}");
+            }
+            let mut inside = 0;
+            for _ in 0..500 {
+                let key = keys.key_within(range);
+                let contained = ranges.contains(&key);
+                assert_eq!(contained, matches(&key, prefix), "{prefix}: {key:02x?}");
+                inside += usize::from(contained);
+            }
+            assert!(inside >= 10, "{prefix}: only {inside} keys fell inside {range:02x?}");
+        }
+        for _ in 0..2000 {
+            let key = keys.next_key();
+            assert_eq!(ranges.contains(&key), matches(&key, prefix), "{prefix}: {key:02x?}");
+        }
+    }
+
+    #[test]
+    fn ranges_agree_with_address_matches() {
+        let mut keys = Keys(0x5eed);
+        for prefix in ["2", "A", "z", "So1", "Dopp", "zzzz", "JUP1", "9xQeWvG816bUx9EP"] {
+            assert_agrees_with_address_matches(prefix, &mut keys);
+        }
+    }
+
+    #[test]
+    fn ranges_cover_43_and_44_character_addresses() {
+        let mut keys = Keys(0x43_44);
+        // Only some leading digits occur at both lengths: 43-digit addresses
+        // from '2' up hold a leading zero byte, and 44-digit ones stop at 'J'
+        let both = PrefixRanges::new("A").unwrap();
+        let lengths: [usize; 2] = core::array::from_fn(|i| address_len(&both.ranges()[i].start));
+        assert_eq!(lengths, [43, 44]);
+        assert_eq!(PrefixRanges::new("2").unwrap().ranges().len(), 1);
+        assert_eq!(PrefixRanges::new("z").unwrap().ranges().len(), 1);
+
+        // Whole addresses of each length are prefixes with a single key
+        let (mut short, mut long) = (None, None);
+        while short.is_none() || long.is_none() {
+            let key = keys.next_key();
+            match address_len(&key) {
+                43 => short = short.or(Some(key)),
+                44 => long = long.or(Some(key)),
+                _ => {}
+            }
+        }
+        for key in [short.unwrap(), long.unwrap()] {
+            let mut buf = [0; MAX_ADDRESS_LEN];
+            let address = encode_address(&key, &mut buf);
+            let ranges = PrefixRanges::new(address).unwrap();
+            assert!(ranges.contains(&key), "{address}");
+            assert!(ranges.ranges().iter().any(|range| range.start == key && range.end == key), "{address}");
+            assert!(!ranges.contains(&step(&key, true)) && !ranges.contains(&step(&key, false)), "{address}");
+
+            // One digit shorter, the prefix holds the 58 keys of the same
+            // length ending in each digit, and longer addresses if any
+            let shorter = &address[..address.len() - 1];
+            assert_agrees_with_address_matches(shorter, &mut keys);
+        }
+
+        // No key has an address longer than 44 digits
+        assert!(PrefixRanges::new(&"z".repeat(45)).unwrap().ranges().is_empty());
+    }
+
+    #[test]
+    fn leading_ones_are_left_to_address_matches() {
+        let mut keys = Keys(0x1111);
+        for prefix in ["1", "11", "1A", "111z"] {
+            assert_eq!(PrefixRanges::new(prefix), None, "{prefix}");
+        }
+        // Keys with a leading zero byte have addresses starting with '1',
+        // which no range holds
+        let ranges = [PrefixRanges::new("2").unwrap(), PrefixRanges::new("z").unwrap()];
+        for _ in 0..1000 {
+            let mut key = keys.next_key();
+            key[0] = 0;
+            assert!(matches(&key, "1"));
+            assert!(ranges.iter().all(|ranges| !ranges.contains(&key)));
+        }
+        assert!(!ranges[0].contains(&step(&MIN_KEY, false)));
+    }
+}
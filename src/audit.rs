@@ -0,0 +1,245 @@
+//! Hash-chained audit log of keys written to disk.
+//!
+//! Each output directory gets an append-only `audit.log` with one JSON line
+//! per saved key. Every entry carries the SHA-256 of the previous entry, so
+//! removing or editing a line breaks the chain from that point on, which
+//! `doppler-keygen verify-audit` reports. Lines dropped from the end leave a
+//! valid but shorter chain, so the latest hash should also be recorded
+//! somewhere the grinding box cannot write to.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cli_error::CliError;
+
+/// Name of the audit log kept in the output directory.
+pub const AUDIT_FILE: &str = "audit.log";
+
+/// The `prev` hash of the first entry in a log.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The hashed part of an audit entry.
+#[derive(Debug, Serialize, Deserialize)]
+struct AuditRecord {
+    seq: u64,
+    /// Seconds since the Unix epoch
+    time: u64,
+    host: String,
+    command: String,
+    pubkey: String,
+    /// Hash of the previous entry
+    prev: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AuditEntry {
+    #[serde(flatten)]
+    record: AuditRecord,
+    hash: String,
+}
+
+impl AuditRecord {
+    fn hash(&self) -> String {
+        let bytes = serde_json::to_vec(self).expect("audit records always serialize");
+        hex::encode(Sha256::digest(bytes))
+    }
+}
+
+/// The audit log of an output directory, ready to append to.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    host: String,
+    command: String,
+    /// Sequence number and hash of the last entry
+    tail: Mutex<(u64, String)>,
+}
+
+impl AuditLog {
+    /// Opens the audit log in `output_dir`, picking up the chain where the
+    /// last entry left off.
+    pub fn open(output_dir: &Path) -> Result<AuditLog, CliError> {
+        let path = output_dir.join(AUDIT_FILE);
+        let tail = match fs::read_to_string(&path) {
+            Ok(contents) => match contents.lines().rev().find(|line| !line.trim().is_empty()) {
+                Some(line) => {
+                    let entry: AuditEntry = serde_json::from_str(line).map_err(|e| CliError::Audit {
+                        path: path.clone(),
+                        source: io::Error::new(io::ErrorKind::InvalidData, e),
+                    })?;
+                    (entry.record.seq, entry.hash)
+                }
+                None => (0, GENESIS_HASH.to_string()),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (0, GENESIS_HASH.to_string()),
+            Err(source) => return Err(CliError::Audit { path, source }),
+        };
+
+        Ok(AuditLog {
+            path,
+            host: hostname(),
+            command: env::args().collect::<Vec<_>>().join(" "),
+            tail: Mutex::new(tail),
+        })
+    }
+
+    /// Appends an entry for a key that has just been written to disk.
+    pub fn record(&self, pubkey: &str) -> Result<(), CliError> {
+        let mut tail = self.tail.lock().unwrap_or_else(PoisonError::into_inner);
+        let record = AuditRecord {
+            seq: tail.0 + 1,
+            time: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            host: self.host.clone(),
+            command: self.command.clone(),
+            pubkey: pubkey.to_string(),
+            prev: tail.1.clone(),
+        };
+        let entry = AuditEntry { hash: record.hash(), record };
+        let line = serde_json::to_string(&entry).expect("audit entries always serialize");
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{line}"))
+            .map_err(|source| CliError::Audit { path: self.path.clone(), source })?;
+
+        *tail = (entry.record.seq, entry.hash);
+        Ok(())
+    }
+}
+
+/// Checks the chain of the audit log at `path`, returning the number of
+/// entries and the hash of the last one, or a description of the first
+/// broken link.
+pub fn verify(path: &Path) -> Result<(u64, String), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+
+    let mut expected_prev = GENESIS_HASH.to_string();
+    let mut expected_seq = 1;
+    for (index, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let line_number = index + 1;
+        let entry: AuditEntry =
+            serde_json::from_str(line).map_err(|e| format!("Line {line_number}: not a valid entry: {e}"))?;
+        let record = &entry.record;
+
+        if record.seq != expected_seq {
+            return Err(format!(
+                "Line {line_number}: expected entry #{expected_seq} but found #{}; entries are missing or reordered",
+                record.seq
+            ));
+        }
+        if record.prev != expected_prev {
+            return Err(format!(
+                "Line {line_number}: entry #{} does not follow the previous entry; the log was edited",
+                record.seq
+            ));
+        }
+        if record.hash() != entry.hash {
+            return Err(format!(
+                "Line {line_number}: entry #{} ({}) does not match its hash; the entry was edited",
+                record.seq, record.pubkey
+            ));
+        }
+
+        expected_prev = entry.hash;
+        expected_seq += 1;
+    }
+    Ok((expected_seq - 1, expected_prev))
+}
+
+/// Best-effort name of this machine.
+pub fn hostname() -> String {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .into_iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+        .or_else(|| fs::read_to_string("/etc/hostname").ok().map(|name| name.trim().to_string()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a log of `count` entries and returns its path and lines.
+    fn chain(dir: &Path, count: usize) -> (PathBuf, Vec<String>) {
+        let log = AuditLog::open(dir).unwrap();
+        for n in 0..count {
+            log.record(&format!("Pubkey{n}")).unwrap();
+        }
+        let path = dir.join(AUDIT_FILE);
+        let lines = fs::read_to_string(&path).unwrap().lines().map(str::to_string).collect();
+        (path, lines)
+    }
+
+    fn rewrite(path: &Path, lines: &[String]) {
+        fs::write(path, lines.iter().map(|line| format!("{line}\n")).collect::<String>()).unwrap();
+    }
+
+    #[test]
+    fn recorded_chain_verifies() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, lines) = chain(dir.path(), 3);
+        let last: AuditEntry = serde_json::from_str(&lines[2]).unwrap();
+        assert_eq!(verify(&path).unwrap(), (3, last.hash));
+    }
+
+    #[test]
+    fn edited_entry_fails_its_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut lines) = chain(dir.path(), 3);
+        lines[1] = lines[1].replace("Pubkey1", "Pubkey9");
+        rewrite(&path, &lines);
+        let error = verify(&path).unwrap_err();
+        assert!(error.starts_with("Line 2:") && error.contains("does not match its hash"), "{error}");
+    }
+
+    #[test]
+    fn deleted_middle_entry_leaves_a_gap() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut lines) = chain(dir.path(), 3);
+        lines.remove(1);
+        rewrite(&path, &lines);
+        let error = verify(&path).unwrap_err();
+        assert!(error.contains("expected entry #2 but found #3"), "{error}");
+    }
+
+    #[test]
+    fn swapped_entries_leave_a_gap() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut lines) = chain(dir.path(), 3);
+        lines.swap(0, 1);
+        rewrite(&path, &lines);
+        let error = verify(&path).unwrap_err();
+        assert!(error.contains("expected entry #1 but found #2"), "{error}");
+    }
+
+    #[test]
+    fn changed_prev_breaks_the_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut lines) = chain(dir.path(), 3);
+        let mut entry: AuditEntry = serde_json::from_str(&lines[2]).unwrap();
+        entry.record.prev = GENESIS_HASH.to_string();
+        entry.hash = entry.record.hash();
+        lines[2] = serde_json::to_string(&entry).unwrap();
+        rewrite(&path, &lines);
+        let error = verify(&path).unwrap_err();
+        assert!(error.starts_with("Line 3:") && error.contains("does not follow the previous entry"), "{error}");
+    }
+
+    #[test]
+    fn reopened_log_continues_the_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = chain(dir.path(), 2);
+        AuditLog::open(dir.path()).unwrap().record("Pubkey2").unwrap();
+        let (count, _) = verify(&path).unwrap();
+        assert_eq!(count, 3);
+    }
+}
@@ -0,0 +1,537 @@
+//! Grinding for several vanity patterns at once, each with its own quota.
+
+use core::cmp::Reverse;
+use core::ops::ControlFlow;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use doppler_core::pattern::PrefixRanges;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::DopplerError;
+use crate::grinder::{FoundKey, GrindConfig};
+use crate::pattern::{BatchPattern, VanityPattern};
+use crate::progress::{self, ProgressCallback};
+use crate::scheduler::{self, Candidate};
+use crate::shutdown::ShutdownToken;
+use crate::stats::{StatsCollector, StatsSummary};
+
+type MatchCallback = Box<dyn Fn(&BatchMatch) -> bool + Send + Sync>;
+pub type FilterCallback = Box<dyn Fn(&[u8; 32]) -> Option<Vec<(&'static str, f64)>> + Send + Sync>;
+type ReloadCallback = Box<dyn FnMut() -> Option<Vec<BatchPattern>> + Send>;
+
+/// A found key and the pattern it was found for.
+#[derive(Debug)]
+pub struct BatchMatch {
+    pub key: FoundKey,
+    /// Index of the pattern in the list passed to `vanity_keys_batch`,
+    /// followed by any patterns added by a reload
+    pub pattern_index: usize,
+    pub pattern: VanityPattern,
+    /// Keys requested for the pattern
+    pub requested: usize,
+    /// Which of the pattern's keys this is, counting from 1
+    pub pattern_number: usize,
+    /// The pattern's sink, if it has one
+    pub sink: Option<String>,
+    /// The scores `filter` gave the key
+    pub scores: Vec<(&'static str, f64)>,
+}
+
+/// How far a run got on one pattern.
+#[derive(Debug, Clone)]
+pub struct PatternStats {
+    pub pattern: VanityPattern,
+    pub requested: usize,
+    pub found: usize,
+    /// Dropped by a reload before it was complete
+    pub removed: bool,
+    /// Workers dedicated to the pattern; 0 while every worker checks it
+    pub workers: usize,
+}
+
+/// Everything a batch run produced.
+#[derive(Debug)]
+pub struct BatchResult {
+    /// Accepted keys in the order they were found
+    pub keys: Vec<BatchMatch>,
+    /// One entry per pattern, in the order they were given, followed by any
+    /// patterns added by a reload
+    pub patterns: Vec<PatternStats>,
+    /// Attempt counts and timing for the run
+    pub stats: StatsSummary,
+}
+
+/// Optional callbacks for `vanity_keys_batch`.
+#[derive(Default)]
+pub struct BatchHooks {
+    /// Called with a progress snapshot at the given interval
+    pub on_progress: Option<(Duration, ProgressCallback)>,
+    /// Called on the worker thread for a candidate that matches a pattern
+    /// still needing keys, before it takes the pattern's slot. Returning
+    /// `None` skips it; otherwise the scores returned are passed on in
+    /// `BatchMatch::scores`. No slot is held while it runs, so a slow filter
+    /// never keeps a key that it rejects from another worker.
+    pub filter: Option<FilterCallback>,
+    /// Called on the worker thread for each match. Returning false discards
+    /// the key and frees its slot so the search carries on for the pattern.
+    pub on_match: Option<MatchCallback>,
+    /// Polled at the given interval, and once more as the run stops, for a
+    /// new list of patterns, which replaces the current one without stopping
+    /// the search. With a reload
+    /// callback the run continues once every pattern is complete, until
+    /// cancelled, so that patterns added later are picked up.
+    pub reload: Option<(Duration, ReloadCallback)>,
+    /// Cancelling this token ends the run early, e.g. on Ctrl-C
+    pub shutdown: Option<ShutdownToken>,
+}
+
+/// One pattern's place in a running batch.
+struct PatternState {
+    pattern: VanityPattern,
+    requested: usize,
+    priority: u32,
+    sink: Option<String>,
+    /// Slots taken, including keys still being accepted by `on_match`
+    reserved: usize,
+    /// Keys accepted by `on_match`
+    found: usize,
+    removed: bool,
+}
+
+/// The pattern a worker is dedicated to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Assignment {
+    index: usize,
+    /// Key ranges of the pattern's prefix, so the worker can skip candidates
+    /// outside them without encoding them
+    ranges: Option<PrefixRanges>,
+}
+
+/// The patterns of a running batch. Only candidates that passed the imm32
+/// check get this far, so a single lock is cheap.
+struct PatternTable {
+    patterns: Vec<PatternState>,
+    /// Indices of the patterns that still need keys, in the order candidates
+    /// are offered to them
+    order: Vec<usize>,
+    /// The pattern each worker is dedicated to, if any. Each worker reads
+    /// only its own, so checking it never waits on the table's lock.
+    assignments: Arc<[Mutex<Option<Assignment>>]>,
+    ignore_case: bool,
+}
+
+impl PatternTable {
+    fn new(patterns: &[BatchPattern], ignore_case: bool, workers: usize) -> PatternTable {
+        let mut table = PatternTable {
+            patterns: Vec::new(),
+            order: Vec::new(),
+            assignments: (0..workers).map(|_| Mutex::new(None)).collect(),
+            ignore_case,
+        };
+        table.update(patterns);
+        table
+    }
+
+    /// Offers candidates to the patterns with the highest priority first,
+    /// then the hardest, so a key matching several patterns goes to the most
+    /// urgent one or the one least likely to see another match.
+    fn reorder(&mut self) {
+        let patterns = &self.patterns;
+        let probability = |index: usize| patterns[index].pattern.match_probability(self.ignore_case);
+        self.order = (0..patterns.len())
+            .filter(|&index| !patterns[index].removed && patterns[index].reserved < patterns[index].requested)
+            .collect();
+        self.order.sort_by(|&a, &b| {
+            patterns[b].priority.cmp(&patterns[a].priority).then(probability(a).total_cmp(&probability(b)))
+        });
+        self.allocate();
+    }
+
+    /// Shares the workers out among the patterns still needing keys when
+    /// their priorities differ, each getting at least one and the rest in
+    /// proportion to its priority plus one, so an urgent pattern has most of
+    /// the machine while the others trickle along. With equal priorities,
+    /// or more patterns than workers, every worker checks every pattern.
+    /// Called whenever the patterns needing keys change, which rebalances
+    /// the workers of a completed pattern onto the others.
+    fn allocate(&mut self) {
+        let workers = self.assignments.len();
+        let priorities: Vec<u32> = self.order.iter().map(|&index| self.patterns[index].priority).collect();
+        let shared = priorities.len() > workers || priorities.windows(2).all(|pair| pair[0] == pair[1]);
+        let weights: Vec<u64> = priorities.iter().map(|&priority| u64::from(priority) + 1).collect();
+        let shares = if shared { vec![0; weights.len()] } else { shares(&weights, workers) };
+        let mut assignments = self.assignments.iter();
+        for (&index, share) in self.order.iter().zip(shares) {
+            let ranges = self.patterns[index].pattern.prefix_ranges(self.ignore_case);
+            for slot in assignments.by_ref().take(share) {
+                *slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(Assignment { index, ranges });
+            }
+        }
+        for slot in assignments {
+            *slot.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        }
+    }
+
+    /// The pattern `worker` is dedicated to, if any.
+    fn assignment(&self, worker: usize) -> Option<Assignment> {
+        *self.assignments[worker].lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replaces the pattern list: patterns already in the table keep their
+    /// progress and take the new count and priority, new ones are added and
+    /// missing ones are removed.
+    fn update(&mut self, patterns: &[BatchPattern]) {
+        for state in &mut self.patterns {
+            state.removed = true;
+        }
+        for batch_pattern in patterns {
+            match self.patterns.iter_mut().find(|state| state.pattern == batch_pattern.pattern) {
+                Some(state) => {
+                    state.requested = batch_pattern.count;
+                    state.priority = batch_pattern.priority;
+                    state.sink.clone_from(&batch_pattern.sink);
+                    state.removed = false;
+                }
+                None => self.patterns.push(PatternState {
+                    pattern: batch_pattern.pattern.clone(),
+                    requested: batch_pattern.count,
+                    priority: batch_pattern.priority,
+                    sink: batch_pattern.sink.clone(),
+                    reserved: 0,
+                    found: 0,
+                    removed: false,
+                }),
+            }
+        }
+        self.reorder();
+    }
+
+    /// The first pattern still needing keys that `address` matches, only
+    /// considering pattern `only` if given.
+    fn matching(&self, address: &str, only: Option<usize>) -> Option<usize> {
+        self.order.iter().copied().find(|&index| {
+            only.is_none_or(|only| only == index)
+                && self.patterns[index].pattern.matches_with_case(address, self.ignore_case)
+        })
+    }
+
+    /// Whether `address` matches a pattern that still needs keys, or
+    /// pattern `only` if given.
+    fn wants(&self, address: &str, only: Option<usize>) -> bool {
+        self.matching(address, only).is_some()
+    }
+
+    /// Takes a slot in the first pattern `address` matches, or in pattern
+    /// `only` if given, returning its index and the slot's number.
+    fn reserve(&mut self, address: &str, only: Option<usize>) -> Option<(usize, usize)> {
+        let index = self.matching(address, only)?;
+        let state = &mut self.patterns[index];
+        state.reserved += 1;
+        let number = state.reserved;
+        if state.reserved >= state.requested {
+            self.reorder();
+        }
+        Some((index, number))
+    }
+
+    /// Gives back a slot whose key `on_match` discarded.
+    fn release(&mut self, index: usize) {
+        self.patterns[index].reserved -= 1;
+        self.reorder();
+    }
+
+    /// True once every pattern has all of its keys accepted.
+    fn is_done(&self) -> bool {
+        self.patterns.iter().filter(|state| !state.removed).all(|state| state.found >= state.requested)
+    }
+
+    fn stats(&self) -> Vec<PatternStats> {
+        let mut workers = vec![0; self.patterns.len()];
+        for assignment in (0..self.assignments.len()).filter_map(|worker| self.assignment(worker)) {
+            workers[assignment.index] += 1;
+        }
+        self.patterns
+            .iter()
+            .zip(workers)
+            .map(|(state, workers)| PatternStats {
+                pattern: state.pattern.clone(),
+                requested: state.requested,
+                found: state.found,
+                removed: state.removed && state.found < state.requested,
+                workers,
+            })
+            .collect()
+    }
+}
+
+/// Splits `workers` in proportion to `weights`, with at least one each.
+/// What rounding down leaves over goes to the largest remainders, ties to
+/// the earlier weight. There must be at least as many workers as weights.
+fn shares(weights: &[u64], workers: usize) -> Vec<usize> {
+    let spare = (workers - weights.len()) as u64;
+    let total: u64 = weights.iter().sum();
+    let mut shares: Vec<usize> = weights.iter().map(|&weight| 1 + (spare * weight / total) as usize).collect();
+    let left = workers - shares.iter().sum::<usize>();
+    let mut by_remainder: Vec<usize> = (0..weights.len()).collect();
+    by_remainder.sort_by_key(|&index| Reverse(spare * weights[index] % total));
+    for index in by_remainder.into_iter().take(left) {
+        shares[index] += 1;
+    }
+    shares
+}
+
+/// Searches until every pattern has found `count` imm32 compatible keys.
+///
+/// `config.pattern` is ignored in favour of `patterns`; the other settings
+/// apply as for a `Grinder`. Matching honours `config.ignore_case`. Every
+/// pattern is validated before any work starts; patterns from a reload are
+/// expected to have been validated by the callback.
+///
+/// Candidates are checked against the patterns with the highest `priority`
+/// first, then the hardest, and a pattern that is complete drops out of the
+/// check. When priorities differ, workers are dedicated to patterns by
+/// priority instead of each checking them all; see `PatternTable::allocate`.
+pub fn vanity_keys_batch(
+    patterns: &[BatchPattern],
+    config: &GrindConfig,
+    hooks: BatchHooks,
+) -> Result<BatchResult, DopplerError> {
+    for batch_pattern in patterns {
+        batch_pattern.pattern.validate(config.ignore_case).map_err(DopplerError::InvalidPattern)?;
+    }
+    config.constraints.validate().map_err(DopplerError::InvalidConstraint)?;
+
+    let constraints = config.constraints.clone();
+    let table = Arc::new(Mutex::new(PatternTable::new(patterns, config.ignore_case, config.num_threads)));
+    let assignments = Arc::clone(&table.lock().unwrap_or_else(PoisonError::into_inner).assignments);
+    let keys_found = Arc::new(AtomicUsize::new(0));
+    let found_keys = Arc::new(Mutex::new(Vec::new()));
+    let stats = Arc::new(StatsCollector::new(config.num_threads));
+    let shutdown = hooks.shutdown.unwrap_or_default();
+    let keep_running = hooks.reload.is_some();
+
+    let reporter = hooks
+        .on_progress
+        .map(|(interval, mut callback)| {
+            let table = Arc::clone(&table);
+            progress::spawn_reporter(&stats, &shutdown, interval, Arc::clone(&keys_found), move |mut snapshot| {
+                snapshot.patterns = table.lock().unwrap_or_else(PoisonError::into_inner).stats();
+                callback(snapshot);
+            })
+        })
+        .transpose()?;
+
+    let reloader = hooks
+        .reload
+        .map(|(interval, mut reload)| {
+            let table = Arc::clone(&table);
+            let shutdown = shutdown.clone();
+            thread::Builder::new()
+                .name("reload".to_string())
+                .spawn(move || loop {
+                    // Poll once more as the run stops so the result reflects
+                    // the last change
+                    let stopping = shutdown.wait_timeout(interval);
+                    if let Some(patterns) = reload() {
+                        table.lock().unwrap_or_else(PoisonError::into_inner).update(&patterns);
+                    }
+                    if stopping {
+                        break;
+                    }
+                })
+                .map_err(DopplerError::SpawnThread)
+        })
+        .transpose()?;
+
+    let check = {
+        let table = Arc::clone(&table);
+        let keys_found = Arc::clone(&keys_found);
+        let found_keys = Arc::clone(&found_keys);
+        let shutdown = shutdown.clone();
+        let filter = hooks.filter;
+        let on_match = hooks.on_match;
+
+        move |thread_id: usize, candidate: Candidate| {
+            // The imm32 check is far cheaper than base58 encoding, so only
+            // candidates that pass it are checked against the vanity patterns
+            let Some(segment) = constraints.matching_segment(&candidate.address, candidate.imm32_mask) else {
+                return ControlFlow::Continue(());
+            };
+            // A dedicated worker only looks for its own pattern, skipping
+            // candidates outside its prefix without encoding them
+            let assignment = *assignments[thread_id].lock().unwrap_or_else(PoisonError::into_inner);
+            if assignment.is_some_and(|assignment| {
+                assignment.ranges.is_some_and(|ranges| !ranges.contains(&candidate.address))
+            }) {
+                return ControlFlow::Continue(());
+            }
+            let only = assignment.map(|assignment| assignment.index);
+            let address = bs58::encode(candidate.address).into_string();
+
+            let scores = match &filter {
+                Some(filter) => {
+                    if !table.lock().unwrap_or_else(PoisonError::into_inner).wants(&address, only) {
+                        return ControlFlow::Continue(());
+                    }
+                    let Some(scores) = filter(&candidate.address) else {
+                        return ControlFlow::Continue(());
+                    };
+                    scores
+                }
+                None => Vec::new(),
+            };
+
+            // Reserve the slot before releasing the lock
+            let mut patterns = table.lock().unwrap_or_else(PoisonError::into_inner);
+            let Some((pattern_index, pattern_number)) = patterns.reserve(&address, only) else {
+                return ControlFlow::Continue(());
+            };
+            let pattern = patterns.patterns[pattern_index].pattern.clone();
+            let requested = patterns.patterns[pattern_index].requested;
+            let sink = patterns.patterns[pattern_index].sink.clone();
+            drop(patterns);
+
+            let key = FoundKey { keypair: candidate.keypair(), address, segment, thread_id };
+            let batch_match = BatchMatch { key, pattern_index, pattern, requested, pattern_number, sink, scores };
+            if on_match.as_ref().is_some_and(|on_match| !on_match(&batch_match)) {
+                // Give the slot back so the search carries on
+                table.lock().unwrap_or_else(PoisonError::into_inner).release(pattern_index);
+                return ControlFlow::Continue(());
+            }
+
+            found_keys.lock().unwrap_or_else(PoisonError::into_inner).push(batch_match);
+            keys_found.fetch_add(1, Ordering::Relaxed);
+            let mut patterns = table.lock().unwrap_or_else(PoisonError::into_inner);
+            patterns.patterns[pattern_index].found += 1;
+            // Shut the run down once every pattern is complete
+            if patterns.is_done() && !keep_running {
+                shutdown.cancel();
+            }
+            ControlFlow::Continue(())
+        }
+    };
+
+    let result = scheduler::run_workers(&config.workers(), &stats, &shutdown, check);
+    shutdown.cancel();
+    if let Some(reporter) = reporter {
+        let _ = reporter.join();
+    }
+    if let Some(reloader) = reloader {
+        let _ = reloader.join();
+    }
+    result?;
+
+    let pattern_stats = table.lock().unwrap_or_else(PoisonError::into_inner).stats();
+    let keys = Arc::try_unwrap(found_keys)
+        .map(|keys| keys.into_inner().unwrap_or_else(PoisonError::into_inner))
+        .unwrap_or_default();
+
+    Ok(BatchResult { keys, patterns: pattern_stats, stats: stats.summary() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(prefix: &str, count: usize) -> BatchPattern {
+        BatchPattern {
+            pattern: VanityPattern { prefix: Some(prefix.to_string()), suffix: None },
+            count,
+            found: 0,
+            priority: 0,
+            sink: None,
+        }
+    }
+
+    /// How many workers are dedicated to each pattern.
+    fn workers(table: &PatternTable) -> Vec<usize> {
+        table.stats().iter().map(|stats| stats.workers).collect()
+    }
+
+    #[test]
+    fn a_key_matching_several_patterns_goes_to_the_hardest() {
+        let mut table = PatternTable::new(&[prefix("a", 5), prefix("abc", 5), prefix("ab", 5)], false, 1);
+        assert_eq!(table.reserve("abcXYZ", None), Some((1, 1)));
+        assert_eq!(table.reserve("abXYZ", None), Some((2, 1)));
+        assert_eq!(table.reserve("aXYZ", None), Some((0, 1)));
+        assert_eq!(table.reserve("XYZ", None), None);
+    }
+
+    #[test]
+    fn priority_comes_before_difficulty() {
+        let urgent = BatchPattern { priority: 2, ..prefix("a", 5) };
+        let patterns = [prefix("abc", 5), urgent, BatchPattern { priority: 1, ..prefix("ab", 5) }];
+        let mut table = PatternTable::new(&patterns, false, 1);
+        assert_eq!(table.reserve("abcXYZ", None), Some((1, 1)));
+        assert_eq!(table.order, [1, 2, 0]);
+        assert_eq!(table.reserve("abXYZ", None), Some((1, 2)));
+    }
+
+    #[test]
+    fn complete_patterns_drop_out() {
+        let mut table = PatternTable::new(&[prefix("a", 5), prefix("ab", 1)], false, 1);
+        assert_eq!(table.reserve("abXYZ", None), Some((1, 1)));
+        // "ab" has its key, so the next match goes to "a"
+        assert_eq!(table.reserve("abXYZ", None), Some((0, 1)));
+        assert_eq!(table.order, [0]);
+        // A discarded key gives the slot back
+        table.release(1);
+        assert_eq!(table.reserve("abXYZ", None), Some((1, 1)));
+
+        table.patterns[1].found = 1;
+        assert!(!table.is_done());
+        table.patterns[0].found = 5;
+        assert!(table.is_done());
+    }
+
+    #[test]
+    fn only_patterns_still_needing_keys_are_wanted() {
+        let mut table = PatternTable::new(&[prefix("ab", 1), prefix("c", 1)], false, 1);
+        assert!(table.wants("abXYZ", None));
+        assert!(!table.wants("XYZ", None));
+        // Checking doesn't take a slot
+        assert!(table.wants("abXYZ", None));
+        assert_eq!(table.reserve("abXYZ", None), Some((0, 1)));
+        assert!(!table.wants("abXYZ", None));
+        assert!(table.wants("cXYZ", None));
+    }
+
+    #[test]
+    fn workers_are_shared_out_by_priority() {
+        let urgent = BatchPattern { priority: 10, ..prefix("abc", 1) };
+        let mut table = PatternTable::new(&[prefix("a", 5), urgent, prefix("b", 5)], false, 16);
+        assert_eq!(workers(&table), [2, 12, 2]);
+        assert_eq!(table.assignment(0), Some(Assignment { index: 1, ranges: PrefixRanges::new("abc") }));
+        assert_eq!(table.assignment(15).map(|assignment| assignment.index), Some(2));
+
+        // A dedicated worker only takes keys for its own pattern
+        assert!(!table.wants("aXYZ", Some(1)));
+        assert_eq!(table.reserve("aXYZ", Some(1)), None);
+        assert_eq!(table.reserve("aXYZ", Some(0)), Some((0, 1)));
+
+        // Once the urgent pattern is complete its workers move on
+        assert_eq!(table.reserve("abcXYZ", Some(1)), Some((1, 1)));
+        assert_eq!(workers(&table), [0, 0, 0]);
+        assert_eq!(table.assignment(0), None);
+        table.update(&[prefix("a", 5), BatchPattern { priority: 1, ..prefix("b", 5) }]);
+        assert_eq!(workers(&table), [6, 0, 10]);
+    }
+
+    #[test]
+    fn equal_priorities_or_too_few_workers_share_every_worker() {
+        let table = PatternTable::new(&[prefix("a", 5), prefix("b", 5)], false, 8);
+        assert_eq!(workers(&table), [0, 0]);
+        let urgent = BatchPattern { priority: 3, ..prefix("c", 5) };
+        let table = PatternTable::new(&[prefix("a", 5), prefix("b", 5), urgent], false, 2);
+        assert_eq!(workers(&table), [0, 0, 0]);
+    }
+
+    #[test]
+    fn shares_add_up_to_the_workers() {
+        assert_eq!(shares(&[11, 1], 8), [7, 1]);
+        assert_eq!(shares(&[2, 1], 8), [5, 3]);
+        assert_eq!(shares(&[1, 1, 1], 3), [1, 1, 1]);
+        assert_eq!(shares(&[100, 1, 1], 4), [2, 1, 1]);
+    }
+}
@@ -0,0 +1,159 @@
+//! `bench` command: measures key generation throughput and picks the batch
+//! size that performs best on this machine.
+
+use core::ops::ControlFlow;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use doppler_keygen::derive::Derivation;
+use doppler_keygen::entropy::Entropy;
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::scheduler::{self, Scheduler, WorkerConfig};
+use doppler_keygen::shutdown::ShutdownToken;
+use doppler_keygen::stats::StatsCollector;
+
+use crate::cli_error::CliError;
+
+/// Batch sizes tried by the bench, smallest first.
+const BATCH_SIZES: [usize; 6] = [1, 16, 64, 256, 1_024, 4_096];
+
+/// How long auto-tuning runs each setting before measuring it, so thread
+/// startup and cold caches are left out of the rate.
+const TUNE_WARMUP: Duration = Duration::from_millis(500);
+
+/// How long auto-tuning measures each setting for.
+const TUNE_TRIAL: Duration = Duration::from_secs(2);
+
+/// Runs candidate generation and the imm32 check at each batch size for
+/// `duration` and returns the batch size with the highest rate.
+pub fn run_bench(num_threads: usize, scheduler: Scheduler, duration: Duration) -> Result<usize, CliError> {
+    println!("Doppler Keygen - Benchmarking key generation");
+    println!("Using {num_threads} threads, {:.0} seconds per batch size\n", duration.as_secs_f64());
+
+    let mut best = (BATCH_SIZES[0], 0.0);
+    for batch_size in BATCH_SIZES {
+        let rate = measure_rate(num_threads, scheduler, batch_size, None, duration)?;
+        println!("  batch size {batch_size:>5}: {rate:>10.0} keys/sec");
+        if rate > best.1 {
+            best = (batch_size, rate);
+        }
+    }
+
+    println!("\nBest batch size: {} ({:.0} keys/sec)", best.0, best.1);
+    println!("Use it with --batch-size {} or `batch_size = {}` in the config file", best.0, best.0);
+
+    Ok(best.0)
+}
+
+/// Finds the thread count and batch size with the best sustained rate,
+/// including the cost of `derivation` if given. Each batch size is tried on
+/// `max_threads` workers, then fractions of `max_threads` are tried at the
+/// best of them. Returns the thread count and batch size.
+pub fn auto_tune(
+    max_threads: usize,
+    scheduler: Scheduler,
+    derivation: Option<Derivation>,
+) -> Result<(usize, usize), CliError> {
+    let mut thread_counts: Vec<usize> = [max_threads / 4, max_threads / 2, max_threads * 3 / 4, max_threads]
+        .into_iter()
+        .filter(|&n| n > 0)
+        .collect();
+    thread_counts.dedup();
+    let trial = TUNE_WARMUP + TUNE_TRIAL;
+    println!(
+        "Auto-tuning: {} settings, {:.1} seconds each",
+        BATCH_SIZES.len() + thread_counts.len() - 1,
+        trial.as_secs_f64()
+    );
+
+    let mut best = (max_threads, BATCH_SIZES[0], 0.0);
+    for batch_size in BATCH_SIZES {
+        let rate = sustained_rate(max_threads, scheduler, batch_size, derivation)?;
+        println!("  {max_threads:>3} threads, batch size {batch_size:>5}: {rate:>10.0} keys/sec");
+        if rate > best.2 {
+            best = (max_threads, batch_size, rate);
+        }
+    }
+    let batch_size = best.1;
+    // The full thread count at this batch size was measured above
+    for &num_threads in thread_counts.iter().filter(|&&n| n != max_threads) {
+        let rate = sustained_rate(num_threads, scheduler, batch_size, derivation)?;
+        println!("  {num_threads:>3} threads, batch size {batch_size:>5}: {rate:>10.0} keys/sec");
+        if rate > best.2 {
+            best = (num_threads, batch_size, rate);
+        }
+    }
+
+    println!("Best: {} threads, batch size {} ({:.0} keys/sec)", best.0, best.1, best.2);
+    Ok((best.0, best.1))
+}
+
+/// Runs the workers for [`TUNE_WARMUP`] and returns their rate over the
+/// [`TUNE_TRIAL`] that follows.
+fn sustained_rate(
+    num_threads: usize,
+    scheduler: Scheduler,
+    batch_size: usize,
+    derivation: Option<Derivation>,
+) -> Result<f64, CliError> {
+    let stats = Arc::new(StatsCollector::new(num_threads));
+    let shutdown = ShutdownToken::new();
+
+    let timer_stats = Arc::clone(&stats);
+    let timer_shutdown = shutdown.clone();
+    let timer = thread::Builder::new()
+        .name("tune-timer".to_string())
+        .spawn(move || {
+            thread::sleep(TUNE_WARMUP);
+            timer_stats.sample();
+            thread::sleep(TUNE_TRIAL);
+            let rate = timer_stats.sample().rate;
+            timer_shutdown.cancel();
+            rate
+        })
+        .map_err(DopplerError::SpawnThread)?;
+
+    let workers = WorkerConfig { scheduler, num_threads, batch_size, derivation, entropy: Entropy::default() };
+    scheduler::run_workers(&workers, &stats, &shutdown, |_, candidate| {
+        black_box(candidate.imm32_segment());
+        ControlFlow::Continue(())
+    })?;
+    // A timer that panicked measured nothing
+    Ok(timer.join().unwrap_or(0.0))
+}
+
+/// Generates and checks candidates on `num_threads` workers for `duration`
+/// and returns the average rate in keys per second, including the cost of
+/// `derivation` if given.
+pub fn measure_rate(
+    num_threads: usize,
+    scheduler: Scheduler,
+    batch_size: usize,
+    derivation: Option<Derivation>,
+    duration: Duration,
+) -> Result<f64, CliError> {
+    let stats = Arc::new(StatsCollector::new(num_threads));
+    let shutdown = ShutdownToken::new();
+
+    let timer_shutdown = shutdown.clone();
+    let timer = thread::Builder::new()
+        .name("bench-timer".to_string())
+        .spawn(move || {
+            thread::sleep(duration);
+            timer_shutdown.cancel();
+        })
+        .map_err(DopplerError::SpawnThread)?;
+
+    let workers = WorkerConfig { scheduler, num_threads, batch_size, derivation, entropy: Entropy::default() };
+    scheduler::run_workers(&workers, &stats, &shutdown, |_, candidate| {
+        black_box(candidate.imm32_segment());
+        ControlFlow::Continue(())
+    })?;
+    if timer.join().is_err() {
+        eprintln!("Bench timer panicked");
+    }
+
+    Ok(stats.summary().average_rate)
+}
@@ -0,0 +1,360 @@
+//! `blind-grind` and `blind-combine`: vanity keys ground by someone who
+//! never holds the secret.
+//!
+//! The requester makes a fresh keypair and hands over only its public key
+//! `A = a·G`. The grinder walks `A + t·G` for offsets `t`, which costs a
+//! point addition per attempt, and returns the offsets whose points are
+//! imm32 compatible and match the patterns. The requester then computes the
+//! secret scalar `a + t` locally. Knowing `t` and `A` tells the grinder
+//! nothing about `a + t`.
+//!
+//! An Ed25519 keypair file holds a seed that is hashed into the scalar, and
+//! there is no seed whose hash gives `a + t`, so the combined key is saved
+//! as its scalar in a `doppler-keygen` blind key file rather than in
+//! `solana-keygen` format.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::{clamp_integer, Scalar};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use zeroize::Zeroizing;
+
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::imm32;
+use doppler_keygen::pattern::BatchPattern;
+use doppler_keygen::shutdown::ShutdownToken;
+use doppler_keygen::stats::StatsCollector;
+
+use crate::cli_error::CliError;
+
+/// Points tried per batch; counters are only updated once per batch.
+const POINT_BATCH: usize = 1_024;
+
+/// Mixed into the nonce prefix of a combined key so it differs from the
+/// requester's own.
+const PREFIX_DOMAIN: &[u8] = b"doppler-keygen blind key nonce prefix";
+
+/// What to search for.
+#[derive(Debug, Clone)]
+pub struct BlindSearch {
+    /// The requester's public key
+    pub point: EdwardsPoint,
+    /// Patterns and how many offsets to find for each; an empty pattern
+    /// accepts any imm32 compatible address
+    pub patterns: Vec<BatchPattern>,
+    pub ignore_case: bool,
+}
+
+/// An offset whose point satisfied the search.
+#[derive(Debug, Clone)]
+pub struct BlindMatch {
+    /// The scalar to add to the requester's secret, little-endian
+    pub offset: [u8; 32],
+    /// The base58 address of the requester's key plus the offset
+    pub address: String,
+    /// The first segment that forms a valid 32-bit immediate
+    pub segment: usize,
+    pub pattern_index: usize,
+    /// Which of the pattern's offsets this is, counting from 1
+    pub pattern_number: usize,
+}
+
+/// Decodes a requester's public key, which must be a point of the prime
+/// order subgroup like every Ed25519 public key.
+pub fn parse_point(value: &str) -> Result<EdwardsPoint, String> {
+    bs58::decode(value)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| CompressedEdwardsY(bytes).decompress())
+        .filter(EdwardsPoint::is_torsion_free)
+        .ok_or_else(|| format!("'{value}' is not an Ed25519 public key"))
+}
+
+/// Decodes an offset printed by `blind-grind`.
+pub fn parse_offset(value: &str) -> Result<Scalar, String> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| Option::from(Scalar::from_canonical_bytes(bytes)))
+        .ok_or_else(|| format!("'{value}' is not an offset printed by blind-grind"))
+}
+
+/// Searches offsets on `num_threads` workers until every pattern has its
+/// offsets or `shutdown` is cancelled, calling `on_match` for each offset
+/// found.
+pub fn run<F>(
+    search: &BlindSearch,
+    num_threads: usize,
+    stats: &Arc<StatsCollector>,
+    shutdown: &ShutdownToken,
+    keys_found: &AtomicUsize,
+    on_match: F,
+) -> Result<Vec<BlindMatch>, CliError>
+where
+    F: Fn(&BlindMatch) + Sync,
+{
+    let total_count: usize = search.patterns.iter().map(|p| p.count).sum();
+    let found_per_pattern = Mutex::new(vec![0usize; search.patterns.len()]);
+    let matches = Mutex::new(Vec::new());
+
+    // Each worker starts its walk at its own random offset, so none repeats
+    // another's points. The starting offsets are drawn up front, where a
+    // failing OS RNG can still be reported
+    let start_offsets = (0..num_threads)
+        .map(|_| {
+            let mut wide = Zeroizing::new([0u8; 64]);
+            getrandom::getrandom(&mut *wide)
+                .map_err(|e| DopplerError::Entropy(format!("OS random number generator failed: {e}")))?;
+            Ok(Scalar::from_bytes_mod_order_wide(&wide))
+        })
+        .collect::<Result<Vec<_>, CliError>>()?;
+
+    let worker = |thread_id: usize, mut offset: Scalar| {
+        let mut point = search.point + EdwardsPoint::mul_base(&offset);
+        let mut addresses = vec![[0u8; 32]; POINT_BATCH];
+        let mut masks = vec![0u8; POINT_BATCH];
+
+        while !shutdown.is_cancelled() {
+            walk(&mut point, &mut addresses);
+            imm32::imm32_masks(&addresses, &mut masks);
+            stats.record_attempts(thread_id, POINT_BATCH as u64);
+
+            for (position, (address, &mask)) in addresses.iter().zip(&masks).enumerate() {
+                let Some(segment) = imm32::first_segment(mask) else {
+                    continue;
+                };
+                let address = bs58::encode(address).into_string();
+
+                let mut found = found_per_pattern.lock().unwrap_or_else(PoisonError::into_inner);
+                let matched = search.patterns.iter().enumerate().find(|(index, batch_pattern)| {
+                    found[*index] < batch_pattern.count
+                        && batch_pattern.pattern.matches_with_case(&address, search.ignore_case)
+                });
+                let Some((pattern_index, _)) = matched else {
+                    continue;
+                };
+                found[pattern_index] += 1;
+                let blind_match = BlindMatch {
+                    offset: (offset + Scalar::from(position as u64)).to_bytes(),
+                    address,
+                    segment,
+                    pattern_index,
+                    pattern_number: found[pattern_index],
+                };
+                drop(found);
+
+                on_match(&blind_match);
+                matches.lock().unwrap_or_else(PoisonError::into_inner).push(blind_match);
+                if keys_found.fetch_add(1, Ordering::Relaxed) + 1 >= total_count {
+                    shutdown.cancel();
+                }
+            }
+            offset += Scalar::from(POINT_BATCH as u64);
+        }
+    };
+
+    thread::scope(|scope| {
+        let handles = start_offsets
+            .into_iter()
+            .enumerate()
+            .map(|(thread_id, offset)| {
+                thread::Builder::new()
+                    .name(format!("worker-{thread_id}"))
+                    .spawn_scoped(scope, move || worker(thread_id, offset))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                shutdown.cancel();
+                DopplerError::SpawnThread(e)
+            })?;
+
+        let mut result = Ok(());
+        for (thread_id, handle) in handles.into_iter().enumerate() {
+            if handle.join().is_err() && result.is_ok() {
+                shutdown.cancel();
+                result = Err(DopplerError::WorkerPanicked(thread_id));
+            }
+        }
+        result
+    })?;
+
+    Ok(matches.into_inner().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Fills `addresses` with `point` and the points after it, one base point
+/// apart, so address `i` is at the batch's offset plus `i`. Leaves `point`
+/// at the start of the next batch.
+fn walk(point: &mut EdwardsPoint, addresses: &mut [[u8; 32]]) {
+    for address in addresses {
+        *address = point.compress().to_bytes();
+        *point += ED25519_BASEPOINT_POINT;
+    }
+}
+
+/// A requester's key combined with a ground offset.
+pub struct BlindKey {
+    pub pubkey: [u8; 32],
+    scalar: Zeroizing<Scalar>,
+    /// Hashed with each message into the signature's nonce, as the second
+    /// half of an expanded Ed25519 key is
+    nonce_prefix: Zeroizing<[u8; 32]>,
+}
+
+/// A blind key file: the combined key's scalar and nonce prefix in hex.
+#[derive(Serialize, Deserialize)]
+struct BlindKeyFile {
+    address: String,
+    scalar: String,
+    nonce_prefix: String,
+}
+
+impl BlindKey {
+    /// Adds `offset` to the secret scalar of the keypair with `seed`.
+    ///
+    /// The nonce prefix is derived from the requester's own prefix and the
+    /// offset. Reusing the requester's prefix unchanged would give both keys
+    /// the same nonce for a message, and since the grinder knows the offset,
+    /// one signature from each would reveal the secret.
+    pub fn combine(seed: &[u8; 32], offset: &Scalar) -> BlindKey {
+        let expanded = Zeroizing::new(<[u8; 64]>::from(Sha512::digest(seed)));
+        let (lower, upper) = expanded.split_at(32);
+        let secret = Zeroizing::new(Scalar::from_bytes_mod_order(clamp_integer(lower.try_into().expect("32 bytes"))));
+        let scalar = Zeroizing::new(*secret + offset);
+        let prefix = Sha512::new().chain_update(PREFIX_DOMAIN).chain_update(upper).chain_update(offset.as_bytes()).finalize();
+        BlindKey {
+            pubkey: EdwardsPoint::mul_base(&scalar).compress().to_bytes(),
+            scalar,
+            nonce_prefix: Zeroizing::new(prefix[..32].try_into().expect("32 bytes")),
+        }
+    }
+
+    pub fn address(&self) -> String {
+        bs58::encode(self.pubkey).into_string()
+    }
+
+    /// Signs `message` as RFC 8032 Ed25519 does with an expanded key.
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        let nonce = Zeroizing::new(Scalar::from_hash(
+            Sha512::new().chain_update(*self.nonce_prefix).chain_update(message),
+        ));
+        let r = EdwardsPoint::mul_base(&nonce).compress();
+        let challenge = Scalar::from_hash(Sha512::new().chain_update(r.as_bytes()).chain_update(self.pubkey).chain_update(message));
+        let s = challenge * *self.scalar + *nonce;
+
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(r.as_bytes());
+        signature[32..].copy_from_slice(s.as_bytes());
+        signature
+    }
+
+    /// The key in blind key file format.
+    pub fn to_json(&self) -> Zeroizing<Vec<u8>> {
+        let file = BlindKeyFile {
+            address: self.address(),
+            scalar: hex::encode(self.scalar.as_bytes()),
+            nonce_prefix: hex::encode(*self.nonce_prefix),
+        };
+        Zeroizing::new(serde_json::to_vec_pretty(&file).expect("a blind key serializes"))
+    }
+
+    /// Reads a blind key file, checking that its scalar gives its address.
+    pub fn from_json(contents: &[u8]) -> Result<BlindKey, String> {
+        let invalid = || "Not a blind key file written by blind-combine".to_string();
+        let file: BlindKeyFile = serde_json::from_slice(contents).map_err(|_| invalid())?;
+        let bytes32 = |value: &str| -> Option<[u8; 32]> { hex::decode(value).ok()?.try_into().ok() };
+        let scalar = bytes32(&file.scalar).and_then(|bytes| Option::from(Scalar::from_canonical_bytes(bytes))).ok_or_else(invalid)?;
+        let nonce_prefix = bytes32(&file.nonce_prefix).ok_or_else(invalid)?;
+
+        let key = BlindKey {
+            pubkey: EdwardsPoint::mul_base(&scalar).compress().to_bytes(),
+            scalar: Zeroizing::new(scalar),
+            nonce_prefix: Zeroizing::new(nonce_prefix),
+        };
+        if key.address() != file.address {
+            return Err(format!("The blind key file's scalar does not give its address {}", file.address));
+        }
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signature, VerifyingKey};
+    use solana_keypair::Keypair;
+    use solana_signer::Signer as _;
+
+    fn requester() -> Keypair {
+        Keypair::new_from_array([7; 32])
+    }
+
+    #[test]
+    fn combining_an_offset_gives_the_ground_address() {
+        let requester = requester();
+        let point = parse_point(&requester.pubkey().to_string()).unwrap();
+        let offset = Scalar::from(123_456_789u64);
+
+        let key = BlindKey::combine(requester.secret_bytes(), &offset);
+        assert_eq!(key.pubkey, (point + EdwardsPoint::mul_base(&offset)).compress().to_bytes());
+        assert_ne!(key.address(), requester.pubkey().to_string());
+    }
+
+    #[test]
+    fn combined_keys_sign_like_ed25519_keys() {
+        let key = BlindKey::combine(requester().secret_bytes(), &Scalar::from(42u64));
+        let signature = Signature::from_bytes(&key.sign(b"hello"));
+        let verifying_key = VerifyingKey::from_bytes(&key.pubkey).unwrap();
+        verifying_key.verify_strict(b"hello", &signature).unwrap();
+        assert!(verifying_key.verify_strict(b"goodbye", &signature).is_err());
+
+        // The nonce differs from the requester's, so the two keys never sign
+        // a message with the same R
+        let requester_signature = requester().sign_message(b"hello");
+        assert_ne!(signature.r_bytes(), &requester_signature.as_ref()[..32]);
+    }
+
+    #[test]
+    fn blind_key_files_round_trip_and_are_checked() {
+        let key = BlindKey::combine(requester().secret_bytes(), &Scalar::from(42u64));
+        let read = BlindKey::from_json(&key.to_json()).unwrap();
+        assert_eq!(read.pubkey, key.pubkey);
+        assert_eq!(read.sign(b"hello"), key.sign(b"hello"));
+
+        let mut file: serde_json::Value = serde_json::from_slice(&key.to_json()).unwrap();
+        file["address"] = requester().pubkey().to_string().into();
+        assert!(BlindKey::from_json(&serde_json::to_vec(&file).unwrap()).is_err());
+    }
+
+    #[test]
+    fn each_walked_address_is_the_key_of_its_offset() {
+        let requester = requester();
+        let offset = Scalar::from(987_654_321u64);
+        let mut point = parse_point(&requester.pubkey().to_string()).unwrap() + EdwardsPoint::mul_base(&offset);
+        let mut addresses = [[0u8; 32]; 4];
+        walk(&mut point, &mut addresses);
+
+        for (index, address) in addresses.iter().enumerate() {
+            let key = BlindKey::combine(requester.secret_bytes(), &(offset + Scalar::from(index as u64)));
+            assert_eq!(&key.pubkey, address);
+        }
+        let next = BlindKey::combine(requester.secret_bytes(), &(offset + Scalar::from(4u64)));
+        assert_eq!(point.compress().to_bytes(), next.pubkey);
+    }
+
+    #[test]
+    fn points_and_offsets_are_checked() {
+        assert!(parse_point(&requester().pubkey().to_string()).is_ok());
+        assert!(parse_point("not-base58!").is_err());
+        // A low order point is not an Ed25519 public key
+        assert!(parse_point(&bs58::encode([0u8; 32]).into_string()).is_err());
+
+        assert_eq!(parse_offset(&hex::encode(Scalar::from(5u64).to_bytes())).unwrap(), Scalar::from(5u64));
+        assert!(parse_offset("00").is_err());
+        assert!(parse_offset(&"ff".repeat(32)).is_err());
+    }
+}
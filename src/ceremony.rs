@@ -0,0 +1,160 @@
+//! `ceremony`: grind keys from entropy contributed by several participants.
+//!
+//! Participants contribute files or type text at the prompt. A SHA-256
+//! commitment of every contribution is printed before the search starts, so
+//! each participant can check that theirs was used. The contributions are
+//! then mixed into the master seed the workers draw from, as with
+//! `--entropy`. The run ends with a JSON transcript of the commitments, the
+//! search settings and the keys found, for auditors.
+
+use serde::Serialize;
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+use std::fs;
+use std::io::{self, BufRead as _, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use doppler_keygen::entropy::{Contribution, Entropy, EntropyInput};
+
+use crate::cli_error::CliError;
+
+/// A ceremony needs at least this many participant contributions.
+pub const MIN_PARTICIPANTS: usize = 2;
+
+/// How the contributions are mixed, as recorded in the transcript.
+const MIX: &str = "master = SHA-256(\"doppler-keygen entropy v1\" || for each contribution: length as u64 LE || \
+                   SHA-256(contribution)); commitment = SHA-256(\"doppler-keygen master seed commitment v1\" || master)";
+
+/// One entry of the transcript's contribution list.
+#[derive(Debug, Serialize)]
+struct TranscriptContribution {
+    participant: String,
+    bytes: usize,
+    /// Left out for OS randomness, which nobody needs to check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+/// The audit record of a ceremony.
+#[derive(Debug, Serialize)]
+struct Transcript<'a> {
+    version: u32,
+    /// Seconds since the Unix epoch
+    started_at: u64,
+    finished_at: u64,
+    /// The grind options of the search
+    arguments: &'a [String],
+    contributions: Vec<TranscriptContribution>,
+    mix: &'static str,
+    master_commitment: String,
+    keys: Vec<String>,
+}
+
+/// A ceremony whose entropy has been collected and committed to.
+pub struct Ceremony {
+    started_at: u64,
+    entropy: Entropy,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Collects a contribution from each file in `files` and from `interactive`
+/// participants at the prompt, adds OS randomness if `os_entropy` is set,
+/// and prints the commitments.
+pub fn collect(files: &[PathBuf], interactive: usize, os_entropy: bool) -> Result<Ceremony, CliError> {
+    if files.len() + interactive < MIN_PARTICIPANTS {
+        return Err(CliError::Usage(format!(
+            "a ceremony needs contributions from at least {MIN_PARTICIPANTS} participants; \
+             pass --contribution FILE or --participants N"
+        )));
+    }
+    let started_at = now();
+
+    let mut contributions = files.iter().map(|path| Contribution::file(path)).collect::<Result<Vec<_>, _>>()?;
+    for number in 1..=interactive {
+        contributions.push(prompt_contribution(number)?);
+    }
+    if os_entropy {
+        contributions.push(Contribution::os()?);
+    }
+    let entropy = Entropy::mix(contributions);
+
+    println!("\nCommitments (SHA-256 of each contribution):");
+    for input in entropy.inputs() {
+        match input.sha256 {
+            Some(sha256) => println!("  {}: {} ({} bytes)", input.name, hex::encode(sha256), input.bytes),
+            None => println!("  {}: {} bytes, not published", input.name, input.bytes),
+        }
+    }
+    let commitment = entropy.commitment().expect("a mixed seed has a commitment");
+    println!("Master seed commitment: {}", hex::encode(commitment));
+    println!("Each participant should check their commitment before the search starts\n");
+
+    Ok(Ceremony { started_at, entropy })
+}
+
+/// Asks participant `number` for their name and contribution.
+fn prompt_contribution(number: usize) -> Result<Contribution, CliError> {
+    let mut lines = io::stdin().lock().lines();
+    let mut read_line = |prompt: &str| -> Result<Option<String>, CliError> {
+        print!("{prompt}");
+        io::stdout().flush().map_err(CliError::Prompt)?;
+        lines.next().transpose().map_err(CliError::Prompt)
+    };
+
+    let name = read_line(&format!("Participant {number}, your name: "))?
+        .ok_or_else(|| CliError::Usage("stdin closed before every participant contributed".to_string()))?;
+    let name = match name.trim() {
+        "" => format!("participant {number}"),
+        name => name.to_string(),
+    };
+    println!("{name}, type your contribution (dice rolls, coin flips or random text) and end it with an empty line.");
+    println!("Keep the screen out of sight of the others.");
+    let mut text = String::new();
+    while let Some(line) = read_line("> ")? {
+        if line.is_empty() {
+            break;
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    if text.is_empty() {
+        return Err(CliError::Usage(format!("{name} gave no contribution")));
+    }
+    // Scroll the contribution off the screen before the next participant
+    print!("{}", "\n".repeat(50));
+    Ok(Contribution { name, bytes: text.into_bytes(), fingerprint: true })
+}
+
+impl Ceremony {
+    /// Seed material for the search.
+    pub fn entropy(&self) -> Entropy {
+        self.entropy.clone()
+    }
+
+    /// Writes the transcript of a search with `arguments` that found `keys`
+    /// to `output_dir` and returns its path.
+    pub fn write_transcript(&self, arguments: &[String], keys: &[Keypair], output_dir: &Path) -> Result<PathBuf, CliError> {
+        let transcript = Transcript {
+            version: 1,
+            started_at: self.started_at,
+            finished_at: now(),
+            arguments,
+            contributions: self.entropy.inputs().iter().map(transcript_contribution).collect(),
+            mix: MIX,
+            master_commitment: hex::encode(self.entropy.commitment().expect("a mixed seed has a commitment")),
+            keys: keys.iter().map(|keypair| keypair.pubkey().to_string()).collect(),
+        };
+        let path = output_dir.join(format!("ceremony-{}.json", self.started_at));
+        let json = serde_json::to_string_pretty(&transcript).expect("the transcript serializes");
+        fs::write(&path, format!("{json}\n")).map_err(|source| CliError::Transcript { path: path.clone(), source })?;
+        Ok(path)
+    }
+}
+
+fn transcript_contribution(input: &EntropyInput) -> TranscriptContribution {
+    TranscriptContribution { participant: input.name.clone(), bytes: input.bytes, sha256: input.sha256.map(hex::encode) }
+}
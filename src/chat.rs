@@ -0,0 +1,273 @@
+//! Found-key alerts posted to Telegram chats and Discord channels.
+//!
+//! Notifiers are named tables under `[notifiers]` in the config file, each
+//! posting to a Telegram bot's chat or to a Discord webhook. Every saved key
+//! is announced to every notifier with its `template`, in which `{pubkey}`,
+//! `{address}`, `{segment}`, `{pattern}` and `{host}` are filled in.
+//!
+//! Only public values can be placed in a message, and every message goes
+//! through the output policy in `redact` before it is sent. Bot tokens and webhook
+//! URLs are credentials too, so they are scrubbed from any error reported.
+//! A failed post is reported without stopping the run.
+
+use serde::Deserialize;
+use serde_json::json;
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use doppler_keygen::pattern::VanityPattern;
+
+use crate::cli_error::CliError;
+use crate::config::env_var;
+use crate::redact;
+
+/// How long posting a message may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Message used when a notifier has no template.
+const DEFAULT_TEMPLATE: &str = "Found {address} (segment {segment}) for {pattern} on {host}";
+
+/// Values a template can use; all of them are public.
+const PLACEHOLDERS: [&str; 5] = ["pubkey", "address", "segment", "pattern", "host"];
+
+const TELEGRAM_API: &str = "https://api.telegram.org";
+
+/// A `[notifiers.NAME]` table of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotifierConfig {
+    /// Discord webhook URL messages are posted to
+    pub discord_webhook: Option<String>,
+    /// Telegram chat the bot posts to
+    pub telegram_chat_id: Option<String>,
+    /// Token of the Telegram bot; `DOPPLER_TELEGRAM_BOT_TOKEN` if not set
+    pub telegram_bot_token: Option<String>,
+    /// Message posted for each saved key
+    pub template: Option<String>,
+}
+
+#[derive(Debug)]
+enum Channel {
+    Discord { url: String },
+    Telegram { token: String, chat_id: String },
+}
+
+/// An opened notifier.
+struct Notifier {
+    name: String,
+    channel: Channel,
+    template: String,
+}
+
+/// The notifiers a run posts to, by name.
+pub struct Notifiers {
+    agent: ureq::Agent,
+    host: String,
+    notifiers: Vec<Notifier>,
+}
+
+impl std::fmt::Debug for Notifiers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Replaces every occurrence of each of `credentials` in `text`. These are
+/// hidden even with `--reveal-secrets`, which only covers secret keys.
+fn hide_credentials(text: &str, credentials: &[&str]) -> String {
+    credentials
+        .iter()
+        .filter(|credential| !credential.is_empty())
+        .fold(text.to_string(), |text, credential| text.replace(credential, redact::REDACTED))
+}
+
+/// Fills in the placeholders of `template` from `values`, failing on one
+/// that isn't among them.
+fn render(template: &str, values: &[(&str, &str)]) -> Result<String, String> {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or_else(|| format!("template has an unclosed '{{' in '{template}'"))?;
+        let name = &rest[start + 1..start + end];
+        let value = values.iter().find(|(placeholder, _)| *placeholder == name).ok_or_else(|| {
+            format!("template uses {{{name}}}, but only {{{}}} can be used", PLACEHOLDERS.join("}, {"))
+        })?;
+        text.push_str(&rest[..start]);
+        text.push_str(value.1);
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+impl Notifier {
+    fn open(name: &str, config: &NotifierConfig) -> Result<Notifier, CliError> {
+        let notifier_error = |reason: String| CliError::Notifier { name: name.to_string(), reason };
+        let channel = match (&config.discord_webhook, &config.telegram_chat_id) {
+            (Some(url), None) if url.starts_with("https://") => Channel::Discord { url: url.clone() },
+            (Some(_), None) => return Err(notifier_error("discord_webhook must be an https:// URL".to_string())),
+            (None, Some(chat_id)) => {
+                let token = config
+                    .telegram_bot_token
+                    .clone()
+                    .or_else(|| env_var("DOPPLER_TELEGRAM_BOT_TOKEN"))
+                    .ok_or_else(|| notifier_error("telegram_chat_id needs a bot token in telegram_bot_token or DOPPLER_TELEGRAM_BOT_TOKEN".to_string()))?;
+                Channel::Telegram { token, chat_id: chat_id.clone() }
+            }
+            _ => return Err(notifier_error("it needs exactly one of discord_webhook or telegram_chat_id".to_string())),
+        };
+        if config.telegram_bot_token.is_some() && matches!(channel, Channel::Discord { .. }) {
+            return Err(notifier_error("telegram_bot_token requires telegram_chat_id".to_string()));
+        }
+        let template = config.template.clone().unwrap_or_else(|| DEFAULT_TEMPLATE.to_string());
+        render(&template, &PLACEHOLDERS.map(|name| (name, ""))).map_err(notifier_error)?;
+        Ok(Notifier { name: name.to_string(), channel, template })
+    }
+
+    /// The notifier's credentials, which never appear in what it reports.
+    fn credentials(&self) -> Vec<&str> {
+        match &self.channel {
+            Channel::Discord { url } => vec![url.as_str()],
+            Channel::Telegram { token, .. } => vec![token.as_str()],
+        }
+    }
+
+    fn post(&self, agent: &ureq::Agent, text: &str) -> Result<(), String> {
+        let result = match &self.channel {
+            Channel::Discord { url } => agent
+                .post(url)
+                .send_json(json!({ "content": text, "allowed_mentions": { "parse": [] } })),
+            Channel::Telegram { token, chat_id } => agent
+                .post(&format!("{TELEGRAM_API}/bot{token}/sendMessage"))
+                .send_json(json!({ "chat_id": chat_id, "text": text, "disable_web_page_preview": true })),
+        };
+        result.map(|_| ()).map_err(|e| hide_credentials(&e.to_string(), &self.credentials()))
+    }
+}
+
+impl Notifiers {
+    /// Opens every notifier in `configs`, checking its settings and template.
+    pub fn open(configs: &BTreeMap<String, NotifierConfig>) -> Result<Notifiers, CliError> {
+        let notifiers = configs
+            .iter()
+            .map(|(name, config)| Notifier::open(name, config))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Notifiers {
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            host: crate::audit::hostname(),
+            notifiers,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notifiers.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.notifiers.iter().map(|notifier| notifier.name.as_str())
+    }
+
+    /// Announces a saved key to every notifier. The key is already saved, so
+    /// failures are only reported.
+    pub fn key(&self, keypair: &Keypair, address: &str, segment: usize, pattern: Option<&VanityPattern>) {
+        let pubkey = keypair.pubkey().to_string();
+        let pattern = pattern.map_or_else(|| "imm32".to_string(), ToString::to_string);
+        let segment = segment.to_string();
+        let values = [
+            ("pubkey", pubkey.as_str()),
+            ("address", address),
+            ("segment", segment.as_str()),
+            ("pattern", pattern.as_str()),
+            ("host", self.host.as_str()),
+        ];
+        for notifier in &self.notifiers {
+            // Templates are checked when the notifier is opened
+            let text = render(&notifier.template, &values).unwrap_or_else(|_| notifier.template.clone());
+            // The secret can't be named in a template, but a pattern or host
+            // name could still spell it out
+            let text = redact::scrub(&text);
+            if let Err(e) = notifier.post(&self.agent, &text) {
+                eprintln!("Warning: notifier '{}' failed: {e}", notifier.name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discord(url: &str) -> NotifierConfig {
+        NotifierConfig { discord_webhook: Some(url.to_string()), ..NotifierConfig::default() }
+    }
+
+    fn telegram(token: &str) -> NotifierConfig {
+        NotifierConfig {
+            telegram_chat_id: Some("-1001".to_string()),
+            telegram_bot_token: Some(token.to_string()),
+            ..NotifierConfig::default()
+        }
+    }
+
+    fn open_error(config: &NotifierConfig) -> String {
+        Notifier::open("ops", config).err().expect("the notifier is refused").to_string()
+    }
+
+    #[test]
+    fn templates_fill_in_public_values() {
+        let values = [("address", "AbcXyz"), ("segment", "2"), ("host", "rig")];
+        assert_eq!(render("{address} in segment {segment} on {host}", &values).unwrap(), "AbcXyz in segment 2 on rig");
+        assert_eq!(render("no placeholders", &values).unwrap(), "no placeholders");
+
+        let error = render("{secret}", &values).unwrap_err();
+        assert!(error.contains("template uses {secret}"), "{error}");
+        assert!(render("found {address", &values).unwrap_err().contains("unclosed"));
+    }
+
+    #[test]
+    fn default_template_uses_only_known_placeholders() {
+        let values = PLACEHOLDERS.map(|name| (name, "x"));
+        assert_eq!(render(DEFAULT_TEMPLATE, &values).unwrap(), "Found x (segment x) for x on x");
+    }
+
+    #[test]
+    fn webhooks_must_be_https() {
+        let notifier = Notifier::open("ops", &discord("https://discord.com/api/webhooks/1/abc")).unwrap();
+        assert_eq!(notifier.credentials(), ["https://discord.com/api/webhooks/1/abc"]);
+        assert_eq!(notifier.template, DEFAULT_TEMPLATE);
+
+        let error = open_error(&discord("http://discord.com/api/webhooks/1/abc"));
+        assert!(error.contains("'ops'") && error.contains("https://"), "{error}");
+    }
+
+    #[test]
+    fn each_notifier_has_exactly_one_channel() {
+        let notifier = Notifier::open("ops", &telegram("123:token")).unwrap();
+        assert!(matches!(&notifier.channel, Channel::Telegram { chat_id, .. } if chat_id == "-1001"));
+        assert_eq!(notifier.credentials(), ["123:token"]);
+
+        let both = NotifierConfig { discord_webhook: Some("https://discord.com/x".to_string()), ..telegram("t") };
+        assert!(open_error(&both).contains("exactly one"));
+        assert!(open_error(&NotifierConfig::default()).contains("exactly one"));
+        let stray_token =
+            NotifierConfig { telegram_bot_token: Some("t".to_string()), ..discord("https://discord.com/x") };
+        assert!(open_error(&stray_token).contains("telegram_bot_token requires telegram_chat_id"));
+    }
+
+    #[test]
+    fn bad_templates_are_refused_when_opened() {
+        let config = NotifierConfig { template: Some("{address} {secret_key}".to_string()), ..telegram("t") };
+        assert!(open_error(&config).contains("template uses {secret_key}"));
+    }
+
+    #[test]
+    fn credentials_are_hidden_in_errors() {
+        let text = "POST https://api.telegram.org/bot123:token/sendMessage: 401, token 123:token";
+        assert_eq!(
+            hide_credentials(text, &["123:token", ""]),
+            "POST https://api.telegram.org/bot[redacted]/sendMessage: 401, token [redacted]"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Errors surfaced by the CLI commands: everything a grind run can fail with,
+//! plus saving, storing and reporting the keys it finds.
+
+use std::io;
+use std::path::PathBuf;
+
+use doppler_keygen::error::DopplerError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Grind(#[from] DopplerError),
+
+    #[error("failed to read keypair from {from}: {reason}")]
+    ReadKeypair { from: String, reason: String },
+
+    #[error("failed to write keypair file {path}: {source}")]
+    WriteKeypair { path: PathBuf, source: io::Error },
+
+    #[error("failed to create QR code: {0}")]
+    Qr(String),
+
+    #[error("failed to encrypt keypair: {0}")]
+    Encrypt(String),
+
+    #[error("machine key {path}: {reason}")]
+    MachineKey { path: PathBuf, reason: String },
+
+    #[error("failed to wrap keypair with KMS: {0}")]
+    Kms(String),
+
+    #[error("failed to store keypair in Vault: {0}")]
+    Vault(String),
+
+    #[error("PKCS#11: {0}")]
+    Pkcs11(String),
+
+    #[error("pattern sink '{name}': {reason}")]
+    Sink { name: String, reason: String },
+
+    #[error("notifier '{name}': {reason}")]
+    Notifier { name: String, reason: String },
+
+    #[error("failed to record public key in {path}: {source}")]
+    RecordPubkey { path: PathBuf, source: io::Error },
+
+    #[error("failed to update key index {path}: {source}")]
+    KeyIndex { path: PathBuf, source: io::Error },
+
+    #[error("failed to update audit log {path}: {source}")]
+    Audit { path: PathBuf, source: io::Error },
+
+    #[error("failed to update job store {path}: {reason}")]
+    JobStore { path: PathBuf, reason: String },
+
+    #[error("failed to update results database {path}: {reason}")]
+    Results { path: PathBuf, reason: String },
+
+    #[error("failed to write metadata sidecar {path}: {source}")]
+    Sidecar { path: PathBuf, source: io::Error },
+
+    #[error("failed to write handoff transaction {path}: {source}")]
+    Handoff { path: PathBuf, source: io::Error },
+
+    #[error("failed to write ceremony transcript {path}: {source}")]
+    Transcript { path: PathBuf, source: io::Error },
+
+    #[error("failed to shred {path}: {source}")]
+    Shred { path: PathBuf, source: io::Error },
+
+    #[error("refusing to emit duplicate key: {0}")]
+    DuplicateKey(String),
+
+    #[error("failed to create output directory {path}: {source}")]
+    CreateOutputDir { path: PathBuf, source: io::Error },
+
+    #[error("output directory {path} can't be used: {reason}")]
+    OutputDir { path: PathBuf, reason: String },
+
+    #[error("failed to determine available parallelism: {0}")]
+    Parallelism(io::Error),
+
+    #[error("failed to harden the process: {0}")]
+    Hardening(String),
+
+    #[error("refusing to run with --air-gapped: {0}")]
+    AirGap(String),
+
+    #[error("failed to read confirmation: {0}")]
+    Prompt(io::Error),
+
+    #[error("failed to install default keypair: {0}")]
+    InstallDefault(String),
+
+    #[error("{0}")]
+    Config(String),
+
+    #[error("{0}")]
+    Usage(String),
+}
@@ -0,0 +1,173 @@
+//! API clients of the daemon: bearer tokens, request rates and job quotas.
+//!
+//! Clients are named tables under `[clients]` in the config file. Each
+//! holds the SHA-256 of the client's token rather than the token itself, so
+//! the config file gives nothing away. Once any client is configured, every
+//! request but `/healthz` needs an `Authorization: Bearer TOKEN` header,
+//! and each client only sees and manages its own jobs unless it is an
+//! admin.
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::http::Request;
+
+/// The window `max_requests_per_minute` is counted over.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// A `[clients.NAME]` table of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Hex SHA-256 of the client's bearer token
+    pub token_sha256: String,
+    /// Sees and manages every client's jobs, and has no quotas
+    #[serde(default)]
+    pub admin: bool,
+    /// API requests allowed per minute
+    pub max_requests_per_minute: Option<u32>,
+    /// Jobs the client may have queued, running or paused at once
+    pub max_jobs: Option<usize>,
+    /// Highest priority the client may give a job
+    pub max_priority: Option<u32>,
+    /// Most attempts a job of the client's may be expected to need
+    pub max_attempts: Option<f64>,
+    /// Seconds a job of the client's may run before it is stopped
+    pub max_runtime: Option<u64>,
+}
+
+/// An authenticated client.
+#[derive(Debug)]
+pub struct Client {
+    pub name: String,
+    pub config: ClientConfig,
+    token_hash: [u8; 32],
+    /// Start of the current rate window and the requests made in it
+    window: Mutex<(Instant, u32)>,
+}
+
+impl Client {
+    /// Counts a request, returning false if it is over the client's rate.
+    fn allow_request(&self) -> bool {
+        let Some(limit) = self.config.max_requests_per_minute else {
+            return true;
+        };
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        if window.0.elapsed() >= RATE_WINDOW {
+            *window = (Instant::now(), 0);
+        }
+        window.1 += 1;
+        window.1 <= limit
+    }
+}
+
+/// The configured clients, by name.
+#[derive(Debug)]
+pub struct Clients(Vec<Client>);
+
+impl Clients {
+    /// Checks the `[clients]` tables: every token hash must be 64 hex digits
+    /// and belong to one client.
+    pub fn new(configs: &BTreeMap<String, ClientConfig>) -> Result<Clients, String> {
+        let mut clients: Vec<Client> = Vec::new();
+        for (name, config) in configs {
+            let token_hash: [u8; 32] = hex::decode(&config.token_sha256)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| format!("Client '{name}': token_sha256 must be 64 hex digits"))?;
+            if let Some(other) = clients.iter().find(|client| client.token_hash == token_hash) {
+                return Err(format!("Clients '{}' and '{name}' have the same token", other.name));
+            }
+            clients.push(Client {
+                name: name.clone(),
+                config: config.clone(),
+                token_hash,
+                window: Mutex::new((Instant::now(), 0)),
+            });
+        }
+        Ok(Clients(clients))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The client whose token `request` carries, and that is within its
+    /// request rate. Fails with the status and message to answer with.
+    pub fn authenticate(&self, request: &Request) -> Result<&Client, (u16, String)> {
+        let token = request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| (401, "Pass a client token in an Authorization: Bearer header".to_string()))?;
+        let hash: [u8; 32] = Sha256::digest(token.trim().as_bytes()).into();
+        // Every hash is compared in full, so timing shows nothing about the
+        // tokens
+        let client = self
+            .0
+            .iter()
+            .filter(|client| client.token_hash.iter().zip(&hash).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0)
+            .last()
+            .ok_or_else(|| (401, "Unknown client token".to_string()))?;
+        if !client.allow_request() {
+            return Err((429, format!("Client '{}' is over its request rate; try again in a minute", client.name)));
+        }
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(token: &str) -> ClientConfig {
+        ClientConfig { token_sha256: hex::encode(Sha256::digest(token.as_bytes())), ..ClientConfig::default() }
+    }
+
+    fn request(authorization: Option<&str>) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: "/jobs".to_string(),
+            headers: authorization.map(|value| ("authorization".to_string(), value.to_string())).into_iter().collect(),
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn requests_are_matched_to_clients_by_token() {
+        let clients = Clients::new(&BTreeMap::from([
+            ("alice".to_string(), client("alice-token")),
+            ("bob".to_string(), client("bob-token")),
+        ]))
+        .unwrap();
+        assert_eq!(clients.authenticate(&request(Some("Bearer bob-token"))).unwrap().name, "bob");
+        assert_eq!(clients.authenticate(&request(None)).unwrap_err().0, 401);
+        assert_eq!(clients.authenticate(&request(Some("bob-token"))).unwrap_err().0, 401);
+        let (status, message) = clients.authenticate(&request(Some("Bearer carol-token"))).unwrap_err();
+        assert_eq!((status, message.as_str()), (401, "Unknown client token"));
+    }
+
+    #[test]
+    fn requests_over_the_rate_are_refused() {
+        let config = ClientConfig { max_requests_per_minute: Some(2), ..client("alice-token") };
+        let clients = Clients::new(&BTreeMap::from([("alice".to_string(), config)])).unwrap();
+        let request = request(Some("Bearer alice-token"));
+        assert!(clients.authenticate(&request).is_ok());
+        assert!(clients.authenticate(&request).is_ok());
+        assert_eq!(clients.authenticate(&request).unwrap_err().0, 429);
+    }
+
+    #[test]
+    fn token_hashes_are_checked() {
+        let short = ClientConfig { token_sha256: "abcd".to_string(), ..ClientConfig::default() };
+        assert!(Clients::new(&BTreeMap::from([("alice".to_string(), short)])).unwrap_err().contains("64 hex digits"));
+        let error = Clients::new(&BTreeMap::from([
+            ("alice".to_string(), client("shared")),
+            ("bob".to_string(), client("shared")),
+        ]))
+        .unwrap_err();
+        assert_eq!(error, "Clients 'alice' and 'bob' have the same token");
+    }
+}
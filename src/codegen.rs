@@ -0,0 +1,399 @@
+//! Comparison code generated from addresses.
+//!
+//! Each 8-byte segment of an address is compared as a u64. A segment that
+//! is imm32 compatible can be compared against a `jne` immediate directly;
+//! any other segment first needs an `lddw` into a scratch register.
+
+use doppler_keygen::imm32;
+
+/// How one segment of an address is compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    /// Fits a sign-extended 32-bit immediate
+    Imm32(i32),
+    /// Needs the full 64-bit value
+    Wide(u64),
+}
+
+impl Segment {
+    /// The constant as written in an `.equ` directive.
+    pub fn constant(self) -> String {
+        match self {
+            Segment::Imm32(value) => format!("0x{:08x}", value as u32),
+            Segment::Wide(value) => format!("0x{value:016x}"),
+        }
+    }
+}
+
+/// The segments of an address, in address order.
+pub fn segments(address: &[u8; 32]) -> [Segment; 4] {
+    core::array::from_fn(|segment| {
+        let bytes: [u8; 8] = address[segment * 8..segment * 8 + 8].try_into().expect("segments are 8 bytes");
+        if imm32::segment_is_imm32_compatible(address, segment) {
+            Segment::Imm32(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        } else {
+            Segment::Wide(u64::from_le_bytes(bytes))
+        }
+    })
+}
+
+/// What `address` and `address-set` generate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeFormat {
+    /// sBPF assembly
+    Asm,
+    /// An `is_admin` function for pinocchio-style Rust programs
+    Pinocchio,
+    /// Constants and an `only_admin` check for Anchor programs
+    Anchor,
+}
+
+impl CodeFormat {
+    pub fn parse(value: &str) -> Result<CodeFormat, String> {
+        match value {
+            "asm" => Ok(CodeFormat::Asm),
+            "pinocchio" => Ok(CodeFormat::Pinocchio),
+            "anchor" => Ok(CodeFormat::Anchor),
+            _ => Err(format!("Unknown format '{value}' (expected asm, pinocchio or anchor)")),
+        }
+    }
+}
+
+/// Segment indices in the order they are best compared: immediates first,
+/// so a mismatch is usually found without an `lddw`.
+fn comparison_order(segments: &[Segment; 4]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..4).collect();
+    order.sort_by_key(|&segment| matches!(segments[segment], Segment::Wide(_)));
+    order
+}
+
+/// Appends instructions leaving in `diff` the OR of each segment in r2-r5
+/// XORed with the constants `{prefix}_0`-`{prefix}_3`, which is zero only if
+/// the key matches. `scratch` holds wide constants.
+fn push_xor_segments(asm: &mut String, prefix: &str, segments: &[Segment; 4], diff: &str, scratch: &str) {
+    asm.push_str(&format!("  mov64 {diff}, 0\n"));
+    for (segment, value) in segments.iter().enumerate() {
+        let register = segment + 2;
+        match value {
+            Segment::Imm32(_) => {
+                asm.push_str(&format!("  mov64 {scratch}, r{register}\n"));
+                asm.push_str(&format!("  xor64 {scratch}, {prefix}_{segment}\n"));
+            }
+            Segment::Wide(_) => {
+                asm.push_str(&format!("  lddw {scratch}, {prefix}_{segment}\n"));
+                asm.push_str(&format!("  xor64 {scratch}, r{register}\n"));
+            }
+        }
+        asm.push_str(&format!("  or64 {diff}, {scratch}\n"));
+    }
+}
+
+/// The `.equ` directives for the constants `{prefix}_0`-`{prefix}_3` of
+/// `address`.
+pub fn constants_asm(prefix: &str, address: &[u8; 32]) -> String {
+    segments(address)
+        .iter()
+        .enumerate()
+        .map(|(segment, value)| format!(".equ {prefix}_{segment}, {}\n", value.constant()))
+        .collect()
+}
+
+/// Generates a comparison of the key at `r1` with the constants
+/// `EXPECTED_ADMIN_KEY_0`-`EXPECTED_ADMIN_KEY_3`, jumping to `abort` at the
+/// first mismatching segment and falling through on a match. imm32
+/// compatible segments are compared as immediates; the others are loaded
+/// into r3 first. r2 holds each segment of the key in turn.
+pub fn address_asm(address: &[u8; 32]) -> String {
+    let mut asm = String::new();
+    for (segment, value) in segments(address).iter().enumerate() {
+        asm.push_str(&format!("  ldxdw r2, [r1+ADMIN_KEY_{segment}]\n"));
+        match value {
+            Segment::Imm32(_) => asm.push_str(&format!("  jne r2, EXPECTED_ADMIN_KEY_{segment}, abort\n")),
+            Segment::Wide(_) => {
+                asm.push_str(&format!("  lddw r3, EXPECTED_ADMIN_KEY_{segment}\n"));
+                asm.push_str("  jne r2, r3, abort\n");
+            }
+        }
+        asm.push('\n');
+    }
+    asm
+}
+
+/// Generates a branch-free comparison of the key at `r1` with the constants
+/// `EXPECTED_ADMIN_KEY_0`-`EXPECTED_ADMIN_KEY_3`: every segment is always
+/// compared, and the only branch is the final jump to `abort`. r2-r5 hold
+/// the key's segments, with r0 and r6 used as scratch.
+pub fn constant_time_asm(address: &[u8; 32]) -> String {
+    let mut asm = String::new();
+    for segment in 0..4 {
+        asm.push_str(&format!("  ldxdw r{}, [r1+ADMIN_KEY_{segment}]\n", segment + 2));
+    }
+    push_xor_segments(&mut asm, "EXPECTED_ADMIN_KEY", &segments(address), "r6", "r0");
+    asm.push_str("  jne r6, 0, abort\n");
+    asm
+}
+
+/// Generates an sBPF routine that checks the key at `r1` against every
+/// address in `admins`.
+///
+/// The key's segments are loaded into r2-r5 once, then each admin is tried
+/// in turn, moving on to the next at its first mismatching segment. A match
+/// jumps to `is_admin`; after the last admin the routine jumps to
+/// `not_admin`. Both labels, and the `ADMIN_KEY_0`-`ADMIN_KEY_3` offsets,
+/// are defined by the surrounding program. r0 is used as scratch.
+///
+/// With `constant_time` every segment of every admin is compared whichever
+/// admin matches, and the routine branches only once at the end, so its
+/// running time says nothing about the key. r0 and r6-r8 are used as
+/// scratch.
+pub fn address_set_asm(admins: &[[u8; 32]], constant_time: bool) -> String {
+    let mut asm = String::new();
+    asm.push_str(&format!("; Admin set: {} keys\n", admins.len()));
+    for (index, admin) in admins.iter().enumerate() {
+        asm.push_str(&format!(";   ADMIN_{index}: {}\n", bs58::encode(admin).into_string()));
+    }
+
+    asm.push('\n');
+    for (index, admin) in admins.iter().enumerate() {
+        asm.push_str(&constants_asm(&format!("ADMIN_{index}_KEY"), admin));
+    }
+
+    asm.push_str("\ncheck_admins:\n");
+    for segment in 0..4 {
+        asm.push_str(&format!("  ldxdw r{}, [r1+ADMIN_KEY_{segment}]\n", segment + 2));
+    }
+    if constant_time {
+        // r6 stays 1 while every admin so far differs from the key:
+        // (diff | -diff) >> 63 is 1 for a nonzero diff and 0 for a match
+        asm.push_str("  mov64 r6, 1\n");
+        for (index, admin) in admins.iter().enumerate() {
+            asm.push_str(&format!("  ; ADMIN_{index}\n"));
+            push_xor_segments(&mut asm, &format!("ADMIN_{index}_KEY"), &segments(admin), "r7", "r0");
+            asm.push_str("  mov64 r8, 0\n");
+            asm.push_str("  sub64 r8, r7\n");
+            asm.push_str("  or64 r8, r7\n");
+            asm.push_str("  rsh64 r8, 63\n");
+            asm.push_str("  and64 r6, r8\n");
+        }
+        asm.push_str("  jne r6, 0, not_admin\n");
+        asm.push_str("  ja is_admin\n");
+        return asm;
+    }
+    for (index, admin) in admins.iter().enumerate() {
+        let segments = segments(admin);
+        let next = if index + 1 == admins.len() { "not_admin".to_string() } else { format!("admin_{}", index + 1) };
+        asm.push_str(&format!("admin_{index}:\n"));
+        for segment in comparison_order(&segments) {
+            let register = segment + 2;
+            match segments[segment] {
+                Segment::Imm32(_) => {
+                    asm.push_str(&format!("  jne r{register}, ADMIN_{index}_KEY_{segment}, {next}\n"));
+                }
+                Segment::Wide(_) => {
+                    asm.push_str(&format!("  lddw r0, ADMIN_{index}_KEY_{segment}\n"));
+                    asm.push_str(&format!("  jne r{register}, r0, {next}\n"));
+                }
+            }
+        }
+        asm.push_str("  ja is_admin\n");
+    }
+    asm
+}
+
+/// Helpers for `rust_test` output; a base58 decoder is included so the
+/// test needs no dependencies.
+const RUST_TEST_HELPERS: &str = r#"/// Rebuilds an address from its segment constants, as the program compares
+/// them: each segment is a little-endian u64, and an imm32 constant is
+/// sign-extended to 64 bits.
+fn rebuild(segments: [u64; 4]) -> [u8; 32] {
+    let mut address = [0u8; 32];
+    for (chunk, segment) in address.chunks_exact_mut(8).zip(segments) {
+        chunk.copy_from_slice(&segment.to_le_bytes());
+    }
+    address
+}
+
+#[allow(dead_code)]
+fn imm32(value: i32) -> u64 {
+    i64::from(value) as u64
+}
+
+fn decode_base58(encoded: &str) -> [u8; 32] {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    let mut bytes = [0u8; 32];
+    for character in encoded.bytes() {
+        let mut carry = ALPHABET.iter().position(|&c| c == character).expect("base58 character") as u32;
+        for byte in bytes.iter_mut().rev() {
+            carry += u32::from(*byte) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        assert_eq!(carry, 0, "address is longer than 32 bytes");
+    }
+    bytes
+}
+"#;
+
+/// Generates a Rust test file that rebuilds each address from the segment
+/// constants named `{prefix}_0` to `{prefix}_3` and checks it against the
+/// base58 address, catching constants that were mistyped or byte-swapped on
+/// their way into a program.
+pub fn rust_test(keys: &[(String, [u8; 32])]) -> String {
+    let mut test = String::new();
+    test.push_str("//! Generated by doppler-keygen: checks that the segment constants rebuild\n");
+    test.push_str("//! the addresses they were generated from.\n\n");
+    for (prefix, address) in keys {
+        for (segment, value) in segments(address).iter().enumerate() {
+            match value {
+                Segment::Imm32(value) => {
+                    test.push_str(&format!("const {prefix}_{segment}: i32 = 0x{:08x}_u32 as i32;\n", *value as u32));
+                }
+                Segment::Wide(value) => test.push_str(&format!("const {prefix}_{segment}: u64 = 0x{value:016x};\n")),
+            }
+        }
+    }
+    test.push('\n');
+    test.push_str(RUST_TEST_HELPERS);
+
+    for (prefix, address) in keys {
+        let segments = segments(address);
+        let values: Vec<String> = (0..4)
+            .map(|segment| match segments[segment] {
+                Segment::Imm32(_) => format!("imm32({prefix}_{segment})"),
+                Segment::Wide(_) => format!("{prefix}_{segment}"),
+            })
+            .collect();
+        test.push_str(&format!("\n#[test]\nfn {}_rebuilds_address() {{\n", prefix.to_lowercase()));
+        test.push_str(&format!("    let segments = [{}];\n", values.join(", ")));
+        test.push_str(&format!(
+            "    assert_eq!(rebuild(segments), decode_base58(\"{}\"));\n}}\n",
+            bs58::encode(address).into_string()
+        ));
+    }
+    test
+}
+
+/// Appends the segments of `address` as u64 array elements, one per line,
+/// annotating imm32 compatible ones.
+fn push_segment_values(code: &mut String, address: &[u8; 32], indent: &str) {
+    for value in segments(address) {
+        match value {
+            Segment::Imm32(value) => {
+                code.push_str(&format!("{indent}0x{:016x}, // imm32 0x{:08x}\n", i64::from(value) as u64, value as u32));
+            }
+            Segment::Wide(value) => code.push_str(&format!("{indent}0x{value:016x},\n")),
+        }
+    }
+}
+
+/// Generates `is_admin(key: &[u8; 32]) -> bool` for Rust programs that
+/// compare keys as four u64 segments instead of with `Pubkey::eq`.
+///
+/// A single address is named `ADMIN`, a set `ADMIN_0`, `ADMIN_1`, ... Each
+/// admin's imm32 segments are compared first and annotated, since they
+/// compile to a `jne` against an immediate. With `constant_time` the
+/// segments are XORed and ORed together and the function has no early exit.
+pub fn pinocchio(admins: &[[u8; 32]], constant_time: bool) -> String {
+    let names: Vec<String> = match admins {
+        [_] => vec!["ADMIN".to_string()],
+        _ => (0..admins.len()).map(|index| format!("ADMIN_{index}")).collect(),
+    };
+
+    let mut code = String::new();
+    code.push_str("// Generated by doppler-keygen. Each segment is 8 bytes of the address as a\n");
+    code.push_str("// little-endian u64; imm32 segments compare against a sign-extended immediate.\n");
+    for (name, admin) in names.iter().zip(admins) {
+        code.push_str(&format!("\n/// {}\nconst {name}: [u64; 4] = [\n", bs58::encode(admin).into_string()));
+        push_segment_values(&mut code, admin, "    ");
+        code.push_str("];\n");
+    }
+
+    code.push_str("\n#[inline(always)]\nfn segment(key: &[u8; 32], index: usize) -> u64 {\n");
+    code.push_str("    // SAFETY: callers pass index < 4, so all 8 bytes read are inside key\n");
+    code.push_str("    u64::from_le(unsafe { core::ptr::read_unaligned(key.as_ptr().add(index * 8).cast::<u64>()) })\n}\n");
+    code.push_str("\n#[inline(always)]\nfn is_admin(key: &[u8; 32]) -> bool {\n");
+    code.push_str("    let key = [segment(key, 0), segment(key, 1), segment(key, 2), segment(key, 3)];\n");
+
+    let xor = |name: &str| (0..4).map(|segment| format!("(key[{segment}] ^ {name}[{segment}])")).collect::<Vec<_>>().join(" | ");
+    match (constant_time, &names[..]) {
+        (true, [name]) => code.push_str(&format!("    ({}) == 0\n", xor(name))),
+        (true, _) => {
+            // differs stays 1 while every admin differs from the key
+            code.push_str("    let mut differs = 1;\n");
+            for name in &names {
+                code.push_str(&format!("    let diff = {};\n", xor(name)));
+                code.push_str("    differs &= (diff | diff.wrapping_neg()) >> 63;\n");
+            }
+            code.push_str("    differs == 0\n");
+        }
+        (false, _) => {
+            let checks: Vec<String> = names
+                .iter()
+                .zip(admins)
+                .map(|(name, admin)| {
+                    comparison_order(&segments(admin))
+                        .iter()
+                        .map(|segment| format!("key[{segment}] == {name}[{segment}]"))
+                        .collect::<Vec<_>>()
+                        .join(" && ")
+                })
+                .collect();
+            match &checks[..] {
+                [check] => code.push_str(&format!("    {check}\n")),
+                _ => code.push_str(&format!("    ({})\n", checks.join(")\n        || ("))),
+            }
+        }
+    }
+    code.push_str("}\n");
+    code
+}
+
+/// Generates an Anchor snippet: `declare_id!` for a single address, in
+/// case it is the program's own keypair, an `admin` module of constants,
+/// and `only_admin(key: &Pubkey) -> Result<()>` for `#[access_control]`.
+pub fn anchor(admins: &[[u8; 32]]) -> String {
+    let encoded: Vec<String> = admins.iter().map(|admin| bs58::encode(admin).into_string()).collect();
+    let mut code = String::new();
+    code.push_str("// Generated by doppler-keygen.\nuse anchor_lang::prelude::*;\n\n");
+    if let [address] = &encoded[..] {
+        code.push_str(&format!("// If this is the program's own keypair\ndeclare_id!(\"{address}\");\n\n"));
+    }
+
+    code.push_str("pub mod admin {\n    use anchor_lang::prelude::*;\n\n");
+    match (&encoded[..], admins) {
+        ([address], [admin]) => {
+            code.push_str(&format!("    pub const ADMIN: Pubkey = pubkey!(\"{address}\");\n\n"));
+            code.push_str("    /// ADMIN as four little-endian u64 segments\n    pub const ADMIN_SEGMENTS: [u64; 4] = [\n");
+            push_segment_values(&mut code, admin, "        ");
+            code.push_str("    ];\n");
+        }
+        _ => {
+            code.push_str(&format!("    pub const ADMINS: [Pubkey; {}] = [\n", admins.len()));
+            for address in &encoded {
+                code.push_str(&format!("        pubkey!(\"{address}\"),\n"));
+            }
+            code.push_str("    ];\n\n");
+            code.push_str(&format!(
+                "    /// ADMINS as four little-endian u64 segments each\n    pub const ADMIN_SEGMENTS: [[u64; 4]; {}] = [\n",
+                admins.len()
+            ));
+            for admin in admins {
+                code.push_str("        [\n");
+                push_segment_values(&mut code, admin, "            ");
+                code.push_str("        ],\n");
+            }
+            code.push_str("    ];\n");
+        }
+    }
+    code.push_str("}\n\n");
+
+    code.push_str("/// Fails unless `key` is an admin. Use it as\n");
+    code.push_str("/// `#[access_control(only_admin(ctx.accounts.authority.key))]`.\n");
+    code.push_str("pub fn only_admin(key: &Pubkey) -> Result<()> {\n");
+    match admins {
+        [_] => code.push_str("    require_keys_eq!(*key, admin::ADMIN);\n"),
+        _ => code.push_str("    require!(admin::ADMINS.contains(key), anchor_lang::error::ErrorCode::ConstraintAddress);\n"),
+    }
+    code.push_str("    Ok(())\n}\n");
+    code
+}
@@ -0,0 +1,147 @@
+//! The subcommands of the CLI, one module each.
+//!
+//! Every module parses its arguments into a struct of its own and exposes a
+//! `command` entry point that `main` dispatches to. `grind` also owns the
+//! flag table the commands built on it check their conflicts against; the
+//! helpers below are shared by the smaller commands.
+
+pub mod address;
+pub mod address_set;
+pub mod bench;
+pub mod blind_combine;
+pub mod blind_grind;
+pub mod ceremony;
+pub mod combine;
+pub mod daemon;
+pub mod diff;
+pub mod due;
+pub mod from_dice;
+pub mod grind;
+pub mod grind_seed;
+pub mod import;
+pub mod inspect;
+pub mod inventory;
+pub mod job;
+pub mod kms_decrypt;
+pub mod lookup_table;
+pub mod machine_key;
+pub mod migrate;
+pub mod mint_vanity;
+pub mod paper;
+pub mod pool;
+pub mod repl;
+pub mod rotate;
+pub mod selftest;
+pub mod shred;
+pub mod sign;
+pub mod simulate;
+pub mod verify_audit;
+pub mod verify_sig;
+pub mod work_unit;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use doppler_keygen::pattern::BatchPattern;
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+
+use crate::codegen::{self, CodeFormat};
+use crate::encrypt;
+use crate::grind::OutputFormat;
+use crate::keysource::{self, KeypairSource};
+use crate::machinekey::MachineKey;
+use crate::secretfile;
+
+/// Falls back to the base58 keypair in `DOPPLER_KEYPAIR` when a command
+/// was given no keypair.
+pub fn keypair_sources(sources: Vec<KeypairSource>, command: &str) -> Result<Vec<KeypairSource>, String> {
+    if !sources.is_empty() {
+        return Ok(sources);
+    }
+    KeypairSource::from_env().map(|source| vec![source]).ok_or_else(|| {
+        format!("{command} requires a keypair (file, prompt://, stdin://, usb://ledger, --keypair-base58 or {})", keysource::KEYPAIR_ENV)
+    })
+}
+
+/// Rejects a count given alongside batch patterns, and `--ignore-case`
+/// without any.
+pub fn check_patterns(count: Option<usize>, patterns: &[BatchPattern], ignore_case: bool) -> Result<(), String> {
+    if count.is_some() && !patterns.is_empty() {
+        return Err("A count cannot be combined with --starts-with/--ends-with patterns; \
+                    give the count in each PATTERN:COUNT instead"
+            .to_string());
+    }
+    if ignore_case && patterns.is_empty() {
+        return Err("--ignore-case requires at least one vanity pattern".to_string());
+    }
+    Ok(())
+}
+
+/// Rejects `--constant-time` for formats that have no such variant.
+pub fn check_code_options(format: CodeFormat, constant_time: bool) -> Result<(), String> {
+    if constant_time && format == CodeFormat::Anchor {
+        return Err("--constant-time applies to --format asm and pinocchio, not anchor".to_string());
+    }
+    Ok(())
+}
+
+/// Parses a base58 address argument, unless a file of that name exists.
+pub fn parse_address(arg: &str) -> Option<[u8; 32]> {
+    if Path::new(arg).exists() {
+        return None;
+    }
+    bs58::decode(arg).into_vec().ok().and_then(|bytes| bytes.try_into().ok())
+}
+
+/// Reads a key argument for commands that only need the address: a base58
+/// address, or any keypair argument.
+pub fn read_address(arg: &str) -> Result<[u8; 32], String> {
+    if let Some(address) = parse_address(arg) {
+        return Ok(address);
+    }
+    let source = KeypairSource::parse(arg).map_err(|e| e.to_string())?;
+    source.pubkey().map_err(|e| e.to_string())
+}
+
+/// Reads the message given with `--message TEXT` or `--message-file FILE`.
+pub fn read_message(flag: &str, value: &str) -> Result<Vec<u8>, String> {
+    match flag {
+        "--message-file" => fs::read(value).map_err(|e| format!("Failed to read {value}: {e}")),
+        _ => Ok(value.as_bytes().to_vec()),
+    }
+}
+
+/// Saves a keypair made outside a grind the way a grind saves one: in
+/// `solana-keygen` format, encrypted to the machine key as
+/// `<pubkey>.json.age` unless `plaintext`, to `output` if given.
+pub fn save_single_keypair(keypair: &Keypair, output: Option<PathBuf>, plaintext: bool) -> Result<PathBuf, String> {
+    let name = format!("{}.{}", keypair.pubkey(), OutputFormat::Json.extension());
+    save_single_secret(name, OutputFormat::Json.encode(keypair), output, plaintext)
+}
+
+/// Writes a secret key file named `name`, encrypted to the machine key as
+/// `<name>.age` unless `plaintext`, to `output` if given.
+pub fn save_single_secret(name: String, json: Vec<u8>, output: Option<PathBuf>, plaintext: bool) -> Result<PathBuf, String> {
+    let (default_name, contents) = if plaintext {
+        println!("Encryption at rest: off (--plaintext); the keypair file is written unencrypted");
+        (name, json)
+    } else {
+        let (machine_key, created) = MachineKey::load_or_create().map_err(|e| e.to_string())?;
+        if created {
+            println!("Created machine key: {}", machine_key.path.display());
+        }
+        println!("Encryption at rest: the keypair file is encrypted to the machine key {}", machine_key.public_key());
+        (format!("{name}.{}", encrypt::ENCRYPTED_EXTENSION), machine_key.recipients().encrypt(&json).map_err(|e| e.to_string())?)
+    };
+    let path = output.unwrap_or_else(|| PathBuf::from(default_name));
+    secretfile::write(&path, &contents).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+/// Writes the `--rust-test` file for generated constants.
+pub fn write_rust_test(path: &Path, keys: &[(String, [u8; 32])]) -> Result<(), String> {
+    fs::write(path, codegen::rust_test(keys)).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    println!("Wrote a test for the constants to {}", path.display());
+    Ok(())
+}
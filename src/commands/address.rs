@@ -0,0 +1,185 @@
+//! The `address` command: the assembly constants and comparison code for a key's address.
+
+use std::path::PathBuf;
+
+use doppler_keygen::imm32;
+
+use crate::codegen::{self, CodeFormat};
+use crate::commands::{check_code_options, keypair_sources, parse_address, write_rust_test};
+use crate::keysource::KeypairSource;
+use crate::rpc::{self, RpcClient};
+
+/// Programs that `address --program` accepts by name.
+const KNOWN_PROGRAMS: &[(&str, &str)] = &[
+    ("system", "11111111111111111111111111111111"),
+    ("token", "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+    ("token-2022", "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"),
+    ("associated-token", "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL"),
+    ("memo", "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"),
+    ("compute-budget", "ComputeBudget111111111111111111111111111111"),
+    ("address-lookup-table", "AddressLookupTab1e1111111111111111111111111"),
+    ("bpf-loader-upgradeable", "BPFLoaderUpgradeab1e11111111111111111111111"),
+    ("stake", "Stake11111111111111111111111111111111111111"),
+    ("vote", "Vote111111111111111111111111111111111111111"),
+    ("config", "Config1111111111111111111111111111111111111"),
+];
+
+/// What `address` prints the comparison code for.
+enum Target {
+    Address([u8; 32]),
+    Keypair(KeypairSource),
+    /// A program name or address, looked up on `cluster`
+    Program { program: String, cluster: String },
+}
+
+/// Arguments of `address`: `address <keypair>`, `address <pubkey>`,
+/// `address --keypair-base58 SECRET` or `address --program NAME [--rpc URL]`.
+struct AddressArgs {
+    target: Target,
+    rust_test: Option<PathBuf>,
+    constant_time: bool,
+    format: CodeFormat,
+}
+
+impl AddressArgs {
+    fn parse(args: &[String]) -> Result<AddressArgs, String> {
+        let mut sources = Vec::new();
+        let mut addresses = Vec::new();
+        let mut program = None;
+        let mut cluster = None;
+        let mut rust_test = None;
+        let mut constant_time = false;
+        let mut format = CodeFormat::Asm;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--keypair-base58" => sources.push(KeypairSource::Base58(value()?.clone())),
+                "--program" => program = Some(value()?.clone()),
+                "--rpc" => cluster = Some(value()?.clone()),
+                "--rust-test" => rust_test = Some(PathBuf::from(value()?)),
+                "--constant-time" => constant_time = true,
+                "--format" => format = CodeFormat::parse(value()?)?,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                other if program.is_some() => return Err(format!("Unexpected argument '{other}'")),
+                source => match parse_address(source) {
+                    Some(address) => addresses.push(address),
+                    None => sources.push(KeypairSource::parse(source).map_err(|e| e.to_string())?),
+                },
+            }
+        }
+        check_code_options(format, constant_time)?;
+
+        let target = match (program, &addresses[..]) {
+            (Some(program), []) if sources.is_empty() => {
+                Target::Program { program, cluster: cluster.unwrap_or_else(|| "mainnet-beta".to_string()) }
+            }
+            (Some(_), _) => return Err("--program cannot be combined with a keypair or address".to_string()),
+            (None, _) if cluster.is_some() => return Err("--rpc requires --program".to_string()),
+            (None, &[address]) if sources.is_empty() => Target::Address(address),
+            (None, []) => {
+                let [source] = <[KeypairSource; 1]>::try_from(keypair_sources(sources, "address")?)
+                    .map_err(|_| "address takes a single keypair or address".to_string())?;
+                Target::Keypair(source)
+            }
+            (None, _) => return Err("address takes a single keypair or address".to_string()),
+        };
+        Ok(AddressArgs { target, rust_test, constant_time, format })
+    }
+}
+
+/// Prints the comparison code for a key's address, and with `--rust-test`
+/// writes a test rebuilding it from the constants.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let AddressArgs { target, rust_test, constant_time, format } = AddressArgs::parse(args)?;
+    let address = match target {
+        Target::Address(address) => {
+            print_address_code(&address, format, constant_time);
+            address
+        }
+        Target::Keypair(source) => address_from_keypair(&source, format, constant_time).map_err(|e| e.to_string())?,
+        Target::Program { program, cluster } => {
+            let rpc = RpcClient::new(&rpc::cluster_url(&cluster));
+            address_from_program(&rpc, &program, format, constant_time).map_err(|e| e.to_string())?
+        }
+    };
+    match rust_test {
+        Some(path) => write_rust_test(&path, &[("EXPECTED_ADMIN_KEY".to_string(), address)]),
+        None => Ok(()),
+    }
+}
+
+fn address_from_keypair(
+    source: &KeypairSource,
+    format: CodeFormat,
+    constant_time: bool,
+) -> Result<[u8; 32], Box<dyn core::error::Error>> {
+    let address = source.pubkey()?;
+    print_address_code(&address, format, constant_time);
+    Ok(address)
+}
+
+/// Resolves a program name or address, confirms it exists on the cluster
+/// behind `rpc`, and prints the comparison code for its address.
+fn address_from_program(
+    rpc: &RpcClient,
+    program: &str,
+    format: CodeFormat,
+    constant_time: bool,
+) -> Result<[u8; 32], Box<dyn core::error::Error>> {
+    let address = KNOWN_PROGRAMS
+        .iter()
+        .find(|(name, _)| *name == program)
+        .map_or(program, |(_, address)| address);
+    let pubkey_bytes: [u8; 32] = bs58::decode(address)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            let names: Vec<&str> = KNOWN_PROGRAMS.iter().map(|(name, _)| *name).collect();
+            format!("'{program}' is neither a base58 address nor a known program ({})", names.join(", "))
+        })?;
+
+    let account = rpc
+        .get_account_info(address)?
+        .ok_or_else(|| format!("{address} has no account on {}", rpc.url()))?;
+    let executable = account.get("executable").and_then(serde_json::Value::as_bool).unwrap_or(false);
+    let owner = account.get("owner").and_then(serde_json::Value::as_str).unwrap_or("unknown");
+    println!("Resolved {program} on {}: {} owned by {owner}", rpc.url(), if executable { "program" } else { "account" });
+    println!();
+
+    print_address_code(&pubkey_bytes, format, constant_time);
+    Ok(pubkey_bytes)
+}
+
+/// Prints the comparison code for an address in `format`.
+fn print_address_code(pubkey_bytes: &[u8; 32], format: CodeFormat, constant_time: bool) {
+    match format {
+        CodeFormat::Asm => print_address_constants(pubkey_bytes, constant_time),
+        CodeFormat::Pinocchio => print!("{}", codegen::pinocchio(&[*pubkey_bytes], constant_time)),
+        CodeFormat::Anchor => print!("{}", codegen::anchor(&[*pubkey_bytes])),
+    }
+}
+
+/// Prints the assembly constants and comparison code for an address,
+/// branch-free with `constant_time`.
+fn print_address_constants(pubkey_bytes: &[u8; 32], constant_time: bool) {
+    println!("Public Key: {}", bs58::encode(pubkey_bytes).into_string());
+    println!("\nPublic Key (hex): {}", hex::encode(pubkey_bytes));
+
+    // Check which segments are 32-bit immediate compatible
+    let mut segment_is_imm32 = [false; 4];
+    for (segment, is_imm32) in segment_is_imm32.iter_mut().enumerate() {
+        *is_imm32 = imm32::segment_is_imm32_compatible(pubkey_bytes, segment);
+    }
+
+    println!("\n=== Assembly Constants ===");
+    print!("{}", codegen::constants_asm("EXPECTED_ADMIN_KEY", pubkey_bytes));
+
+    println!("\n=== Assembly Comparison Code ===");
+    if constant_time {
+        print!("{}", codegen::constant_time_asm(pubkey_bytes));
+    } else {
+        print!("{}", codegen::address_asm(pubkey_bytes));
+    }
+}
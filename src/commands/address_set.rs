@@ -0,0 +1,72 @@
+//! The `address-set` command: a check accepting any of several admin keys.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::codegen::{self, CodeFormat};
+use crate::commands::{check_code_options, read_address, write_rust_test};
+
+/// Arguments of `address-set`: `address-set <key>... [--out FILE] [options]`.
+struct AddressSetArgs {
+    admins: Vec<[u8; 32]>,
+    out: Option<PathBuf>,
+    rust_test: Option<PathBuf>,
+    constant_time: bool,
+    format: CodeFormat,
+}
+
+impl AddressSetArgs {
+    fn parse(args: &[String]) -> Result<AddressSetArgs, String> {
+        let mut admins: Vec<[u8; 32]> = Vec::new();
+        let mut out = None;
+        let mut rust_test = None;
+        let mut constant_time = false;
+        let mut format = CodeFormat::Asm;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--out" => out = Some(PathBuf::from(iter.next().ok_or_else(|| format!("{arg} requires a value"))?)),
+                "--rust-test" => rust_test = Some(PathBuf::from(iter.next().ok_or_else(|| format!("{arg} requires a value"))?)),
+                "--constant-time" => constant_time = true,
+                "--format" => format = CodeFormat::parse(iter.next().ok_or_else(|| format!("{arg} requires a value"))?)?,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                key => {
+                    let address = read_address(key)?;
+                    if admins.contains(&address) {
+                        return Err(format!("{} is given more than once", bs58::encode(address).into_string()));
+                    }
+                    admins.push(address);
+                }
+            }
+        }
+        if admins.is_empty() {
+            return Err("address-set requires at least one key (keypair file or base58 address)".to_string());
+        }
+        check_code_options(format, constant_time)?;
+        Ok(AddressSetArgs { admins, out, rust_test, constant_time, format })
+    }
+}
+
+/// Writes a check that accepts any of several admin keys.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let AddressSetArgs { admins, out, rust_test, constant_time, format } = AddressSetArgs::parse(args)?;
+
+    let code = match format {
+        CodeFormat::Asm => codegen::address_set_asm(&admins, constant_time),
+        CodeFormat::Pinocchio => codegen::pinocchio(&admins, constant_time),
+        CodeFormat::Anchor => codegen::anchor(&admins),
+    };
+    match out {
+        Some(path) => {
+            fs::write(&path, code).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+            println!("Wrote the check for {} admin keys to {}", admins.len(), path.display());
+        }
+        None => print!("{code}"),
+    }
+    if let Some(path) = rust_test {
+        let keys: Vec<(String, [u8; 32])> =
+            admins.iter().enumerate().map(|(index, &admin)| (format!("ADMIN_{index}_KEY"), admin)).collect();
+        write_rust_test(&path, &keys)?;
+    }
+    Ok(())
+}
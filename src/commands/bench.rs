@@ -0,0 +1,66 @@
+//! The `bench` command: the key rate of this machine at each batch size.
+
+use std::thread;
+use std::time::Duration;
+
+use doppler_keygen::scheduler::Scheduler;
+
+use crate::bench;
+use crate::cli_error::CliError;
+
+/// Arguments of `bench`.
+struct BenchArgs {
+    num_threads: Option<usize>,
+    scheduler: Scheduler,
+    seconds: u64,
+}
+
+impl BenchArgs {
+    fn parse(args: &[String]) -> Result<BenchArgs, String> {
+        let mut num_threads = None;
+        let mut scheduler = Scheduler::default();
+        let mut seconds = 5;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--num-threads" => {
+                    let value = value()?;
+                    num_threads = Some(
+                        value
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| format!("Invalid thread count '{value}'"))?,
+                    );
+                }
+                "--scheduler" => scheduler = Scheduler::parse(value()?)?,
+                "--seconds" => {
+                    let value = value()?;
+                    seconds = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&s| s > 0)
+                        .ok_or_else(|| format!("Invalid duration '{value}'"))?;
+                }
+                other => return Err(format!("Unknown option '{other}'")),
+            }
+        }
+        Ok(BenchArgs { num_threads, scheduler, seconds })
+    }
+}
+
+/// Measures the key rate at each batch size and reports the fastest.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let BenchArgs { num_threads, scheduler, seconds } = BenchArgs::parse(args).map_err(CliError::Usage)?;
+    let num_threads = match num_threads {
+        Some(num_threads) => num_threads,
+        None => thread::available_parallelism()
+            .map_err(CliError::Parallelism)?
+            .get(),
+    };
+
+    bench::run_bench(num_threads, scheduler, Duration::from_secs(seconds))?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! The `blind-combine` command: a requester's key combined with an offset from `blind-grind`.
+
+use curve25519_dalek::scalar::Scalar;
+use std::path::PathBuf;
+
+use solana_signer::Signer as _;
+
+use crate::blind::{self, BlindKey};
+use crate::commands::{keypair_sources, save_single_secret};
+use crate::keysource::KeypairSource;
+
+/// Arguments of `blind-combine`.
+struct BlindCombineArgs {
+    /// The requester's keypair
+    source: KeypairSource,
+    offset: Scalar,
+    /// The address `blind-grind` reported, to check the result against
+    expected: Option<String>,
+    output: Option<PathBuf>,
+    plaintext: bool,
+}
+
+impl BlindCombineArgs {
+    fn parse(args: &[String]) -> Result<BlindCombineArgs, String> {
+        let mut sources = Vec::new();
+        let mut offset = None;
+        let mut expected = None;
+        let mut output = None;
+        let mut plaintext = false;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--offset" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    offset = Some(blind::parse_offset(value)?);
+                }
+                "--address" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    expected = Some(value.clone());
+                }
+                "--keypair-base58" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    sources.push(KeypairSource::Base58(value.clone()));
+                }
+                "--output" | "-o" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    output = Some(PathBuf::from(value));
+                }
+                "--plaintext" => plaintext = true,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                source => sources.push(KeypairSource::parse(source).map_err(|e| e.to_string())?),
+            }
+        }
+
+        let [source] = <[KeypairSource; 1]>::try_from(keypair_sources(sources, "blind-combine")?)
+            .map_err(|_| "blind-combine takes a single keypair".to_string())?;
+        let offset = offset.ok_or_else(|| "blind-combine requires --offset OFFSET".to_string())?;
+        Ok(BlindCombineArgs { source, offset, expected, output, plaintext })
+    }
+}
+
+/// Adds an offset from `blind-grind` to the requester's secret and saves the
+/// combined key as a blind key file.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let BlindCombineArgs { source, offset, expected, output, plaintext } = BlindCombineArgs::parse(args)?;
+    let keypair = source.read().map_err(|e| e.to_string())?;
+
+    let key = BlindKey::combine(keypair.secret_bytes(), &offset);
+    let address = key.address();
+    if let Some(expected) = expected.filter(|expected| *expected != address) {
+        return Err(format!("The offset gives {address}, not {expected}; check the keypair and offset"));
+    }
+    let path = save_single_secret(format!("{address}.blind.json"), key.to_json().to_vec(), output, plaintext)?;
+    println!("Combined {} into {address}, saved to {}", keypair.pubkey(), path.display());
+    println!("Sign with it using: doppler-keygen sign --blind-key {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! The `blind-grind` command: offsets that turn a requester's public key into
+//! an imm32 compatible vanity address.
+
+use curve25519_dalek::edwards::EdwardsPoint;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::pattern::{BatchPattern, VanityPattern};
+use doppler_keygen::progress;
+use doppler_keygen::shutdown::ShutdownToken;
+use doppler_keygen::stats::StatsCollector;
+
+use crate::blind::{self, BlindSearch};
+use crate::cli_error::CliError;
+use crate::commands::check_patterns;
+use crate::grind::{finish_progress_reporter, parse_batch_pattern, print_progress, print_summary, stop_on_interrupt};
+
+/// Arguments of `blind-grind`.
+struct BlindGrindArgs {
+    /// The requester's public key
+    point: EdwardsPoint,
+    count: Option<usize>,
+    patterns: Vec<BatchPattern>,
+    ignore_case: bool,
+    num_threads: Option<usize>,
+    progress_interval: u64,
+}
+
+impl BlindGrindArgs {
+    fn parse(args: &[String]) -> Result<BlindGrindArgs, String> {
+        let mut point = None;
+        let mut count = None;
+        let mut patterns = Vec::new();
+        let mut ignore_case = false;
+        let mut num_threads = None;
+        let mut progress_interval = 5;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--point" => point = Some(blind::parse_point(value()?)?),
+                "--starts-with" | "--ends-with" | "--starts-and-ends-with" => {
+                    patterns.push(parse_batch_pattern(arg, value()?)?);
+                }
+                "--ignore-case" => ignore_case = true,
+                "--num-threads" => {
+                    let value = value()?;
+                    num_threads = Some(
+                        value
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| format!("Invalid thread count '{value}'"))?,
+                    );
+                }
+                "--progress-interval" => {
+                    let value = value()?;
+                    progress_interval = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("Invalid progress interval '{value}'"))?;
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                value if count.is_none() => {
+                    count = Some(value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| "Invalid count number".to_string())?);
+                }
+                other => return Err(format!("Unexpected argument '{other}'")),
+            }
+        }
+
+        let point = point.ok_or_else(|| "blind-grind requires --point PUBKEY".to_string())?;
+        check_patterns(count, &patterns, ignore_case)?;
+        Ok(BlindGrindArgs { point, count, patterns, ignore_case, num_threads, progress_interval })
+    }
+}
+
+/// Grinds offsets that turn a requester's public key into an imm32
+/// compatible vanity address, without the requester's secret.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let BlindGrindArgs { point, count, mut patterns, ignore_case, num_threads, progress_interval } =
+        BlindGrindArgs::parse(args).map_err(CliError::Usage)?;
+    for batch_pattern in &patterns {
+        batch_pattern.pattern.validate(ignore_case).map_err(DopplerError::InvalidPattern)?;
+    }
+    if patterns.is_empty() {
+        // The empty pattern accepts any imm32 compatible address
+        patterns.push(BatchPattern {
+            pattern: VanityPattern::default(),
+            count: count.unwrap_or(1),
+            found: 0,
+            priority: 0,
+            sink: None,
+        });
+    }
+    let num_threads = match num_threads {
+        Some(num_threads) => num_threads,
+        None => thread::available_parallelism().map_err(CliError::Parallelism)?.get(),
+    };
+
+    let search = BlindSearch { point, patterns, ignore_case };
+    let total_count: usize = search.patterns.iter().map(|p| p.count).sum();
+    println!("Doppler Keygen - Mining offsets for imm32 compatible keys blinded from a requester's public key");
+    println!("Requester: {}", bs58::encode(point.compress().as_bytes()).into_string());
+    println!("Using {num_threads} threads\n");
+
+    let stats = Arc::new(StatsCollector::new(num_threads));
+    let shutdown = ShutdownToken::new();
+    let keys_found = Arc::new(AtomicUsize::new(0));
+    stop_on_interrupt(&shutdown)?;
+    let progress_handle =
+        progress::spawn_reporter(&stats, &shutdown, Duration::from_secs(progress_interval), Arc::clone(&keys_found), move |snapshot| {
+            print_progress(&snapshot, Some(total_count))
+        })?;
+
+    let result = blind::run(&search, num_threads, &stats, &shutdown, &keys_found, |blind_match| {
+        let batch_pattern = &search.patterns[blind_match.pattern_index];
+        println!("\n✅ FOUND MATCHING OFFSET #{}/{}", blind_match.pattern_number, batch_pattern.count);
+        if batch_pattern.pattern != VanityPattern::default() {
+            println!("Vanity Pattern: {}", batch_pattern.pattern);
+        }
+        println!("Offset: {}", hex::encode(blind_match.offset));
+        println!("Address: {}", blind_match.address);
+        let offset = blind_match.segment * 8;
+        println!("Matched Segment: {} (bytes {}-{})", blind_match.segment, offset, offset + 7);
+        println!("Combine it with: doppler-keygen blind-combine <REQUESTER_KEYPAIR> --offset {}", hex::encode(blind_match.offset));
+    });
+    finish_progress_reporter(&shutdown, progress_handle);
+    let matches = result?;
+
+    print_summary(&format!("{}/{total_count}", matches.len()), &stats.summary());
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! The `ceremony` command: a grind seeded from entropy several participants contribute.
+
+use std::path::PathBuf;
+
+use crate::ceremony;
+use crate::cli_error::CliError;
+use crate::commands::grind::{self, GrindArgs};
+
+/// Arguments of `ceremony`: the contributions along with the grind options.
+struct CeremonyArgs {
+    files: Vec<PathBuf>,
+    participants: usize,
+    os_entropy: bool,
+    /// The grind options as given, for the transcript
+    grind_argv: Vec<String>,
+    grind_args: GrindArgs,
+}
+
+impl CeremonyArgs {
+    fn parse(args: &[String]) -> Result<CeremonyArgs, String> {
+        let mut files = Vec::new();
+        let mut participants = 0;
+        let mut os_entropy = true;
+        let mut grind_argv = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--contribution" => files.push(PathBuf::from(value()?)),
+                "--participants" => {
+                    let value = value()?;
+                    participants =
+                        value.parse::<usize>().map_err(|_| format!("Invalid participant count '{value}'"))?;
+                }
+                "--no-os-entropy" => os_entropy = false,
+                _ => grind_argv.push(arg.clone()),
+            }
+        }
+        let grind_args = GrindArgs::parse(&grind_argv)?;
+        grind_args.refuse("ceremony", &["--entropy"], "mixes the participants' contributions")?;
+        Ok(CeremonyArgs { files, participants, os_entropy, grind_argv, grind_args })
+    }
+}
+
+/// Grinds keys from entropy contributed by several participants and writes a
+/// transcript of the ceremony.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let CeremonyArgs { files, participants, os_entropy, grind_argv, mut grind_args } =
+        CeremonyArgs::parse(args).map_err(CliError::Usage)?;
+
+    let ceremony = ceremony::collect(&files, participants, os_entropy)?;
+    grind_args.seeded_entropy = Some(ceremony.entropy());
+    let Some((options, keypairs)) = grind::run(grind_args)? else {
+        return Ok(());
+    };
+    let path = ceremony.write_transcript(&grind_argv, &keypairs, &options.output_dir)?;
+    println!("Ceremony transcript saved to: {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,46 @@
+//! The `combine` command: a keypair reassembled from `grind --split` shares.
+
+use std::path::PathBuf;
+
+use solana_signer::Signer as _;
+
+use crate::commands::save_single_keypair;
+use crate::split;
+
+/// Arguments of `combine`.
+struct CombineArgs {
+    share_paths: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    plaintext: bool,
+}
+
+impl CombineArgs {
+    fn parse(args: &[String]) -> Result<CombineArgs, String> {
+        let mut output = None;
+        let mut plaintext = false;
+        let mut share_paths = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--output" | "-o" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    output = Some(PathBuf::from(value));
+                }
+                "--plaintext" => plaintext = true,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                path => share_paths.push(PathBuf::from(path)),
+            }
+        }
+        Ok(CombineArgs { share_paths, output, plaintext })
+    }
+}
+
+/// Reassembles a keypair from share files written by `grind --split` and
+/// saves it in `solana-keygen` format.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let CombineArgs { share_paths, output, plaintext } = CombineArgs::parse(args)?;
+    let keypair = split::combine(&share_paths)?;
+    let path = save_single_keypair(&keypair, output, plaintext)?;
+    println!("Recovered {} into {}", keypair.pubkey(), path.display());
+    Ok(())
+}
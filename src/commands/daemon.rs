@@ -0,0 +1,62 @@
+//! The `daemon` command: a grind service that takes jobs over HTTP.
+
+use std::time::Duration;
+
+use crate::cli_error::CliError;
+use crate::commands::grind::{self, COUNT, GrindArgs, PATTERNS};
+use crate::daemon::{self, DaemonSettings};
+
+/// Arguments of `daemon`: the grind options along with `--bind`, `--port`,
+/// `--max-running` and `--time-slice`.
+struct DaemonArgs {
+    settings: DaemonSettings,
+    grind_args: GrindArgs,
+}
+
+impl DaemonArgs {
+    fn parse(args: &[String]) -> Result<DaemonArgs, String> {
+        let mut settings = DaemonSettings {
+            bind: daemon::DEFAULT_BIND,
+            port: daemon::DEFAULT_PORT,
+            max_running: daemon::DEFAULT_MAX_RUNNING,
+            time_slice: daemon::DEFAULT_TIME_SLICE,
+        };
+        let mut grind_args = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--bind" | "--port" | "--max-running" | "--time-slice" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    let invalid = || format!("Invalid {arg} '{value}'");
+                    match arg.as_str() {
+                        "--bind" => settings.bind = value.parse().map_err(|_| invalid())?,
+                        "--port" => settings.port = value.parse::<u16>().map_err(|_| invalid())?,
+                        "--max-running" => {
+                            settings.max_running = value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(invalid)?;
+                        }
+                        _ => {
+                            let seconds = value.parse::<u64>().ok().filter(|&n| n > 0).ok_or_else(invalid)?;
+                            settings.time_slice = Duration::from_secs(seconds);
+                        }
+                    }
+                }
+                _ => grind_args.push(arg.clone()),
+            }
+        }
+        let grind_args = GrindArgs::parse(&grind_args)?;
+        grind_args.refuse(
+            "the daemon",
+            &[COUNT, PATTERNS, "--stream", "--watch", "--near", "--targets", "--install-as-default", "--rotate"],
+            "takes its patterns from submitted jobs",
+        )?;
+        grind_args.refuse("the daemon", &["--hardened", "--air-gapped"], "takes jobs over the network")?;
+        Ok(DaemonArgs { settings, grind_args })
+    }
+}
+
+/// Runs a grind service that takes jobs over HTTP.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let DaemonArgs { settings, mut grind_args } = DaemonArgs::parse(args).map_err(CliError::Usage)?;
+    grind_args.daemon = Some(settings);
+    grind::run(grind_args).map(|_| ())
+}
@@ -0,0 +1,84 @@
+//! The `diff` command: two keys' addresses compared segment by segment.
+
+use crate::commands::read_address;
+use crate::diff;
+
+/// Arguments of `diff`.
+struct DiffArgs {
+    a: [u8; 32],
+    b: [u8; 32],
+}
+
+impl DiffArgs {
+    fn parse(args: &[String]) -> Result<DiffArgs, String> {
+        let [a, b] = args else {
+            return Err("diff takes two keypairs or addresses".to_string());
+        };
+        Ok(DiffArgs { a: read_address(a)?, b: read_address(b)? })
+    }
+}
+
+/// Compares the addresses of two keys segment by segment.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let DiffArgs { a, b } = DiffArgs::parse(args)?;
+    print!("{}", diff::report(&a, &b));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grind::OutputFormat;
+    use solana_keypair::Keypair;
+    use solana_signer::Signer as _;
+    use std::fs;
+    use std::path::Path;
+
+    fn write_keypair(dir: &Path, name: &str, keypair: &Keypair) -> String {
+        let path = dir.join(name);
+        fs::write(&path, OutputFormat::Json.encode(keypair)).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn a_keypair_file_and_its_address_are_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = Keypair::new();
+        let path = write_keypair(dir.path(), "id.json", &keypair);
+        let DiffArgs { a, b } = DiffArgs::parse(&[path, keypair.pubkey().to_string()]).unwrap();
+        assert!(diff::report(&a, &b).contains("The addresses are identical"));
+    }
+
+    #[test]
+    fn two_keypair_files_are_compared_byte_by_byte() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (Keypair::new(), Keypair::new());
+        let args = [write_keypair(dir.path(), "old.json", &old), write_keypair(dir.path(), "new.json", &new)];
+        let DiffArgs { a, b } = DiffArgs::parse(&args).unwrap();
+        assert_eq!((a, b), (old.pubkey().to_bytes(), new.pubkey().to_bytes()));
+
+        let report = diff::report(&a, &b);
+        let differing = a.iter().zip(&b).filter(|(a, b)| a != b).count();
+        assert!(report.starts_with(&format!("A: {}\nB: {}\n", old.pubkey(), new.pubkey())), "{report}");
+        assert!(report.contains(&format!("Differing bytes: {differing} of 32")), "{report}");
+    }
+
+    #[test]
+    fn corrupt_or_mismatched_keypair_files_on_either_side_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = write_keypair(dir.path(), "good.json", &Keypair::new());
+        // A secret key paired with the public half of another key
+        let mut mismatched = Keypair::new().to_bytes();
+        mismatched[32..].copy_from_slice(&Keypair::new().pubkey().to_bytes());
+        let files = [("corrupt.json", b"[1, 2, 3]".to_vec()), ("mismatched.json", serde_json::to_vec(&mismatched.to_vec()).unwrap())];
+        for (name, contents) in files {
+            let bad = dir.path().join(name);
+            fs::write(&bad, contents).unwrap();
+            let bad = bad.display().to_string();
+            for args in [[good.clone(), bad.clone()], [bad.clone(), good.clone()]] {
+                let error = DiffArgs::parse(&args).err().unwrap();
+                assert!(error.starts_with(&format!("failed to read keypair from {bad}")), "{error}");
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! The `due` command: list and announce keys due for rotation.
+
+use std::path::PathBuf;
+
+use crate::due::{self, Lifetime};
+
+/// Arguments of `due`.
+struct DueArgs {
+    dirs: Vec<PathBuf>,
+    /// Seconds ahead to look for keys coming due
+    within: u64,
+    webhook: Option<String>,
+    json: bool,
+}
+
+impl DueArgs {
+    fn parse(args: &[String]) -> Result<DueArgs, String> {
+        let mut dirs = Vec::new();
+        let mut within = 0;
+        let mut webhook = None;
+        let mut json = false;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--within" => within = Lifetime::parse(iter.next().ok_or_else(|| format!("{arg} requires a value"))?)?.0,
+                "--webhook" => webhook = Some(iter.next().ok_or_else(|| format!("{arg} requires a value"))?.clone()),
+                "--json" => json = true,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                dir => dirs.push(PathBuf::from(dir)),
+            }
+        }
+        if dirs.is_empty() {
+            return Err("due requires at least one directory".to_string());
+        }
+        Ok(DueArgs { dirs, within, webhook, json })
+    }
+}
+
+/// Lists the keys under each directory that are due for rotation, and
+/// posts them to a webhook.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let DueArgs { dirs, within, webhook, json } = DueArgs::parse(args)?;
+    let due = due::find(&dirs, within).map_err(|e| e.to_string())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&due).expect("due keys serialize to JSON"));
+    } else if due.is_empty() {
+        println!("No keys are due for rotation");
+    } else {
+        print!("{}", due::to_text(&due));
+    }
+    // An empty list isn't news, so nothing is posted
+    if let (Some(url), false) = (webhook, due.is_empty()) {
+        due::post(&url, &due)?;
+        if !json {
+            println!("Posted {} key(s) to the webhook", due.len());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! The `from-dice` command: a keypair derived from dice rolls or coin flips.
+
+use std::io;
+use std::path::PathBuf;
+
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+
+use crate::commands::save_single_keypair;
+use crate::dice;
+use crate::inspect;
+
+/// Arguments of `from-dice`.
+struct FromDiceArgs {
+    sides: u8,
+    output: Option<PathBuf>,
+    plaintext: bool,
+    /// Rolls given as arguments; read from stdin when there are none
+    rolls: Vec<String>,
+}
+
+impl FromDiceArgs {
+    fn parse(args: &[String]) -> Result<FromDiceArgs, String> {
+        let mut sides = 6;
+        let mut output = None;
+        let mut plaintext = false;
+        let mut rolls = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--sides" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    sides = match value.parse::<u8>() {
+                        Ok(sides) if sides >= 2 => sides,
+                        _ => return Err(format!("Invalid number of sides '{value}': must be 2 to 255")),
+                    };
+                }
+                "--coin" => sides = 2,
+                "--output" | "-o" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    output = Some(PathBuf::from(value));
+                }
+                "--plaintext" => plaintext = true,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                value => rolls.push(value.to_string()),
+            }
+        }
+        Ok(FromDiceArgs { sides, output, plaintext, rolls })
+    }
+}
+
+/// Derives a keypair from dice rolls or coin flips, given as arguments or on
+/// stdin, and saves it in `solana-keygen` format.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let FromDiceArgs { sides, output, plaintext, rolls } = FromDiceArgs::parse(args)?;
+
+    let input = if rolls.is_empty() {
+        println!("Enter the rolls, then an empty line or end of input:");
+        let mut input = String::new();
+        for line in io::stdin().lines() {
+            let line = line.map_err(|e| format!("Failed to read rolls: {e}"))?;
+            if line.trim().is_empty() {
+                break;
+            }
+            input.push_str(&line);
+            input.push('\n');
+        }
+        input
+    } else {
+        rolls.join(" ")
+    };
+    let rolls = dice::Rolls::parse(&input, sides)?;
+    let bits_per_roll = rolls.bits_per_roll();
+    println!(
+        "Rolls: {} with a {sides}-sided die, about {:.0} bits of entropy ({bits_per_roll:.2} bits per roll, from the most \
+         frequent face)",
+        rolls.values.len(),
+        rolls.entropy_bits()
+    );
+    if bits_per_roll == 0.0 {
+        return Err("Every roll shows the same face; these rolls carry no entropy".to_string());
+    }
+    if rolls.entropy_bits() < dice::TARGET_BITS {
+        return Err(format!(
+            "A seed needs {:.0} bits; roll about {} more time(s) and enter all the rolls again",
+            dice::TARGET_BITS,
+            rolls.rolls_needed()
+        ));
+    }
+
+    let keypair = Keypair::new_from_array(rolls.seed());
+    let path = save_single_keypair(&keypair, output, plaintext)?;
+    println!("Keypair saved to: {}\n", path.display());
+    print!("{}", inspect::report(&keypair.pubkey().to_bytes()));
+    Ok(())
+}
@@ -0,0 +1,1223 @@
+//! The `grind` command: parses and checks the options of a search, applies
+//! the config file's profile, and resolves them into `GrindOptions`.
+//!
+//! Which options cannot be combined, and which need another, is kept in the
+//! `CONFLICTS` and `REQUIREMENTS` tables; the commands built on `grind` add
+//! their own refusals with `GrindArgs::refuse`.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use doppler_keygen::constraints::{self, Constraints, ImmSign};
+use doppler_keygen::derive::Derivation;
+use doppler_keygen::entropy::{Entropy, EntropySource};
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::pattern::BatchPattern;
+use doppler_keygen::scheduler::{self, Scheduler};
+use solana_keypair::Keypair;
+
+use crate::audit::AuditLog;
+use crate::bench;
+use crate::chat::Notifiers;
+use crate::cli_error::CliError;
+use crate::clients::Clients;
+use crate::commands::read_address;
+use crate::config::{self, Config};
+use crate::daemon::{self, DaemonSettings};
+use crate::due::Lifetime;
+use crate::encrypt::AgeRecipients;
+use crate::grind::{confirm_expected_time, files_per_key, grind_keys, grind_near, GrindOptions, install_as_default, key_match_probability, OutputFormat, parse_batch_pattern, parse_batch_size, patterns_from_env, print_entropy, PUBKEY_RECORD_FILE, SetAuthority, vanity_keys_batch};
+use crate::handoff::{self, Handoff, HandoffTarget};
+use crate::hardening;
+use crate::index::{self, KeyIndex};
+use crate::job::{JobStatus, Shard};
+use crate::kms::{KmsKey, KmsWrapper};
+use crate::machinekey::MachineKey;
+use crate::near::{Distance, NearSearch};
+use crate::notify::{EmailNotifier, RunReport};
+use crate::pkcs11::Pkcs11Sink;
+use crate::plugin::Plugin;
+use crate::predicate::Predicate;
+use crate::preflight::{self, OutputPlan};
+use crate::publish::{Publishers, PublishTarget};
+use crate::repl;
+use crate::results::ResultsDb;
+use crate::rotation::{Period, Rotation};
+use crate::rpc::{self, RpcClient};
+use crate::sinks::Sinks;
+use crate::split::SplitSpec;
+use crate::tags::KeyTags;
+use crate::targets::TargetSet;
+use crate::vault::VaultSink;
+use crate::webhook::{self, Webhooks};
+
+/// Keys kept by `grind --near` unless `--top` says otherwise.
+const DEFAULT_NEAR_TOP: usize = 10;
+
+/// Stands in for a key count among the given options.
+pub const COUNT: &str = "a key count";
+
+/// Stands in for `--starts-with`/`--ends-with` patterns among the given
+/// options.
+pub const PATTERNS: &str = "--starts-with/--ends-with patterns";
+
+/// Options that `--hardened` and `--air-gapped` refuse, since they need the
+/// network.
+const NETWORK: &[&str] = &[
+    "--publish",
+    "--vault-path",
+    "--kms-key",
+    "--airdrop",
+    "--check-onchain",
+    "--handoff-cluster",
+    "--email-to",
+    "--notify-url",
+];
+
+/// An option that cannot be combined with any of `with`, because it
+/// `reason`.
+struct Conflict {
+    flag: &'static str,
+    with: &'static [&'static str],
+    reason: &'static str,
+}
+
+/// Pairs of `grind` options that cannot be given together, checked in order.
+const CONFLICTS: &[Conflict] = &[
+    Conflict { flag: "--install-as-default", with: &["--no-save-secret"], reason: "needs the secret key" },
+    Conflict {
+        flag: "--install-as-default",
+        with: &["--split", "--encrypt-to", "--kms-key", "--vault-path", "--pkcs11-module"],
+        reason: "writes the secret key unprotected to the Solana CLI config",
+    },
+    Conflict { flag: "--split", with: &["--no-save-secret"], reason: "writes shares of the secret key" },
+    Conflict { flag: "--encrypt-to", with: &["--no-save-secret", "--split"], reason: "encrypts the keypair files" },
+    Conflict {
+        flag: "--plaintext",
+        with: &["--encrypt-to", "--kms-key", "--split", "--vault-path", "--pkcs11-module"],
+        reason: "writes the keypair files unencrypted",
+    },
+    Conflict {
+        flag: "--derive",
+        with: &["--no-save-secret"],
+        reason: "needs the secret key to create the derived account",
+    },
+    Conflict { flag: "--airdrop", with: &["--no-save-secret"], reason: "funds keys whose secrets would be discarded" },
+    Conflict {
+        flag: "--qr-secret",
+        with: &["--plaintext", "--kms-key", "--split", "--no-save-secret", "--vault-path", "--pkcs11-module"],
+        reason: "only renders age-encrypted keypair files",
+    },
+    Conflict {
+        flag: "--kms-key",
+        with: &["--no-save-secret", "--split", "--encrypt-to", "--vault-path", "--pkcs11-module"],
+        reason: "wraps the keypair files",
+    },
+    Conflict {
+        flag: "--vault-path",
+        with: &["--no-save-secret", "--split", "--encrypt-to"],
+        reason: "writes each keypair to Vault",
+    },
+    Conflict {
+        flag: "--pkcs11-module",
+        with: &["--no-save-secret", "--split", "--encrypt-to", "--vault-path"],
+        reason: "imports each keypair into a token",
+    },
+    Conflict { flag: "--rotate", with: &["--vault-path", "--pkcs11-module"], reason: "files keys by date" },
+    Conflict {
+        flag: "--emit-set-authority",
+        with: &["--no-save-secret"],
+        reason: "makes the found key an upgrade authority",
+    },
+    Conflict {
+        flag: "--upgrade-authority",
+        with: &["--handoff", "--handoff-program"],
+        reason: "writes its own handoff transaction",
+    },
+    Conflict {
+        flag: "--handoff",
+        with: &["--no-save-secret"],
+        reason: "needs the found key to sign or hold the authority",
+    },
+    Conflict { flag: "--hardened", with: NETWORK, reason: "blocks the network" },
+    Conflict { flag: "--air-gapped", with: NETWORK, reason: "blocks the network" },
+    Conflict { flag: "--near", with: &[COUNT, "--stream", PATTERNS], reason: "runs until interrupted" },
+    Conflict {
+        flag: "--near",
+        with: &["--segment", "--segment-ascii", "--imm-range", "--imm-sign", "--min-zero-bytes"],
+        reason: "ranks keys by distance alone",
+    },
+    Conflict {
+        flag: "--near",
+        with: &["--no-save-secret", "--vault-path", "--pkcs11-module"],
+        reason: "keeps keypair files while they rank",
+    },
+    Conflict { flag: "--predicate", with: &["--near"], reason: "accepts or rejects each key" },
+    Conflict { flag: "--plugin", with: &["--near"], reason: "accepts or rejects each key" },
+    Conflict { flag: "--targets", with: &[PATTERNS, "--near"], reason: "matches the prefixes in its file" },
+    Conflict {
+        flag: "--auto-tune",
+        with: &["--num-threads", "--batch-size"],
+        reason: "picks the thread count and batch size",
+    },
+    Conflict { flag: "--stream", with: &[COUNT, PATTERNS], reason: "grinds until interrupted" },
+    Conflict {
+        flag: "--watch",
+        with: &[PATTERNS, COUNT, "--stream", "--near", "--targets"],
+        reason: "reloads the patterns in the config file",
+    },
+    Conflict { flag: COUNT, with: &[PATTERNS], reason: "is given in each PATTERN:COUNT instead" },
+];
+
+/// Options that need one of the others to be given too.
+const REQUIREMENTS: &[(&str, &[&str])] = &[
+    ("--install-as-default", &["--plaintext"]),
+    ("--vault-addr", &["--vault-path"]),
+    ("--pkcs11-token", &["--pkcs11-module"]),
+    ("--rotate", &["--stream", "--watch"]),
+    ("--keep", &["--rotate"]),
+    ("--upgrade-authority", &["--emit-set-authority"]),
+    ("--handoff", &["--handoff-program"]),
+    ("--handoff-program", &["--handoff"]),
+    ("--handoff-cluster", &["--handoff", "--upgrade-authority"]),
+    ("--top", &["--near"]),
+    ("--distance", &["--near"]),
+    ("--shred-losers", &["--near"]),
+];
+
+/// Checks the given options against `CONFLICTS` and `REQUIREMENTS`.
+fn check_flags(given: &BTreeSet<String>) -> Result<(), String> {
+    for conflict in CONFLICTS.iter().filter(|conflict| given.contains(conflict.flag)) {
+        if let Some(other) = conflict.with.iter().find(|other| given.contains(**other)) {
+            let mut message = format!("{} {}, so it cannot be combined with {other}", conflict.flag, conflict.reason);
+            // `COUNT` opens with a word rather than a flag
+            message[..1].make_ascii_uppercase();
+            return Err(message);
+        }
+    }
+    for (flag, any_of) in REQUIREMENTS.iter().filter(|(flag, _)| given.contains(*flag)) {
+        if !any_of.iter().any(|other| given.contains(*other)) {
+            return Err(format!("{flag} requires {}", any_of.join(" or ")));
+        }
+    }
+    Ok(())
+}
+
+/// Options accepted by the `grind` command.
+pub struct GrindArgs {
+    pub count: Option<usize>,
+    pub patterns: Vec<BatchPattern>,
+    pub ignore_case: bool,
+    pub num_threads: Option<usize>,
+    pub output_formats: Option<Vec<OutputFormat>>,
+    pub output_dir: Option<PathBuf>,
+    pub progress_interval: Option<u64>,
+    pub scheduler: Option<Scheduler>,
+    pub batch_size: Option<usize>,
+    pub config: Option<PathBuf>,
+    /// Calibrate the thread count and batch size and save them to the
+    /// config file
+    pub auto_tune: bool,
+    pub install_as_default: bool,
+    pub yes: bool,
+    /// Keep grinding until interrupted instead of stopping at a count
+    pub stream: bool,
+    /// Reload the config file's patterns when it changes
+    pub watch: bool,
+    /// Take pattern changes from an interactive prompt; set by `repl`
+    pub repl: bool,
+    /// Take jobs over HTTP; set by `daemon`
+    pub daemon: Option<DaemonSettings>,
+    pub no_save_secret: bool,
+    pub no_index: bool,
+    pub split: Option<SplitSpec>,
+    pub encrypt_to: Option<AgeRecipients>,
+    /// Write keypair files unencrypted instead of to the machine key
+    pub plaintext: bool,
+    pub vault_addr: Option<String>,
+    pub vault_path: Option<String>,
+    pkcs11_module: Option<PathBuf>,
+    pkcs11_token: Option<String>,
+    pub kms_key: Option<KmsKey>,
+    pub qr: bool,
+    pub qr_secret: bool,
+    pub airdrop: Option<String>,
+    pub check_onchain: Option<String>,
+    pub derivation: Option<Derivation>,
+    pub constraints: Constraints,
+    /// Keep the keys closest to this address instead of matching a pattern
+    pub near: Option<NearSearch>,
+    /// File of wanted prefixes and addresses
+    pub targets: Option<PathBuf>,
+    /// Rhai script with a custom match rule
+    pub predicate: Option<PathBuf>,
+    /// WebAssembly matcher and sink plugin
+    pub plugin: Option<PathBuf>,
+    /// Run as one shard of a Kubernetes Job; set by `job`
+    pub job: Option<Arc<JobStatus>>,
+    /// MQTT and ZeroMQ endpoints to publish events to
+    pub publish: Vec<PublishTarget>,
+    /// Where seed material comes from, if not the OS
+    pub entropy: Option<EntropySource>,
+    /// Seed material mixed by `ceremony`, used instead of `entropy`
+    pub seeded_entropy: Option<Entropy>,
+    /// Block network system calls with seccomp before the search starts
+    pub hardened: bool,
+    /// Refuse to start while a network interface is up; implies `hardened`
+    pub air_gapped: bool,
+    /// Refuse an output directory inside a cloud-synced folder
+    pub no_sync_dirs: bool,
+    /// Name of the profile whose options were added
+    pub profile: Option<String>,
+    /// Save keys to a new dated subdirectory every period
+    pub rotate: Option<Period>,
+    /// Period directories to keep when rotating
+    pub keep: Option<usize>,
+    /// Recipients of the summary email, besides the config file's
+    pub email_to: Vec<String>,
+    /// URLs saved keys are posted to, replacing the config file's
+    pub notify_urls: Vec<String>,
+    /// Given with `--tag` and `--meta`
+    pub tags: KeyTags,
+    pub lifetime: Option<Lifetime>,
+    /// Where the upgrade authority of `handoff_program` goes
+    pub handoff: Option<HandoffTarget>,
+    pub handoff_program: Option<[u8; 32]>,
+    /// Cluster the handoff transaction's blockhash comes from
+    pub handoff_cluster: Option<String>,
+    pub set_authority: Option<SetAuthority>,
+    /// Every option given, with `COUNT` and `PATTERNS` standing in for a
+    /// key count and `--starts-with`/`--ends-with` patterns
+    given: BTreeSet<String>,
+}
+
+impl GrindArgs {
+    /// Parses the options of `grind`, as also taken by the commands built on
+    /// it, and checks them against `CONFLICTS` and `REQUIREMENTS`.
+    pub fn parse(args: &[String]) -> Result<GrindArgs, String> {
+        let mut grind_args = GrindArgs {
+            count: None,
+            patterns: Vec::new(),
+            ignore_case: false,
+            num_threads: None,
+            output_formats: None,
+            output_dir: None,
+            progress_interval: None,
+            scheduler: None,
+            batch_size: None,
+            config: None,
+            auto_tune: false,
+            install_as_default: false,
+            yes: false,
+            stream: false,
+            watch: false,
+            repl: false,
+            daemon: None,
+            no_save_secret: false,
+            no_index: false,
+            split: None,
+            encrypt_to: None,
+            plaintext: false,
+            vault_addr: None,
+            vault_path: None,
+            pkcs11_module: None,
+            pkcs11_token: None,
+            kms_key: None,
+            qr: false,
+            qr_secret: false,
+            airdrop: None,
+            check_onchain: None,
+            derivation: None,
+            constraints: Constraints::default(),
+            near: None,
+            targets: None,
+            predicate: None,
+            plugin: None,
+            job: None,
+            publish: Vec::new(),
+            entropy: None,
+            seeded_entropy: None,
+            hardened: false,
+            air_gapped: false,
+            no_sync_dirs: false,
+            profile: None,
+            rotate: None,
+            keep: None,
+            email_to: Vec::new(),
+            notify_urls: Vec::new(),
+            tags: KeyTags::default(),
+            lifetime: None,
+            handoff: None,
+            handoff_program: None,
+            handoff_cluster: None,
+            set_authority: None,
+            given: BTreeSet::new(),
+        };
+        let mut profile = None;
+        let mut near_top = None;
+        let mut emit_set_authority = None;
+        let mut upgrade_authority = None;
+        let mut near_distance = None;
+        let mut shred_losers = false;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg.starts_with("--") {
+                grind_args.given.insert(arg.clone());
+            }
+            match arg.as_str() {
+                "--starts-with" | "--ends-with" | "--starts-and-ends-with" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.patterns.push(parse_batch_pattern(arg, value)?);
+                }
+                "--priority" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    let batch_pattern = grind_args
+                        .patterns
+                        .last_mut()
+                        .ok_or_else(|| "--priority applies to the pattern before it; give a --starts-with or --ends-with first".to_string())?;
+                    batch_pattern.priority = value.parse::<u32>().map_err(|_| format!("Invalid priority '{value}'"))?;
+                }
+                "--sink" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    let batch_pattern = grind_args
+                        .patterns
+                        .last_mut()
+                        .ok_or_else(|| "--sink applies to the pattern before it; give a --starts-with or --ends-with first".to_string())?;
+                    batch_pattern.sink = Some(value.clone());
+                }
+                "--ignore-case" => grind_args.ignore_case = true,
+                "--num-threads" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    let num_threads = value
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid thread count '{value}'"))?;
+                    if num_threads == 0 {
+                        return Err("Thread count must be at least 1".to_string());
+                    }
+                    grind_args.num_threads = Some(num_threads);
+                }
+                "--output-format" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    let mut output_formats = Vec::new();
+                    for format in value.split(',') {
+                        let format = OutputFormat::parse(format)?;
+                        if !output_formats.contains(&format) {
+                            output_formats.push(format);
+                        }
+                    }
+                    grind_args.output_formats = Some(output_formats);
+                }
+                "--output-dir" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.output_dir = Some(PathBuf::from(value));
+                }
+                "--progress-interval" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    let seconds = value
+                        .parse::<u64>()
+                        .map_err(|_| format!("Invalid progress interval '{value}'"))?;
+                    if seconds == 0 {
+                        return Err("Progress interval must be at least 1 second".to_string());
+                    }
+                    grind_args.progress_interval = Some(seconds);
+                }
+                "--scheduler" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.scheduler = Some(Scheduler::parse(value)?);
+                }
+                "--batch-size" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.batch_size = Some(parse_batch_size(value)?);
+                }
+                "--config" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.config = Some(PathBuf::from(value));
+                }
+                "--profile" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    profile = Some(value.clone());
+                }
+                "--auto-tune" => grind_args.auto_tune = true,
+                "--install-as-default" => grind_args.install_as_default = true,
+                "--yes" | "-y" => grind_args.yes = true,
+                "--stream" => grind_args.stream = true,
+                "--watch" => grind_args.watch = true,
+                "--no-save-secret" => grind_args.no_save_secret = true,
+                "--no-index" => grind_args.no_index = true,
+                "--airdrop" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    if matches!(value.as_str(), "mainnet-beta" | "mainnet") {
+                        return Err("--airdrop only works on devnet, testnet or a local validator".to_string());
+                    }
+                    grind_args.airdrop = Some(value.clone());
+                }
+                "--handoff" => {
+                    grind_args.handoff = Some(HandoffTarget::parse(iter.next().ok_or_else(|| format!("{arg} requires a value"))?)?);
+                }
+                "--handoff-program" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.handoff_program = Some(handoff::decode_pubkey("program id", value)?);
+                }
+                "--emit-set-authority" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    emit_set_authority = Some(handoff::decode_pubkey("program id", value)?);
+                }
+                "--upgrade-authority" => {
+                    upgrade_authority = Some(iter.next().ok_or_else(|| format!("{arg} requires a value"))?.clone());
+                }
+                "--handoff-cluster" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.handoff_cluster = Some(value.clone());
+                }
+                "--check-onchain" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.check_onchain = Some(value.clone());
+                }
+                "--derive" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.derivation = Some(Derivation::parse(value)?);
+                }
+                "--segment-ascii" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.constraints.segment_ascii = Some(value.clone());
+                }
+                "--imm-range" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.constraints.imm_range = Some(constraints::parse_imm_range(value)?);
+                }
+                "--imm-sign" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.constraints.imm_sign = Some(ImmSign::parse(value)?);
+                }
+                "--segment" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.constraints.segment = Some(value.parse::<usize>().map_err(|_| format!("Invalid segment '{value}'"))?);
+                }
+                "--near" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    let target = bs58::decode(value)
+                        .into_vec()
+                        .ok()
+                        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                        .ok_or_else(|| format!("Invalid --near address '{value}'"))?;
+                    grind_args.near =
+                        Some(NearSearch { target, top: DEFAULT_NEAR_TOP, distance: Distance::Bits, shred_losers: false });
+                }
+                "--top" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    match value.parse::<usize>() {
+                        Ok(top) if top > 0 => near_top = Some(top),
+                        _ => return Err(format!("Invalid --top '{value}': must be at least 1")),
+                    }
+                }
+                "--distance" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    near_distance = Some(Distance::parse(value)?);
+                }
+                "--shred-losers" => shred_losers = true,
+                "--targets" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.targets = Some(PathBuf::from(value));
+                }
+                "--predicate" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.predicate = Some(PathBuf::from(value));
+                }
+                "--plugin" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.plugin = Some(PathBuf::from(value));
+                }
+                "--min-zero-bytes" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    let min_zero_bytes = value.parse::<usize>().map_err(|_| format!("Invalid zero byte count '{value}'"))?;
+                    grind_args.constraints.min_zero_bytes = Some(min_zero_bytes);
+                }
+                "--qr" => grind_args.qr = true,
+                "--qr-secret" => grind_args.qr_secret = true,
+                "--split" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.split = Some(SplitSpec::parse(value)?);
+                }
+                "--encrypt-to" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.encrypt_to = Some(AgeRecipients::parse(value)?);
+                }
+                "--plaintext" => grind_args.plaintext = true,
+                "--kms-key" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.kms_key = Some(KmsKey::parse(value)?);
+                }
+                "--vault-addr" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.vault_addr = Some(value.clone());
+                }
+                "--vault-path" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.vault_path = Some(value.clone());
+                }
+                "--pkcs11-module" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.pkcs11_module = Some(PathBuf::from(value));
+                }
+                "--pkcs11-token" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.pkcs11_token = Some(value.clone());
+                }
+                "--entropy" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.entropy = Some(EntropySource::parse(value)?);
+                }
+                "--publish" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.publish.push(PublishTarget::parse(value)?);
+                }
+                "--email-to" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.email_to.push(value.clone());
+                }
+                "--notify-url" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    webhook::check_url(value)?;
+                    grind_args.notify_urls.push(value.clone());
+                }
+                "--tag" => grind_args.tags.add_tag(iter.next().ok_or_else(|| format!("{arg} requires a value"))?)?,
+                "--meta" => grind_args.tags.add_meta(iter.next().ok_or_else(|| format!("{arg} requires a value"))?)?,
+                "--lifetime" => {
+                    grind_args.lifetime = Some(Lifetime::parse(iter.next().ok_or_else(|| format!("{arg} requires a value"))?)?);
+                }
+                "--hardened" => grind_args.hardened = true,
+                "--no-sync-dirs" => grind_args.no_sync_dirs = true,
+                "--rotate" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    grind_args.rotate = Some(Period::parse(value)?);
+                }
+                "--keep" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    match value.parse::<usize>() {
+                        Ok(keep) if keep > 0 => grind_args.keep = Some(keep),
+                        _ => return Err(format!("Invalid --keep '{value}': must be at least 1")),
+                    }
+                }
+                "--air-gapped" => {
+                    grind_args.air_gapped = true;
+                    grind_args.hardened = true;
+                }
+                "--count" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    match value.parse::<usize>().map_err(|_| format!("Invalid count '{value}'"))? {
+                        0 => grind_args.stream = true,
+                        count => grind_args.count = Some(count),
+                    }
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                value => {
+                    if grind_args.count.is_some() {
+                        return Err(format!("Unexpected argument '{value}'"));
+                    }
+                    let count = value.parse::<usize>().map_err(|_| "Invalid count number".to_string())?;
+                    if count == 0 {
+                        return Err("Count must be at least 1 (use --stream to grind until interrupted)".to_string());
+                    }
+                    grind_args.count = Some(count);
+                }
+            }
+        }
+
+        if let Some(name) = profile {
+            return apply_profile(args, &name, grind_args.config.as_deref());
+        }
+        if grind_args.count.is_some() {
+            grind_args.given.insert(COUNT.to_string());
+        }
+        if !grind_args.patterns.is_empty() {
+            grind_args.given.insert(PATTERNS.to_string());
+        }
+        if grind_args.stream {
+            // Also set by `--count 0`
+            grind_args.given.insert("--stream".to_string());
+        }
+        check_flags(&grind_args.given)?;
+
+        match (emit_set_authority, upgrade_authority) {
+            (Some(program), Some(current)) => {
+                // The current authority signs the handoff transaction
+                grind_args.handoff = Some(HandoffTarget::Authority { current: read_address(&current)? });
+                grind_args.handoff_program = Some(program);
+                grind_args.set_authority = Some(SetAuthority { program, upgrade_authority: Some(current) });
+            }
+            (Some(program), None) => grind_args.set_authority = Some(SetAuthority { program, upgrade_authority: None }),
+            (None, _) => {}
+        }
+        grind_args.constraints.validate().map_err(|e| format!("Invalid search constraint: {e}"))?;
+        if let Some(near) = &mut grind_args.near {
+            near.top = near_top.unwrap_or(DEFAULT_NEAR_TOP);
+            near.distance = near_distance.unwrap_or(Distance::Bits);
+            near.shred_losers = shred_losers;
+        }
+        Ok(grind_args)
+    }
+
+    /// Refuses any of `flags` among the given options, for a `command` built
+    /// on `grind` that `reason`.
+    pub fn refuse(&self, command: &str, flags: &[&str], reason: &str) -> Result<(), String> {
+        match flags.iter().find(|flag| self.given.contains(**flag)) {
+            Some(flag) => Err(format!("{command} {reason}, so it cannot be combined with {flag}")),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether keypair files are encrypted to the machine key: the secret is
+    /// saved to files and nothing else protects it.
+    fn machine_encrypted(&self) -> bool {
+        !self.plaintext
+            && !self.no_save_secret
+            && self.encrypt_to.is_none()
+            && self.kms_key.is_none()
+            && self.split.is_none()
+            && self.vault_path.is_none()
+            && self.pkcs11_module.is_none()
+    }
+
+    /// Fills in any setting not given on the command line from the config
+    /// file, falling back to the built-in defaults.
+    pub fn resolve(&self, config: &Config) -> Result<GrindOptions, CliError> {
+        let num_threads = match self.num_threads.or(config.threads) {
+            Some(num_threads) => num_threads,
+            None => thread::available_parallelism()
+                .map_err(CliError::Parallelism)?
+                .get(),
+        };
+
+        Ok(GrindOptions {
+            num_threads,
+            output_formats: self
+                .output_formats
+                .clone()
+                .or_else(|| config.output_formats.clone())
+                .unwrap_or_else(|| vec![OutputFormat::Json]),
+            output_dir: self
+                .output_dir
+                .clone()
+                .or_else(|| config.output_dir.clone())
+                .unwrap_or_else(|| PathBuf::from(".")),
+            progress_interval: Duration::from_secs(
+                self.progress_interval.or(config.progress_interval).unwrap_or(5),
+            ),
+            scheduler: self.scheduler.or(config.scheduler).unwrap_or_default(),
+            batch_size: self
+                .batch_size
+                .or(config.batch_size)
+                .unwrap_or(scheduler::DEFAULT_BATCH_SIZE),
+            save_secret: !self.no_save_secret,
+            key_index: None,
+            audit_log: None,
+            results: None,
+            split: self.split,
+            encrypt_to: self.encrypt_to.clone(),
+            vault: None,
+            pkcs11: None,
+            kms: self.kms_key.clone().map(|key| Arc::new(KmsWrapper::new(key))),
+            qr: self.qr || self.qr_secret,
+            qr_secret: self.qr_secret,
+            airdrop: self.airdrop.as_deref().map(|cluster| Arc::new(RpcClient::new(&rpc::cluster_url(cluster)))),
+            check_onchain: self.check_onchain.as_deref().map(|cluster| Arc::new(RpcClient::new(&rpc::cluster_url(cluster)))),
+            derivation: self.derivation,
+            constraints: self.constraints.clone(),
+            targets: None,
+            predicate: None,
+            plugin: None,
+            job: self.job.clone(),
+            publishers: None,
+            entropy: Entropy::default(),
+            rotation: None,
+            sinks: None,
+            report: None,
+            notifiers: None,
+            webhooks: None,
+            tags: self.tags.clone(),
+            lifetime: self.lifetime,
+            handoff: self.handoff.zip(self.handoff_program).map(|(target, program)| {
+                let rpc = self.handoff_cluster.as_deref().map(|cluster| RpcClient::new(&rpc::cluster_url(cluster)));
+                Arc::new(Handoff { target, program, rpc })
+            }),
+            set_authority: self.set_authority.clone(),
+        })
+    }
+}
+
+/// Parses `args` again with the options of profile `name` in front of them,
+/// so options on the command line override the profile's, and enforces the
+/// profile's requirements.
+fn apply_profile(args: &[String], name: &str, config_path: Option<&Path>) -> Result<GrindArgs, String> {
+    let config_path = config_path.map(Path::to_path_buf).or_else(|| config::env_var("DOPPLER_CONFIG").map(PathBuf::from));
+    let profile = Config::load(config_path.as_deref())?.profile(name)?;
+
+    let mut expanded = profile.args.clone();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--profile" {
+            iter.next();
+        } else {
+            expanded.push(arg.clone());
+        }
+    }
+    let mut grind_args = GrindArgs::parse(&expanded).map_err(|e| format!("{e} (with profile '{name}')"))?;
+    if profile.require_encryption && grind_args.plaintext {
+        return Err(format!("Profile '{name}' never writes a secret key in the clear, so it cannot be combined with --plaintext"));
+    }
+    grind_args.profile = Some(name.to_string());
+    Ok(grind_args)
+}
+
+/// Runs `grind`.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    run(GrindArgs::parse(args).map_err(CliError::Usage)?).map(|_| ())
+}
+
+/// Runs a grind with parsed `grind` arguments, returning the options it ran
+/// with and the keypairs it kept, or `None` if the search was declined at
+/// the confirmation prompt.
+pub fn run(mut grind_args: GrindArgs) -> Result<Option<(GrindOptions, Vec<Keypair>)>, CliError> {
+    if let Some(profile) = &grind_args.profile {
+        println!("Profile: {profile}");
+    }
+    if grind_args.air_gapped {
+        hardening::check_air_gap()?;
+    }
+
+    // Command line flags take precedence over DOPPLER_* environment
+    // variables, which take precedence over the config file
+    let config_path = grind_args
+        .config
+        .clone()
+        .or_else(|| config::env_var("DOPPLER_CONFIG").map(PathBuf::from));
+    let config = Config::load(config_path.as_deref())
+        .and_then(|config| Ok(config.merge(Config::from_env()?)))
+        .map_err(CliError::Config)?;
+    if grind_args.patterns.is_empty()
+        && grind_args.count.is_none()
+        && !grind_args.stream
+        && grind_args.near.is_none()
+        && grind_args.targets.is_none()
+        && grind_args.daemon.is_none()
+    {
+        grind_args.patterns = patterns_from_env().map_err(CliError::Config)?;
+        if grind_args.watch && !grind_args.patterns.is_empty() {
+            return Err(CliError::Usage("--watch reloads the patterns in the config file, but DOPPLER_PATTERNS is set".to_string()));
+        }
+        if grind_args.patterns.is_empty() {
+            grind_args.patterns = config.patterns.clone().unwrap_or_default();
+        }
+    }
+    let watch = if grind_args.watch {
+        let path = config_path
+            .clone()
+            .or_else(config::default_config_path)
+            .filter(|path| path.exists())
+            .ok_or_else(|| CliError::Usage("--watch needs a config file to watch; pass --config FILE".to_string()))?;
+        Some(path)
+    } else {
+        None
+    };
+    if grind_args.ignore_case && grind_args.patterns.is_empty() && !grind_args.repl && grind_args.daemon.is_none() {
+        return Err(CliError::Usage("--ignore-case requires at least one vanity pattern".to_string()));
+    }
+    for batch_pattern in &grind_args.patterns {
+        batch_pattern.pattern.validate(grind_args.ignore_case).map_err(DopplerError::InvalidPattern)?;
+    }
+    let shard_requested = grind_args.job.as_ref().map(|job| job.shard()).map(|shard| assign_shard(&mut grind_args, shard));
+    if grind_args.auto_tune {
+        let path = config_path
+            .clone()
+            .or_else(config::default_config_path)
+            .ok_or_else(|| CliError::Usage("--auto-tune needs a config file to save to; pass --config FILE".to_string()))?;
+        let max_threads = thread::available_parallelism().map_err(CliError::Parallelism)?.get();
+        let scheduler = grind_args.scheduler.or(config.scheduler).unwrap_or_default();
+        let (num_threads, batch_size) = bench::auto_tune(max_threads, scheduler, grind_args.derivation)?;
+        config::save_tuning(&path, num_threads, batch_size).map_err(CliError::Config)?;
+        println!("Saved threads = {num_threads} and batch_size = {batch_size} to {}\n", path.display());
+        grind_args.num_threads = Some(num_threads);
+        grind_args.batch_size = Some(batch_size);
+    }
+    let mut options = grind_args.resolve(&config)?;
+    if let Some(path) = &grind_args.targets {
+        let targets = TargetSet::load(path).map_err(CliError::Usage)?;
+        println!("Targets: {} prefix(es) and address(es) loaded from {}", targets.len(), path.display());
+        options.targets = Some(Arc::new(targets));
+    }
+    if let Some(path) = &grind_args.predicate {
+        options.predicate = Some(Arc::new(Predicate::load(path).map_err(CliError::Usage)?));
+    }
+    if let Some(path) = &grind_args.plugin {
+        options.plugin = Some(Arc::new(Plugin::load(path).map_err(CliError::Usage)?));
+    }
+    if let Some(entropy) = &grind_args.seeded_entropy {
+        options.entropy = entropy.clone();
+    } else if let Some(source) = &grind_args.entropy {
+        options.entropy = Entropy::gather(source)?;
+        print_entropy(&options.entropy);
+    }
+    if let Some(job) = &options.job {
+        options.output_dir = job.shard().output_dir(&options.output_dir);
+    }
+
+    let match_probability = key_match_probability(&options);
+    let expected_attempts = if grind_args.patterns.is_empty() {
+        grind_args.count.unwrap_or(1) as f64 / match_probability
+    } else {
+        grind_args
+            .patterns
+            .iter()
+            .map(|p| p.count as f64 / (match_probability * p.pattern.match_probability(grind_args.ignore_case)))
+            .sum()
+    };
+    // A stream, a watched or interactive batch, a daemon or a --near search
+    // has no end to estimate
+    if !grind_args.yes
+        && !grind_args.stream
+        && !grind_args.watch
+        && !grind_args.repl
+        && grind_args.daemon.is_none()
+        && grind_args.near.is_none()
+        && !confirm_expected_time(expected_attempts, &options)?
+    {
+        println!("Aborted.");
+        return Ok(None);
+    }
+
+    fs::create_dir_all(&options.output_dir).map_err(|source| CliError::CreateOutputDir {
+        path: options.output_dir.clone(),
+        source,
+    })?;
+    let expected_keys = if grind_args.stream
+        || grind_args.watch
+        || grind_args.repl
+        || grind_args.daemon.is_some()
+        || grind_args.near.is_some()
+    {
+        None
+    } else if let Some(requested) = shard_requested {
+        Some(requested)
+    } else if grind_args.patterns.is_empty() {
+        Some(grind_args.count.unwrap_or(1))
+    } else {
+        Some(grind_args.patterns.iter().map(|p| p.count).sum())
+    };
+    if grind_args.machine_encrypted() {
+        let (machine_key, created) = MachineKey::load_or_create()?;
+        if created {
+            println!("Created machine key: {}", machine_key.path.display());
+        }
+        println!("Encryption at rest: keypair files are encrypted to the machine key {}", machine_key.public_key());
+        options.encrypt_to = Some(machine_key.recipients());
+    } else if grind_args.plaintext && options.save_secret {
+        println!("Encryption at rest: off (--plaintext); keypair files are written unencrypted");
+    }
+    if !options.tags.is_empty() {
+        println!("Tags: {}", options.tags.describe());
+    }
+    if let Some(handoff) = &options.handoff {
+        println!("Handoff: {}", handoff.describe());
+    }
+    if let Some(lifetime) = options.lifetime {
+        println!("Lifetime: {lifetime}; `doppler-keygen due` lists keys once it has passed");
+    }
+    let plan = OutputPlan { expected_keys, files_per_key: files_per_key(&options), no_sync_dirs: grind_args.no_sync_dirs };
+    preflight::check_output_dir(&options.output_dir, &plan)?;
+    if let Some(period) = grind_args.rotate {
+        options.rotation = Some(Arc::new(Rotation::new(period, grind_args.keep, &options.output_dir)));
+    }
+    if let (Some(job), Some(requested)) = (&options.job, shard_requested) {
+        job.start(requested, options.progress_interval, &options.output_dir);
+        if requested == 0 {
+            println!("This shard has no keys to find");
+            return Ok(Some((options, Vec::new())));
+        }
+    }
+    if !grind_args.no_index {
+        let key_index = KeyIndex::load(&options.output_dir)?;
+        println!("Key index: {} previously generated key(s) in {}", key_index.len(), index::INDEX_FILE);
+        options.key_index = Some(Arc::new(key_index));
+    }
+    if let Some(vault_path) = &grind_args.vault_path {
+        let vault_addr = grind_args
+            .vault_addr
+            .clone()
+            .or_else(|| config::env_var("VAULT_ADDR"))
+            .ok_or_else(|| CliError::Usage("--vault-path requires --vault-addr or VAULT_ADDR".to_string()))?;
+        options.vault = Some(Arc::new(VaultSink::connect(&vault_addr, vault_path)?));
+    }
+    if let Some(module) = &grind_args.pkcs11_module {
+        let sink = Pkcs11Sink::connect(module, grind_args.pkcs11_token.as_deref())?;
+        options.pkcs11 = Some(Arc::new(sink));
+    }
+    if !grind_args.publish.is_empty() {
+        options.publishers = Some(Arc::new(Publishers::connect(&grind_args.publish)?));
+    }
+    let sinks = Sinks::open(&config.sinks.clone().unwrap_or_default(), &grind_args.patterns)?;
+    if let Some(name) = sinks.networked().next().filter(|_| grind_args.hardened) {
+        return Err(CliError::Usage(format!("--hardened blocks the network, so pattern sink '{name}' cannot have a webhook")));
+    }
+    if let Some(name) = sinks.storing_secrets().next() {
+        if options.split.is_some() || options.kms.is_some() || options.vault.is_some() || options.pkcs11.is_some() {
+            return Err(CliError::Usage(format!(
+                "pattern sink '{name}' saves keypair files itself, so it cannot be combined with --split, --kms-key, \
+                 --vault-path or --pkcs11-module"
+            )));
+        }
+    }
+    if !sinks.is_empty() {
+        println!("Pattern sinks: {}", sinks.names().collect::<Vec<_>>().join(", "));
+        options.sinks = Some(Arc::new(sinks));
+    }
+    let notifiers = Notifiers::open(&config.notifiers.clone().unwrap_or_default())?;
+    if let Some(name) = notifiers.names().next().filter(|_| grind_args.hardened) {
+        return Err(CliError::Usage(format!("--hardened blocks the network, so it cannot post to notifier '{name}'")));
+    }
+    if !notifiers.is_empty() {
+        println!("Notifiers: {}", notifiers.names().collect::<Vec<_>>().join(", "));
+        options.notifiers = Some(Arc::new(notifiers));
+    }
+    let notify_urls = match grind_args.notify_urls.is_empty() {
+        true => config.notify_urls.clone().unwrap_or_default(),
+        false => grind_args.notify_urls.clone(),
+    };
+    if !notify_urls.is_empty() && grind_args.hardened {
+        return Err(CliError::Usage("--hardened blocks the network, so it cannot post to the config file's notify_urls".to_string()));
+    }
+    let webhooks = Webhooks::new(&notify_urls).map_err(CliError::Config)?;
+    if !webhooks.is_empty() {
+        println!("Notifying: {}", webhooks.origins().collect::<Vec<_>>().join(", "));
+        options.webhooks = Some(Arc::new(webhooks));
+    }
+    let email_to = config.email.as_ref().map_or(0, |email| email.to.len()) + grind_args.email_to.len();
+    let email = match &config.email {
+        Some(_) if grind_args.hardened && email_to > 0 => {
+            return Err(CliError::Usage("--hardened blocks the network, so it cannot send the [email] report".to_string()));
+        }
+        Some(email) if email_to > 0 => Some(EmailNotifier::new(email, &grind_args.email_to)?),
+        Some(_) => None,
+        None if email_to > 0 => {
+            return Err(CliError::Usage("--email-to needs an [email] table with an SMTP server in the config file".to_string()));
+        }
+        None => None,
+    };
+    if let Some(email) = &email {
+        println!("Email report: to {} when the run ends", email.recipients().collect::<Vec<_>>().join(", "));
+        options.report = Some(Arc::new(RunReport::new(expected_keys, &options.output_dir, &options.tags)));
+    }
+    if options.save_secret {
+        options.audit_log = Some(Arc::new(AuditLog::open(&options.output_dir)?));
+    }
+    options.results = Some(Arc::new(ResultsDb::open(&options.output_dir)?));
+    if let Some(job) = &options.job {
+        job.set_ready();
+    }
+    if grind_args.hardened {
+        hardening::apply()?;
+    }
+
+    let outcome = if let Some(near) = &grind_args.near {
+        grind_near(near, &options)
+    } else if grind_args.repl {
+        repl::run(&grind_args.patterns, grind_args.ignore_case, &options)
+    } else if let Some(settings) = grind_args.daemon {
+        let clients = config.clients.as_ref().map(Clients::new).transpose().map_err(CliError::Config)?;
+        daemon::run(settings, grind_args.ignore_case, clients.filter(|clients| clients.len() > 0), &options)
+    } else if grind_args.patterns.is_empty() && watch.is_none() {
+        grind_keys((!grind_args.stream).then(|| grind_args.count.unwrap_or(1)), &options)
+    } else {
+        vanity_keys_batch(&grind_args.patterns, grind_args.ignore_case, watch.as_deref(), &options)
+    };
+    if let (Some(email), Some(report)) = (&email, &options.report) {
+        // The keys are saved either way, so a failed email is only reported
+        match email.send(report, outcome.as_ref().err()) {
+            Ok(()) => println!("Email report sent to {}", email.recipients().collect::<Vec<_>>().join(", ")),
+            Err(e) => eprintln!("Warning: failed to send the email report: {e}"),
+        }
+    }
+    let found_keypairs = outcome?;
+    if !options.save_secret {
+        println!("Secret keys were discarded; public keys are in {}", options.output_dir.join(PUBKEY_RECORD_FILE).display());
+    }
+
+    if grind_args.install_as_default {
+        if let Some(keypair) = found_keypairs.first() {
+            install_as_default(keypair).map_err(|e| CliError::InstallDefault(e.to_string()))?;
+        }
+    }
+
+    Ok(Some((options, found_keypairs)))
+}
+
+/// Gives the grind only this shard's part of each key count, dropping
+/// patterns left with none, and returns how many keys the shard has to find.
+fn assign_shard(grind_args: &mut GrindArgs, shard: Shard) -> usize {
+    if grind_args.patterns.is_empty() {
+        let count = shard.share(grind_args.count.unwrap_or(1));
+        grind_args.count = Some(count);
+        return count;
+    }
+    for batch_pattern in &mut grind_args.patterns {
+        batch_pattern.count = shard.share(batch_pattern.count);
+    }
+    grind_args.patterns.retain(|batch_pattern| batch_pattern.count > 0);
+    grind_args.patterns.iter().map(|batch_pattern| batch_pattern.count).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grind::{record_result, report_match, save_batch_match, save_keypair};
+    use crate::inventory;
+    use crate::sinks::SinkConfig;
+    use doppler_keygen::batch::BatchMatch;
+    use doppler_keygen::pattern::VanityPattern;
+    use solana_signer::Signer as _;
+    use std::collections::BTreeMap;
+
+    /// The options of `grind --output-dir DIR ARGS...` with a results
+    /// database, as `run_grind` sets them up.
+    fn grind_options(dir: &Path, args: &[&str]) -> GrindOptions {
+        fs::create_dir_all(dir).unwrap();
+        let mut all_args = vec!["--output-dir".to_string(), dir.display().to_string()];
+        all_args.extend(args.iter().map(ToString::to_string));
+        let mut options = GrindArgs::parse(&all_args).unwrap().resolve(&Config::default()).unwrap();
+        options.results = Some(Arc::new(ResultsDb::open(dir).unwrap()));
+        options
+    }
+
+    fn save_and_record(options: &GrindOptions) {
+        let keypair = Keypair::new();
+        let files = save_keypair(&keypair, Some(0), options).unwrap();
+        record_result(options, &keypair, &keypair.pubkey().to_bytes(), Some(0), None, &files).unwrap();
+    }
+
+    /// `grind` arguments after `--config` naming a file with a few profiles.
+    fn profile_args(dir: &Path, args: &[&str]) -> Vec<String> {
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            "[profiles.farm]\nargs = [\"--num-threads\", \"3\", \"--output-dir\", \"/farm\"]\n\n\
+             [profiles.sealed]\nargs = []\nrequire_encryption = true\n\n\
+             [profiles.broken]\nargs = [\"--batch-size\", \"0\"]\n",
+        )
+        .unwrap();
+        let mut all_args = vec!["--config".to_string(), path.display().to_string()];
+        all_args.extend(args.iter().map(ToString::to_string));
+        all_args
+    }
+
+    #[test]
+    fn a_profile_goes_in_front_of_the_command_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let grind_args = GrindArgs::parse(&profile_args(dir.path(), &["--profile", "farm", "--output-dir", "/mine"])).unwrap();
+        assert_eq!(grind_args.profile.as_deref(), Some("farm"));
+        assert_eq!(grind_args.num_threads, Some(3));
+        // The command line's flag is parsed last, so it wins
+        assert_eq!(grind_args.output_dir, Some(PathBuf::from("/mine")));
+
+        let grind_args = GrindArgs::parse(&profile_args(dir.path(), &["--profile", "vanity-short"])).unwrap();
+        assert!(grind_args.ignore_case);
+        assert_eq!(grind_args.output_formats, Some(vec![OutputFormat::Json, OutputFormat::Bs58]));
+    }
+
+    #[test]
+    fn conflicting_options_are_refused() {
+        let parse = |args: &[&str]| GrindArgs::parse(&args.iter().map(ToString::to_string).collect::<Vec<_>>()).err();
+        let error = parse(&["--plaintext", "--split", "2/3"]).unwrap();
+        assert_eq!(error, "--plaintext writes the keypair files unencrypted, so it cannot be combined with --split");
+        let error = parse(&["2", "--starts-with", "ab:1"]).unwrap();
+        assert!(error.starts_with("A key count is given in each PATTERN:COUNT"), "{error}");
+        assert_eq!(parse(&["--keep", "3"]).unwrap(), "--keep requires --rotate");
+        assert_eq!(parse(&["--rotate", "daily"]).unwrap(), "--rotate requires --stream or --watch");
+        assert!(parse(&["--stream", "--rotate", "daily", "--keep", "3"]).is_none());
+    }
+
+    #[test]
+    fn profile_errors_name_the_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = GrindArgs::parse(&profile_args(dir.path(), &["--profile", "sealed", "--plaintext"])).err().unwrap();
+        assert!(error.contains("Profile 'sealed' never writes a secret key in the clear"), "{error}");
+        let error = GrindArgs::parse(&profile_args(dir.path(), &["--profile", "broken"])).err().unwrap();
+        assert!(error.ends_with("(with profile 'broken')"), "{error}");
+        let error = GrindArgs::parse(&profile_args(dir.path(), &["--profile", "nope"])).err().unwrap();
+        assert!(error.starts_with("Unknown profile 'nope'") && error.contains("farm"), "{error}");
+    }
+
+    #[test]
+    fn freshly_saved_keys_are_not_flagged_by_inventory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut encrypted = grind_options(&dir.path().join("encrypted"), &[]);
+        encrypted.encrypt_to = Some(AgeRecipients::from(vec![age::x25519::Identity::generate().to_public()]));
+        save_and_record(&encrypted);
+        save_and_record(&grind_options(&dir.path().join("plain"), &["--plaintext"]));
+
+        let inventory = inventory::take(&[dir.path().join("encrypted"), dir.path().join("plain")]).unwrap();
+        assert_eq!(inventory.keys.len(), 2);
+        assert!(inventory.keys[0].flags.is_empty(), "{:?}", inventory.keys[0].flags);
+        // Only what --plaintext asked for, not weak permissions
+        assert_eq!(inventory.keys[1].flags, ["plaintext"]);
+    }
+
+    #[test]
+    fn a_key_that_fails_to_save_is_not_indexed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = grind_options(dir.path(), &["--plaintext"]);
+        options.key_index = Some(Arc::new(KeyIndex::load(dir.path()).unwrap()));
+        let keypair = Keypair::new();
+        // A directory where the keypair file would go makes the save fail
+        let blocker = dir.path().join(format!("{}.json", keypair.pubkey()));
+        fs::create_dir(&blocker).unwrap();
+        assert!(report_match(&keypair, 0, 0, None, &options).is_err());
+        assert_eq!(options.key_index.as_ref().unwrap().len(), 0);
+
+        fs::remove_dir(&blocker).unwrap();
+        report_match(&keypair, 0, 0, None, &options).unwrap();
+        assert_eq!(options.key_index.as_ref().unwrap().len(), 1);
+        assert!(report_match(&keypair, 0, 0, None, &options).is_err());
+    }
+
+    fn batch_match(sink: Option<&str>) -> BatchMatch {
+        let keypair = Keypair::new();
+        let address = keypair.pubkey().to_string();
+        BatchMatch {
+            key: doppler_keygen::grinder::FoundKey { keypair, address, segment: 0, thread_id: 0 },
+            pattern_index: 0,
+            pattern: VanityPattern { prefix: Some("a".to_string()), suffix: None },
+            requested: 1,
+            pattern_number: 1,
+            sink: sink.map(str::to_string),
+            scores: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn pattern_keys_are_saved_to_their_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().join("out");
+        let team_dir = dir.path().join("team");
+        let mut options = grind_options(&output_dir, &["--plaintext"]);
+        let team = SinkConfig { dir: Some(team_dir.clone()), ..SinkConfig::default() };
+        let patterns = [BatchPattern { sink: Some("team".to_string()), ..parse_batch_pattern("--starts-with", "a:1").unwrap() }];
+        let configs = BTreeMap::from([("team".to_string(), team)]);
+        options.sinks = Some(Arc::new(Sinks::open(&configs, &patterns).unwrap()));
+
+        let routed = batch_match(Some("team"));
+        assert!(save_batch_match(&routed, &options));
+        let file = format!("{}.json", routed.key.address);
+        assert!(team_dir.join(&file).exists());
+        assert!(!output_dir.join(&file).exists());
+
+        let unrouted = batch_match(None);
+        assert!(save_batch_match(&unrouted, &options));
+        let file = format!("{}.json", unrouted.key.address);
+        assert!(output_dir.join(&file).exists());
+        assert!(!team_dir.join(&file).exists());
+    }
+}
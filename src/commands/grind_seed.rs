@@ -0,0 +1,162 @@
+//! The `grind-seed` command: `create_with_seed` seeds for imm32 compatible stake and nonce accounts.
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use doppler_keygen::derive;
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::pattern::{BatchPattern, VanityPattern};
+use doppler_keygen::progress;
+use doppler_keygen::shutdown::ShutdownToken;
+use doppler_keygen::stats::StatsCollector;
+
+use crate::cli_error::CliError;
+use crate::commands::check_patterns;
+use crate::grind::{finish_progress_reporter, parse_batch_pattern, print_progress, print_summary, stop_on_interrupt};
+use crate::seeds::{self, SeedAccount, SeedSearch};
+
+/// Characters in each seed tried by `grind-seed`.
+const DEFAULT_SEED_LEN: usize = 12;
+
+/// Arguments of `grind-seed`.
+struct GrindSeedArgs {
+    base: [u8; 32],
+    account: SeedAccount,
+    count: Option<usize>,
+    patterns: Vec<BatchPattern>,
+    ignore_case: bool,
+    num_threads: Option<usize>,
+    progress_interval: u64,
+    seed_len: usize,
+}
+
+impl GrindSeedArgs {
+    fn parse(args: &[String]) -> Result<GrindSeedArgs, String> {
+        let mut base = None;
+        let mut account = None;
+        let mut count = None;
+        let mut patterns = Vec::new();
+        let mut ignore_case = false;
+        let mut num_threads = None;
+        let mut progress_interval = 5;
+        let mut seed_len = DEFAULT_SEED_LEN;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--base" => {
+                    let value = value()?;
+                    let bytes: [u8; 32] = bs58::decode(value)
+                        .into_vec()
+                        .ok()
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .ok_or_else(|| format!("Invalid base public key '{value}'"))?;
+                    base = Some(bytes);
+                }
+                "--account" => account = Some(SeedAccount::parse(value()?)?),
+                "--starts-with" | "--ends-with" | "--starts-and-ends-with" => {
+                    patterns.push(parse_batch_pattern(arg, value()?)?);
+                }
+                "--ignore-case" => ignore_case = true,
+                "--num-threads" => {
+                    let value = value()?;
+                    num_threads = Some(
+                        value
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| format!("Invalid thread count '{value}'"))?,
+                    );
+                }
+                "--progress-interval" => {
+                    let value = value()?;
+                    progress_interval = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("Invalid progress interval '{value}'"))?;
+                }
+                "--seed-length" => {
+                    let value = value()?;
+                    seed_len = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|n| (1..=derive::MAX_SEED_LEN).contains(n))
+                        .ok_or_else(|| format!("Invalid seed length '{value}': must be 1 to {}", derive::MAX_SEED_LEN))?;
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                value if count.is_none() => {
+                    count = Some(value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| "Invalid count number".to_string())?);
+                }
+                other => return Err(format!("Unexpected argument '{other}'")),
+            }
+        }
+
+        let base = base.ok_or_else(|| "grind-seed requires --base PUBKEY".to_string())?;
+        let account = account.ok_or_else(|| "grind-seed requires --account stake|nonce".to_string())?;
+        check_patterns(count, &patterns, ignore_case)?;
+        Ok(GrindSeedArgs { base, account, count, patterns, ignore_case, num_threads, progress_interval, seed_len })
+    }
+}
+
+/// Grinds `create_with_seed` seeds for stake or nonce accounts under an
+/// existing base key.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let GrindSeedArgs { base, account, count, mut patterns, ignore_case, num_threads, progress_interval, seed_len } =
+        GrindSeedArgs::parse(args).map_err(CliError::Usage)?;
+    for batch_pattern in &patterns {
+        batch_pattern.pattern.validate(ignore_case).map_err(DopplerError::InvalidPattern)?;
+    }
+    if patterns.is_empty() {
+        // The empty pattern accepts any imm32 compatible address
+        patterns.push(BatchPattern {
+            pattern: VanityPattern::default(),
+            count: count.unwrap_or(1),
+            found: 0,
+            priority: 0,
+            sink: None,
+        });
+    }
+    let num_threads = match num_threads {
+        Some(num_threads) => num_threads,
+        None => thread::available_parallelism().map_err(CliError::Parallelism)?.get(),
+    };
+
+    let search = SeedSearch { base, account, seed_len, patterns, ignore_case };
+    let total_count: usize = search.patterns.iter().map(|p| p.count).sum();
+    println!("Doppler Keygen - Mining {seed_len}-character seeds for imm32 compatible {} accounts", match account {
+        SeedAccount::Stake => "stake",
+        SeedAccount::Nonce => "nonce",
+    });
+    println!("Base: {}", bs58::encode(base).into_string());
+    println!("Using {num_threads} threads\n");
+
+    let stats = Arc::new(StatsCollector::new(num_threads));
+    let shutdown = ShutdownToken::new();
+    let keys_found = Arc::new(AtomicUsize::new(0));
+    stop_on_interrupt(&shutdown)?;
+    let progress_handle =
+        progress::spawn_reporter(&stats, &shutdown, Duration::from_secs(progress_interval), Arc::clone(&keys_found), move |snapshot| {
+            print_progress(&snapshot, Some(total_count))
+        })?;
+
+    let result = seeds::run(&search, num_threads, &stats, &shutdown, &keys_found, |seed_match| {
+        let batch_pattern = &search.patterns[seed_match.pattern_index];
+        println!("\n✅ FOUND MATCHING SEED #{}/{}", seed_match.pattern_number, batch_pattern.count);
+        if batch_pattern.pattern != VanityPattern::default() {
+            println!("Vanity Pattern: {}", batch_pattern.pattern);
+        }
+        println!("Seed: {}", seed_match.seed);
+        println!("Address: {}", seed_match.address);
+        let offset = seed_match.segment * 8;
+        println!("Matched Segment: {} (bytes {}-{})", seed_match.segment, offset, offset + 7);
+        println!("Create it with: {}", account.command(&seed_match.seed));
+    });
+    finish_progress_reporter(&shutdown, progress_handle);
+    let matches = result?;
+
+    print_summary(&format!("{}/{total_count}", matches.len()), &stats.summary());
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! The `import` command: keypair files written by other tools brought into an output directory.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use solana_signer::Signer as _;
+
+use crate::audit::AuditLog;
+use crate::cli_error::CliError;
+use crate::config::{self, Config};
+use crate::due::Lifetime;
+use crate::encrypt;
+use crate::grind::OutputFormat;
+use crate::import::{self, FoundKeypair};
+use crate::index::KeyIndex;
+use crate::machinekey::MachineKey;
+use crate::redact;
+use crate::results::{KeyRecord, ResultsDb};
+use crate::secretfile;
+
+/// Arguments of `import`.
+struct ImportArgs {
+    dirs: Vec<PathBuf>,
+    /// Falls back to the config file's output directory
+    output_dir: Option<PathBuf>,
+    plaintext: bool,
+    dry_run: bool,
+    lifetime: Option<Lifetime>,
+}
+
+impl ImportArgs {
+    fn parse(args: &[String]) -> Result<ImportArgs, String> {
+        let mut dirs = Vec::new();
+        let mut output_dir = None;
+        let mut plaintext = false;
+        let mut dry_run = false;
+        let mut lifetime = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--output-dir" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    output_dir = Some(PathBuf::from(value));
+                }
+                "--lifetime" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    lifetime = Some(Lifetime::parse(value)?);
+                }
+                "--plaintext" => plaintext = true,
+                "--dry-run" => dry_run = true,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                dir => dirs.push(PathBuf::from(dir)),
+            }
+        }
+        if dirs.is_empty() {
+            return Err("import requires at least one directory".to_string());
+        }
+        Ok(ImportArgs { dirs, output_dir, plaintext, dry_run, lifetime })
+    }
+}
+
+/// Imports keypair files written by other tools into an output directory
+/// and its results database.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let ImportArgs { dirs, output_dir, plaintext, dry_run, lifetime } = ImportArgs::parse(args).map_err(CliError::Usage)?;
+    let output_dir = match output_dir {
+        Some(output_dir) => output_dir,
+        None => {
+            let config_path = config::env_var("DOPPLER_CONFIG").map(PathBuf::from);
+            let config = Config::load(config_path.as_deref())
+                .and_then(|config| Ok(config.merge(Config::from_env()?)))
+                .map_err(CliError::Config)?;
+            config.output_dir.unwrap_or_else(|| PathBuf::from("."))
+        }
+    };
+
+    let mut found = Vec::new();
+    for dir in &dirs {
+        found.extend(import::find_keypairs(dir)?);
+    }
+    if found.is_empty() {
+        println!("No keypair files found");
+        return Ok(());
+    }
+    fs::create_dir_all(&output_dir)
+        .map_err(|source| CliError::CreateOutputDir { path: output_dir.clone(), source })?;
+    let results = ResultsDb::open(&output_dir)?;
+    let recipients = if plaintext || dry_run {
+        None
+    } else {
+        let (machine_key, created) = MachineKey::load_or_create()?;
+        if created {
+            println!("Created machine key: {}", machine_key.path.display());
+        }
+        Some(machine_key.recipients())
+    };
+    let (key_index, audit_log) = match dry_run {
+        true => (None, None),
+        false => (Some(KeyIndex::load(&output_dir)?), Some(AuditLog::open(&output_dir)?)),
+    };
+
+    let mut seen: HashMap<String, &PathBuf> = HashMap::new();
+    let (mut imported, mut recorded, mut duplicates) = (0, 0, 0);
+    for FoundKeypair { path, keypair } in &found {
+        redact::register(keypair);
+        let pubkey = keypair.pubkey().to_string();
+        if let Some(first) = seen.get(&pubkey) {
+            println!("Skipping {}: the same key as {}", path.display(), first.display());
+            duplicates += 1;
+            continue;
+        }
+        seen.insert(pubkey.clone(), path);
+        if results.get(&pubkey)?.is_some() {
+            println!("Skipping {}: {pubkey} is already in {}", path.display(), results.path().display());
+            recorded += 1;
+            continue;
+        }
+
+        let mut record = KeyRecord::new(pubkey.clone(), &keypair.pubkey().to_bytes(), "import");
+        record.origin = Some(path.clone());
+        record.rotate_by = lifetime.map(|lifetime| record.recorded_at + lifetime.0);
+        let analysis = record.analysis();
+        let json = output_dir.join(format!("{pubkey}.{}", OutputFormat::Json.extension()));
+        let encrypted = PathBuf::from(format!("{}.{}", json.display(), encrypt::ENCRYPTED_EXTENSION));
+        let existing = [&json, &encrypted].into_iter().find(|file| file.exists());
+        if dry_run {
+            let action = if existing.is_some() { "Would record" } else { "Would import" };
+            println!("{action} {pubkey} from {} ({analysis})", path.display());
+            imported += 1;
+            continue;
+        }
+
+        let file = match existing {
+            // Already in the output directory under its own name; only the
+            // database needs it
+            Some(file) => file.clone(),
+            None => {
+                let (file, contents) = match &recipients {
+                    Some(recipients) => (encrypted, recipients.encrypt(&OutputFormat::Json.encode(keypair))?),
+                    None => (json, OutputFormat::Json.encode(keypair)),
+                };
+                secretfile::write(&file, &contents).map_err(|source| CliError::WriteKeypair { path: file.clone(), source })?;
+                if let Some(audit_log) = &audit_log {
+                    audit_log.record(&pubkey)?;
+                }
+                file
+            }
+        };
+        if let Some(key_index) = &key_index {
+            // A key ground into this directory before is already indexed
+            match key_index.insert(&pubkey) {
+                Ok(()) | Err(CliError::DuplicateKey(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        record.files = vec![results.relative(&file)];
+        results.save(&record)?;
+        println!("Imported {pubkey} from {} to {} ({analysis})", path.display(), file.display());
+        imported += 1;
+    }
+
+    let verb = if dry_run { "would be imported" } else { "imported" };
+    println!(
+        "\n{imported} key(s) {verb} into {}; {recorded} already recorded, {duplicates} duplicate file(s)",
+        results.path().display()
+    );
+    if imported > 0 && !dry_run {
+        println!("The source files were left in place; remove them with `doppler-keygen shred` once the import is checked");
+    }
+    Ok(())
+}
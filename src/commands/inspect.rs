@@ -0,0 +1,79 @@
+//! The `inspect` command: a full report on a key's address.
+
+use crate::commands::read_address;
+use crate::inspect;
+
+/// Arguments of `inspect`.
+struct InspectArgs {
+    address: [u8; 32],
+}
+
+impl InspectArgs {
+    fn parse(args: &[String]) -> Result<InspectArgs, String> {
+        let [arg] = args else {
+            return Err("inspect takes a single keypair or address".to_string());
+        };
+        Ok(InspectArgs { address: read_address(arg)? })
+    }
+}
+
+/// Prints a full report on a key's address.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let InspectArgs { address } = InspectArgs::parse(args)?;
+    print!("{}", inspect::report(&address));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grind::OutputFormat;
+    use solana_keypair::Keypair;
+    use solana_signer::Signer as _;
+    use std::fs;
+    use std::path::Path;
+
+    fn parse(path: &Path) -> Result<[u8; 32], String> {
+        InspectArgs::parse(&[path.display().to_string()]).map(|args| args.address)
+    }
+
+    #[test]
+    fn reports_the_address_of_a_keypair_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = Keypair::new();
+        let json = dir.path().join("id.json");
+        fs::write(&json, OutputFormat::Json.encode(&keypair)).unwrap();
+        let bin = dir.path().join("id.bin");
+        fs::write(&bin, keypair.to_bytes()).unwrap();
+
+        for path in [json, bin] {
+            let address = parse(&path).unwrap();
+            assert_eq!(address, keypair.pubkey().to_bytes(), "{}", path.display());
+            let report = inspect::report(&address);
+            assert!(report.starts_with(&format!("Address (base58): {}\n", keypair.pubkey())), "{report}");
+        }
+    }
+
+    #[test]
+    fn corrupt_and_mismatched_keypair_files_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = Keypair::new();
+        // A secret key paired with the public half of another key
+        let mut mismatched = keypair.to_bytes();
+        mismatched[32..].copy_from_slice(&Keypair::new().pubkey().to_bytes());
+        let files: [(&str, Vec<u8>, &str); 3] = [
+            ("truncated.json", b"[1, 2, 3".to_vec(), "EOF while parsing"),
+            ("short.json", serde_json::to_vec(&keypair.to_bytes()[..63]).unwrap(), "signature error"),
+            ("mismatched.json", serde_json::to_vec(&mismatched.to_vec()).unwrap(), "signature error"),
+        ];
+        for (name, contents, reason) in files {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            let error = parse(&path).unwrap_err();
+            assert!(error.contains(&path.display().to_string()) && error.contains(reason), "{error}");
+        }
+
+        // A missing file is read as a base58 address, which it isn't
+        assert!(parse(&dir.path().join("missing.json")).is_err());
+    }
+}
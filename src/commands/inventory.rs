@@ -0,0 +1,62 @@
+//! The `inventory` command: report on the keys under some directories.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::inventory;
+
+/// Arguments of `inventory`.
+struct InventoryArgs {
+    dirs: Vec<PathBuf>,
+    format: String,
+    out: Option<PathBuf>,
+}
+
+impl InventoryArgs {
+    fn parse(args: &[String]) -> Result<InventoryArgs, String> {
+        let mut dirs = Vec::new();
+        let mut format = "text".to_string();
+        let mut out = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--format" | "--out" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    match arg.as_str() {
+                        "--format" => format = value.clone(),
+                        _ => out = Some(PathBuf::from(value)),
+                    }
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                dir => dirs.push(PathBuf::from(dir)),
+            }
+        }
+        if dirs.is_empty() {
+            return Err("inventory requires at least one directory".to_string());
+        }
+        Ok(InventoryArgs { dirs, format, out })
+    }
+}
+
+/// Reports on the keys under each directory, as text, CSV or JSON.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let InventoryArgs { dirs, format, out } = InventoryArgs::parse(args)?;
+    let inventory = inventory::take(&dirs).map_err(|e| e.to_string())?;
+    let report = match format.as_str() {
+        "text" => inventory.to_text(),
+        "csv" => inventory.to_csv(),
+        "json" => {
+            let json = serde_json::to_string_pretty(&inventory).expect("the inventory serializes to JSON");
+            format!("{json}\n")
+        }
+        other => return Err(format!("Unknown report format '{other}' (expected text, csv or json)")),
+    };
+    match out {
+        Some(path) => {
+            fs::write(&path, report).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+            println!("Wrote the inventory of {} key(s) to {}", inventory.summary.keys, path.display());
+        }
+        None => print!("{report}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! The `job` command: one shard of a grind run as a Kubernetes Job.
+
+use std::sync::Arc;
+
+use crate::cli_error::CliError;
+use crate::commands::grind::{self, GrindArgs};
+use crate::config;
+use crate::job::{self, JobStatus, Shard};
+
+/// Arguments of `job`: the grind options along with `--health-port`.
+struct JobArgs {
+    health_port: Option<u16>,
+    grind_args: GrindArgs,
+}
+
+impl JobArgs {
+    fn parse(args: &[String]) -> Result<JobArgs, String> {
+        let mut health_port = config::env_var("DOPPLER_HEALTH_PORT");
+        let mut grind_args = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--health-port" => health_port = Some(iter.next().ok_or_else(|| format!("{arg} requires a value"))?.clone()),
+                _ => grind_args.push(arg.clone()),
+            }
+        }
+        let health_port = health_port
+            .map(|port| port.parse::<u16>().map_err(|_| format!("Invalid health port '{port}'")))
+            .transpose()?;
+        let grind_args = GrindArgs::parse(&grind_args)?;
+        grind_args.refuse(
+            "a job",
+            &["--hardened", "--air-gapped"],
+            "serves health probes and runs on a cluster network",
+        )?;
+        grind_args.refuse(
+            "a job",
+            &["--stream", "--watch", "--near", "--install-as-default", "--auto-tune"],
+            "has to finish on its own and runs unattended",
+        )?;
+        Ok(JobArgs { health_port, grind_args })
+    }
+}
+
+/// Runs one shard of a grind as a Kubernetes Job: takes the grind options,
+/// serves liveness and readiness probes on `--health-port` (or
+/// `DOPPLER_HEALTH_PORT`), and ends with a JSON summary. Fails unless every
+/// key of the shard was saved, so the Job retries the pod.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let JobArgs { health_port, mut grind_args } = JobArgs::parse(args).map_err(CliError::Usage)?;
+    let shard = Shard::from_env().map_err(CliError::Config)?;
+    let job = Arc::new(JobStatus::new(shard));
+    if let Some(port) = health_port {
+        job::serve_probes(port, Arc::clone(&job))?;
+    }
+    println!("Shard {} of {} (numbered from 0)", shard.index, shard.count);
+
+    // Nobody is there to answer the confirmation prompt
+    grind_args.yes = true;
+    grind_args.job = Some(Arc::clone(&job));
+    match grind::run(grind_args) {
+        Ok(_) if job.finish(None) => Ok(()),
+        Ok(_) => Err(CliError::Usage("The shard stopped before all of its keys were saved".to_string())),
+        Err(e) => {
+            job.finish(Some(e.to_string()));
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! The `kms-decrypt` command: unwraps a keypair file written with `grind --kms-key`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::kms;
+use crate::secretfile;
+
+/// Arguments of `kms-decrypt`.
+struct KmsDecryptArgs {
+    input: PathBuf,
+    output: Option<PathBuf>,
+}
+
+impl KmsDecryptArgs {
+    fn parse(args: &[String]) -> Result<KmsDecryptArgs, String> {
+        let mut output = None;
+        let mut input = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--output" | "-o" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    output = Some(PathBuf::from(value));
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                path if input.is_none() => input = Some(PathBuf::from(path)),
+                other => return Err(format!("Unexpected argument '{other}'")),
+            }
+        }
+        let input = input.ok_or_else(|| "kms-decrypt requires a wrapped keypair file".to_string())?;
+        Ok(KmsDecryptArgs { input, output })
+    }
+}
+
+/// Unwraps a keypair file written with `grind --kms-key`.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let KmsDecryptArgs { input, output } = KmsDecryptArgs::parse(args)?;
+    let contents = fs::read(&input).map_err(|e| format!("Failed to read {}: {e}", input.display()))?;
+    let plaintext = kms::open(&contents).map_err(|e| format!("{}: {e}", input.display()))?;
+
+    let path = match output {
+        Some(output) => output,
+        None if input.extension().is_some_and(|extension| extension == kms::WRAPPED_EXTENSION) => input.with_extension(""),
+        None => return Err(format!("Pass --output; {} does not end in .{}", input.display(), kms::WRAPPED_EXTENSION)),
+    };
+    secretfile::write(&path, &plaintext).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    println!("Decrypted {} into {}", input.display(), path.display());
+    Ok(())
+}
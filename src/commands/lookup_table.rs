@@ -0,0 +1,87 @@
+//! The `lookup-table` command: recent slots ranked by the lookup table address they give.
+
+use core::ops::Range;
+
+use doppler_keygen::pattern::VanityPattern;
+
+use crate::lookup_table;
+
+/// Slots listed by `lookup-table` unless `--top` says otherwise.
+const DEFAULT_LOOKUP_TABLE_TOP: usize = 10;
+
+/// Arguments of `lookup-table`.
+struct LookupTableArgs {
+    authority: [u8; 32],
+    slots: Range<u64>,
+    patterns: Vec<VanityPattern>,
+    ignore_case: bool,
+    top: usize,
+}
+
+impl LookupTableArgs {
+    fn parse(args: &[String]) -> Result<LookupTableArgs, String> {
+        let mut authority = None;
+        let mut slots = None;
+        let mut patterns = Vec::new();
+        let mut ignore_case = false;
+        let mut top = DEFAULT_LOOKUP_TABLE_TOP;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--authority" => {
+                    let value = value()?;
+                    let bytes: [u8; 32] = bs58::decode(value)
+                        .into_vec()
+                        .ok()
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .ok_or_else(|| format!("Invalid authority '{value}'"))?;
+                    authority = Some(bytes);
+                }
+                "--slots" => slots = Some(lookup_table::parse_slots(value()?)?),
+                "--starts-with" => patterns.push(VanityPattern { prefix: Some(value()?.clone()), suffix: None }),
+                "--ends-with" => patterns.push(VanityPattern { prefix: None, suffix: Some(value()?.clone()) }),
+                "--ignore-case" => ignore_case = true,
+                "--top" => {
+                    let value = value()?;
+                    top = value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid --top '{value}'"))?;
+                }
+                other => return Err(format!("Unexpected argument '{other}'")),
+            }
+        }
+
+        let authority = authority.ok_or_else(|| "lookup-table requires --authority PUBKEY".to_string())?;
+        let slots = slots.ok_or_else(|| "lookup-table requires --slots START..END".to_string())?;
+        for pattern in &patterns {
+            pattern.validate(ignore_case)?;
+        }
+        Ok(LookupTableArgs { authority, slots, patterns, ignore_case, top })
+    }
+}
+
+/// Ranks the recent slots an authority could create an address lookup table
+/// at by how close the resulting table address comes to imm32 compatibility.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let LookupTableArgs { authority, slots, patterns, ignore_case, top } = LookupTableArgs::parse(args)?;
+
+    println!("Scanning {} slots ({}..{}) for authority {}", slots.end - slots.start, slots.start, slots.end, bs58::encode(authority).into_string());
+    let best = lookup_table::scan(&authority, slots, &patterns, ignore_case, top);
+    if best.is_empty() {
+        println!("No slot in the range gives a table address matching the patterns");
+        return Ok(());
+    }
+
+    println!("\n{:<12} {:<44}  Closest segment", "Slot", "Table address");
+    for candidate in &best {
+        let closeness = if candidate.sign_bytes == 4 {
+            let offset = candidate.segment * 8;
+            let bytes = &candidate.address[offset..offset + 4];
+            format!("{}: imm32 {}", candidate.segment, i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        } else {
+            format!("{}: {}/4 sign-extension bytes", candidate.segment, candidate.sign_bytes)
+        };
+        println!("{:<12} {:<44}  {closeness}", candidate.slot, bs58::encode(candidate.address).into_string());
+    }
+    println!("\nPass the slot as recent_slot to CreateLookupTable while it is among the last 512 slots");
+    Ok(())
+}
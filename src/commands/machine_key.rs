@@ -0,0 +1,38 @@
+//! The `machine-key` command: shows or creates the key that keypair files are encrypted to.
+
+use crate::cli_error::CliError;
+use crate::machinekey::{self, MachineKey};
+
+/// Arguments of `machine-key`: `machine-key [init [--passphrase]]`.
+enum MachineKeyArgs {
+    Show,
+    Init { passphrase: bool },
+}
+
+impl MachineKeyArgs {
+    fn parse(args: &[String]) -> Result<MachineKeyArgs, String> {
+        match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            [] => Ok(MachineKeyArgs::Show),
+            ["init"] => Ok(MachineKeyArgs::Init { passphrase: false }),
+            ["init", "--passphrase"] => Ok(MachineKeyArgs::Init { passphrase: true }),
+            _ => Err("Usage: doppler-keygen machine-key [init [--passphrase]]".to_string()),
+        }
+    }
+}
+
+/// Shows the machine key, or creates it with `init [--passphrase]`.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let key = match MachineKeyArgs::parse(args).map_err(CliError::Usage)? {
+        MachineKeyArgs::Show => MachineKey::load()?.ok_or_else(|| {
+            CliError::Usage(format!(
+                "No machine key at {}; the first grind creates one, or run machine-key init",
+                machinekey::path().map(|path| path.display().to_string()).unwrap_or_default()
+            ))
+        })?,
+        MachineKeyArgs::Init { passphrase } => machinekey::init(passphrase)?,
+    };
+    println!("Machine key: {}", key.path.display());
+    println!("Public key: {}", key.public_key());
+    println!("Passphrase: {}", if key.protected { "yes" } else { "no" });
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! The `migrate` command: encrypt plaintext keypair files to the machine key.
+
+use std::path::PathBuf;
+
+use crate::cli_error::CliError;
+use crate::machinekey::{MachineKey, UnlockedKey};
+use crate::migrate;
+use crate::redact;
+
+/// Arguments of `migrate`.
+struct MigrateArgs {
+    dirs: Vec<PathBuf>,
+    dry_run: bool,
+}
+
+impl MigrateArgs {
+    fn parse(args: &[String]) -> Result<MigrateArgs, CliError> {
+        let mut dirs = Vec::new();
+        let mut dry_run = false;
+        for arg in args {
+            match arg.as_str() {
+                "--dry-run" => dry_run = true,
+                flag if flag.starts_with("--") => return Err(CliError::Usage(format!("Unknown option '{flag}'"))),
+                dir => dirs.push(PathBuf::from(dir)),
+            }
+        }
+        if dirs.is_empty() {
+            return Err(CliError::Usage("migrate requires at least one directory".to_string()));
+        }
+        Ok(MigrateArgs { dirs, dry_run })
+    }
+}
+
+/// Encrypts the plaintext keypair files under each directory to the machine
+/// key, shredding the originals.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let MigrateArgs { dirs, dry_run } = MigrateArgs::parse(args)?;
+    let mut files = Vec::new();
+    for dir in &dirs {
+        files.extend(migrate::plaintext_keypairs(dir)?);
+    }
+    if files.is_empty() {
+        println!("No plaintext keypair files found");
+        return Ok(());
+    }
+    if dry_run {
+        for file in &files {
+            println!("Would encrypt {}", file.display());
+        }
+        println!("{} plaintext keypair file(s) would be encrypted to the machine key", files.len());
+        return Ok(());
+    }
+
+    let (machine_key, created) = MachineKey::load_or_create()?;
+    if created {
+        println!("Created machine key: {}", machine_key.path.display());
+    }
+    // Unlocked once, to check every file decrypts before its plaintext goes
+    let unlocked = UnlockedKey::unlock()?;
+    let recipients = machine_key.recipients();
+    let mut failed = 0;
+    for file in &files {
+        match migrate::migrate_file(file, &recipients, &unlocked) {
+            Ok(path) => println!("Encrypted {} to {}", file.display(), path.display()),
+            Err(e) => {
+                eprintln!("Error: {}", redact::scrub(&e.to_string()));
+                failed += 1;
+            }
+        }
+    }
+    println!("Migrated {} of {} keypair file(s) to the machine key {}", files.len() - failed, files.len(), machine_key.public_key());
+    match failed {
+        0 => Ok(()),
+        _ => Err(CliError::Usage(format!("{failed} keypair file(s) could not be migrated and were left as they were"))),
+    }
+}
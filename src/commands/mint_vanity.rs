@@ -0,0 +1,98 @@
+//! The `mint-vanity` command: vanity mint keypairs and how to create each mint.
+
+use solana_signer::Signer as _;
+
+use crate::cli_error::CliError;
+use crate::commands::grind::{self, GrindArgs};
+use crate::grind::OutputFormat;
+use crate::mint::{MintPlan, TokenProgram};
+
+/// Arguments of `mint-vanity`: how to create each mint, along with the grind
+/// options.
+struct MintVanityArgs {
+    plan: MintPlan,
+    /// Print the raw instructions instead of an `spl-token` command
+    json: bool,
+    grind_args: GrindArgs,
+}
+
+impl MintVanityArgs {
+    fn parse(args: &[String]) -> Result<MintVanityArgs, String> {
+        let mut plan = MintPlan {
+            program: TokenProgram::Token2022,
+            decimals: 9,
+            mint_authority: None,
+            enable_freeze: false,
+            payer: None,
+        };
+        let mut json = false;
+        let mut grind_args = Vec::new();
+        let mut output_format = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--decimals" => {
+                    let value = value()?;
+                    plan.decimals = value.parse().map_err(|_| format!("Invalid decimals '{value}'"))?;
+                }
+                "--token-program" => plan.program = TokenProgram::parse(value()?)?,
+                "--mint-authority" => plan.mint_authority = Some(value()?.clone()),
+                "--payer" => plan.payer = Some(value()?.clone()),
+                "--enable-freeze" => plan.enable_freeze = true,
+                "--json" => json = true,
+                "--output-format" => output_format = Some(value()?.clone()),
+                // Checked before `--plaintext` is added below, which these
+                // would conflict with
+                "--no-save-secret" | "--split" | "--encrypt-to" | "--kms-key" | "--vault-path" | "--pkcs11-module"
+                | "--qr-secret" | "--stream" | "--derive" => {
+                    return Err(format!(
+                        "{arg} cannot be used with mint-vanity, which needs a plain JSON keypair file for each mint"
+                    ));
+                }
+                _ => grind_args.push(arg.clone()),
+            }
+        }
+        if json && plan.mint_authority.is_none() {
+            return Err("--json requires --mint-authority".to_string());
+        }
+        // spl-token reads the mint keypair from a plaintext JSON keypair file
+        grind_args.push("--plaintext".to_string());
+        match output_format {
+            Some(formats) if !formats.split(',').any(|format| format == "json") => {
+                return Err("mint-vanity needs json among the --output-format formats".to_string());
+            }
+            formats => grind_args.extend(["--output-format".to_string(), formats.unwrap_or_else(|| "json".to_string())]),
+        }
+        let grind_args = GrindArgs::parse(&grind_args)?;
+        // `--count 0` streams
+        grind_args.refuse("mint-vanity", &["--stream"], "needs a key count")?;
+        Ok(MintVanityArgs { plan, json, grind_args })
+    }
+}
+
+/// Grinds mint keypairs and prints how to create each mint: an `spl-token`
+/// command, or with `--json` the raw instructions. Any other arguments are
+/// passed on to `grind`.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let MintVanityArgs { plan, json, grind_args } = MintVanityArgs::parse(args).map_err(CliError::Usage)?;
+    let Some((options, mints)) = grind::run(grind_args)? else {
+        return Ok(());
+    };
+
+    println!();
+    let mut plans = Vec::new();
+    for mint in &mints {
+        let keypair_path = options.output_dir.join(format!("{}.{}", mint.pubkey(), OutputFormat::Json.extension()));
+        if json {
+            plans.push(plan.instructions(&mint.pubkey().to_string(), &keypair_path).map_err(CliError::Usage)?);
+        } else {
+            println!("Mint {}:", mint.pubkey());
+            println!("  {}", plan.command(&keypair_path));
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&plans).expect("instructions serialize to JSON"));
+    }
+    Ok(())
+}
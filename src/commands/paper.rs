@@ -0,0 +1,60 @@
+//! The `paper` command: printable paper wallet sheets for keypairs.
+
+use std::fs;
+use std::path::PathBuf;
+
+use solana_signer::Signer as _;
+
+use crate::commands::keypair_sources;
+use crate::keysource::KeypairSource;
+use crate::paper;
+use crate::secretfile;
+
+/// Arguments of `paper`.
+struct PaperArgs {
+    sources: Vec<KeypairSource>,
+    output_dir: PathBuf,
+    /// Print the secret key on the sheet; false with `--public-only`
+    include_secret: bool,
+}
+
+impl PaperArgs {
+    fn parse(args: &[String]) -> Result<PaperArgs, String> {
+        let mut output_dir = PathBuf::from(".");
+        let mut include_secret = true;
+        let mut sources = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--output-dir" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    output_dir = PathBuf::from(value);
+                }
+                "--public-only" => include_secret = false,
+                "--keypair-base58" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    sources.push(KeypairSource::Base58(value.clone()));
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                source => sources.push(KeypairSource::parse(source).map_err(|e| e.to_string())?),
+            }
+        }
+        let sources = keypair_sources(sources, "paper")?;
+        Ok(PaperArgs { sources, output_dir, include_secret })
+    }
+}
+
+/// Renders printable paper wallet sheets for keypair files.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let PaperArgs { sources, output_dir, include_secret } = PaperArgs::parse(args)?;
+    fs::create_dir_all(&output_dir).map_err(|e| format!("Failed to create {}: {e}", output_dir.display()))?;
+    for source in &sources {
+        let keypair = source.read().map_err(|e| e.to_string())?;
+        let sheet = paper::render(&keypair, include_secret).map_err(|e| e.to_string())?;
+        let path = output_dir.join(format!("{}.paper.svg", keypair.pubkey()));
+        let written = if include_secret { secretfile::replace(&path, sheet.as_bytes()) } else { fs::write(&path, sheet) };
+        written.map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        println!("Paper wallet saved to: {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+//! The `pool` command: run a grinding pool of requesters and workers.
+
+use std::fs;
+use std::path::PathBuf;
+
+use doppler_keygen::pattern::VanityPattern;
+use solana_signer::Signer as _;
+
+use crate::commands::save_single_keypair;
+use crate::encrypt;
+use crate::pool::Pool;
+use crate::secretfile;
+use crate::workunit::{self, Proof};
+
+/// Arguments of `pool`, by subcommand.
+enum PoolArgs {
+    Submit { requester: String, count: usize, patterns: Vec<VanityPattern>, ignore_case: bool },
+    Issue { worker: String, job: Option<u64>, count: u64, chunk_len: u64, output: Option<PathBuf> },
+    Credit { proof_path: PathBuf, samples: usize, plaintext: bool },
+    Report { json: bool },
+}
+
+impl PoolArgs {
+    /// Parses the subcommand's arguments, returning them with the pool directory.
+    fn parse(args: &[String]) -> Result<(PathBuf, PoolArgs), String> {
+        let (command, args) = match args.split_first() {
+            Some((command, args)) if ["submit", "issue", "credit", "report"].contains(&command.as_str()) => (command.as_str(), args),
+            _ => return Err("Usage: doppler-keygen pool submit|issue|credit|report [options]".to_string()),
+        };
+        let mut dir = PathBuf::from("pool");
+        let mut requester = None;
+        let mut worker = None;
+        let mut job = None;
+        let mut count = None;
+        let mut chunk_len = workunit::DEFAULT_CHUNK_LEN;
+        let mut patterns = Vec::new();
+        let mut ignore_case = false;
+        let mut output = None;
+        let mut samples = workunit::DEFAULT_SAMPLES;
+        let mut plaintext = false;
+        let mut json = false;
+        let mut proof_path = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--pool" => dir = PathBuf::from(value()?),
+                "--requester" => requester = Some(value()?.clone()),
+                "--worker" => worker = Some(value()?.clone()),
+                "--job" => {
+                    let value = value()?;
+                    job = Some(value.parse::<u64>().map_err(|_| format!("Invalid --job '{value}'"))?);
+                }
+                "--count" => {
+                    let value = value()?;
+                    count = Some(value.parse::<u64>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid --count '{value}'"))?);
+                }
+                "--chunk-len" => {
+                    let value = value()?;
+                    chunk_len = value.parse::<u64>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid --chunk-len '{value}'"))?;
+                }
+                "--starts-with" => patterns.push(VanityPattern { prefix: Some(value()?.clone()), suffix: None }),
+                "--ends-with" => patterns.push(VanityPattern { prefix: None, suffix: Some(value()?.clone()) }),
+                "--ignore-case" => ignore_case = true,
+                "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+                "--samples" => {
+                    let value = value()?;
+                    samples = value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid --samples '{value}'"))?;
+                }
+                "--plaintext" => plaintext = true,
+                "--json" => json = true,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                path if command == "credit" && proof_path.is_none() => proof_path = Some(PathBuf::from(path)),
+                other => return Err(format!("Unexpected argument '{other}'")),
+            }
+        }
+
+        let args = match command {
+            "submit" => PoolArgs::Submit {
+                requester: requester.ok_or_else(|| "pool submit requires --requester NAME".to_string())?,
+                count: count.unwrap_or(1) as usize,
+                patterns,
+                ignore_case,
+            },
+            "issue" => PoolArgs::Issue {
+                worker: worker.ok_or_else(|| "pool issue requires --worker NAME".to_string())?,
+                job,
+                count: count.ok_or_else(|| "pool issue requires --count N".to_string())?,
+                chunk_len,
+                output,
+            },
+            "credit" => PoolArgs::Credit {
+                proof_path: proof_path.ok_or_else(|| "pool credit requires a proof file".to_string())?,
+                samples,
+                plaintext,
+            },
+            _ => PoolArgs::Report { json },
+        };
+        Ok((dir, args))
+    }
+}
+
+/// Runs a grinding pool: takes jobs from requesters, hands work units for
+/// them to workers, and credits the attempts of each proof that checks out.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let (dir, args) = PoolArgs::parse(args)?;
+    let mut pool = Pool::load(&dir)?;
+    match args {
+        PoolArgs::Submit { requester, count, patterns, ignore_case } => {
+            let id = pool.submit(&requester, patterns, ignore_case, count)?;
+            pool.save(&dir)?;
+            println!("Job {id} submitted for {requester}: {count} key(s)");
+        }
+        PoolArgs::Issue { worker, job, count, chunk_len, output } => {
+            let issued = pool.issue(&worker, job, count, chunk_len)?;
+            let (id, unit) = (issued.job, issued.unit.clone());
+            let output = output.unwrap_or_else(|| PathBuf::from(format!("unit-{}.json", &unit.digest()[..16])));
+            let contents = serde_json::to_vec_pretty(&unit).map_err(|e| e.to_string())?;
+            // The pool is saved first, so a unit that reaches a worker can
+            // always be credited
+            pool.save(&dir)?;
+            secretfile::write(&output, &contents).map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+            println!("Issued {count} candidates of job {id} to {worker} in {}", output.display());
+            println!("Anyone holding the unit can rebuild the keys it finds; send it to the worker privately");
+        }
+        PoolArgs::Credit { proof_path, samples, plaintext } => {
+            let contents = fs::read(&proof_path).map_err(|e| format!("Failed to read {}: {e}", proof_path.display()))?;
+            let proof: Proof =
+                serde_json::from_slice(&contents).map_err(|e| format!("{}: not a proof: {e}", proof_path.display()))?;
+            let credited = pool.credit(&proof, samples);
+            // A rejection is recorded against the worker too
+            pool.save(&dir)?;
+            let credit = credited?;
+            let job_dir = dir.join(format!("job-{}", credit.job));
+            if !credit.keys.is_empty() {
+                fs::create_dir_all(&job_dir).map_err(|e| format!("Failed to create {}: {e}", job_dir.display()))?;
+            }
+            for keypair in &credit.keys {
+                let extension = if plaintext { "json".to_string() } else { format!("json.{}", encrypt::ENCRYPTED_EXTENSION) };
+                let path = save_single_keypair(keypair, Some(job_dir.join(format!("{}.{extension}", keypair.pubkey()))), plaintext)?;
+                println!("Saved {} to {}", keypair.pubkey(), path.display());
+            }
+            println!(
+                "Credited {} attempts to job {} ({} of {} chunks checked, {} key(s) found)",
+                credit.attempts,
+                credit.job,
+                credit.verification.sampled.len(),
+                credit.verification.total_chunks,
+                credit.keys.len()
+            );
+        }
+        PoolArgs::Report { json } => print_pool_report(&pool, json),
+    }
+    Ok(())
+}
+
+fn print_pool_report(pool: &Pool, json: bool) {
+    if json {
+        let jobs: Vec<serde_json::Value> = pool
+            .jobs
+            .iter()
+            .map(|job| {
+                serde_json::json!({
+                    "id": job.id,
+                    "requester": job.requester,
+                    "patterns": job.patterns.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                    "count": job.count,
+                    "found": job.keys.len(),
+                    "attempts": job.attempts.to_string(),
+                })
+            })
+            .collect();
+        let workers: serde_json::Map<String, serde_json::Value> = pool
+            .workers
+            .iter()
+            .map(|(name, account)| {
+                let account = serde_json::json!({
+                    "issued": account.issued,
+                    "credited": account.credited,
+                    "rejected": account.rejected,
+                    "attempts": account.attempts.to_string(),
+                });
+                (name.clone(), account)
+            })
+            .collect();
+        let requesters: serde_json::Map<String, serde_json::Value> =
+            pool.requesters().into_iter().map(|(name, attempts)| (name.to_string(), attempts.to_string().into())).collect();
+        let report = serde_json::json!({ "workers": workers, "jobs": jobs, "requesters": requesters });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+        return;
+    }
+
+    println!("Workers (attempts contributed):");
+    for (name, account) in &pool.workers {
+        println!(
+            "  {name}: {} attempts; {} unit(s) issued, {} credited, {} rejected",
+            account.attempts, account.issued, account.credited, account.rejected
+        );
+    }
+    println!("Jobs (attempts received):");
+    for job in &pool.jobs {
+        let patterns: Vec<String> = job.patterns.iter().map(ToString::to_string).collect();
+        println!(
+            "  Job {} for {}: {} attempts; {}/{} key(s) for {}",
+            job.id,
+            job.requester,
+            job.attempts,
+            job.keys.len(),
+            job.count,
+            patterns.join(", ")
+        );
+    }
+    println!("Requesters (attempts received):");
+    for (name, attempts) in pool.requesters() {
+        println!("  {name}: {attempts} attempts");
+    }
+}
@@ -0,0 +1,29 @@
+//! The `repl` command: a batch search steered from an interactive prompt.
+
+use crate::cli_error::CliError;
+use crate::commands::grind::{self, COUNT, GrindArgs};
+
+/// Arguments of `repl`: the grind options, whose patterns start the batch.
+struct ReplArgs {
+    grind_args: GrindArgs,
+}
+
+impl ReplArgs {
+    fn parse(args: &[String]) -> Result<ReplArgs, String> {
+        let grind_args = GrindArgs::parse(args)?;
+        grind_args.refuse(
+            "repl",
+            &[COUNT, "--stream", "--watch", "--near", "--targets", "--install-as-default"],
+            "takes its patterns from the prompt",
+        )?;
+        Ok(ReplArgs { grind_args })
+    }
+}
+
+/// Runs a batch search steered from an interactive prompt. Takes the grind
+/// options; any patterns given start the batch.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let ReplArgs { mut grind_args } = ReplArgs::parse(args).map_err(CliError::Usage)?;
+    grind_args.repl = true;
+    grind::run(grind_args).map(|_| ())
+}
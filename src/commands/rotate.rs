@@ -0,0 +1,178 @@
+//! The `rotate` command: a replacement for an existing key with the same structure.
+
+use std::fs;
+use std::path::PathBuf;
+
+use doppler_keygen::constraints::ImmSign;
+use doppler_keygen::imm32;
+use doppler_keygen::pattern::{BatchPattern, VanityPattern};
+use solana_signer::Signer as _;
+
+use crate::cli_error::CliError;
+use crate::commands::grind::{self, COUNT, GrindArgs, PATTERNS};
+use crate::commands::read_address;
+use crate::diff;
+use crate::rotate;
+
+/// Arguments of `rotate`: the old key and how much of it to keep, along with
+/// the grind options.
+struct RotateArgs {
+    /// The key being replaced
+    old: [u8; 32],
+    /// Leading characters of the old address the replacement keeps
+    prefix_len: usize,
+    /// Program source to write a migration patch for
+    source: Option<PathBuf>,
+    grind_args: GrindArgs,
+}
+
+impl RotateArgs {
+    fn parse(args: &[String]) -> Result<RotateArgs, String> {
+        let mut like = None;
+        let mut prefix_len = 0;
+        let mut source = None;
+        let mut rest = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--like" => like = Some(read_address(value()?)?),
+                "--prefix-len" => {
+                    let value = value()?;
+                    prefix_len = value.parse().map_err(|_| format!("Invalid prefix length '{value}'"))?;
+                }
+                "--source" => source = Some(PathBuf::from(value()?)),
+                _ => rest.push(arg.clone()),
+            }
+        }
+        let old = like.ok_or_else(|| "rotate requires --like with the old keypair or address".to_string())?;
+        let grind_args = GrindArgs::parse(&rest)?;
+        grind_args.refuse("rotate", &["--segment", "--imm-sign"], "takes the segment and sign from the old key")?;
+        grind_args.refuse(
+            "rotate",
+            &[COUNT, PATTERNS, "--stream", "--watch", "--near", "--targets", "--no-save-secret", "--derive"],
+            "grinds one key shaped like the old one",
+        )?;
+        Ok(RotateArgs { old, prefix_len, source, grind_args })
+    }
+}
+
+/// Grinds a replacement for an existing key with the same structure: the
+/// immediate in the same segment with the same sign, and optionally the
+/// same prefix. Prints the old and new constants, and with `--source` a
+/// patch updating them in a program's source. Any other arguments are
+/// passed on to `grind`.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let RotateArgs { old, prefix_len, source, mut grind_args } = RotateArgs::parse(args).map_err(CliError::Usage)?;
+    let old_address = bs58::encode(old).into_string();
+    let segment = (0..4).find(|&segment| imm32::segment_is_imm32_compatible(&old, segment)).ok_or_else(|| {
+        CliError::Usage(format!("{old_address} has no imm32 compatible segment, so there is no structure to keep"))
+    })?;
+    let sign = if old[segment * 8 + 3] & 0x80 == 0 { ImmSign::Positive } else { ImmSign::Negative };
+    grind_args.constraints.segment = Some(segment);
+    grind_args.constraints.imm_sign = Some(sign);
+    let prefix = old_address.get(..prefix_len).ok_or_else(|| {
+        CliError::Usage(format!("--prefix-len {prefix_len} is longer than {old_address}"))
+    })?;
+    if !prefix.is_empty() {
+        grind_args.patterns.push(BatchPattern {
+            pattern: VanityPattern { prefix: Some(prefix.to_string()), suffix: None },
+            count: 1,
+            found: 0,
+            priority: 0,
+            sink: None,
+        });
+    }
+    println!(
+        "Rotating {old_address}: the replacement needs a {} immediate in segment {segment}{}\n",
+        if sign == ImmSign::Positive { "positive" } else { "negative" },
+        if prefix.is_empty() { String::new() } else { format!(" and the prefix '{prefix}'") }
+    );
+
+    let Some((_, keypairs)) = grind::run(grind_args)? else {
+        return Ok(());
+    };
+    let Some(keypair) = keypairs.first() else {
+        println!("No replacement key was found");
+        return Ok(());
+    };
+    let new = keypair.pubkey().to_bytes();
+
+    println!("\n=== Old Constants ===");
+    print!("{}", rotate::constants(&old));
+    println!("\n=== New Constants ===");
+    print!("{}", rotate::constants(&new));
+    println!();
+    print!("{}", diff::report(&old, &new));
+
+    if let Some(path) = source {
+        let code = fs::read_to_string(&path)
+            .map_err(|e| CliError::Usage(format!("Failed to read {}: {e}", path.display())))?;
+        println!("\n=== Migration Patch ===");
+        match rotate::migration_patch(&path.display().to_string(), &code, &old, &new) {
+            Some(patch) => print!("{patch}"),
+            None => println!("{} contains none of the old key's constants", path.display()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grind::OutputFormat;
+    use solana_keypair::Keypair;
+    use std::path::Path;
+
+    fn write_keypair(dir: &Path, name: &str, contents: &[u8]) -> String {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
+
+    fn parse(args: &[&str]) -> Result<RotateArgs, String> {
+        RotateArgs::parse(&args.iter().map(ToString::to_string).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn the_old_key_is_read_from_a_keypair_file_and_patched_out_of_its_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (Keypair::new(), Keypair::new());
+        let like = write_keypair(dir.path(), "old.json", &OutputFormat::Json.encode(&old));
+        let source = dir.path().join("admin.s");
+        let code = rotate::constants(&old.pubkey().to_bytes());
+        fs::write(&source, &code).unwrap();
+
+        let args = parse(&["--like", &like, "--source", &source.display().to_string(), "--prefix-len", "2"]).unwrap();
+        assert_eq!(args.old, old.pubkey().to_bytes());
+        assert_eq!((args.prefix_len, args.source.as_deref()), (2, Some(source.as_path())));
+
+        // The patch turns each changed constant of the old key into the new one
+        let (old, new) = (old.pubkey().to_bytes(), new.pubkey().to_bytes());
+        let patch = rotate::migration_patch("admin.s", &fs::read_to_string(&source).unwrap(), &old, &new).unwrap();
+        let new_code = rotate::constants(&new);
+        let added: Vec<&str> =
+            patch.lines().filter(|line| !line.starts_with("+++")).filter_map(|line| line.strip_prefix('+')).collect();
+        let expected: Vec<&str> = code.lines().zip(new_code.lines()).filter(|(a, b)| a != b).map(|(_, b)| b).collect();
+        assert_eq!(added, expected);
+
+        // Source written for another key has nothing to patch
+        let other = rotate::constants(&Keypair::new().pubkey().to_bytes());
+        assert_eq!(rotate::migration_patch("admin.s", &other, &old, &new), None);
+    }
+
+    #[test]
+    fn corrupt_or_mismatched_keypair_files_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        // A secret key paired with the public half of another key
+        let mut mismatched = Keypair::new().to_bytes();
+        mismatched[32..].copy_from_slice(&Keypair::new().pubkey().to_bytes());
+        let files =
+            [("corrupt.json", b"not a keypair".to_vec()), ("mismatched.json", serde_json::to_vec(&mismatched.to_vec()).unwrap())];
+        for (name, contents) in files {
+            let like = write_keypair(dir.path(), name, &contents);
+            let error = parse(&["--like", &like]).err().unwrap();
+            assert!(error.starts_with(&format!("failed to read keypair from {like}")), "{error}");
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! The `selftest` command: statistical checks on a sample of the key stream.
+
+use std::thread;
+
+use doppler_keygen::entropy::{Entropy, EntropySource};
+
+use crate::cli_error::CliError;
+use crate::grind::print_entropy;
+use crate::selftest;
+
+/// Arguments of `selftest`.
+struct SelftestArgs {
+    samples: u64,
+    num_threads: Option<usize>,
+    entropy: EntropySource,
+}
+
+impl SelftestArgs {
+    fn parse(args: &[String]) -> Result<SelftestArgs, String> {
+        let mut samples = selftest::DEFAULT_SAMPLES;
+        let mut num_threads = None;
+        let mut entropy = EntropySource::Os;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--samples" => {
+                    let value = value()?;
+                    samples = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("Invalid sample count '{value}'"))?;
+                }
+                "--num-threads" => {
+                    let value = value()?;
+                    num_threads = Some(
+                        value
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .ok_or_else(|| format!("Invalid thread count '{value}'"))?,
+                    );
+                }
+                "--entropy" => entropy = EntropySource::parse(value()?)?,
+                other => return Err(format!("Unknown option '{other}'")),
+            }
+        }
+        Ok(SelftestArgs { samples, num_threads, entropy })
+    }
+}
+
+/// Samples the key stream and runs statistical checks on it.
+pub fn command(args: &[String]) -> Result<(), CliError> {
+    let SelftestArgs { samples, num_threads, entropy } = SelftestArgs::parse(args).map_err(CliError::Usage)?;
+    let num_threads = match num_threads {
+        Some(num_threads) => num_threads,
+        None => thread::available_parallelism()
+            .map_err(CliError::Parallelism)?
+            .get(),
+    };
+
+    let entropy = Entropy::gather(&entropy)?;
+    print_entropy(&entropy);
+    println!("Sampling {samples} keys on {num_threads} threads...\n");
+    let checks = selftest::run(samples, num_threads, entropy)?;
+    for check in &checks {
+        println!("{} {}: {}", if check.passed { "PASS" } else { "FAIL" }, check.name, check.detail);
+    }
+    match checks.iter().filter(|check| !check.passed).count() {
+        0 => {
+            println!("\nAll checks passed");
+            Ok(())
+        }
+        failed => Err(CliError::Usage(format!(
+            "{failed} check(s) failed; run selftest again, and if they keep failing do not use this machine for keys"
+        ))),
+    }
+}
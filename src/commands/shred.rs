@@ -0,0 +1,57 @@
+//! The `shred` command: overwrites and removes files.
+
+use std::path::PathBuf;
+
+use crate::redact;
+use crate::shred;
+
+/// Arguments of `shred`: `shred FILE...`.
+struct ShredArgs {
+    files: Vec<PathBuf>,
+}
+
+impl ShredArgs {
+    fn parse(args: &[String]) -> Result<ShredArgs, String> {
+        if args.is_empty() {
+            return Err("shred requires at least one file".to_string());
+        }
+        Ok(ShredArgs { files: args.iter().map(PathBuf::from).collect() })
+    }
+}
+
+/// Shreds each file, carrying on past the ones that fail.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let ShredArgs { files } = ShredArgs::parse(args)?;
+    let mut failed = 0;
+    for file in &files {
+        match shred::shred_file(file) {
+            Ok(()) => println!("Shredded {}", file.display()),
+            Err(e) => {
+                eprintln!("Error: {}", redact::scrub(&e.to_string()));
+                failed += 1;
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{failed} of {} file(s) could not be shredded", files.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_file_is_tried_and_failures_are_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let [first, missing, last] = ["a.json", "missing.json", "b.json"].map(|name| dir.path().join(name));
+        std::fs::write(&first, "a").unwrap();
+        std::fs::write(&last, "b").unwrap();
+
+        let args = [&first, &missing, &last].map(|path| path.display().to_string());
+        assert_eq!(command(&args), Err("1 of 3 file(s) could not be shredded".to_string()));
+        assert!(!first.exists() && !last.exists());
+        assert_eq!(command(&[]), Err("shred requires at least one file".to_string()));
+    }
+}
@@ -0,0 +1,86 @@
+//! The `sign` command: a message signed with a keypair, proving possession of the key.
+
+use std::fs;
+use std::path::PathBuf;
+
+use solana_signer::Signer as _;
+
+use crate::blind::BlindKey;
+use crate::commands::{keypair_sources, read_message};
+use crate::keysource::KeypairSource;
+use crate::machinekey;
+
+/// What `sign` signs with.
+enum SigningKey {
+    Keypair(KeypairSource),
+    /// A blind key file written by `blind-combine`
+    BlindKey(PathBuf),
+}
+
+/// Arguments of `sign`.
+struct SignArgs {
+    signer: SigningKey,
+    message: Vec<u8>,
+}
+
+impl SignArgs {
+    fn parse(args: &[String]) -> Result<SignArgs, String> {
+        let mut sources = Vec::new();
+        let mut message = None;
+        let mut blind_key = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--message" | "--message-file" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    message = Some(read_message(arg, value)?);
+                }
+                "--keypair-base58" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    sources.push(KeypairSource::Base58(value.clone()));
+                }
+                "--blind-key" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    blind_key = Some(PathBuf::from(value));
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                source => sources.push(KeypairSource::parse(source).map_err(|e| e.to_string())?),
+            }
+        }
+
+        let message = message.ok_or_else(|| "sign requires --message or --message-file".to_string())?;
+        let signer = match blind_key {
+            Some(_) if !sources.is_empty() => return Err("sign takes a keypair or --blind-key, not both".to_string()),
+            Some(path) => SigningKey::BlindKey(path),
+            None => {
+                let [source] = <[KeypairSource; 1]>::try_from(keypair_sources(sources, "sign")?)
+                    .map_err(|_| "sign takes a single keypair".to_string())?;
+                SigningKey::Keypair(source)
+            }
+        };
+        Ok(SignArgs { signer, message })
+    }
+}
+
+/// Signs a message with a keypair file, proving possession of the key.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let SignArgs { signer, message } = SignArgs::parse(args)?;
+    match signer {
+        SigningKey::BlindKey(path) => {
+            let contents = fs::read(&path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+            let contents = match machinekey::is_encrypted(&contents) {
+                true => machinekey::decrypt(&contents).map_err(|e| e.to_string())?,
+                false => contents,
+            };
+            let key = BlindKey::from_json(&contents)?;
+            println!("Public Key: {}", key.address());
+            println!("Signature: {}", bs58::encode(key.sign(&message)).into_string());
+        }
+        SigningKey::Keypair(source) => {
+            let keypair = source.read().map_err(|e| e.to_string())?;
+            println!("Public Key: {}", keypair.pubkey());
+            println!("Signature: {}", keypair.sign_message(&message));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! The `simulate` command: the generated comparison code run in the sBPF interpreter.
+
+use crate::commands::read_address;
+use crate::simulate;
+
+/// Arguments of `simulate`.
+struct SimulateArgs {
+    keys: Vec<[u8; 32]>,
+}
+
+impl SimulateArgs {
+    fn parse(args: &[String]) -> Result<SimulateArgs, String> {
+        let mut keys: Vec<[u8; 32]> = Vec::new();
+        for arg in args {
+            if arg.starts_with("--") {
+                return Err(format!("Unknown option '{arg}'"));
+            }
+            let address = read_address(arg)?;
+            if keys.contains(&address) {
+                return Err(format!("{} is given more than once", bs58::encode(address).into_string()));
+            }
+            keys.push(address);
+        }
+        if keys.is_empty() {
+            return Err("simulate requires at least one key (keypair file or base58 address)".to_string());
+        }
+        Ok(SimulateArgs { keys })
+    }
+}
+
+/// Runs the generated comparison code for each key in the sBPF interpreter.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let SimulateArgs { keys } = SimulateArgs::parse(args)?;
+
+    let reports: Vec<simulate::Report> = simulate::routines(&keys).iter().map(simulate::check).collect();
+    for report in &reports {
+        let accepted: Vec<String> = report.accepted.iter().map(ToString::to_string).collect();
+        println!(
+            "{} {}: accepted {}/{} admin key(s) in {} instruction(s), rejected {}/{} mutants, {}/32 bytes covered",
+            if report.passed() { "PASS" } else { "FAIL" },
+            report.name,
+            report.accepted.len(),
+            report.admins,
+            if accepted.is_empty() { "no".to_string() } else { accepted.join("/") },
+            report.rejected,
+            report.mutants,
+            report.coverage()
+        );
+        let uncovered = report.uncovered();
+        if !uncovered.is_empty() {
+            println!("    not covered: bytes {}", uncovered.join(", "));
+        }
+        for failure in &report.failures {
+            println!("    {failure}");
+        }
+    }
+    match reports.iter().filter(|report| !report.passed()).count() {
+        0 => {
+            println!("\nAll {} routines passed", reports.len());
+            Ok(())
+        }
+        failed => Err(format!("{failed} of {} routines failed; don't deploy the generated code", reports.len())),
+    }
+}
@@ -0,0 +1,29 @@
+//! The `verify-audit` command: checks the hash chain of the audit log.
+
+use std::path::PathBuf;
+
+use crate::audit;
+
+/// Arguments of `verify-audit`: `verify-audit [PATH]`.
+struct VerifyAuditArgs {
+    path: PathBuf,
+}
+
+impl VerifyAuditArgs {
+    fn parse(args: &[String]) -> Result<VerifyAuditArgs, String> {
+        match args {
+            [] => Ok(VerifyAuditArgs { path: PathBuf::from(audit::AUDIT_FILE) }),
+            [path] => Ok(VerifyAuditArgs { path: PathBuf::from(path) }),
+            _ => Err("Usage: doppler-keygen verify-audit [PATH]".to_string()),
+        }
+    }
+}
+
+/// Checks that no entry of the audit log was changed, removed or reordered.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let VerifyAuditArgs { path } = VerifyAuditArgs::parse(args)?;
+    let (entries, last_hash) = audit::verify(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    println!("{}: chain intact, {entries} entries", path.display());
+    println!("Latest hash: {last_hash}");
+    Ok(())
+}
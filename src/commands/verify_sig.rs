@@ -0,0 +1,57 @@
+//! The `verify-sig` command: checks a signature made with `sign`.
+
+use crate::commands::read_message;
+
+/// Arguments of `verify-sig`.
+struct VerifySigArgs {
+    pubkey: [u8; 32],
+    signature: [u8; 64],
+    message: Vec<u8>,
+}
+
+impl VerifySigArgs {
+    fn parse(args: &[String]) -> Result<VerifySigArgs, String> {
+        let mut positional = Vec::new();
+        let mut message = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--message" | "--message-file" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    message = Some(read_message(arg, value)?);
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                value => positional.push(value),
+            }
+        }
+
+        let [pubkey, signature] = positional[..] else {
+            return Err("verify-sig requires a public key and a signature".to_string());
+        };
+        let message = message.ok_or_else(|| "verify-sig requires --message or --message-file".to_string())?;
+
+        let pubkey: [u8; 32] = bs58::decode(pubkey)
+            .into_vec()
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("Invalid public key '{pubkey}'"))?;
+        let signature: [u8; 64] = bs58::decode(signature)
+            .into_vec()
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("Invalid signature '{signature}'"))?;
+        Ok(VerifySigArgs { pubkey, signature, message })
+    }
+}
+
+/// Checks a signature produced by `sign`.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let VerifySigArgs { pubkey, signature, message } = VerifySigArgs::parse(args)?;
+    let verifying_key =
+        ed25519_dalek::VerifyingKey::from_bytes(&pubkey).map_err(|_| "The public key is not a valid Ed25519 point".to_string())?;
+    verifying_key
+        .verify_strict(&message, &ed25519_dalek::Signature::from_bytes(&signature))
+        .map_err(|_| "Signature does not match the public key and message".to_string())?;
+    println!("Signature verified");
+    Ok(())
+}
@@ -0,0 +1,214 @@
+//! The `work-unit` command: issue, run and verify work units.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use doppler_keygen::pattern::VanityPattern;
+
+use crate::commands::save_single_keypair;
+use crate::secretfile;
+use crate::workunit::{self, Proof, WorkUnit};
+
+/// Issues, grinds and checks work units whose attempts can be verified.
+pub fn command(args: &[String]) -> Result<(), String> {
+    match args.first().map(String::as_str) {
+        Some("issue") => issue(IssueArgs::parse(&args[1..])?),
+        Some("run") => run(RunArgs::parse(&args[1..])?),
+        Some("verify") => verify(VerifyArgs::parse(&args[1..])?),
+        _ => Err("Usage: doppler-keygen work-unit issue|run|verify [options]".to_string()),
+    }
+}
+
+fn read_work_unit(path: &Path) -> Result<WorkUnit, String> {
+    let contents = fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    WorkUnit::from_json(&contents).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Arguments of `work-unit issue`.
+struct IssueArgs {
+    start: u64,
+    count: u64,
+    chunk_len: u64,
+    patterns: Vec<VanityPattern>,
+    ignore_case: bool,
+    output: PathBuf,
+}
+
+impl IssueArgs {
+    fn parse(args: &[String]) -> Result<IssueArgs, String> {
+        let mut start = 0;
+        let mut count = None;
+        let mut chunk_len = workunit::DEFAULT_CHUNK_LEN;
+        let mut patterns = Vec::new();
+        let mut ignore_case = false;
+        let mut output = PathBuf::from("unit.json");
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--start" => {
+                    let value = value()?;
+                    start = value.parse::<u64>().map_err(|_| format!("Invalid --start '{value}'"))?;
+                }
+                "--count" => {
+                    let value = value()?;
+                    count = Some(value.parse::<u64>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid --count '{value}'"))?);
+                }
+                "--chunk-len" => {
+                    let value = value()?;
+                    chunk_len = value.parse::<u64>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid --chunk-len '{value}'"))?;
+                }
+                "--starts-with" => patterns.push(VanityPattern { prefix: Some(value()?.clone()), suffix: None }),
+                "--ends-with" => patterns.push(VanityPattern { prefix: None, suffix: Some(value()?.clone()) }),
+                "--ignore-case" => ignore_case = true,
+                "--output" | "-o" => output = PathBuf::from(value()?),
+                other => return Err(format!("Unexpected argument '{other}'")),
+            }
+        }
+        let count = count.ok_or_else(|| "work-unit issue requires --count N".to_string())?;
+        Ok(IssueArgs { start, count, chunk_len, patterns, ignore_case, output })
+    }
+}
+
+fn issue(IssueArgs { start, count, chunk_len, patterns, ignore_case, output }: IssueArgs) -> Result<(), String> {
+    let unit = WorkUnit::issue(start, count, chunk_len, patterns, ignore_case)?;
+    let json = serde_json::to_vec_pretty(&unit).map_err(|e| e.to_string())?;
+    // The seed leads to every key the unit finds
+    secretfile::write(&output, &json).map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+    println!("Issued candidates {start}..{} in {} chunks to {}", start + count, unit.chunk_count(), output.display());
+    println!("Anyone holding the unit can rebuild the keys it finds; send it to the worker privately");
+    Ok(())
+}
+
+/// Arguments of `work-unit run`.
+struct RunArgs {
+    unit_path: PathBuf,
+    /// Defaults to the unit file with a `.proof.json` extension
+    output: Option<PathBuf>,
+    num_threads: Option<usize>,
+}
+
+impl RunArgs {
+    fn parse(args: &[String]) -> Result<RunArgs, String> {
+        let mut unit_path = None;
+        let mut output = None;
+        let mut num_threads = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("{arg} requires a value"));
+            match arg.as_str() {
+                "--num-threads" => {
+                    let value = value()?;
+                    num_threads = Some(value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid thread count '{value}'"))?);
+                }
+                "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                path if unit_path.is_none() => unit_path = Some(PathBuf::from(path)),
+                other => return Err(format!("Unexpected argument '{other}'")),
+            }
+        }
+        let unit_path = unit_path.ok_or_else(|| "work-unit run requires a unit file".to_string())?;
+        Ok(RunArgs { unit_path, output, num_threads })
+    }
+}
+
+fn run(RunArgs { unit_path, output, num_threads }: RunArgs) -> Result<(), String> {
+    let unit = read_work_unit(&unit_path)?;
+    let output = output.unwrap_or_else(|| unit_path.with_extension("proof.json"));
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads.unwrap_or(0))
+        .build()
+        .map_err(|e| format!("Failed to start worker threads: {e}"))?;
+
+    let total = unit.chunk_count();
+    println!("Grinding candidates {}..{} in {total} chunks on {} threads", unit.start, unit.start + unit.count, pool.current_num_threads());
+    let started = Instant::now();
+    let proof = pool.install(|| {
+        unit.run(|done| {
+            // Report each tenth of the unit
+            if done * 10 / total != (done - 1) * 10 / total {
+                println!("Chunks: {done}/{total}");
+            }
+        })
+    })?;
+    let elapsed = started.elapsed();
+
+    let json = serde_json::to_vec_pretty(&proof).map_err(|e| e.to_string())?;
+    fs::write(&output, json).map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+    for unit_match in &proof.matches {
+        println!("Match #{}: {} (segment {})", unit_match.index, unit_match.address, unit_match.segment);
+    }
+    println!(
+        "{} attempts in {:.2} seconds, {} match(es); proof written to {}",
+        unit.count,
+        elapsed.as_secs_f64(),
+        proof.matches.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Arguments of `work-unit verify`.
+struct VerifyArgs {
+    unit_path: PathBuf,
+    proof_path: PathBuf,
+    samples: usize,
+    save_matches: bool,
+    plaintext: bool,
+}
+
+impl VerifyArgs {
+    fn parse(args: &[String]) -> Result<VerifyArgs, String> {
+        let mut paths = Vec::new();
+        let mut samples = workunit::DEFAULT_SAMPLES;
+        let mut save_matches = false;
+        let mut plaintext = false;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--samples" => {
+                    let value = iter.next().ok_or_else(|| format!("{arg} requires a value"))?;
+                    samples = value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid --samples '{value}'"))?;
+                }
+                "--save-matches" => save_matches = true,
+                "--plaintext" => plaintext = true,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'")),
+                path => paths.push(PathBuf::from(path)),
+            }
+        }
+        let Ok([unit_path, proof_path]) = <[PathBuf; 2]>::try_from(paths) else {
+            return Err("work-unit verify requires a unit file and a proof file".to_string());
+        };
+        Ok(VerifyArgs { unit_path, proof_path, samples, save_matches, plaintext })
+    }
+}
+
+fn verify(VerifyArgs { unit_path, proof_path, samples, save_matches, plaintext }: VerifyArgs) -> Result<(), String> {
+    let unit = read_work_unit(&unit_path)?;
+    let contents = fs::read(&proof_path).map_err(|e| format!("Failed to read {}: {e}", proof_path.display()))?;
+    let proof: Proof = serde_json::from_slice(&contents).map_err(|e| format!("{}: not a proof: {e}", proof_path.display()))?;
+
+    let verification = unit.verify(&proof, samples).map_err(|e| format!("Rejected: {e}"))?;
+    println!(
+        "Verified: {} of {} chunks regenerated and matching, {} match(es) checked",
+        verification.sampled.len(),
+        verification.total_chunks,
+        verification.matches
+    );
+    if verification.sampled.len() < verification.total_chunks {
+        println!(
+            "A worker that skipped half the unit would have passed with probability 2^-{}",
+            verification.sampled.len()
+        );
+    }
+
+    if save_matches {
+        for unit_match in &proof.matches {
+            let keypair = unit.keypair(unit_match.index)?;
+            let path = save_single_keypair(&keypair, None, plaintext)?;
+            println!("Saved match #{} to {}", unit_match.index, path.display());
+        }
+    }
+    Ok(())
+}
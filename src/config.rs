@@ -0,0 +1,418 @@
+//! Defaults loaded from `~/.config/doppler-keygen/config.toml` and
+//! `DOPPLER_*` environment variables.
+//!
+//! Every setting is optional. Values given on the command line take
+//! precedence over environment variables, which take precedence over the
+//! config file.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::chat::NotifierConfig;
+use crate::clients::ClientConfig;
+use crate::notify::EmailConfig;
+use crate::sinks::SinkConfig;
+use crate::grind::OutputFormat;
+use doppler_keygen::pattern::BatchPattern;
+use doppler_keygen::scheduler::Scheduler;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Number of worker threads
+    pub threads: Option<usize>,
+    /// Directory found keypairs are written to
+    pub output_dir: Option<PathBuf>,
+    /// Formats found keypairs are saved in
+    pub output_formats: Option<Vec<OutputFormat>>,
+    /// Seconds between progress reports
+    pub progress_interval: Option<u64>,
+    /// URLs every saved key's public address is posted to
+    pub notify_urls: Option<Vec<String>>,
+    /// How candidate generation is spread across threads
+    pub scheduler: Option<Scheduler>,
+    /// Candidates generated per batch
+    pub batch_size: Option<usize>,
+    /// Vanity patterns to grind for when none are given on the command line
+    /// or in `DOPPLER_PATTERNS`
+    pub patterns: Option<Vec<BatchPattern>>,
+    /// Named bundles of grind options selected with `--profile`, replacing
+    /// any built-in profile of the same name
+    pub profiles: Option<BTreeMap<String, Profile>>,
+    /// Named destinations that patterns deliver their keys to with `sink`
+    pub sinks: Option<BTreeMap<String, SinkConfig>>,
+    /// Tokens and quotas of the daemon's API clients
+    pub clients: Option<BTreeMap<String, ClientConfig>>,
+    /// SMTP server and recipients of the email sent when a run ends
+    pub email: Option<EmailConfig>,
+    /// Telegram chats and Discord channels saved keys are announced in
+    pub notifiers: Option<BTreeMap<String, NotifierConfig>>,
+}
+
+/// A named bundle of grind options for a workflow.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Grind options added in front of the command line's, so the command
+    /// line can override them
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Refuse to run if secret keys would be written in the clear
+    #[serde(default)]
+    pub require_encryption: bool,
+}
+
+/// Names of the built-in profiles.
+const BUILTIN_PROFILES: [&str; 3] = ["doppler", "vanity-short", "authority"];
+
+/// The built-in profile called `name`:
+///
+/// - `doppler`: the imm32 search for program constants, saved as JSON
+/// - `vanity-short`: short case-insensitive vanity prefixes, saved as JSON
+///   and base58 for wallet import
+/// - `authority`: hardened mode, and secrets only ever written encrypted,
+///   wrapped or split
+///
+/// All of them refuse an output directory inside a cloud-synced folder.
+fn builtin_profile(name: &str) -> Option<Profile> {
+    let (args, require_encryption): (&[&str], bool) = match name {
+        "doppler" => (&["--output-format", "json", "--no-sync-dirs"], false),
+        "vanity-short" => (&["--ignore-case", "--output-format", "json,bs58", "--no-sync-dirs"], false),
+        "authority" => (&["--hardened", "--no-sync-dirs"], true),
+        _ => return None,
+    };
+    Some(Profile { args: args.iter().map(|arg| (*arg).to_string()).collect(), require_encryption })
+}
+
+impl Config {
+    /// Loads the config file at `path`, or the default config file if no
+    /// path is given. A missing default config file is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_config_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(format!("Failed to read config file {}: {e}", path.display())),
+        };
+
+        let config: Config = toml::from_str(&contents)
+            .map_err(|e| format!("Invalid config file {}: {e}", path.display()))?;
+
+        if config.threads == Some(0) {
+            return Err(format!("Invalid config file {}: threads must be at least 1", path.display()));
+        }
+        if config.batch_size == Some(0) {
+            return Err(format!("Invalid config file {}: batch_size must be at least 1", path.display()));
+        }
+        for batch_pattern in config.patterns.iter().flatten() {
+            let pattern = &batch_pattern.pattern;
+            if batch_pattern.count == 0 {
+                return Err(format!("Invalid config file {}: pattern count must be at least 1", path.display()));
+            }
+            if pattern.prefix.is_none() && pattern.suffix.is_none()
+                || [&pattern.prefix, &pattern.suffix].into_iter().flatten().any(String::is_empty)
+            {
+                return Err(format!(
+                    "Invalid config file {}: patterns need a non-empty prefix and/or suffix",
+                    path.display()
+                ));
+            }
+        }
+        if config.progress_interval == Some(0) {
+            return Err(format!(
+                "Invalid config file {}: progress_interval must be at least 1",
+                path.display()
+            ));
+        }
+        for url in config.notify_urls.iter().flatten() {
+            crate::webhook::check_url(url).map_err(|e| format!("Invalid config file {}: {e}", path.display()))?;
+        }
+        for (name, profile) in config.profiles.iter().flatten() {
+            if profile.args.iter().any(|arg| arg == "--profile") {
+                return Err(format!("Invalid config file {}: profile '{name}' cannot select another profile", path.display()));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Reads settings from `DOPPLER_THREADS`, `DOPPLER_OUTPUT_DIR`,
+    /// `DOPPLER_OUTPUT_FORMATS`, `DOPPLER_PROGRESS_INTERVAL`,
+    /// `DOPPLER_NOTIFY_URLS`, `DOPPLER_SCHEDULER` and `DOPPLER_BATCH_SIZE`.
+    pub fn from_env() -> Result<Config, String> {
+        Config::from_vars(env_var)
+    }
+
+    /// Reads the `DOPPLER_*` settings from `var`, which returns the value
+    /// of a variable or `None` if it is unset.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Config, String> {
+        let threads = var("DOPPLER_THREADS")
+            .map(|value| match value.parse::<usize>() {
+                Ok(threads) if threads > 0 => Ok(threads),
+                _ => Err(format!("Invalid DOPPLER_THREADS '{value}': must be at least 1")),
+            })
+            .transpose()?;
+
+        let output_formats = var("DOPPLER_OUTPUT_FORMATS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|format| OutputFormat::parse(format.trim()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("Invalid DOPPLER_OUTPUT_FORMATS: {e}"))
+            })
+            .transpose()?;
+
+        let progress_interval = var("DOPPLER_PROGRESS_INTERVAL")
+            .map(|value| match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => Ok(seconds),
+                _ => Err(format!("Invalid DOPPLER_PROGRESS_INTERVAL '{value}': must be at least 1")),
+            })
+            .transpose()?;
+
+        let notify_urls = var("DOPPLER_NOTIFY_URLS")
+            .map(|value| {
+                let urls = value.split(',').map(|url| url.trim().to_string()).collect::<Vec<_>>();
+                match urls.iter().try_for_each(|url| crate::webhook::check_url(url)) {
+                    Ok(()) => Ok(urls),
+                    Err(e) => Err(format!("Invalid DOPPLER_NOTIFY_URLS: {e}")),
+                }
+            })
+            .transpose()?;
+
+        let scheduler = var("DOPPLER_SCHEDULER")
+            .map(|value| Scheduler::parse(&value).map_err(|e| format!("Invalid DOPPLER_SCHEDULER: {e}")))
+            .transpose()?;
+
+        let batch_size = var("DOPPLER_BATCH_SIZE")
+            .map(|value| crate::grind::parse_batch_size(&value).map_err(|e| format!("Invalid DOPPLER_BATCH_SIZE: {e}")))
+            .transpose()?;
+
+        Ok(Config {
+            threads,
+            output_dir: var("DOPPLER_OUTPUT_DIR").map(PathBuf::from),
+            output_formats,
+            progress_interval,
+            notify_urls,
+            scheduler,
+            batch_size,
+            patterns: None,
+            profiles: None,
+            sinks: None,
+            clients: None,
+            email: None,
+            notifiers: None,
+        })
+    }
+
+    /// The profile called `name`, from the config file or built in.
+    pub fn profile(&self, name: &str) -> Result<Profile, String> {
+        if let Some(profile) = self.profiles.as_ref().and_then(|profiles| profiles.get(name)) {
+            return Ok(profile.clone());
+        }
+        builtin_profile(name).ok_or_else(|| {
+            let names = BUILTIN_PROFILES
+                .iter()
+                .map(|name| (*name).to_string())
+                .chain(
+                    self.profiles
+                        .iter()
+                        .flat_map(|profiles| profiles.keys().filter(|name| !BUILTIN_PROFILES.contains(&name.as_str())).cloned()),
+                )
+                .collect::<Vec<_>>();
+            format!("Unknown profile '{name}' (expected one of: {})", names.join(", "))
+        })
+    }
+
+    /// Returns this config with every setting present in `overrides` replaced.
+    pub fn merge(self, overrides: Config) -> Config {
+        Config {
+            threads: overrides.threads.or(self.threads),
+            output_dir: overrides.output_dir.or(self.output_dir),
+            output_formats: overrides.output_formats.or(self.output_formats),
+            progress_interval: overrides.progress_interval.or(self.progress_interval),
+            notify_urls: overrides.notify_urls.or(self.notify_urls),
+            scheduler: overrides.scheduler.or(self.scheduler),
+            batch_size: overrides.batch_size.or(self.batch_size),
+            patterns: overrides.patterns.or(self.patterns),
+            profiles: overrides.profiles.or(self.profiles),
+            sinks: overrides.sinks.or(self.sinks),
+            clients: overrides.clients.or(self.clients),
+            email: overrides.email.or(self.email),
+            notifiers: overrides.notifiers.or(self.notifiers),
+        }
+    }
+}
+
+/// Records `threads` and `batch_size` in the config file at `path`, creating
+/// it if needed and keeping its other settings and comments.
+pub fn save_tuning(path: &Path, threads: usize, batch_size: usize) -> Result<(), String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Failed to read config file {}: {e}", path.display())),
+    };
+    let mut document = contents
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|e| format!("Invalid config file {}: {e}", path.display()))?;
+    document["threads"] = toml_edit::value(threads as i64);
+    document["batch_size"] = toml_edit::value(batch_size as i64);
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+    }
+    fs::write(path, document.to_string()).map_err(|e| format!("Failed to write config file {}: {e}", path.display()))
+}
+
+/// Returns the value of an environment variable, treating an empty value as unset.
+pub fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Returns the user's home directory.
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// Returns `$XDG_CONFIG_HOME/doppler-keygen/config.toml`, falling back to
+/// `~/.config/doppler-keygen/config.toml`.
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".config")))?;
+    Some(config_dir.join("doppler-keygen").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads a config file holding `contents`.
+    fn load(contents: &str) -> Result<Config, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, contents).unwrap();
+        Config::load(Some(&path))
+    }
+
+    #[test]
+    fn settings_are_read_from_the_file() {
+        let config = load(
+            "threads = 16\noutput_dir = \"/srv/keys\"\noutput_formats = [\"json\", \"bs58\"]\n\
+             progress_interval = 30\nnotify_urls = [\"https://hooks.example.com/doppler\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.threads, Some(16));
+        assert_eq!(config.output_dir, Some(PathBuf::from("/srv/keys")));
+        assert_eq!(config.output_formats, Some(vec![OutputFormat::Json, OutputFormat::Bs58]));
+        assert_eq!(config.progress_interval, Some(30));
+        assert_eq!(config.notify_urls, Some(vec!["https://hooks.example.com/doppler".to_string()]));
+        assert!(config.batch_size.is_none());
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        for (contents, reason) in [
+            ("threads = 0", "threads must be at least 1"),
+            ("batch_size = 0", "batch_size must be at least 1"),
+            ("progress_interval = 0", "progress_interval must be at least 1"),
+            ("notify_urls = [\"ftp://example.com/keys\"]", "is not an http:// or https:// URL"),
+            ("patterns = [{ prefix = \"ab\", count = 0 }]", "pattern count must be at least 1"),
+            ("patterns = [{ prefix = \"\", count = 1 }]", "non-empty prefix"),
+            ("threds = 4", "unknown field"),
+            ("[profiles.loop]\nargs = [\"--profile\", \"doppler\"]", "cannot select another profile"),
+        ] {
+            let error = load(contents).unwrap_err();
+            assert!(error.contains(reason), "{contents}: {error}");
+        }
+    }
+
+    /// The settings of the `DOPPLER_*` variables in `vars`.
+    fn from_vars(vars: &[(&str, &str)]) -> Result<Config, String> {
+        Config::from_vars(|name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn settings_are_read_from_the_environment() {
+        let config = from_vars(&[
+            ("DOPPLER_THREADS", "4"),
+            ("DOPPLER_OUTPUT_DIR", "/keys"),
+            ("DOPPLER_OUTPUT_FORMATS", "json, bs58"),
+            ("DOPPLER_PROGRESS_INTERVAL", "10"),
+            ("DOPPLER_NOTIFY_URLS", "https://a.example.com/x,http://b.example.com"),
+            ("DOPPLER_SCHEDULER", "rayon"),
+            ("DOPPLER_BATCH_SIZE", "64"),
+        ])
+        .unwrap();
+        assert_eq!(config.threads, Some(4));
+        assert_eq!(config.output_dir, Some(PathBuf::from("/keys")));
+        assert_eq!(config.output_formats, Some(vec![OutputFormat::Json, OutputFormat::Bs58]));
+        assert_eq!(config.progress_interval, Some(10));
+        assert_eq!(config.notify_urls.unwrap(), ["https://a.example.com/x", "http://b.example.com"]);
+        assert_eq!(config.scheduler, Some(Scheduler::Rayon));
+        assert_eq!(config.batch_size, Some(64));
+        assert!(from_vars(&[]).unwrap().threads.is_none());
+    }
+
+    #[test]
+    fn invalid_environment_variables_are_rejected() {
+        for (var, value) in [
+            ("DOPPLER_THREADS", "0"),
+            ("DOPPLER_THREADS", "many"),
+            ("DOPPLER_OUTPUT_FORMATS", "json,pem"),
+            ("DOPPLER_PROGRESS_INTERVAL", "0"),
+            ("DOPPLER_NOTIFY_URLS", "https://a.example.com,ftp://b.example.com"),
+            ("DOPPLER_SCHEDULER", "fibers"),
+            ("DOPPLER_BATCH_SIZE", "0"),
+        ] {
+            let error = from_vars(&[(var, value)]).unwrap_err();
+            assert!(error.starts_with(&format!("Invalid {var}")), "{var}={value}: {error}");
+        }
+    }
+
+    #[test]
+    fn environment_variables_override_the_file() {
+        let file = load("threads = 16
+output_dir = \"/srv/keys\"
+progress_interval = 30
+").unwrap();
+        let config = file.merge(from_vars(&[("DOPPLER_THREADS", "4"), ("DOPPLER_OUTPUT_DIR", "/keys")]).unwrap());
+        assert_eq!(config.threads, Some(4));
+        assert_eq!(config.output_dir, Some(PathBuf::from("/keys")));
+        // Settings the environment leaves unset keep the file's value
+        assert_eq!(config.progress_interval, Some(30));
+    }
+
+    #[test]
+    fn flags_override_the_environment_and_the_file() {
+        let file = load("threads = 16\noutput_dir = \"/srv/keys\"\nprogress_interval = 30\nbatch_size = 128\n").unwrap();
+        let config = file.merge(from_vars(&[("DOPPLER_THREADS", "4"), ("DOPPLER_OUTPUT_DIR", "/keys")]).unwrap());
+        let args = ["--num-threads", "2", "--count", "1"].map(String::from);
+        let options = crate::commands::grind::GrindArgs::parse(&args).unwrap().resolve(&config).unwrap();
+        assert_eq!(options.num_threads, 2);
+        assert_eq!(options.output_dir, PathBuf::from("/keys"));
+        assert_eq!(options.progress_interval, std::time::Duration::from_secs(30));
+        assert_eq!(options.batch_size, 128);
+        // Nothing sets the formats, so the built-in default applies
+        assert_eq!(options.output_formats, [OutputFormat::Json]);
+    }
+
+    #[test]
+    fn a_missing_file_is_only_an_error_when_named() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert!(Config::load(Some(&path)).unwrap_err().contains("Failed to read config file"));
+    }
+}
@@ -0,0 +1,323 @@
+//! Requirements on the matched address beyond imm32 compatibility.
+//!
+//! Any imm32 compatible segment satisfies a plain search. `Constraints`
+//! narrow that down, e.g. to a segment whose immediate spells an ASCII tag
+//! so the constant is recognizable in a hexdump, or whose value fits a
+//! smaller encoding, or to addresses with many zero bytes.
+
+use core::ops::Range;
+use serde::{Deserialize, Serialize};
+
+use crate::imm32;
+
+/// Longest ASCII tag: the four low bytes of the immediate.
+pub const MAX_TAG_LEN: usize = 4;
+
+/// The sign of the immediate's i32 value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImmSign {
+    /// Bit 31 clear: zero or positive, with bytes 4-7 all 0x00
+    Positive,
+    /// Bit 31 set, with bytes 4-7 all 0xFF
+    Negative,
+}
+
+impl ImmSign {
+    pub fn parse(value: &str) -> Result<ImmSign, String> {
+        match value {
+            "positive" => Ok(ImmSign::Positive),
+            "negative" => Ok(ImmSign::Negative),
+            _ => Err(format!("Unknown sign '{value}' (expected positive or negative)")),
+        }
+    }
+
+    /// The i32 values with this sign.
+    fn range(self) -> Range<i64> {
+        match self {
+            ImmSign::Positive => 0..i64::from(i32::MAX) + 1,
+            ImmSign::Negative => i64::from(i32::MIN)..0,
+        }
+    }
+}
+
+/// Parses `START..END`, a half-open range of i32 values such as `0..65536`.
+pub fn parse_imm_range(value: &str) -> Result<Range<i64>, String> {
+    let (start, end) = value
+        .split_once("..")
+        .ok_or_else(|| format!("Invalid range '{value}' (expected START..END)"))?;
+    let parse = |bound: &str| bound.parse::<i64>().map_err(|_| format!("Invalid bound '{bound}' in '{value}'"));
+    Ok(parse(start)?..parse(end)?)
+}
+
+/// Constraints on which imm32 compatible segment counts as a match. The
+/// default accepts any.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Constraints {
+    /// The only segment, 0-3, that may hold the immediate
+    pub segment: Option<usize>,
+    /// Printable ASCII the segment must start with. Bytes after the tag are
+    /// unconstrained up to byte 3; bytes 4-7 are the sign extension as for
+    /// any immediate, which is 0x00 for a full four-character tag.
+    pub segment_ascii: Option<String>,
+    /// Half-open range the immediate's i32 value must fall in
+    pub imm_range: Option<Range<i64>>,
+    /// Sign the immediate's i32 value must have
+    pub imm_sign: Option<ImmSign>,
+    /// Zero bytes the whole address must contain, counting those of the
+    /// matched segment
+    pub min_zero_bytes: Option<usize>,
+}
+
+impl Constraints {
+    /// Checks that the constraints can be satisfied.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(segment) = self.segment {
+            if segment > 3 {
+                return Err(format!("segment {segment} does not exist; an address has segments 0 to 3"));
+            }
+        }
+        if let Some(tag) = &self.segment_ascii {
+            if tag.is_empty() || tag.len() > MAX_TAG_LEN {
+                return Err(format!("ASCII tag '{tag}' must be 1 to {MAX_TAG_LEN} characters"));
+            }
+            if !tag.bytes().all(|byte| byte.is_ascii_graphic() || byte == b' ') {
+                return Err(format!("ASCII tag '{tag}' must be printable ASCII"));
+            }
+        }
+        if let Some(range) = &self.imm_range {
+            let i32_range = i64::from(i32::MIN)..i64::from(i32::MAX) + 1;
+            if range.start < i32_range.start || range.end > i32_range.end {
+                return Err(format!("immediate range {range:?} is outside the i32 range {i32_range:?}"));
+            }
+        }
+        if self.min_zero_bytes.is_some_and(|min_zero_bytes| min_zero_bytes > 32) {
+            return Err("an address has only 32 bytes, so at most 32 can be zero".to_string());
+        }
+        if self.allowed_values() == 0 {
+            return Err("no i32 value satisfies the immediate range and sign".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns the first segment of `address` that is imm32 compatible
+    /// according to `imm32_mask` and satisfies the constraints.
+    pub fn matching_segment(&self, address: &[u8; 32], imm32_mask: u8) -> Option<usize> {
+        if *self == Constraints::default() {
+            return imm32::first_segment(imm32_mask);
+        }
+        if self
+            .min_zero_bytes
+            .is_some_and(|min_zero_bytes| address.iter().filter(|&&byte| byte == 0).count() < min_zero_bytes)
+        {
+            return None;
+        }
+        (0..4)
+            .filter(|segment| imm32_mask & (1 << segment) != 0)
+            .find(|&segment| self.segment_matches(address, segment))
+    }
+
+    fn segment_matches(&self, address: &[u8; 32], segment: usize) -> bool {
+        let bytes = &address[segment * 8..segment * 8 + 8];
+        let value = i64::from(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        self.segment.is_none_or(|wanted| segment == wanted)
+            && self.segment_ascii.as_ref().is_none_or(|tag| bytes.starts_with(tag.as_bytes()))
+            && self.imm_range.as_ref().is_none_or(|range| range.contains(&value))
+            && self.imm_sign.is_none_or(|sign| sign.range().contains(&value))
+    }
+
+    /// How many i32 values the range and sign leave allowed.
+    fn allowed_values(&self) -> u64 {
+        let full = i64::from(i32::MIN)..i64::from(i32::MAX) + 1;
+        let range = self.imm_range.clone().unwrap_or(full.clone());
+        let sign = self.imm_sign.map_or(full, ImmSign::range);
+        (range.end.min(sign.end) - range.start.max(sign.start)).max(0) as u64
+    }
+
+    /// Chance that an imm32 compatible address also satisfies the
+    /// constraints, for estimating how long a search takes.
+    pub fn match_probability(&self) -> f64 {
+        let tag_probability = self.segment_ascii.as_ref().map_or(1.0, |tag| (1.0f64 / 256.0).powi(tag.len() as i32));
+        let zero_probability = self.min_zero_bytes.map_or(1.0, |min_zero_bytes| {
+            // A positive immediate, half of all matches, brings four zero
+            // bytes; the 28 bytes outside bytes 4-7 are random
+            let positive = self.imm_sign.map_or(0.5, |sign| if sign == ImmSign::Positive { 1.0 } else { 0.0 });
+            positive * at_least_zero_bytes(28, min_zero_bytes.saturating_sub(4))
+                + (1.0 - positive) * at_least_zero_bytes(28, min_zero_bytes)
+        });
+        // Nearly every imm32 compatible address has exactly one such segment
+        let segment_probability = if self.segment.is_some() { 0.25 } else { 1.0 };
+        segment_probability * tag_probability * zero_probability * self.allowed_values() as f64 / 4_294_967_296.0
+    }
+
+    /// Describes the constraints for run headers, or `None` if there are none.
+    pub fn describe(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(segment) = self.segment {
+            parts.push(format!("be in segment {segment} (bytes {}-{})", segment * 8, segment * 8 + 7));
+        }
+        if let Some(tag) = &self.segment_ascii {
+            parts.push(format!("start with the ASCII tag \"{tag}\""));
+        }
+        if let Some(range) = &self.imm_range {
+            parts.push(format!("have an i32 value in {}..{}", range.start, range.end));
+        }
+        if let Some(sign) = self.imm_sign {
+            parts.push(format!("be {}", if sign == ImmSign::Positive { "zero or positive" } else { "negative" }));
+        }
+        let mut description = (!parts.is_empty()).then(|| format!("the immediate must {}", parts.join(" and ")));
+        if let Some(min_zero_bytes) = self.min_zero_bytes {
+            let zero_bytes = format!("the address must contain at least {min_zero_bytes} zero bytes");
+            description = Some(match description {
+                Some(description) => format!("{description}; {zero_bytes}"),
+                None => zero_bytes,
+            });
+        }
+        description
+    }
+}
+
+/// Chance that at least `min` of `bytes` uniformly random bytes are zero.
+fn at_least_zero_bytes(bytes: usize, min: usize) -> f64 {
+    let p = 1.0f64 / 256.0;
+    // Summing the upper tail keeps tiny probabilities from rounding to zero
+    (min..=bytes)
+        .map(|k| {
+            let binomial = (0..k).fold(1.0, |binomial, i| binomial * (bytes - i) as f64 / (i + 1) as f64);
+            binomial * p.powi(k as i32) * (1.0 - p).powi((bytes - k) as i32)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An address whose segment `segment` holds the immediate `value`, with
+    /// every other byte 0x11 so no other segment is imm32 compatible.
+    fn address_with(segment: usize, value: i32) -> [u8; 32] {
+        let mut address = [0x11; 32];
+        address[segment * 8..segment * 8 + 8].copy_from_slice(&i64::from(value).to_le_bytes());
+        address
+    }
+
+    fn mask(address: &[u8; 32]) -> u8 {
+        let mut masks = [0];
+        imm32::imm32_masks(&[*address], &mut masks);
+        masks[0]
+    }
+
+    fn tagged(tag: &str) -> Constraints {
+        Constraints { segment_ascii: Some(tag.to_string()), ..Constraints::default() }
+    }
+
+    #[test]
+    fn tags_match_only_at_the_start_of_an_imm32_segment() {
+        for tag in ["D", "Do", "Dop", "Dopl"] {
+            let mut bytes = [0; 4];
+            bytes[..tag.len()].copy_from_slice(tag.as_bytes());
+            let address = address_with(2, i32::from_le_bytes(bytes));
+            assert_eq!(tagged(tag).matching_segment(&address, mask(&address)), Some(2), "{tag}");
+            // The same bytes in a segment that is not imm32 compatible
+            assert_eq!(tagged(tag).matching_segment(&address, 0), None, "{tag}");
+        }
+
+        // The tag one byte into the segment
+        let address = address_with(1, i32::from_le_bytes([b' ', b'D', b'o', 0]));
+        assert_eq!(tagged("Do").matching_segment(&address, mask(&address)), None);
+        assert_eq!(tagged(" Do").matching_segment(&address, mask(&address)), Some(1));
+    }
+
+    #[test]
+    fn tags_must_be_short_printable_ascii() {
+        assert!(tagged("Dopl").validate().is_ok());
+        assert!(tagged("a b").validate().is_ok());
+        assert!(tagged("").validate().is_err());
+        assert!(tagged("Doppl").validate().is_err());
+        assert!(tagged("a\tb").validate().unwrap_err().contains("printable"));
+        assert!(tagged("\u{7f}").validate().unwrap_err().contains("printable"));
+        assert!(tagged("é").validate().is_err());
+    }
+
+    fn ranged(range: &str, sign: Option<ImmSign>) -> Constraints {
+        Constraints { imm_range: Some(parse_imm_range(range).unwrap()), imm_sign: sign, ..Constraints::default() }
+    }
+
+    fn matches(constraints: &Constraints, value: i32) -> bool {
+        let address = address_with(0, value);
+        constraints.matching_segment(&address, mask(&address)).is_some()
+    }
+
+    #[test]
+    fn ranges_are_half_open_up_to_the_i32_bounds() {
+        let low = ranged("-2147483648..-2147483647", None);
+        assert!(low.validate().is_ok());
+        assert!(matches(&low, i32::MIN));
+        assert!(!matches(&low, i32::MIN + 1));
+
+        let high = ranged("2147483647..2147483648", None);
+        assert!(high.validate().is_ok());
+        assert!(matches(&high, i32::MAX));
+        assert!(!matches(&high, i32::MAX - 1));
+
+        let small = ranged("0..65536", None);
+        assert!(matches(&small, 0) && matches(&small, 65_535));
+        assert!(!matches(&small, 65_536) && !matches(&small, -1));
+
+        assert!(ranged("-2147483649..0", None).validate().unwrap_err().contains("outside the i32 range"));
+        assert!(ranged("0..2147483649", None).validate().unwrap_err().contains("outside the i32 range"));
+        assert!(parse_imm_range("0-5").is_err());
+        assert!(parse_imm_range("a..5").is_err());
+    }
+
+    #[test]
+    fn empty_ranges_are_rejected() {
+        assert!(ranged("10..5", None).validate().unwrap_err().contains("no i32 value"));
+        assert!(ranged("5..5", None).validate().unwrap_err().contains("no i32 value"));
+    }
+
+    #[test]
+    fn range_and_sign_intersect() {
+        let negative = ranged("-10..10", Some(ImmSign::Negative));
+        assert!(matches(&negative, -10) && matches(&negative, -1));
+        assert!(!matches(&negative, 0) && !matches(&negative, 9));
+        assert_eq!(negative.allowed_values(), 10);
+
+        assert!(ranged("0..100", Some(ImmSign::Negative)).validate().unwrap_err().contains("no i32 value"));
+        assert!(ranged("-100..0", Some(ImmSign::Positive)).validate().unwrap_err().contains("no i32 value"));
+    }
+
+    #[test]
+    fn probability_scales_with_the_allowed_values() {
+        assert_eq!(Constraints::default().match_probability(), 1.0);
+        assert_eq!(Constraints { imm_sign: Some(ImmSign::Positive), ..Constraints::default() }.match_probability(), 0.5);
+        assert_eq!(ranged("0..65536", None).match_probability(), 65_536.0 / 4_294_967_296.0);
+        assert_eq!(tagged("ab").match_probability(), 1.0 / 65_536.0);
+        let in_segment = Constraints { segment: Some(3), ..Constraints::default() };
+        assert_eq!(in_segment.match_probability(), 0.25);
+    }
+
+    #[test]
+    fn zero_byte_threshold_is_inclusive() {
+        let constraints = Constraints { min_zero_bytes: Some(6), ..Constraints::default() };
+        // A positive immediate brings its four zero bytes
+        let mut address = address_with(0, 0x1111_1111);
+        assert_eq!(constraints.matching_segment(&address, mask(&address)), None);
+        address[20] = 0;
+        assert_eq!(constraints.matching_segment(&address, mask(&address)), None);
+        address[21] = 0;
+        assert_eq!(constraints.matching_segment(&address, mask(&address)), Some(0));
+        address[22] = 0;
+        assert_eq!(constraints.matching_segment(&address, mask(&address)), Some(0));
+    }
+
+    #[test]
+    fn zero_byte_tail_is_a_probability() {
+        assert!((at_least_zero_bytes(28, 0) - 1.0).abs() < 1e-12);
+        assert!((at_least_zero_bytes(28, 1) - (1.0 - (255.0f64 / 256.0).powi(28))).abs() < 1e-12);
+        assert_eq!(at_least_zero_bytes(28, 28), (1.0f64 / 256.0).powi(28));
+        assert_eq!(at_least_zero_bytes(28, 29), 0.0);
+        assert!(at_least_zero_bytes(28, 3) < at_least_zero_bytes(28, 2));
+    }
+}
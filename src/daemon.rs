@@ -0,0 +1,1019 @@
+//! `daemon`: a long-running grind service with a priority queue of jobs.
+//!
+//! Jobs are submitted, reprioritized and cancelled over an HTTP API while a
+//! single batch search runs for the life of the process, and the queue
+//! decides which jobs' patterns are in it. Every candidate is checked
+//! against every running job, so each running job has the whole machine;
+//! what the queue hands out is one of `--max-running` slots:
+//!
+//! - jobs with a higher priority get a slot first, and one submitted while
+//!   the slots are taken by lower-priority jobs preempts the lowest of them
+//! - jobs of equal priority take turns, one `--time-slice` at a time, with
+//!   the job that has run the least going first
+//!
+//! A preempted job is paused with the keys it has found and picks up where
+//! it left off once it has a slot again. Running jobs with different
+//! priorities share the threads out by priority, and a key that matches
+//! several running jobs goes to the one with the highest priority. Each
+//! job's keys are saved to its own `job-N` directory under the output
+//! directory, while the key index and the audit log cover them all.
+//!
+//! With `[clients]` in the config file, every request needs a client token,
+//! and each client's jobs are held to its quotas: how many it may have at
+//! once, how high their priority and how hard their patterns may be, and
+//! how long each may run before it is stopped as expired.
+//!
+//! Jobs are kept in a `daemon.db` job store in the output directory, so a
+//! restarted daemon resumes every unfinished job with the keys, run time
+//! and attempts it had at its last checkpoint. Each attempt is independent
+//! of the ones before it, so the search itself loses nothing by restarting.
+//!
+//! The API also serves a web dashboard at `/` that shows the jobs, their
+//! ETAs and keys, and the search rate.
+
+use core::cmp::Reverse;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use doppler_keygen::batch::{self, BatchHooks, BatchMatch};
+use doppler_keygen::pattern::{BatchPattern, VanityPattern};
+use doppler_keygen::progress::ProgressSnapshot;
+use doppler_keygen::shutdown::ShutdownToken;
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+
+use crate::cli_error::CliError;
+use crate::clients::{Client, Clients};
+use crate::dashboard;
+use crate::http::{self, Request, Response};
+use crate::jobstore::JobStore;
+use crate::grind::GrindOptions;
+
+/// Port the API listens on by default.
+pub const DEFAULT_PORT: u16 = 8420;
+
+/// The address the daemon listens on by default: this machine only.
+pub const DEFAULT_BIND: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Jobs that run at once by default.
+pub const DEFAULT_MAX_RUNNING: usize = 4;
+
+/// How long jobs of equal priority run before taking turns, by default.
+pub const DEFAULT_TIME_SLICE: Duration = Duration::from_secs(600);
+
+/// How often the running batch picks up changes to the queue.
+const RELOAD_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How the daemon shares its search between jobs.
+#[derive(Debug, Clone, Copy)]
+pub struct DaemonSettings {
+    /// Address the API listens on
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Jobs whose patterns are searched for at once
+    pub max_running: usize,
+    /// How long a job runs before one of equal priority may take its slot
+    pub time_slice: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JobState {
+    /// Waiting for its first slot
+    Queued,
+    Running,
+    /// Preempted, or waiting for its turn, after running for a while
+    Paused,
+    Complete,
+    Cancelled,
+    /// Stopped after running for its client's `max_runtime`
+    Expired,
+}
+
+impl JobState {
+    fn name(self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Paused => "paused",
+            JobState::Complete => "complete",
+            JobState::Cancelled => "cancelled",
+            JobState::Expired => "expired",
+        }
+    }
+
+    /// Whether the job still wants keys.
+    fn is_active(self) -> bool {
+        matches!(self, JobState::Queued | JobState::Running | JobState::Paused)
+    }
+}
+
+/// The body of a `POST /jobs`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobRequest {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    priority: u32,
+    patterns: Vec<BatchPattern>,
+}
+
+/// The body of a `POST /jobs/ID/priority`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PriorityRequest {
+    priority: u32,
+}
+
+/// A key saved for a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JobKey {
+    pubkey: String,
+    address: String,
+    pattern: VanityPattern,
+    segment: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JobPattern {
+    pattern: VanityPattern,
+    count: usize,
+    /// Keys taken for the pattern, including any still being saved
+    found: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Job {
+    id: u64,
+    name: Option<String>,
+    /// The client that submitted the job, when the API has clients
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client: Option<String>,
+    /// Seconds the job may run before it expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_runtime: Option<u64>,
+    priority: u32,
+    state: JobState,
+    patterns: Vec<JobPattern>,
+    keys: Vec<JobKey>,
+    /// Unix time the job was submitted
+    submitted_at: u64,
+    /// Time spent running, which decides whose turn it is among jobs of
+    /// equal priority
+    served: Duration,
+    /// Candidates checked while the job was running
+    #[serde(default)]
+    attempts: u128,
+}
+
+impl Job {
+    fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("Job {} ({name})", self.id),
+            None => format!("Job {}", self.id),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.patterns.iter().all(|p| p.found >= p.count)
+    }
+
+    /// Whether `client` may see and manage the job. Without clients the API
+    /// is open.
+    fn is_visible_to(&self, client: Option<&Client>) -> bool {
+        client.is_none_or(|client| client.config.admin || self.client.as_deref() == Some(client.name.as_str()))
+    }
+}
+
+/// The jobs and what the batch has been told about them.
+#[derive(Debug)]
+struct Queue {
+    jobs: BTreeMap<u64, Job>,
+    next_id: u64,
+    /// Keys taken for each pattern over the whole run, including any still
+    /// being saved. The batch holds a slot for each of them too, so a
+    /// pattern's count in the batch is this plus what the running jobs
+    /// still need.
+    taken: Vec<(VanityPattern, usize)>,
+    /// Set when the jobs changed in a way that may change the schedule
+    changed: bool,
+    slice_started: Instant,
+    last_tick: Instant,
+    /// Attempt total of the run at the last checkpoint
+    last_attempts: u128,
+}
+
+impl Queue {
+    /// Gives the slots to the active jobs. Outside a turn change a running
+    /// job only loses its slot to a job with a higher priority.
+    fn schedule(&mut self, max_running: usize, next_turn: bool) {
+        let mut active: Vec<&mut Job> = self.jobs.values_mut().filter(|job| job.state.is_active()).collect();
+        active.sort_by_key(|job| {
+            let keeps_slot = !next_turn && job.state == JobState::Running;
+            (Reverse(job.priority), !keeps_slot, job.served, job.id)
+        });
+        for (rank, job) in active.into_iter().enumerate() {
+            let state = match job.state {
+                _ if rank < max_running => JobState::Running,
+                JobState::Queued => JobState::Queued,
+                _ => JobState::Paused,
+            };
+            match (job.state, state) {
+                (JobState::Queued, JobState::Running) => println!("{} started", job.label()),
+                (JobState::Paused, JobState::Running) => println!("{} resumed", job.label()),
+                (JobState::Running, JobState::Paused) => println!("{} paused", job.label()),
+                _ => {}
+            }
+            job.state = state;
+        }
+    }
+
+    /// The batch's patterns: those of the running jobs that still need
+    /// keys, each once, with the highest priority of the jobs that want it.
+    fn batch_patterns(&self) -> Vec<BatchPattern> {
+        let mut patterns: Vec<BatchPattern> = Vec::new();
+        for job in self.jobs.values().filter(|job| job.state == JobState::Running) {
+            for job_pattern in job.patterns.iter().filter(|p| p.found < p.count) {
+                let remaining = job_pattern.count - job_pattern.found;
+                match patterns.iter_mut().find(|p| p.pattern == job_pattern.pattern) {
+                    Some(batch_pattern) => {
+                        batch_pattern.count += remaining;
+                        batch_pattern.priority = batch_pattern.priority.max(job.priority);
+                    }
+                    None => patterns.push(BatchPattern {
+                        pattern: job_pattern.pattern.clone(),
+                        count: self.taken(&job_pattern.pattern) + remaining,
+                        found: 0,
+                        priority: job.priority,
+                        sink: None,
+                    }),
+                }
+            }
+        }
+        patterns
+    }
+
+    fn taken(&self, pattern: &VanityPattern) -> usize {
+        self.taken.iter().find(|(p, _)| p == pattern).map_or(0, |&(_, count)| count)
+    }
+}
+
+/// The daemon's state, shared by the API and the running batch.
+struct Daemon {
+    settings: DaemonSettings,
+    ignore_case: bool,
+    options: GrindOptions,
+    queue: Mutex<Queue>,
+    store: JobStore,
+    /// The API's clients, if it has any
+    clients: Option<Clients>,
+    /// The latest progress report, once there is one
+    snapshot: Mutex<Option<ProgressSnapshot>>,
+}
+
+/// An API error: the status and the message returned to the client.
+type ApiError = (u16, String);
+
+impl Daemon {
+    fn queue(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn job_dir(&self, id: u64) -> PathBuf {
+        self.options.output_dir.join(format!("job-{id}"))
+    }
+
+    /// Writes `jobs` to the store. The daemon keeps going if that fails,
+    /// since it only matters after a restart.
+    fn persist<'a>(&self, jobs: impl IntoIterator<Item = &'a Job>) {
+        if let Err(e) = self.store.save(jobs.into_iter().map(|job| (job.id, job.state.name(), job))) {
+            eprintln!("Warning: {e}");
+        }
+    }
+
+    /// The attempts a job for `patterns` is expected to need.
+    fn expected_attempts(&self, patterns: &[JobPattern]) -> f64 {
+        let key_probability = crate::grind::key_match_probability(&self.options);
+        patterns
+            .iter()
+            .map(|p| p.count as f64 / (key_probability * p.pattern.match_probability(self.ignore_case)))
+            .sum()
+    }
+
+    /// Holds a new job or a new priority to `client`'s quotas. The caller
+    /// keeps `queue` locked until the job is inserted, so concurrent
+    /// submissions can't both slip under `max_jobs`.
+    fn check_quota(
+        &self,
+        queue: &Queue,
+        client: Option<&Client>,
+        priority: u32,
+        patterns: Option<&[JobPattern]>,
+    ) -> Result<(), ApiError> {
+        let Some(client) = client.filter(|client| !client.config.admin) else {
+            return Ok(());
+        };
+        if let Some(max_priority) = client.config.max_priority.filter(|&max| priority > max) {
+            return Err((403, format!("Client '{}' may not use a priority above {max_priority}", client.name)));
+        }
+        let Some(patterns) = patterns else {
+            return Ok(());
+        };
+        let expected = self.expected_attempts(patterns);
+        if let Some(max_attempts) = client.config.max_attempts.filter(|&max| expected > max) {
+            return Err((
+                403,
+                format!(
+                    "The job needs about {expected:.3e} attempts, over the limit of {max_attempts:.3e} for client '{}'; \
+                     ask for fewer keys or shorter patterns",
+                    client.name
+                ),
+            ));
+        }
+        if let Some(max_jobs) = client.config.max_jobs {
+            let active = queue
+                .jobs
+                .values()
+                .filter(|job| job.state.is_active() && job.client.as_deref() == Some(client.name.as_str()))
+                .count();
+            if active >= max_jobs {
+                return Err((429, format!("Client '{}' already has {active} unfinished job(s), its limit", client.name)));
+            }
+        }
+        Ok(())
+    }
+
+    fn submit(&self, client: Option<&Client>, body: &str) -> Result<Value, ApiError> {
+        let request: JobRequest = serde_json::from_str(body).map_err(|e| (400, format!("Invalid job: {e}")))?;
+        if request.patterns.is_empty() {
+            return Err((400, "A job needs at least one pattern".to_string()));
+        }
+        let mut patterns: Vec<JobPattern> = Vec::new();
+        for batch_pattern in request.patterns {
+            let pattern = batch_pattern.pattern;
+            pattern.validate(self.ignore_case).map_err(|e| (400, format!("{pattern}: {e}")))?;
+            if batch_pattern.count == 0 {
+                return Err((400, format!("{pattern}: count must be at least 1")));
+            }
+            if batch_pattern.priority != 0 || batch_pattern.sink.is_some() {
+                return Err((400, format!("{pattern}: priority is set on the job, and sinks are not supported by the daemon")));
+            }
+            match patterns.iter_mut().find(|p| p.pattern == pattern) {
+                Some(job_pattern) => job_pattern.count += batch_pattern.count,
+                None => patterns.push(JobPattern { pattern, count: batch_pattern.count, found: 0 }),
+            }
+        }
+        let mut queue = self.queue();
+        self.check_quota(&queue, client, request.priority, Some(&patterns))?;
+        let id = queue.next_id;
+        let dir = self.job_dir(id);
+        fs::create_dir_all(&dir).map_err(|e| (500, format!("Failed to create {}: {e}", dir.display())))?;
+        queue.next_id += 1;
+        let submitted_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let job = Job {
+            id,
+            name: request.name,
+            client: client.map(|client| client.name.clone()),
+            max_runtime: client.filter(|client| !client.config.admin).and_then(|client| client.config.max_runtime),
+            priority: request.priority,
+            state: JobState::Queued,
+            patterns,
+            keys: Vec::new(),
+            submitted_at,
+            served: Duration::ZERO,
+            attempts: 0,
+        };
+        self.store
+            .save([(job.id, job.state.name(), &job)])
+            .map_err(|e| (500, e.to_string()))?;
+        println!("{} queued with priority {}", job.label(), job.priority);
+        let view = self.view(&job);
+        queue.jobs.insert(id, job);
+        queue.changed = true;
+        Ok(view)
+    }
+
+    fn set_priority(&self, client: Option<&Client>, id: u64, body: &str) -> Result<Value, ApiError> {
+        let request: PriorityRequest = serde_json::from_str(body).map_err(|e| (400, format!("Invalid priority: {e}")))?;
+        let mut queue = self.queue();
+        self.check_quota(&queue, client, request.priority, None)?;
+        let job = queue.jobs.get_mut(&id).filter(|job| job.is_visible_to(client)).ok_or_else(|| no_job(id))?;
+        if !job.state.is_active() {
+            return Err((409, format!("{} is {}", job.label(), job.state.name())));
+        }
+        job.priority = request.priority;
+        println!("{} reprioritized to {}", job.label(), job.priority);
+        self.persist([&*job]);
+        let view = self.view(job);
+        queue.changed = true;
+        Ok(view)
+    }
+
+    fn cancel(&self, client: Option<&Client>, id: u64) -> Result<Value, ApiError> {
+        let mut queue = self.queue();
+        let job = queue.jobs.get_mut(&id).filter(|job| job.is_visible_to(client)).ok_or_else(|| no_job(id))?;
+        if !job.state.is_active() {
+            return Err((409, format!("{} is already {}", job.label(), job.state.name())));
+        }
+        job.state = JobState::Cancelled;
+        println!("{} cancelled with {} key(s) found", job.label(), job.keys.len());
+        self.persist([&*job]);
+        let view = self.view(job);
+        queue.changed = true;
+        Ok(view)
+    }
+
+    /// Called by the batch at every reload: charges the running jobs for
+    /// their time, and returns the new pattern list if the schedule or the
+    /// jobs changed.
+    fn tick(&self) -> Option<Vec<BatchPattern>> {
+        let mut queue = self.queue();
+        let now = Instant::now();
+        let elapsed = now - queue.last_tick;
+        queue.last_tick = now;
+        let mut expired = false;
+        for job in queue.jobs.values_mut().filter(|job| job.state == JobState::Running) {
+            job.served += elapsed;
+            if job.max_runtime.is_some_and(|max| job.served.as_secs() >= max) {
+                job.state = JobState::Expired;
+                println!(
+                    "{} expired after {} with {} key(s) found",
+                    job.label(),
+                    crate::grind::format_eta(job.served.as_secs_f64()),
+                    job.keys.len()
+                );
+                expired = true;
+            }
+        }
+        queue.changed |= expired;
+
+        let next_turn = now - queue.slice_started >= self.settings.time_slice;
+        if next_turn {
+            queue.slice_started = now;
+        }
+        if !queue.changed && !next_turn {
+            return None;
+        }
+        queue.changed = false;
+        queue.schedule(self.settings.max_running, next_turn);
+        self.persist(queue.jobs.values().filter(|job| job.state.is_active()));
+        Some(queue.batch_patterns())
+    }
+
+    /// Credits the attempts made since the last checkpoint to the running
+    /// jobs, which see every one of them, and stores the running jobs.
+    fn checkpoint(&self, total_attempts: u128) {
+        let mut queue = self.queue();
+        let attempts = total_attempts.saturating_sub(queue.last_attempts);
+        queue.last_attempts = total_attempts;
+        for job in queue.jobs.values_mut().filter(|job| job.state == JobState::Running) {
+            job.attempts += attempts;
+        }
+        self.persist(queue.jobs.values().filter(|job| job.state == JobState::Running));
+    }
+
+    /// Takes a slot for a key matching `pattern` in the running job that
+    /// wants it most, returning the job's id.
+    fn assign(&self, pattern: &VanityPattern) -> Option<u64> {
+        let mut queue = self.queue();
+        let job = queue
+            .jobs
+            .values_mut()
+            .filter(|job| job.state == JobState::Running)
+            .filter(|job| job.patterns.iter().any(|p| p.pattern == *pattern && p.found < p.count))
+            .min_by_key(|job| (Reverse(job.priority), job.id))?;
+        let id = job.id;
+        let job_pattern = job.patterns.iter_mut().find(|p| p.pattern == *pattern)?;
+        job_pattern.found += 1;
+        match queue.taken.iter_mut().find(|(p, _)| p == pattern) {
+            Some((_, count)) => *count += 1,
+            None => queue.taken.push((pattern.clone(), 1)),
+        }
+        Some(id)
+    }
+
+    /// Records the outcome of saving a key assigned to job `id`, giving the
+    /// slot back if it wasn't saved.
+    fn record(&self, id: u64, batch_match: &BatchMatch, saved: bool) {
+        let mut queue = self.queue();
+        if !saved {
+            if let Some((_, count)) = queue.taken.iter_mut().find(|(p, _)| *p == batch_match.pattern) {
+                *count -= 1;
+            }
+        }
+        let Some(job) = queue.jobs.get_mut(&id) else {
+            return;
+        };
+        if !saved {
+            if let Some(job_pattern) = job.patterns.iter_mut().find(|p| p.pattern == batch_match.pattern) {
+                job_pattern.found -= 1;
+            }
+            return;
+        }
+        job.keys.push(JobKey {
+            pubkey: batch_match.key.keypair.pubkey().to_string(),
+            address: batch_match.key.address.clone(),
+            pattern: batch_match.pattern.clone(),
+            segment: batch_match.key.segment,
+        });
+        // A job paused or cancelled meanwhile keeps its key; one paused with
+        // every key found is complete all the same
+        let complete = job.state.is_active() && job.is_done();
+        if complete {
+            job.state = JobState::Complete;
+            println!("{} complete: {} key(s) in {}", job.label(), job.keys.len(), self.job_dir(id).display());
+        }
+        self.persist([&*job]);
+        queue.changed |= complete;
+    }
+
+    /// Estimated time for a running job to find the rest of its keys: its
+    /// patterns are searched for at once, so the slowest one decides.
+    fn eta(&self, job: &Job) -> Option<f64> {
+        let snapshot = self.snapshot.lock().unwrap_or_else(PoisonError::into_inner);
+        let rate = snapshot.as_ref()?.rate.max(1.0);
+        let key_probability = crate::grind::key_match_probability(&self.options);
+        (job.state == JobState::Running).then(|| {
+            job.patterns
+                .iter()
+                .map(|p| {
+                    let probability = key_probability * p.pattern.match_probability(self.ignore_case);
+                    p.count.saturating_sub(p.found) as f64 / (probability * rate)
+                })
+                .fold(0.0, f64::max)
+        })
+    }
+
+    fn view(&self, job: &Job) -> Value {
+        let patterns: Vec<Value> = job
+            .patterns
+            .iter()
+            .map(|p| json!({ "pattern": p.pattern.to_string(), "count": p.count, "found": p.found }))
+            .collect();
+        let keys: Vec<Value> = job
+            .keys
+            .iter()
+            .map(|key| {
+                json!({
+                    "pubkey": key.pubkey,
+                    "address": key.address,
+                    "pattern": key.pattern.to_string(),
+                    "segment": key.segment,
+                })
+            })
+            .collect();
+        json!({
+            "id": job.id,
+            "name": job.name,
+            "client": job.client,
+            "max_runtime_secs": job.max_runtime,
+            "priority": job.priority,
+            "state": job.state.name(),
+            "submitted_at": job.submitted_at,
+            "run_secs": job.served.as_secs(),
+            "attempts": job.attempts,
+            "requested": job.patterns.iter().map(|p| p.count).sum::<usize>(),
+            "found": job.keys.len(),
+            "eta_secs": self.eta(job),
+            "patterns": patterns,
+            "keys": keys,
+            "output_dir": self.job_dir(job.id),
+        })
+    }
+
+    fn status(&self) -> Value {
+        let snapshot = self.snapshot.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        let queue = self.queue();
+        let mut jobs = BTreeMap::new();
+        for job in queue.jobs.values() {
+            *jobs.entry(job.state.name()).or_insert(0) += 1;
+        }
+        json!({
+            "attempts": snapshot.as_ref().map_or(0, |s| s.total_attempts),
+            "rate": snapshot.as_ref().map_or(0.0, |s| s.rate),
+            "rate_history": snapshot.as_ref().map_or(&[][..], |s| &s.rate_history[..]),
+            "elapsed_secs": snapshot.as_ref().map_or(0, |s| s.elapsed.as_secs()),
+            "keys_found": snapshot.as_ref().map_or(0, |s| s.keys_found),
+            "max_running": self.settings.max_running,
+            "time_slice_secs": self.settings.time_slice.as_secs(),
+            "jobs": jobs,
+        })
+    }
+
+    /// Answers an API request, from a client if the API has clients.
+    fn handle(&self, request: &Request) -> Response {
+        if request.method == "GET" {
+            if let Some(asset) = dashboard::asset(&request.path) {
+                return asset;
+            }
+        }
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let client = match &self.clients {
+            Some(clients) if segments != ["healthz"] => match clients.authenticate(request) {
+                Ok(client) => Some(client),
+                Err((status, message)) => return Response::json(status, &json!({ "error": message })),
+            },
+            _ => None,
+        };
+        let result = match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["healthz"]) => Ok(json!({ "status": "live" })),
+            ("GET", ["status"]) => Ok(self.status()),
+            ("GET", ["jobs"]) => {
+                let queue = self.queue();
+                let jobs = queue.jobs.values().filter(|job| job.is_visible_to(client));
+                Ok(Value::Array(jobs.map(|job| self.view(job)).collect()))
+            }
+            ("POST", ["jobs"]) => {
+                return match self.submit(client, &request.body) {
+                    Ok(view) => Response::json(201, &view),
+                    Err((status, message)) => Response::json(status, &json!({ "error": message })),
+                };
+            }
+            ("GET", ["jobs", id]) => parse_id(id).and_then(|id| {
+                let queue = self.queue();
+                let job = queue.jobs.get(&id).filter(|job| job.is_visible_to(client));
+                job.map(|job| self.view(job)).ok_or_else(|| no_job(id))
+            }),
+            ("DELETE", ["jobs", id]) => parse_id(id).and_then(|id| self.cancel(client, id)),
+            ("POST", ["jobs", id, "priority"]) => {
+                parse_id(id).and_then(|id| self.set_priority(client, id, &request.body))
+            }
+            (
+                _,
+                [""]
+                | ["index.html" | "dashboard.js" | "dashboard.css" | "healthz" | "status" | "jobs"]
+                | ["jobs", _]
+                | ["jobs", _, "priority"],
+            ) => {
+                Err((405, format!("{} is not supported on {}", request.method, request.path)))
+            }
+            _ => Err((404, format!("No endpoint {}", request.path))),
+        };
+        match result {
+            Ok(body) => Response::json(200, &body),
+            Err((status, message)) => Response::json(status, &json!({ "error": message })),
+        }
+    }
+}
+
+fn parse_id(id: &str) -> Result<u64, ApiError> {
+    id.parse::<u64>().map_err(|_| (404, format!("No job {id}")))
+}
+
+fn no_job(id: u64) -> ApiError {
+    (404, format!("No job {id}"))
+}
+
+/// The jobs in `store`, as a restarted daemon picks them up: the running
+/// ones are paused until the queue schedules them again.
+fn resume(store: &JobStore) -> Result<BTreeMap<u64, Job>, CliError> {
+    let mut jobs = BTreeMap::new();
+    for mut job in store.load::<Job>()? {
+        // Keys still being saved when the daemon stopped were never recorded
+        for job_pattern in &mut job.patterns {
+            job_pattern.found = job.keys.iter().filter(|key| key.pattern == job_pattern.pattern).count();
+        }
+        if job.state == JobState::Running {
+            job.state = JobState::Paused;
+        }
+        jobs.insert(job.id, job);
+    }
+    Ok(jobs)
+}
+
+/// Refuses to open the API beyond this machine without `[clients]`, since
+/// without them anyone who can reach the port could submit and cancel jobs.
+fn check_bind(bind: IpAddr, clients: Option<&Clients>) -> Result<(), CliError> {
+    if bind.is_loopback() || clients.is_some() {
+        return Ok(());
+    }
+    Err(CliError::Usage(format!(
+        "the daemon would be open to anyone who can reach {bind}; configure [clients] to listen beyond 127.0.0.1"
+    )))
+}
+
+/// Serves the job API on `settings.bind` and `settings.port`, to `clients`
+/// if there are any, and runs the batch search for the queued jobs until Ctrl-C. Returns the
+/// keypairs kept, as `grind` does.
+pub fn run(
+    settings: DaemonSettings,
+    ignore_case: bool,
+    clients: Option<Clients>,
+    options: &GrindOptions,
+) -> Result<Vec<Keypair>, CliError> {
+    check_bind(settings.bind, clients.as_ref())?;
+    println!("Doppler Keygen daemon - Mining for 32-bit immediate value compatible vanity keys for queued jobs");
+    crate::grind::print_derivation(options);
+    crate::grind::print_constraints(options);
+    println!("Using {} threads", options.num_threads);
+    if ignore_case {
+        println!("Vanity patterns are matched case-insensitively");
+    }
+    println!(
+        "Running up to {} job(s) at once; jobs of equal priority take turns every {}",
+        settings.max_running,
+        crate::grind::format_eta(settings.time_slice.as_secs_f64())
+    );
+
+    let store = JobStore::open(&options.output_dir)?;
+    let jobs = resume(&store)?;
+    let unfinished = jobs.values().filter(|job| job.state.is_active()).count();
+    println!("Job store: {} ({unfinished} unfinished job(s) resumed)", store.path().display());
+    match &clients {
+        Some(clients) => println!("API clients: {} configured; requests need a client token", clients.len()),
+        None => println!("API clients: none configured; the API is open to anyone on this machine"),
+    }
+
+    let now = Instant::now();
+    let daemon = Arc::new(Daemon {
+        settings,
+        ignore_case,
+        options: options.clone(),
+        queue: Mutex::new(Queue {
+            jobs,
+            next_id: store.last_id()? + 1,
+            taken: Vec::new(),
+            changed: unfinished > 0,
+            slice_started: now,
+            last_tick: now,
+            last_attempts: 0,
+        }),
+        store,
+        clients,
+        snapshot: Mutex::new(None),
+    });
+    let addr = {
+        let daemon = Arc::clone(&daemon);
+        http::serve(SocketAddr::new(settings.bind, settings.port), "daemon-api", move |request| daemon.handle(request))?
+    };
+    if settings.bind.is_loopback() {
+        println!("Dashboard: http://localhost:{}/", addr.port());
+    } else {
+        println!("Dashboard: http://{addr}/");
+    }
+    println!("Taking jobs on port {}; press Ctrl-C to stop\n", addr.port());
+
+    let shutdown = ShutdownToken::new();
+    crate::grind::stop_on_interrupt(&shutdown)?;
+
+    let hooks = {
+        let progress_daemon = Arc::clone(&daemon);
+        let match_daemon = Arc::clone(&daemon);
+        let reload_daemon = Arc::clone(&daemon);
+
+        BatchHooks {
+            on_progress: Some((
+                options.progress_interval,
+                Box::new(move |snapshot| {
+                    crate::grind::record_progress(&progress_daemon.options, &snapshot);
+                    progress_daemon.checkpoint(snapshot.total_attempts);
+                    crate::grind::print_progress(&snapshot, None);
+                    {
+                        let queue = progress_daemon.queue();
+                        for job in queue.jobs.values().filter(|job| job.state == JobState::Running) {
+                            let requested: usize = job.patterns.iter().map(|p| p.count).sum();
+                            let eta = progress_daemon.eta(job).map_or_else(String::new, |eta| {
+                                format!(", ETA {}", crate::grind::format_eta(eta))
+                            });
+                            println!("  {}: {}/{requested}{eta}", job.label(), job.keys.len());
+                        }
+                    }
+                    *progress_daemon.snapshot.lock().unwrap_or_else(PoisonError::into_inner) = Some(snapshot);
+                }),
+            )),
+            filter: crate::grind::custom_filter(options),
+            on_match: Some(Box::new(move |batch_match: &BatchMatch| {
+                let Some(id) = match_daemon.assign(&batch_match.pattern) else {
+                    // The job that wanted it was paused or cancelled meanwhile
+                    return false;
+                };
+                let mut job_options = match_daemon.options.clone();
+                job_options.output_dir = match_daemon.job_dir(id);
+                println!("\nJob {id}:");
+                let saved = crate::grind::save_batch_match(batch_match, &job_options);
+                match_daemon.record(id, batch_match, saved);
+                saved
+            })),
+            reload: Some((RELOAD_INTERVAL, Box::new(move || reload_daemon.tick()))),
+            shutdown: Some(shutdown.clone()),
+        }
+    };
+
+    let result = batch::vanity_keys_batch(&[], &options.grind_config(ignore_case), hooks)?;
+    daemon.checkpoint(result.stats.total_attempts);
+    Ok(crate::grind::finish_batch(result, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ClientConfig;
+    use sha2::Digest as _;
+    use std::thread;
+    use tempfile::TempDir;
+
+    fn prefix(prefix: &str) -> VanityPattern {
+        VanityPattern { prefix: Some(prefix.to_string()), suffix: None }
+    }
+
+    fn job(id: u64, priority: u32, state: JobState, served_secs: u64) -> Job {
+        Job {
+            id,
+            name: None,
+            client: None,
+            max_runtime: None,
+            priority,
+            state,
+            patterns: vec![JobPattern { pattern: prefix("ab"), count: 2, found: 0 }],
+            keys: Vec::new(),
+            submitted_at: 0,
+            served: Duration::from_secs(served_secs),
+            attempts: 0,
+        }
+    }
+
+    fn queue(jobs: Vec<Job>) -> Queue {
+        let now = Instant::now();
+        Queue {
+            next_id: jobs.len() as u64 + 1,
+            jobs: jobs.into_iter().map(|job| (job.id, job)).collect(),
+            taken: Vec::new(),
+            changed: false,
+            slice_started: now,
+            last_tick: now,
+            last_attempts: 0,
+        }
+    }
+
+    fn states(queue: &Queue) -> Vec<JobState> {
+        queue.jobs.values().map(|job| job.state).collect()
+    }
+
+    #[test]
+    fn a_restart_resumes_unfinished_jobs_from_the_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JobStore::open(dir.path()).unwrap();
+
+        let mut running = job(1, 5, JobState::Running, 120);
+        running.attempts = 1_000;
+        // One key was saved; the second was still being saved at the crash
+        running.patterns[0].found = 2;
+        running.keys.push(JobKey { pubkey: "Pubkey1".to_string(), address: "ab1".to_string(), pattern: prefix("ab"), segment: 0 });
+        let cancelled = job(2, 1, JobState::Cancelled, 30);
+        store.save([(1, "running", &running), (2, "cancelled", &cancelled)]).unwrap();
+        drop(store);
+
+        let store = JobStore::open(dir.path()).unwrap();
+        assert_eq!(store.last_id().unwrap(), 2);
+        let jobs = resume(&store).unwrap();
+        assert_eq!(jobs[&1].state, JobState::Paused);
+        assert_eq!(jobs[&1].patterns[0].found, 1);
+        assert_eq!(jobs[&1].served, Duration::from_secs(120));
+        assert_eq!(jobs[&1].attempts, 1_000);
+        assert_eq!(jobs[&1].keys[0].pubkey, "Pubkey1");
+        assert_eq!(jobs[&2].state, JobState::Cancelled);
+    }
+
+    /// A daemon saving to a temporary directory, with no clients. The
+    /// directory goes away when the returned `TempDir` is dropped.
+    fn daemon() -> (TempDir, Daemon) {
+        let dir = tempfile::tempdir().unwrap();
+        let args = ["--output-dir".to_string(), dir.path().display().to_string()];
+        let options = crate::commands::grind::GrindArgs::parse(&args).unwrap().resolve(&crate::config::Config::default()).unwrap();
+        let daemon = Daemon {
+            settings: DaemonSettings { bind: DEFAULT_BIND, port: 0, max_running: 1, time_slice: DEFAULT_TIME_SLICE },
+            ignore_case: false,
+            store: JobStore::open(dir.path()).unwrap(),
+            options,
+            queue: Mutex::new(queue(Vec::new())),
+            clients: None,
+            snapshot: Mutex::new(None),
+        };
+        (dir, daemon)
+    }
+
+    fn client(token: &str) -> ClientConfig {
+        ClientConfig { token_sha256: hex::encode(sha2::Sha256::digest(token.as_bytes())), ..ClientConfig::default() }
+    }
+
+    /// Submits a job for `body` with `token`, returning the status.
+    fn submit(daemon: &Daemon, token: &str, body: &str) -> u16 {
+        let request = Request {
+            method: "POST".to_string(),
+            path: "/jobs".to_string(),
+            headers: vec![("authorization".to_string(), format!("Bearer {token}"))],
+            body: body.to_string(),
+        };
+        daemon.handle(&request).status
+    }
+
+    #[test]
+    fn jobs_over_a_clients_quotas_are_rejected() {
+        let (_dir, mut daemon) = daemon();
+        // Room for a two-letter prefix, but not an eight-letter one
+        let max_attempts = 100.0 * daemon.expected_attempts(&[JobPattern { pattern: prefix("ab"), count: 1, found: 0 }]);
+        let limited = ClientConfig { max_jobs: Some(1), max_priority: Some(3), max_attempts: Some(max_attempts), ..client("alice-token") };
+        let admin = ClientConfig { admin: true, ..client("root-token") };
+        let configs = BTreeMap::from([("alice".to_string(), limited), ("root".to_string(), admin)]);
+        daemon.clients = Some(Clients::new(&configs).unwrap());
+
+        assert_eq!(submit(&daemon, "alice-token", r#"{"priority": 5, "patterns": [{"prefix": "ab", "count": 1}]}"#), 403);
+        assert_eq!(submit(&daemon, "alice-token", r#"{"patterns": [{"prefix": "abcdefgh", "count": 1}]}"#), 403);
+        assert_eq!(submit(&daemon, "alice-token", r#"{"priority": 3, "patterns": [{"prefix": "ab", "count": 1}]}"#), 201);
+        assert_eq!(submit(&daemon, "alice-token", r#"{"patterns": [{"prefix": "cd", "count": 1}]}"#), 429);
+        // Admins have no quotas
+        assert_eq!(submit(&daemon, "root-token", r#"{"priority": 9, "patterns": [{"prefix": "abcdefgh", "count": 1}]}"#), 201);
+        assert_eq!(submit(&daemon, "wrong-token", r#"{"patterns": [{"prefix": "ab", "count": 1}]}"#), 401);
+
+        let queue = daemon.queue();
+        let owners: Vec<_> = queue.jobs.values().map(|job| job.client.as_deref()).collect();
+        assert_eq!(owners, [Some("alice"), Some("root")]);
+    }
+
+    #[test]
+    fn only_a_daemon_with_clients_listens_beyond_loopback() {
+        let clients = Clients::new(&BTreeMap::from([("alice".to_string(), client("alice-token"))])).unwrap();
+        assert!(check_bind(DEFAULT_BIND, None).is_ok());
+        assert!(check_bind("::1".parse().unwrap(), None).is_ok());
+        assert!(check_bind("0.0.0.0".parse().unwrap(), None).unwrap_err().to_string().contains("[clients]"));
+        assert!(check_bind("10.0.0.5".parse().unwrap(), None).is_err());
+        assert!(check_bind("0.0.0.0".parse().unwrap(), Some(&clients)).is_ok());
+    }
+
+    #[test]
+    fn concurrent_submissions_stay_within_max_jobs() {
+        let (_dir, mut daemon) = daemon();
+        let limited = ClientConfig { max_jobs: Some(1), ..client("alice-token") };
+        daemon.clients = Some(Clients::new(&BTreeMap::from([("alice".to_string(), limited)])).unwrap());
+
+        let statuses: Vec<u16> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| submit(&daemon, "alice-token", r#"{"patterns": [{"prefix": "ab", "count": 1}]}"#)))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        assert_eq!(statuses.iter().filter(|&&status| status == 201).count(), 1, "{statuses:?}");
+        assert_eq!(statuses.iter().filter(|&&status| status == 429).count(), 7, "{statuses:?}");
+        assert_eq!(daemon.queue().jobs.len(), 1);
+    }
+
+    #[test]
+    fn a_higher_priority_job_preempts_the_lowest_running_one() {
+        let mut queue = queue(vec![
+            job(1, 5, JobState::Running, 0),
+            job(2, 1, JobState::Running, 0),
+            job(3, 9, JobState::Queued, 0),
+        ]);
+        queue.schedule(2, false);
+        assert_eq!(states(&queue), [JobState::Running, JobState::Paused, JobState::Running]);
+
+        // A lower priority than every running job waits, whatever its turn
+        queue.jobs.insert(4, job(4, 0, JobState::Queued, 0));
+        queue.schedule(2, true);
+        assert_eq!(queue.jobs[&4].state, JobState::Queued);
+        assert_eq!(queue.jobs[&2].state, JobState::Paused);
+    }
+
+    #[test]
+    fn jobs_of_equal_priority_take_turns() {
+        let mut queue = queue(vec![job(1, 3, JobState::Running, 600), job(2, 3, JobState::Paused, 300)]);
+        // Until the time slice is up, the running job keeps its slot
+        queue.schedule(1, false);
+        assert_eq!(states(&queue), [JobState::Running, JobState::Paused]);
+        // Then the job that has run the least goes next
+        queue.schedule(1, true);
+        assert_eq!(states(&queue), [JobState::Paused, JobState::Running]);
+        queue.jobs.get_mut(&2).unwrap().served = Duration::from_secs(900);
+        queue.schedule(1, true);
+        assert_eq!(states(&queue), [JobState::Running, JobState::Paused]);
+    }
+
+    #[test]
+    fn finished_jobs_give_up_their_slots() {
+        let mut queue = queue(vec![job(1, 5, JobState::Cancelled, 0), job(2, 1, JobState::Queued, 0)]);
+        queue.schedule(1, false);
+        assert_eq!(states(&queue), [JobState::Cancelled, JobState::Running]);
+    }
+
+    #[test]
+    fn running_jobs_share_batch_patterns() {
+        let mut queue = queue(vec![job(1, 2, JobState::Running, 0), job(2, 7, JobState::Running, 0), job(3, 9, JobState::Paused, 0)]);
+        queue.jobs.get_mut(&1).unwrap().patterns.push(JobPattern { pattern: prefix("cd"), count: 1, found: 1 });
+        queue.taken.push((prefix("ab"), 3));
+
+        let patterns = queue.batch_patterns();
+        // The paused job and the finished pattern are left out; the slots
+        // already taken stay held
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern, prefix("ab"));
+        assert_eq!(patterns[0].count, 3 + 2 + 2);
+        assert_eq!(patterns[0].priority, 7);
+    }
+}
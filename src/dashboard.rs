@@ -0,0 +1,23 @@
+//! The daemon's web dashboard: a page, a script and a stylesheet built into
+//! the binary and served at `/`.
+//!
+//! The assets hold no data of their own. The page polls `/status` and
+//! `/jobs` like any other API client, asking for a client token when the
+//! daemon has clients, so it shows exactly what that token may see.
+
+use crate::http::Response;
+
+const INDEX_HTML: &str = include_str!("dashboard/index.html");
+const DASHBOARD_JS: &str = include_str!("dashboard/dashboard.js");
+const DASHBOARD_CSS: &str = include_str!("dashboard/dashboard.css");
+
+/// The dashboard asset at `path`, if there is one.
+pub fn asset(path: &str) -> Option<Response> {
+    let (content_type, body) = match path {
+        "/" | "/index.html" => ("text/html; charset=utf-8", INDEX_HTML),
+        "/dashboard.js" => ("text/javascript; charset=utf-8", DASHBOARD_JS),
+        "/dashboard.css" => ("text/css; charset=utf-8", DASHBOARD_CSS),
+        _ => return None,
+    };
+    Some(Response { status: 200, content_type, body: body.to_string() })
+}
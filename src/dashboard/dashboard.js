@@ -0,0 +1,140 @@
+// Polls the daemon's API and renders its jobs. Everything is drawn with
+// textContent, since job names come from API clients.
+"use strict";
+
+const POLL_INTERVAL_MS = 2000;
+const TOKEN_KEY = "doppler-keygen-token";
+const SPARK = "▁▂▃▄▅▆▇█";
+
+const $ = (id) => document.getElementById(id);
+
+function formatCount(n) {
+  const units = [[1e12, "T"], [1e9, "B"], [1e6, "M"], [1e3, "K"]];
+  for (const [size, unit] of units) {
+    if (n >= size) return (n / size).toFixed(1) + unit;
+  }
+  return String(Math.round(n));
+}
+
+function formatDuration(secs) {
+  if (secs === null || secs === undefined || !isFinite(secs)) return "";
+  secs = Math.round(secs);
+  if (secs < 60) return secs + "s";
+  if (secs < 3600) return Math.floor(secs / 60) + "m " + (secs % 60) + "s";
+  if (secs < 86400) return Math.floor(secs / 3600) + "h " + Math.floor((secs % 3600) / 60) + "m";
+  if (secs < 86400 * 365) return Math.floor(secs / 86400) + "d " + Math.floor((secs % 86400) / 3600) + "h";
+  return (secs / (86400 * 365)).toFixed(1) + " years";
+}
+
+function sparkline(rates) {
+  if (!rates || rates.length === 0) return "";
+  const max = Math.max(...rates, 1);
+  return rates.map((r) => SPARK[Math.min(SPARK.length - 1, Math.floor((r / max) * SPARK.length))]).join("");
+}
+
+function cell(row, text, className) {
+  const td = row.insertCell();
+  td.textContent = text;
+  if (className) td.className = className;
+  return td;
+}
+
+async function api(path) {
+  const headers = {};
+  const token = sessionStorage.getItem(TOKEN_KEY);
+  if (token) headers.Authorization = "Bearer " + token;
+  const response = await fetch(path, { headers });
+  const body = await response.json();
+  if (!response.ok) {
+    const error = new Error(body.error || response.statusText);
+    error.status = response.status;
+    throw error;
+  }
+  return body;
+}
+
+function renderStatus(status) {
+  $("rate").textContent = formatCount(status.rate) + " keys/s";
+  $("sparkline").textContent = sparkline(status.rate_history);
+  $("attempts").textContent = formatCount(status.attempts);
+  $("keys-found").textContent = status.keys_found;
+  $("elapsed").textContent = formatDuration(status.elapsed_secs);
+  const counts = Object.entries(status.jobs).map(([state, n]) => n + " " + state);
+  $("job-counts").textContent = counts.length ? counts.join(", ") : "none";
+}
+
+function renderJobs(jobs) {
+  const showFinished = $("show-finished").checked;
+  const shown = jobs.filter((job) => showFinished || ["queued", "running", "paused"].includes(job.state));
+  const tbody = $("jobs");
+  tbody.replaceChildren();
+  for (const job of shown) {
+    const row = tbody.insertRow();
+    cell(row, job.name ? job.id + " (" + job.name + ")" : String(job.id));
+    cell(row, job.client || "");
+    cell(row, job.priority);
+    const state = row.insertCell();
+    const badge = document.createElement("span");
+    badge.className = "state " + job.state;
+    badge.textContent = job.state;
+    state.append(badge);
+    cell(row, job.patterns.map((p) => p.pattern + " " + p.found + "/" + p.count).join(", "));
+    cell(row, job.found + "/" + job.requested);
+    cell(row, formatDuration(job.run_secs));
+    cell(row, formatDuration(job.eta_secs));
+  }
+  $("no-jobs").hidden = shown.length > 0;
+
+  const keys = $("keys");
+  keys.replaceChildren();
+  let found = 0;
+  for (const job of jobs) {
+    for (const key of job.keys) {
+      const row = keys.insertRow();
+      cell(row, String(job.id));
+      cell(row, key.pubkey, "pubkey");
+      cell(row, key.pattern);
+      cell(row, key.segment);
+      found += 1;
+    }
+  }
+  $("no-keys").hidden = found > 0;
+}
+
+function showLogin(message) {
+  $("dashboard").hidden = true;
+  $("login").hidden = false;
+  $("login-error").textContent = message || "";
+}
+
+async function refresh() {
+  try {
+    const [status, jobs] = await Promise.all([api("/status"), api("/jobs")]);
+    $("login").hidden = true;
+    $("dashboard").hidden = false;
+    renderStatus(status);
+    renderJobs(jobs);
+    $("updated").textContent = "Updated " + new Date().toLocaleTimeString();
+  } catch (error) {
+    if (error.status === 401) {
+      const rejected = sessionStorage.getItem(TOKEN_KEY) !== null;
+      sessionStorage.removeItem(TOKEN_KEY);
+      showLogin(rejected ? error.message : "");
+      return;
+    }
+    $("updated").textContent = "Update failed: " + error.message;
+  }
+}
+
+$("login").addEventListener("submit", (event) => {
+  event.preventDefault();
+  sessionStorage.setItem(TOKEN_KEY, $("token").value.trim());
+  $("token").value = "";
+  refresh();
+});
+$("show-finished").addEventListener("change", refresh);
+
+refresh();
+setInterval(() => {
+  if ($("login").hidden) refresh();
+}, POLL_INTERVAL_MS);
@@ -0,0 +1,135 @@
+//! Addresses derived from a candidate key.
+//!
+//! Some accounts don't live at a keypair's own address but at one derived
+//! from it, e.g. a Squads multisig lives at a PDA seeded with the key that
+//! created it. With a `Derivation` the search matches the derived address
+//! instead of the candidate's public key.
+
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The Squads v4 multisig program, `SQDS4ep65T869zMMBKyuUq6aD6EgTu8psMjkvj52pCf`.
+pub const SQUADS_PROGRAM_ID: [u8; 32] = [
+    0x06, 0x81, 0xc4, 0xce, 0x47, 0xe2, 0x23, 0x68, 0xb8, 0xb1, 0x55, 0x5e, 0xc8, 0x87, 0xaf, 0x09,
+    0x2e, 0xfc, 0x7e, 0xfb, 0xb6, 0x6c, 0xa3, 0xf5, 0x2f, 0xbf, 0x68, 0xd4, 0xac, 0x9c, 0xb7, 0xa8,
+];
+
+/// The stake program, `Stake11111111111111111111111111111111111111`.
+pub const STAKE_PROGRAM_ID: [u8; 32] = [
+    0x06, 0xa1, 0xd8, 0x17, 0x91, 0x37, 0x54, 0x2a, 0x98, 0x34, 0x37, 0xbd, 0xfe, 0x2a, 0x7a, 0xb2,
+    0x55, 0x7f, 0x53, 0x5c, 0x8a, 0x78, 0x72, 0x2b, 0x68, 0xa4, 0x9d, 0xc0, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// The address lookup table program, `AddressLookupTab1e1111111111111111111111111`.
+pub const ADDRESS_LOOKUP_TABLE_PROGRAM_ID: [u8; 32] = [
+    0x02, 0x77, 0xa6, 0xaf, 0x97, 0x33, 0x9b, 0x7a, 0xc8, 0x8d, 0x18, 0x92, 0xc9, 0x04, 0x46, 0xf5,
+    0x00, 0x02, 0x30, 0x92, 0x66, 0xf6, 0x2e, 0x53, 0xc1, 0x18, 0x24, 0x49, 0x82, 0x00, 0x00, 0x00,
+];
+
+/// The system program, which owns durable nonce accounts.
+pub const SYSTEM_PROGRAM_ID: [u8; 32] = [0; 32];
+
+/// Longest seed `create_with_seed` accepts, in bytes.
+pub const MAX_SEED_LEN: usize = 32;
+
+/// Seed prefix of every Squads v4 account.
+const SQUADS_SEED_PREFIX: &[u8] = b"multisig";
+
+/// Marker appended to the seeds when hashing a program derived address.
+const PDA_MARKER: &[u8] = b"ProgramDerivedAddress";
+
+/// An address derived from each candidate's public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Derivation {
+    /// The Squads v4 multisig created with the candidate as its create key
+    Squads,
+}
+
+impl Derivation {
+    pub fn parse(value: &str) -> Result<Derivation, String> {
+        match value {
+            "squads" => Ok(Derivation::Squads),
+            _ => Err(format!("Unknown derivation '{value}' (expected squads)")),
+        }
+    }
+
+    /// Derives the address for the candidate key `pubkey`, or `None` in the
+    /// vanishingly rare case that no bump yields an off-curve address.
+    pub fn derive(&self, pubkey: &[u8; 32]) -> Option<[u8; 32]> {
+        match self {
+            Derivation::Squads => squads_multisig(pubkey).map(|(address, _)| address),
+        }
+    }
+
+    /// What the derived address is, for reports.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Derivation::Squads => "Squads multisig",
+        }
+    }
+}
+
+/// Finds the program derived address for `seeds` under `program_id` and its
+/// bump seed, like `Pubkey::find_program_address`.
+pub fn find_program_address(seeds: &[&[u8]], program_id: &[u8; 32]) -> Option<([u8; 32], u8)> {
+    (0..=u8::MAX).rev().find_map(|bump| {
+        let mut hasher = Sha256::new();
+        for seed in seeds {
+            hasher.update(seed);
+        }
+        hasher.update([bump]);
+        hasher.update(program_id);
+        hasher.update(PDA_MARKER);
+        let address: [u8; 32] = hasher.finalize().into();
+
+        // A PDA must not be a valid Ed25519 public key
+        CompressedEdwardsY(address).decompress().is_none().then_some((address, bump))
+    })
+}
+
+/// The address of the account `base` creates with `seed` for `owner`, like
+/// `Pubkey::create_with_seed`. `seed` must be at most `MAX_SEED_LEN` bytes.
+pub fn create_with_seed(base: &[u8; 32], seed: &[u8], owner: &[u8; 32]) -> [u8; 32] {
+    Sha256::new().chain_update(base).chain_update(seed).chain_update(owner).finalize().into()
+}
+
+/// The Squads v4 multisig account created with `create_key`.
+pub fn squads_multisig(create_key: &[u8; 32]) -> Option<([u8; 32], u8)> {
+    find_program_address(&[SQUADS_SEED_PREFIX, SQUADS_SEED_PREFIX, create_key], &SQUADS_PROGRAM_ID)
+}
+
+/// The Squads v4 vault with `index` belonging to `multisig`, which is
+/// where the multisig's funds are held.
+pub fn squads_vault(multisig: &[u8; 32], index: u8) -> Option<([u8; 32], u8)> {
+    find_program_address(&[SQUADS_SEED_PREFIX, multisig, b"vault", &[index]], &SQUADS_PROGRAM_ID)
+}
+
+/// The address lookup table `authority` creates with `recent_slot`.
+pub fn lookup_table_address(authority: &[u8; 32], recent_slot: u64) -> Option<([u8; 32], u8)> {
+    find_program_address(&[authority, &recent_slot.to_le_bytes()], &ADDRESS_LOOKUP_TABLE_PROGRAM_ID)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(encoded: &str) -> [u8; 32] {
+        bs58::decode(encoded).into_vec().unwrap().try_into().unwrap()
+    }
+
+    /// Addresses from the Squads v4 SDK's `get_multisig_pda` and
+    /// `get_vault_pda` for the create key `[7; 32]`.
+    #[test]
+    fn squads_addresses_match_the_sdk() {
+        let create_key = [7; 32];
+        assert_eq!(bs58::encode(create_key).into_string(), "US517G5965aydkZ46HS38QLi7UQiSojurfbQfKCELFx");
+
+        let multisig = squads_multisig(&create_key).unwrap();
+        assert_eq!(multisig, (address("EL91oYPRjUKLnFif8X7mizqHbEMZjwV1CWm9z6aaj1pT"), 254));
+        assert_eq!(squads_vault(&multisig.0, 0).unwrap(), (address("7W2knopF17mjUkwXLHbeh9CTZnEAayggHcohdRgKHAAP"), 253));
+        assert_eq!(squads_vault(&multisig.0, 1).unwrap(), (address("8DckwzZF7VWMw7tqf7qjX95Yu7ywoF8m2vHxnFhUYYe5"), 255));
+        assert_eq!(Derivation::Squads.derive(&create_key), Some(multisig.0));
+    }
+}
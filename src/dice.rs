@@ -0,0 +1,134 @@
+//! `from-dice`: a keypair from dice rolls or coin flips, for an offline root
+//! key whose origin can be audited.
+//!
+//! Real dice are never perfectly fair, so the rolls are not read as digits
+//! of the seed. Their entropy is estimated from the most frequent face
+//! (min-entropy), and once it reaches `TARGET_BITS` the rolls are condensed
+//! into the 32-byte seed with SHA-256. The same rolls always give the same
+//! keypair.
+
+use sha2::{Digest as _, Sha256};
+
+/// Domain separation for the seed.
+const SEED_CONTEXT: &[u8] = b"doppler-keygen dice v1";
+
+/// Entropy the rolls must carry: the size of an ed25519 seed.
+pub const TARGET_BITS: f64 = 256.0;
+
+/// Rolls of a die with `sides` faces, each from 1 to `sides`. Coin flips are
+/// rolls of a two-sided die, heads being 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rolls {
+    pub sides: u8,
+    pub values: Vec<u8>,
+}
+
+impl Rolls {
+    /// Parses rolls typed by the user. Coin flips are `H` and `T`. Up to nine
+    /// sides, every digit is a roll; with more, rolls are numbers separated
+    /// by spaces or commas.
+    pub fn parse(input: &str, sides: u8) -> Result<Rolls, String> {
+        let values = if sides == 2 {
+            input
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != ',')
+                .map(|c| match c.to_ascii_uppercase() {
+                    'H' => Ok(1),
+                    'T' => Ok(2),
+                    _ => Err(format!("Invalid coin flip '{c}' (expected H or T)")),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        } else if sides <= 9 {
+            input
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != ',')
+                .map(|c| match c.to_digit(10) {
+                    Some(value @ 1..) if value <= u32::from(sides) => Ok(value as u8),
+                    _ => Err(format!("Invalid roll '{c}' for a {sides}-sided die")),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            input
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|roll| !roll.is_empty())
+                .map(|roll| match roll.parse::<u8>() {
+                    Ok(value @ 1..) if value <= sides => Ok(value),
+                    _ => Err(format!("Invalid roll '{roll}' for a {sides}-sided die")),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        Ok(Rolls { sides, values })
+    }
+
+    /// Min-entropy of one roll, estimated from the most frequent face and
+    /// never more than a fair die's.
+    pub fn bits_per_roll(&self) -> f64 {
+        let mut counts = vec![0usize; usize::from(self.sides)];
+        for &value in &self.values {
+            counts[usize::from(value - 1)] += 1;
+        }
+        let most_frequent = counts.iter().max().copied().unwrap_or(0);
+        let p_max = (most_frequent as f64 / self.values.len().max(1) as f64).max(1.0 / f64::from(self.sides));
+        (1.0 / p_max).log2()
+    }
+
+    /// Estimated entropy of all the rolls together.
+    pub fn entropy_bits(&self) -> f64 {
+        self.values.len() as f64 * self.bits_per_roll()
+    }
+
+    /// About how many more rolls reach `TARGET_BITS` at the current
+    /// estimate.
+    pub fn rolls_needed(&self) -> usize {
+        let missing = (TARGET_BITS - self.entropy_bits()).max(0.0);
+        (missing / self.bits_per_roll()).ceil() as usize
+    }
+
+    /// SHA-256 of a context string, the number of sides and the rolls.
+    pub fn seed(&self) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(SEED_CONTEXT)
+            .chain_update([self.sides])
+            .chain_update(&self.values)
+            .finalize()
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_keypair::Keypair;
+    use solana_signer::Signer as _;
+
+    #[test]
+    fn estimates_min_entropy_from_the_most_frequent_face() {
+        let fair = Rolls::parse("123456", 6).unwrap();
+        assert!((fair.bits_per_roll() - 6f64.log2()).abs() < 1e-12);
+
+        // Face 1 comes up half the time, so each roll is worth one bit
+        // however the other faces fall.
+        let biased = Rolls::parse("1 1 2 3 1 4 1 2", 6).unwrap();
+        assert_eq!(biased.bits_per_roll(), 1.0);
+        assert_eq!(biased.entropy_bits(), 8.0);
+        assert_eq!(biased.rolls_needed(), 248);
+
+        let stuck = Rolls::parse("HHHH", 2).unwrap();
+        assert_eq!(stuck.bits_per_roll(), 0.0);
+    }
+
+    #[test]
+    fn condenses_fixed_rolls_into_a_fixed_seed() {
+        let rolls = Rolls::parse(&"123456".repeat(17), 6).unwrap();
+        assert_eq!(rolls.values.len(), 102);
+        assert!(rolls.entropy_bits() >= TARGET_BITS);
+        assert_eq!(rolls.rolls_needed(), 0);
+
+        let seed = rolls.seed();
+        assert_eq!(hex::encode(seed), "a7a1136e0d7991bc0d69e98631a915a228e461d57af44d168279e41869fe86c7");
+        assert_eq!(
+            Keypair::new_from_array(seed).pubkey().to_string(),
+            "3MYEdaLSQQPxStSN3sxGP8NZzsvUDyWtRTec1ct2kxoT"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! `diff`: compares two addresses segment by segment, e.g. before rotating
+//! the admin key of a program that embeds the old one as constants.
+
+use crate::codegen::{self, Segment};
+
+/// Lays out the eight bytes of a segment as `inspect` shows them, with
+/// `cell` giving the two characters for each byte.
+fn format_segment(cell: impl Fn(usize) -> String) -> String {
+    let low: Vec<String> = (0..4).map(&cell).collect();
+    let high: Vec<String> = (4..8).map(&cell).collect();
+    format!("{} | {}", low.join(" "), high.join(" "))
+}
+
+/// Builds the comparison of `a` and `b`.
+pub fn report(a: &[u8; 32], b: &[u8; 32]) -> String {
+    let mut report = String::new();
+    report.push_str(&format!("A: {}\n", bs58::encode(a).into_string()));
+    report.push_str(&format!("B: {}\n", bs58::encode(b).into_string()));
+    if a == b {
+        report.push_str("\nThe addresses are identical; the generated constants and code are unchanged\n");
+        return report;
+    }
+
+    let (a_segments, b_segments) = (codegen::segments(a), codegen::segments(b));
+    let mut changed = Vec::new();
+    let mut reshaped = Vec::new();
+    for segment in 0..4 {
+        let offset = segment * 8;
+        let (a_bytes, b_bytes) = (&a[offset..offset + 8], &b[offset..offset + 8]);
+        let name = format!("EXPECTED_ADMIN_KEY_{segment}");
+        let (a_segment, b_segment) = (a_segments[segment], b_segments[segment]);
+
+        report.push_str(&format!("\nSegment {segment} (bytes {}-{}):", offset, offset + 7));
+        if a_bytes == b_bytes {
+            report.push_str(&format!(" identical, {name} stays {}\n", a_segment.constant()));
+            continue;
+        }
+        report.push('\n');
+        report.push_str(&format!("  A: {}\n", format_segment(|i| format!("{:02x}", a_bytes[i]))));
+        report.push_str(&format!("  B: {}\n", format_segment(|i| format!("{:02x}", b_bytes[i]))));
+        let markers = format_segment(|i| if a_bytes[i] == b_bytes[i] { "  " } else { "^^" }.to_string());
+        report.push_str(&format!("     {}\n", markers.replace('|', " ").trim_end()));
+
+        changed.push(name.clone());
+        report.push_str(&format!("  {name}: {} -> {}\n", a_segment.constant(), b_segment.constant()));
+        match (a_segment, b_segment) {
+            (Segment::Imm32(_), Segment::Wide(_)) => {
+                reshaped.push(segment);
+                report.push_str("  No longer fits an immediate: the comparison needs an lddw\n");
+            }
+            (Segment::Wide(_), Segment::Imm32(_)) => {
+                reshaped.push(segment);
+                report.push_str("  Now fits an immediate: the comparison can drop its lddw\n");
+            }
+            _ => {}
+        }
+    }
+
+    let differing = a.iter().zip(b).filter(|(a, b)| a != b).count();
+    report.push_str(&format!("\nDiffering bytes: {differing} of 32\n"));
+    report.push_str(&format!("Assembly constants that change: {}\n", changed.join(", ")));
+    if reshaped.is_empty() {
+        report.push_str("Comparison code: unchanged; only the .equ values need updating\n");
+    } else {
+        let segments: Vec<String> = reshaped.iter().map(usize::to_string).collect();
+        report.push_str(&format!(
+            "Comparison code: changes for segment(s) {}; regenerate it with address\n",
+            segments.join(", ")
+        ));
+    }
+    report
+}
@@ -0,0 +1,214 @@
+//! Key lifetimes and the `due` command.
+//!
+//! `--lifetime 90d` on a grind or an import records, with each key in the
+//! results database, the date it should be rotated by. `due` reads the
+//! results databases under the directories it is given and lists the keys
+//! past that date, or coming up to it with `--within`, so the authority
+//! keys the tool creates don't outlive their purpose unnoticed. With
+//! `--webhook URL` the list is also POSTed as JSON, for a cron job to feed
+//! into chat or a ticket queue. Keys recorded without a lifetime are never
+//! due.
+
+use serde::Serialize;
+use serde_json::json;
+use std::fmt;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cli_error::CliError;
+use crate::migrate;
+use crate::redact;
+use crate::results::{ResultsDb, RESULTS_FILE};
+use crate::rotation;
+use crate::tags::KeyTags;
+
+/// How long posting the list may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Units a lifetime can be given in, largest first, with their length in
+/// seconds.
+const UNITS: [(char, u64); 4] = [('y', 365 * 86_400), ('w', 7 * 86_400), ('d', 86_400), ('h', 3600)];
+
+/// How long a key is meant to be used before it is rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lifetime(pub u64);
+
+impl Lifetime {
+    /// Parses a count of hours, days, weeks or years: `12h`, `90d`, `6w`,
+    /// `1y`.
+    pub fn parse(value: &str) -> Result<Lifetime, String> {
+        let invalid = || format!("Invalid lifetime '{value}' (expected a number of h, d, w or y, e.g. 90d)");
+        let unit = value.chars().last().ok_or_else(invalid)?;
+        let (_, seconds) = UNITS.iter().find(|(name, _)| *name == unit).ok_or_else(invalid)?;
+        match value[..value.len() - 1].parse::<u64>() {
+            Ok(count) if count > 0 => count.checked_mul(*seconds).map(Lifetime).ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Lifetime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unit, seconds) = UNITS
+            .iter()
+            .find(|(_, seconds)| self.0.is_multiple_of(*seconds))
+            .copied()
+            .unwrap_or(('h', 3600));
+        write!(f, "{}{unit}", self.0 / seconds)
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// A Unix timestamp as a UTC date.
+pub fn date(unix_seconds: u64) -> String {
+    let (year, month, day) = rotation::utc_date(unix_seconds);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// A key due for rotation.
+#[derive(Debug, Clone, Serialize)]
+pub struct DueKey {
+    pub pubkey: String,
+    /// The directory of the results database recording it
+    pub dir: PathBuf,
+    pub recorded_at: u64,
+    pub rotate_by: u64,
+    /// Days since `rotate_by`; negative while it is still ahead
+    pub overdue_days: i64,
+    pub files: Vec<PathBuf>,
+    #[serde(flatten)]
+    pub tags: KeyTags,
+}
+
+/// The keys recorded under `dirs` that are due for rotation by `now +
+/// within` seconds, the most overdue first.
+pub fn find(dirs: &[PathBuf], within: u64) -> Result<Vec<DueKey>, CliError> {
+    find_at(dirs, within, unix_time())
+}
+
+/// Like `find`, as of the Unix time `now`.
+fn find_at(dirs: &[PathBuf], within: u64, now: u64) -> Result<Vec<DueKey>, CliError> {
+    let mut due = Vec::new();
+    for dir in dirs {
+        let databases = migrate::files_under(dir)?
+            .into_iter()
+            .filter(|path| path.file_name().is_some_and(|name| name == RESULTS_FILE))
+            .filter_map(|path| path.parent().map(Path::to_path_buf));
+        for db_dir in databases {
+            for record in ResultsDb::open(&db_dir)?.load()? {
+                let Some(rotate_by) = record.rotate_by.filter(|&rotate_by| rotate_by <= now.saturating_add(within)) else {
+                    continue;
+                };
+                due.push(DueKey {
+                    pubkey: record.pubkey,
+                    dir: db_dir.clone(),
+                    recorded_at: record.recorded_at,
+                    rotate_by,
+                    overdue_days: (now as i64 - rotate_by as i64).div_euclid(86_400),
+                    files: record.files.iter().map(|file| db_dir.join(file)).collect(),
+                    tags: record.tags,
+                });
+            }
+        }
+    }
+    due.sort_by(|a, b| a.rotate_by.cmp(&b.rotate_by).then_with(|| a.pubkey.cmp(&b.pubkey)));
+    Ok(due)
+}
+
+/// The due keys as a list for the terminal.
+pub fn to_text(due: &[DueKey]) -> String {
+    let mut text = String::new();
+    for key in due {
+        let when = match key.overdue_days {
+            days if days >= 0 => format!("{days} day(s) overdue"),
+            days => format!("due within {} day(s)", -days),
+        };
+        let _ = write!(text, "{} in {}: rotate by {} ({when})", key.pubkey, key.dir.display(), date(key.rotate_by));
+        if !key.tags.is_empty() {
+            let _ = write!(text, " [{}]", key.tags.describe());
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// POSTs the due keys to `url` as JSON.
+pub fn post(url: &str, due: &[DueKey]) -> Result<(), String> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!("webhook '{url}' is not an http:// or https:// URL"));
+    }
+    let body = json!({
+        "event": "due",
+        "host": crate::audit::hostname(),
+        "keys": due,
+    });
+    ureq::AgentBuilder::new()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&redact::scrub(&body.to_string()))
+        .map(|_| ())
+        .map_err(|e| format!("webhook {url} failed: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::KeyRecord;
+    use std::fs;
+
+    const NOW: u64 = 1_750_000_000;
+    const DAY: u64 = 86_400;
+
+    /// Records a key in `dir`'s results database due at `rotate_by`.
+    fn record(db: &ResultsDb, seed: u8, rotate_by: Option<u64>) -> String {
+        let pubkey = bs58::encode([seed; 32]).into_string();
+        let mut record = KeyRecord::new(pubkey.clone(), &[seed; 32], "grind");
+        record.recorded_at = NOW - 90 * DAY;
+        record.rotate_by = rotate_by;
+        db.save(&record).unwrap();
+        pubkey
+    }
+
+    #[test]
+    fn keys_are_due_once_their_lifetime_has_passed() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_path_buf();
+        fs::create_dir_all(dir.join("2025-06-01")).unwrap();
+        let db = ResultsDb::open(&dir).unwrap();
+        let overdue = record(&db, 1, Some(NOW - 3 * DAY));
+        let at = record(&db, 2, Some(NOW));
+        let ahead = record(&db, 3, Some(NOW + 2 * DAY));
+        record(&db, 4, None);
+        // Databases in subdirectories are read too
+        let rotated = record(&ResultsDb::open(&dir.join("2025-06-01")).unwrap(), 5, Some(NOW - DAY - 1));
+
+        let due = find_at(std::slice::from_ref(&dir), 0, NOW).unwrap();
+        let pubkeys: Vec<_> = due.iter().map(|key| key.pubkey.as_str()).collect();
+        assert_eq!(pubkeys, [overdue.as_str(), rotated.as_str(), at.as_str()]);
+        assert_eq!(due.iter().map(|key| key.overdue_days).collect::<Vec<_>>(), [3, 1, 0]);
+        assert_eq!(due[1].dir, dir.join("2025-06-01"));
+
+        // One second short of the key still ahead, then on it
+        assert_eq!(find_at(std::slice::from_ref(&dir), 2 * DAY - 1, NOW).unwrap().len(), 3);
+        let due = find_at(std::slice::from_ref(&dir), 2 * DAY, NOW).unwrap();
+        assert_eq!(due.last().map(|key| (key.pubkey.as_str(), key.overdue_days)), Some((ahead.as_str(), -2)));
+        assert!(to_text(&due).contains("due within 2 day(s)"));
+    }
+
+    #[test]
+    fn lifetimes_parse_and_print_in_their_largest_unit() {
+        assert_eq!(Lifetime::parse("90d"), Ok(Lifetime(90 * DAY)));
+        assert_eq!(Lifetime::parse("12h").unwrap().to_string(), "12h");
+        assert_eq!(Lifetime::parse("14d").unwrap().to_string(), "2w");
+        assert!(Lifetime::parse("0d").is_err());
+        assert!(Lifetime::parse("90").is_err());
+        assert!(Lifetime::parse("d").is_err());
+        assert_eq!(date(NOW), "2025-06-15");
+    }
+}
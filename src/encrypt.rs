@@ -0,0 +1,109 @@
+//! age encryption of keypair files.
+//!
+//! With `--encrypt-to` every keypair file is encrypted to the given age
+//! recipients before it is written, so the grinding machine only ever holds
+//! ciphertext its operator cannot read. Any one recipient can decrypt with
+//! `age -d -i key.txt <pubkey>.json.age`.
+//!
+//! Without `--encrypt-to`, files are encrypted to the machine key the same
+//! way; see `machinekey`.
+
+use age::x25519;
+use std::fmt;
+use std::io::Write as _;
+
+use crate::cli_error::CliError;
+
+/// Extension appended to the names of encrypted keypair files.
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+/// The age public keys found keypairs are encrypted to.
+#[derive(Clone)]
+pub struct AgeRecipients(Vec<x25519::Recipient>);
+
+impl AgeRecipients {
+    /// Parses a comma-separated list of `age1...` public keys.
+    pub fn parse(value: &str) -> Result<AgeRecipients, String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|recipient| !recipient.is_empty())
+            .map(|recipient| {
+                recipient
+                    .parse::<x25519::Recipient>()
+                    .map_err(|e| format!("Invalid age recipient '{recipient}': {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|recipients| match recipients.is_empty() {
+                true => Err("--encrypt-to requires at least one age recipient".to_string()),
+                false => Ok(AgeRecipients(recipients)),
+            })
+    }
+
+    /// Encrypts `plaintext` so that any one of the recipients can decrypt it.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, CliError> {
+        let recipients = self.0.iter().map(|recipient| recipient as &dyn age::Recipient);
+        let encryptor = age::Encryptor::with_recipients(recipients).map_err(|e| CliError::Encrypt(e.to_string()))?;
+
+        let mut ciphertext = Vec::with_capacity(plaintext.len() + 256);
+        let mut writer = encryptor.wrap_output(&mut ciphertext).map_err(|e| CliError::Encrypt(e.to_string()))?;
+        writer
+            .write_all(plaintext)
+            .and_then(|()| writer.finish())
+            .map_err(|e| CliError::Encrypt(e.to_string()))?;
+        Ok(ciphertext)
+    }
+}
+
+impl From<Vec<x25519::Recipient>> for AgeRecipients {
+    fn from(recipients: Vec<x25519::Recipient>) -> AgeRecipients {
+        AgeRecipients(recipients)
+    }
+}
+
+impl fmt::Debug for AgeRecipients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.iter().map(ToString::to_string)).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read as _;
+
+    fn decrypt(ciphertext: &[u8], identity: &x25519::Identity) -> Result<Vec<u8>, String> {
+        let decryptor = age::Decryptor::new(ciphertext).map_err(|e| e.to_string())?;
+        let mut reader =
+            decryptor.decrypt(std::iter::once(identity as &dyn age::Identity)).map_err(|e| e.to_string())?;
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext).map_err(|e| e.to_string())?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn any_recipient_can_decrypt() {
+        let (alice, bob) = (x25519::Identity::generate(), x25519::Identity::generate());
+        let recipients = AgeRecipients::parse(&format!(" {}, {} ,", alice.to_public(), bob.to_public())).unwrap();
+        assert_eq!(recipients.0.len(), 2);
+
+        let ciphertext = recipients.encrypt(b"[1,2,3]").unwrap();
+        assert!(!ciphertext.windows(7).any(|window| window == b"[1,2,3]"));
+        assert_eq!(decrypt(&ciphertext, &alice).unwrap(), b"[1,2,3]");
+        assert_eq!(decrypt(&ciphertext, &bob).unwrap(), b"[1,2,3]");
+    }
+
+    #[test]
+    fn other_identities_cannot_decrypt() {
+        let recipients = AgeRecipients::from(vec![x25519::Identity::generate().to_public()]);
+        let ciphertext = recipients.encrypt(b"[1,2,3]").unwrap();
+        assert!(decrypt(&ciphertext, &x25519::Identity::generate()).is_err());
+    }
+
+    #[test]
+    fn recipients_must_be_age_public_keys() {
+        let error = AgeRecipients::parse("age1notakey").unwrap_err();
+        assert!(error.contains("Invalid age recipient 'age1notakey'"), "{error}");
+        assert!(AgeRecipients::parse(" , ").unwrap_err().contains("at least one age recipient"));
+    }
+}
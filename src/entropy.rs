@@ -0,0 +1,333 @@
+//! Where the seed material of a grind run comes from.
+//!
+//! By default every worker seeds its ChaCha20 RNG straight from the OS.
+//! Other sources are gathered once into a 32-byte master seed, and each
+//! worker's RNG is seeded with a hash of the master seed and a counter, so
+//! no two workers draw the same keys.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use sha2::{Digest as _, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+use crate::error::DopplerError;
+
+/// Domain separation for the master seed and the worker seeds.
+const MASTER_CONTEXT: &[u8] = b"doppler-keygen entropy v1";
+const WORKER_CONTEXT: &[u8] = b"doppler-keygen worker seed v1";
+const COMMITMENT_CONTEXT: &[u8] = b"doppler-keygen master seed commitment v1";
+
+/// The least a file may hold to be the only source: one byte per byte of
+/// seed, which is only enough if every byte is uniformly random.
+pub const MIN_FILE_BYTES: usize = 32;
+
+/// A source of seed material, as given to `--entropy`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum EntropySource {
+    /// The operating system's RNG, read by every worker
+    #[default]
+    Os,
+    /// The CPU's RDRAND instruction
+    Rdrand,
+    /// The contents of a file, such as a hardware RNG dump or dice rolls
+    File(PathBuf),
+    /// OS randomness, RDRAND where the CPU has it, and the contents of a
+    /// file if one is given
+    Mix(Option<PathBuf>),
+}
+
+impl EntropySource {
+    pub fn parse(value: &str) -> Result<EntropySource, String> {
+        match value {
+            "os" => Ok(EntropySource::Os),
+            "rdrand" => Ok(EntropySource::Rdrand),
+            "mix" => Ok(EntropySource::Mix(None)),
+            _ => match value.split_once(':') {
+                Some(("file", path)) if !path.is_empty() => Ok(EntropySource::File(PathBuf::from(path))),
+                Some(("mix", path)) if !path.is_empty() => Ok(EntropySource::Mix(Some(PathBuf::from(path)))),
+                _ => Err(format!("Unknown entropy source '{value}' (expected os, rdrand, file:PATH, mix or mix:PATH)")),
+            },
+        }
+    }
+}
+
+/// Seed material to be mixed into a master seed.
+pub struct Contribution {
+    /// `os`, `rdrand`, a file's path or a participant's name
+    pub name: String,
+    pub bytes: Vec<u8>,
+    /// Whether to report a SHA-256 fingerprint of the bytes. Generated
+    /// randomness is never fingerprinted.
+    pub fingerprint: bool,
+}
+
+impl Contribution {
+    /// 32 bytes from the OS random number generator.
+    pub fn os() -> Result<Contribution, DopplerError> {
+        let mut bytes = vec![0; 32];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| DopplerError::Entropy(format!("OS random number generator failed: {e}")))?;
+        Ok(Contribution { name: "os".to_string(), bytes, fingerprint: false })
+    }
+
+    /// 32 bytes from the CPU's RDRAND instruction.
+    pub fn rdrand() -> Result<Contribution, DopplerError> {
+        Ok(Contribution { name: "rdrand".to_string(), bytes: rdrand::fill()?, fingerprint: false })
+    }
+
+    /// The contents of the file at `path`, which must not be empty.
+    pub fn file(path: &Path) -> Result<Contribution, DopplerError> {
+        let bytes = fs::read(path).map_err(|e| DopplerError::Entropy(format!("failed to read {}: {e}", path.display())))?;
+        if bytes.is_empty() {
+            return Err(DopplerError::Entropy(format!("{} is empty", path.display())));
+        }
+        Ok(Contribution { name: path.display().to_string(), bytes, fingerprint: true })
+    }
+}
+
+/// One input to the master seed, as reported to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntropyInput {
+    pub name: String,
+    pub bytes: usize,
+    /// SHA-256 of the contribution, if it is fingerprinted, so it can be
+    /// matched to a transcript
+    pub sha256: Option<[u8; 32]>,
+}
+
+/// Seed material for the workers of a run. Cloning shares the same master
+/// seed and worker counter.
+#[derive(Clone, Default)]
+pub struct Entropy {
+    master: Option<Arc<MasterSeed>>,
+}
+
+struct MasterSeed {
+    /// Wiped when the last clone of the `Entropy` is dropped
+    seed: Zeroizing<[u8; 32]>,
+    inputs: Vec<EntropyInput>,
+    next_worker: AtomicU64,
+}
+
+impl fmt::Debug for Entropy {
+    /// Names the inputs but never shows the master seed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.inputs().iter().map(|input| &input.name)).finish()
+    }
+}
+
+impl Entropy {
+    /// Gathers the seed material of `source`.
+    pub fn gather(source: &EntropySource) -> Result<Entropy, DopplerError> {
+        let contributions = match source {
+            EntropySource::Os => return Ok(Entropy::default()),
+            EntropySource::Rdrand => vec![Contribution::rdrand()?],
+            EntropySource::File(path) => {
+                let contribution = Contribution::file(path)?;
+                if contribution.bytes.len() < MIN_FILE_BYTES {
+                    return Err(DopplerError::Entropy(format!(
+                        "{} holds {} bytes; a file used on its own needs at least {MIN_FILE_BYTES}, or use mix:{}",
+                        path.display(),
+                        contribution.bytes.len(),
+                        path.display()
+                    )));
+                }
+                vec![contribution]
+            }
+            EntropySource::Mix(path) => {
+                let mut contributions = vec![Contribution::os()?];
+                if rdrand::is_available() {
+                    contributions.push(Contribution::rdrand()?);
+                }
+                if let Some(path) = path {
+                    contributions.push(Contribution::file(path)?);
+                }
+                contributions
+            }
+        };
+        Ok(Entropy::mix(contributions))
+    }
+
+    /// Mixes `contributions` into a master seed: SHA-256 over a context
+    /// string and, for each contribution in order, its length as a
+    /// little-endian u64 and its SHA-256. Hashing each input with its length
+    /// keeps any input from shifting bytes into the next.
+    pub fn mix(contributions: Vec<Contribution>) -> Entropy {
+        let mut hasher = Sha256::new();
+        hasher.update(MASTER_CONTEXT);
+        let inputs = contributions
+            .into_iter()
+            .map(|contribution| {
+                let digest: [u8; 32] = Sha256::digest(&contribution.bytes).into();
+                hasher.update((contribution.bytes.len() as u64).to_le_bytes());
+                hasher.update(digest);
+                EntropyInput {
+                    name: contribution.name,
+                    bytes: contribution.bytes.len(),
+                    sha256: contribution.fingerprint.then_some(digest),
+                }
+            })
+            .collect();
+
+        Entropy {
+            master: Some(Arc::new(MasterSeed {
+                seed: Zeroizing::new(hasher.finalize().into()),
+                inputs,
+                next_worker: AtomicU64::new(0),
+            })),
+        }
+    }
+
+    /// The inputs of the master seed; empty when workers read the OS RNG.
+    pub fn inputs(&self) -> &[EntropyInput] {
+        self.master.as_ref().map_or(&[], |master| &master.inputs)
+    }
+
+    /// SHA-256 of a context string and the master seed. Publishing it lets
+    /// anyone holding every contribution check the mix without revealing
+    /// the seed itself.
+    pub fn commitment(&self) -> Option<[u8; 32]> {
+        self.master
+            .as_ref()
+            .map(|master| Sha256::new().chain_update(COMMITMENT_CONTEXT).chain_update(*master.seed).finalize().into())
+    }
+
+    /// A fresh seed for a worker's RNG. Seeds are drawn before the workers
+    /// start, so a failing OS RNG is reported rather than taking a worker
+    /// down.
+    pub fn worker_seed(&self) -> Result<[u8; 32], DopplerError> {
+        Ok(match &self.master {
+            Some(master) => {
+                let worker = master.next_worker.fetch_add(1, Ordering::Relaxed);
+                Sha256::new()
+                    .chain_update(WORKER_CONTEXT)
+                    .chain_update(*master.seed)
+                    .chain_update(worker.to_le_bytes())
+                    .finalize()
+                    .into()
+            }
+            None => {
+                let mut seed = [0; 32];
+                // A failing OS RNG leaves nothing safe to generate keys from
+                getrandom::getrandom(&mut seed)
+                    .map_err(|e| DopplerError::Entropy(format!("OS random number generator failed: {e}")))?;
+                seed
+            }
+        })
+    }
+}
+
+mod rdrand {
+    use crate::error::DopplerError;
+
+    /// Intel recommends giving up after this many failed attempts in a row.
+    #[cfg(target_arch = "x86_64")]
+    const RETRIES: usize = 10;
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn is_available() -> bool {
+        std::arch::is_x86_feature_detected!("rdrand")
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn is_available() -> bool {
+        false
+    }
+
+    /// Reads 32 bytes from RDRAND.
+    #[cfg(target_arch = "x86_64")]
+    pub fn fill() -> Result<Vec<u8>, DopplerError> {
+        if !is_available() {
+            return Err(DopplerError::Entropy("this CPU has no RDRAND instruction".to_string()));
+        }
+        let mut bytes = Vec::with_capacity(32);
+        for _ in 0..4 {
+            // SAFETY: RDRAND support was checked above
+            let word = (0..RETRIES).find_map(|_| unsafe { step() });
+            let word = word.ok_or_else(|| DopplerError::Entropy("RDRAND kept failing".to_string()))?;
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn fill() -> Result<Vec<u8>, DopplerError> {
+        Err(DopplerError::Entropy("RDRAND is only available on x86_64".to_string()))
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "rdrand")]
+    unsafe fn step() -> Option<u64> {
+        let mut word = 0;
+        (core::arch::x86_64::_rdrand64_step(&mut word) == 1).then_some(word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contribution(name: &str, bytes: &[u8]) -> Contribution {
+        Contribution { name: name.to_string(), bytes: bytes.to_vec(), fingerprint: true }
+    }
+
+    #[test]
+    fn every_worker_gets_its_own_seed() {
+        let entropy = Entropy::mix(vec![contribution("dice", b"3141592653")]);
+        let shared = entropy.clone();
+        let seeds = [entropy.worker_seed().unwrap(), shared.worker_seed().unwrap(), entropy.worker_seed().unwrap()];
+        // Clones share the worker counter, so they never hand out a seed twice
+        assert_ne!(seeds[0], seeds[1]);
+        assert_ne!(seeds[1], seeds[2]);
+        assert_ne!(seeds[0], seeds[2]);
+
+        // The same inputs give the same seeds, in the same order
+        let again = Entropy::mix(vec![contribution("dice", b"3141592653")]);
+        assert_eq!(again.worker_seed().unwrap(), seeds[0]);
+        assert_ne!(Entropy::default().worker_seed().unwrap(), Entropy::default().worker_seed().unwrap());
+    }
+
+    #[test]
+    fn the_commitment_depends_only_on_the_inputs() {
+        let commitment = |parts: &[&[u8]]| {
+            let contributions = parts.iter().map(|bytes| contribution("part", bytes)).collect();
+            Entropy::mix(contributions).commitment().unwrap()
+        };
+        assert_eq!(commitment(&[b"ab", b"c"]), commitment(&[b"ab", b"c"]));
+        // Neither the order nor the split between inputs can be changed
+        assert_ne!(commitment(&[b"ab", b"c"]), commitment(&[b"c", b"ab"]));
+        assert_ne!(commitment(&[b"ab", b"c"]), commitment(&[b"a", b"bc"]));
+        // Drawing seeds doesn't change it
+        let entropy = Entropy::mix(vec![contribution("part", b"abc")]);
+        let before = entropy.commitment();
+        entropy.worker_seed().unwrap();
+        assert_eq!(entropy.commitment(), before);
+        assert_eq!(Entropy::default().commitment(), None);
+    }
+
+    #[test]
+    fn a_file_on_its_own_needs_a_full_seeds_worth() {
+        let dir = tempfile::tempdir().unwrap();
+        let short = dir.path().join("short.bin");
+        fs::write(&short, [7; MIN_FILE_BYTES - 1]).unwrap();
+        let full = dir.path().join("full.bin");
+        fs::write(&full, [7; MIN_FILE_BYTES]).unwrap();
+        let empty = dir.path().join("empty.bin");
+        fs::write(&empty, []).unwrap();
+
+        let error = Entropy::gather(&EntropySource::File(short.clone())).unwrap_err().to_string();
+        assert!(error.contains("holds 31 bytes; a file used on its own needs at least 32"), "{error}");
+        let entropy = Entropy::gather(&EntropySource::File(full)).unwrap();
+        assert_eq!(entropy.inputs()[0].bytes, MIN_FILE_BYTES);
+        assert_eq!(entropy.inputs()[0].sha256, Some(Sha256::digest([7; MIN_FILE_BYTES]).into()));
+        // Mixed with the OS RNG, any non-empty file will do
+        let entropy = Entropy::gather(&EntropySource::Mix(Some(short))).unwrap();
+        assert_eq!(entropy.inputs()[0].name, "os");
+        assert_eq!(entropy.inputs()[0].sha256, None);
+        assert_eq!(entropy.inputs().last().unwrap().bytes, MIN_FILE_BYTES - 1);
+        assert!(Entropy::gather(&EntropySource::Mix(Some(empty))).unwrap_err().to_string().contains("is empty"));
+    }
+}
@@ -0,0 +1,28 @@
+//! Errors surfaced by grind runs: pattern and constraint validation, entropy,
+//! and the worker threads.
+
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DopplerError {
+    #[error("failed to gather entropy: {0}")]
+    Entropy(String),
+
+    #[error("failed to spawn thread: {0}")]
+    SpawnThread(io::Error),
+
+    #[error("failed to build thread pool: {0}")]
+    ThreadPool(String),
+
+    #[error("worker thread {0} panicked")]
+    WorkerPanicked(usize),
+
+    #[error("every worker was retired after repeated panics before the run finished")]
+    WorkersRetired,
+
+    #[error("invalid vanity pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("invalid search constraint: {0}")]
+    InvalidConstraint(String),
+}
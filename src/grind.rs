@@ -0,0 +1,1452 @@
+//! The grind engine behind `grind` and the commands built on it: running
+//! single, batch and near searches, saving and reporting the keys they find,
+//! and the progress and summary output of a run.
+//!
+//! `GrindOptions` is the resolved form of a command line; the commands in
+//! `commands` parse their arguments into it and hand it here.
+
+use core::ops::ControlFlow;
+use core::sync::atomic::Ordering;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use doppler_keygen::batch::{self, BatchHooks, BatchMatch, BatchResult, FilterCallback, PatternStats};
+use doppler_keygen::constraints::Constraints;
+use doppler_keygen::derive::{self, Derivation};
+use doppler_keygen::entropy::Entropy;
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::grinder::GrindConfig;
+use doppler_keygen::imm32;
+use doppler_keygen::pattern::{BatchPattern, VanityPattern};
+use doppler_keygen::progress::{self, ProgressSnapshot};
+use doppler_keygen::scheduler::{self, Candidate, Scheduler, WorkerConfig};
+use doppler_keygen::shutdown::ShutdownToken;
+use doppler_keygen::stats::{self, StatsCollector, StatsSummary};
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+
+use crate::audit::AuditLog;
+use crate::bench;
+use crate::chat::Notifiers;
+use crate::cli_error::CliError;
+use crate::config::{self, Config};
+use crate::due::Lifetime;
+use crate::encrypt::{self, AgeRecipients};
+use crate::handoff::{self, Handoff};
+use crate::index::KeyIndex;
+use crate::job::JobStatus;
+use crate::kms::{self, KmsWrapper};
+use crate::near::{Closest, NearKey, NearSearch};
+use crate::notify::RunReport;
+use crate::pkcs11::Pkcs11Sink;
+use crate::plugin::Plugin;
+use crate::predicate::{Predicate, Verdict};
+use crate::publish::Publishers;
+use crate::qr;
+use crate::redact;
+use crate::results::{KeyRecord, ResultsDb};
+use crate::rotation::Rotation;
+use crate::rpc::{self, RpcClient};
+use crate::secretfile;
+use crate::shred;
+use crate::sinks::{Sink, Sinks};
+use crate::split::{self, SplitSpec};
+use crate::tags::{self, KeyTags};
+use crate::targets::TargetSet;
+use crate::vault::VaultSink;
+use crate::webhook::Webhooks;
+
+/// Searches expected to take longer than this ask for confirmation first.
+const CONFIRM_THRESHOLD: Duration = Duration::from_secs(60 * 60);
+
+/// File in the output directory that collects public keys and matched
+/// segments under `--no-save-secret`.
+pub const PUBKEY_RECORD_FILE: &str = "pubkeys.txt";
+
+/// SOL requested for each key with `--airdrop`.
+const AIRDROP_SOL: u64 = 1;
+
+/// How long to wait for an airdrop to be confirmed.
+const AIRDROP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often `grind --watch` checks the config file for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long the key rate is sampled for the expected-time estimate.
+const RATE_SAMPLE: Duration = Duration::from_secs(1);
+
+/// File format used when saving a found keypair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// JSON array of the 64 keypair bytes, as written by `solana-keygen`
+    Json,
+    /// Base58 string of the 64 keypair bytes, as imported by most wallets
+    Bs58,
+    /// Raw 64 keypair bytes
+    Bin,
+    /// Hex string of the 32-byte secret seed
+    Seed,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Result<OutputFormat, String> {
+        match value {
+            "json" => Ok(OutputFormat::Json),
+            "bs58" => Ok(OutputFormat::Bs58),
+            "bin" => Ok(OutputFormat::Bin),
+            "seed" => Ok(OutputFormat::Seed),
+            _ => Err(format!("Unknown output format '{value}' (expected json, bs58, bin or seed)")),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Bs58 => "bs58",
+            OutputFormat::Bin => "bin",
+            OutputFormat::Seed => "seed",
+        }
+    }
+
+    pub fn encode(self, keypair: &Keypair) -> Vec<u8> {
+        match self {
+            OutputFormat::Json => format!(
+                "[{}]",
+                keypair
+                    .to_bytes()
+                    .iter()
+                    .map(std::string::ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )
+            .into_bytes(),
+            OutputFormat::Bs58 => keypair.to_base58_string().into_bytes(),
+            OutputFormat::Bin => keypair.to_bytes().to_vec(),
+            OutputFormat::Seed => hex::encode(keypair.secret_bytes()).into_bytes(),
+        }
+    }
+}
+
+/// Prints the details of a matching keypair and saves it to disk.
+pub fn report_match(
+    keypair: &Keypair,
+    segment: usize,
+    thread_id: usize,
+    pattern: Option<&VanityPattern>,
+    options: &GrindOptions,
+) -> Result<(), CliError> {
+    redact::register(keypair);
+    // With a derivation the matched segment is in the derived address
+    let pubkey_bytes = match options.derivation {
+        Some(derivation) => {
+            let address = derivation.derive(&keypair.pubkey().to_bytes()).expect("matched keys have a derived address");
+            println!("Thread: {thread_id}");
+            println!("Create Key (base58): {}", keypair.pubkey());
+            println!("{}: {}", derivation.describe(), hex::encode(address));
+            println!("{} (base58): {}", derivation.describe(), bs58::encode(address).into_string());
+            if derivation == Derivation::Squads {
+                if let Some((vault, _)) = derive::squads_vault(&address, 0) {
+                    println!("Squads vault 0 (base58): {}", bs58::encode(vault).into_string());
+                }
+            }
+            address
+        }
+        None => {
+            let pubkey_bytes = keypair.pubkey().to_bytes();
+            println!("Thread: {thread_id}");
+            println!("Public Key: {}", hex::encode(pubkey_bytes));
+            println!("Public Key (base58): {}", keypair.pubkey());
+            pubkey_bytes
+        }
+    };
+    let address = bs58::encode(pubkey_bytes).into_string();
+
+    // Display which segment matched
+    let offset = segment * 8;
+    println!("Matched Segment: {} (bytes {}-{})", segment, offset, offset + 7);
+
+    // Extract and display the i32 value from the matched segment
+    let i32_value = i32::from_le_bytes([
+        pubkey_bytes[offset], pubkey_bytes[offset + 1],
+        pubkey_bytes[offset + 2], pubkey_bytes[offset + 3]
+    ]);
+    let i64_value = i32_value as i64;
+
+    // Display the matched segment bytes in hex
+    print!("Segment {} bytes (hex): ", segment);
+    for i in 0..8 {
+        print!("{:02x}", pubkey_bytes[offset + i]);
+        if i == 3 {
+            print!(" | ");
+        } else if i < 7 {
+            print!(" ");
+        }
+    }
+    println!();
+    println!("  i32 value: {} (0x{:08x})", i32_value, i32_value as u32);
+    println!("  i64 value: {} (0x{:016x})", i64_value, i64_value as u64);
+    if options.constraints.segment_ascii.is_some() {
+        let ascii: String = pubkey_bytes[offset..offset + 4]
+            .iter()
+            .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+            .collect();
+        println!("  ASCII: \"{ascii}\"");
+    }
+    println!();
+
+    if let Some(rpc) = &options.check_onchain {
+        check_onchain(rpc, &address);
+    }
+
+    // Refuse well-known addresses and keys this directory has already seen
+    if let Some(key_index) = &options.key_index {
+        key_index.check(&keypair.pubkey().to_string())?;
+    }
+
+    if options.qr {
+        qr::print(&address)?;
+        for path in qr::save(address.as_bytes(), &key_dir(options)?.join(&address))? {
+            println!("Address QR code saved to: {}", path.display());
+        }
+    }
+
+    if !options.save_secret {
+        // Research runs keep only the public key; the secret is dropped
+        // with the keypair
+        let path = options.output_dir.join(PUBKEY_RECORD_FILE);
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{} {segment}", keypair.pubkey()))
+            .map_err(|source| CliError::RecordPubkey { path: path.clone(), source })?;
+        println!("Public key recorded in: {}", path.display());
+        index_key(options, keypair)?;
+        record_result(options, keypair, &pubkey_bytes, Some(segment), pattern, &[])?;
+        run_plugin_sink(options, &pubkey_bytes, &address, segment);
+        record_saved_key(options, keypair, &address, segment, pattern);
+        return Ok(());
+    }
+
+    let files = save_keypair(keypair, Some(segment), options)?;
+    index_key(options, keypair)?;
+    if let Some(audit_log) = &options.audit_log {
+        audit_log.record(&keypair.pubkey().to_string())?;
+    }
+    record_result(options, keypair, &pubkey_bytes, Some(segment), pattern, &files)?;
+    write_handoff(options, keypair, &files)?;
+    if let Some(airdrop) = &options.airdrop {
+        request_airdrop(airdrop, keypair);
+    }
+    run_plugin_sink(options, &pubkey_bytes, &address, segment);
+    record_saved_key(options, keypair, &address, segment, pattern);
+
+    Ok(())
+}
+
+/// Adds a saved key to the output directory's index. Only a key that was
+/// written is indexed, so a failed save leaves nothing that would refuse it.
+fn index_key(options: &GrindOptions, keypair: &Keypair) -> Result<(), CliError> {
+    match &options.key_index {
+        Some(key_index) => key_index.insert(&keypair.pubkey().to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Lists the inputs of a run's master seed, with a fingerprint of each file
+/// so it can be matched against the file later.
+pub fn print_entropy(entropy: &Entropy) {
+    let inputs: Vec<String> = entropy
+        .inputs()
+        .iter()
+        .map(|input| match input.sha256 {
+            Some(sha256) => format!("{} ({} bytes, SHA-256 {})", input.name, input.bytes, hex::encode(sha256)),
+            None => format!("{} ({} bytes)", input.name, input.bytes),
+        })
+        .collect();
+    match inputs.as_slice() {
+        [] => println!("Entropy: OS random number generator"),
+        _ => println!("Entropy: {}", inputs.join(", ")),
+    }
+}
+
+/// Adds a saved key, the address it matched and the files holding it to the
+/// output directory's results database, and writes its metadata sidecar
+/// when the run is tagged.
+pub fn record_result(
+    options: &GrindOptions,
+    keypair: &Keypair,
+    address: &[u8; 32],
+    segment: Option<usize>,
+    pattern: Option<&VanityPattern>,
+    files: &[PathBuf],
+) -> Result<(), CliError> {
+    let mut record = KeyRecord::new(keypair.pubkey().to_string(), address, "grind");
+    record.matched_segment = segment;
+    record.pattern = pattern.map(ToString::to_string);
+    record.tags = options.tags.clone();
+    record.rotate_by = options.lifetime.map(|lifetime| record.recorded_at + lifetime.0);
+    if let Some(results) = &options.results {
+        record.files = files.iter().map(|file| results.relative(file)).collect();
+        results.save(&record)?;
+    }
+    if !options.tags.is_empty() && !files.is_empty() {
+        let path = tags::write_sidecar(&key_dir(options)?, &record)?;
+        println!("Metadata saved to: {}", path.display());
+    }
+    Ok(())
+}
+
+/// Writes the unsigned transaction handing over a program's upgrade
+/// authority for a saved key, with `--handoff`, and prints the Solana CLI
+/// command for it with `--emit-set-authority`.
+fn write_handoff(options: &GrindOptions, keypair: &Keypair, files: &[PathBuf]) -> Result<(), CliError> {
+    if let Some(handoff) = &options.handoff {
+        let path = handoff.write(&key_dir(options)?, &keypair.pubkey().to_bytes())?;
+        println!("Handoff transaction saved to: {}", path.display());
+    }
+    if let Some(set_authority) = &options.set_authority {
+        // The CLI can only read a plaintext JSON keypair file
+        let keypair_file = files
+            .iter()
+            .find(|file| file.extension().is_some_and(|extension| extension == OutputFormat::Json.extension()));
+        if keypair_file.is_none() {
+            eprintln!(
+                "Warning: no plaintext JSON keypair was saved for {}, so the command below names only its public key \
+                 and skips the CLI's check that the new authority can sign. Grind with --plaintext to get a command \
+                 that passes the keypair file.",
+                keypair.pubkey()
+            );
+        }
+        let command = handoff::set_authority_command(
+            &set_authority.program,
+            &keypair.pubkey().to_bytes(),
+            keypair_file.map(PathBuf::as_path),
+            set_authority.upgrade_authority.as_deref(),
+        );
+        println!("Set upgrade authority: {command}");
+    }
+    Ok(())
+}
+
+/// Adds a saved key to the summary of a `job` shard and the run report, and
+/// publishes and announces it.
+fn record_saved_key(options: &GrindOptions, keypair: &Keypair, address: &str, segment: usize, pattern: Option<&VanityPattern>) {
+    let derived = options.derivation.map(|_| address);
+    if let Some(publishers) = &options.publishers {
+        publishers.key(&keypair.pubkey().to_string(), derived, segment);
+    }
+    if let Some(notifiers) = &options.notifiers {
+        notifiers.key(keypair, address, segment, pattern);
+    }
+    if let Some(webhooks) = &options.webhooks {
+        webhooks.key(&keypair.pubkey().to_string(), address, segment);
+    }
+    if let Some(job) = &options.job {
+        job.record_key(keypair.pubkey().to_string(), derived.map(str::to_string), segment);
+    }
+    if let Some(report) = &options.report {
+        report.record_key(keypair.pubkey().to_string(), derived.map(str::to_string), segment);
+    }
+}
+
+/// Adds a run's final stats to the summary of a `job` shard and the run
+/// report.
+fn record_summary(options: &GrindOptions, summary: &StatsSummary) {
+    if let Some(job) = &options.job {
+        job.record_stats(summary);
+    }
+    if let Some(report) = &options.report {
+        report.record_stats(summary);
+    }
+}
+
+/// Passes a progress report on to the `job` status and the publishers.
+pub fn record_progress(options: &GrindOptions, snapshot: &ProgressSnapshot) {
+    if let Some(job) = &options.job {
+        job.record_progress(snapshot);
+    }
+    if let Some(publishers) = &options.publishers {
+        publishers.progress(snapshot);
+    }
+}
+
+/// Hands a saved key's address to the plugin sink, if there is one. The key
+/// is already saved, so failures are only reported.
+fn run_plugin_sink(options: &GrindOptions, address: &[u8; 32], encoded: &str, segment: usize) {
+    if let Some(plugin) = &options.plugin {
+        if let Err(e) = plugin.sink(address, encoded, segment, &options.output_dir) {
+            eprintln!("Warning: plugin sink failed: {e}");
+        }
+    }
+}
+
+/// How many files `save_keypair` and `report_match` write for each key.
+pub fn files_per_key(options: &GrindOptions) -> usize {
+    let secret_files = if !options.save_secret || options.vault.is_some() || options.pkcs11.is_some() {
+        0
+    } else if let Some(split) = options.split {
+        usize::from(split.shares)
+    } else {
+        // A QR code of an encrypted keypair is saved as PNG and SVG
+        options.output_formats.len() * if options.qr_secret { 3 } else { 1 }
+    };
+    let sidecar = usize::from(secret_files > 0 && !options.tags.is_empty());
+    secret_files + sidecar + usize::from(options.handoff.is_some()) + if options.qr { 2 } else { 0 }
+}
+
+/// The directory key files are saved to: the output directory, or the
+/// current period's subdirectory of it with `--rotate`.
+fn key_dir(options: &GrindOptions) -> Result<PathBuf, CliError> {
+    match &options.rotation {
+        Some(rotation) => rotation.dir(),
+        None => Ok(options.output_dir.clone()),
+    }
+}
+
+/// Stores a found keypair wherever the options send it: Vault, a PKCS#11
+/// token, Shamir shares, or keypair files in each requested format.
+/// Returns the files written.
+pub fn save_keypair(keypair: &Keypair, segment: Option<usize>, options: &GrindOptions) -> Result<Vec<PathBuf>, CliError> {
+    if let Some(vault) = &options.vault {
+        let location = vault.store(keypair, segment)?;
+        println!("Keypair stored in Vault at: {location}");
+        return Ok(Vec::new());
+    }
+
+    if let Some(pkcs11) = &options.pkcs11 {
+        let token = pkcs11.store(keypair)?;
+        println!("Keypair imported into PKCS#11 token '{token}' as {}", keypair.pubkey());
+        return Ok(Vec::new());
+    }
+
+    let dir = key_dir(options)?;
+    if let Some(split) = options.split {
+        // The secret only reaches the disk as shares
+        let shares = split::write_shares(keypair, split, &dir)?;
+        for path in &shares {
+            println!("Share saved to: {}", path.display());
+        }
+        return Ok(shares);
+    }
+
+    // Save keypair to file in each requested format
+    let mut files = Vec::new();
+    for format in &options.output_formats {
+        let filename = format!("{}.{}", keypair.pubkey(), format.extension());
+        let (filename, contents) = match (&options.encrypt_to, &options.kms) {
+            (Some(recipients), _) => (
+                format!("{filename}.{}", encrypt::ENCRYPTED_EXTENSION),
+                recipients.encrypt(&format.encode(keypair))?,
+            ),
+            (None, Some(kms)) => (
+                format!("{filename}.{}", kms::WRAPPED_EXTENSION),
+                kms.seal(&keypair.pubkey().to_string(), &format.encode(keypair))?,
+            ),
+            (None, None) => (filename, format.encode(keypair)),
+        };
+        let filename = dir.join(filename);
+        secretfile::write(&filename, &contents)
+            .map_err(|source| CliError::WriteKeypair { path: filename.clone(), source })?;
+        println!("Keypair saved to: {}", filename.display());
+        if options.qr_secret {
+            for path in qr::save(&contents, &filename)? {
+                println!("Encrypted keypair QR code saved to: {}", path.display());
+            }
+        }
+        files.push(filename);
+    }
+    Ok(files)
+}
+
+/// Warns if `address` already has an account or transaction history on the
+/// cluster behind `rpc`.
+pub fn check_onchain(rpc: &RpcClient, address: &str) {
+    let account = match rpc.get_account_info(address) {
+        Ok(account) => account,
+        Err(e) => {
+            eprintln!("Warning: could not check {address} on {}: {e}", rpc.url());
+            return;
+        }
+    };
+    if let Some(account) = account {
+        let executable = account.get("executable").and_then(serde_json::Value::as_bool).unwrap_or(false);
+        let lamports = account.get("lamports").and_then(serde_json::Value::as_u64).unwrap_or(0);
+        let owner = account.get("owner").and_then(serde_json::Value::as_str).unwrap_or("unknown");
+        eprintln!(
+            "⚠️  Warning: {address} already has {} on {} ({lamports} lamports, owner {owner})",
+            if executable { "a deployed program" } else { "an account" },
+            rpc.url()
+        );
+        return;
+    }
+    match rpc.has_history(address) {
+        Ok(true) => eprintln!("⚠️  Warning: {address} has transaction history on {}", rpc.url()),
+        Ok(false) => println!("On-chain check: {address} is unused on {}", rpc.url()),
+        Err(e) => eprintln!("Warning: could not check the history of {address} on {}: {e}", rpc.url()),
+    }
+}
+
+/// Funds a freshly saved key and waits for confirmation. Failures, such as
+/// faucet rate limits, are only reported: the key itself is already saved.
+pub fn request_airdrop(rpc: &RpcClient, keypair: &Keypair) {
+    let pubkey = keypair.pubkey().to_string();
+    println!("Requesting {AIRDROP_SOL} SOL airdrop from {}...", rpc.url());
+    let result = rpc
+        .request_airdrop(&pubkey, AIRDROP_SOL * rpc::LAMPORTS_PER_SOL)
+        .and_then(|signature| rpc.confirm_signature(&signature, AIRDROP_TIMEOUT).map(|()| signature));
+    match result {
+        Ok(signature) => println!("Airdrop confirmed: {signature}"),
+        Err(e) => eprintln!("Warning: airdrop to {pubkey} failed: {e}"),
+    }
+}
+
+/// Grinds for `count` imm32 compatible keys, or with no count, keeps saving
+/// keys until interrupted.
+pub fn grind_keys(count: Option<usize>, options: &GrindOptions) -> Result<Vec<Keypair>, CliError> {
+    println!("Doppler Keygen - Mining for 32-bit immediate value compatible keys...");
+    print_derivation(options);
+    println!("Pattern: Checking all 4 segments (bytes 0-7, 8-15, 16-23, 24-31)");
+    print_constraints(options);
+    println!("Each segment must form a valid 32-bit immediate with sign extension:");
+    println!("  - If bit 31 clear: bytes 4-7 of segment must be 0x00 (positive i32)");
+    println!("  - If bit 31 set:   bytes 4-7 of segment must be 0xFF (negative i32)");
+    match count {
+        Some(count) => println!("Target: {count} key(s)\n"),
+        None => println!("Target: unlimited, streaming keys until interrupted\n"),
+    }
+
+    let num_threads = options.num_threads;
+    println!("Using {num_threads} threads");
+
+    let keys_found = Arc::new(AtomicUsize::new(0));
+    let found_keypairs = Arc::new(Mutex::new(Vec::new()));
+    let stats = Arc::new(StatsCollector::new(num_threads));
+    let shutdown = ShutdownToken::new();
+
+    if count.is_none() {
+        stop_on_interrupt(&shutdown)?;
+    }
+    let progress_handle = spawn_progress_reporter(options, &stats, &shutdown, Arc::clone(&keys_found), count)?;
+
+    let check = {
+        let keys_found = Arc::clone(&keys_found);
+        let found_keypairs = Arc::clone(&found_keypairs);
+        let shutdown = shutdown.clone();
+        let options = options.clone();
+
+        move |thread_id: usize, candidate: Candidate| {
+            // Check all 4 segments of the 32-byte key for valid 32-bit immediate patterns
+            let Some(segment) = options.constraints.matching_segment(&candidate.address, candidate.imm32_mask) else {
+                return ControlFlow::Continue(());
+            };
+            let target = match &options.targets {
+                Some(targets) => {
+                    let address = bs58::encode(candidate.address).into_string();
+                    let Some(target) = targets.find(&address) else {
+                        return ControlFlow::Continue(());
+                    };
+                    Some(target.to_string())
+                }
+                None => None,
+            };
+            let Some(scores) = custom_scores(&options, &candidate.address) else {
+                return ControlFlow::Continue(());
+            };
+            let keypair = candidate.keypair();
+
+            // Found a match!
+            let key_number = keys_found.fetch_add(1, Ordering::Relaxed) + 1;
+
+            // Another worker may have claimed the last slot in the meantime
+            if count.is_some_and(|count| key_number > count) {
+                return ControlFlow::Break(());
+            }
+
+            println!("\n✅ FOUND MATCHING KEYPAIR #{}", format_found(key_number, count));
+            if let Some(target) = &target {
+                println!("Matched target: {target}");
+            }
+            for (source, score) in scores {
+                println!("{source} score: {score}");
+            }
+            if let Err(e) = report_match(&keypair, segment, thread_id, None, &options) {
+                // Give the slot back so the search carries on rather than aborting
+                eprintln!("Error: {}", redact::scrub(&e.to_string()));
+                eprintln!("Discarding keypair {} and continuing the search", keypair.pubkey());
+                keys_found.fetch_sub(1, Ordering::Relaxed);
+                return ControlFlow::Continue(());
+            }
+            // A stream never ends, so only the first key is kept for
+            // --install-as-default rather than every key found
+            let mut found_keypairs = found_keypairs.lock().unwrap_or_else(PoisonError::into_inner);
+            if options.save_secret && (count.is_some() || found_keypairs.is_empty()) {
+                found_keypairs.push(keypair);
+            }
+            drop(found_keypairs);
+
+            // Shut the run down once the last key has been saved
+            if count.is_some_and(|count| key_number >= count) {
+                shutdown.cancel();
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        }
+    };
+
+    let result = scheduler::run_workers(&options.workers(), &stats, &shutdown, check);
+    finish_progress_reporter(&shutdown, progress_handle);
+    result?;
+
+    let final_keys = keys_found.load(Ordering::Relaxed);
+
+    let summary = stats.summary();
+    record_summary(options, &summary);
+    print_summary(&format_found(final_keys, count), &summary);
+
+    Ok(take_found_keypairs(found_keypairs))
+}
+
+/// Grinds until interrupted, keeping the `top` keys whose addresses are
+/// closest to the target, then saves them closest first.
+pub fn grind_near(search: &NearSearch, options: &GrindOptions) -> Result<Vec<Keypair>, CliError> {
+    println!("Doppler Keygen - Mining for keys near {}...", bs58::encode(search.target).into_string());
+    print_derivation(options);
+    println!("Keeping the {} key(s) with the fewest differing {} until interrupted\n", search.top, search.distance.unit());
+    println!("Using {} threads", options.num_threads);
+
+    let closest = Arc::new(Closest::new(search.clone()));
+    let stats = Arc::new(StatsCollector::new(options.num_threads));
+    let shutdown = ShutdownToken::new();
+    stop_on_interrupt(&shutdown)?;
+
+    let progress_handle = {
+        let closest = Arc::clone(&closest);
+        let unit = search.distance.unit();
+        progress::spawn_reporter(&stats, &shutdown, options.progress_interval, Arc::new(AtomicUsize::new(0)), move |snapshot| {
+            let best = closest.best().map_or_else(|| "-".to_string(), |best| format!("{best} {unit}"));
+            println!("Progress: {} attempts | {:.0} keys/sec | Closest: {best}", snapshot.total_attempts, snapshot.rate);
+        })?
+    };
+
+    // With --shred-losers keys are saved as they enter the top, so the
+    // files of each key pushed out can be shredded
+    let saved = search.shred_losers.then(|| Arc::new(Mutex::new(HashMap::new())));
+    let check = {
+        let closest = Arc::clone(&closest);
+        let saved = saved.clone();
+        let options = options.clone();
+        let unit = search.distance.unit();
+        move |_thread_id: usize, candidate: Candidate| {
+            let offered = closest.offer(&candidate, |key, evicted| {
+                if let Some(saved) = &saved {
+                    save_near_key(key, evicted, &options, &mut saved.lock().unwrap_or_else(PoisonError::into_inner));
+                }
+            });
+            if let Some(distance) = offered {
+                println!("New closest: {} ({distance} {unit})", bs58::encode(candidate.address).into_string());
+            }
+            ControlFlow::Continue(())
+        }
+    };
+
+    let result = scheduler::run_workers(&options.workers(), &stats, &shutdown, check);
+    finish_progress_reporter(&shutdown, progress_handle);
+    result?;
+
+    let keys = Arc::try_unwrap(closest).map(Closest::into_keys).unwrap_or_default();
+    println!("\nTarget: {}", bs58::encode(search.target).into_string());
+    let mut keypairs = Vec::new();
+    for (rank, key) in keys.iter().enumerate() {
+        let keypair = Keypair::new_from_array(key.seed);
+        redact::register(&keypair);
+        println!("\n#{} {} ({} {})", rank + 1, bs58::encode(key.address).into_string(), key.distance, search.distance.unit());
+        if options.derivation.is_some() {
+            println!("Create Key (base58): {}", keypair.pubkey());
+        }
+        // A near key need not hold an imm32 segment; record the first one
+        // it does
+        let segment = imm32::first_segment(key.imm32_mask);
+        let files = match &saved {
+            Some(saved) => {
+                // Saved when it entered the top, unless that failed
+                let Some(files) = saved.lock().unwrap_or_else(PoisonError::into_inner).remove(&key.address) else {
+                    continue;
+                };
+                for path in &files {
+                    println!("Keypair saved to: {}", path.display());
+                }
+                files
+            }
+            None => {
+                if let Some(key_index) = &options.key_index {
+                    if let Err(e) = key_index.check(&keypair.pubkey().to_string()) {
+                        eprintln!("Error: {}", redact::scrub(&e.to_string()));
+                        continue;
+                    }
+                }
+                let files = save_keypair(&keypair, segment, options)?;
+                index_key(options, &keypair)?;
+                files
+            }
+        };
+        if let Some(audit_log) = &options.audit_log {
+            audit_log.record(&keypair.pubkey().to_string())?;
+        }
+        record_result(options, &keypair, &key.address, segment, None, &files)?;
+        write_handoff(options, &keypair, &files)?;
+        keypairs.push(keypair);
+    }
+
+    let summary = stats.summary();
+    record_summary(options, &summary);
+    print_summary(&keys.len().to_string(), &summary);
+    Ok(keypairs)
+}
+
+/// Saves a key that entered the top of a `--near --shred-losers` run and
+/// shreds the files of the key it pushed out. `saved` maps the address of
+/// each saved key to its files.
+fn save_near_key(key: &NearKey, evicted: Option<NearKey>, options: &GrindOptions, saved: &mut HashMap<[u8; 32], Vec<PathBuf>>) {
+    if let Some((evicted, files)) = evicted.and_then(|evicted| saved.remove(&evicted.address).map(|files| (evicted, files))) {
+        let mut shredded = true;
+        for path in files {
+            let qr_codes = qr::paths(&path).into_iter().filter(|qr_code| qr_code.exists());
+            for path in std::iter::once(path.clone()).chain(qr_codes) {
+                match shred::shred_file(&path) {
+                    Ok(()) => println!("Shredded {}", path.display()),
+                    Err(e) => {
+                        eprintln!("Error: {}", redact::scrub(&e.to_string()));
+                        shredded = false;
+                    }
+                }
+            }
+        }
+        // The index must not vouch for a key that no longer exists
+        if let (true, Some(key_index)) = (shredded, &options.key_index) {
+            let pubkey = Keypair::new_from_array(evicted.seed).pubkey().to_string();
+            if let Err(e) = key_index.remove(&pubkey) {
+                eprintln!("Error: {}", redact::scrub(&e.to_string()));
+            }
+        }
+    }
+
+    let keypair = Keypair::new_from_array(key.seed);
+    redact::register(&keypair);
+    if let Some(key_index) = &options.key_index {
+        if let Err(e) = key_index.check(&keypair.pubkey().to_string()) {
+            eprintln!("Error: {}", redact::scrub(&e.to_string()));
+            return;
+        }
+    }
+    match save_keypair(&keypair, imm32::first_segment(key.imm32_mask), options) {
+        Ok(files) => {
+            saved.insert(key.address, files);
+            if let Err(e) = index_key(options, &keypair) {
+                eprintln!("Error: {}", redact::scrub(&e.to_string()));
+            }
+        }
+        Err(e) => eprintln!("Error: {}", redact::scrub(&e.to_string())),
+    }
+}
+
+/// Parses a `solana-keygen grind` style pattern argument, e.g. `PREFIX:COUNT`
+/// for `--starts-with` or `PREFIX:SUFFIX:COUNT` for `--starts-and-ends-with`.
+pub fn parse_batch_pattern(flag: &str, value: &str) -> Result<BatchPattern, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    let expected_parts = if flag == "--starts-and-ends-with" { 3 } else { 2 };
+    if parts.len() != expected_parts || parts[..expected_parts - 1].iter().any(|p| p.is_empty()) {
+        return Err(if expected_parts == 3 {
+            format!("{flag} expects PREFIX:SUFFIX:COUNT, got '{value}'")
+        } else {
+            format!("{flag} expects PATTERN:COUNT, got '{value}'")
+        });
+    }
+
+    let count = parts[expected_parts - 1]
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid count in {flag} '{value}'"))?;
+    if count == 0 {
+        return Err(format!("Count in {flag} '{value}' must be at least 1"));
+    }
+
+    let pattern = match flag {
+        "--starts-with" => VanityPattern { prefix: Some(parts[0].to_string()), suffix: None },
+        "--ends-with" => VanityPattern { prefix: None, suffix: Some(parts[0].to_string()) },
+        _ => VanityPattern {
+            prefix: Some(parts[0].to_string()),
+            suffix: Some(parts[1].to_string()),
+        },
+    };
+
+    Ok(BatchPattern { pattern, count, found: 0, priority: 0, sink: None })
+}
+
+/// Parses `DOPPLER_PATTERNS`, a comma-separated list of patterns written as
+/// the flag name without dashes followed by its argument, e.g.
+/// `starts-with:ab:2,ends-with:xyz:1,starts-and-ends-with:ab:yz:1`.
+pub fn patterns_from_env() -> Result<Vec<BatchPattern>, String> {
+    let Some(value) = config::env_var("DOPPLER_PATTERNS") else {
+        return Ok(Vec::new());
+    };
+
+    value
+        .split(',')
+        .map(|entry| {
+            let entry = entry.trim();
+            parse_pattern_entry(entry).map_err(|e| format!("Invalid DOPPLER_PATTERNS entry '{entry}': {e}"))
+        })
+        .collect()
+}
+
+/// Parses one pattern written as the flag name without dashes followed by
+/// its argument, e.g. `starts-with:ab:2`.
+pub fn parse_pattern_entry(entry: &str) -> Result<BatchPattern, String> {
+    match entry.split_once(':') {
+        Some((kind @ ("starts-with" | "ends-with" | "starts-and-ends-with"), pattern)) => {
+            parse_batch_pattern(&format!("--{kind}"), pattern)
+        }
+        _ => Err("expected starts-with, ends-with or starts-and-ends-with".to_string()),
+    }
+}
+
+/// Grinds keys that are both imm32 compatible and satisfy one of the base58
+/// vanity patterns, finding `count` keys for each pattern. With `watch`,
+/// patterns are reloaded from that config file whenever it changes.
+pub fn vanity_keys_batch(
+    patterns: &[BatchPattern],
+    ignore_case: bool,
+    watch: Option<&Path>,
+    options: &GrindOptions,
+) -> Result<Vec<Keypair>, CliError> {
+    println!("Doppler Keygen - Mining for 32-bit immediate value compatible vanity keys...");
+    print_derivation(options);
+    println!("Pattern: Checking all 4 segments (bytes 0-7, 8-15, 16-23, 24-31) for a valid 32-bit immediate");
+    print_constraints(options);
+    println!(
+        "Vanity patterns{} (the highest priority, then the hardest, are checked first):",
+        if ignore_case { " (case-insensitive)" } else { "" }
+    );
+    // Each pattern's chance per attempt, for standalone estimates
+    let probabilities: Vec<f64> = patterns
+        .iter()
+        .map(|p| key_match_probability(options) * p.pattern.match_probability(ignore_case))
+        .collect();
+    for (batch_pattern, probability) in patterns.iter().zip(&probabilities) {
+        let priority = match batch_pattern.priority {
+            0 => String::new(),
+            priority => format!(", priority {priority}"),
+        };
+        println!(
+            "  - {}: {} key(s), ~{:.3e} attempts each{priority}",
+            batch_pattern.pattern,
+            batch_pattern.count,
+            1.0 / probability
+        );
+    }
+    println!();
+    println!("Using {} threads", options.num_threads);
+    if patterns.len() <= options.num_threads && patterns.windows(2).any(|pair| pair[0].priority != pair[1].priority) {
+        println!("Threads are shared out among the patterns by priority and rebalanced as patterns complete");
+    }
+
+    // A watched batch runs until interrupted
+    let shutdown = match watch {
+        Some(path) => {
+            println!("Watching {} for pattern changes; press Ctrl-C to stop", path.display());
+            let shutdown = ShutdownToken::new();
+            stop_on_interrupt(&shutdown)?;
+            Some(shutdown)
+        }
+        None => None,
+    };
+
+    let hooks = {
+        let options = options.clone();
+        let progress_options = options.clone();
+
+        BatchHooks {
+            on_progress: Some((
+                options.progress_interval,
+                Box::new(move |snapshot| {
+                    record_progress(&progress_options, &snapshot);
+                    let active = snapshot.patterns.iter().filter(|p| !p.removed);
+                    print_progress(&snapshot, Some(active.map(|p| p.requested).sum()));
+                    for pattern_stats in snapshot.patterns.iter().filter(|p| !p.removed && p.found < p.requested) {
+                        println!(
+                            "  {}: {}/{}{}, ETA {}",
+                            pattern_stats.pattern,
+                            pattern_stats.found,
+                            pattern_stats.requested,
+                            pattern_threads(pattern_stats),
+                            pattern_eta(pattern_stats, snapshot.rate, ignore_case, &progress_options)
+                        );
+                    }
+                }),
+            )),
+            filter: custom_filter(&options),
+            on_match: Some(Box::new(move |batch_match: &BatchMatch| save_batch_match(batch_match, &options))),
+            reload: watch.map(|path| (WATCH_INTERVAL, watch_patterns(path, patterns.to_vec(), ignore_case))),
+            shutdown,
+        }
+    };
+
+    let result = batch::vanity_keys_batch(patterns, &options.grind_config(ignore_case), hooks)?;
+    Ok(finish_batch(result, options))
+}
+
+/// The batch filter running the predicate script and plugin matcher, if the
+/// run has either, so they judge a key before it takes its pattern's slot.
+pub fn custom_filter(options: &GrindOptions) -> Option<FilterCallback> {
+    if options.predicate.is_none() && options.plugin.as_ref().is_none_or(|plugin| !plugin.has_match()) {
+        return None;
+    }
+    let options = options.clone();
+    Some(Box::new(move |address| custom_scores(&options, address)))
+}
+
+/// Saves a batch match, returning false to give its slot back.
+pub fn save_batch_match(batch_match: &BatchMatch, options: &GrindOptions) -> bool {
+    let key = &batch_match.key;
+    println!(
+        "\n✅ FOUND MATCHING KEYPAIR #{}/{} FOR PATTERN",
+        batch_match.pattern_number, batch_match.requested
+    );
+    println!("Vanity Pattern: {}", batch_match.pattern);
+    for (source, score) in &batch_match.scores {
+        println!("{source} score: {score}");
+    }
+    let sink = batch_match.sink.as_deref().and_then(|name| {
+        let sink = options.sinks.as_ref().and_then(|sinks| sinks.get(name));
+        if sink.is_none() {
+            eprintln!("Warning: pattern sink '{name}' was not opened when the run started; saving to the output directory");
+        }
+        sink
+    });
+    let sink_options = sink.map(|sink| sink_options(options, sink));
+    match report_match(
+        &key.keypair,
+        key.segment,
+        key.thread_id,
+        Some(&batch_match.pattern),
+        sink_options.as_ref().unwrap_or(options),
+    ) {
+        Ok(()) => {
+            // The key is saved, so a failed delivery is only reported
+            if let Some(sink) = sink {
+                if let Err(e) = sink.deliver(&key.keypair, &key.address, key.segment, &batch_match.pattern, options.save_secret) {
+                    eprintln!("Warning: {}", redact::scrub(&e.to_string()));
+                }
+            }
+            true
+        }
+        Err(e) => {
+            // Give the slot back so the search carries on rather than aborting
+            eprintln!("Error: {}", redact::scrub(&e.to_string()));
+            eprintln!("Discarding keypair {} and continuing the search", key.address);
+            false
+        }
+    }
+}
+
+/// The options for saving a key of a pattern with `sink`: its directory and
+/// recipients in place of the run's.
+fn sink_options(options: &GrindOptions, sink: &Sink) -> GrindOptions {
+    let mut options = options.clone();
+    if let Some(dir) = &sink.dir {
+        options.output_dir.clone_from(dir);
+        options.rotation = None;
+    }
+    if let Some(recipients) = &sink.encrypt_to {
+        options.encrypt_to = Some(recipients.clone());
+    }
+    options
+}
+
+/// Estimated time to find the rest of a pattern's keys at `rate`. Every
+/// candidate is checked against every pattern, so each estimate stands on
+/// its own.
+pub fn pattern_eta(pattern_stats: &PatternStats, rate: f64, ignore_case: bool, options: &GrindOptions) -> String {
+    let probability = key_match_probability(options) * pattern_stats.pattern.match_probability(ignore_case);
+    let remaining = pattern_stats.requested.saturating_sub(pattern_stats.found) as f64;
+    // A pattern with dedicated workers only sees their share of the rate
+    let rate = match pattern_stats.workers {
+        0 => rate,
+        workers => rate * workers as f64 / options.num_threads as f64,
+    };
+    format_eta(remaining / (probability * rate.max(1.0)))
+}
+
+/// The threads dedicated to a pattern, for progress lines; empty while
+/// every thread checks it.
+pub fn pattern_threads(pattern_stats: &PatternStats) -> String {
+    match pattern_stats.workers {
+        0 => String::new(),
+        1 => ", 1 thread".to_string(),
+        workers => format!(", {workers} threads"),
+    }
+}
+
+/// Prints the summary of a batch run and returns the keypairs it kept.
+pub fn finish_batch(result: BatchResult, options: &GrindOptions) -> Vec<Keypair> {
+    let requested: usize = result.patterns.iter().filter(|p| !p.removed).map(|p| p.requested).sum();
+    record_summary(options, &result.stats);
+    if let Some(report) = &options.report {
+        report.record_patterns(&result.patterns);
+    }
+    print_summary(&format!("{}/{requested}", result.keys.len()), &result.stats);
+    for pattern_stats in &result.patterns {
+        let removed = if pattern_stats.removed { " (removed)" } else { "" };
+        println!("  {}: {}/{}{removed}", pattern_stats.pattern, pattern_stats.found, pattern_stats.requested);
+    }
+
+    if !options.save_secret {
+        return Vec::new();
+    }
+    result.keys.into_iter().map(|batch_match| batch_match.key.keypair).collect()
+}
+
+/// Returns a reload callback for `vanity_keys_batch` that rereads the
+/// patterns in the config file at `path` whenever its modification time
+/// changes. A change that fails to load or validate is reported and ignored.
+fn watch_patterns(
+    path: &Path,
+    mut current: Vec<BatchPattern>,
+    ignore_case: bool,
+) -> Box<dyn FnMut() -> Option<Vec<BatchPattern>> + Send> {
+    let path = path.to_path_buf();
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut last_modified = modified(&path);
+
+    Box::new(move || {
+        let now_modified = modified(&path);
+        if now_modified == last_modified {
+            return None;
+        }
+        last_modified = now_modified;
+
+        let patterns = Config::load(Some(&path)).and_then(|config| {
+            let patterns = config.patterns.unwrap_or_default();
+            for batch_pattern in &patterns {
+                batch_pattern
+                    .pattern
+                    .validate(ignore_case)
+                    .map_err(|e| format!("{}: {e}", batch_pattern.pattern))?;
+            }
+            Ok(patterns)
+        });
+        let patterns = match patterns {
+            Ok(patterns) => patterns,
+            Err(e) => {
+                eprintln!("Ignoring the change to {}: {e}", path.display());
+                return None;
+            }
+        };
+
+        for batch_pattern in patterns.iter().filter(|p| !current.iter().any(|c| c.pattern == p.pattern)) {
+            println!("\nAdded pattern {}: {} key(s)", batch_pattern.pattern, batch_pattern.count);
+        }
+        for batch_pattern in current.iter().filter(|c| !patterns.iter().any(|p| p.pattern == c.pattern)) {
+            println!("\nRemoved pattern {}", batch_pattern.pattern);
+        }
+        current.clone_from(&patterns);
+        Some(patterns)
+    })
+}
+
+/// Chance that a random key passes the imm32 check, constraints and target
+/// list, before any vanity pattern.
+pub fn key_match_probability(options: &GrindOptions) -> f64 {
+    let target_probability = options.targets.as_ref().map_or(1.0, |targets| targets.match_probability());
+    imm32::MATCH_PROBABILITY * options.constraints.match_probability() * target_probability
+}
+
+/// Notes when the search matches a derived address rather than the key.
+pub fn print_derivation(options: &GrindOptions) {
+    if let Some(derivation) = options.derivation {
+        println!("Matching the {} address derived from each key, not the key itself", derivation.describe());
+    }
+}
+
+/// Notes any constraints beyond imm32 compatibility.
+pub fn print_constraints(options: &GrindOptions) {
+    if let Some(constraints) = options.constraints.describe() {
+        println!("Constraints: {constraints}");
+    }
+    if let Some(predicate) = &options.predicate {
+        println!("Predicate: {} (not included in time estimates)", predicate.path().display());
+    }
+    if let Some(plugin) = &options.plugin {
+        println!("Plugin: {}{}", plugin.path().display(), if plugin.has_match() { " (not included in time estimates)" } else { "" });
+    }
+}
+
+/// Runs the predicate script and the plugin matcher, if any, on an
+/// address. Returns `None` if either rejects it, otherwise the scores they
+/// gave. Errors count as a rejection and only the first from each is
+/// reported.
+fn custom_scores(options: &GrindOptions, address: &[u8; 32]) -> Option<Vec<(&'static str, f64)>> {
+    if options.predicate.is_none() && options.plugin.as_ref().is_none_or(|plugin| !plugin.has_match()) {
+        return Some(Vec::new());
+    }
+    let encoded = bs58::encode(address).into_string();
+    let mut scores = Vec::new();
+
+    if let Some(predicate) = &options.predicate {
+        match predicate.evaluate(address, &encoded) {
+            Ok(Verdict::Rejected) => return None,
+            Ok(Verdict::Accepted) => {}
+            Ok(Verdict::Scored(score)) => scores.push(("Predicate", score)),
+            Err(e) => {
+                if predicate.first_failure() {
+                    eprintln!("Warning: predicate failed, rejecting the key (later failures are not shown): {e}");
+                }
+                return None;
+            }
+        }
+    }
+    if let Some(plugin) = options.plugin.as_ref().filter(|plugin| plugin.has_match()) {
+        match plugin.evaluate(address, &encoded) {
+            Ok(Verdict::Rejected) => return None,
+            Ok(Verdict::Accepted) => {}
+            Ok(Verdict::Scored(score)) => scores.push(("Plugin", score)),
+            Err(e) => {
+                if plugin.first_failure() {
+                    eprintln!("Warning: plugin failed, rejecting the key (later failures are not shown): {e}");
+                }
+                return None;
+            }
+        }
+    }
+    Some(scores)
+}
+
+/// Starts the thread that periodically prints progress until `shutdown` is cancelled.
+fn spawn_progress_reporter(
+    options: &GrindOptions,
+    stats: &Arc<StatsCollector>,
+    shutdown: &ShutdownToken,
+    keys_found: Arc<AtomicUsize>,
+    total_count: Option<usize>,
+) -> Result<thread::JoinHandle<()>, CliError> {
+    let options = options.clone();
+    let reporter = progress::spawn_reporter(stats, shutdown, options.progress_interval, keys_found, move |snapshot| {
+        record_progress(&options, &snapshot);
+        print_progress(&snapshot, total_count)
+    })?;
+    Ok(reporter)
+}
+
+/// Formats a found-key count against the target, if there is one.
+fn format_found(found: usize, target: Option<usize>) -> String {
+    match target {
+        Some(target) => format!("{found}/{target}"),
+        None => found.to_string(),
+    }
+}
+
+/// Cancels `shutdown` on the first SIGINT or SIGTERM so the run can wind
+/// down and print its summary. A second signal exits immediately.
+#[cfg(unix)]
+pub fn stop_on_interrupt(shutdown: &ShutdownToken) -> Result<(), CliError> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGINT, SIGTERM]).map_err(DopplerError::SpawnThread)?;
+    let shutdown = shutdown.clone();
+    thread::Builder::new()
+        .name("signals".to_string())
+        .spawn(move || {
+            let mut signals = signals.forever();
+            if signals.next().is_some() {
+                println!("\nInterrupted, finishing up...");
+                shutdown.cancel();
+            }
+            if signals.next().is_some() {
+                process::exit(130);
+            }
+        })
+        .map_err(DopplerError::SpawnThread)?;
+    Ok(())
+}
+
+/// Without signal handling the default Ctrl-C behaviour ends the process.
+#[cfg(not(unix))]
+pub fn stop_on_interrupt(_shutdown: &ShutdownToken) -> Result<(), CliError> {
+    Ok(())
+}
+
+/// Prints one progress report.
+pub fn print_progress(snapshot: &ProgressSnapshot, total_count: Option<usize>) {
+    println!(
+        "Progress: {} attempts | {:.0} keys/sec | Found: {}",
+        snapshot.total_attempts,
+        snapshot.rate,
+        format_found(snapshot.keys_found, total_count)
+    );
+    println!("  Per-thread keys/sec: {}", stats::format_worker_rates(snapshot.worker_rates.iter().copied()));
+    if let Some(history) = format_rate_history(&snapshot.rate_history) {
+        println!("  Rate history: {history}");
+    }
+}
+
+/// Charts the rate over a run as a sparkline with its range, once there are
+/// at least two values to compare.
+pub fn format_rate_history(rate_history: &[f64]) -> Option<String> {
+    if rate_history.len() < 2 {
+        return None;
+    }
+    let min = rate_history.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = rate_history.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    Some(format!("{} (min {min:.0}, max {max:.0} keys/sec)", stats::sparkline(rate_history)))
+}
+
+/// Stops the progress reporter and waits for it so nothing prints after the summary.
+pub fn finish_progress_reporter(shutdown: &ShutdownToken, progress_handle: thread::JoinHandle<()>) {
+    shutdown.cancel();
+    if progress_handle.join().is_err() {
+        eprintln!("Progress reporter panicked");
+    }
+}
+
+/// Unwraps the shared list of found keypairs once all workers have been joined.
+fn take_found_keypairs(found_keypairs: Arc<Mutex<Vec<Keypair>>>) -> Vec<Keypair> {
+    Arc::try_unwrap(found_keypairs)
+        .map(|found| found.into_inner().unwrap_or_else(PoisonError::into_inner))
+        .unwrap_or_default()
+}
+
+/// Returns the keypair path configured for the Solana CLI, falling back to
+/// the CLI's default of `~/.config/solana/id.json`.
+fn solana_default_keypair_path() -> Result<PathBuf, String> {
+    let home = config::home_dir()
+        .ok_or_else(|| "Could not determine the home directory".to_string())?;
+    let solana_dir = home.join(".config").join("solana");
+
+    // Honor `keypair_path` from the Solana CLI config if one exists
+    if let Ok(config) = fs::read_to_string(solana_dir.join("cli").join("config.yml")) {
+        for line in config.lines() {
+            if let Some(value) = line.trim().strip_prefix("keypair_path:") {
+                let value = value.trim().trim_matches(|c| c == '\'' || c == '"');
+                if !value.is_empty() {
+                    return Ok(PathBuf::from(value));
+                }
+            }
+        }
+    }
+
+    Ok(solana_dir.join("id.json"))
+}
+
+/// Writes the keypair to the Solana CLI default keypair location after
+/// asking the user for confirmation.
+pub fn install_as_default(keypair: &Keypair) -> Result<(), Box<dyn core::error::Error>> {
+    let path = solana_default_keypair_path()?;
+
+    println!("\nInstall {} as the Solana CLI default keypair?", keypair.pubkey());
+    println!("Destination: {}", path.display());
+    if path.exists() {
+        println!("⚠️  This will OVERWRITE the existing keypair at that path. Back it up first if you still need it.");
+    }
+    print!("Continue? [y/N] ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        println!("Not installed.");
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    secretfile::replace(&path, &OutputFormat::Json.encode(keypair))?;
+
+    println!("Installed default keypair: {}", path.display());
+    Ok(())
+}
+
+pub fn print_summary(keys_found: &str, summary: &StatsSummary) {
+    println!("\n------- Summary -------");
+    println!("Keys found: {keys_found}");
+    println!("Total attempts: {}", summary.total_attempts);
+    println!("Time elapsed: {:.2} seconds", summary.elapsed.as_secs_f64());
+    println!("Average rate: {:.0} keys/sec", summary.average_rate);
+    if let Some([p10, p50, p90]) = summary.rate_percentiles {
+        println!("Rate percentiles: p10 {p10:.0} | p50 {p50:.0} | p90 {p90:.0} keys/sec");
+    }
+
+    // Flag threads well below the median, which usually means they were
+    // starved by the OS or by contention
+    let mut worker_rates: Vec<f64> = summary.workers.iter().map(|worker| worker.average_rate).collect();
+    worker_rates.sort_by(f64::total_cmp);
+    let median_rate = worker_rates.get(worker_rates.len() / 2).copied().unwrap_or(0.0);
+    println!("Per-thread:");
+    for (thread_id, worker) in summary.workers.iter().enumerate() {
+        let starved = worker.average_rate < median_rate * 0.75;
+        println!(
+            "  Thread {thread_id}: {} attempts | {:.0} keys/sec{}",
+            worker.attempts,
+            worker.average_rate,
+            if starved { " ⚠️  below 75% of median" } else { "" }
+        );
+    }
+}
+
+/// Settings shared by every worker of a grind run.
+#[derive(Debug, Clone)]
+pub struct GrindOptions {
+    pub num_threads: usize,
+    pub output_formats: Vec<OutputFormat>,
+    pub output_dir: PathBuf,
+    pub progress_interval: Duration,
+    pub scheduler: Scheduler,
+    pub batch_size: usize,
+    /// Write keypair files; when false only public keys are recorded
+    pub save_secret: bool,
+    /// Keys already emitted to the output directory, unless disabled
+    pub key_index: Option<Arc<KeyIndex>>,
+    /// Log of every keypair written to the output directory
+    pub audit_log: Option<Arc<AuditLog>>,
+    /// Catalogue of the keys saved to the output directory
+    pub results: Option<Arc<ResultsDb>>,
+    /// Write Shamir shares of each secret instead of keypair files
+    pub split: Option<SplitSpec>,
+    /// Encrypt keypair files to these age recipients
+    pub encrypt_to: Option<AgeRecipients>,
+    /// Write keypairs to Vault instead of local files
+    pub vault: Option<Arc<VaultSink>>,
+    /// Import keypairs into a PKCS#11 token instead of local files
+    pub pkcs11: Option<Arc<Pkcs11Sink>>,
+    /// Wrap keypair files with a cloud KMS key
+    pub kms: Option<Arc<KmsWrapper>>,
+    /// Check each match for existing accounts on this cluster
+    pub check_onchain: Option<Arc<RpcClient>>,
+    /// Fund each saved key from this cluster's faucet
+    pub airdrop: Option<Arc<RpcClient>>,
+    /// Show and save each address as a QR code
+    pub qr: bool,
+    /// Also save each encrypted keypair file as a QR code
+    pub qr_secret: bool,
+    /// Match an address derived from each key instead of the key itself
+    pub derivation: Option<Derivation>,
+    /// Which imm32 compatible segments count as a match
+    pub constraints: Constraints,
+    /// Only accept addresses starting with one of these prefixes
+    pub targets: Option<Arc<TargetSet>>,
+    /// Only accept addresses this script matches
+    pub predicate: Option<Arc<Predicate>>,
+    /// WebAssembly matcher and sink
+    pub plugin: Option<Arc<Plugin>>,
+    /// Progress, keys and stats of a `job` shard
+    pub job: Option<Arc<JobStatus>>,
+    /// Brokers that saved keys and progress are published to
+    pub publishers: Option<Arc<Publishers>>,
+    /// Where the workers' RNGs are seeded from
+    pub entropy: Entropy,
+    /// Dated subdirectories that key files are saved to
+    pub rotation: Option<Arc<Rotation>>,
+    /// Destinations of the batch patterns that name a sink
+    pub sinks: Option<Arc<Sinks>>,
+    /// What the run did, for the email sent when it ends
+    pub report: Option<Arc<RunReport>>,
+    /// Telegram chats and Discord channels saved keys are announced in
+    pub notifiers: Option<Arc<Notifiers>>,
+    /// URLs each saved key's public address is posted to
+    pub webhooks: Option<Arc<Webhooks>>,
+    /// Tags and metadata every saved key is labelled with
+    pub tags: KeyTags,
+    /// How long saved keys are meant to be used before they are rotated
+    pub lifetime: Option<Lifetime>,
+    /// Upgrade authority transaction prepared for each saved key
+    pub handoff: Option<Arc<Handoff>>,
+    /// Program whose `set-upgrade-authority` command is printed for each
+    /// saved key
+    pub set_authority: Option<SetAuthority>,
+}
+
+impl GrindOptions {
+    /// The library settings for a run with these options.
+    pub fn grind_config(&self, ignore_case: bool) -> GrindConfig {
+        GrindConfig {
+            pattern: None,
+            ignore_case,
+            num_threads: self.num_threads,
+            batch_size: self.batch_size,
+            scheduler: self.scheduler,
+            derivation: self.derivation,
+            constraints: self.constraints.clone(),
+            entropy: self.entropy.clone(),
+        }
+    }
+
+    pub fn workers(&self) -> WorkerConfig {
+        self.grind_config(false).workers()
+    }
+}
+
+/// `--emit-set-authority` and `--upgrade-authority`.
+#[derive(Debug, Clone)]
+pub struct SetAuthority {
+    pub program: [u8; 32],
+    /// The current authority as given: a keypair file or a public key
+    pub upgrade_authority: Option<String>,
+}
+
+pub fn parse_batch_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(batch_size) if batch_size > 0 => Ok(batch_size),
+        _ => Err(format!("Invalid batch size '{value}': must be at least 1")),
+    }
+}
+
+/// Samples the key rate and, if the search is expected to take longer than
+/// `CONFIRM_THRESHOLD`, asks whether to go ahead. Returns true to proceed.
+pub fn confirm_expected_time(expected_attempts: f64, options: &GrindOptions) -> Result<bool, CliError> {
+    let rate = bench::measure_rate(options.num_threads, options.scheduler, options.batch_size, options.derivation, RATE_SAMPLE)?;
+    let expected_seconds = expected_attempts / rate.max(1.0);
+    if expected_seconds < CONFIRM_THRESHOLD.as_secs_f64() {
+        return Ok(true);
+    }
+
+    println!("Expected time: {} at {rate:.0} keys/sec ({expected_attempts:.3e} attempts)", format_eta(expected_seconds));
+    print!("Start the search? [y/N] ");
+    io::stdout().flush().map_err(CliError::Prompt)?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(CliError::Prompt)?;
+    let confirmed = matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+    if !confirmed && answer.is_empty() {
+        eprintln!("No answer on stdin; pass --yes to skip this prompt");
+    }
+    Ok(confirmed)
+}
+
+/// Formats a duration in seconds as the two most significant units.
+pub fn format_eta(seconds: f64) -> String {
+    const UNITS: [(&str, f64); 5] =
+        [("y", 365.0 * 86_400.0), ("d", 86_400.0), ("h", 3_600.0), ("m", 60.0), ("s", 1.0)];
+
+    let Some(index) = UNITS.iter().position(|&(_, unit)| seconds >= unit) else {
+        return "under a second".to_string();
+    };
+    let (name, unit) = UNITS[index];
+    let whole = (seconds / unit).floor();
+    match UNITS.get(index + 1) {
+        Some(&(next_name, next_unit)) if whole < 1e6 => {
+            let rest = ((seconds - whole * unit) / next_unit).floor();
+            format!("{whole:.0}{name} {rest:.0}{next_name}")
+        }
+        _ => format!("{whole:.0}{name}"),
+    }
+}
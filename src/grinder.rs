@@ -0,0 +1,363 @@
+//! A streaming API over a grind run.
+//!
+//! ```no_run
+//! use doppler_keygen::grinder::{GrindConfig, Grinder};
+//! use doppler_keygen::pattern::VanityPattern;
+//!
+//! let config = GrindConfig {
+//!     pattern: Some(VanityPattern { prefix: Some("ab".to_string()), suffix: None }),
+//!     ..GrindConfig::default()
+//! };
+//! for found in Grinder::new(config).into_iter().take(2) {
+//!     println!("{} (segment {})", found.address, found.segment);
+//! }
+//! ```
+
+use core::ops::ControlFlow;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use serde::{Deserialize, Serialize};
+use solana_keypair::Keypair;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::constraints::Constraints;
+use crate::derive::Derivation;
+use crate::entropy::Entropy;
+use crate::error::DopplerError;
+use crate::pattern::VanityPattern;
+use crate::progress::{self, ProgressCallback, ProgressSnapshot};
+use crate::scheduler::{self, Candidate, Scheduler, WorkerConfig, DEFAULT_BATCH_SIZE};
+use crate::shutdown::ShutdownToken;
+use crate::stats::StatsCollector;
+
+#[cfg(feature = "tokio")]
+use tokio_util::sync::CancellationToken;
+
+/// How many found keys may be waiting for the consumer before workers pause.
+const FOUND_KEY_BUFFER: usize = 16;
+
+/// Settings for a `Grinder`. Missing fields take their defaults when
+/// deserializing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrindConfig {
+    /// An optional vanity constraint on top of the imm32 requirement
+    pub pattern: Option<VanityPattern>,
+    /// Match the vanity pattern ignoring ASCII case
+    pub ignore_case: bool,
+    pub num_threads: usize,
+    pub batch_size: usize,
+    pub scheduler: Scheduler,
+    /// Match an address derived from each key instead of the key itself
+    pub derivation: Option<Derivation>,
+    /// Which imm32 compatible segments count as a match
+    pub constraints: Constraints,
+    /// Where the workers' RNGs are seeded from; the OS unless set
+    #[serde(skip)]
+    pub entropy: Entropy,
+}
+
+impl Default for GrindConfig {
+    fn default() -> GrindConfig {
+        GrindConfig {
+            pattern: None,
+            ignore_case: false,
+            num_threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            batch_size: DEFAULT_BATCH_SIZE,
+            scheduler: Scheduler::default(),
+            derivation: None,
+            constraints: Constraints::default(),
+            entropy: Entropy::default(),
+        }
+    }
+}
+
+impl GrindConfig {
+    /// The worker settings of a run with this config.
+    pub fn workers(&self) -> WorkerConfig {
+        WorkerConfig {
+            scheduler: self.scheduler,
+            num_threads: self.num_threads,
+            batch_size: self.batch_size,
+            derivation: self.derivation,
+            entropy: self.entropy.clone(),
+        }
+    }
+}
+
+/// A key that satisfied the search.
+#[derive(Debug)]
+pub struct FoundKey {
+    pub keypair: Keypair,
+    /// The base58 address that matched: the public key, or the address
+    /// derived from it
+    pub address: String,
+    /// The first segment of the address that forms a valid 32-bit immediate
+    /// and satisfies the run's constraints
+    pub segment: usize,
+    /// The worker that found the key
+    pub thread_id: usize,
+}
+
+type FoundCallback = Box<dyn Fn(&FoundKey) + Send + Sync>;
+
+/// Finds the segment of a candidate that counts as a match, if any.
+type SegmentCheck = fn(&Constraints, &[u8; 32], u8) -> Option<usize>;
+
+/// Searches for imm32 compatible keys, yielding them as they are found.
+pub struct Grinder {
+    config: GrindConfig,
+    on_progress: Option<(Duration, ProgressCallback)>,
+    on_found: Option<FoundCallback>,
+    // Only tests swap this out, since real imm32 matches are too rare to
+    // wait for
+    matching_segment: SegmentCheck,
+}
+
+impl Grinder {
+    pub fn new(config: GrindConfig) -> Grinder {
+        Grinder { config, on_progress: None, on_found: None, matching_segment: Constraints::matching_segment }
+    }
+
+    /// Calls `callback` with a progress snapshot every `interval` while the
+    /// run is going.
+    pub fn on_progress<F>(mut self, interval: Duration, callback: F) -> Grinder
+    where
+        F: FnMut(ProgressSnapshot) + Send + 'static,
+    {
+        self.on_progress = Some((interval, Box::new(callback)));
+        self
+    }
+
+    /// Calls `callback` on the worker thread that found each key, before the
+    /// key is handed to the consumer.
+    pub fn on_found<F>(mut self, callback: F) -> Grinder
+    where
+        F: Fn(&FoundKey) + Send + Sync + 'static,
+    {
+        self.on_found = Some(Box::new(callback));
+        self
+    }
+
+    /// Starts the workers on a background thread, handing each found key to
+    /// `deliver`. The run stops once `deliver` returns false or `shutdown` is
+    /// cancelled.
+    fn start<D>(
+        self,
+        stats: &Arc<StatsCollector>,
+        shutdown: &ShutdownToken,
+        deliver: D,
+    ) -> Result<JoinHandle<Result<(), DopplerError>>, DopplerError>
+    where
+        D: Fn(FoundKey) -> bool + Send + Sync + 'static,
+    {
+        let config = self.config;
+        if let Some(pattern) = &config.pattern {
+            pattern.validate(config.ignore_case).map_err(DopplerError::InvalidPattern)?;
+        }
+        config.constraints.validate().map_err(DopplerError::InvalidConstraint)?;
+
+        let keys_found = Arc::new(AtomicUsize::new(0));
+        let reporter = self
+            .on_progress
+            .map(|(interval, callback)| progress::spawn_reporter(stats, shutdown, interval, Arc::clone(&keys_found), callback))
+            .transpose()?;
+
+        let on_found = self.on_found;
+        let matching_segment = self.matching_segment;
+        let check = {
+            let shutdown = shutdown.clone();
+            let pattern = config.pattern.clone();
+            let ignore_case = config.ignore_case;
+            let constraints = config.constraints.clone();
+            let prefix_ranges = pattern.as_ref().and_then(|pattern| pattern.prefix_ranges(ignore_case));
+
+            move |thread_id: usize, candidate: Candidate| {
+                let Some(segment) = matching_segment(&constraints, &candidate.address, candidate.imm32_mask) else {
+                    return ControlFlow::Continue(());
+                };
+                // Keys outside the prefix's ranges are rejected before encoding
+                if prefix_ranges.is_some_and(|ranges| !ranges.contains(&candidate.address)) {
+                    return ControlFlow::Continue(());
+                }
+                let address = bs58::encode(candidate.address).into_string();
+                if pattern.as_ref().is_some_and(|pattern| !pattern.matches_with_case(&address, ignore_case)) {
+                    return ControlFlow::Continue(());
+                }
+
+                let found = FoundKey { keypair: candidate.keypair(), address, segment, thread_id };
+                keys_found.fetch_add(1, Ordering::Relaxed);
+                if let Some(on_found) = &on_found {
+                    on_found(&found);
+                }
+                if !deliver(found) {
+                    // The consumer has gone away
+                    shutdown.cancel();
+                    return ControlFlow::Break(());
+                }
+                ControlFlow::Continue(())
+            }
+        };
+
+        let run_stats = Arc::clone(stats);
+        let run_shutdown = shutdown.clone();
+        thread::Builder::new()
+            .name("grinder".to_string())
+            .spawn(move || {
+                let result = scheduler::run_workers(&config.workers(), &run_stats, &run_shutdown, check);
+
+                // Stop the progress reporter along with the workers
+                run_shutdown.cancel();
+                if let Some(reporter) = reporter {
+                    let _ = reporter.join();
+                }
+                result
+            })
+            .map_err(|e| {
+                shutdown.cancel();
+                DopplerError::SpawnThread(e)
+            })
+    }
+
+    /// Starts the workers and returns a channel of found keys along with a
+    /// token that stops the search when cancelled.
+    ///
+    /// Must be called from within a Tokio runtime. The channel closes once
+    /// the run ends, whether through cancellation, the receiver being
+    /// dropped, or the workers failing to start.
+    #[cfg(feature = "tokio")]
+    pub fn spawn(self) -> Result<(tokio::sync::mpsc::Receiver<FoundKey>, CancellationToken), DopplerError> {
+        let stats = Arc::new(StatsCollector::new(self.config.num_threads));
+        let shutdown = ShutdownToken::new();
+        let cancel = CancellationToken::new();
+        let (sender, receiver) = tokio::sync::mpsc::channel(FOUND_KEY_BUFFER);
+
+        // Forward cancellation to the workers
+        tokio::spawn({
+            let cancel = cancel.clone();
+            let shutdown = shutdown.clone();
+            async move {
+                cancel.cancelled().await;
+                shutdown.cancel();
+            }
+        });
+        // Workers are plain threads rather than runtime tasks, so they can
+        // block on a full channel
+        let runner = self.start(&stats, &shutdown, move |found| sender.blocking_send(found).is_ok())?;
+
+        // Release the forwarding task once the run is over
+        let done = cancel.clone();
+        thread::Builder::new()
+            .name("grinder-join".to_string())
+            .spawn(move || {
+                let _ = runner.join();
+                done.cancel();
+            })
+            .map_err(DopplerError::SpawnThread)?;
+
+        Ok((receiver, cancel))
+    }
+}
+
+impl IntoIterator for Grinder {
+    type Item = FoundKey;
+    type IntoIter = FoundKeys;
+
+    /// Starts the workers. They run until the iterator is dropped or
+    /// [`FoundKeys::finish`] is called, pausing whenever the consumer falls
+    /// behind.
+    fn into_iter(self) -> FoundKeys {
+        let stats = Arc::new(StatsCollector::new(self.config.num_threads));
+        let shutdown = ShutdownToken::new();
+        let (sender, receiver) = mpsc::sync_channel(FOUND_KEY_BUFFER);
+
+        let runner = self.start(&stats, &shutdown, move |found| sender.send(found).is_ok());
+        FoundKeys { receiver: Some(receiver), shutdown, stats, runner: Some(runner) }
+    }
+}
+
+/// The keys found by a running `Grinder`.
+///
+/// Dropping the iterator stops the workers and waits for them to exit.
+pub struct FoundKeys {
+    receiver: Option<Receiver<FoundKey>>,
+    shutdown: ShutdownToken,
+    stats: Arc<StatsCollector>,
+    runner: Option<Result<JoinHandle<Result<(), DopplerError>>, DopplerError>>,
+}
+
+impl FoundKeys {
+    /// Attempt counters for the run so far.
+    pub fn stats(&self) -> &StatsCollector {
+        &self.stats
+    }
+
+    /// Stops the workers and reports whether the run failed, for example
+    /// because a worker could not be spawned.
+    pub fn finish(mut self) -> Result<(), DopplerError> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), DopplerError> {
+        self.shutdown.cancel();
+        // Unblocks any worker waiting to hand over a key
+        self.receiver = None;
+
+        match self.runner.take() {
+            Some(Ok(runner)) => runner.join().unwrap_or(Err(DopplerError::WorkerPanicked(0))),
+            Some(Err(e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Iterator for FoundKeys {
+    type Item = FoundKey;
+
+    /// Blocks until the next key is found. Returns `None` once every worker
+    /// has stopped.
+    fn next(&mut self) -> Option<FoundKey> {
+        self.receiver.as_ref()?.recv().ok()
+    }
+}
+
+impl Drop for FoundKeys {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_signer::Signer as _;
+
+    #[test]
+    fn dropping_the_keys_stops_the_workers() {
+        let config = GrindConfig {
+            pattern: Some(VanityPattern { prefix: None, suffix: Some("z".to_string()) }),
+            num_threads: 2,
+            batch_size: 64,
+            ..GrindConfig::default()
+        };
+        let mut grinder = Grinder::new(config);
+        grinder.matching_segment = |_, _, _| Some(0);
+
+        let mut keys = grinder.into_iter();
+        let found: Vec<FoundKey> = keys.by_ref().take(5).collect();
+        assert_eq!(found.len(), 5);
+        for key in &found {
+            assert!(key.address.ends_with('z'));
+            assert_eq!(key.address, key.keypair.pubkey().to_string());
+        }
+
+        let stats = Arc::clone(&keys.stats);
+        drop(keys);
+        let attempts = stats.summary().total_attempts;
+        assert!(attempts > 0);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(stats.summary().total_attempts, attempts);
+    }
+}
@@ -0,0 +1,353 @@
+//! `--handoff`: an unsigned transaction moving a program's upgrade authority,
+//! prepared as soon as a key is found.
+//!
+//! A freshly ground authority key is a single point of failure until the
+//! authority it holds is moved somewhere safer. With `--handoff` each saved
+//! key gets a `<pubkey>.handoff.json` next to its keypair file, holding the
+//! BPF upgradeable loader `SetAuthority` instruction and the unsigned
+//! transaction around it, ready for `solana` tooling or a Squads proposal:
+//!
+//! - `squads:<MULTISIG>` moves the upgrade authority of `--handoff-program`
+//!   from the found key to the multisig's vault, so the found key only has
+//!   to sign once.
+//! - `authority:<CURRENT>` makes the found key the program's upgrade
+//!   authority, signed by the current one.
+//!
+//! `--emit-set-authority <PROGRAM_ID>` is the same handoff for the Solana
+//! CLI: it prints the `solana program set-upgrade-authority` command making
+//! each saved key the program's upgrade authority, and with
+//! `--upgrade-authority` also writes the `authority:` transaction.
+//!
+//! The transaction needs a recent blockhash. With `--handoff-cluster` it is
+//! fetched when each key is saved; otherwise the blockhash is all zeros and
+//! must be replaced when the transaction is signed.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli_error::CliError;
+use doppler_keygen::derive;
+use crate::rpc::RpcClient;
+
+/// The BPF upgradeable loader, `BPFLoaderUpgradeab1e11111111111111111111111`.
+pub const BPF_LOADER_UPGRADEABLE_ID: [u8; 32] = [
+    0x02, 0xa8, 0xf6, 0x91, 0x4e, 0x88, 0xa1, 0xb0, 0xe2, 0x10, 0x15, 0x3e, 0xf7, 0x63, 0xae, 0x2b,
+    0x00, 0xc2, 0xb9, 0x3d, 0x16, 0xc1, 0x24, 0xd2, 0xc0, 0x53, 0x7a, 0x10, 0x04, 0x80, 0x00, 0x00,
+];
+
+/// Upgradeable loader `SetAuthority` instruction index.
+const SET_AUTHORITY: u32 = 4;
+
+/// Extension of the file written next to a key's keypair file.
+pub const HANDOFF_EXTENSION: &str = "handoff.json";
+
+/// Where the upgrade authority goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandoffTarget {
+    /// From the found key to vault 0 of this Squads multisig
+    Squads { multisig: [u8; 32], vault: [u8; 32] },
+    /// From this authority to the found key
+    Authority { current: [u8; 32] },
+}
+
+impl HandoffTarget {
+    /// Parses `squads:<MULTISIG>` or `authority:<CURRENT>`.
+    pub fn parse(value: &str) -> Result<HandoffTarget, String> {
+        let invalid = || format!("Invalid handoff '{value}' (expected squads:<MULTISIG> or authority:<CURRENT>)");
+        let (kind, address) = value.split_once(':').ok_or_else(invalid)?;
+        let address = decode_pubkey("handoff address", address)?;
+        match kind {
+            "squads" => {
+                let (vault, _) = derive::squads_vault(&address, 0).ok_or_else(invalid)?;
+                Ok(HandoffTarget::Squads { multisig: address, vault })
+            }
+            "authority" => Ok(HandoffTarget::Authority { current: address }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+pub fn decode_pubkey(name: &str, value: &str) -> Result<[u8; 32], String> {
+    bs58::decode(value)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid {name} '{value}'"))
+}
+
+fn encode(address: &[u8; 32]) -> String {
+    bs58::encode(address).into_string()
+}
+
+/// Appends `value` in Solana's compact-u16 encoding.
+fn push_compact_u16(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// The `SetAuthority` of `program`'s upgrade authority from `current` to
+/// `new`, as an unsigned legacy transaction paid for by `current`: the
+/// message and the transaction with an empty signature.
+fn set_authority_transaction(program_data: &[u8; 32], current: &[u8; 32], new: &[u8; 32], blockhash: &[u8; 32]) -> (Vec<u8>, Vec<u8>) {
+    // Writable signer, writable, then the read-only accounts
+    let accounts = [current, program_data, new, &BPF_LOADER_UPGRADEABLE_ID];
+    let mut message = vec![1, 0, 2];
+    push_compact_u16(&mut message, accounts.len());
+    for account in accounts {
+        message.extend_from_slice(account);
+    }
+    message.extend_from_slice(blockhash);
+    push_compact_u16(&mut message, 1);
+    message.push(3);
+    push_compact_u16(&mut message, 3);
+    message.extend_from_slice(&[1, 0, 2]);
+    let data = SET_AUTHORITY.to_le_bytes();
+    push_compact_u16(&mut message, data.len());
+    message.extend_from_slice(&data);
+
+    let mut transaction = Vec::new();
+    push_compact_u16(&mut transaction, 1);
+    transaction.extend_from_slice(&[0; 64]);
+    transaction.extend_from_slice(&message);
+    (message, transaction)
+}
+
+/// The `solana program set-upgrade-authority` command making `new` the
+/// upgrade authority of `program`. The CLI checks that the new authority
+/// can sign, which it can only do with a plaintext keypair file; given just
+/// the public key the check is skipped.
+pub fn set_authority_command(program: &[u8; 32], new: &[u8; 32], keypair_file: Option<&Path>, upgrade_authority: Option<&str>) -> String {
+    let mut command = format!("solana program set-upgrade-authority {}", encode(program));
+    if let Some(upgrade_authority) = upgrade_authority {
+        command.push_str(&format!(" --upgrade-authority {upgrade_authority}"));
+    }
+    match keypair_file {
+        Some(path) => command.push_str(&format!(" --new-upgrade-authority {}", path.display())),
+        None => command.push_str(&format!(
+            " --new-upgrade-authority {} --skip-new-upgrade-authority-signer-check",
+            encode(new)
+        )),
+    }
+    command
+}
+
+/// The handoff each saved key gets.
+#[derive(Debug)]
+pub struct Handoff {
+    pub target: HandoffTarget,
+    pub program: [u8; 32],
+    /// Where recent blockhashes come from, if anywhere
+    pub rpc: Option<RpcClient>,
+}
+
+impl Handoff {
+    /// What happens to the upgrade authority, for display.
+    pub fn describe(&self) -> String {
+        match self.target {
+            HandoffTarget::Squads { multisig, vault } => format!(
+                "move the upgrade authority of {} from each key to Squads multisig {} (vault {})",
+                encode(&self.program),
+                encode(&multisig),
+                encode(&vault)
+            ),
+            HandoffTarget::Authority { current } => format!(
+                "make each key the upgrade authority of {} in place of {}",
+                encode(&self.program),
+                encode(&current)
+            ),
+        }
+    }
+
+    /// The prepared transaction for `key`, with `blockhash` or a zero
+    /// placeholder.
+    pub fn transaction(&self, key: &[u8; 32], blockhash: Option<[u8; 32]>) -> Value {
+        let (program_data, _) = derive::find_program_address(&[&self.program], &BPF_LOADER_UPGRADEABLE_ID)
+            .expect("every program has a program data address");
+        let (kind, current, new) = match self.target {
+            HandoffTarget::Squads { vault, .. } => ("squads", *key, vault),
+            HandoffTarget::Authority { current } => ("set-upgrade-authority", current, *key),
+        };
+        let (message, transaction) = set_authority_transaction(&program_data, &current, &new, &blockhash.unwrap_or_default());
+        let mut plan = json!({
+            "kind": kind,
+            "authority": encode(key),
+            "program": encode(&self.program),
+            "programData": encode(&program_data),
+            "currentAuthority": encode(&current),
+            "newAuthority": encode(&new),
+            "feePayer": encode(&current),
+            "signers": [encode(&current)],
+            "recentBlockhash": blockhash.map(|blockhash| encode(&blockhash)),
+            "instructions": [
+                {
+                    "programId": encode(&BPF_LOADER_UPGRADEABLE_ID),
+                    "accounts": [
+                        { "pubkey": encode(&program_data), "isSigner": false, "isWritable": true },
+                        { "pubkey": encode(&current), "isSigner": true, "isWritable": false },
+                        { "pubkey": encode(&new), "isSigner": false, "isWritable": false },
+                    ],
+                    "data": bs58::encode(SET_AUTHORITY.to_le_bytes()).into_string(),
+                },
+            ],
+            "message": BASE64.encode(message),
+            "transaction": BASE64.encode(transaction),
+        });
+        if let HandoffTarget::Squads { multisig, .. } = self.target {
+            plan["multisig"] = json!(encode(&multisig));
+        }
+        plan
+    }
+
+    /// Writes the prepared transaction for `key` to `dir`, fetching a
+    /// blockhash if there is a cluster to ask. A failed fetch is only
+    /// reported, leaving the placeholder.
+    pub fn write(&self, dir: &Path, key: &[u8; 32]) -> Result<PathBuf, CliError> {
+        let blockhash = self.rpc.as_ref().and_then(|rpc| match rpc.get_latest_blockhash() {
+            Ok(blockhash) => decode_pubkey("blockhash", &blockhash).ok(),
+            Err(e) => {
+                eprintln!("Warning: could not fetch a recent blockhash from {}: {e}", rpc.url());
+                None
+            }
+        });
+        let path = dir.join(format!("{}.{HANDOFF_EXTENSION}", encode(key)));
+        let json = serde_json::to_string_pretty(&self.transaction(key, blockhash)).expect("transactions serialize to JSON");
+        fs::write(&path, format!("{json}\n")).map_err(|source| CliError::Handoff { path: path.clone(), source })?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_pubkey::Pubkey;
+
+    /// Reads a compact-u16 at `*at`, moving past it.
+    fn compact_u16(bytes: &[u8], at: &mut usize) -> usize {
+        let mut value = 0;
+        for shift in [0, 7, 14] {
+            let byte = bytes[*at];
+            *at += 1;
+            value |= usize::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    /// A legacy message taken apart.
+    struct Message {
+        header: [u8; 3],
+        accounts: Vec<[u8; 32]>,
+        blockhash: [u8; 32],
+        /// Program index, account indices and data of each instruction
+        instructions: Vec<(u8, Vec<u8>, Vec<u8>)>,
+    }
+
+    fn decode_message(bytes: &[u8]) -> Message {
+        let header = bytes[..3].try_into().unwrap();
+        let mut at = 3;
+        let accounts = (0..compact_u16(bytes, &mut at))
+            .map(|_| {
+                at += 32;
+                bytes[at - 32..at].try_into().unwrap()
+            })
+            .collect();
+        let blockhash = bytes[at..at + 32].try_into().unwrap();
+        at += 32;
+        let instructions = (0..compact_u16(bytes, &mut at))
+            .map(|_| {
+                let program = bytes[at];
+                at += 1;
+                let len = compact_u16(bytes, &mut at);
+                let indices = bytes[at..at + len].to_vec();
+                at += len;
+                let len = compact_u16(bytes, &mut at);
+                let data = bytes[at..at + len].to_vec();
+                at += len;
+                (program, indices, data)
+            })
+            .collect();
+        assert_eq!(at, bytes.len(), "trailing bytes after the message");
+        Message { header, accounts, blockhash, instructions }
+    }
+
+    /// Whether account `index` is writable under the message header.
+    fn is_writable(message: &Message, index: usize) -> bool {
+        let [signers, readonly_signed, readonly_unsigned] = message.header.map(usize::from);
+        match index < signers {
+            true => index < signers - readonly_signed,
+            false => index < message.accounts.len() - readonly_unsigned,
+        }
+    }
+
+    #[test]
+    fn transactions_hold_one_set_authority_in_the_loader_format() {
+        let program = [5; 32];
+        let key = [6; 32];
+        let current = [7; 32];
+        let blockhash = [8; 32];
+        let handoff = Handoff { target: HandoffTarget::Authority { current }, program, rpc: None };
+        let plan = handoff.transaction(&key, Some(blockhash));
+
+        let transaction = BASE64.decode(plan["transaction"].as_str().unwrap()).unwrap();
+        let mut at = 0;
+        assert_eq!(compact_u16(&transaction, &mut at), 1);
+        assert_eq!(&transaction[at..at + 64], &[0; 64]);
+        let message_bytes = &transaction[at + 64..];
+        assert_eq!(BASE64.decode(plan["message"].as_str().unwrap()).unwrap(), message_bytes);
+
+        let message = decode_message(message_bytes);
+        let (program_data, _) = Pubkey::find_program_address(&[&program], &Pubkey::new_from_array(BPF_LOADER_UPGRADEABLE_ID));
+        assert_eq!(message.blockhash, blockhash);
+        // Only the current authority signs, and it pays
+        assert_eq!(message.header[0], 1);
+        assert_eq!(message.accounts[0], current);
+
+        // SetAuthority: [program data (writable), current authority
+        // (signer), new authority (read-only)], data the u32 variant index
+        let [(program_index, indices, data)] = &message.instructions[..] else {
+            panic!("expected one instruction");
+        };
+        assert_eq!(message.accounts[usize::from(*program_index)], BPF_LOADER_UPGRADEABLE_ID);
+        let accounts: Vec<_> = indices.iter().map(|&index| message.accounts[usize::from(index)]).collect();
+        assert_eq!(accounts, [program_data.to_bytes(), current, key]);
+        let writable: Vec<_> = indices.iter().map(|&index| is_writable(&message, usize::from(index))).collect();
+        assert_eq!(writable, [true, true, false]);
+        assert!(usize::from(indices[1]) < usize::from(message.header[0]));
+        assert_eq!(data, &[4, 0, 0, 0]);
+        assert!(!is_writable(&message, usize::from(*program_index)));
+
+        assert_eq!(plan["programData"], program_data.to_string());
+        assert_eq!(plan["newAuthority"], encode(&key));
+    }
+
+    #[test]
+    fn squads_handoffs_move_the_authority_to_the_vault() {
+        let multisig = [9; 32];
+        let target = HandoffTarget::parse(&format!("squads:{}", encode(&multisig))).unwrap();
+        let HandoffTarget::Squads { vault, .. } = target else {
+            panic!("expected a Squads target");
+        };
+        let key = [6; 32];
+        let plan = Handoff { target, program: [5; 32], rpc: None }.transaction(&key, None);
+
+        let message = decode_message(&BASE64.decode(plan["message"].as_str().unwrap()).unwrap());
+        assert_eq!(message.blockhash, [0; 32]);
+        let (_, indices, _) = &message.instructions[0];
+        assert_eq!(message.accounts[usize::from(indices[1])], key);
+        assert_eq!(message.accounts[usize::from(indices[2])], vault);
+        assert_eq!(plan["multisig"], encode(&multisig));
+        assert!(HandoffTarget::parse("vault:abc").is_err());
+    }
+}
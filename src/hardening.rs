@@ -0,0 +1,200 @@
+//! `--hardened` and `--air-gapped`: lock the process down for offline
+//! generation of high-value keys.
+//!
+//! Hardening installs a seccomp filter on every thread that makes the
+//! network system calls, and the ones a compromised process would use to
+//! escape or inspect others, fail with `EPERM`. It also marks the process
+//! non-dumpable, so no core dump or same-user debugger can read secret keys
+//! out of its memory. The flags that send keys or events elsewhere are
+//! refused when the command line is parsed, so nothing is left to fail later.
+//!
+//! `--air-gapped` also refuses to start while any network interface other
+//! than loopback is up.
+
+use crate::cli_error::CliError;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+/// Where Linux lists the network interfaces.
+#[cfg(target_os = "linux")]
+const NET_CLASS_DIR: &str = "/sys/class/net";
+
+/// Network interfaces listed in `net_class_dir` that are up, other than
+/// loopback. An interface counts as up when its operational state says so,
+/// or when the driver does not report one but the link has a carrier, as for
+/// most tunnels.
+#[cfg(target_os = "linux")]
+fn active_interfaces(net_class_dir: &Path) -> Result<Vec<String>, CliError> {
+    use std::fs;
+
+    let read = |path: &Path| fs::read_to_string(path).map(|value| value.trim().to_string()).unwrap_or_default();
+    let list_error = |e: std::io::Error| CliError::AirGap(format!("failed to list {}: {e}", net_class_dir.display()));
+    let mut active = Vec::new();
+    for entry in fs::read_dir(net_class_dir).map_err(list_error)? {
+        let entry = entry.map_err(list_error)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == "lo" {
+            continue;
+        }
+        let up = match read(&entry.path().join("operstate")).as_str() {
+            "up" => true,
+            "unknown" => read(&entry.path().join("carrier")) == "1",
+            _ => false,
+        };
+        if up {
+            active.push(name);
+        }
+    }
+    active.sort();
+    Ok(active)
+}
+
+/// Fails if any network interface other than loopback is up.
+#[cfg(target_os = "linux")]
+pub fn check_air_gap() -> Result<(), CliError> {
+    check_interfaces(Path::new(NET_CLASS_DIR))
+}
+
+#[cfg(target_os = "linux")]
+fn check_interfaces(net_class_dir: &Path) -> Result<(), CliError> {
+    let active = active_interfaces(net_class_dir)?;
+    if active.is_empty() {
+        println!("Air gap: no network interface is up");
+        return Ok(());
+    }
+    Err(CliError::AirGap(format!(
+        "network interface(s) {} are up; disconnect them or bring them down first",
+        active.join(", ")
+    )))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn check_air_gap() -> Result<(), CliError> {
+    Err(CliError::AirGap("checking network interfaces is only supported on Linux".to_string()))
+}
+
+/// System calls that fail with `EPERM` once the process is hardened:
+/// creating, connecting and accepting sockets, running other programs,
+/// reading or writing other processes' memory, and the kernel interfaces no
+/// key generator needs. Socket pairs only connect the process to itself and
+/// stay allowed, since signal handling uses one.
+#[cfg(target_os = "linux")]
+const BLOCKED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_socket,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept,
+    libc::SYS_accept4,
+    libc::SYS_io_uring_setup,
+    libc::SYS_io_uring_enter,
+    libc::SYS_io_uring_register,
+    libc::SYS_execve,
+    libc::SYS_execveat,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_kexec_load,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_reboot,
+];
+
+/// Makes the process non-dumpable and installs the seccomp filter on every
+/// thread. The filter cannot be removed, and threads spawned later inherit
+/// it.
+#[cfg(target_os = "linux")]
+pub fn apply() -> Result<(), CliError> {
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, TargetArch};
+    use std::collections::BTreeMap;
+
+    // SAFETY: PR_SET_DUMPABLE takes a plain integer and touches no memory
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+        return Err(CliError::Hardening(format!(
+            "failed to disable core dumps: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+
+    let arch = TargetArch::try_from(std::env::consts::ARCH).map_err(|e| CliError::Hardening(e.to_string()))?;
+    let rules = BLOCKED_SYSCALLS.iter().map(|&syscall| (syscall, Vec::new())).collect::<BTreeMap<_, _>>();
+    let filter = SeccompFilter::new(rules, SeccompAction::Allow, SeccompAction::Errno(libc::EPERM as u32), arch)
+        .map_err(|e| CliError::Hardening(e.to_string()))?;
+    let program = BpfProgram::try_from(filter).map_err(|e| CliError::Hardening(e.to_string()))?;
+    seccompiler::apply_filter_all_threads(&program).map_err(|e| CliError::Hardening(e.to_string()))?;
+    println!("Hardened: network system calls blocked by seccomp, core dumps disabled");
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply() -> Result<(), CliError> {
+    Err(CliError::Hardening("--hardened is only supported on Linux".to_string()))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A fake `/sys/class/net` with each interface's `operstate` and
+    /// `carrier`, leaving out files given as `None`.
+    fn net_class_dir(interfaces: &[(&str, Option<&str>, Option<&str>)]) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (interface, operstate, carrier) in interfaces {
+            let path = dir.path().join(interface);
+            fs::create_dir_all(&path).unwrap();
+            if let Some(operstate) = operstate {
+                fs::write(path.join("operstate"), format!("{operstate}\n")).unwrap();
+            }
+            if let Some(carrier) = carrier {
+                fs::write(path.join("carrier"), format!("{carrier}\n")).unwrap();
+            }
+        }
+        dir
+    }
+
+    #[test]
+    fn interfaces_up_are_listed_except_loopback() {
+        let dir = net_class_dir(
+            &[
+                ("lo", Some("unknown"), Some("1")),
+                ("eth0", Some("down"), Some("0")),
+                ("wlan0", Some("up"), None),
+                ("tun0", Some("unknown"), Some("1")),
+                ("dummy0", Some("unknown"), Some("0")),
+                ("veth0", None, None),
+            ],
+        );
+        assert_eq!(active_interfaces(dir.path()).unwrap(), ["tun0", "wlan0"]);
+        let error = check_interfaces(dir.path()).unwrap_err().to_string();
+        assert!(error.contains("tun0, wlan0 are up"), "{error}");
+    }
+
+    #[test]
+    fn loopback_and_interfaces_down_pass() {
+        let dir = net_class_dir(&[("lo", Some("unknown"), Some("1")), ("eth0", Some("down"), Some("0"))]);
+        assert!(check_interfaces(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn missing_interface_list_fails() {
+        let dir = net_class_dir(&[]);
+        assert!(matches!(check_interfaces(&dir.path().join("missing")), Err(CliError::AirGap(_))));
+    }
+}
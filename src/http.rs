@@ -0,0 +1,283 @@
+//! A minimal HTTP/1.1 server for health, status and control endpoints.
+//!
+//! Each connection is handled on its own thread and closed after its
+//! response, which is all that probes, monitoring scrapers and the daemon's
+//! API clients need. A client gets a fixed time to send its whole request,
+//! and the size of the request line, headers and body is capped, so a slow
+//! or oversized request only ties up its own thread.
+
+use std::io::{self, BufRead as _, BufReader, Read, Write as _};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use doppler_keygen::error::DopplerError;
+
+use crate::cli_error::CliError;
+use crate::redact;
+
+/// How long a client may take to send its whole request.
+const REQUEST_DEADLINE: Duration = Duration::from_secs(10);
+
+/// How long writing a response may take.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest request line and headers accepted, together.
+const MAX_HEAD: u64 = 16 * 1024;
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 64 * 1024;
+
+/// Connections handled at once; further ones are closed unanswered.
+const MAX_CONNECTIONS: usize = 64;
+
+/// The parts of a request that handlers look at.
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// The path without any query string
+    pub path: String,
+    /// Header names in lowercase, with their values
+    pub headers: Vec<(String, String)>,
+    /// The body, empty unless the request has a `Content-Length`
+    pub body: String,
+}
+
+impl Request {
+    /// The value of the header called `name`, which must be lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &serde_json::Value) -> Response {
+        Response { status, content_type: "application/json", body: body.to_string() }
+    }
+
+    pub fn text(status: u16, body: &str) -> Response {
+        Response { status, content_type: "text/plain; charset=utf-8", body: format!("{body}\n") }
+    }
+}
+
+/// Listens on `addr` and answers each request with `handler`, accepting
+/// connections on a thread named `name`. Returns the bound address, which
+/// has the actual port when the port of `addr` is 0.
+pub fn serve<F>(addr: SocketAddr, name: &str, handler: F) -> Result<SocketAddr, CliError>
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener =
+        TcpListener::bind(addr).map_err(|e| CliError::Usage(format!("Failed to listen on {addr}: {e}")))?;
+    let addr = listener.local_addr().map_err(DopplerError::SpawnThread)?;
+
+    let handler = Arc::new(handler);
+    let connections = Arc::new(AtomicUsize::new(0));
+    let connection_name = format!("{name}-conn");
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                let handler = Arc::clone(&handler);
+                let finished = Arc::clone(&connections);
+                let spawned = thread::Builder::new().name(connection_name.clone()).spawn(move || {
+                    // A client that hangs up or sends garbage only loses its own request
+                    let _ = handle(stream, &*handler);
+                    finished.fetch_sub(1, Ordering::SeqCst);
+                });
+                if spawned.is_err() {
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        })
+        .map_err(DopplerError::SpawnThread)?;
+    Ok(addr)
+}
+
+/// Reads from a stream until a deadline, after which every read fails.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request deadline passed"));
+        }
+        // Each read may only wait for what is left of the deadline, so a
+        // client trickling bytes in can't stretch it
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+fn handle<F>(stream: TcpStream, handler: &F) -> io::Result<()>
+where
+    F: Fn(&Request) -> Response,
+{
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let deadline = Instant::now() + REQUEST_DEADLINE;
+    let mut reader = BufReader::new(DeadlineReader { stream: &stream, deadline }).take(MAX_HEAD);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    if !request_line.ends_with('\n') && reader.limit() == 0 {
+        return refuse(&stream, deadline, &Response::text(414, "Request line too long"));
+    }
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return refuse(&stream, deadline, &Response::text(400, "Bad request"));
+    };
+    let path = target.split_once('?').map_or(target, |(path, _)| path).to_string();
+    let method = method.to_string();
+
+    let mut headers = Vec::new();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        if !header.ends_with('\n') && reader.limit() == 0 {
+            return refuse(&stream, deadline, &Response::text(431, "Request headers too large"));
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim().to_string());
+        if name == "content-length" {
+            let Ok(length) = value.parse::<usize>() else {
+                return refuse(&stream, deadline, &Response::text(400, "Bad Content-Length"));
+            };
+            content_length = length;
+        }
+        headers.push((name, value));
+    }
+    if content_length > MAX_BODY {
+        return refuse(&stream, deadline, &Response::text(413, "Request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.set_limit(content_length as u64);
+    reader.read_exact(&mut body)?;
+    let Ok(body) = String::from_utf8(body) else {
+        return refuse(&stream, deadline, &Response::text(400, "Request body is not UTF-8"));
+    };
+
+    write_response(&stream, &handler(&Request { method, path, headers, body }))
+}
+
+/// Answers a request that is refused before all of it was read.
+fn refuse(stream: &TcpStream, deadline: Instant, response: &Response) -> io::Result<()> {
+    write_response(stream, response)?;
+    // Closing with unread input makes the kernel reset the connection, which
+    // can throw the response away before the client reads it
+    stream.shutdown(Shutdown::Write)?;
+    let mut rest = DeadlineReader { stream, deadline }.take(MAX_HEAD + MAX_BODY as u64);
+    let _ = io::copy(&mut rest, &mut io::sink());
+    Ok(())
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> io::Result<()> {
+    let body = redact::scrub(&response.body);
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves a handler that echoes each request's method, path and body.
+    fn echo_server() -> SocketAddr {
+        serve(SocketAddr::from(([127, 0, 0, 1], 0)), "test-http", |request| {
+            Response::text(200, &format!("{} {} {}", request.method, request.path, request.body))
+        })
+        .unwrap()
+    }
+
+    /// Sends `raw` and returns the whole response.
+    fn send(addr: SocketAddr, raw: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(raw).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn requests_reach_the_handler() {
+        let addr = echo_server();
+        let response = send(addr, b"POST /jobs?x=1 HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with("POST /jobs hello\n"), "{response}");
+    }
+
+    #[test]
+    fn oversized_requests_are_refused() {
+        let addr = echo_server();
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEAD as usize));
+        assert!(send(addr, long_line.as_bytes()).starts_with("HTTP/1.1 414 "));
+
+        let many_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X-Padding: aaaaaaaaaaaaaaaa\r\n".repeat(1024));
+        assert!(send(addr, many_headers.as_bytes()).starts_with("HTTP/1.1 431 "));
+
+        let large_body = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert!(send(addr, large_body.as_bytes()).starts_with("HTTP/1.1 413 "));
+    }
+
+    #[test]
+    fn a_stalled_client_does_not_hold_up_others() {
+        let addr = echo_server();
+        // Sends half a request line and then nothing
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled.write_all(b"GET /slo").unwrap();
+
+        let started = Instant::now();
+        assert!(send(addr, b"GET /fast HTTP/1.1\r\n\r\n").ends_with("GET /fast \n"));
+        assert!(started.elapsed() < REQUEST_DEADLINE);
+    }
+}
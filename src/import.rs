@@ -0,0 +1,105 @@
+//! `import`: bringing keypair files written by other tools into an output
+//! directory.
+//!
+//! `solana-keygen grind` and other vanity grinders leave keypairs behind
+//! under names like `<pubkey>.json`, `id.json` or `wallet-3.txt`, as JSON
+//! byte arrays, base58 strings or raw bytes. `import` finds them under the
+//! directories it is given, saves each key to the output directory as
+//! `<pubkey>.json`, encrypted to the machine key unless `--plaintext` is
+//! given, and records it in the results database with the analysis of its
+//! address. Keys the database already has are skipped, so importing the
+//! same directory twice does nothing the second time.
+
+use solana_keypair::Keypair;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli_error::CliError;
+use crate::keysource;
+use crate::machinekey;
+use crate::migrate;
+
+/// No keypair file is larger than this; anything bigger isn't read.
+const MAX_KEYPAIR_FILE_LEN: u64 = 1024;
+
+/// A keypair found in a file.
+#[derive(Debug)]
+pub struct FoundKeypair {
+    pub path: PathBuf,
+    pub keypair: Keypair,
+}
+
+/// The keypair `path` holds, if it holds one. A hex seed is only taken from
+/// a `.seed` file, since any SHA-256 in hex would pass for one.
+fn read_keypair(path: &Path) -> Option<Keypair> {
+    if fs::metadata(path).ok()?.len() > MAX_KEYPAIR_FILE_LEN {
+        return None;
+    }
+    let contents = fs::read(path).ok()?;
+    if machinekey::is_encrypted(&contents) {
+        return None;
+    }
+    let is_hex = std::str::from_utf8(&contents)
+        .map(str::trim)
+        .is_ok_and(|text| text.len() == 64 && text.bytes().all(|byte| byte.is_ascii_hexdigit()));
+    if is_hex && path.extension().is_none_or(|extension| extension != "seed") {
+        return None;
+    }
+    keysource::parse_keypair_file(&contents).ok()
+}
+
+/// Every keypair file under `dir` and its subdirectories, in path order.
+pub fn find_keypairs(dir: &Path) -> Result<Vec<FoundKeypair>, CliError> {
+    Ok(migrate::files_under(dir)?
+        .into_iter()
+        .filter_map(|path| read_keypair(&path).map(|keypair| FoundKeypair { path, keypair }))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_signer::Signer as _;
+
+    use crate::grind::OutputFormat;
+
+    #[test]
+    fn keypairs_are_found_in_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypairs: Vec<Keypair> = (0..4).map(|_| Keypair::new()).collect();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("id.json"), OutputFormat::Json.encode(&keypairs[0])).unwrap();
+        fs::write(dir.path().join("nested").join("wallet-3.txt"), OutputFormat::Bs58.encode(&keypairs[1])).unwrap();
+        fs::write(dir.path().join("key.bin"), OutputFormat::Bin.encode(&keypairs[2])).unwrap();
+        fs::write(dir.path().join("key.seed"), OutputFormat::Seed.encode(&keypairs[3])).unwrap();
+
+        let found = find_keypairs(dir.path()).unwrap();
+        let names: Vec<_> = found.iter().map(|found| found.path.strip_prefix(dir.path()).unwrap().to_path_buf()).collect();
+        assert_eq!(names, [PathBuf::from("id.json"), "key.bin".into(), "key.seed".into(), "nested/wallet-3.txt".into()]);
+        let pubkeys: Vec<_> = found.iter().map(|found| found.keypair.pubkey()).collect();
+        assert_eq!(pubkeys, [keypairs[0].pubkey(), keypairs[2].pubkey(), keypairs[3].pubkey(), keypairs[1].pubkey()]);
+    }
+
+    #[test]
+    fn malformed_and_unrelated_files_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = Keypair::new();
+        let json = OutputFormat::Json.encode(&keypair);
+        // Truncated and out of range JSON arrays
+        fs::write(dir.path().join("truncated.json"), &json[..json.len() / 2]).unwrap();
+        fs::write(dir.path().join("overflow.json"), format!("[{}]", vec!["256"; 64].join(","))).unwrap();
+        fs::write(dir.path().join("short.json"), "[1,2,3]").unwrap();
+        // A secret that doesn't match its public half
+        let mut mismatched = keypair.to_bytes();
+        mismatched[63] ^= 1;
+        fs::write(dir.path().join("mismatched.bin"), mismatched).unwrap();
+        // A digest in hex is only a seed in a .seed file
+        fs::write(dir.path().join("checksum.txt"), OutputFormat::Seed.encode(&keypair)).unwrap();
+        fs::write(dir.path().join("notes.md"), "# keys\nsee the vault\n").unwrap();
+        fs::write(dir.path().join("big.json"), vec![b' '; MAX_KEYPAIR_FILE_LEN as usize + 1]).unwrap();
+        fs::write(dir.path().join("locked.json.age"), b"age-encryption.org/v1\n-> X25519 abc\n").unwrap();
+
+        assert!(find_keypairs(dir.path()).unwrap().is_empty());
+        assert!(find_keypairs(&dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Guard against emitting the same key twice.
+//!
+//! Every key saved to an output directory is appended to an index file
+//! there, and new matches are checked against it and against a blocklist of
+//! well-known program and sysvar addresses before anything is written. With
+//! a working RNG neither check should ever fire, so a hit means something is
+//! badly wrong.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use crate::cli_error::CliError;
+
+/// Name of the index file kept in the output directory.
+pub const INDEX_FILE: &str = ".doppler-index";
+
+/// Addresses that must never be emitted as freshly generated keys.
+const WELL_KNOWN_ADDRESSES: &[&str] = &[
+    "11111111111111111111111111111111",
+    "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+    "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+    "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL",
+    "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+    "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo",
+    "BPFLoader1111111111111111111111111111111111",
+    "BPFLoader2111111111111111111111111111111111",
+    "BPFLoaderUpgradeab1e11111111111111111111111",
+    "LoaderV411111111111111111111111111111111111",
+    "NativeLoader1111111111111111111111111111111",
+    "ComputeBudget111111111111111111111111111111",
+    "AddressLookupTab1e1111111111111111111111111",
+    "Config1111111111111111111111111111111111111",
+    "Stake11111111111111111111111111111111111111",
+    "Vote111111111111111111111111111111111111111",
+    "Ed25519SigVerify111111111111111111111111111",
+    "KeccakSecp256k11111111111111111111111111111",
+    "Secp256r1SigVerify1111111111111111111111111",
+    "SysvarC1ock11111111111111111111111111111111",
+    "SysvarEpochSchedu1e111111111111111111111111",
+    "SysvarFees111111111111111111111111111111111",
+    "SysvarRecentB1ockHashes11111111111111111111",
+    "SysvarRent111111111111111111111111111111111",
+    "SysvarRewards111111111111111111111111111111",
+    "SysvarS1otHashes111111111111111111111111111",
+    "SysvarS1otHistory11111111111111111111111111",
+    "SysvarStakeHistory1111111111111111111111111",
+    "Sysvar1nstructions1111111111111111111111111",
+];
+
+/// The addresses already emitted to an output directory.
+#[derive(Debug)]
+pub struct KeyIndex {
+    path: PathBuf,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl KeyIndex {
+    /// Loads the index kept in `output_dir`, starting an empty one if the
+    /// directory has none yet.
+    pub fn load(output_dir: &Path) -> Result<KeyIndex, CliError> {
+        let path = output_dir.join(INDEX_FILE);
+        let seen = match fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(source) => return Err(CliError::KeyIndex { path, source }),
+        };
+        Ok(KeyIndex { path, seen: Mutex::new(seen) })
+    }
+
+    /// Number of addresses in the index.
+    pub fn len(&self) -> usize {
+        self.seen.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    /// Refuses `address` if it is a well-known address or was emitted
+    /// before. Checked before a key is saved; the key is only inserted once
+    /// it has been.
+    pub fn check(&self, address: &str) -> Result<(), CliError> {
+        refuse(&self.seen.lock().unwrap_or_else(PoisonError::into_inner), address)
+    }
+
+    /// Records `address` as emitted, refusing it if it is a well-known
+    /// address or was emitted before.
+    pub fn insert(&self, address: &str) -> Result<(), CliError> {
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        refuse(&seen, address)?;
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{address}"))
+            .map_err(|source| CliError::KeyIndex { path: self.path.clone(), source })?;
+        seen.insert(address.to_string());
+        Ok(())
+    }
+
+    /// Drops `address` from the index once its key has been destroyed.
+    pub fn remove(&self, address: &str) -> Result<(), CliError> {
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        if !seen.remove(address) {
+            return Ok(());
+        }
+
+        // Rewrite through a temporary file so a crash leaves either index whole
+        let temp = self.path.with_extension("tmp");
+        fs::read_to_string(&self.path)
+            .and_then(|contents| {
+                let kept: String = contents.lines().filter(|line| line.trim() != address).map(|line| format!("{line}\n")).collect();
+                fs::write(&temp, kept)
+            })
+            .and_then(|()| fs::rename(&temp, &self.path))
+            .map_err(|source| CliError::KeyIndex { path: self.path.clone(), source })?;
+        Ok(())
+    }
+}
+
+/// Refuses `address` if it is well-known or already in `seen`.
+fn refuse(seen: &HashSet<String>, address: &str) -> Result<(), CliError> {
+    if WELL_KNOWN_ADDRESSES.contains(&address) {
+        return Err(CliError::DuplicateKey(format!(
+            "{address} is a well-known program or sysvar address; this should be impossible \
+             and points to a broken random number generator"
+        )));
+    }
+    if seen.contains(address) {
+        return Err(CliError::DuplicateKey(format!(
+            "{address} was already generated into this output directory; a repeat is \
+             astronomically unlikely and points to a broken random number generator"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_addresses_are_gone_after_a_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = KeyIndex::load(dir.path()).unwrap();
+        index.insert("Kept111").unwrap();
+        index.insert("Shredded111").unwrap();
+        assert!(index.insert("Shredded111").is_err());
+        assert!(index.check("Kept111").is_err());
+        assert!(index.check("11111111111111111111111111111111").is_err());
+        index.check("New111").unwrap();
+        assert_eq!(index.len(), 2);
+
+        index.remove("Shredded111").unwrap();
+        assert_eq!(index.len(), 1);
+        let reloaded = KeyIndex::load(dir.path()).unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert!(reloaded.insert("Kept111").is_err());
+        reloaded.insert("Shredded111").unwrap();
+
+    }
+}
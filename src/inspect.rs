@@ -0,0 +1,117 @@
+//! `inspect`: a full report on an address, for judging a found key or any
+//! other address at a glance.
+
+use doppler_keygen::pattern::{VanityPattern, BASE58_ALPHABET};
+
+use crate::codegen::{self, Segment};
+
+/// Shortest run of characters reported as a vanity pattern; shorter runs
+/// are too common to be worth mentioning.
+const MIN_RUN: usize = 3;
+
+/// Builds the report for `address`.
+pub fn report(address: &[u8; 32]) -> String {
+    let encoded = bs58::encode(address).into_string();
+    let mut report = String::new();
+    report.push_str(&format!("Address (base58): {encoded}\n"));
+    report.push_str(&format!("Base58 length: {} characters\n", encoded.len()));
+    report.push_str(&format!("Hex: {}\n", hex::encode(address)));
+    report.push_str(&format!("Zero bytes: {}\n", address.iter().filter(|&&byte| byte == 0).count()));
+
+    report.push_str("\nBytes:              0  1  2  3    4  5  6  7\n");
+    for (segment, bytes) in address.chunks(8).enumerate() {
+        let low: Vec<String> = bytes[..4].iter().map(|byte| format!("{byte:02x}")).collect();
+        let high: Vec<String> = bytes[4..].iter().map(|byte| format!("{byte:02x}")).collect();
+        let range = format!("{}-{}", segment * 8, segment * 8 + 7);
+        report.push_str(&format!("  Segment {segment} {range:>5}: {} | {}\n", low.join(" "), high.join(" ")));
+    }
+
+    report.push_str("\nSegments:\n");
+    let segments = codegen::segments(address);
+    for (segment, value) in segments.iter().enumerate() {
+        let offset = segment * 8;
+        match *value {
+            Segment::Imm32(value) => {
+                let sign = if value < 0 { "negative, bytes 4-7 are 0xff" } else { "positive, bytes 4-7 are 0x00" };
+                report.push_str(&format!("  {segment} (bytes {}-{}): imm32 compatible, {sign}\n", offset, offset + 7));
+                report.push_str(&format!("    i32 value: {value} (0x{:08x})\n", value as u32));
+                report.push_str(&format!("    i64 value: {} (0x{:016x})\n", i64::from(value), i64::from(value) as u64));
+                let tag: String = address[offset..offset + 4]
+                    .iter()
+                    .take_while(|&&byte| byte.is_ascii_graphic() || byte == b' ')
+                    .map(|&byte| byte as char)
+                    .collect();
+                if !tag.is_empty() {
+                    report.push_str(&format!("    ASCII tag: \"{tag}\"\n"));
+                }
+            }
+            Segment::Wide(value) => {
+                report.push_str(&format!(
+                    "  {segment} (bytes {}-{}): not imm32 compatible, u64 0x{value:016x} needs lddw\n",
+                    offset,
+                    offset + 7
+                ));
+            }
+        }
+    }
+    let compatible = segments.iter().filter(|segment| matches!(segment, Segment::Imm32(_))).count();
+    report.push_str(&format!("Imm32 compatible segments: {compatible} of 4\n"));
+
+    report.push_str("\nVanity patterns:\n");
+    let patterns = vanity_patterns(&encoded);
+    if patterns.is_empty() {
+        report.push_str("  none\n");
+    }
+    for pattern in patterns {
+        report.push_str(&format!("  {pattern} (~1 in {:.3e} addresses)\n", 1.0 / pattern.match_probability(false)));
+    }
+    report
+}
+
+/// Length of the run at the start of `chars` in which each character
+/// `follows` the one before it.
+fn run_len(chars: &[char], follows: impl Fn(char, char) -> bool) -> usize {
+    if chars.is_empty() {
+        return 0;
+    }
+    1 + chars.windows(2).take_while(|pair| follows(pair[0], pair[1])).count()
+}
+
+/// The notable patterns `address` satisfies: a repeated character, a run of
+/// digits or a run through the base58 alphabet at either end, and ends that
+/// mirror each other.
+pub fn vanity_patterns(address: &str) -> Vec<VanityPattern> {
+    let position = |c: char| BASE58_ALPHABET.find(c);
+    let runs = |chars: &[char]| -> Vec<usize> {
+        vec![
+            run_len(chars, |a, b| a == b),
+            chars.iter().take_while(|c| c.is_ascii_digit()).count(),
+            run_len(chars, |a, b| position(a).zip(position(b)).is_some_and(|(a, b)| b == a + 1)),
+            run_len(chars, |a, b| position(a).zip(position(b)).is_some_and(|(a, b)| a == b + 1)),
+        ]
+    };
+
+    let chars: Vec<char> = address.chars().collect();
+    let reversed: Vec<char> = chars.iter().rev().copied().collect();
+    let mut patterns = Vec::new();
+    for len in runs(&chars).into_iter().filter(|&len| len >= MIN_RUN) {
+        patterns.push(VanityPattern { prefix: Some(chars[..len].iter().collect()), suffix: None });
+    }
+    for len in runs(&reversed).into_iter().filter(|&len| len >= MIN_RUN) {
+        patterns.push(VanityPattern { prefix: None, suffix: Some(chars[chars.len() - len..].iter().collect()) });
+    }
+
+    // The same characters read inwards from both ends
+    let mirrored = chars.iter().zip(&reversed).take(chars.len() / 2).take_while(|(a, b)| a == b).count();
+    if mirrored * 2 >= MIN_RUN {
+        patterns.push(VanityPattern {
+            prefix: Some(chars[..mirrored].iter().collect()),
+            suffix: Some(chars[chars.len() - mirrored..].iter().collect()),
+        });
+    }
+
+    // Rarest first; a run of one repeated digit is found twice
+    patterns.sort_by(|a, b| a.match_probability(false).total_cmp(&b.match_probability(false)));
+    patterns.dedup();
+    patterns
+}
@@ -0,0 +1,422 @@
+//! `inventory`: a report on the keys held in output directories, for ops
+//! reviews.
+//!
+//! Keys are gathered from the results databases under each directory and
+//! from its key files, so keys saved before the database existed, or
+//! copied in by hand, are counted too. The report counts keys by pattern,
+//! by the kind of imm32 segment they match, by age and by tag, and flags:
+//!
+//! - `duplicate`: the same key is held more than once, in two directories
+//!   or two shards of one
+//! - `plaintext`: a keypair file is not encrypted
+//! - `weak-permissions`: a plaintext keypair or share file can be read by
+//!   other users
+//! - `missing`: the database lists files that are gone
+//! - `unrecorded`: key files with no entry in the results database
+//! - `overdue`: the key is past the rotation date its `--lifetime` set
+//!
+//! It holds public keys and file names only.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use doppler_keygen::imm32;
+
+use crate::cli_error::CliError;
+use crate::encrypt::ENCRYPTED_EXTENSION;
+use crate::kms::WRAPPED_EXTENSION;
+use crate::migrate;
+use crate::grind::OutputFormat;
+use crate::results::{KeyRecord, ResultsDb, RESULTS_FILE};
+use crate::tags::KeyTags;
+
+/// Age brackets keys are counted in, by the most days they cover.
+const AGE_BRACKETS: [(u64, &str); 4] = [(1, "under a day"), (7, "under a week"), (30, "under a month"), (365, "under a year")];
+
+/// How a key's secret is kept in a file, judged by the file's name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Plaintext,
+    Encrypted,
+    Share,
+}
+
+/// The public key a key file is named after, and how it holds the secret.
+/// QR codes and everything else are not key files.
+fn key_file(path: &Path) -> Option<(String, FileKind)> {
+    let name = path.file_name()?.to_str()?;
+    let (pubkey, rest) = name.split_once('.')?;
+    bs58::decode(pubkey).into_vec().ok().filter(|bytes| bytes.len() == 32)?;
+    let parts: Vec<&str> = rest.split('.').collect();
+    let is_format = |part: &str| OutputFormat::parse(part).is_ok();
+    let kind = match parts[..] {
+        [format] if is_format(format) => FileKind::Plaintext,
+        [format, wrapper] if is_format(format) && (wrapper == ENCRYPTED_EXTENSION || wrapper == WRAPPED_EXTENSION) => {
+            FileKind::Encrypted
+        }
+        [share, "json"] if share.starts_with("share-") => FileKind::Share,
+        _ => return None,
+    };
+    Some((pubkey.to_string(), kind))
+}
+
+/// Whether users other than the owner can read `path`.
+#[cfg(unix)]
+fn readable_by_others(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o077 != 0)
+}
+
+#[cfg(not(unix))]
+fn readable_by_others(_path: &Path) -> bool {
+    false
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// One key in the inventory.
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryKey {
+    pub pubkey: String,
+    pub dir: PathBuf,
+    /// `grind` or `import` from the database, or `unrecorded`
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// `imm32 positive`, `imm32 negative` or `none`, for the matched segment
+    /// or else the first compatible one
+    pub segment_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segment: Option<usize>,
+    /// Seconds since the Unix epoch the key was recorded, or its oldest file
+    /// was last changed
+    pub recorded_at: u64,
+    pub age_days: u64,
+    pub files: Vec<PathBuf>,
+    #[serde(flatten)]
+    pub tags: KeyTags,
+    pub flags: Vec<&'static str>,
+}
+
+impl InventoryKey {
+    /// `record` comes with the directory of the database it is from, which
+    /// its file names are relative to.
+    fn new(
+        pubkey: String,
+        dir: &Path,
+        record: Option<(&Path, &KeyRecord)>,
+        files: Vec<(PathBuf, FileKind)>,
+        now: u64,
+    ) -> InventoryKey {
+        let (db_dir, record) = record.unzip();
+        let mut flags = Vec::new();
+        if files.iter().any(|(_, kind)| *kind == FileKind::Plaintext) {
+            flags.push("plaintext");
+        }
+        if files.iter().any(|(path, kind)| *kind != FileKind::Encrypted && readable_by_others(path)) {
+            flags.push("weak-permissions");
+        }
+        let oldest_file = files
+            .iter()
+            .filter_map(|(path, _)| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+            .min()
+            .map_or(now, unix_time);
+        let recorded_at = match record {
+            Some(record) => {
+                if record.files.iter().any(|file| !db_dir.unwrap_or(dir).join(file).exists()) {
+                    flags.push("missing");
+                }
+                if record.rotate_by.is_some_and(|rotate_by| rotate_by <= now) {
+                    flags.push("overdue");
+                }
+                record.recorded_at
+            }
+            None => {
+                flags.push("unrecorded");
+                oldest_file
+            }
+        };
+
+        // The matched address decides the segment, not the key behind it
+        let address = record
+            .and_then(|record| record.address.as_deref())
+            .unwrap_or(&pubkey)
+            .to_string();
+        let bytes: [u8; 32] = bs58::decode(&address)
+            .into_vec()
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .unwrap_or([0xff; 32]);
+        let segment = record
+            .and_then(|record| record.matched_segment)
+            .or_else(|| (0..4).find(|&segment| imm32::segment_is_imm32_compatible(&bytes, segment)));
+        let segment_type = match segment {
+            Some(segment) if bytes[segment * 8 + 3] & 0x80 == 0 => "imm32 positive",
+            Some(_) => "imm32 negative",
+            None => "none",
+        };
+
+        let mut paths: Vec<PathBuf> = files.into_iter().map(|(path, _)| path).collect();
+        if let Some(record) = record {
+            for file in &record.files {
+                let path = db_dir.unwrap_or(dir).join(file);
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        InventoryKey {
+            dir: dir.to_path_buf(),
+            source: record.map_or_else(|| "unrecorded".to_string(), |record| record.source.clone()),
+            pattern: record.and_then(|record| record.pattern.clone()),
+            segment_type: segment_type.to_string(),
+            segment,
+            recorded_at,
+            age_days: now.saturating_sub(recorded_at) / 86_400,
+            files: paths,
+            tags: record.map(|record| record.tags.clone()).unwrap_or_default(),
+            flags,
+            pubkey,
+        }
+    }
+
+    fn age_bracket(&self) -> &'static str {
+        AGE_BRACKETS
+            .iter()
+            .find(|(days, _)| self.age_days < *days)
+            .map_or("a year or more", |(_, bracket)| bracket)
+    }
+}
+
+/// Counts of the keys by each property, and of each flag.
+#[derive(Debug, Default, Serialize)]
+pub struct InventorySummary {
+    pub keys: usize,
+    pub by_pattern: BTreeMap<String, usize>,
+    pub by_segment_type: BTreeMap<String, usize>,
+    pub by_age: BTreeMap<String, usize>,
+    pub by_tag: BTreeMap<String, usize>,
+    pub flags: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Inventory {
+    pub generated_at: u64,
+    pub dirs: Vec<PathBuf>,
+    pub summary: InventorySummary,
+    pub keys: Vec<InventoryKey>,
+}
+
+/// The keys held in `dir`, from its key files and the results databases
+/// under it: a job's shards each keep their own.
+fn scan_dir(dir: &Path, now: u64) -> Result<Vec<InventoryKey>, CliError> {
+    let mut files: BTreeMap<String, Vec<(PathBuf, FileKind)>> = BTreeMap::new();
+    let mut databases = Vec::new();
+    for path in migrate::files_under(dir)? {
+        if path.file_name().is_some_and(|name| name == RESULTS_FILE) {
+            databases.extend(path.parent().map(Path::to_path_buf));
+        } else if let Some((pubkey, kind)) = key_file(&path) {
+            files.entry(pubkey).or_default().push((path, kind));
+        }
+    }
+
+    let mut keys = Vec::new();
+    for db_dir in databases {
+        for record in ResultsDb::open(&db_dir)?.load()? {
+            let key_files = files.remove(&record.pubkey).unwrap_or_default();
+            keys.push(InventoryKey::new(record.pubkey.clone(), dir, Some((&db_dir, &record)), key_files, now));
+        }
+    }
+    for (pubkey, key_files) in files {
+        keys.push(InventoryKey::new(pubkey, dir, None, key_files, now));
+    }
+    Ok(keys)
+}
+
+/// Takes the inventory of `dirs`.
+pub fn take(dirs: &[PathBuf]) -> Result<Inventory, CliError> {
+    let now = unix_time(SystemTime::now());
+    let mut keys = Vec::new();
+    for dir in dirs {
+        keys.extend(scan_dir(dir, now)?);
+    }
+
+    let mut dirs_of: BTreeMap<String, usize> = BTreeMap::new();
+    for key in &keys {
+        *dirs_of.entry(key.pubkey.clone()).or_default() += 1;
+    }
+    for key in &mut keys {
+        if dirs_of[&key.pubkey] > 1 {
+            key.flags.insert(0, "duplicate");
+        }
+    }
+
+    let mut summary = InventorySummary { keys: keys.len(), ..InventorySummary::default() };
+    for key in &keys {
+        *summary.by_pattern.entry(key.pattern.clone().unwrap_or_else(|| "no pattern".to_string())).or_default() += 1;
+        *summary.by_segment_type.entry(key.segment_type.clone()).or_default() += 1;
+        *summary.by_age.entry(key.age_bracket().to_string()).or_default() += 1;
+        for tag in &key.tags.tags {
+            *summary.by_tag.entry(tag.clone()).or_default() += 1;
+        }
+        for flag in &key.flags {
+            *summary.flags.entry((*flag).to_string()).or_default() += 1;
+        }
+    }
+    Ok(Inventory { generated_at: now, dirs: dirs.to_vec(), summary, keys })
+}
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Inventory {
+    /// One row per key, with a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("pubkey,dir,source,pattern,segment_type,segment,recorded_at,age_days,files,tags,meta,flags\n");
+        for key in &self.keys {
+            let fields = [
+                key.pubkey.clone(),
+                key.dir.display().to_string(),
+                key.source.clone(),
+                key.pattern.clone().unwrap_or_default(),
+                key.segment_type.clone(),
+                key.segment.map(|segment| segment.to_string()).unwrap_or_default(),
+                key.recorded_at.to_string(),
+                key.age_days.to_string(),
+                key.files.iter().map(|file| file.display().to_string()).collect::<Vec<_>>().join(";"),
+                key.tags.tags.join(";"),
+                key.tags.meta.iter().map(|(name, value)| format!("{name}={value}")).collect::<Vec<_>>().join(";"),
+                key.flags.join(";"),
+            ];
+            let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            let _ = writeln!(csv, "{}", row.join(","));
+        }
+        csv
+    }
+
+    /// The counts, and the flagged keys.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let dirs: Vec<String> = self.dirs.iter().map(|dir| dir.display().to_string()).collect();
+        let _ = writeln!(text, "Inventory of {}: {} key(s)", dirs.join(", "), self.summary.keys);
+        let sections = [
+            ("By pattern", &self.summary.by_pattern),
+            ("By segment type", &self.summary.by_segment_type),
+            ("By age", &self.summary.by_age),
+            ("By tag", &self.summary.by_tag),
+            ("Flags", &self.summary.flags),
+        ];
+        for (title, counts) in sections {
+            let _ = writeln!(text, "\n{title}:");
+            if counts.is_empty() {
+                let _ = writeln!(text, "  none");
+            }
+            for (name, count) in counts {
+                let _ = writeln!(text, "  {name}: {count}");
+            }
+        }
+        let flagged: Vec<&InventoryKey> = self.keys.iter().filter(|key| !key.flags.is_empty()).collect();
+        if !flagged.is_empty() {
+            let _ = writeln!(text, "\nFlagged keys:");
+            for key in flagged {
+                let _ = writeln!(text, "  {} in {}: {}", key.pubkey, key.dir.display(), key.flags.join(", "));
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_keypair::Keypair;
+    use solana_pubkey::Pubkey;
+    use solana_signer::Signer as _;
+
+    #[test]
+    fn key_files_are_told_apart_by_name() {
+        let pubkey = Keypair::new().pubkey().to_string();
+        let kind = |name: &str| key_file(Path::new(name)).map(|(key, kind)| (key == pubkey, kind));
+        assert_eq!(kind(&format!("{pubkey}.json")), Some((true, FileKind::Plaintext)));
+        assert_eq!(kind(&format!("{pubkey}.seed")), Some((true, FileKind::Plaintext)));
+        assert_eq!(kind(&format!("{pubkey}.json.age")), Some((true, FileKind::Encrypted)));
+        assert_eq!(kind(&format!("{pubkey}.bs58.kms")), Some((true, FileKind::Encrypted)));
+        assert_eq!(kind(&format!("{pubkey}.share-2.json")), Some((true, FileKind::Share)));
+        assert_eq!(kind(&format!("{pubkey}.png")), None);
+        assert_eq!(kind(&format!("{pubkey}.meta.json")), None);
+        assert_eq!(kind("id.json"), None);
+    }
+
+    #[test]
+    fn keys_are_counted_and_flagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("first"), dir.path().join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        let db = ResultsDb::open(&first).unwrap();
+        let now = unix_time(SystemTime::now());
+
+        // Recorded, tagged and encrypted, but past its rotation date
+        let overdue = Keypair::new().pubkey();
+        let mut record = KeyRecord::new(overdue.to_string(), &overdue.to_bytes(), "grind");
+        record.pattern = Some("prefix 'ab'".to_string());
+        record.rotate_by = Some(now - 1);
+        record.files = vec![PathBuf::from(format!("{overdue}.json.age"))];
+        record.tags.add_tag("launch-q3").unwrap();
+        fs::write(first.join(&record.files[0]), b"age").unwrap();
+        db.save(&record).unwrap();
+        // Recorded with a file that has since gone
+        let missing = Keypair::new().pubkey();
+        let mut record = KeyRecord::new(missing.to_string(), &missing.to_bytes(), "import");
+        record.files = vec![PathBuf::from(format!("{missing}.json.age"))];
+        record.tags.add_tag("launch-q3").unwrap();
+        db.save(&record).unwrap();
+        // A plaintext copy held in both directories, recorded in neither
+        let copied = Keypair::new().pubkey();
+        fs::write(first.join(format!("{copied}.json")), b"[]").unwrap();
+        crate::secretfile::write(&second.join(format!("{copied}.json")), b"[]").unwrap();
+
+        let inventory = take(&[first.clone(), second.clone()]).unwrap();
+        let flags = |pubkey: &Pubkey, dir: &Path| {
+            let key = inventory.keys.iter().find(|key| key.pubkey == pubkey.to_string() && key.dir == dir).unwrap();
+            key.flags.clone()
+        };
+        assert_eq!(flags(&overdue, &first), ["overdue"]);
+        assert_eq!(flags(&missing, &first), ["missing"]);
+        #[cfg(unix)]
+        assert_eq!(flags(&copied, &first), ["duplicate", "plaintext", "weak-permissions", "unrecorded"]);
+        assert_eq!(flags(&copied, &second), ["duplicate", "plaintext", "unrecorded"]);
+
+        let summary = &inventory.summary;
+        assert_eq!(summary.keys, 4);
+        assert_eq!(summary.by_pattern, BTreeMap::from([("no pattern".to_string(), 3), ("prefix 'ab'".to_string(), 1)]));
+        assert_eq!(summary.by_tag, BTreeMap::from([("launch-q3".to_string(), 2)]));
+        assert_eq!(summary.by_age, BTreeMap::from([("under a day".to_string(), 4)]));
+        assert_eq!(summary.flags["duplicate"], 2);
+        assert_eq!(summary.flags["unrecorded"], 2);
+
+        let csv = inventory.to_csv();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.contains(",prefix 'ab',"), "{csv}");
+        assert!(inventory.to_text().contains(&format!("{overdue} in {}: overdue", first.display())));
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
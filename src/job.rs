@@ -0,0 +1,250 @@
+//! `job`: one shard of a grind run as a Kubernetes Job.
+//!
+//! Each replica reads its shard index from `DOPPLER_SHARD_INDEX`, or from
+//! `JOB_COMPLETION_INDEX` as set for Indexed Jobs, and the shard count from
+//! `DOPPLER_SHARD_COUNT`. Key counts are divided between the shards, and
+//! each shard writes to its own `shard-N` directory under the output
+//! directory so replicas sharing a volume never touch the same file. The
+//! run ends with a JSON summary on stdout, in the shard directory and in
+//! the container's termination log.
+
+use serde::Serialize;
+use serde_json::json;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use doppler_keygen::progress::ProgressSnapshot;
+use doppler_keygen::stats::StatsSummary;
+
+use crate::cli_error::CliError;
+use crate::config::env_var;
+use crate::http::{self, Response};
+use crate::redact;
+
+/// Where Kubernetes reads a container's termination message by default.
+const TERMINATION_LOG: &str = "/dev/termination-log";
+
+/// Name of the summary written to the shard directory.
+pub const SUMMARY_FILE: &str = "summary.json";
+
+/// Progress reports that may be missed before the job counts as stalled.
+const MISSED_REPORTS: u32 = 3;
+
+/// This replica's part of a sharded run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    /// Reads the shard from the environment. Without any shard variables
+    /// the job is the only shard.
+    pub fn from_env() -> Result<Shard, String> {
+        let parse = |name: &str, value: String| {
+            value.parse::<usize>().map_err(|_| format!("Invalid {name} '{value}'"))
+        };
+        let index = match (env_var("DOPPLER_SHARD_INDEX"), env_var("JOB_COMPLETION_INDEX")) {
+            (Some(index), _) => parse("DOPPLER_SHARD_INDEX", index)?,
+            (None, Some(index)) => parse("JOB_COMPLETION_INDEX", index)?,
+            (None, None) => 0,
+        };
+        let count = env_var("DOPPLER_SHARD_COUNT").map_or(Ok(1), |count| parse("DOPPLER_SHARD_COUNT", count))?;
+        if count == 0 || index >= count {
+            return Err(format!("Shard index {index} is out of range for {count} shard(s)"));
+        }
+        Ok(Shard { index, count })
+    }
+
+    /// This shard's part of `total` keys. The remainder goes to the lowest
+    /// shards, so the parts add up to `total`.
+    pub fn share(&self, total: usize) -> usize {
+        total / self.count + usize::from(self.index < total % self.count)
+    }
+
+    /// The shard's directory under the output directory.
+    pub fn output_dir(&self, output_dir: &Path) -> PathBuf {
+        output_dir.join(format!("shard-{}", self.index))
+    }
+}
+
+/// A key saved by the job.
+#[derive(Debug, Clone, Serialize)]
+struct JobKey {
+    pubkey: String,
+    /// The matched address, when it is derived from the key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    segment: usize,
+}
+
+/// Health and results of a running job, shared by the grind and the probe
+/// endpoints.
+#[derive(Debug)]
+pub struct JobStatus {
+    shard: Shard,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    requested: usize,
+    progress_interval: Duration,
+    /// The shard's output directory, once the run has started
+    output_dir: Option<PathBuf>,
+    ready: bool,
+    /// Time and attempt total of the last progress report
+    last_report: Option<(Instant, u128)>,
+    /// Whether the last report showed no new attempts
+    stalled: bool,
+    keys: Vec<JobKey>,
+    stats: Option<StatsSummary>,
+}
+
+/// The machine-readable outcome of a job.
+#[derive(Debug, Serialize)]
+struct JobSummary {
+    /// `complete` once every key of the shard is saved, else `incomplete` or
+    /// `failed`
+    status: &'static str,
+    shard_index: usize,
+    shard_count: usize,
+    requested: usize,
+    found: usize,
+    keys: Vec<JobKey>,
+    attempts: u128,
+    elapsed_secs: f64,
+    average_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl JobStatus {
+    pub fn new(shard: Shard) -> JobStatus {
+        JobStatus { shard, state: Mutex::new(State::default()) }
+    }
+
+    pub fn shard(&self) -> Shard {
+        self.shard
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records the settings of the run once they are resolved: how many keys
+    /// this shard has to find, how often progress is reported and where the
+    /// shard's output goes.
+    pub fn start(&self, requested: usize, progress_interval: Duration, output_dir: &Path) {
+        let mut state = self.state();
+        state.requested = requested;
+        state.progress_interval = progress_interval;
+        state.output_dir = Some(output_dir.to_path_buf());
+    }
+
+    /// Marks setup as done, so the readiness probe passes.
+    pub fn set_ready(&self) {
+        self.state().ready = true;
+    }
+
+    pub fn record_progress(&self, snapshot: &ProgressSnapshot) {
+        let mut state = self.state();
+        state.stalled = state.last_report.is_some_and(|(_, attempts)| snapshot.total_attempts <= attempts);
+        state.last_report = Some((Instant::now(), snapshot.total_attempts));
+    }
+
+    pub fn record_key(&self, pubkey: String, address: Option<String>, segment: usize) {
+        self.state().keys.push(JobKey { pubkey, address, segment });
+    }
+
+    pub fn record_stats(&self, stats: &StatsSummary) {
+        self.state().stats = Some(stats.clone());
+    }
+
+    /// True unless the workers stopped making attempts, or progress reports
+    /// stopped arriving.
+    fn is_live(&self) -> bool {
+        let state = self.state();
+        let overdue = state
+            .last_report
+            .is_some_and(|(time, _)| time.elapsed() > state.progress_interval * MISSED_REPORTS);
+        !state.stalled && !overdue
+    }
+
+    /// Publishes the summary of the job, with `error` if it failed, and
+    /// returns whether every key of the shard was saved.
+    ///
+    /// The summary is printed as the last line of output and written to the
+    /// shard directory, if the run got that far, and to the termination log,
+    /// if Kubernetes provides one. Failures to write are only reported.
+    pub fn finish(&self, error: Option<String>) -> bool {
+        let summary = self.summary(error);
+        let json = redact::scrub(&serde_json::to_string(&summary).expect("the summary serializes"));
+        if let Some(dir) = &self.state().output_dir {
+            let path = dir.join(SUMMARY_FILE);
+            if let Err(e) = fs::write(&path, format!("{json}\n")) {
+                eprintln!("Warning: failed to write {}: {e}", path.display());
+            }
+        }
+        if Path::new(TERMINATION_LOG).exists() {
+            if let Err(e) = fs::write(TERMINATION_LOG, &json) {
+                eprintln!("Warning: failed to write {TERMINATION_LOG}: {e}");
+            }
+        }
+        println!("{json}");
+        summary.status == "complete"
+    }
+
+    fn summary(&self, error: Option<String>) -> JobSummary {
+        let state = self.state();
+        let (attempts, elapsed, average_rate) = state
+            .stats
+            .as_ref()
+            .map_or((0, Duration::ZERO, 0.0), |stats| (stats.total_attempts, stats.elapsed, stats.average_rate));
+        let status = match &error {
+            Some(_) => "failed",
+            None if state.keys.len() >= state.requested => "complete",
+            None => "incomplete",
+        };
+        JobSummary {
+            status,
+            shard_index: self.shard.index,
+            shard_count: self.shard.count,
+            requested: state.requested,
+            found: state.keys.len(),
+            keys: state.keys.clone(),
+            attempts,
+            elapsed_secs: elapsed.as_secs_f64(),
+            average_rate,
+            error,
+        }
+    }
+}
+
+/// Serves `/healthz` for the liveness probe and `/readyz` for the readiness
+/// probe on `port`. The kubelet probes the pod's address, so this listens on
+/// all interfaces.
+pub fn serve_probes(port: u16, status: Arc<JobStatus>) -> Result<(), CliError> {
+    let addr = http::serve(SocketAddr::from(([0, 0, 0, 0], port)), "probes", move |request| {
+        if request.method != "GET" {
+            return Response::text(405, "Only GET is supported");
+        }
+        let (ok, state) = match request.path.as_str() {
+            "/healthz" => {
+                let live = status.is_live();
+                (live, if live { "live" } else { "stalled" })
+            }
+            "/readyz" => {
+                let ready = status.state().ready;
+                (ready, if ready { "ready" } else { "starting" })
+            }
+            _ => return Response::text(404, "Not found"),
+        };
+        Response::json(if ok { 200 } else { 503 }, &json!({ "status": state }))
+    })?;
+    println!("Serving /healthz and /readyz on port {}", addr.port());
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! The daemon's job store: a SQLite database in the output directory.
+//!
+//! Each job is one row holding its JSON: what was asked for, its state, the
+//! keys found (public keys only), how long it has run and how many attempts
+//! it has seen. The daemon writes a job whenever it changes and checkpoints
+//! the running ones at every progress report, so a daemon restarted after a
+//! crash or an upgrade picks up every unfinished job where it left off.
+
+use rusqlite::{params, Connection, OptionalExtension as _};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cli_error::CliError;
+
+/// Name of the store in the output directory.
+pub const STORE_FILE: &str = "daemon.db";
+
+/// How long to wait for another process to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct JobStore {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl JobStore {
+    /// Opens the store in `dir`, creating it if needed.
+    pub fn open(dir: &Path) -> Result<JobStore, CliError> {
+        let path = dir.join(STORE_FILE);
+        let store_error = |e: rusqlite::Error| CliError::JobStore { path: path.clone(), reason: e.to_string() };
+        let connection = Connection::open(&path).map_err(store_error)?;
+        connection.busy_timeout(BUSY_TIMEOUT).map_err(store_error)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS jobs (
+                    id INTEGER PRIMARY KEY,
+                    state TEXT NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    job TEXT NOT NULL
+                )",
+            )
+            .map_err(store_error)?;
+        Ok(JobStore { path, connection: Mutex::new(connection) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn error(&self, reason: impl ToString) -> CliError {
+        CliError::JobStore { path: self.path.clone(), reason: reason.to_string() }
+    }
+
+    /// Every stored job, in order of id.
+    pub fn load<T: DeserializeOwned>(&self) -> Result<Vec<T>, CliError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT id, job FROM jobs ORDER BY id").map_err(|e| self.error(e))?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| self.error(e))?;
+        rows.map(|row| {
+            let (id, json) = row.map_err(|e| self.error(e))?;
+            serde_json::from_str(&json).map_err(|e| self.error(format!("job {id} is unreadable: {e}")))
+        })
+        .collect()
+    }
+
+    /// The highest job id ever stored.
+    pub fn last_id(&self) -> Result<u64, CliError> {
+        self.connection()
+            .query_row("SELECT MAX(id) FROM jobs", [], |row| row.get::<_, Option<i64>>(0))
+            .optional()
+            .map(|id| id.flatten().map_or(0, |id| id as u64))
+            .map_err(|e| self.error(e))
+    }
+
+    /// Writes `jobs`, given as id, state and job, in one transaction.
+    pub fn save<'a, T, I>(&self, jobs: I) -> Result<(), CliError>
+    where
+        T: Serialize + 'a,
+        I: IntoIterator<Item = (u64, &'a str, &'a T)>,
+    {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(|e| self.error(e))?;
+        for (id, state, job) in jobs {
+            let json = serde_json::to_string(job).map_err(|e| self.error(e))?;
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO jobs (id, state, updated_at, job) VALUES (?1, ?2, ?3, ?4)",
+                    params![id as i64, state, now, json],
+                )
+                .map_err(|e| self.error(e))?;
+        }
+        transaction.commit().map_err(|e| self.error(e))
+    }
+}
@@ -0,0 +1,176 @@
+//! Where a keypair is read from.
+//!
+//! Commands that take a keypair follow the Solana CLI's signer conventions:
+//! the argument is a keypair file path (optionally `file://PATH`),
+//! `prompt://` to enter a seed phrase at a hidden prompt, or `stdin://` to
+//! pipe in a JSON or base58 keypair. `prompt://?key=ACCOUNT[/CHANGE]` and
+//! `prompt://?full-path=m/44'/501'/...` derive the key like the Solana CLI
+//! does. A base58 secret can also be given with `--keypair-base58` or the
+//! `DOPPLER_KEYPAIR` environment variable.
+//!
+//! `usb://ledger?key=0` names a key held by a hardware wallet. Its secret
+//! never leaves the device, so it only serves commands that need the
+//! address.
+//!
+//! Keypair files may be in any of grind's output formats, and files
+//! encrypted to the machine key (`<pubkey>.json.age`) are decrypted first.
+
+use bip39::{Language, Mnemonic};
+use solana_derivation_path::DerivationPath;
+use solana_keypair::seed_derivable::keypair_from_seed_and_derivation_path;
+use solana_keypair::{keypair_from_seed, Keypair};
+use solana_signer::Signer as _;
+use std::fmt;
+use std::io::{self, Read as _};
+use std::path::PathBuf;
+
+use crate::cli_error::CliError;
+use crate::config::env_var;
+use crate::{ledger, machinekey};
+
+/// Environment variable holding a base58 keypair, used when a command is
+/// given no keypair argument.
+pub const KEYPAIR_ENV: &str = "DOPPLER_KEYPAIR";
+
+#[derive(Debug, Clone)]
+pub enum KeypairSource {
+    /// A keypair file in one of grind's output formats, possibly encrypted
+    /// to the machine key.
+    File(PathBuf),
+    /// A seed phrase and passphrase entered at a hidden prompt, derived
+    /// along `derivation` if given.
+    Prompt { derivation: Option<DerivationPath> },
+    /// A JSON byte array or base58 keypair read from standard input.
+    Stdin,
+    /// A base58 keypair given on the command line or in the environment.
+    Base58(String),
+    /// A key on a hardware wallet, `usb://ledger[/PUBKEY]`, derived along
+    /// `derivation` if given. Only its address can be read.
+    Usb { locator: String, derivation: Option<DerivationPath> },
+}
+
+impl KeypairSource {
+    /// Parses a keypair argument: a signer URI or a file path.
+    pub fn parse(arg: &str) -> Result<KeypairSource, CliError> {
+        if let Some(query) = arg.strip_prefix("prompt://") {
+            return Ok(KeypairSource::Prompt { derivation: derivation(arg, "prompt://", query)? });
+        }
+        if arg.starts_with("usb://") {
+            let locator = arg.split('?').next().unwrap_or(arg);
+            let derivation = derivation(arg, "usb://", &arg[locator.len()..])?;
+            return Ok(KeypairSource::Usb { locator: locator.to_string(), derivation });
+        }
+
+        match arg.split_once("://") {
+            Some(("stdin", "")) => Ok(KeypairSource::Stdin),
+            Some(("file", path)) => Ok(KeypairSource::File(PathBuf::from(path))),
+            Some((scheme, _)) if !scheme.contains(['/', '\\']) => {
+                Err(CliError::Usage(format!("Unsupported keypair source '{scheme}://'")))
+            }
+            _ => Ok(KeypairSource::File(PathBuf::from(arg))),
+        }
+    }
+
+    /// The base58 keypair in `DOPPLER_KEYPAIR`, if set.
+    pub fn from_env() -> Option<KeypairSource> {
+        env_var(KEYPAIR_ENV).map(KeypairSource::Base58)
+    }
+
+    /// Reads the public key, from the device for `usb://` and from the
+    /// keypair otherwise.
+    pub fn pubkey(&self) -> Result<[u8; 32], CliError> {
+        match self {
+            KeypairSource::Usb { locator, derivation } => ledger::pubkey(locator, derivation.clone())
+                .map_err(|reason| CliError::ReadKeypair { from: self.to_string(), reason }),
+            _ => Ok(self.read()?.pubkey().to_bytes()),
+        }
+    }
+
+    /// Reads the keypair, prompting on the terminal for `prompt://`.
+    pub fn read(&self) -> Result<Keypair, CliError> {
+        let error = |reason: String| CliError::ReadKeypair { from: self.to_string(), reason };
+        match self {
+            KeypairSource::Usb { .. } => {
+                Err(error("the secret key of a hardware wallet never leaves the device; this command needs a keypair".to_string()))
+            }
+            KeypairSource::File(path) => {
+                let contents = std::fs::read(path).map_err(|e| error(e.to_string()))?;
+                let contents = match machinekey::is_encrypted(&contents) {
+                    true => machinekey::decrypt(&contents).map_err(|e| error(e.to_string()))?,
+                    false => contents,
+                };
+                parse_keypair_file(&contents).map_err(error)
+            }
+            KeypairSource::Stdin => {
+                let mut contents = String::new();
+                io::stdin().read_to_string(&mut contents).map_err(|e| error(e.to_string()))?;
+                parse_keypair(&contents).map_err(error)
+            }
+            KeypairSource::Base58(secret) => parse_keypair(secret).map_err(error),
+            KeypairSource::Prompt { derivation } => {
+                let phrase = rpassword::prompt_password("Seed phrase: ").map_err(|e| error(e.to_string()))?;
+                let mnemonic = Mnemonic::parse_in(Language::English, phrase.trim()).map_err(|e| error(e.to_string()))?;
+                let passphrase =
+                    rpassword::prompt_password("If this seed phrase has an associated passphrase, enter it now. Otherwise, press ENTER to continue: ")
+                        .map_err(|e| error(e.to_string()))?;
+
+                let seed = mnemonic.to_seed(passphrase);
+                match derivation {
+                    Some(derivation) => keypair_from_seed_and_derivation_path(&seed, Some(derivation.clone())),
+                    None => keypair_from_seed(&seed),
+                }
+                .map_err(|e| error(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Parses the `?key=` or `?full-path=` query of a `scheme` argument.
+fn derivation(arg: &str, scheme: &str, query: &str) -> Result<Option<DerivationPath>, CliError> {
+    let derivation = match query.strip_prefix('?').and_then(|query| query.split_once('=')) {
+        _ if query.is_empty() => None,
+        Some(("key", path)) => Some(DerivationPath::from_key_str(path)),
+        Some(("full-path", path)) => Some(DerivationPath::from_absolute_path_str(path)),
+        _ => return Err(CliError::Usage(format!("Unsupported {scheme} query '{query}'; use ?key= or ?full-path="))),
+    };
+    derivation.transpose().map_err(|e| CliError::Usage(format!("Invalid derivation path in '{arg}': {e}")))
+}
+
+impl fmt::Display for KeypairSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeypairSource::File(path) => write!(f, "{}", path.display()),
+            KeypairSource::Prompt { .. } => f.write_str("prompt://"),
+            KeypairSource::Stdin => f.write_str("stdin://"),
+            KeypairSource::Base58(_) => f.write_str("base58 secret"),
+            KeypairSource::Usb { locator, derivation } => {
+                write!(f, "{locator}{}", derivation.as_ref().map(DerivationPath::get_query).unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// Parses a keypair given as a JSON byte array or a base58 string.
+fn parse_keypair(contents: &str) -> Result<Keypair, String> {
+    let contents = contents.trim();
+    let bytes: Vec<u8> = if contents.starts_with('[') {
+        serde_json::from_str(contents).map_err(|e| e.to_string())?
+    } else {
+        bs58::decode(contents).into_vec().map_err(|e| format!("not a JSON byte array or base58 keypair: {e}"))?
+    };
+    Keypair::try_from(bytes.as_slice()).map_err(|e| e.to_string())
+}
+
+/// Parses the contents of a keypair file: a JSON byte array, base58, the 64
+/// raw bytes of `--output-format bin`, or the hex seed of `seed`.
+pub fn parse_keypair_file(contents: &[u8]) -> Result<Keypair, String> {
+    match std::str::from_utf8(contents).map(str::trim) {
+        Ok(text) if text.len() == 64 && text.bytes().all(|byte| byte.is_ascii_hexdigit()) => {
+            let seed = hex::decode(text).map_err(|e| e.to_string())?;
+            Ok(Keypair::new_from_array(seed.try_into().expect("64 hex digits are 32 bytes")))
+        }
+        Ok(text) if text.starts_with('[') || text.bytes().all(|byte| byte.is_ascii_alphanumeric()) => parse_keypair(text),
+        _ if contents.len() == 64 => Keypair::try_from(contents).map_err(|e| e.to_string()),
+        _ => Err("not a JSON, base58, binary or seed keypair file".to_string()),
+    }
+}
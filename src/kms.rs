@@ -0,0 +1,503 @@
+//! Envelope encryption of keypair files with a cloud KMS key.
+//!
+//! With `--kms-key` every keypair file is encrypted with a fresh AES-256-GCM
+//! data key, and only the data key is sent to AWS KMS or Google Cloud KMS to
+//! be wrapped. The file on disk holds the wrapped data key and the
+//! ciphertext, so recovering the secret takes a KMS decrypt call by a
+//! principal allowed to use the key. The public key is bound to both layers
+//! (encryption context on AWS, additional authenticated data on GCP and in
+//! the AES-GCM layer), so envelopes cannot be swapped between keys.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+use crate::cli_error::CliError;
+use crate::config::env_var;
+
+/// Extension appended to the names of wrapped keypair files.
+pub const WRAPPED_EXTENSION: &str = "kms";
+
+/// How long a single request to the KMS may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A KMS key that wraps the data keys of keypair files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KmsKey {
+    /// An AWS KMS key ARN, alias ARN, or `aws:<key id or alias>`
+    Aws { key_id: String, region: String },
+    /// A Google Cloud KMS key, `projects/.../cryptoKeys/<name>`
+    Gcp { name: String },
+}
+
+impl KmsKey {
+    /// Parses an AWS key ARN (`arn:aws:kms:REGION:ACCOUNT:key/ID`), an AWS
+    /// key id or alias in the region from `AWS_REGION` (`aws:alias/NAME`),
+    /// or a Google Cloud key name (`projects/P/locations/L/keyRings/R/cryptoKeys/K`).
+    pub fn parse(value: &str) -> Result<KmsKey, String> {
+        if value.starts_with("arn:aws:kms:") {
+            let region = value.split(':').nth(3).filter(|region| !region.is_empty());
+            return match region {
+                Some(region) => Ok(KmsKey::Aws { key_id: value.to_string(), region: region.to_string() }),
+                None => Err(format!("Invalid KMS key '{value}': the ARN has no region")),
+            };
+        }
+        if let Some(key_id) = value.strip_prefix("aws:") {
+            let region = env_var("AWS_REGION")
+                .or_else(|| env_var("AWS_DEFAULT_REGION"))
+                .ok_or_else(|| format!("KMS key '{value}' needs AWS_REGION to be set; or pass the full key ARN"))?;
+            return Ok(KmsKey::Aws { key_id: key_id.to_string(), region });
+        }
+        if value.starts_with("projects/") && value.contains("/cryptoKeys/") {
+            return Ok(KmsKey::Gcp { name: value.to_string() });
+        }
+        Err(format!(
+            "Invalid KMS key '{value}' (expected an AWS key ARN, aws:<key id or alias>, \
+             or projects/.../cryptoKeys/<name>)"
+        ))
+    }
+
+    fn provider(&self) -> &'static str {
+        match self {
+            KmsKey::Aws { .. } => "aws",
+            KmsKey::Gcp { .. } => "gcp",
+        }
+    }
+}
+
+/// Contents of a wrapped keypair file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    pubkey: String,
+    /// `aws` or `gcp`
+    provider: String,
+    /// The key id, ARN or Google Cloud key name that wrapped the data key
+    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
+    /// Base64 KMS ciphertext of the AES-256-GCM data key
+    wrapped_key: String,
+    /// Base64 AES-GCM nonce
+    nonce: String,
+    /// Base64 AES-GCM ciphertext of the keypair file
+    ciphertext: String,
+}
+
+/// Wraps keypair files with a KMS key.
+#[derive(Debug)]
+pub struct KmsWrapper {
+    key: KmsKey,
+    agent: ureq::Agent,
+}
+
+impl KmsWrapper {
+    pub fn new(key: KmsKey) -> KmsWrapper {
+        KmsWrapper { key, agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build() }
+    }
+
+    /// Encrypts `plaintext`, the contents of the keypair file for `pubkey`,
+    /// and returns the envelope to write in its place.
+    pub fn seal(&self, pubkey: &str, plaintext: &[u8]) -> Result<Vec<u8>, CliError> {
+        let mut data_key = Zeroizing::new([0u8; 32]);
+        let mut nonce = [0u8; 12];
+        getrandom::getrandom(data_key.as_mut())
+            .and_then(|()| getrandom::getrandom(&mut nonce))
+            .map_err(|e| CliError::Kms(format!("no random source available: {e}")))?;
+
+        let ciphertext = Aes256Gcm::new((&*data_key).into())
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: pubkey.as_bytes() })
+            .map_err(|_| CliError::Kms("AES-GCM encryption failed".to_string()))?;
+        let wrapped_key = wrap(&self.agent, &self.key, pubkey, data_key.as_ref()).map_err(CliError::Kms)?;
+
+        let (key, region) = match &self.key {
+            KmsKey::Aws { key_id, region } => (key_id.clone(), Some(region.clone())),
+            KmsKey::Gcp { name } => (name.clone(), None),
+        };
+        let envelope = Envelope {
+            pubkey: pubkey.to_string(),
+            provider: self.key.provider().to_string(),
+            key,
+            region,
+            wrapped_key: BASE64.encode(wrapped_key),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        Ok(serde_json::to_vec_pretty(&envelope).expect("envelopes always serialize"))
+    }
+}
+
+/// Unwraps the data key of an envelope written by [`KmsWrapper::seal`] and
+/// returns the original keypair file contents. The data key and the
+/// contents are wiped from memory once dropped.
+pub fn open(contents: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+    let envelope: Envelope = serde_json::from_slice(contents).map_err(|e| format!("Not a wrapped keypair file: {e}"))?;
+    let key = match (envelope.provider.as_str(), envelope.region) {
+        ("aws", Some(region)) => KmsKey::Aws { key_id: envelope.key, region },
+        ("gcp", _) => KmsKey::Gcp { name: envelope.key },
+        (provider, _) => return Err(format!("Unsupported KMS provider '{provider}'")),
+    };
+    let decode = |field: &str, value: &str| BASE64.decode(value).map_err(|e| format!("Invalid {field}: {e}"));
+    let wrapped_key = decode("wrapped_key", &envelope.wrapped_key)?;
+    let nonce = decode("nonce", &envelope.nonce)?;
+    let ciphertext = decode("ciphertext", &envelope.ciphertext)?;
+    if nonce.len() != 12 {
+        return Err("Invalid nonce: expected 12 bytes".to_string());
+    }
+
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    let data_key = unwrap(&agent, &key, &envelope.pubkey, &wrapped_key)?;
+    if data_key.len() != 32 {
+        return Err("The KMS returned a data key of the wrong length".to_string());
+    }
+    Aes256Gcm::new_from_slice(&data_key)
+        .expect("data keys are 32 bytes")
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: envelope.pubkey.as_bytes() })
+        .map(Zeroizing::new)
+        .map_err(|_| format!("Decryption failed; the envelope for {} was modified", envelope.pubkey))
+}
+
+fn wrap(agent: &ureq::Agent, key: &KmsKey, pubkey: &str, data_key: &[u8]) -> Result<Vec<u8>, String> {
+    match key {
+        KmsKey::Aws { key_id, region } => {
+            let response = aws_request(
+                agent,
+                region,
+                "Encrypt",
+                &json!({
+                    "KeyId": key_id,
+                    "Plaintext": BASE64.encode(data_key),
+                    "EncryptionContext": { "pubkey": pubkey },
+                }),
+            )?;
+            response_field(&response, "CiphertextBlob")
+        }
+        KmsKey::Gcp { name } => {
+            let response = gcp_request(
+                agent,
+                name,
+                "encrypt",
+                &json!({
+                    "plaintext": BASE64.encode(data_key),
+                    "additionalAuthenticatedData": BASE64.encode(pubkey),
+                }),
+            )?;
+            response_field(&response, "ciphertext")
+        }
+    }
+}
+
+fn unwrap(agent: &ureq::Agent, key: &KmsKey, pubkey: &str, wrapped_key: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+    match key {
+        KmsKey::Aws { key_id, region } => {
+            let response = aws_request(
+                agent,
+                region,
+                "Decrypt",
+                &json!({
+                    "KeyId": key_id,
+                    "CiphertextBlob": BASE64.encode(wrapped_key),
+                    "EncryptionContext": { "pubkey": pubkey },
+                }),
+            )?;
+            response_field(&response, "Plaintext").map(Zeroizing::new)
+        }
+        KmsKey::Gcp { name } => {
+            let response = gcp_request(
+                agent,
+                name,
+                "decrypt",
+                &json!({
+                    "ciphertext": BASE64.encode(wrapped_key),
+                    "additionalAuthenticatedData": BASE64.encode(pubkey),
+                }),
+            )?;
+            response_field(&response, "plaintext").map(Zeroizing::new)
+        }
+    }
+}
+
+/// Decodes the base64 `field` of a KMS response.
+fn response_field(response: &Value, field: &str) -> Result<Vec<u8>, String> {
+    let value = response
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("The KMS response has no {field}"))?;
+    BASE64.decode(value).map_err(|e| format!("The KMS response has an invalid {field}: {e}"))
+}
+
+/// Calls a Google Cloud KMS method on `name`, authenticating with the
+/// access token in `GOOGLE_OAUTH_ACCESS_TOKEN`.
+fn gcp_request(agent: &ureq::Agent, name: &str, method: &str, body: &Value) -> Result<Value, String> {
+    let token = env_var("GOOGLE_OAUTH_ACCESS_TOKEN").ok_or_else(|| {
+        "Google Cloud KMS needs an access token in GOOGLE_OAUTH_ACCESS_TOKEN \
+         (e.g. from `gcloud auth print-access-token`)"
+            .to_string()
+    })?;
+    let endpoint = env_var("GOOGLE_CLOUD_KMS_ENDPOINT").unwrap_or_else(|| "https://cloudkms.googleapis.com".to_string());
+
+    agent
+        .post(&format!("{}/v1/{name}:{method}", endpoint.trim_end_matches('/')))
+        .set("Authorization", &format!("Bearer {token}"))
+        .send_json(body)
+        .map_err(|e| request_error(&format!("Google Cloud KMS {method}"), e))?
+        .into_json()
+        .map_err(|e| format!("Google Cloud KMS {method} returned an unexpected response: {e}"))
+}
+
+/// Calls an AWS KMS action, signing the request with SigV4 using the
+/// credentials in `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+/// `AWS_SESSION_TOKEN`.
+fn aws_request(agent: &ureq::Agent, region: &str, action: &str, body: &Value) -> Result<Value, String> {
+    let (Some(access_key), Some(secret_key)) = (env_var("AWS_ACCESS_KEY_ID"), env_var("AWS_SECRET_ACCESS_KEY")) else {
+        return Err("AWS KMS needs credentials in AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY".to_string());
+    };
+    let session_token = env_var("AWS_SESSION_TOKEN");
+    let endpoint = env_var("AWS_ENDPOINT_URL_KMS")
+        .or_else(|| env_var("AWS_ENDPOINT_URL"))
+        .unwrap_or_else(|| format!("https://kms.{region}.amazonaws.com"));
+    let endpoint = endpoint.trim_end_matches('/');
+    let host = endpoint.split_once("://").map_or(endpoint, |(_, host)| host);
+
+    let payload = body.to_string();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let amz_date = amz_date(now);
+    let target = format!("TrentService.{action}");
+
+    let mut headers = vec![
+        ("content-type", "application/x-amz-json-1.1"),
+        ("host", host),
+        ("x-amz-date", amz_date.as_str()),
+        ("x-amz-target", target.as_str()),
+    ];
+    if let Some(session_token) = &session_token {
+        headers.push(("x-amz-security-token", session_token));
+    }
+    headers.sort_by_key(|(name, _)| *name);
+    let authorization = sigv4_authorization(&access_key, &secret_key, region, "kms", &amz_date, &headers, &payload);
+
+    let mut request = agent.post(&format!("{endpoint}/")).set("Authorization", &authorization);
+    for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+        request = request.set(name, value);
+    }
+    request
+        .send_string(&payload)
+        .map_err(|e| request_error(&format!("AWS KMS {action}"), e))?
+        .into_json()
+        .map_err(|e| format!("AWS KMS {action} returned an unexpected response: {e}"))
+}
+
+/// Builds the SigV4 `Authorization` header for a POST to `/` with the given
+/// headers, which must be lowercase and sorted by name.
+fn sigv4_authorization(
+    access_key: &str,
+    secret_key: &str,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+    headers: &[(&str, &str)],
+    payload: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{name}:{}\n", value.trim())).collect();
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+
+    let canonical_request = format!(
+        "POST\n/\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex::encode(Sha256::digest(payload))
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request))
+    );
+
+    let signing_key = [region, service, "aws4_request"]
+        .into_iter()
+        .fold(hmac_sha256(format!("AWS4{secret_key}").as_bytes(), date.as_bytes()), |key, part| {
+            hmac_sha256(&key, part.as_bytes())
+        });
+    let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+    format!("AWS4-HMAC-SHA256 Credential={access_key}/{scope}, SignedHeaders={signed_headers}, Signature={signature}")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Formats a Unix timestamp as an ISO 8601 basic UTC time, e.g.
+/// `20150830T123600Z`.
+fn amz_date(unix_seconds: u64) -> String {
+    let (year, month, day) = crate::rotation::utc_date(unix_seconds);
+    let seconds_of_day = unix_seconds % 86_400;
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Describes a failed request, including the error the KMS reported.
+fn request_error(action: &str, error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            format!("{action} failed with HTTP {status}: {}", body.trim())
+        }
+        e => format!("{action} failed: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{self, Response};
+    use std::net::SocketAddr;
+    use std::sync::OnceLock;
+
+    const KEY_NAME: &str = "projects/p/locations/global/keyRings/r/cryptoKeys/doppler";
+
+    // From the AWS Signature Version 4 test suite, which signs for the
+    // service `service` in us-east-1 at 20150830T123600Z
+    const ACCESS_KEY: &str = "AKIDEXAMPLE";
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    const AMZ_DATE: &str = "20150830T123600Z";
+
+    #[test]
+    fn signs_the_post_vanilla_test_suite_request() {
+        let headers = [("host", "example.amazonaws.com"), ("x-amz-date", AMZ_DATE)];
+        assert_eq!(
+            sigv4_authorization(ACCESS_KEY, SECRET_KEY, "us-east-1", "service", AMZ_DATE, &headers, ""),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+        );
+    }
+
+    #[test]
+    fn signs_the_post_x_www_form_urlencoded_test_suite_request() {
+        let headers = [
+            ("content-type", "application/x-www-form-urlencoded"),
+            ("host", "example.amazonaws.com"),
+            ("x-amz-date", AMZ_DATE),
+        ];
+        assert_eq!(
+            sigv4_authorization(ACCESS_KEY, SECRET_KEY, "us-east-1", "service", AMZ_DATE, &headers, "Param1=value1"),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a"
+        );
+    }
+
+    #[test]
+    fn formats_the_test_suite_date() {
+        assert_eq!(amz_date(1_440_938_160), AMZ_DATE);
+    }
+
+    /// Points the Google Cloud KMS client at a stand-in that "wraps" a data
+    /// key by recording it with the key name and additional authenticated
+    /// data, and unwraps it only when both are the same. Every test shares
+    /// it, as the endpoint is read from the environment.
+    fn fake_gcp_kms() {
+        static ENDPOINT: OnceLock<SocketAddr> = OnceLock::new();
+        ENDPOINT.get_or_init(|| {
+            let addr = http::serve(SocketAddr::from(([127, 0, 0, 1], 0)), "test-kms", |request| {
+                let body: Value = serde_json::from_str(&request.body).unwrap();
+                let (name, method) = request.path.trim_start_matches("/v1/").rsplit_once(':').unwrap();
+                match method {
+                    "encrypt" => {
+                        let aad = &body["additionalAuthenticatedData"];
+                        let wrapped = json!({ "name": name, "aad": aad, "key": body["plaintext"] });
+                        Response::json(200, &json!({ "ciphertext": BASE64.encode(wrapped.to_string()) }))
+                    }
+                    _ => {
+                        let wrapped = BASE64.decode(body["ciphertext"].as_str().unwrap()).unwrap();
+                        let wrapped: Value = serde_json::from_slice(&wrapped).unwrap();
+                        if wrapped["name"] != name || wrapped["aad"] != body["additionalAuthenticatedData"] {
+                            return Response::json(400, &json!({ "error": { "message": "Decryption failed" } }));
+                        }
+                        Response::json(200, &json!({ "plaintext": wrapped["key"] }))
+                    }
+                }
+            })
+            .unwrap();
+            std::env::set_var("GOOGLE_CLOUD_KMS_ENDPOINT", format!("http://{addr}"));
+            std::env::set_var("GOOGLE_OAUTH_ACCESS_TOKEN", "test-token");
+            addr
+        });
+    }
+
+    fn sealed(pubkey: &str) -> Value {
+        fake_gcp_kms();
+        let wrapper = KmsWrapper::new(KmsKey::parse(KEY_NAME).unwrap());
+        serde_json::from_slice(&wrapper.seal(pubkey, b"[1,2,3]").unwrap()).unwrap()
+    }
+
+    fn open_json(envelope: &Value) -> Result<Zeroizing<Vec<u8>>, String> {
+        open(envelope.to_string().as_bytes())
+    }
+
+    #[test]
+    fn sealed_files_open() {
+        let envelope = sealed("Pubkey1");
+        assert_eq!(envelope["provider"], "gcp");
+        assert_eq!(envelope["key"], KEY_NAME);
+        assert!(!envelope["ciphertext"].as_str().unwrap().is_empty());
+        assert_eq!(*open_json(&envelope).unwrap(), b"[1,2,3]");
+    }
+
+    #[test]
+    fn another_key_cannot_unwrap() {
+        let mut envelope = sealed("Pubkey1");
+        envelope["key"] = json!("projects/p/locations/global/keyRings/r/cryptoKeys/other");
+        let error = open_json(&envelope).unwrap_err();
+        assert!(error.contains("Google Cloud KMS decrypt failed with HTTP 400"), "{error}");
+    }
+
+    #[test]
+    fn envelopes_cannot_be_moved_or_modified() {
+        let mut moved = sealed("Pubkey1");
+        moved["pubkey"] = json!("Pubkey2");
+        assert!(open_json(&moved).unwrap_err().contains("HTTP 400"));
+
+        let mut modified = sealed("Pubkey1");
+        let mut ciphertext = BASE64.decode(modified["ciphertext"].as_str().unwrap()).unwrap();
+        ciphertext[0] ^= 1;
+        modified["ciphertext"] = json!(BASE64.encode(ciphertext));
+        assert_eq!(open_json(&modified).unwrap_err(), "Decryption failed; the envelope for Pubkey1 was modified");
+    }
+
+    #[test]
+    fn malformed_envelopes_are_refused() {
+        assert!(open(b"[1,2,3]").unwrap_err().starts_with("Not a wrapped keypair file"));
+        let mut envelope = sealed("Pubkey1");
+        envelope["nonce"] = json!(BASE64.encode([0u8; 8]));
+        assert_eq!(open_json(&envelope).unwrap_err(), "Invalid nonce: expected 12 bytes");
+        envelope["provider"] = json!("azure");
+        assert_eq!(open_json(&envelope).unwrap_err(), "Unsupported KMS provider 'azure'");
+    }
+
+    #[test]
+    fn key_names_are_parsed() {
+        assert_eq!(
+            KmsKey::parse("arn:aws:kms:eu-west-1:111122223333:key/abcd").unwrap(),
+            KmsKey::Aws {
+                key_id: "arn:aws:kms:eu-west-1:111122223333:key/abcd".to_string(),
+                region: "eu-west-1".to_string()
+            }
+        );
+        assert_eq!(KmsKey::parse(KEY_NAME).unwrap(), KmsKey::Gcp { name: KEY_NAME.to_string() });
+        assert!(KmsKey::parse("arn:aws:kms::111122223333:key/abcd").unwrap_err().contains("no region"));
+        assert!(KmsKey::parse("vault:transit/doppler").unwrap_err().starts_with("Invalid KMS key"));
+    }
+}
@@ -0,0 +1,33 @@
+//! Addresses of keys held by a Ledger hardware wallet.
+//!
+//! Only the public key is read, through solana-remote-wallet as the Solana
+//! CLI reads it, so `address usb://ledger?key=0` can print the imm32
+//! analysis and constants of an upgrade authority whose secret never
+//! touches the machine. Without `?key=` the device's base key
+//! (`m/44'/501'`) is used, and `usb://ledger/<pubkey>` picks one of several
+//! connected devices. Support needs the `ledger` feature.
+
+use solana_derivation_path::DerivationPath;
+
+/// Reads the public key at `derivation` from the device at `locator`, a
+/// `usb://ledger[/PUBKEY]` URI without its query.
+#[cfg(feature = "ledger")]
+pub fn pubkey(locator: &str, derivation: Option<DerivationPath>) -> Result<[u8; 32], String> {
+    use solana_remote_wallet::locator::Locator;
+    use solana_remote_wallet::remote_keypair::generate_remote_keypair;
+    use solana_remote_wallet::remote_wallet::maybe_wallet_manager;
+    use solana_signer::Signer as _;
+
+    let locator = Locator::new_from_path(locator).map_err(|e| e.to_string())?;
+    let wallet_manager = maybe_wallet_manager()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "no hardware wallet found; connect and unlock it and open the Solana app".to_string())?;
+    let keypair = generate_remote_keypair(locator, derivation.unwrap_or_default(), &wallet_manager, false, "address")
+        .map_err(|e| e.to_string())?;
+    Ok(keypair.pubkey().to_bytes())
+}
+
+#[cfg(not(feature = "ledger"))]
+pub fn pubkey(_locator: &str, _derivation: Option<DerivationPath>) -> Result<[u8; 32], String> {
+    Err("this build has no hardware wallet support; build with --features ledger".to_string())
+}
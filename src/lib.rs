@@ -0,0 +1,26 @@
+//! The search machinery behind doppler-keygen: the imm32 and vanity pattern
+//! predicates, the worker schedulers, and a streaming `Grinder` API. With the
+//! `wasm` feature it also exports a browser-facing grinder.
+
+pub use doppler_core::imm32;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod batch;
+pub mod constraints;
+pub mod derive;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod entropy;
+pub mod error;
+pub mod pattern;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod grinder;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod progress;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod scheduler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod shutdown;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod stats;
+#[cfg(feature = "wasm")]
+pub mod wasm;
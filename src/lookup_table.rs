@@ -0,0 +1,136 @@
+//! `lookup-table`: picks the recent slot that gives an address lookup table
+//! the best address.
+//!
+//! A lookup table lives at a PDA of its authority and the recent slot passed
+//! to `CreateLookupTable`, and that slot must still be in the SlotHashes
+//! sysvar when the transaction lands. Scanning the slots a team could use
+//! shows which one to create the table at.
+
+use core::ops::Range;
+use rayon::prelude::*;
+
+use doppler_keygen::derive;
+use doppler_keygen::pattern::VanityPattern;
+
+/// A lookup table address for one recent slot.
+#[derive(Debug, Clone)]
+pub struct SlotAddress {
+    pub slot: u64,
+    pub address: [u8; 32],
+    /// The segment that comes closest to a 32-bit immediate
+    pub segment: usize,
+    /// How many of that segment's bytes 4-7 already sign-extend byte 3; 4
+    /// means the segment is imm32 compatible
+    pub sign_bytes: usize,
+}
+
+/// Parses `START..END`, a half-open range of slots.
+pub fn parse_slots(value: &str) -> Result<Range<u64>, String> {
+    let (start, end) = value
+        .split_once("..")
+        .ok_or_else(|| format!("Invalid slot range '{value}' (expected START..END)"))?;
+    let parse = |slot: &str| slot.parse::<u64>().map_err(|_| format!("Invalid slot '{slot}' in '{value}'"));
+    let slots = parse(start)?..parse(end)?;
+    if slots.is_empty() {
+        return Err(format!("Slot range '{value}' is empty"));
+    }
+    Ok(slots)
+}
+
+/// Derives the table address `authority` would get at every slot in
+/// `slots` and returns the `top` closest to imm32 compatibility, keeping
+/// only addresses that match one of `patterns` if any are given. Ties go to
+/// the earliest slot.
+pub fn scan(
+    authority: &[u8; 32],
+    slots: Range<u64>,
+    patterns: &[VanityPattern],
+    ignore_case: bool,
+    top: usize,
+) -> Vec<SlotAddress> {
+    let keep_best = |mut best: Vec<SlotAddress>| {
+        best.sort_by(|a, b| b.sign_bytes.cmp(&a.sign_bytes).then(a.slot.cmp(&b.slot)));
+        best.truncate(top);
+        best
+    };
+
+    let best = slots
+        .into_par_iter()
+        .filter_map(|slot| {
+            let (address, _) = derive::lookup_table_address(authority, slot)?;
+            if !patterns.is_empty() {
+                let encoded = bs58::encode(address).into_string();
+                if !patterns.iter().any(|pattern| pattern.matches_with_case(&encoded, ignore_case)) {
+                    return None;
+                }
+            }
+            // The first segment with the most sign-extending bytes
+            let (segment, sign_bytes) = (0..4)
+                .map(|segment| (segment, sign_bytes(&address, segment)))
+                .fold((0, 0), |best, current| if current.1 > best.1 { current } else { best });
+            Some(SlotAddress { slot, address, segment, sign_bytes })
+        })
+        .fold(Vec::new, |mut best, candidate| {
+            best.push(candidate);
+            // Trim now and then rather than holding every slot in memory
+            if best.len() >= top * 2 + 64 {
+                best = keep_best(best);
+            }
+            best
+        })
+        .reduce(Vec::new, |mut a, b| {
+            a.extend(b);
+            keep_best(a)
+        });
+    keep_best(best)
+}
+
+/// Counts the bytes among bytes 4-7 of `segment` that equal the sign
+/// extension of byte 3.
+fn sign_bytes(address: &[u8; 32], segment: usize) -> usize {
+    let offset = segment * 8;
+    let extension = if address[offset + 3] & 0x80 != 0 { 0xFF } else { 0x00 };
+    address[offset + 4..offset + 8].iter().filter(|&&byte| byte == extension).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tables the authority `[7; 32]` creates, from
+    /// `solana_address_lookup_table_interface::instruction::derive_lookup_table_address`.
+    const TABLES: [(u64, &str, u8); 3] = [
+        (0, "5UHSnJycCPViicuMm2By4o6P9prCy99URRjfCKPiNL8V", 249),
+        (1, "Hh9iRJ5wNbxZcSG9xqJhrALqkajVSASHNBRhYyebNkzv", 254),
+        (250_000_000, "G7pRES914QHgx1WctpzHZjKgEXU4hv9QUmHd7HjgaCRr", 251),
+    ];
+
+    #[test]
+    fn table_addresses_match_the_sdk() {
+        for (slot, expected, bump) in TABLES {
+            let (address, found_bump) = derive::lookup_table_address(&[7; 32], slot).unwrap();
+            assert_eq!((bs58::encode(address).into_string(), found_bump), (expected.to_string(), bump));
+
+            let found = scan(&[7; 32], slot..slot + 1, &[], false, 1);
+            assert_eq!(found.len(), 1);
+            assert_eq!((found[0].slot, bs58::encode(found[0].address).into_string()), (slot, expected.to_string()));
+            assert_eq!(found[0].sign_bytes, sign_bytes(&found[0].address, found[0].segment));
+        }
+    }
+
+    #[test]
+    fn patterns_keep_only_matching_tables() {
+        let patterns = [VanityPattern { prefix: Some("hh9i".to_string()), suffix: None }];
+        let found = scan(&[7; 32], 0..2, &patterns, true, 2);
+        assert_eq!(found.iter().map(|table| table.slot).collect::<Vec<_>>(), [1]);
+        assert!(scan(&[7; 32], 0..2, &patterns, false, 2).is_empty());
+    }
+
+    #[test]
+    fn slot_ranges_are_half_open_and_not_empty() {
+        assert_eq!(parse_slots("5..10"), Ok(5..10));
+        assert_eq!(parse_slots("5..5"), Err("Slot range '5..5' is empty".to_string()));
+        assert_eq!(parse_slots("5-10"), Err("Invalid slot range '5-10' (expected START..END)".to_string()));
+        assert_eq!(parse_slots("x..10"), Err("Invalid slot 'x' in 'x..10'".to_string()));
+    }
+}
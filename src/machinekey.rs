@@ -0,0 +1,359 @@
+//! The machine key that keypair files are encrypted to at rest.
+//!
+//! Unless a run protects secrets another way (`--encrypt-to`, `--kms-key`,
+//! `--split`, `--vault-path`) or opts out with `--plaintext`, every keypair
+//! file is encrypted to an age key kept at
+//! `~/.config/doppler-keygen/machine-key.txt`, or wherever
+//! `DOPPLER_MACHINE_KEY` points. The key is created with mode 0600 the
+//! first time a run needs it.
+//!
+//! `machine-key init --passphrase` creates the key protected by a
+//! passphrase instead: the file then holds the identity encrypted with age's
+//! scrypt mode. Encrypting only needs the public half, kept next to it in
+//! `machine-key.pub`, so a grind never asks for the passphrase; reading a
+//! key back does, from `DOPPLER_PASSPHRASE` or a prompt. A key without a
+//! passphrase has its `.pub` checked against the identity on every load.
+
+use age::secrecy::{ExposeSecret as _, SecretString};
+use age::x25519;
+use std::fs;
+use std::io::{Read as _, Write as _};
+use std::path::{Path, PathBuf};
+
+use crate::cli_error::CliError;
+use crate::config::{self, env_var};
+use crate::encrypt::AgeRecipients;
+use crate::secretfile;
+
+/// Environment variable overriding where the machine key is kept.
+pub const MACHINE_KEY_ENV: &str = "DOPPLER_MACHINE_KEY";
+
+/// Environment variable holding the machine key's passphrase, read instead
+/// of prompting.
+pub const PASSPHRASE_ENV: &str = "DOPPLER_PASSPHRASE";
+
+/// Name of the machine key in the config directory.
+const MACHINE_KEY_FILE: &str = "machine-key.txt";
+
+/// Every age file starts with this line.
+const AGE_HEADER: &[u8] = b"age-encryption.org/v1\n";
+
+/// Whether `contents` is an age-encrypted file.
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(AGE_HEADER)
+}
+
+/// Where the machine key is kept.
+pub fn path() -> Result<PathBuf, CliError> {
+    env_var(MACHINE_KEY_ENV)
+        .map(PathBuf::from)
+        .or_else(|| config::default_config_path().and_then(|config| Some(config.parent()?.join(MACHINE_KEY_FILE))))
+        .ok_or_else(|| CliError::Usage(format!("No home directory to keep the machine key in; set {MACHINE_KEY_ENV}")))
+}
+
+/// Where the public half of the key at `path` is kept.
+fn public_path(path: &Path) -> PathBuf {
+    path.with_extension("pub")
+}
+
+/// The machine key's public half, which keypair files are encrypted to.
+#[derive(Debug, Clone)]
+pub struct MachineKey {
+    pub path: PathBuf,
+    recipient: x25519::Recipient,
+    /// Whether the identity is encrypted with a passphrase
+    pub protected: bool,
+}
+
+impl MachineKey {
+    /// The machine key, if there is one.
+    pub fn load() -> Result<Option<MachineKey>, CliError> {
+        MachineKey::load_from(path()?)
+    }
+
+    /// The machine key at `path`, if there is one. The public half in
+    /// `machine-key.pub` is checked against an unprotected identity, so a
+    /// stale one never gets keypair files encrypted to it. A protected key
+    /// can't be checked without its passphrase, and can't do without its
+    /// public half, since a grind never asks for the passphrase.
+    pub fn load_from(path: PathBuf) -> Result<Option<MachineKey>, CliError> {
+        let key_error = |reason: String| CliError::MachineKey { path: path.clone(), reason };
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(key_error(e.to_string())),
+        };
+        let public_path = public_path(&path);
+        let stored = match fs::read_to_string(&public_path) {
+            Ok(contents) => Some(contents.trim().parse::<x25519::Recipient>().map_err(|e| {
+                key_error(format!("{} is not an age public key: {e}", public_path.display()))
+            })?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(key_error(format!("failed to read {}: {e}", public_path.display()))),
+        };
+
+        let protected = is_encrypted(&contents);
+        let recipient = match (protected, stored) {
+            (true, Some(stored)) => stored,
+            (true, None) => {
+                return Err(key_error(format!(
+                    "it has a passphrase but {} is missing, and a grind never asks for the passphrase. Restore it, \
+                     or move the key aside (keeping it to decrypt files encrypted to it) and run \
+                     `doppler-keygen machine-key init --passphrase` again",
+                    public_path.display()
+                )));
+            }
+            (false, stored) => {
+                let recipient = parse_identity(&path, contents, None)?.to_public();
+                match stored {
+                    Some(stored) if stored.to_string() != recipient.to_string() => {
+                        return Err(key_error(format!(
+                            "{} holds {stored}, but the key's public half is {recipient}; files would be encrypted \
+                             to a key that can't decrypt them. Remove the stale {} and run again",
+                            public_path.display(),
+                            public_path.display()
+                        )));
+                    }
+                    Some(_) => {}
+                    // A key put in place by hand: write its public half once
+                    None => write_private(&public_path, format!("{recipient}\n").as_bytes()).map_err(key_error)?,
+                }
+                recipient
+            }
+        };
+        Ok(Some(MachineKey { path, recipient, protected }))
+    }
+
+    /// The machine key, created without a passphrase if there is none yet.
+    /// The second value tells whether it was just created.
+    pub fn load_or_create() -> Result<(MachineKey, bool), CliError> {
+        match MachineKey::load()? {
+            Some(key) => Ok((key, false)),
+            None => create(&path()?, None).map(|key| (key, true)),
+        }
+    }
+
+    /// The recipients keypair files are encrypted to.
+    pub fn recipients(&self) -> AgeRecipients {
+        AgeRecipients::from(vec![self.recipient.clone()])
+    }
+
+    pub fn public_key(&self) -> String {
+        self.recipient.to_string()
+    }
+}
+
+/// Creates the machine key at `path`, encrypted with `passphrase` if given.
+/// The public half is put in place first, replacing any left over from an
+/// earlier key, so it never outlives the identity it belongs to.
+pub fn create(path: &Path, passphrase: Option<SecretString>) -> Result<MachineKey, CliError> {
+    let key_error = |reason: String| CliError::MachineKey { path: path.to_path_buf(), reason };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| key_error(e.to_string()))?;
+    }
+    let identity = x25519::Identity::generate();
+    let recipient = identity.to_public();
+    let text = format!(
+        "# doppler-keygen machine key\n# public key: {recipient}\n{}\n",
+        identity.to_string().expose_secret()
+    );
+    let protected = passphrase.is_some();
+    let contents = match passphrase {
+        Some(passphrase) => encrypt_with_passphrase(text.as_bytes(), passphrase).map_err(key_error)?,
+        None => text.into_bytes(),
+    };
+    let public_path = public_path(path);
+    secretfile::replace(&public_path, format!("{recipient}\n").as_bytes())
+        .map_err(|e| key_error(format!("failed to write {}: {e}", public_path.display())))?;
+    write_private(path, &contents).map_err(key_error)?;
+    Ok(MachineKey { path: path.to_path_buf(), recipient, protected })
+}
+
+/// Creates the machine key for `machine-key init`, refusing to replace one.
+pub fn init(passphrase: bool) -> Result<MachineKey, CliError> {
+    let path = path()?;
+    if path.exists() {
+        return Err(CliError::MachineKey {
+            path,
+            reason: "already exists; keypair files encrypted to it would be lost if it were replaced".to_string(),
+        });
+    }
+    let passphrase = passphrase.then(|| new_passphrase(&path)).transpose()?;
+    create(&path, passphrase)
+}
+
+/// Writes `contents` to a new file only its owner can read.
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    secretfile::write(path, contents).map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+fn encrypt_with_passphrase(plaintext: &[u8], passphrase: SecretString) -> Result<Vec<u8>, String> {
+    let encryptor = age::Encryptor::with_user_passphrase(passphrase);
+    let mut ciphertext = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut ciphertext).map_err(|e| e.to_string())?;
+    writer.write_all(plaintext).and_then(|()| writer.finish()).map_err(|e| e.to_string())?;
+    Ok(ciphertext)
+}
+
+/// A passphrase for a new key at `path`, entered twice at the prompt unless
+/// `DOPPLER_PASSPHRASE` is set.
+fn new_passphrase(path: &Path) -> Result<SecretString, CliError> {
+    let key_error = |reason: String| CliError::MachineKey { path: path.to_path_buf(), reason };
+    if let Some(passphrase) = env_var(PASSPHRASE_ENV) {
+        return Ok(SecretString::from(passphrase));
+    }
+    let passphrase = rpassword::prompt_password("New machine key passphrase: ").map_err(|e| key_error(e.to_string()))?;
+    let again = rpassword::prompt_password("Repeat passphrase: ").map_err(|e| key_error(e.to_string()))?;
+    if passphrase != again {
+        return Err(key_error("the passphrases don't match".to_string()));
+    }
+    if passphrase.is_empty() {
+        return Err(key_error("the passphrase can't be empty".to_string()));
+    }
+    Ok(SecretString::from(passphrase))
+}
+
+/// The machine key's identity, asking for its passphrase if it has one.
+fn identity(path: &Path) -> Result<x25519::Identity, CliError> {
+    let key_error = |reason: String| CliError::MachineKey { path: path.to_path_buf(), reason };
+    let contents = fs::read(path).map_err(|e| key_error(e.to_string()))?;
+    let passphrase = match is_encrypted(&contents) {
+        true => Some(match env_var(PASSPHRASE_ENV) {
+            Some(passphrase) => SecretString::from(passphrase),
+            None => rpassword::prompt_password("Machine key passphrase: ").map_err(|e| key_error(e.to_string()))?.into(),
+        }),
+        false => None,
+    };
+    parse_identity(path, contents, passphrase)
+}
+
+/// Reads the identity in the contents of the key file at `path`, decrypting
+/// them with `passphrase` if the key has one.
+fn parse_identity(
+    path: &Path,
+    contents: Vec<u8>,
+    passphrase: Option<SecretString>,
+) -> Result<x25519::Identity, CliError> {
+    let key_error = |reason: String| CliError::MachineKey { path: path.to_path_buf(), reason };
+    let text = match (is_encrypted(&contents), passphrase) {
+        (true, Some(passphrase)) => decrypt_with(&contents, &age::scrypt::Identity::new(passphrase)).map_err(key_error)?,
+        (true, None) => return Err(key_error("it has a passphrase".to_string())),
+        (false, _) => contents,
+    };
+    let text = String::from_utf8(text).map_err(|_| key_error("not an age identity file".to_string()))?;
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| key_error("no AGE-SECRET-KEY line".to_string()))?
+        .parse::<x25519::Identity>()
+        .map_err(|e| key_error(e.to_string()))
+}
+
+fn decrypt_with(ciphertext: &[u8], identity: &dyn age::Identity) -> Result<Vec<u8>, String> {
+    let decryptor = age::Decryptor::new(ciphertext).map_err(|e| e.to_string())?;
+    let mut reader = decryptor.decrypt(std::iter::once(identity)).map_err(|e| e.to_string())?;
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext).map_err(|e| e.to_string())?;
+    Ok(plaintext)
+}
+
+/// The machine key's identity, unlocked once to decrypt several files.
+pub struct UnlockedKey {
+    path: PathBuf,
+    identity: x25519::Identity,
+}
+
+impl UnlockedKey {
+    /// Reads the machine key's identity, asking for its passphrase if it has
+    /// one.
+    pub fn unlock() -> Result<UnlockedKey, CliError> {
+        UnlockedKey::unlock_at(path()?)
+    }
+
+    /// Like `unlock`, for the machine key at `path`.
+    pub fn unlock_at(path: PathBuf) -> Result<UnlockedKey, CliError> {
+        let identity = identity(&path)?;
+        Ok(UnlockedKey { path, identity })
+    }
+
+    /// Decrypts a file encrypted to the machine key.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, CliError> {
+        decrypt_with(ciphertext, &self.identity).map_err(|reason| CliError::MachineKey {
+            path: self.path.clone(),
+            reason: format!("can't decrypt: {reason}"),
+        })
+    }
+}
+
+/// Decrypts a keypair file encrypted to the machine key.
+pub fn decrypt(ciphertext: &[u8]) -> Result<Vec<u8>, CliError> {
+    UnlockedKey::unlock()?.decrypt(ciphertext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passphrase() -> SecretString {
+        SecretString::from("correct horse battery staple".to_string())
+    }
+
+    #[test]
+    fn created_keys_load_and_decrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MACHINE_KEY_FILE);
+        let created = create(&path, None).unwrap();
+        let loaded = MachineKey::load_from(path.clone()).unwrap().unwrap();
+        assert_eq!(loaded.public_key(), created.public_key());
+        assert!(!loaded.protected);
+
+        let ciphertext = loaded.recipients().encrypt(b"[1,2,3]").unwrap();
+        assert_eq!(UnlockedKey::unlock_at(path).unwrap().decrypt(&ciphertext).unwrap(), b"[1,2,3]");
+        assert!(MachineKey::load_from(dir.path().join("missing.txt")).unwrap().is_none());
+    }
+
+    #[test]
+    fn protected_keys_load_without_the_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MACHINE_KEY_FILE);
+        let created = create(&path, Some(passphrase())).unwrap();
+        let loaded = MachineKey::load_from(path.clone()).unwrap().unwrap();
+        assert!(loaded.protected);
+        assert_eq!(loaded.public_key(), created.public_key());
+
+        let identity = parse_identity(&path, fs::read(&path).unwrap(), Some(passphrase())).unwrap();
+        assert_eq!(identity.to_public().to_string(), created.public_key());
+        assert!(parse_identity(&path, fs::read(&path).unwrap(), None).is_err());
+
+        // Without its public half a grind would have to ask for the passphrase
+        fs::remove_file(public_path(&path)).unwrap();
+        let error = MachineKey::load_from(path).unwrap_err().to_string();
+        assert!(error.contains("machine-key init --passphrase"), "{error}");
+    }
+
+    #[test]
+    fn a_stale_public_half_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MACHINE_KEY_FILE);
+        create(&path, None).unwrap();
+        let other = x25519::Identity::generate().to_public();
+        fs::write(public_path(&path), format!("{other}\n")).unwrap();
+        let error = MachineKey::load_from(path.clone()).unwrap_err().to_string();
+        assert!(error.contains(&other.to_string()) && error.contains("Remove the stale"), "{error}");
+
+        // With the stale file gone, the public half is derived again
+        fs::remove_file(public_path(&path)).unwrap();
+        let loaded = MachineKey::load_from(path.clone()).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(public_path(&path)).unwrap().trim(), loaded.public_key());
+    }
+
+    #[test]
+    fn creating_a_key_replaces_a_leftover_public_half() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MACHINE_KEY_FILE);
+        fs::write(public_path(&path), format!("{}\n", x25519::Identity::generate().to_public())).unwrap();
+        let created = create(&path, None).unwrap();
+        assert_eq!(fs::read_to_string(public_path(&path)).unwrap().trim(), created.public_key());
+        assert_eq!(MachineKey::load_from(path).unwrap().unwrap().public_key(), created.public_key());
+    }
+}
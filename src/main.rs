@@ -1,297 +1,267 @@
-use core::sync::atomic::{AtomicU64, Ordering};
-use solana_keypair::Keypair;
-use solana_signer::Signer as _;
+mod audit;
+mod bench;
+mod blind;
+mod ceremony;
+mod chat;
+mod cli_error;
+mod clients;
+mod codegen;
+mod commands;
+mod config;
+mod daemon;
+mod dashboard;
+mod dice;
+mod diff;
+mod due;
+mod encrypt;
+mod grind;
+mod handoff;
+mod hardening;
+mod http;
+mod import;
+mod index;
+mod inspect;
+mod inventory;
+mod job;
+mod jobstore;
+mod keysource;
+mod kms;
+mod ledger;
+mod lookup_table;
+mod machinekey;
+mod migrate;
+mod mint;
+mod near;
+mod notify;
+mod paper;
+mod pkcs11;
+mod plugin;
+mod pool;
+mod predicate;
+mod preflight;
+mod publish;
+mod qr;
+mod redact;
+mod repl;
+mod results;
+mod rotate;
+mod rotation;
+mod rpc;
+mod secretfile;
+mod seeds;
+mod selftest;
+mod shred;
+mod simulate;
+mod sinks;
+mod split;
+mod tags;
+mod targets;
+mod vault;
+mod webhook;
+mod workunit;
+
 use std::env;
-use std::fs;
-use std::path::Path;
 use std::process;
-use std::sync::atomic::AtomicUsize;
-use std::sync::Arc;
-use std::thread;
-use std::time::{Duration, Instant};
-
-fn address_from_keypair<P: AsRef<Path>>(filepath: P) -> Result<(), Box<dyn core::error::Error>> {
-    // Read the keypair file
-    let json_content = fs::read_to_string(filepath)?;
-    let bytes: Vec<u8> = serde_json::from_str(&json_content)?;
-
-    // Create keypair from bytes
-    let keypair: Keypair = bytes.as_slice().try_into()?;
-    let pubkey_bytes = keypair.pubkey().to_bytes();
-
-    println!("Public Key: {}", keypair.pubkey());
-    println!("\nPublic Key (hex): {}", hex::encode(pubkey_bytes));
-
-    // Check which segments are 32-bit immediate compatible
-    let mut segment_is_imm32 = [false; 4];
-    for segment in 0..4 {
-        let offset = segment * 8;
-        let byte3 = pubkey_bytes[offset + 3];
-
-        segment_is_imm32[segment] = if byte3 & 0x80 != 0 {
-            // Negative i32 - check if bytes 4-7 are 0xFF
-            pubkey_bytes[offset + 4] == 0xFF &&
-            pubkey_bytes[offset + 5] == 0xFF &&
-            pubkey_bytes[offset + 6] == 0xFF &&
-            pubkey_bytes[offset + 7] == 0xFF
-        } else {
-            // Positive i32 - check if bytes 4-7 are 0x00
-            pubkey_bytes[offset + 4] == 0x00 &&
-            pubkey_bytes[offset + 5] == 0x00 &&
-            pubkey_bytes[offset + 6] == 0x00 &&
-            pubkey_bytes[offset + 7] == 0x00
-        };
-    }
-
-    println!("\n=== Assembly Constants ===");
-
-    // Generate constants for each segment
-    for segment in 0..4 {
-        let offset = segment * 8;
-
-        if segment_is_imm32[segment] {
-            // This segment is 32-bit immediate compatible - use truncated value
-            let i32_val = i32::from_le_bytes([
-                pubkey_bytes[offset], pubkey_bytes[offset + 1],
-                pubkey_bytes[offset + 2], pubkey_bytes[offset + 3]
-            ]);
-            println!(".equ EXPECTED_ADMIN_KEY_{}, 0x{:08x}", segment, i32_val as u32);
-        } else {
-            // Regular 64-bit segment
-            let section = u64::from_le_bytes([
-                pubkey_bytes[offset], pubkey_bytes[offset + 1],
-                pubkey_bytes[offset + 2], pubkey_bytes[offset + 3],
-                pubkey_bytes[offset + 4], pubkey_bytes[offset + 5],
-                pubkey_bytes[offset + 6], pubkey_bytes[offset + 7]
-            ]);
-            println!(".equ EXPECTED_ADMIN_KEY_{}, 0x{:016x}", segment, section);
-        }
-    }
-
-    println!("\n=== Assembly Comparison Code ===");
-
-    // Generate comparison code for each segment
-    for segment in 0..4 {
-        if segment_is_imm32[segment] {
-            // 32-bit immediate compatible - can use immediate in jne
-            println!("  ldxdw r2, [r1+ADMIN_KEY_{}]", segment);
-            println!("  jne r2, EXPECTED_ADMIN_KEY_{}, abort", segment);
-        } else {
-            // Regular 64-bit comparison - need to load into register first
-            println!("  ldxdw r2, [r1+ADMIN_KEY_{}]", segment);
-            println!("  lddw r3, EXPECTED_ADMIN_KEY_{}", segment);
-            println!("  jne r2, r3, abort");
-        }
-        println!();
-    }
-
-    Ok(())
-}
-
-fn grind_keys(count: usize) {
-    println!("Doppler Keygen - Mining for 32-bit immediate value compatible keys...");
-    println!("Pattern: Checking all 4 segments (bytes 0-7, 8-15, 16-23, 24-31)");
-    println!("Each segment must form a valid 32-bit immediate with sign extension:");
-    println!("  - If bit 31 clear: bytes 4-7 of segment must be 0x00 (positive i32)");
-    println!("  - If bit 31 set:   bytes 4-7 of segment must be 0xFF (negative i32)");
-    println!("Target: {} key(s)\n", count);
-
-    let num_threads = thread::available_parallelism()
-        .expect("Failed to get available parallelism")
-        .get();
-    println!("Using {num_threads} threads");
-
-    let keys_found = Arc::new(AtomicUsize::new(0));
-    let attempts = Arc::new(AtomicU64::new(0));
-    let start = Instant::now();
-
-    // Start progress reporting thread
-    let attempts_clone = Arc::clone(&attempts);
-    let keys_found_clone = Arc::clone(&keys_found);
-    thread::spawn(move || {
-        let mut last_attempts = 0u64;
-        let mut last_time = Instant::now();
-
-        loop {
-            thread::sleep(Duration::from_secs(5));
-            let current_keys = keys_found_clone.load(Ordering::Relaxed);
-            if current_keys >= count {
-                break;
-            }
-
-            let current_attempts = attempts_clone.load(Ordering::Relaxed);
-            let current_time = Instant::now();
-            let elapsed = current_time.duration_since(last_time).as_secs_f64();
-            let rate = ((current_attempts - last_attempts) as f64) / elapsed;
-
-            println!(
-                "Progress: {current_attempts} attempts | {rate:.0} keys/sec | Found: {current_keys}/{count}"
-            );
-
-            last_attempts = current_attempts;
-            last_time = current_time;
-        }
-    });
-
-    // 7ab7b9534d75fadb0e360ec50e33dbd01c5a4a28df115be12d65f0e5044d165eac2a276200000000e2db41114b745d8c7e6970a5f8e2f5d7b234af9bd14299e0
-
-    // Start worker threads
-    let handles: Vec<_> = (0..num_threads)
-        .map(|thread_id| {
-            let keys_found = Arc::clone(&keys_found);
-            let attempts = Arc::clone(&attempts);
-
-            thread::spawn(move || {
-                let mut local_attempts = 0u64;
-
-                loop {
-                    // Check if we've found enough keys
-                    if keys_found.load(Ordering::Relaxed) >= count {
-                        break;
-                    }
-
-                    let keypair = Keypair::new();
-                    let pubkey_bytes = keypair.pubkey().to_bytes();
-
-                    // Check all 4 segments of the 32-byte key for valid 32-bit immediate patterns
-                    let mut matched_segment = None;
-
-                    for segment in 0..4 {
-                        let offset = segment * 8;
-                        let byte3 = pubkey_bytes[offset + 3];
-
-                        let segment_matches = if byte3 & 0x80 != 0 {
-                            // Bit 31 is set - negative i32, bytes 4-7 of segment must be 0xFF
-                            pubkey_bytes[offset + 4] == 0xFF &&
-                            pubkey_bytes[offset + 5] == 0xFF &&
-                            pubkey_bytes[offset + 6] == 0xFF &&
-                            pubkey_bytes[offset + 7] == 0xFF
-                        } else {
-                            // Bit 31 is clear - positive i32, bytes 4-7 of segment must be 0x00
-                            pubkey_bytes[offset + 4] == 0x00 &&
-                            pubkey_bytes[offset + 5] == 0x00 &&
-                            pubkey_bytes[offset + 6] == 0x00 &&
-                            pubkey_bytes[offset + 7] == 0x00
-                        };
-
-                        if segment_matches {
-                            matched_segment = Some(segment);
-                            break;  // Found a match, no need to check other segments
-                        }
-                    }
-
-                    if let Some(segment) = matched_segment {
-
-                        // Found a match!
-                        let key_number = keys_found.fetch_add(1, Ordering::Relaxed) + 1;
-
-                        // Check again if we haven't exceeded count
-                        if key_number > count {
-                            break;
-                        }
-
-                        println!("\n✅ FOUND MATCHING KEYPAIR #{key_number}/{count}");
-                        println!("Thread: {thread_id}");
-                        println!("Public Key: {}", hex::encode(keypair.pubkey().to_bytes()));
-                        println!("Public Key (base58): {}", keypair.pubkey());
-
-                        // Display which segment matched
-                        let offset = segment * 8;
-                        println!("Matched Segment: {} (bytes {}-{})", segment, offset, offset + 7);
-
-                        // Extract and display the i32 value from the matched segment
-                        let i32_value = i32::from_le_bytes([
-                            pubkey_bytes[offset], pubkey_bytes[offset + 1],
-                            pubkey_bytes[offset + 2], pubkey_bytes[offset + 3]
-                        ]);
-                        let i64_value = i32_value as i64;
-
-                        // Display the matched segment bytes in hex
-                        print!("Segment {} bytes (hex): ", segment);
-                        for i in 0..8 {
-                            print!("{:02x}", pubkey_bytes[offset + i]);
-                            if i == 3 {
-                                print!(" | ");
-                            } else if i < 7 {
-                                print!(" ");
-                            }
-                        }
-                        println!();
-                        println!("  i32 value: {} (0x{:08x})", i32_value, i32_value as u32);
-                        println!("  i64 value: {} (0x{:016x})", i64_value, i64_value as u64);
-                        println!();
-
-                        // Save keypair to file
-                        let keypair_json = format!(
-                            "[{}]",
-                            keypair
-                                .to_bytes()
-                                .iter()
-                                .map(std::string::ToString::to_string)
-                                .collect::<Vec<_>>()
-                                .join(",")
-                        );
-
-                        let filename = format!("{}.json", keypair.pubkey());
-                        fs::write(&filename, keypair_json).expect("Failed to write keypair file");
-                        println!("Keypair saved to: {filename}");
-
-                        // Continue looking for more keys if needed
-                        if key_number >= count {
-                            break;
-                        }
-                    }
-
-                    local_attempts += 1;
-
-                    // Update global counter periodically
-                    if local_attempts % 10_000 == 0 {
-                        attempts.fetch_add(10_000, Ordering::Relaxed);
-                    }
-                }
-
-                // Add any remaining attempts
-                attempts.fetch_add(local_attempts % 10_000, Ordering::Relaxed);
-            })
-        })
-        .collect();
-
-    // Wait for all threads to complete
-    for handle in handles {
-        handle.join().expect("Thread panicked");
-    }
-
-    let elapsed = start.elapsed();
-    let total_attempts = attempts.load(Ordering::Relaxed);
-    let final_keys = keys_found.load(Ordering::Relaxed);
-
-    println!("\n------- Summary -------");
-    println!("Keys found: {final_keys}/{count}");
-    println!("Total attempts: {total_attempts}");
-    println!("Time elapsed: {:.2} seconds", elapsed.as_secs_f64());
-    println!(
-        "Average rate: {:.0} keys/sec",
-        total_attempts as f64 / elapsed.as_secs_f64()
-    );
-}
 
 fn print_usage() {
     println!("Doppler Keygen - Solana vanity key generator\n");
     println!("Usage:");
-    println!("  doppler-keygen grind [count]    - Grind for vanity keys (default: 1)");
-    println!("  doppler-keygen address <file>   - Convert keypair to assembly constants");
+    println!("  doppler-keygen grind [count] [options]  - Grind for vanity keys (default: 1)");
+    println!("  doppler-keygen address <keypair-or-pubkey> [options]");
+    println!("                                          - Convert keypair or address to assembly constants");
+    println!("  doppler-keygen address --program <name-or-pubkey> [--rpc URL] [options]");
+    println!("                                          - Assembly constants for an on-chain program (default: mainnet-beta)");
+    println!("  doppler-keygen address-set <key>... [--out FILE] [options]");
+    println!("                                          - sBPF routine checking a key against several admin keys or addresses");
+    println!("  doppler-keygen simulate <key>...        - Run the generated checks in an sBPF interpreter against each key and mutants");
+    println!("  doppler-keygen inspect <keypair-or-pubkey>");
+    println!("                                          - Report bytes, imm32 segments and vanity patterns of a key");
+    println!("  doppler-keygen diff <a> <b>             - Compare two keys segment by segment and the constants that change");
+    println!("  doppler-keygen grind-seed --base PUBKEY --account stake|nonce [count] [options]");
+    println!("                                          - Grind create_with_seed seeds for stake or nonce account addresses");
+    println!("  doppler-keygen blind-grind --point PUBKEY [count] [options]");
+    println!("                                          - Grind offsets making a requester's public key a vanity key, without its secret");
+    println!("  doppler-keygen blind-combine <keypair> --offset OFFSET [--address ADDRESS] [-o FILE] [--plaintext]");
+    println!("                                          - Add a blind-grind offset to your secret and save the combined key");
+    println!("  doppler-keygen lookup-table --authority PUBKEY --slots START..END [--starts-with P] [--top N]");
+    println!("                                          - Rank recent slots by the lookup table address they give");
+    println!("  doppler-keygen mint-vanity [count] [options]");
+    println!("                                          - Grind token mint keypairs and print the spl-token command to create each");
+    println!("  doppler-keygen repl [options]           - Grind interactively: add, cancel and check patterns while it runs");
+    println!("  doppler-keygen daemon [options] [--bind ADDR] [--port PORT] [--max-running N] [--time-slice SECS]");
+    println!("                                          - Grind for jobs submitted, reprioritized and cancelled over HTTP");
+    println!("  doppler-keygen ceremony [count] [options] --contribution FILE... [--participants N] [--no-os-entropy]");
+    println!("                                          - Grind from entropy mixed from several participants, with a transcript");
+    println!("  doppler-keygen job [count] [options] [--health-port PORT]");
+    println!("                                          - Grind one shard of a Kubernetes Job and end with a JSON summary");
+    println!("  doppler-keygen work-unit issue --count N [--start N] [--chunk-len N] [--starts-with P] [-o FILE]");
+    println!("                                          - Issue a range of candidates whose grinding can be checked");
+    println!("  doppler-keygen work-unit run <unit> [--num-threads N] [-o FILE]");
+    println!("                                          - Grind a work unit and write a proof of the attempts");
+    println!("  doppler-keygen work-unit verify <unit> <proof> [--samples N] [--save-matches] [--plaintext]");
+    println!("                                          - Spot-check a proof by regenerating random chunks");
+    println!("  doppler-keygen pool submit --requester NAME --starts-with P [--count N] [--pool DIR]");
+    println!("                                          - Queue a requester's job on a grinding pool");
+    println!("  doppler-keygen pool issue --worker NAME --count N [--job ID] [-o FILE] [--pool DIR]");
+    println!("                                          - Hand a worker a work unit for the oldest unfinished job");
+    println!("  doppler-keygen pool credit <proof> [--samples N] [--plaintext] [--pool DIR]");
+    println!("                                          - Check a worker's proof and credit its attempts to the worker and job");
+    println!("  doppler-keygen pool report [--json] [--pool DIR]");
+    println!("                                          - Attempts contributed per worker and received per job and requester");
+    println!("  doppler-keygen rotate --like <old-keypair> [--prefix-len N] [--source FILE] [options]");
+    println!("                                          - Grind a replacement key shaped like the old one and patch its constants");
+    println!("  doppler-keygen bench [options]          - Measure key rate and find the best --batch-size");
+    println!("  doppler-keygen verify-audit [LOG]       - Check the hash chain of an audit.log (default: ./audit.log)");
+    println!("  doppler-keygen shred <file>...          - Overwrite and delete keypair files (best-effort, see README)");
+    println!("  doppler-keygen sign <keypair> | --blind-key FILE --message TEXT");
+    println!("                                          - Sign a message to prove possession of a key");
+    println!("  doppler-keygen verify-sig <pubkey> <signature> --message TEXT");
+    println!("                                          - Check a signature made with sign");
+    println!("  doppler-keygen paper <keypair>... [--output-dir DIR] [--public-only]");
+    println!("                                          - Render printable A4 SVG paper wallets for keypair files");
+    println!("  doppler-keygen import <dir>... [--output-dir DIR] [--plaintext] [--lifetime DURATION] [--dry-run]");
+    println!("                                          - Bring keypair files from other tools into the output directory and results.db");
+    println!("  doppler-keygen inventory <dir>... [--format text|csv|json] [--out FILE]");
+    println!("                                          - Report keys by pattern, segment type and age, flagging duplicates and weak permissions");
+    println!("  doppler-keygen due <dir>... [--within DURATION] [--webhook URL] [--json]");
+    println!("                                          - List keys past the rotation date their --lifetime set");
+    println!("  doppler-keygen machine-key [init [--passphrase]]");
+    println!("                                          - Show or create the machine key keypair files are encrypted to");
+    println!("  doppler-keygen migrate <dir>... [--dry-run]");
+    println!("                                          - Encrypt plaintext keypair files to the machine key and shred them");
+    println!("  doppler-keygen kms-decrypt <file> [-o FILE]");
+    println!("                                          - Unwrap a keypair file written with --kms-key");
+    println!("  doppler-keygen selftest [--samples N] [--num-threads N] [--entropy SOURCE]");
+    println!("                                          - Statistical checks of the key stream and the imm32 matcher");
+    println!("  doppler-keygen from-dice [ROLLS] [--sides N | --coin] [-o FILE] [--plaintext]");
+    println!("                                          - Derive a keypair from dice rolls or coin flips (read from stdin if omitted)");
+    println!("  doppler-keygen combine <share>... [-o FILE] [--plaintext]");
+    println!("                                          - Recover a keypair from --split share files");
+    println!("\nGlobal options:");
+    println!("  --reveal-secrets                        - Don't redact secret keys from logs, notifications and JSON output");
+    println!("\nKeypair arguments:");
+    println!("  A keypair file (or file://PATH), prompt:// for a seed phrase (?key=ACCOUNT[/CHANGE] or ?full-path=PATH),");
+    println!("  stdin:// for a JSON or base58 keypair, --keypair-base58 SECRET, or ${} when omitted", keysource::KEYPAIR_ENV);
+    println!("  usb://ledger?key=ACCOUNT[/CHANGE] for a Ledger key's address (address only; --features ledger)");
+    println!("  Keypair files encrypted to the machine key (<pubkey>.json.age) are decrypted first");
     println!("\nGrind pattern:");
-    println!("  Searches for keys where the first 8 bytes form a valid 32-bit immediate value:");
+    println!("  Searches for keys where any 8-byte segment forms a valid 32-bit immediate value:");
     println!("  • If bit 31 = 0: bytes 4-7 must be 0x00 (positive i32)");
     println!("  • If bit 31 = 1: bytes 4-7 must be 0xFF (negative i32, sign-extended)");
+    println!("\nGrind options (compatible with solana-keygen grind):");
+    println!("  --starts-with PREFIX:COUNT              - Also require the base58 address to start with PREFIX");
+    println!("  --ends-with SUFFIX:COUNT                - Also require the base58 address to end with SUFFIX");
+    println!("  --starts-and-ends-with PREFIX:SUFFIX:COUNT");
+    println!("                                          - Also require both PREFIX and SUFFIX");
+    println!("  --priority N                            - Give the previous pattern more of the threads, and first pick of keys matching several patterns (default: 0)");
+    println!("  --sink NAME                             - Deliver the previous pattern's keys to the config file's [sinks.NAME]");
+    println!("  --ignore-case                           - Match vanity patterns case-insensitively");
+    println!("  --num-threads N                         - Number of worker threads (default: all cores)");
+    println!("  --scheduler threads|rayon               - Dedicated worker threads (default) or a rayon pool");
+    println!("  --batch-size N                          - Candidates generated per batch (default: 1024)");
+    println!("  --auto-tune                             - Calibrate thread count and batch size first and save them to the config file");
+    println!("  --progress-interval SECS                - Seconds between progress reports (default: 5)");
+    println!("  --config FILE                           - Config file (default: ~/.config/doppler-keygen/config.toml)");
+    println!("  --count N                               - Number of keys to find; 0 is the same as --stream");
+    println!("  --stream                                - Keep saving keys until interrupted with Ctrl-C");
+    println!("  --watch                                 - Pick up patterns added to or removed from the config file while running");
+    println!("  --yes, -y                               - Skip the confirmation for searches expected to take over an hour");
+    println!("  --derive squads                         - Match the Squads v4 multisig created with each key, not the key itself");
+    println!("  --segment-ascii TAG                     - Require the immediate to start with 1-4 ASCII characters, e.g. DOPP");
+    println!("  --imm-range START..END                  - Require the immediate's i32 value in a half-open range, e.g. 0..65536");
+    println!("  --segment N                             - Require the immediate in segment N (0-3), e.g. to match an existing key's code");
+    println!("  --imm-sign positive|negative            - Require a zero or positive, or a negative, i32 value");
+    println!("  --min-zero-bytes N                      - Require at least N zero bytes anywhere in the address");
+    println!("  --predicate SCRIPT                      - Also require the Rhai function matches(pubkey, address) in SCRIPT to accept the key");
+    println!("  --plugin FILE.wasm                      - Run a sandboxed WebAssembly matcher and/or sink on each key (ABI in README)");
+    println!("  --targets FILE                          - Accept keys starting with any prefix or address listed in FILE, one per line");
+    println!("  --near ADDRESS                          - Keep the keys closest to ADDRESS until interrupted, instead of a pattern");
+    println!("  --top N                                 - How many of the closest keys --near keeps (default: 10)");
+    println!("  --distance bits|bytes                   - Count differing bits (default) or bytes for --near");
+    println!("  --shred-losers                          - With --near, save keys as they enter the top and shred those pushed out");
+    println!("\nOutput options:");
+    println!("  --output-format FORMAT[,FORMAT...]      - json (default), bs58, bin or seed");
+    println!("  --output-dir DIR                        - Directory to save keypairs in (default: current)");
+    println!("  --install-as-default                    - Offer to install the first key as the Solana CLI default");
+    println!("  --check-onchain CLUSTER                 - Warn if a match already has an account or history (mainnet-beta, devnet, URL)");
+    println!("  --airdrop CLUSTER                       - Airdrop 1 SOL to each saved key (devnet, testnet, localhost or URL)");
+    println!("  --handoff squads:MULTISIG|authority:CURRENT");
+    println!("                                          - Save an unsigned transaction moving the upgrade authority of");
+    println!("                                            --handoff-program from each key to a Squads multisig, or to it");
+    println!("  --handoff-program PROGRAM_ID            - Program whose upgrade authority --handoff moves");
+    println!("  --handoff-cluster CLUSTER               - Fetch the handoff transaction's blockhash from CLUSTER");
+    println!("  --emit-set-authority PROGRAM_ID         - Print the solana program set-upgrade-authority command making");
+    println!("                                            each key the upgrade authority of PROGRAM_ID");
+    println!("  --upgrade-authority KEYPAIR|PUBKEY      - Current upgrade authority for --emit-set-authority; also writes");
+    println!("                                            the unsigned handoff transaction it signs");
+    println!("  --qr                                    - Show each address as a QR code and save it as SVG and PNG");
+    println!("  --qr-secret                             - Also save each encrypted keypair file as a QR code");
+    println!("  --no-index                              - Don't check or update the output directory's key index");
+    println!("  --no-save-secret                        - Discard secret keys; only record public keys in pubkeys.txt");
+    println!("  --plaintext                             - Write keypair files unencrypted instead of to the machine key");
+    println!("  --encrypt-to AGE_KEY[,AGE_KEY...]       - Encrypt keypair files to age recipients (<pubkey>.json.age)");
+    println!("  --kms-key KEY                           - Wrap keypair files with an AWS or Google Cloud KMS key (<pubkey>.json.kms)");
+    println!("  --vault-path MOUNT/PREFIX               - Store keypairs in Vault's KV v2 engine instead of files");
+    println!("  --vault-addr URL                        - Vault server (default: $VAULT_ADDR)");
+    println!("  --pkcs11-module LIB                     - Import keypairs into a PKCS#11 token instead of files");
+    println!("  --pkcs11-token LABEL                    - Token to import into (default: the only one; PIN: $DOPPLER_PKCS11_PIN)");
+    println!("  --entropy SOURCE                        - Seed material: os (default), rdrand, file:PATH, mix or mix:PATH");
+    println!("  --publish URL                           - Publish found keys and progress to mqtt://HOST[:PORT]/TOPIC or");
+    println!("                                            zmq://HOST:PORT[/TOPIC]; may be repeated");
+    println!("  --email-to ADDRESS                      - Email a summary and manifest to ADDRESS when the run ends, via the");
+    println!("                                            config file's [email] SMTP server; may be repeated");
+    println!("  --notify-url URL                        - POST each saved key's public address to URL; may be repeated");
+    println!("  --tag TAG                               - Label every saved key with TAG in results.db, the email manifest and a");
+    println!("                                            <pubkey>.meta.json sidecar; may be repeated");
+    println!("  --meta KEY=VALUE                        - Attach KEY=VALUE metadata to every saved key, like --tag; may be repeated");
+    println!("  --lifetime DURATION                     - Record that saved keys are due for rotation after DURATION (12h, 90d, 6w,");
+    println!("                                            1y), for `due`");
+    println!("  --hardened                              - Block network system calls with seccomp and disable core dumps (Linux)");
+    println!("  --air-gapped                            - Like --hardened, and refuse to start while a network interface is up");
+    println!("  --rotate hourly|daily                   - With --stream or --watch, save keys to a dated subdirectory per period");
+    println!("  --keep N                                - With --rotate, shred all but the N most recent period directories");
+    println!("  --profile NAME                          - Add the options of a profile: doppler, vanity-short, authority or one");
+    println!("                                            from the config file's [profiles] table");
+    println!("  --no-sync-dirs                          - Refuse an output directory inside a Dropbox, OneDrive or other synced folder");
+    println!("  --split K/N                             - Write N Shamir shares of each secret, any K of which recover it");
+    println!("\nSeed options (grind-seed also takes --starts-with/--ends-with, --ignore-case, --num-threads and --progress-interval):");
+    println!("  --seed-length N                         - Characters in each seed, 1 to 32 (default: 12)");
+    println!("\nMint options (mint-vanity also takes the grind options):");
+    println!("  --token-program token|token-2022        - Program that owns the mint (default: token-2022)");
+    println!("  --decimals N                            - Mint decimals (default: 9)");
+    println!("  --mint-authority PUBKEY                 - Mint authority (default: the spl-token client keypair)");
+    println!("  --enable-freeze                         - Make the mint authority the freeze authority too");
+    println!("  --json                                  - Print the create instructions as JSON (needs --mint-authority)");
+    println!("  --payer PUBKEY                          - Funds the mint account in --json output (default: mint authority)");
+    println!("\nAddress options (address and address-set):");
+    println!("  --rust-test FILE                        - Also write a Rust test rebuilding each address from the constants");
+    println!("  --constant-time                         - Compare every segment and branch once, instead of exiting early");
+    println!("  --format asm|pinocchio|anchor           - sBPF assembly (default), an is_admin function for Rust programs,");
+    println!("                                            or Anchor constants and an only_admin access_control check");
+    println!("\nBench options:");
+    println!("  --seconds N                             - Seconds to run each batch size (default: 5)");
+    println!("  --num-threads N, --scheduler threads|rayon");
     println!("\nExamples:");
     println!("  doppler-keygen grind         - Find 1 key");
     println!("  doppler-keygen grind 5       - Find 5 keys");
+    println!("  doppler-keygen grind --starts-with ab:2 - Find 2 keys starting with 'ab'");
     println!("  doppler-keygen address key.json - Convert key.json to assembly format");
 }
 
+/// Prints `error`, with any secret in it redacted, and exits.
+fn exit_with_error(error: impl core::fmt::Display) -> ! {
+    eprintln!("Error: {}", redact::scrub(&error.to_string()));
+    process::exit(1);
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    // Accepted anywhere, since the policy covers every command
+    if args.iter().any(|arg| arg == "--reveal-secrets") {
+        args.retain(|arg| arg != "--reveal-secrets");
+        redact::reveal_secrets();
+        eprintln!("Warning: --reveal-secrets is set; secret keys will not be redacted from output");
+    }
 
     if args.len() < 2 {
         print_usage();
@@ -300,33 +270,169 @@ fn main() {
 
     match args[1].as_str() {
         "grind" => {
-            let count = if args.len() > 2 {
-                args[2].parse::<usize>().unwrap_or_else(|_| {
-                    eprintln!("Error: Invalid count number");
-                    process::exit(1);
-                })
-            } else {
-                1
-            };
-
-            if count == 0 {
-                eprintln!("Error: Count must be at least 1");
-                process::exit(1);
+            if let Err(e) = commands::grind::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "grind-seed" => {
+            if let Err(e) = commands::grind_seed::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "blind-grind" => {
+            if let Err(e) = commands::blind_grind::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "blind-combine" => {
+            if let Err(e) = commands::blind_combine::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "lookup-table" => {
+            if let Err(e) = commands::lookup_table::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "mint-vanity" => {
+            if let Err(e) = commands::mint_vanity::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "bench" => {
+            if let Err(e) = commands::bench::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "verify-audit" => {
+            if let Err(e) = commands::verify_audit::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "sign" => {
+            if let Err(e) = commands::sign::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "verify-sig" => {
+            if let Err(e) = commands::verify_sig::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "paper" => {
+            if let Err(e) = commands::paper::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "import" => {
+            if let Err(e) = commands::import::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "machine-key" => {
+            if let Err(e) = commands::machine_key::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "migrate" => {
+            if let Err(e) = commands::migrate::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "due" => {
+            if let Err(e) = commands::due::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "inventory" => {
+            if let Err(e) = commands::inventory::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "kms-decrypt" => {
+            if let Err(e) = commands::kms_decrypt::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "from-dice" => {
+            if let Err(e) = commands::from_dice::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "selftest" => {
+            if let Err(e) = commands::selftest::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "combine" => {
+            if let Err(e) = commands::combine::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "shred" => {
+            if let Err(e) = commands::shred::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "ceremony" => {
+            if let Err(e) = commands::ceremony::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "job" => {
+            if let Err(e) = commands::job::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "work-unit" => {
+            if let Err(e) = commands::work_unit::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "pool" => {
+            if let Err(e) = commands::pool::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "repl" => {
+            if let Err(e) = commands::repl::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "daemon" => {
+            if let Err(e) = commands::daemon::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "inspect" => {
+            if let Err(e) = commands::inspect::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "rotate" => {
+            if let Err(e) = commands::rotate::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "diff" => {
+            if let Err(e) = commands::diff::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "address-set" => {
+            if let Err(e) = commands::address_set::command(&args[2..]) {
+                exit_with_error(e);
+            }
+        }
+        "simulate" => {
+            if let Err(e) = commands::simulate::command(&args[2..]) {
+                exit_with_error(e);
             }
-
-            grind_keys(count);
         }
         "address" => {
-            if args.len() != 3 {
-                eprintln!("Error: address command requires a keypair file");
-                eprintln!("Usage: {} address <keypair.json>", args[0]);
-                process::exit(1);
+            if let Err(e) = commands::address::command(&args[2..]) {
+                exit_with_error(e);
             }
-
-            if let Err(e) = address_from_keypair(&args[2]) {
-                eprintln!("Error converting keypair: {e}");
-                process::exit(1);
-            };
         }
         _ => {
             eprintln!("Error: Unknown command '{}'\n", args[1]);
@@ -0,0 +1,157 @@
+//! Moving output directories written before encryption at rest to the
+//! machine key.
+//!
+//! `migrate` looks for plaintext keypair files under a directory, including
+//! rotation and shard subdirectories: files named `<pubkey>.json`, `.bs58`,
+//! `.bin` or `.seed` whose contents are the keypair of that public key.
+//! Anything else, such as `pubkeys.txt` or a ceremony transcript, is left
+//! alone. Each file is encrypted to the machine key as `<file>.age`, the
+//! result is read back and decrypted to check it, and only then is the
+//! plaintext shredded.
+
+use solana_signer::Signer as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::cli_error::CliError;
+use crate::encrypt::{AgeRecipients, ENCRYPTED_EXTENSION};
+use crate::keysource;
+use crate::machinekey::UnlockedKey;
+use crate::secretfile;
+use crate::shred;
+use crate::grind::OutputFormat;
+
+/// Whether `path` is a plaintext keypair file: its name is the public key
+/// of its contents with a keypair format's extension.
+fn is_plaintext_keypair(path: &Path) -> bool {
+    let (Some(stem), Some(extension)) = (path.file_stem().and_then(|s| s.to_str()), path.extension().and_then(|e| e.to_str()))
+    else {
+        return false;
+    };
+    OutputFormat::parse(extension).is_ok()
+        && fs::read(path)
+            .ok()
+            .and_then(|contents| keysource::parse_keypair_file(&contents).ok())
+            .is_some_and(|keypair| keypair.pubkey().to_string() == stem)
+}
+
+/// Every regular file under `dir` and its subdirectories, in path order.
+/// Symbolic links are not followed.
+pub fn files_under(dir: &Path) -> Result<Vec<PathBuf>, CliError> {
+    let read_error = |e: std::io::Error| CliError::OutputDir { path: dir.to_path_buf(), reason: e.to_string() };
+    let mut entries = fs::read_dir(dir)
+        .map_err(read_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_error)?;
+    entries.sort();
+
+    let mut files = Vec::new();
+    for path in entries {
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            files.extend(files_under(&path)?);
+        } else if metadata.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Every plaintext keypair file under `dir`, in path order.
+pub fn plaintext_keypairs(dir: &Path) -> Result<Vec<PathBuf>, CliError> {
+    Ok(files_under(dir)?.into_iter().filter(|path| is_plaintext_keypair(path)).collect())
+}
+
+/// Encrypts one plaintext keypair file to `recipients`, checks the result
+/// decrypts with `key`, and shreds the plaintext. Returns the encrypted
+/// file's path.
+pub fn migrate_file(path: &Path, recipients: &AgeRecipients, key: &UnlockedKey) -> Result<PathBuf, CliError> {
+    let mut encrypted_path = path.as_os_str().to_owned();
+    encrypted_path.push(format!(".{ENCRYPTED_EXTENSION}"));
+    let encrypted_path = PathBuf::from(encrypted_path);
+
+    let plaintext =
+        fs::read(path).map_err(|e| CliError::ReadKeypair { from: path.display().to_string(), reason: e.to_string() })?;
+    let ciphertext = recipients.encrypt(&plaintext)?;
+    secretfile::create(&encrypted_path)
+        .and_then(|mut out| out.write_all(&ciphertext).and_then(|()| out.sync_all()))
+        .map_err(|source| CliError::WriteKeypair { path: encrypted_path.clone(), source })?;
+
+    // Check what reached the disk before the only plaintext copy goes
+    let written = fs::read(&encrypted_path)
+        .map_err(|e| CliError::ReadKeypair { from: encrypted_path.display().to_string(), reason: e.to_string() })?;
+    if key.decrypt(&written)? != plaintext {
+        return Err(CliError::Encrypt(format!("{} did not decrypt back to {}", encrypted_path.display(), path.display())));
+    }
+    shred::shred_file(path)?;
+    Ok(encrypted_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machinekey::{self, MachineKey};
+    use solana_keypair::Keypair;
+
+    /// Saves `keypair` under `dir` as `<pubkey>.<format>`.
+    fn save(dir: &Path, keypair: &Keypair, format: OutputFormat) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join(format!("{}.{}", keypair.pubkey(), format.extension()));
+        fs::write(&path, format.encode(keypair)).unwrap();
+        path
+    }
+
+    #[test]
+    fn only_keypairs_named_after_their_key_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = save(dir.path(), &Keypair::new(), OutputFormat::Json);
+        let bs58 = save(&dir.path().join("2025-06-01"), &Keypair::new(), OutputFormat::Bs58);
+        // A keypair under another key's name, and files that hold no keypair
+        let misnamed = save(dir.path(), &Keypair::new(), OutputFormat::Json);
+        fs::rename(&misnamed, dir.path().join(format!("{}.json", Keypair::new().pubkey()))).unwrap();
+        fs::write(dir.path().join("pubkeys.txt"), "Pubkey111 0\n").unwrap();
+        fs::write(dir.path().join("notes.json"), "[]").unwrap();
+
+        let mut expected = vec![json, bs58];
+        expected.sort();
+        assert_eq!(plaintext_keypairs(dir.path()).unwrap(), expected);
+    }
+
+    #[test]
+    fn migrated_files_decrypt_to_the_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("machine-key.txt");
+        let machine_key = machinekey::create(&key_path, None).unwrap();
+        let unlocked = UnlockedKey::unlock_at(key_path.clone()).unwrap();
+
+        let keypair = Keypair::new();
+        let path = save(&dir.path().join("keys"), &keypair, OutputFormat::Json);
+        let plaintext = fs::read(&path).unwrap();
+        let encrypted = migrate_file(&path, &machine_key.recipients(), &unlocked).unwrap();
+
+        assert_eq!(encrypted, dir.path().join("keys").join(format!("{}.json.age", keypair.pubkey())));
+        assert!(!path.exists());
+        let ciphertext = fs::read(&encrypted).unwrap();
+        assert!(machinekey::is_encrypted(&ciphertext));
+        assert_eq!(unlocked.decrypt(&ciphertext).unwrap(), plaintext);
+        assert!(plaintext_keypairs(&dir.path().join("keys")).unwrap().is_empty());
+        assert!(MachineKey::load_from(key_path).unwrap().is_some());
+    }
+
+    #[test]
+    fn a_key_that_cant_decrypt_leaves_the_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let machine_key = machinekey::create(&dir.path().join("machine-key.txt"), None).unwrap();
+        let other_path = dir.path().join("other-key.txt");
+        machinekey::create(&other_path, None).unwrap();
+        let other = UnlockedKey::unlock_at(other_path).unwrap();
+
+        let path = save(&dir.path().join("keys"), &Keypair::new(), OutputFormat::Json);
+        assert!(migrate_file(&path, &machine_key.recipients(), &other).is_err());
+        assert!(path.exists());
+    }
+}
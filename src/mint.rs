@@ -0,0 +1,200 @@
+//! `mint-vanity`: token mints at vanity addresses.
+//!
+//! A mint's address is the public key of the keypair that signs its
+//! creation, so a vanity mint is an ordinary ground keypair. This module
+//! turns each found keypair into the `spl-token create-token` command that
+//! creates the mint, or the raw instructions for tooling that builds its own
+//! transactions.
+
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Bytes in a mint account without extensions.
+const MINT_SIZE: u64 = 82;
+
+/// Rent-exempt balance of a `MINT_SIZE` account at the default rent rate:
+/// (82 + 128 bytes of account overhead) * 3480 lamports/byte-year * 2 years.
+const MINT_RENT_EXEMPT_LAMPORTS: u64 = (MINT_SIZE + 128) * 3_480 * 2;
+
+const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
+
+/// System program `CreateAccount` instruction index.
+const CREATE_ACCOUNT: u32 = 0;
+
+/// Token program `InitializeMint2` instruction index.
+const INITIALIZE_MINT2: u8 = 20;
+
+/// The token program that owns the mint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenProgram {
+    Token,
+    Token2022,
+}
+
+impl TokenProgram {
+    pub fn parse(value: &str) -> Result<TokenProgram, String> {
+        match value {
+            "token" => Ok(TokenProgram::Token),
+            "token-2022" => Ok(TokenProgram::Token2022),
+            _ => Err(format!("Unknown token program '{value}' (expected token or token-2022)")),
+        }
+    }
+
+    pub fn program_id(&self) -> &'static str {
+        match self {
+            TokenProgram::Token => "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+            TokenProgram::Token2022 => "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+        }
+    }
+}
+
+/// How each found mint is to be created.
+#[derive(Debug, Clone)]
+pub struct MintPlan {
+    pub program: TokenProgram,
+    pub decimals: u8,
+    /// Defaults to the `spl-token` client keypair for commands; required for
+    /// instructions
+    pub mint_authority: Option<String>,
+    /// Also make the mint authority the freeze authority
+    pub enable_freeze: bool,
+    /// Funds the mint account; defaults to the mint authority
+    pub payer: Option<String>,
+}
+
+impl MintPlan {
+    /// The `spl-token` invocation that creates the mint from `keypair_path`.
+    pub fn command(&self, keypair_path: &Path) -> String {
+        let mut command = format!(
+            "spl-token create-token --program-id {} --decimals {}",
+            self.program.program_id(),
+            self.decimals
+        );
+        if let Some(mint_authority) = &self.mint_authority {
+            command.push_str(&format!(" --mint-authority {mint_authority}"));
+        }
+        if self.enable_freeze {
+            command.push_str(" --enable-freeze");
+        }
+        command.push_str(&format!(" {}", keypair_path.display()));
+        command
+    }
+
+    /// The instructions that create and initialize `mint`: a system
+    /// `CreateAccount` funded by the payer, then `InitializeMint2`. Both the
+    /// payer and the mint keypair at `keypair_path` must sign.
+    pub fn instructions(&self, mint: &str, keypair_path: &Path) -> Result<Value, String> {
+        let mint_authority = self
+            .mint_authority
+            .as_deref()
+            .ok_or_else(|| "--json requires --mint-authority".to_string())?;
+        let payer = self.payer.as_deref().unwrap_or(mint_authority);
+        let mint_authority_bytes = decode_pubkey("--mint-authority", mint_authority)?;
+        decode_pubkey("--payer", payer)?;
+
+        let mut create_account = CREATE_ACCOUNT.to_le_bytes().to_vec();
+        create_account.extend_from_slice(&MINT_RENT_EXEMPT_LAMPORTS.to_le_bytes());
+        create_account.extend_from_slice(&MINT_SIZE.to_le_bytes());
+        create_account.extend_from_slice(&decode_pubkey("token program", self.program.program_id())?);
+
+        let mut initialize_mint = vec![INITIALIZE_MINT2, self.decimals];
+        initialize_mint.extend_from_slice(&mint_authority_bytes);
+        if self.enable_freeze {
+            initialize_mint.push(1);
+            initialize_mint.extend_from_slice(&mint_authority_bytes);
+        } else {
+            initialize_mint.push(0);
+        }
+
+        Ok(json!({
+            "mint": mint,
+            "keypair": keypair_path.display().to_string(),
+            "tokenProgram": self.program.program_id(),
+            "decimals": self.decimals,
+            "instructions": [
+                {
+                    "programId": SYSTEM_PROGRAM_ID,
+                    "accounts": [
+                        { "pubkey": payer, "isSigner": true, "isWritable": true },
+                        { "pubkey": mint, "isSigner": true, "isWritable": true },
+                    ],
+                    "data": bs58::encode(create_account).into_string(),
+                },
+                {
+                    "programId": self.program.program_id(),
+                    "accounts": [
+                        { "pubkey": mint, "isSigner": false, "isWritable": true },
+                    ],
+                    "data": bs58::encode(initialize_mint).into_string(),
+                },
+            ],
+        }))
+    }
+}
+
+fn decode_pubkey(name: &str, value: &str) -> Result<[u8; 32], String> {
+    bs58::decode(value)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid {name} '{value}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `[1; 32]`, `[2; 32]` and `[3; 32]` as the payer, mint and mint authority.
+    const PAYER: &str = "4vJ9JU1bJJE96FWSJKvHsmmFADCg4gpZQff4P3bkLKi";
+    const MINT: &str = "8qbHbw2BbbTHBW1sbeqakYXVKRQM8Ne7pLK7m6CVfeR";
+    const AUTHORITY: &str = "CktRuQ2mttgRGkXJtyksdKHjUdc2C4TgDzyB98oEzy8";
+
+    fn plan(program: TokenProgram, decimals: u8, enable_freeze: bool) -> MintPlan {
+        MintPlan {
+            program,
+            decimals,
+            mint_authority: Some(AUTHORITY.to_string()),
+            enable_freeze,
+            payer: Some(PAYER.to_string()),
+        }
+    }
+
+    /// Instruction data from `solana_system_interface::instruction::create_account`
+    /// with `Rent::default().minimum_balance(82)`, and from the token
+    /// programs' `initialize_mint2`.
+    #[test]
+    fn instructions_match_the_sdk() {
+        let cases = [
+            (
+                plan(TokenProgram::Token, 6, false),
+                "11114XtYk9gGfZoo968fyjNUYQJKf9gdmkGoaoBpzFv4vyaSMBn3VKxZdv7mZLzoyX5YNC",
+                "2zt5MQ3M1inojhpcMqUrTyWUY2bqRBaQJHnbJGtT4uDmePZZ",
+            ),
+            (
+                plan(TokenProgram::Token2022, 9, true),
+                "11114XtYk9gGfZoo968fyjNUYQJKf9ge1WDaHjxgexU9L1x1WxdBDTdfqHEowgS2kReNPd",
+                "bSkCaTaBgDCNau8NA7rxSCFxS1zdpFrByzf1MSruJ4E7vVKARNMaW9c9Yo8mPrTjSgrQtXmcbwpZEMRnatcvxa4SoW2",
+            ),
+        ];
+        for (plan, create_account, initialize_mint) in cases {
+            let json = plan.instructions(MINT, Path::new("mint.json")).unwrap();
+            let instructions = &json["instructions"];
+            assert_eq!(instructions[0]["programId"], SYSTEM_PROGRAM_ID);
+            assert_eq!(instructions[0]["accounts"][0]["pubkey"], PAYER);
+            assert_eq!(instructions[0]["accounts"][1]["pubkey"], MINT);
+            assert_eq!(instructions[0]["data"], create_account, "{:?}", plan.program);
+            assert_eq!(instructions[1]["programId"], plan.program.program_id());
+            assert_eq!(instructions[1]["accounts"][0]["pubkey"], MINT);
+            assert_eq!(instructions[1]["data"], initialize_mint, "{:?}", plan.program);
+        }
+    }
+
+    #[test]
+    fn instructions_need_valid_authorities() {
+        let mut plan = plan(TokenProgram::Token, 6, false);
+        plan.payer = Some("not-base58".to_string());
+        assert_eq!(plan.instructions(MINT, Path::new("mint.json")).unwrap_err(), "Invalid --payer 'not-base58'");
+        plan.mint_authority = None;
+        assert_eq!(plan.instructions(MINT, Path::new("mint.json")).unwrap_err(), "--json requires --mint-authority");
+    }
+}
@@ -0,0 +1,165 @@
+//! `grind --near`: keys whose address is close to a target address.
+//!
+//! Instead of a pass/fail pattern every candidate gets a distance to the
+//! target, and the run keeps the closest keys it has seen. Near misses of a
+//! real address are what address-poisoning attacks send from, so these keys
+//! are test material for the detection side.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use doppler_keygen::scheduler::Candidate;
+
+/// How close two addresses are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distance {
+    /// Number of differing bits
+    Bits,
+    /// Number of differing bytes
+    Bytes,
+}
+
+impl Distance {
+    pub fn parse(value: &str) -> Result<Distance, String> {
+        match value {
+            "bits" => Ok(Distance::Bits),
+            "bytes" => Ok(Distance::Bytes),
+            _ => Err(format!("Unknown distance '{value}' (expected bits or bytes)")),
+        }
+    }
+
+    pub fn measure(&self, a: &[u8; 32], b: &[u8; 32]) -> u32 {
+        let pairs = a.iter().zip(b);
+        match self {
+            Distance::Bits => pairs.map(|(a, b)| (a ^ b).count_ones()).sum(),
+            Distance::Bytes => pairs.filter(|(a, b)| a != b).count() as u32,
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Distance::Bits => "bits",
+            Distance::Bytes => "bytes",
+        }
+    }
+}
+
+/// What to search near.
+#[derive(Debug, Clone)]
+pub struct NearSearch {
+    pub target: [u8; 32],
+    /// How many of the closest keys to keep
+    pub top: usize,
+    pub distance: Distance,
+    /// Save each key as it enters the top and shred it once it is pushed
+    /// out, rather than saving the survivors when the run ends
+    pub shred_losers: bool,
+}
+
+/// A candidate kept for being close to the target.
+#[derive(Debug, Clone)]
+pub struct NearKey {
+    pub distance: u32,
+    pub seed: [u8; 32],
+    pub address: [u8; 32],
+    /// Bit `n` is set if segment `n` of `address` forms a valid 32-bit
+    /// immediate
+    pub imm32_mask: u8,
+}
+
+/// The closest keys seen so far, shared by every worker.
+pub struct Closest {
+    search: NearSearch,
+    keys: Mutex<Vec<NearKey>>,
+    /// Distance a candidate must beat to be kept; only raised from
+    /// `u32::MAX` once `top` keys are held, so most candidates are turned
+    /// away without taking the lock
+    threshold: AtomicU32,
+    best: AtomicU32,
+}
+
+impl Closest {
+    pub fn new(search: NearSearch) -> Closest {
+        Closest {
+            keys: Mutex::new(Vec::with_capacity(search.top + 1)),
+            search,
+            threshold: AtomicU32::new(u32::MAX),
+            best: AtomicU32::new(u32::MAX),
+        }
+    }
+
+    /// Keeps `candidate` if it is among the closest so far, returning its
+    /// distance if it is the closest yet. `kept` is called with the new key
+    /// and the key it pushed out of the top, if any, while the lock is
+    /// held, so it sees keys enter and leave in order.
+    pub fn offer(&self, candidate: &Candidate, kept: impl FnOnce(&NearKey, Option<NearKey>)) -> Option<u32> {
+        let distance = self.search.distance.measure(&candidate.address, &self.search.target);
+        if distance >= self.threshold.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        // Another worker may have tightened the threshold since the check
+        // above
+        if distance >= self.threshold.load(Ordering::Relaxed) {
+            return None;
+        }
+        let key = NearKey { distance, seed: candidate.seed, address: candidate.address, imm32_mask: candidate.imm32_mask };
+        keys.push(key.clone());
+        let mut evicted = None;
+        if keys.len() > self.search.top {
+            // The new key beats the threshold, so it is never the one
+            // dropped
+            keys.sort_by_key(|key| key.distance);
+            evicted = keys.pop();
+        }
+        if keys.len() == self.search.top {
+            let worst = keys.iter().map(|key| key.distance).max().unwrap_or(u32::MAX);
+            self.threshold.store(worst, Ordering::Relaxed);
+        }
+        kept(&key, evicted);
+        drop(keys);
+
+        (self.best.fetch_min(distance, Ordering::Relaxed) > distance).then_some(distance)
+    }
+
+    /// The smallest distance seen so far.
+    pub fn best(&self) -> Option<u32> {
+        Some(self.best.load(Ordering::Relaxed)).filter(|&best| best != u32::MAX)
+    }
+
+    /// The keys kept, closest first.
+    pub fn into_keys(self) -> Vec<NearKey> {
+        let mut keys = self.keys.into_inner().unwrap_or_else(PoisonError::into_inner);
+        keys.sort_by_key(|key| key.distance);
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A candidate whose address differs from the zero address in `bits`
+    /// bits.
+    fn candidate(bits: u32) -> Candidate {
+        let mut address = [0u8; 32];
+        address[..8].copy_from_slice(&((1u64 << bits) - 1).to_le_bytes());
+        Candidate { seed: [bits as u8; 32], pubkey: address, address, imm32_mask: 0 }
+    }
+
+    #[test]
+    fn reports_the_keys_pushed_out_of_the_top() {
+        let search = NearSearch { target: [0; 32], top: 2, distance: Distance::Bits, shred_losers: true };
+        let closest = Closest::new(search);
+        let mut log = Vec::new();
+        for bits in [5, 3, 4, 6, 1] {
+            closest.offer(&candidate(bits), |key, evicted| log.push((key.distance, evicted.map(|key| key.distance))));
+        }
+
+        assert_eq!(log, [(5, None), (3, None), (4, Some(5)), (1, Some(4))]);
+        assert_eq!(closest.best(), Some(1));
+        let kept: Vec<_> = closest.into_keys().iter().map(|key| (key.distance, key.seed[0])).collect();
+        assert_eq!(kept, [(1, 1), (3, 3)]);
+    }
+}
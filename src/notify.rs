@@ -0,0 +1,414 @@
+//! Notifications sent when a grind run ends.
+//!
+//! With an `[email]` table in the config file and at least one recipient,
+//! from its `to` list or `--email-to`, a summary email goes out once the
+//! search finishes or fails: the patterns and the keys each found, the
+//! attempts and the time taken, with a `manifest.json` attachment holding
+//! the same for scripts. The email is for unattended runs, so failing to
+//! send it is reported but never fails the run. It carries public keys
+//! only, never secrets.
+
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport as _};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use doppler_keygen::batch::PatternStats;
+use doppler_keygen::stats::StatsSummary;
+
+use crate::cli_error::CliError;
+use crate::config::env_var;
+use crate::redact;
+use crate::tags::KeyTags;
+
+/// How long connecting to the SMTP server, and each command, may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Name of the manifest attached to the email.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The `[email]` table of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    /// SMTP server: `smtps://HOST[:PORT]` for TLS, or
+    /// `smtp://HOST[:PORT]?tls=required` for STARTTLS
+    pub smtp: String,
+    /// Login on the server, whose password is read from
+    /// `DOPPLER_SMTP_PASSWORD`
+    pub username: Option<String>,
+    /// Sender address
+    pub from: String,
+    /// Recipient addresses, added to any given with `--email-to`
+    #[serde(default)]
+    pub to: Vec<String>,
+}
+
+/// A key saved by the run.
+#[derive(Debug, Clone, Serialize)]
+struct ReportKey {
+    pubkey: String,
+    /// The matched address, when it is derived from the key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    segment: usize,
+}
+
+/// How far a batch pattern got.
+#[derive(Debug, Clone, Serialize)]
+struct ReportPattern {
+    pattern: String,
+    requested: usize,
+    found: usize,
+    /// Dropped by a reload before it was complete
+    #[serde(skip_serializing_if = "core::ops::Not::not")]
+    removed: bool,
+}
+
+/// What a run did, collected as it goes for the notifications sent when it
+/// ends.
+#[derive(Debug)]
+pub struct RunReport {
+    started: Instant,
+    started_at: u64,
+    /// Keys the run set out to find, if it has an end
+    requested: Option<usize>,
+    output_dir: PathBuf,
+    tags: KeyTags,
+    state: Mutex<ReportState>,
+}
+
+#[derive(Debug, Default)]
+struct ReportState {
+    keys: Vec<ReportKey>,
+    patterns: Vec<ReportPattern>,
+    stats: Option<StatsSummary>,
+}
+
+/// The machine-readable outcome of a run, attached to the email.
+#[derive(Debug, Serialize)]
+struct Manifest {
+    /// `complete` once every requested key is saved, `incomplete` if the run
+    /// stopped short, `stopped` for a run with no end, or `failed`
+    status: &'static str,
+    host: String,
+    started_at: u64,
+    finished_at: u64,
+    output_dir: PathBuf,
+    #[serde(flatten)]
+    tags: KeyTags,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requested: Option<usize>,
+    found: usize,
+    patterns: Vec<ReportPattern>,
+    keys: Vec<ReportKey>,
+    attempts: u128,
+    elapsed_secs: f64,
+    average_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl RunReport {
+    pub fn new(requested: Option<usize>, output_dir: &Path, tags: &KeyTags) -> RunReport {
+        RunReport {
+            started: Instant::now(),
+            started_at: unix_time(),
+            requested,
+            output_dir: output_dir.to_path_buf(),
+            tags: tags.clone(),
+            state: Mutex::new(ReportState::default()),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, ReportState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn record_key(&self, pubkey: String, address: Option<String>, segment: usize) {
+        self.state().keys.push(ReportKey { pubkey, address, segment });
+    }
+
+    pub fn record_patterns(&self, patterns: &[PatternStats]) {
+        self.state().patterns = patterns
+            .iter()
+            .map(|p| ReportPattern {
+                pattern: p.pattern.to_string(),
+                requested: p.requested,
+                found: p.found,
+                removed: p.removed,
+            })
+            .collect();
+    }
+
+    pub fn record_stats(&self, stats: &StatsSummary) {
+        self.state().stats = Some(stats.clone());
+    }
+
+    fn manifest(&self, error: Option<&CliError>) -> Manifest {
+        let state = self.state();
+        // A failed run has no summary, so its time is measured here
+        let (attempts, elapsed, average_rate) = state
+            .stats
+            .as_ref()
+            .map_or((0, self.started.elapsed(), 0.0), |stats| (stats.total_attempts, stats.elapsed, stats.average_rate));
+        let status = match (error, self.requested) {
+            (Some(_), _) => "failed",
+            (None, Some(requested)) if state.keys.len() >= requested => "complete",
+            (None, Some(_)) => "incomplete",
+            (None, None) => "stopped",
+        };
+        Manifest {
+            status,
+            host: crate::audit::hostname(),
+            started_at: self.started_at,
+            finished_at: unix_time(),
+            output_dir: self.output_dir.clone(),
+            tags: self.tags.clone(),
+            requested: self.requested,
+            found: state.keys.len(),
+            patterns: state.patterns.clone(),
+            keys: state.keys.clone(),
+            attempts,
+            elapsed_secs: elapsed.as_secs_f64(),
+            average_rate,
+            error: error.map(ToString::to_string),
+        }
+    }
+}
+
+/// The summary email's subject and text.
+fn email_text(manifest: &Manifest) -> (String, String) {
+    let found = match manifest.requested {
+        Some(requested) => format!("{}/{requested}", manifest.found),
+        None => manifest.found.to_string(),
+    };
+    let subject = format!("doppler-keygen on {}: {}, {found} key(s) found", manifest.host, manifest.status);
+
+    let mut body = String::new();
+    let _ = writeln!(body, "The grind run on {} ended: {}.", manifest.host, manifest.status);
+    if let Some(error) = &manifest.error {
+        let _ = writeln!(body, "\nError: {error}");
+    }
+    let _ = writeln!(body, "\nKeys found: {found}");
+    let _ = writeln!(body, "Total attempts: {}", manifest.attempts);
+    let _ = writeln!(body, "Time elapsed: {}", crate::grind::format_eta(manifest.elapsed_secs));
+    let _ = writeln!(body, "Average rate: {:.0} keys/sec", manifest.average_rate);
+    let _ = writeln!(body, "Output directory: {}", manifest.output_dir.display());
+    if !manifest.tags.is_empty() {
+        let _ = writeln!(body, "Tags: {}", manifest.tags.describe());
+    }
+    if !manifest.patterns.is_empty() {
+        let _ = writeln!(body, "\nPatterns:");
+        for p in &manifest.patterns {
+            let removed = if p.removed { " (removed)" } else { "" };
+            let _ = writeln!(body, "  {}: {}/{}{removed}", p.pattern, p.found, p.requested);
+        }
+    }
+    if !manifest.keys.is_empty() {
+        let _ = writeln!(body, "\nPublic keys:");
+        for key in &manifest.keys {
+            match &key.address {
+                Some(address) => {
+                    let _ = writeln!(body, "  {} -> {address} (segment {})", key.pubkey, key.segment);
+                }
+                None => {
+                    let _ = writeln!(body, "  {} (segment {})", key.pubkey, key.segment);
+                }
+            }
+        }
+    }
+    let _ = writeln!(body, "\nThe attached {MANIFEST_FILE} has the same details.");
+    (subject, body)
+}
+
+/// Sends the summary email of a run over SMTP.
+pub struct EmailNotifier {
+    transport: SmtpTransport,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl std::fmt::Debug for EmailNotifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmailNotifier").field("from", &self.from).field("to", &self.to).finish_non_exhaustive()
+    }
+}
+
+impl EmailNotifier {
+    /// Checks the server URL and addresses of `config`, sending to its
+    /// recipients and `extra_to`. Nothing is sent until the run ends.
+    pub fn new(config: &EmailConfig, extra_to: &[String]) -> Result<EmailNotifier, CliError> {
+        let email_error = |reason: String| CliError::Config(format!("Invalid [email] settings: {reason}"));
+        let mut builder = SmtpTransport::from_url(&config.smtp)
+            .map_err(|e| email_error(format!("smtp '{}': {e}", config.smtp)))?
+            .timeout(Some(TIMEOUT));
+        if let Some(username) = &config.username {
+            let password = env_var("DOPPLER_SMTP_PASSWORD")
+                .ok_or_else(|| email_error(format!("username '{username}' needs a password in DOPPLER_SMTP_PASSWORD")))?;
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        let parse = |address: &String| {
+            address.parse::<Mailbox>().map_err(|e| email_error(format!("address '{address}': {e}")))
+        };
+        let from = parse(&config.from)?;
+        let to = config.to.iter().chain(extra_to).map(parse).collect::<Result<Vec<_>, _>>()?;
+        Ok(EmailNotifier { transport: builder.build(), from, to })
+    }
+
+    /// The recipients, for display.
+    pub fn recipients(&self) -> impl Iterator<Item = String> + '_ {
+        self.to.iter().map(ToString::to_string)
+    }
+
+    /// Emails the outcome of the run `report` covers, with `error` if it
+    /// failed.
+    pub fn send(&self, report: &RunReport, error: Option<&CliError>) -> Result<(), String> {
+        let manifest = report.manifest(error);
+        let (subject, body) = email_text(&manifest);
+        let (subject, body) = (redact::scrub(&subject), redact::scrub(&body));
+        let json = redact::scrub(&serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?);
+        let attachment = Attachment::new(MANIFEST_FILE.to_string())
+            .body(json, ContentType::parse("application/json").expect("the content type is valid"));
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message
+            .multipart(MultiPart::mixed().singlepart(SinglePart::plain(body)).singlepart(attachment))
+            .map_err(|e| e.to_string())?;
+        self.transport.send(&message).map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use doppler_keygen::pattern::VanityPattern;
+
+    fn config(smtp: &str, from: &str, to: &[&str]) -> EmailConfig {
+        EmailConfig {
+            smtp: smtp.to_string(),
+            username: None,
+            from: from.to_string(),
+            to: to.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    fn report(requested: Option<usize>) -> RunReport {
+        let mut tags = KeyTags::default();
+        tags.add_tag("launch").unwrap();
+        tags.add_meta("owner=ops").unwrap();
+        RunReport::new(requested, Path::new("/keys"), &tags)
+    }
+
+    #[test]
+    fn settings_are_checked_without_connecting() {
+        let notifier = EmailNotifier::new(
+            &config("smtps://mail.example.com", "Doppler <doppler@example.com>", &["ops@example.com"]),
+            &["oncall@example.com".to_string()],
+        )
+        .unwrap();
+        let recipients: Vec<_> = notifier.recipients().collect();
+        assert_eq!(recipients, ["ops@example.com", "oncall@example.com"]);
+
+        for (config, reason) in [
+            (config("mail.example.com", "doppler@example.com", &[]), "smtp 'mail.example.com'"),
+            (config("smtps://mail.example.com", "not an address", &[]), "address 'not an address'"),
+            (config("smtps://mail.example.com", "doppler@example.com", &["ops@"]), "address 'ops@'"),
+        ] {
+            let error = EmailNotifier::new(&config, &[]).unwrap_err().to_string();
+            assert!(error.contains("Invalid [email] settings") && error.contains(reason), "{error}");
+        }
+    }
+
+    #[test]
+    fn status_follows_the_keys_found() {
+        let report = report(Some(2));
+        report.record_key("Pubkey1".to_string(), None, 0);
+        assert_eq!(report.manifest(None).status, "incomplete");
+        report.record_key("Pubkey2".to_string(), Some("Address2".to_string()), 3);
+        assert_eq!(report.manifest(None).status, "complete");
+        let error = CliError::Usage("out of disk".to_string());
+        assert_eq!(report.manifest(Some(&error)).status, "failed");
+        assert_eq!(self::report(None).manifest(None).status, "stopped");
+    }
+
+    #[test]
+    fn email_lists_patterns_and_public_keys() {
+        let report = report(Some(3));
+        report.record_patterns(&[
+            PatternStats {
+                pattern: VanityPattern { prefix: Some("ab".to_string()), suffix: None },
+                requested: 2,
+                found: 2,
+                removed: false,
+                workers: 0,
+            },
+            PatternStats {
+                pattern: VanityPattern { prefix: None, suffix: Some("yz".to_string()) },
+                requested: 1,
+                found: 0,
+                removed: true,
+                workers: 0,
+            },
+        ]);
+        report.record_key("Pubkey1".to_string(), None, 0);
+        report.record_key("Pubkey2".to_string(), Some("Address2".to_string()), 3);
+        report.record_stats(&StatsSummary {
+            total_attempts: 1_500_000,
+            elapsed: Duration::from_secs(90),
+            average_rate: 16_666.7,
+            rate_percentiles: None,
+            workers: Vec::new(),
+        });
+
+        let manifest = report.manifest(None);
+        let (subject, body) = email_text(&manifest);
+        assert_eq!(subject, format!("doppler-keygen on {}: incomplete, 2/3 key(s) found", manifest.host));
+        for line in [
+            "Keys found: 2/3",
+            "Total attempts: 1500000",
+            "Time elapsed: 1m 30s",
+            "Average rate: 16667 keys/sec",
+            "Output directory: /keys",
+            "Tags: launch, owner=ops",
+            "  starts with 'ab': 2/2\n",
+            "  ends with 'yz': 0/1 (removed)",
+            "  Pubkey1 (segment 0)",
+            "  Pubkey2 -> Address2 (segment 3)",
+        ] {
+            assert!(body.contains(line), "missing {line:?} in:\n{body}");
+        }
+        assert!(!body.contains("Error:"));
+
+        let json: serde_json::Value = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["status"], "incomplete");
+        assert_eq!(json["meta"]["owner"], "ops");
+        assert_eq!(json["attempts"], 1_500_000);
+        assert_eq!(json["keys"][0], serde_json::json!({ "pubkey": "Pubkey1", "segment": 0 }));
+        assert_eq!(json["patterns"][1]["removed"], true);
+        assert!(json["patterns"][0].get("removed").is_none());
+        assert!(json.get("error").is_none());
+    }
+
+    #[test]
+    fn a_failed_run_reports_its_error() {
+        let report = report(None);
+        let error = CliError::Usage("out of disk".to_string());
+        let manifest = report.manifest(Some(&error));
+        let (subject, body) = email_text(&manifest);
+        assert!(subject.ends_with(": failed, 0 key(s) found"), "{subject}");
+        assert!(body.contains("\nError: out of disk\n"), "{body}");
+        assert_eq!(manifest.error.as_deref(), Some("out of disk"));
+    }
+}
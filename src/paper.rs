@@ -0,0 +1,145 @@
+//! Printable paper wallet sheets.
+//!
+//! `doppler-keygen paper` renders each keypair as an A4 SVG sheet: the
+//! address and its QR code with the imm32 segment analysis on the top half,
+//! and the secret key with its QR code on the bottom half, below a fold line
+//! so the sheet can be folded and sealed with the secret inside.
+//!
+//! Sheets are SVG only; a browser prints one to PDF. They carry no seed
+//! phrase: a ground key is a random seed, not one derived from a phrase, so
+//! there is no mnemonic a wallet could restore it from.
+
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+
+use doppler_keygen::imm32;
+
+use crate::cli_error::CliError;
+use crate::qr;
+
+/// Sheet size in millimetres (A4 portrait).
+const PAGE_WIDTH: f64 = 210.0;
+const PAGE_HEIGHT: f64 = 297.0;
+
+/// Where the sheet is folded, separating the public and secret halves.
+const FOLD_Y: f64 = PAGE_HEIGHT / 2.0;
+
+const MARGIN: f64 = 15.0;
+const QR_SIZE: f64 = 60.0;
+
+/// Renders the sheet for `keypair`, leaving out the secret half when
+/// `include_secret` is false.
+pub fn render(keypair: &Keypair, include_secret: bool) -> Result<String, CliError> {
+    let address = keypair.pubkey().to_string();
+    let pubkey_bytes = keypair.pubkey().to_bytes();
+
+    let mut body = String::new();
+    body.push_str(&text(MARGIN, 22.0, 7.0, "bold", "Doppler Keygen paper wallet"));
+    body.push_str(&text(MARGIN, 34.0, 4.0, "bold", "Address"));
+    body.push_str(&monospace(MARGIN, 41.0, 4.2, &address));
+    body.push_str(&qr::svg_element(address.as_bytes(), MARGIN, 47.0, QR_SIZE)?);
+
+    // Segment analysis next to the address QR code
+    let table_x = MARGIN + QR_SIZE + 8.0;
+    body.push_str(&text(table_x, 54.0, 4.0, "bold", "32-bit immediate segments"));
+    for segment in 0..4 {
+        let offset = segment * 8;
+        let bytes = &pubkey_bytes[offset..offset + 8];
+        let y = 63.0 + segment as f64 * 11.0;
+        let line = format!("{segment}  {} | {}", hex::encode(&bytes[..4]), hex::encode(&bytes[4..]));
+        body.push_str(&monospace(table_x, y, 3.6, &line));
+
+        let detail = if imm32::segment_is_imm32_compatible(&pubkey_bytes, segment) {
+            let value = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            format!("imm32: {value} (0x{:08x})", value as u32)
+        } else {
+            "64-bit constant".to_string()
+        };
+        body.push_str(&text(table_x + 4.5, y + 4.5, 3.2, "normal", &detail));
+    }
+
+    body.push_str(&format!(
+        r##"<line x1="0" y1="{FOLD_Y}" x2="{PAGE_WIDTH}" y2="{FOLD_Y}" stroke="#000" stroke-width="0.4" stroke-dasharray="3 2"/>"##
+    ));
+    if include_secret {
+        body.push_str(&text(MARGIN, FOLD_Y - 3.0, 3.2, "normal", "Fold along the dashed line and seal. The secret key is below."));
+        body.push_str(&secret_half(keypair)?);
+    } else {
+        body.push_str(&text(MARGIN, FOLD_Y + 8.0, 3.6, "normal", "Public copy: this sheet holds no secret key."));
+    }
+
+    Ok(format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="{PAGE_WIDTH}mm" height="{PAGE_HEIGHT}mm" viewBox="0 0 {PAGE_WIDTH} {PAGE_HEIGHT}">
+<rect width="{PAGE_WIDTH}" height="{PAGE_HEIGHT}" fill="#fff"/>
+{body}
+</svg>
+"##
+    ))
+}
+
+/// The bottom half of the sheet: the base58 keypair and its QR code.
+fn secret_half(keypair: &Keypair) -> Result<String, CliError> {
+    let secret = keypair.to_base58_string();
+    let top = FOLD_Y + 12.0;
+
+    let mut half = String::new();
+    half.push_str(&format!(
+        r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#000" stroke-width="0.6"/>"##,
+        MARGIN - 5.0,
+        top - 6.0,
+        PAGE_WIDTH - 2.0 * (MARGIN - 5.0),
+        PAGE_HEIGHT - top - MARGIN + 6.0
+    ));
+    half.push_str(&text(MARGIN, top + 2.0, 5.0, "bold", "SECRET KEY - anyone holding this controls the address"));
+    half.push_str(&text(MARGIN, top + 10.0, 3.4, "normal", "Base58 keypair, as imported by most wallets:"));
+    // 88 characters do not fit on one line at a legible size
+    let (first, second) = secret.split_at(secret.len() / 2);
+    half.push_str(&monospace(MARGIN, top + 18.0, 4.2, first));
+    half.push_str(&monospace(MARGIN, top + 24.0, 4.2, second));
+    half.push_str(&qr::svg_element(secret.as_bytes(), MARGIN, top + 30.0, QR_SIZE)?);
+    Ok(half)
+}
+
+fn text(x: f64, y: f64, size: f64, weight: &str, content: &str) -> String {
+    format!(r#"<text x="{x}" y="{y}" font-family="sans-serif" font-size="{size}" font-weight="{weight}">{content}</text>"#)
+}
+
+fn monospace(x: f64, y: f64, size: f64, content: &str) -> String {
+    format!(r#"<text x="{x}" y="{y}" font-family="monospace" font-size="{size}">{content}</text>"#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sheets_hold_the_address_and_the_secret() {
+        let keypair = Keypair::new();
+        let address = keypair.pubkey().to_string();
+        let secret = keypair.to_base58_string();
+        let sheet = render(&keypair, true).unwrap();
+
+        assert!(sheet.starts_with("<?xml") && sheet.trim_end().ends_with("</svg>"));
+        assert!(sheet.contains(&format!(">{address}</text>")));
+        assert!(sheet.contains(&qr::svg_element(address.as_bytes(), MARGIN, 47.0, QR_SIZE).unwrap()));
+        let (first, second) = secret.split_at(secret.len() / 2);
+        assert!(sheet.contains(&format!(">{first}</text>")) && sheet.contains(&format!(">{second}</text>")));
+        assert!(sheet.contains(&qr::svg_element(secret.as_bytes(), MARGIN, FOLD_Y + 42.0, QR_SIZE).unwrap()));
+        assert!(sheet.contains("SECRET KEY"));
+        assert_eq!(sheet.matches("32-bit immediate segments").count(), 1);
+        assert_eq!(sheet.matches("imm32: ").count() + sheet.matches("64-bit constant").count(), 4);
+    }
+
+    #[test]
+    fn public_copies_leave_the_secret_out() {
+        let keypair = Keypair::new();
+        let secret = keypair.to_base58_string();
+        let sheet = render(&keypair, false).unwrap();
+
+        assert!(sheet.contains(&keypair.pubkey().to_string()));
+        assert!(!sheet.contains(&secret[..secret.len() / 2]) && !sheet.contains(&secret[secret.len() / 2..]));
+        assert!(!sheet.contains(&qr::svg_element(secret.as_bytes(), MARGIN, FOLD_Y + 42.0, QR_SIZE).unwrap()));
+        assert!(sheet.contains("this sheet holds no secret key"));
+    }
+}
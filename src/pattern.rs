@@ -0,0 +1,185 @@
+//! Vanity patterns matched against base58 addresses.
+
+use doppler_core::pattern::{address_matches, PrefixRanges, MAX_ADDRESS_LEN};
+use serde::{Deserialize, Serialize};
+
+/// The base58 alphabet used for Solana addresses.
+pub const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// A base58 constraint on the public key, mirroring `solana-keygen grind`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VanityPattern {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+}
+
+/// A vanity pattern together with how many keys to find for it.
+///
+/// Serializes flat, e.g. `{ prefix = "ab", count = 2 }` in TOML.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchPattern {
+    #[serde(flatten)]
+    pub pattern: VanityPattern,
+    pub count: usize,
+    /// Keys found so far
+    #[serde(default)]
+    pub found: usize,
+    /// Patterns with a higher priority get more of the workers and are
+    /// offered each candidate first
+    #[serde(default, skip_serializing_if = "is_default_priority")]
+    pub priority: u32,
+    /// Name of the destination keys for this pattern are delivered to, if not
+    /// the run's own output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sink: Option<String>,
+}
+
+fn is_default_priority(priority: &u32) -> bool {
+    *priority == 0
+}
+
+impl VanityPattern {
+    pub fn matches(&self, address: &str) -> bool {
+        self.matches_with_case(address, false)
+    }
+
+    /// Like `matches`, optionally ignoring ASCII case.
+    pub fn matches_with_case(&self, address: &str, ignore_case: bool) -> bool {
+        address_matches(address, self.prefix.as_deref(), self.suffix.as_deref(), ignore_case)
+    }
+
+    /// The raw key ranges the prefix covers, for rejecting keys without
+    /// encoding them. `None` if the prefix cannot be tested that way, e.g.
+    /// when ignoring case.
+    pub fn prefix_ranges(&self, ignore_case: bool) -> Option<PrefixRanges> {
+        self.prefix.as_deref().filter(|_| !ignore_case).and_then(PrefixRanges::new)
+    }
+
+    /// Checks that some 32-byte public key could match the pattern,
+    /// explaining what is wrong and how to fix it if not.
+    ///
+    /// With `ignore_case`, a character is only rejected if neither of its
+    /// cases is in the base58 alphabet, and the prefix is not checked against
+    /// the range of possible addresses.
+    pub fn validate(&self, ignore_case: bool) -> Result<(), String> {
+        if self.prefix.is_none() && self.suffix.is_none() {
+            return Err("A vanity pattern needs a prefix and/or a suffix".to_string());
+        }
+
+        for (kind, part) in [("prefix", &self.prefix), ("suffix", &self.suffix)] {
+            let Some(part) = part else { continue };
+            if part.is_empty() {
+                return Err(format!("The {kind} must not be empty"));
+            }
+            if let Some(c) = part.chars().find(|&c| !is_base58(c, ignore_case)) {
+                return Err(format!(
+                    "The {kind} '{part}' contains '{c}', which never appears in base58 addresses{}",
+                    base58_suggestion(c)
+                ));
+            }
+            if part.len() > MAX_ADDRESS_LEN {
+                return Err(format!(
+                    "The {kind} '{part}' is {} characters long, but addresses are at most {MAX_ADDRESS_LEN}",
+                    part.len()
+                ));
+            }
+        }
+
+        if let Some(prefix) = self.prefix.as_deref().filter(|_| !ignore_case) {
+            if !prefix_is_possible(prefix) {
+                let leading_ones = prefix.chars().take_while(|&c| c == '1').count();
+                let hint = if leading_ones > 0 {
+                    format!("; each leading '1' stands for a zero byte, so {leading_ones} of them leave too few bytes for the rest")
+                } else {
+                    "; it falls outside the range of 32-byte keys, so try a shorter prefix".to_string()
+                };
+                return Err(format!("No 32-byte public key has an address starting with '{prefix}'{hint}"));
+            }
+        }
+        if let Some(suffix) = self.suffix.as_deref().filter(|suffix| suffix.len() == MAX_ADDRESS_LEN) {
+            if !ignore_case && bs58::decode(suffix).into_vec().map_or(true, |bytes| bytes.len() != 32) {
+                return Err(format!("The suffix '{suffix}' is a whole address, but not one of a 32-byte key"));
+            }
+        }
+        Ok(())
+    }
+
+    /// A rough estimate of the chance that a random address matches.
+    ///
+    /// Each character matches one of the 58 base58 digits, or two with
+    /// `ignore_case` if both of its cases are in the alphabet, while each
+    /// leading '1' of the prefix needs a whole zero byte.
+    pub fn match_probability(&self, ignore_case: bool) -> f64 {
+        let char_probability = |c: char| {
+            let other_case = if c.is_ascii_lowercase() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() };
+            if ignore_case && other_case != c && BASE58_ALPHABET.contains(other_case) {
+                2.0 / 58.0
+            } else {
+                1.0 / 58.0
+            }
+        };
+
+        let prefix_probability = self.prefix.as_deref().map_or(1.0, |prefix| {
+            let leading_ones = prefix.chars().take_while(|&c| c == '1').count();
+            (1.0f64 / 256.0).powi(leading_ones as i32)
+                * prefix.chars().skip(leading_ones).map(char_probability).product::<f64>()
+        });
+        let suffix_probability =
+            self.suffix.as_deref().map_or(1.0, |suffix| suffix.chars().map(char_probability).product());
+        prefix_probability * suffix_probability
+    }
+
+    pub fn to_lowercase(&self) -> VanityPattern {
+        VanityPattern {
+            prefix: self.prefix.as_ref().map(|prefix| prefix.to_lowercase()),
+            suffix: self.suffix.as_ref().map(|suffix| suffix.to_lowercase()),
+        }
+    }
+}
+
+impl core::fmt::Display for VanityPattern {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (&self.prefix, &self.suffix) {
+            (Some(prefix), Some(suffix)) => write!(f, "starts with '{prefix}' and ends with '{suffix}'"),
+            (Some(prefix), None) => write!(f, "starts with '{prefix}'"),
+            (None, Some(suffix)) => write!(f, "ends with '{suffix}'"),
+            (None, None) => write!(f, "any address"),
+        }
+    }
+}
+
+fn is_base58(c: char, ignore_case: bool) -> bool {
+    BASE58_ALPHABET.contains(c)
+        || ignore_case && (BASE58_ALPHABET.contains(c.to_ascii_lowercase()) || BASE58_ALPHABET.contains(c.to_ascii_uppercase()))
+}
+
+/// Suggests base58 characters that look like one that is not in the alphabet.
+fn base58_suggestion(c: char) -> &'static str {
+    match c {
+        '0' | 'O' => " (try 'o')",
+        'I' => " (try 'i' or '1')",
+        'l' => " (try 'L' or '1')",
+        _ => "",
+    }
+}
+
+/// Returns true if some 32-byte key has an address starting with `prefix`.
+///
+/// Base58 is a bijection between byte strings and strings in which leading
+/// '1's stand for leading zero bytes, so a prefix is possible exactly when,
+/// for some address length, padding it with the lowest and highest digits
+/// decodes to at most and at least 32 bytes respectively.
+fn prefix_is_possible(prefix: &str) -> bool {
+    if prefix.chars().all(|c| c == '1') {
+        return prefix.len() <= 32;
+    }
+
+    let decoded_len = |address: String| bs58::decode(address).into_vec().map_or(0, |bytes| bytes.len());
+    (prefix.len()..=MAX_ADDRESS_LEN).any(|len| {
+        let padding = len - prefix.len();
+        decoded_len(format!("{prefix}{}", "1".repeat(padding))) <= 32
+            && decoded_len(format!("{prefix}{}", "z".repeat(padding))) >= 32
+    })
+}
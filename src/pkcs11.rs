@@ -0,0 +1,186 @@
+//! PKCS#11 token sink for found keys.
+//!
+//! With `--pkcs11-module LIB` each found keypair is imported into a PKCS#11
+//! token (a YubiHSM, a SoftHSM token, a cloud HSM) instead of local files,
+//! for teams whose policy forbids keys on disk. The secret becomes a
+//! sensitive, non-extractable Ed25519 private key (`CKK_EC_EDWARDS`, from
+//! PKCS#11 3.0) labelled with the address, next to its public key. The
+//! token signs a test message before the import counts, so a key is never
+//! reported stored in a form the token cannot use.
+//!
+//! The token is picked with `--pkcs11-token LABEL`, or is the only one the
+//! module offers. The user PIN is read from `DOPPLER_PKCS11_PIN`, or at a
+//! hidden prompt.
+
+use cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+use cryptoki::mechanism::eddsa::{EddsaParams, EddsaSignatureScheme};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, KeyType, ObjectClass};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use ed25519_dalek::{Signature, VerifyingKey};
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use zeroize::Zeroizing;
+
+use crate::cli_error::CliError;
+use crate::config::env_var;
+
+/// Environment variable holding the token's user PIN.
+pub const PIN_ENV: &str = "DOPPLER_PKCS11_PIN";
+
+/// `CKA_EC_PARAMS` of an Ed25519 key: the DER encoding of its OID,
+/// 1.3.101.112.
+const ED25519_PARAMS: [u8; 5] = [0x06, 0x03, 0x2b, 0x65, 0x70];
+
+/// Signed by each imported key to check the token can use it.
+const TEST_MESSAGE: &[u8] = b"doppler-keygen pkcs11 import check";
+
+/// A logged-in session on the token found keypairs are imported into.
+#[derive(Debug)]
+pub struct Pkcs11Sink {
+    /// Sessions can move between threads but not be shared, so imports
+    /// take turns
+    session: Mutex<Session>,
+    token: String,
+}
+
+impl Pkcs11Sink {
+    /// Loads the PKCS#11 module at `module`, opens a session on the token
+    /// labelled `token` (or the only token there is) and logs in as the
+    /// user.
+    pub fn connect(module: &Path, token: Option<&str>) -> Result<Pkcs11Sink, CliError> {
+        let pkcs11 = Pkcs11::new(module).map_err(|e| error(format!("loading {}", module.display()), e))?;
+        pkcs11
+            .initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK))
+            .map_err(|e| error("initializing the module".to_string(), e))?;
+
+        let mut tokens = Vec::new();
+        for slot in pkcs11.get_slots_with_token().map_err(|e| error("listing slots".to_string(), e))? {
+            let info = pkcs11.get_token_info(slot).map_err(|e| error("reading token info".to_string(), e))?;
+            tokens.push((slot, info.label().trim().to_string()));
+        }
+        let labels = || tokens.iter().map(|(_, label)| label.as_str()).collect::<Vec<_>>().join(", ");
+        let (slot, label) = match token {
+            Some(token) => tokens.iter().find(|(_, label)| label == token).ok_or_else(|| {
+                CliError::Pkcs11(format!("no token labelled '{token}' (tokens: {})", labels()))
+            })?,
+            None => match &tokens[..] {
+                [only] => only,
+                [] => return Err(CliError::Pkcs11(format!("{} offers no tokens", module.display()))),
+                _ => return Err(CliError::Pkcs11(format!("pick a token with --pkcs11-token: {}", labels()))),
+            },
+        };
+
+        let session = pkcs11.open_rw_session(*slot).map_err(|e| error(format!("opening a session on '{label}'"), e))?;
+        let pin = match env_var(PIN_ENV) {
+            Some(pin) => pin,
+            None => rpassword::prompt_password(format!("User PIN for token '{label}': "))
+                .map_err(|e| CliError::Pkcs11(format!("failed to read the PIN: {e}")))?,
+        };
+        session
+            .login(UserType::User, Some(&AuthPin::from(pin)))
+            .map_err(|e| error(format!("logging in to '{label}'"), e))?;
+        Ok(Pkcs11Sink { session: Mutex::new(session), token: label.clone() })
+    }
+
+    /// Imports `keypair`, refusing to add a second copy of a key the token
+    /// already holds, and returns the token's label.
+    pub fn store(&self, keypair: &Keypair) -> Result<&str, CliError> {
+        let pubkey = keypair.pubkey();
+        let label = pubkey.to_string();
+        let id = pubkey.to_bytes().to_vec();
+        let session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let existing = session
+            .find_objects(&[Attribute::Class(ObjectClass::PRIVATE_KEY), Attribute::Id(id.clone())])
+            .map_err(|e| error(format!("looking up {label}"), e))?;
+        if !existing.is_empty() {
+            return Err(CliError::Pkcs11(format!("token '{}' already holds {label}", self.token)));
+        }
+
+        let seed = Zeroizing::new(keypair.secret_bytes().to_vec());
+        let private_key = session
+            .create_object(&[
+                Attribute::Class(ObjectClass::PRIVATE_KEY),
+                Attribute::KeyType(KeyType::EC_EDWARDS),
+                Attribute::Token(true),
+                Attribute::Private(true),
+                Attribute::Sensitive(true),
+                Attribute::Extractable(false),
+                Attribute::Sign(true),
+                Attribute::Label(label.clone().into_bytes()),
+                Attribute::Id(id.clone()),
+                Attribute::EcParams(ED25519_PARAMS.to_vec()),
+                Attribute::Value(seed.to_vec()),
+            ])
+            .map_err(|e| error(format!("importing the secret key of {label}"), e))?;
+        let public_key = session.create_object(&[
+            Attribute::Class(ObjectClass::PUBLIC_KEY),
+            Attribute::KeyType(KeyType::EC_EDWARDS),
+            Attribute::Token(true),
+            Attribute::Private(false),
+            Attribute::Verify(true),
+            Attribute::Label(label.clone().into_bytes()),
+            Attribute::Id(id),
+            Attribute::EcParams(ED25519_PARAMS.to_vec()),
+            Attribute::EcPoint(ec_point(&pubkey.to_bytes())),
+        ]);
+
+        let signature = session.sign(&Mechanism::Eddsa(EddsaParams::new(EddsaSignatureScheme::Pure)), private_key, TEST_MESSAGE);
+        let checked = match (public_key, signature) {
+            (Ok(_), Ok(signature)) => verify(&pubkey.to_bytes(), &signature),
+            (Err(e), _) => Err(error(format!("importing the public key of {label}"), e)),
+            (_, Err(e)) => Err(error(format!("signing with the imported {label}"), e)),
+        };
+        if let Err(e) = checked {
+            // Leave nothing half-imported behind; the keypair is still in
+            // memory and the caller reports the failure
+            for object in session.find_objects(&[Attribute::Label(label.into_bytes())]).unwrap_or_default() {
+                let _ = session.destroy_object(object);
+            }
+            return Err(e);
+        }
+        Ok(&self.token)
+    }
+}
+
+/// `CKA_EC_POINT` of an Ed25519 public key: the key as a DER octet string.
+fn ec_point(pubkey: &[u8; 32]) -> Vec<u8> {
+    let mut point = vec![0x04, 32];
+    point.extend_from_slice(pubkey);
+    point
+}
+
+/// Checks a signature the token made over `TEST_MESSAGE`.
+fn verify(pubkey: &[u8; 32], signature: &[u8]) -> Result<(), CliError> {
+    let signature = Signature::from_slice(signature)
+        .map_err(|_| CliError::Pkcs11(format!("the token returned a {}-byte signature", signature.len())))?;
+    VerifyingKey::from_bytes(pubkey)
+        .and_then(|key| key.verify_strict(TEST_MESSAGE, &signature))
+        .map_err(|_| CliError::Pkcs11("the token's signature does not verify; the key was imported incorrectly".to_string()))
+}
+
+fn error(action: String, error: cryptoki::error::Error) -> CliError {
+    CliError::Pkcs11(format!("{action} failed: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_ed25519_attributes() {
+        let keypair = Keypair::new();
+        let point = ec_point(&keypair.pubkey().to_bytes());
+        assert_eq!(point[..2], [0x04, 0x20]);
+        assert_eq!(point[2..], keypair.pubkey().to_bytes());
+
+        let signature = keypair.sign_message(TEST_MESSAGE);
+        assert!(verify(&keypair.pubkey().to_bytes(), signature.as_ref()).is_ok());
+        assert!(verify(&Keypair::new().pubkey().to_bytes(), signature.as_ref()).is_err());
+        assert!(verify(&keypair.pubkey().to_bytes(), &signature.as_ref()[..63]).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! `grind --plugin`: compiled matcher and sink plugins.
+//!
+//! A plugin is a WebAssembly module run by an interpreter, with no access to
+//! the host beyond the functions below and a fuel limit on every call. Only
+//! public data crosses into it: the matched address, never a secret key.
+//!
+//! ABI version 1. The module exports:
+//!
+//! - `memory`
+//! - `doppler_abi_version() -> i32`, returning 1
+//! - `doppler_buffer() -> i32`, the offset of at least `BUFFER_LEN` bytes
+//!   the host writes its input to: the 32 raw address bytes followed by the
+//!   base58 address
+//! - `doppler_match(address_len: i32) -> i32`, optional: called for each
+//!   candidate that passed the other criteria, returning 0 to reject, 1 to
+//!   accept, more than 1 to accept with that score, or a negative error
+//! - `doppler_sink(address_len: i32, segment: i32) -> i32`, optional:
+//!   called for each saved key, returning a negative value on error
+//!
+//! and may import `doppler.emit(ptr: i32, len: i32)` to write a line of
+//! UTF-8 text, which the host appends to `PLUGIN_OUTPUT_FILE` in the output
+//! directory. A plugin must export at least one of `doppler_match` and
+//! `doppler_sink`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::fmt;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use wasmi::{Caller, Config, Engine, Instance, Linker, Memory, Module, Store};
+
+use crate::predicate::Verdict;
+
+/// The plugin ABI this build implements.
+const ABI_VERSION: i32 = 1;
+
+/// Bytes the plugin's buffer must hold: a raw address and its base58 form.
+pub const BUFFER_LEN: usize = 32 + 44;
+
+/// Fuel each call may burn, roughly one unit per instruction, so a runaway
+/// loop fails instead of hanging a worker.
+const FUEL_PER_CALL: u64 = 10_000_000;
+
+/// Where lines emitted by a plugin are appended, in the output directory.
+pub const PLUGIN_OUTPUT_FILE: &str = "plugin-output.txt";
+
+/// Lines a plugin emitted during the current call.
+#[derive(Default)]
+struct HostState {
+    emitted: Vec<String>,
+}
+
+/// One instantiation of the plugin; each call takes one from the pool so
+/// workers never share plugin memory.
+struct PluginInstance {
+    store: Store<HostState>,
+    instance: Instance,
+    memory: Memory,
+    buffer: usize,
+}
+
+impl PluginInstance {
+    /// Writes the address to the buffer and calls `func` with the base58
+    /// length followed by `args`, returning its result and any lines it
+    /// emitted.
+    fn call(&mut self, func: &str, address: &[u8; 32], encoded: &str, args: &[i32]) -> Result<(i32, Vec<String>), String> {
+        self.store.set_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+        self.memory.write(&mut self.store, self.buffer, address).map_err(|e| e.to_string())?;
+        self.memory.write(&mut self.store, self.buffer + 32, encoded.as_bytes()).map_err(|e| e.to_string())?;
+        let result = match args {
+            [] => self
+                .instance
+                .get_typed_func::<i32, i32>(&self.store, func)
+                .and_then(|f| f.call(&mut self.store, encoded.len() as i32)),
+            [arg] => self
+                .instance
+                .get_typed_func::<(i32, i32), i32>(&self.store, func)
+                .and_then(|f| f.call(&mut self.store, (encoded.len() as i32, *arg))),
+            _ => unreachable!("plugin functions take at most two arguments"),
+        }
+        .map_err(|e| e.to_string())?;
+        Ok((result, std::mem::take(&mut self.store.data_mut().emitted)))
+    }
+}
+
+/// A loaded plugin.
+pub struct Plugin {
+    path: PathBuf,
+    module: Module,
+    linker: Linker<HostState>,
+    pool: Mutex<Vec<PluginInstance>>,
+    has_match: bool,
+    has_sink: bool,
+    /// Set once a plugin error has been reported
+    failed: AtomicBool,
+}
+
+impl Plugin {
+    /// Loads and validates the plugin at `path`.
+    pub fn load(path: &Path) -> Result<Plugin, String> {
+        let error = |reason: String| format!("Invalid plugin {}: {reason}", path.display());
+        let wasm = fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &wasm[..]).map_err(|e| error(e.to_string()))?;
+        let exports: Vec<&str> = module.exports().map(|export| export.name()).collect();
+        let has_match = exports.contains(&"doppler_match");
+        let has_sink = exports.contains(&"doppler_sink");
+        if !has_match && !has_sink {
+            return Err(error("it exports neither doppler_match nor doppler_sink".to_string()));
+        }
+
+        let mut linker = Linker::new(&engine);
+        linker
+            .func_wrap("doppler", "emit", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<(), wasmi::Error> {
+                let memory = caller
+                    .get_export("memory")
+                    .and_then(|export| export.into_memory())
+                    .ok_or_else(|| wasmi::Error::new("plugin has no memory export"))?;
+                let mut bytes = vec![0u8; len.max(0) as usize];
+                memory.read(&caller, ptr as usize, &mut bytes).map_err(|e| wasmi::Error::new(e.to_string()))?;
+                let line = String::from_utf8(bytes).map_err(|_| wasmi::Error::new("emitted text is not UTF-8"))?;
+                caller.data_mut().emitted.push(line);
+                Ok(())
+            })
+            .map_err(|e| error(e.to_string()))?;
+
+        let plugin = Plugin {
+            path: path.to_path_buf(),
+            module,
+            linker,
+            pool: Mutex::new(Vec::new()),
+            has_match,
+            has_sink,
+            failed: AtomicBool::new(false),
+        };
+        // Instantiate once up front so a bad plugin fails before the search
+        let instance = plugin.instantiate().map_err(error)?;
+        plugin.pool.lock().unwrap_or_else(PoisonError::into_inner).push(instance);
+        Ok(plugin)
+    }
+
+    fn instantiate(&self) -> Result<PluginInstance, String> {
+        let mut store = Store::new(self.module.engine(), HostState::default());
+        store.set_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+        let instance = self
+            .linker
+            .instantiate(&mut store, &self.module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|e| e.to_string())?;
+        let memory = instance.get_memory(&store, "memory").ok_or("it exports no memory")?;
+
+        let version = instance
+            .get_typed_func::<(), i32>(&store, "doppler_abi_version")
+            .and_then(|func| func.call(&mut store, ()))
+            .map_err(|e| format!("doppler_abi_version: {e}"))?;
+        if version != ABI_VERSION {
+            return Err(format!("it implements ABI version {version}, but this build supports version {ABI_VERSION}"));
+        }
+        let buffer = instance
+            .get_typed_func::<(), i32>(&store, "doppler_buffer")
+            .and_then(|func| func.call(&mut store, ()))
+            .map_err(|e| format!("doppler_buffer: {e}"))?;
+        let buffer = usize::try_from(buffer).map_err(|_| format!("doppler_buffer returned {buffer}"))?;
+        if buffer + BUFFER_LEN > memory.data(&store).len() {
+            return Err(format!("the buffer at {buffer} does not fit {BUFFER_LEN} bytes in memory"));
+        }
+
+        Ok(PluginInstance { store, instance, memory, buffer })
+    }
+
+    /// Calls `func` on a pooled instance, starting a new one if every
+    /// instance is busy.
+    fn call(&self, func: &str, address: &[u8; 32], encoded: &str, args: &[i32]) -> Result<(i32, Vec<String>), String> {
+        let pooled = self.pool.lock().unwrap_or_else(PoisonError::into_inner).pop();
+        let mut plugin = match pooled {
+            Some(plugin) => plugin,
+            None => self.instantiate()?,
+        };
+
+        let result = plugin.call(func, address, encoded, args);
+
+        // A trapped instance may be left in any state, so only healthy
+        // ones go back to the pool
+        match result {
+            Ok(result) => {
+                self.pool.lock().unwrap_or_else(PoisonError::into_inner).push(plugin);
+                Ok(result)
+            }
+            Err(e) => Err(format!("{}: {func}: {e}", self.path.display())),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn has_match(&self) -> bool {
+        self.has_match
+    }
+
+    /// Runs `doppler_match` on an address.
+    pub fn evaluate(&self, address: &[u8; 32], encoded: &str) -> Result<Verdict, String> {
+        let (result, _) = self.call("doppler_match", address, encoded, &[])?;
+        match result {
+            0 => Ok(Verdict::Rejected),
+            1 => Ok(Verdict::Accepted),
+            score if score > 1 => Ok(Verdict::Scored(f64::from(score))),
+            error => Err(format!("{}: doppler_match returned error {error}", self.path.display())),
+        }
+    }
+
+    /// Runs `doppler_sink` for a saved key, appending anything it emits to
+    /// `PLUGIN_OUTPUT_FILE` in `output_dir`. Does nothing if the plugin has
+    /// no sink.
+    pub fn sink(&self, address: &[u8; 32], encoded: &str, segment: usize, output_dir: &Path) -> Result<(), String> {
+        if !self.has_sink {
+            return Ok(());
+        }
+        let (result, emitted) = self.call("doppler_sink", address, encoded, &[segment as i32])?;
+        if result < 0 {
+            return Err(format!("{}: doppler_sink returned error {result}", self.path.display()));
+        }
+        if emitted.is_empty() {
+            return Ok(());
+        }
+
+        let path = output_dir.join(PLUGIN_OUTPUT_FILE);
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+        for line in emitted {
+            writeln!(file, "{line}").map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Returns true the first time it is called, so a plugin that fails on
+    /// every candidate is only reported once.
+    pub fn first_failure(&self) -> bool {
+        !self.failed.swap(true, Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plugin")
+            .field("path", &self.path)
+            .field("has_match", &self.has_match)
+            .field("has_sink", &self.has_sink)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A plugin whose answer is picked by the first address byte: 0 rejects,
+    /// 1 accepts, 2 loops forever, 4 loops 500,000 times before scoring,
+    /// other bytes below 128 score themselves and the rest are errors. Its
+    /// sink emits the base58 address and the segment's digit.
+    const PLUGIN: &str = r#"
+        (module
+          (import "doppler" "emit" (func $emit (param i32 i32)))
+          (memory (export "memory") 1)
+          (func (export "doppler_abi_version") (result i32) (i32.const 1))
+          (func (export "doppler_buffer") (result i32) (i32.const 0))
+          (func (export "doppler_match") (param $len i32) (result i32)
+            (local $byte i32)
+            (local $n i32)
+            (local.set $byte (i32.load8_u (i32.const 0)))
+            (if (i32.eq (local.get $byte) (i32.const 2))
+              (then (loop $forever (br $forever))))
+            (if (i32.eq (local.get $byte) (i32.const 4))
+              (then (loop $count
+                (local.set $n (i32.add (local.get $n) (i32.const 1)))
+                (br_if $count (i32.lt_u (local.get $n) (i32.const 500000))))))
+            (if (i32.ge_u (local.get $byte) (i32.const 128))
+              (then (return (i32.const -3))))
+            (local.get $byte))
+          (func (export "doppler_sink") (param $len i32) (param $segment i32) (result i32)
+            (call $emit (i32.const 32) (local.get $len))
+            (i32.store8 (i32.const 100) (i32.add (i32.const 48) (local.get $segment)))
+            (call $emit (i32.const 100) (i32.const 1))
+            (i32.const 0)))
+    "#;
+
+    /// Loads the plugin compiled from `source`.
+    fn load(source: &str) -> Result<Plugin, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plugin.wasm");
+        fs::write(&path, wat::parse_str(source).unwrap()).unwrap();
+        Plugin::load(&path)
+    }
+
+    /// An address starting with `byte`.
+    fn address(byte: u8) -> [u8; 32] {
+        let mut address = [9; 32];
+        address[0] = byte;
+        address
+    }
+
+    #[test]
+    fn verdicts_follow_the_return_value() {
+        let plugin = load(PLUGIN).unwrap();
+        assert!(plugin.has_match());
+        assert_eq!(plugin.evaluate(&address(0), "abc").unwrap(), Verdict::Rejected);
+        assert_eq!(plugin.evaluate(&address(1), "abc").unwrap(), Verdict::Accepted);
+        assert_eq!(plugin.evaluate(&address(7), "abc").unwrap(), Verdict::Scored(7.0));
+        let error = plugin.evaluate(&address(200), "abc").unwrap_err();
+        assert!(error.ends_with("doppler_match returned error -3"), "{error}");
+    }
+
+    #[test]
+    fn calls_stop_when_their_fuel_runs_out() {
+        let plugin = load(PLUGIN).unwrap();
+        // Well within FUEL_PER_CALL, and again on the same instance
+        assert_eq!(plugin.evaluate(&address(4), "abc").unwrap(), Verdict::Scored(4.0));
+        assert_eq!(plugin.evaluate(&address(4), "abc").unwrap(), Verdict::Scored(4.0));
+
+        let error = plugin.evaluate(&address(2), "abc").unwrap_err();
+        assert!(error.contains("doppler_match") && error.contains("fuel"), "{error}");
+        // The trapped instance is dropped and a fresh one takes its place
+        assert_eq!(plugin.evaluate(&address(1), "abc").unwrap(), Verdict::Accepted);
+    }
+
+    #[test]
+    fn sinks_append_what_they_emit() {
+        let dir = tempfile::tempdir().unwrap();
+        let plugin = load(PLUGIN).unwrap();
+        plugin.sink(&address(1), "FirstAddress", 2, dir.path()).unwrap();
+        plugin.sink(&address(1), "SecondAddress", 0, dir.path()).unwrap();
+        let output = fs::read_to_string(dir.path().join(PLUGIN_OUTPUT_FILE)).unwrap();
+        assert_eq!(output, "FirstAddress\n2\nSecondAddress\n0\n");
+    }
+
+    #[test]
+    fn plugins_breaking_the_abi_are_refused() {
+        let refused = |source: &str| load(source).err().unwrap();
+        let header = r#"(memory (export "memory") 1)
+            (func (export "doppler_buffer") (result i32) (i32.const 0))"#;
+
+        let error = refused(&format!(r#"(module {header} (func (export "doppler_abi_version") (result i32) (i32.const 1)))"#));
+        assert!(error.ends_with("it exports neither doppler_match nor doppler_sink"), "{error}");
+        let error = refused(&format!(
+            r#"(module {header} (func (export "doppler_abi_version") (result i32) (i32.const 2))
+                (func (export "doppler_match") (param i32) (result i32) (i32.const 1)))"#
+        ));
+        assert!(error.contains("it implements ABI version 2"), "{error}");
+        let error = refused(
+            r#"(module (memory (export "memory") 1)
+                (func (export "doppler_match") (param i32) (result i32) (i32.const 1)))"#,
+        );
+        assert!(error.contains("doppler_abi_version"), "{error}");
+        let error = refused(
+            r#"(module (memory (export "memory") 1)
+                (func (export "doppler_abi_version") (result i32) (i32.const 1))
+                (func (export "doppler_buffer") (result i32) (i32.const 65500))
+                (func (export "doppler_match") (param i32) (result i32) (i32.const 1)))"#,
+        );
+        assert!(error.contains("does not fit"), "{error}");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plugin.wasm");
+        fs::write(&path, b"not wasm").unwrap();
+        assert!(Plugin::load(&path).err().unwrap().starts_with("Invalid plugin"));
+        assert!(Plugin::load(&dir.path().join("missing.wasm")).err().unwrap().starts_with("Failed to read"));
+    }
+
+    #[test]
+    fn a_match_export_of_the_wrong_type_fails_when_called() {
+        let plugin = load(
+            r#"(module (memory (export "memory") 1)
+                (func (export "doppler_abi_version") (result i32) (i32.const 1))
+                (func (export "doppler_buffer") (result i32) (i32.const 0))
+                (func (export "doppler_match") (result i32) (i32.const 1)))"#,
+        )
+        .unwrap();
+        let error = plugin.evaluate(&address(1), "abc").unwrap_err();
+        assert!(error.contains("doppler_match"), "{error}");
+    }
+}
@@ -0,0 +1,293 @@
+//! `pool`: grinding for many requesters on many workers, with the attempts
+//! each one gave or took accounted for.
+//!
+//! Requesters submit jobs to the pool. The coordinator hands each worker a
+//! work unit for the oldest unfinished job, and credits the unit's attempts
+//! to the worker and to the job once its proof checks out. The report then
+//! shows what each worker contributed and what each job, and so each
+//! requester, took.
+//!
+//! The pool lives in `pool.json`, which holds the seeds of the units out
+//! with workers: whoever reads it can rebuild the keys they find, so it is
+//! written owner-only.
+
+use serde::{Deserialize, Serialize};
+use solana_keypair::Keypair;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::secretfile;
+use crate::workunit::{Proof, Verification, WorkUnit};
+use doppler_keygen::pattern::VanityPattern;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitState {
+    /// Out with its worker
+    Issued,
+    /// Proof accepted and attempts credited
+    Credited,
+    /// Proof rejected; nothing credited
+    Rejected,
+}
+
+impl UnitState {
+    pub fn name(self) -> &'static str {
+        match self {
+            UnitState::Issued => "issued",
+            UnitState::Credited => "credited",
+            UnitState::Rejected => "rejected",
+        }
+    }
+}
+
+/// A requester's job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolJob {
+    pub id: u64,
+    pub requester: String,
+    pub patterns: Vec<VanityPattern>,
+    pub ignore_case: bool,
+    /// Keys wanted
+    pub count: usize,
+    /// Addresses of the keys found so far
+    pub keys: Vec<String>,
+    /// Attempts credited to the job
+    pub attempts: u128,
+}
+
+impl PoolJob {
+    pub fn is_done(&self) -> bool {
+        self.keys.len() >= self.count
+    }
+}
+
+/// A unit handed to a worker for a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolUnit {
+    pub job: u64,
+    pub worker: String,
+    pub state: UnitState,
+    pub unit: WorkUnit,
+}
+
+/// What the pool has taken from a worker.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct WorkerAccount {
+    pub issued: u64,
+    pub credited: u64,
+    pub rejected: u64,
+    /// Attempts of the credited units
+    pub attempts: u128,
+}
+
+/// A proof that was credited: the job it went to and the keys it found.
+#[derive(Debug)]
+pub struct Credit {
+    pub job: u64,
+    pub attempts: u64,
+    pub keys: Vec<Keypair>,
+    pub verification: Verification,
+}
+
+/// The pool's jobs, units and workers.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Pool {
+    next_id: u64,
+    pub jobs: Vec<PoolJob>,
+    pub units: Vec<PoolUnit>,
+    pub workers: BTreeMap<String, WorkerAccount>,
+}
+
+impl Pool {
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join("pool.json")
+    }
+
+    /// The pool in `dir`, or an empty one if there is none yet.
+    pub fn load(dir: &Path) -> Result<Pool, String> {
+        let path = Pool::path(dir);
+        match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).map_err(|e| format!("{}: not a pool: {e}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Pool { next_id: 1, ..Pool::default() }),
+            Err(e) => Err(format!("Failed to read {}: {e}", path.display())),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+        let path = Pool::path(dir);
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        secretfile::replace(&path, &json).map_err(|e| format!("Failed to write {}: {e}", path.display()))
+    }
+
+    /// Adds a job, returning its id.
+    pub fn submit(&mut self, requester: &str, patterns: Vec<VanityPattern>, ignore_case: bool, count: usize) -> Result<u64, String> {
+        if requester.is_empty() {
+            return Err("A job needs a requester".to_string());
+        }
+        if patterns.is_empty() {
+            return Err("A job needs at least one pattern".to_string());
+        }
+        if count == 0 {
+            return Err("A job needs to want at least one key".to_string());
+        }
+        for pattern in &patterns {
+            pattern.validate(ignore_case)?;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.push(PoolJob { id, requester: requester.to_string(), patterns, ignore_case, count, keys: Vec::new(), attempts: 0 });
+        Ok(id)
+    }
+
+    /// Issues `worker` a unit of `count` candidates for job `job`, or for
+    /// the oldest unfinished job if none is given.
+    pub fn issue(&mut self, worker: &str, job: Option<u64>, count: u64, chunk_len: u64) -> Result<&PoolUnit, String> {
+        if worker.is_empty() {
+            return Err("A unit needs a worker".to_string());
+        }
+        let job = match job {
+            Some(id) => self.jobs.iter().find(|job| job.id == id).ok_or_else(|| format!("No job {id}"))?,
+            None => self.jobs.iter().find(|job| !job.is_done()).ok_or_else(|| "Every job has its keys".to_string())?,
+        };
+        if job.is_done() {
+            return Err(format!("Job {} has its keys", job.id));
+        }
+        // Every unit has a seed of its own, so each can start at 0
+        let unit = WorkUnit::issue(0, count, chunk_len, job.patterns.clone(), job.ignore_case)?;
+        let id = job.id;
+        self.workers.entry(worker.to_string()).or_default().issued += 1;
+        self.units.push(PoolUnit { job: id, worker: worker.to_string(), state: UnitState::Issued, unit });
+        Ok(self.units.last().expect("just pushed"))
+    }
+
+    /// Checks `proof` against its unit with `samples` chunks. An accepted
+    /// proof credits the unit's attempts to its worker and its job, and
+    /// gives the job the keys it found; a rejected one is held against the
+    /// worker. A unit is settled once, either way.
+    pub fn credit(&mut self, proof: &Proof, samples: usize) -> Result<Credit, String> {
+        let unit = self
+            .units
+            .iter_mut()
+            .find(|unit| unit.unit.digest() == proof.unit)
+            .ok_or_else(|| "The proof is for a unit this pool did not issue".to_string())?;
+        if unit.state != UnitState::Issued {
+            return Err(format!("{}'s unit for job {} is already {}", unit.worker, unit.job, unit.state.name()));
+        }
+        let account = self.workers.entry(unit.worker.clone()).or_default();
+        let verification = match unit.unit.verify(proof, samples) {
+            Ok(verification) => verification,
+            Err(e) => {
+                unit.state = UnitState::Rejected;
+                account.rejected += 1;
+                return Err(format!("Rejected {}'s unit for job {}: {e}", unit.worker, unit.job));
+            }
+        };
+        let keys = proof
+            .matches
+            .iter()
+            .map(|unit_match| unit.unit.keypair(unit_match.index))
+            .collect::<Result<Vec<Keypair>, String>>()?;
+        unit.state = UnitState::Credited;
+        account.credited += 1;
+        account.attempts += u128::from(unit.unit.count);
+
+        let job = self.jobs.iter_mut().find(|job| job.id == unit.job).ok_or_else(|| format!("No job {}", unit.job))?;
+        job.attempts += u128::from(unit.unit.count);
+        job.keys.extend(proof.matches.iter().map(|unit_match| unit_match.address.clone()));
+        Ok(Credit { job: job.id, attempts: unit.unit.count, keys, verification })
+    }
+
+    /// Attempts credited to each requester's jobs.
+    pub fn requesters(&self) -> BTreeMap<&str, u128> {
+        let mut requesters = BTreeMap::new();
+        for job in &self.jobs {
+            *requesters.entry(job.requester.as_str()).or_insert(0) += job.attempts;
+        }
+        requesters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(prefix: &str) -> Vec<VanityPattern> {
+        vec![VanityPattern { prefix: Some(prefix.to_string()), suffix: None }]
+    }
+
+    fn issue(pool: &mut Pool, worker: &str, job: Option<u64>) -> WorkUnit {
+        pool.issue(worker, job, 128, 64).unwrap().unit.clone()
+    }
+
+    #[test]
+    fn credits_go_to_the_worker_and_the_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pool = Pool::load(dir.path()).unwrap();
+        let first = pool.submit("alice", prefix("ab"), false, 1).unwrap();
+        let second = pool.submit("bob", prefix("cd"), false, 1).unwrap();
+
+        // Units go to the oldest unfinished job unless one is named
+        let unit = issue(&mut pool, "w1", None);
+        assert_eq!(unit.patterns, prefix("ab"));
+        let credit = pool.credit(&unit.run(|_| {}).unwrap(), 2).unwrap();
+        assert_eq!((credit.job, credit.attempts), (first, 128));
+        let unit = issue(&mut pool, "w2", Some(second));
+        pool.credit(&unit.run(|_| {}).unwrap(), 2).unwrap();
+        let unit = issue(&mut pool, "w2", Some(second));
+        pool.credit(&unit.run(|_| {}).unwrap(), 2).unwrap();
+
+        assert_eq!(pool.workers["w1"].attempts, 128);
+        assert_eq!((pool.workers["w2"].credited, pool.workers["w2"].attempts), (2, 256));
+        assert_eq!(pool.jobs[1].attempts, 256);
+        assert_eq!(pool.requesters(), BTreeMap::from([("alice", 128), ("bob", 256)]));
+    }
+
+    #[test]
+    fn a_unit_is_settled_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pool = Pool::load(dir.path()).unwrap();
+        pool.submit("alice", prefix("ab"), false, 1).unwrap();
+
+        let unit = issue(&mut pool, "honest", None);
+        let proof = unit.run(|_| {}).unwrap();
+        pool.credit(&proof, 2).unwrap();
+        assert!(pool.credit(&proof, 2).unwrap_err().contains("already credited"));
+
+        let unit = issue(&mut pool, "lazy", None);
+        let mut proof = unit.run(|_| {}).unwrap();
+        proof.chunks[0] = hex::encode([0u8; 32]);
+        assert!(pool.credit(&proof, 2).unwrap_err().contains("Rejected lazy's unit"));
+        // Redoing the work does not get a rejected unit credited after all
+        let proof = unit.run(|_| {}).unwrap();
+        assert!(pool.credit(&proof, 2).unwrap_err().contains("already rejected"));
+
+        assert_eq!((pool.workers["lazy"].rejected, pool.workers["lazy"].attempts), (1, 0));
+        assert_eq!(pool.jobs[0].attempts, 128);
+
+        let other = WorkUnit::issue(0, 128, 64, prefix("ab"), false).unwrap();
+        assert!(pool.credit(&other.run(|_| {}).unwrap(), 2).unwrap_err().contains("did not issue"));
+    }
+
+    #[test]
+    fn the_pool_survives_a_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pool = Pool::load(dir.path()).unwrap();
+        let id = pool.submit("alice", prefix("ab"), true, 2).unwrap();
+        let unit = issue(&mut pool, "w1", None);
+        pool.save(dir.path()).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(Pool::path(dir.path())).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let mut pool = Pool::load(dir.path()).unwrap();
+        assert_eq!(pool.submit("bob", prefix("cd"), false, 1).unwrap(), id + 1);
+        pool.credit(&unit.run(|_| {}).unwrap(), 2).unwrap();
+        assert_eq!(pool.workers["w1"].attempts, 128);
+    }
+}
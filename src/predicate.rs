@@ -0,0 +1,155 @@
+//! `grind --predicate`: custom match rules written in Rhai.
+//!
+//! The script defines `matches(pubkey, address)`, taking the matched
+//! address as an array of 32 byte values and as its base58 string. Returning `true`
+//! or a number above zero accepts the key; a number is reported as its
+//! score. The script is compiled once and shared by every worker, and only
+//! runs for candidates that already passed the imm32 check and any other
+//! criteria, so even a slow script barely affects the key rate. With
+//! several patterns it runs before the key takes a slot of the pattern it
+//! matches, so the script holds up no other worker's key.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use rhai::{Array, Dynamic, Engine, Scope, AST};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Name of the function the script must define.
+const PREDICATE_FN: &str = "matches";
+
+/// Operations one call may run, so a runaway loop fails instead of hanging
+/// a worker.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A compiled predicate script.
+pub struct Predicate {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    /// Set once a script error has been reported
+    failed: AtomicBool,
+}
+
+/// What the script made of a candidate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Rejected,
+    Accepted,
+    /// Accepted with the score the script returned
+    Scored(f64),
+}
+
+impl Predicate {
+    /// Compiles the script at `path` and checks that it defines
+    /// `matches(pubkey, address)`.
+    pub fn load(path: &Path) -> Result<Predicate, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("Failed to compile {}: {e}", path.display()))?;
+        if !ast.iter_functions().any(|f| f.name == PREDICATE_FN && f.params.len() == 2) {
+            return Err(format!("{} must define fn {PREDICATE_FN}(pubkey, address)", path.display()));
+        }
+        Ok(Predicate { path: path.to_path_buf(), engine, ast, failed: AtomicBool::new(false) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Runs the script on an address.
+    pub fn evaluate(&self, pubkey: &[u8; 32], address: &str) -> Result<Verdict, String> {
+        // An array of integers rather than a blob, whose iterator yields u8s
+        // that don't compare with integer literals
+        let pubkey_array: Array = pubkey.iter().map(|&byte| Dynamic::from_int(i64::from(byte))).collect();
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, PREDICATE_FN, (pubkey_array, address.to_string()))
+            .map_err(|e| format!("{}: {e}", self.path.display()))?;
+
+        let score = match result.type_name() {
+            "bool" => return Ok(if result.as_bool().unwrap_or(false) { Verdict::Accepted } else { Verdict::Rejected }),
+            "i64" => result.as_int().unwrap_or(0) as f64,
+            "f64" => result.as_float().unwrap_or(0.0),
+            other => return Err(format!("{}: {PREDICATE_FN} returned {other}, expected a bool or a number", self.path.display())),
+        };
+        Ok(if score > 0.0 { Verdict::Scored(score) } else { Verdict::Rejected })
+    }
+
+    /// Returns true the first time it is called, so a script that fails on
+    /// every candidate is only reported once.
+    pub fn first_failure(&self) -> bool {
+        !self.failed.swap(true, Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Predicate").field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Loads a predicate script holding `source`.
+    fn load(source: &str) -> Result<Predicate, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("predicate.rhai");
+        fs::write(&path, source).unwrap();
+        Predicate::load(&path)
+    }
+
+    #[test]
+    fn verdicts_follow_the_return_value() {
+        let predicate = load(r#"fn matches(pubkey, address) { address.starts_with("A") && pubkey[0] < 128 }"#).unwrap();
+        assert_eq!(predicate.evaluate(&[1; 32], "Abc").unwrap(), Verdict::Accepted);
+        assert_eq!(predicate.evaluate(&[200; 32], "Abc").unwrap(), Verdict::Rejected);
+        assert_eq!(predicate.evaluate(&[1; 32], "xyz").unwrap(), Verdict::Rejected);
+
+        let scored = load("fn matches(pubkey, address) { if pubkey[0] == 0 { 2.5 } else { pubkey[0] - 10 } }").unwrap();
+        assert_eq!(scored.evaluate(&[0; 32], "").unwrap(), Verdict::Scored(2.5));
+        assert_eq!(scored.evaluate(&[42; 32], "").unwrap(), Verdict::Scored(32.0));
+        // Zero and below reject
+        assert_eq!(scored.evaluate(&[10; 32], "").unwrap(), Verdict::Rejected);
+        assert_eq!(scored.evaluate(&[3; 32], "").unwrap(), Verdict::Rejected);
+    }
+
+    #[test]
+    fn scripts_without_matches_are_refused() {
+        let error = load("fn accepts(pubkey) { true }").err().unwrap();
+        assert!(error.ends_with("must define fn matches(pubkey, address)"), "{error}");
+        let error = load("fn matches(pubkey) { true }").err().unwrap();
+        assert!(error.contains("must define fn matches"), "{error}");
+        assert!(load("fn matches(pubkey, address) {").err().unwrap().starts_with("Failed to compile"));
+    }
+
+    #[test]
+    fn failures_are_errors() {
+        let thrown = load(r#"fn matches(pubkey, address) { throw "no luck" }"#).unwrap();
+        assert!(thrown.evaluate(&[0; 32], "").unwrap_err().contains("no luck"));
+        let text = load(r#"fn matches(pubkey, address) { "yes" }"#).unwrap();
+        let error = text.evaluate(&[0; 32], "").unwrap_err();
+        assert!(error.ends_with("matches returned string, expected a bool or a number"), "{error}");
+
+        // Only the first failure is reported
+        assert!(thrown.first_failure());
+        assert!(!thrown.first_failure());
+        assert!(text.first_failure());
+    }
+
+    #[test]
+    fn runaway_scripts_stop_at_the_operation_budget() {
+        let bounded = load("fn matches(pubkey, address) { let n = 0; while n < 100000 { n += 1; } n == 100000 }").unwrap();
+        assert_eq!(bounded.evaluate(&[0; 32], "").unwrap(), Verdict::Accepted);
+
+        let runaway = load("fn matches(pubkey, address) { let n = 0; loop { n += 1; } }").unwrap();
+        let error = runaway.evaluate(&[0; 32], "").unwrap_err();
+        assert!(error.contains("Too many operations"), "{error}");
+        // The budget is per call, so a call using most of it can run again
+        assert_eq!(bounded.evaluate(&[0; 32], "").unwrap(), Verdict::Accepted);
+    }
+}
@@ -0,0 +1,273 @@
+//! Checks of the output directory before a run starts.
+//!
+//! A run that can't save the key it found hours in loses the key, so the
+//! output directory is checked up front: that it is a directory the process
+//! can write to and sync, that other users can't write to it, that its
+//! volume has room for the keys the run is expected to find, and with
+//! `--no-sync-dirs` that it isn't inside a folder a cloud storage client
+//! uploads.
+
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::cli_error::CliError;
+
+/// Space to leave free on the output volume beyond the run's own files,
+/// for the index, the audit log and everything else on the volume.
+const SPACE_MARGIN: u64 = 64 * 1024 * 1024;
+
+/// Bytes one key adds to the index, the audit log and the public key
+/// record, rounded up.
+const RECORD_BYTES: u64 = 512;
+
+/// Folder names that cloud storage clients sync, matched case-insensitively
+/// against the start of each ancestor's name.
+const SYNC_FOLDERS: &[(&str, &str)] = &[
+    ("dropbox", "Dropbox"),
+    ("onedrive", "OneDrive"),
+    ("google drive", "Google Drive"),
+    ("googledrive", "Google Drive"),
+    ("my drive", "Google Drive"),
+    ("icloud drive", "iCloud Drive"),
+    ("icloud~", "iCloud Drive"),
+    ("box sync", "Box"),
+    ("pcloud drive", "pCloud"),
+    ("megasync", "MEGA"),
+    ("nextcloud", "Nextcloud"),
+    ("owncloud", "ownCloud"),
+    ("synologydrive", "Synology Drive"),
+    ("yandex.disk", "Yandex Disk"),
+];
+
+/// Files cloud storage and sync clients leave in the root of a synced
+/// folder.
+const SYNC_MARKERS: &[(&str, &str)] = &[
+    (".dropbox", "Dropbox"),
+    (".dropbox.cache", "Dropbox"),
+    (".stfolder", "Syncthing"),
+    (".sync", "Resilio Sync"),
+    (".owncloudsync.log", "ownCloud"),
+    (".sync_journal.db", "Nextcloud"),
+];
+
+/// What a run is going to write.
+pub struct OutputPlan {
+    /// Keys the run is expected to save, or `None` if it runs until
+    /// interrupted
+    pub expected_keys: Option<usize>,
+    /// Files written for each key
+    pub files_per_key: usize,
+    /// Refuse a directory inside a cloud-synced folder
+    pub no_sync_dirs: bool,
+}
+
+fn unusable(path: &Path, reason: String) -> CliError {
+    CliError::OutputDir { path: path.to_path_buf(), reason }
+}
+
+/// Checks `dir`, which must exist, against `plan`, and prints the free space
+/// on its volume.
+pub fn check_output_dir(dir: &Path, plan: &OutputPlan) -> Result<(), CliError> {
+    let metadata = fs::metadata(dir).map_err(|e| unusable(dir, e.to_string()))?;
+    if !metadata.is_dir() {
+        return Err(unusable(dir, "it is not a directory; pass --output-dir with a directory".to_string()));
+    }
+    check_permissions(dir, &metadata)?;
+    check_writable(dir)?;
+    if plan.no_sync_dirs {
+        if let Some((ancestor, service)) = synced_folder(dir) {
+            return Err(unusable(
+                dir,
+                format!(
+                    "it is inside {}, which looks synced by {service}; secret keys would be uploaded. \
+                     Pass --output-dir outside it",
+                    ancestor.display()
+                ),
+            ));
+        }
+    }
+
+    let Some(space) = free_space(dir) else {
+        return Ok(());
+    };
+    let needed = plan
+        .expected_keys
+        .map(|keys| keys as u64 * (plan.files_per_key as u64 * space.block_size + RECORD_BYTES));
+    match needed {
+        Some(needed) if space.available < needed + SPACE_MARGIN => Err(unusable(
+            dir,
+            format!(
+                "its volume has {} free, and the run needs about {} for its keys plus {} to spare; \
+                 free up space or pass --output-dir on another volume",
+                format_bytes(space.available),
+                format_bytes(needed),
+                format_bytes(SPACE_MARGIN)
+            ),
+        )),
+        _ => {
+            println!("Output directory: {} ({} free)", dir.display(), format_bytes(space.available));
+            Ok(())
+        }
+    }
+}
+
+/// Refuses a directory that its group or every user can write to, where
+/// other users could replace or link over the keypair files.
+#[cfg(unix)]
+fn check_permissions(dir: &Path, metadata: &fs::Metadata) -> Result<(), CliError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
+    let writers = match (mode & 0o020 != 0, mode & 0o002 != 0) {
+        (_, true) => "every user",
+        (true, false) => "its group",
+        (false, false) => return Ok(()),
+    };
+    Err(unusable(
+        dir,
+        format!("{writers} can write to it (mode {:o}); run chmod go-w on it or pass another --output-dir", mode & 0o7777),
+    ))
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_dir: &Path, _metadata: &fs::Metadata) -> Result<(), CliError> {
+    Ok(())
+}
+
+/// Creates, syncs and removes a file in `dir`, which catches read-only
+/// mounts and missing permissions that the directory's metadata doesn't
+/// show.
+fn check_writable(dir: &Path) -> Result<(), CliError> {
+    let probe = dir.join(format!(".doppler-write-test-{}", process::id()));
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .and_then(|mut file| file.write_all(b"doppler").and_then(|()| file.sync_all()));
+    let removed = fs::remove_file(&probe);
+    written
+        .and(removed)
+        .map_err(|e| unusable(dir, format!("a test file could not be written and removed: {e}")))
+}
+
+/// The nearest ancestor of `dir` that looks synced to cloud storage, and the
+/// service that syncs it.
+fn synced_folder(dir: &Path) -> Option<(PathBuf, &'static str)> {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors().find_map(|ancestor| {
+        let name = ancestor.file_name()?.to_string_lossy().to_lowercase();
+        let service = SYNC_FOLDERS
+            .iter()
+            .find(|(folder, _)| name.starts_with(folder))
+            .or_else(|| SYNC_MARKERS.iter().find(|(marker, _)| ancestor.join(marker).exists()))
+            .map(|&(_, service)| service)
+            .or_else(|| {
+                // macOS keeps every File Provider mount under one folder
+                let parent = ancestor.parent()?.file_name()?;
+                (parent == "CloudStorage" || parent == "Mobile Documents").then_some("a File Provider client")
+            })?;
+        Some((ancestor.to_path_buf(), service))
+    })
+}
+
+/// Free space on a volume.
+struct FreeSpace {
+    /// Bytes an unprivileged process can still use
+    available: u64,
+    /// Smallest space a file takes up
+    block_size: u64,
+}
+
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<FreeSpace> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = core::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read if statvfs
+    // filled it in
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    #[allow(clippy::useless_conversion)]
+    let block_size = u64::from(stat.f_frsize).max(1);
+    #[allow(clippy::useless_conversion)]
+    let available = u64::from(stat.f_bavail).saturating_mul(block_size);
+    Some(FreeSpace { available, block_size })
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<FreeSpace> {
+    None
+}
+
+/// Formats a byte count with a binary unit.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(expected_keys: Option<usize>) -> OutputPlan {
+        OutputPlan { expected_keys, files_per_key: 2, no_sync_dirs: false }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_others_can_write_to_are_refused() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        for (mode, writers) in [(0o770, "its group"), (0o707, "every user"), (0o777, "every user")] {
+            fs::set_permissions(dir.path(), fs::Permissions::from_mode(mode)).unwrap();
+            let error = check_output_dir(dir.path(), &plan(Some(1))).unwrap_err().to_string();
+            assert!(error.contains(&format!("{writers} can write to it")), "{mode:o}: {error}");
+        }
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(check_output_dir(dir.path(), &plan(Some(1))).is_ok());
+    }
+
+    #[test]
+    fn runs_needing_more_than_the_free_space_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        if free_space(dir.path()).is_some() {
+            // 2^40 keys need petabytes, more than any test volume has
+            let error = check_output_dir(dir.path(), &plan(Some(1 << 40))).unwrap_err().to_string();
+            assert!(error.contains("free up space"), "{error}");
+        }
+        assert!(check_output_dir(dir.path(), &plan(Some(1))).is_ok());
+        assert!(check_output_dir(dir.path(), &plan(None)).is_ok());
+    }
+
+    #[test]
+    fn files_are_not_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("keys");
+        fs::write(&file, b"").unwrap();
+        assert!(check_output_dir(&file, &plan(None)).unwrap_err().to_string().contains("not a directory"));
+    }
+}
@@ -0,0 +1,68 @@
+//! Periodic progress reporting for a grind run.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::batch::PatternStats;
+use crate::error::DopplerError;
+use crate::shutdown::ShutdownToken;
+use crate::stats::StatsCollector;
+
+/// Most rate history values in a snapshot.
+pub const HISTORY_LEN: usize = 40;
+
+/// A boxed progress callback, as stored by the grinders.
+pub type ProgressCallback = Box<dyn FnMut(ProgressSnapshot) + Send>;
+
+/// The state of a run at one progress tick.
+#[derive(Debug, Clone)]
+pub struct ProgressSnapshot {
+    pub total_attempts: u128,
+    /// Attempts per second since the previous tick
+    pub rate: f64,
+    /// Rate of each worker since the previous tick, indexed by worker id
+    pub worker_rates: Vec<f64>,
+    pub keys_found: usize,
+    pub elapsed: Duration,
+    /// The rate over the whole run so far, oldest first, in at most
+    /// `HISTORY_LEN` evenly spaced averages
+    pub rate_history: Vec<f64>,
+    /// Progress on each pattern of a batch run; empty for other runs
+    pub patterns: Vec<PatternStats>,
+}
+
+/// Starts a thread that calls `on_progress` every `interval` until
+/// `shutdown` is cancelled.
+pub fn spawn_reporter<F>(
+    stats: &Arc<StatsCollector>,
+    shutdown: &ShutdownToken,
+    interval: Duration,
+    keys_found: Arc<AtomicUsize>,
+    mut on_progress: F,
+) -> Result<JoinHandle<()>, DopplerError>
+where
+    F: FnMut(ProgressSnapshot) + Send + 'static,
+{
+    let stats = Arc::clone(stats);
+    let shutdown = shutdown.clone();
+
+    thread::Builder::new()
+        .name("progress".to_string())
+        .spawn(move || {
+            while !shutdown.wait_timeout(interval) {
+                let sample = stats.sample();
+                on_progress(ProgressSnapshot {
+                    total_attempts: sample.total_attempts,
+                    rate: sample.rate,
+                    worker_rates: sample.worker_rates,
+                    keys_found: keys_found.load(Ordering::Relaxed),
+                    elapsed: stats.elapsed(),
+                    rate_history: stats.rate_history(HISTORY_LEN),
+                    patterns: Vec::new(),
+                });
+            }
+        })
+        .map_err(DopplerError::SpawnThread)
+}
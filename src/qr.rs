@@ -0,0 +1,78 @@
+//! QR codes for found keys.
+//!
+//! With `--qr` the address of each found key is printed as a QR code in the
+//! terminal and saved as `<pubkey>.qr.svg` and `<pubkey>.qr.png`, for paper
+//! and cold-storage records. `--qr-secret` also renders each age-encrypted
+//! keypair file; plaintext secrets are never rendered.
+
+use image::Luma;
+use qrcode::render::{svg, unicode};
+use qrcode::{Color, QrCode};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli_error::CliError;
+
+/// Minimum width and height of saved images, in pixels.
+const IMAGE_SIZE: u32 = 320;
+
+/// Prints `data` as a QR code built from Unicode half blocks, drawn light
+/// on dark so it scans from a dark terminal.
+pub fn print(data: &str) -> Result<(), CliError> {
+    let code = QrCode::new(data).map_err(|e| CliError::Qr(e.to_string()))?;
+    let rendered = code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build();
+    println!("{rendered}");
+    Ok(())
+}
+
+/// The files `save` writes for `base`: `<base>.qr.svg` and `<base>.qr.png`.
+pub fn paths(base: &Path) -> [PathBuf; 2] {
+    [".qr.svg", ".qr.png"].map(|suffix| {
+        let mut path = base.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    })
+}
+
+/// Saves `data` as a QR code to `<base>.qr.svg` and `<base>.qr.png`,
+/// returning the paths written.
+pub fn save(data: &[u8], base: &Path) -> Result<Vec<PathBuf>, CliError> {
+    let code = QrCode::new(data).map_err(|e| CliError::Qr(e.to_string()))?;
+    let [svg_path, png_path] = paths(base);
+
+    let svg = code.render::<svg::Color>().min_dimensions(IMAGE_SIZE, IMAGE_SIZE).build();
+    fs::write(&svg_path, svg).map_err(|e| CliError::Qr(format!("failed to write {}: {e}", svg_path.display())))?;
+
+    code.render::<Luma<u8>>()
+        .min_dimensions(IMAGE_SIZE, IMAGE_SIZE)
+        .build()
+        .save(&png_path)
+        .map_err(|e| CliError::Qr(format!("failed to write {}: {e}", png_path.display())))?;
+
+    Ok(vec![svg_path, png_path])
+}
+
+/// Draws `data` as a QR code for embedding in a larger SVG document, filling
+/// the `size` square at (`x`, `y`) including a four-module quiet zone.
+pub fn svg_element(data: &[u8], x: f64, y: f64, size: f64) -> Result<String, CliError> {
+    const QUIET_ZONE: usize = 4;
+
+    let code = QrCode::new(data).map_err(|e| CliError::Qr(e.to_string()))?;
+    let width = code.width();
+    let mut path = String::new();
+    for (index, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let (row, column) = (index / width + QUIET_ZONE, index % width + QUIET_ZONE);
+            path.push_str(&format!("M{column} {row}h1v1h-1z"));
+        }
+    }
+
+    let modules = width + 2 * QUIET_ZONE;
+    Ok(format!(
+        r##"<svg x="{x}" y="{y}" width="{size}" height="{size}" viewBox="0 0 {modules} {modules}" shape-rendering="crispEdges"><rect width="{modules}" height="{modules}" fill="#fff"/><path d="{path}" fill="#000"/></svg>"##
+    ))
+}
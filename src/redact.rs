@@ -0,0 +1,200 @@
+//! The output policy: secret keys never leave through logs, notifications,
+//! API responses, published events or JSON summaries unless
+//! `--reveal-secrets` is passed.
+//!
+//! Every channel that writes somewhere other than a keypair file passes its
+//! text through `scrub`, which replaces anything that is a secret key with
+//! `[redacted]`:
+//!
+//! - a full keypair in `solana-keygen` JSON, base58 or hex, recognized by
+//!   its second half being the public key of its first, so signatures and
+//!   hashes of the same length are left alone
+//! - the 32-byte seed of any key this process found, in base58 or hex
+//!
+//! Seeds are only recognized by their SHA-256, so the policy keeps no copy
+//! of any secret. Keypair files, shares and the other places a secret is
+//! meant to go are written directly and are not affected.
+
+use ed25519_dalek::SigningKey;
+use sha2::{Digest, Sha256};
+use solana_keypair::Keypair;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// What stands in for a redacted secret.
+pub const REDACTED: &str = "[redacted]";
+
+static REVEAL: AtomicBool = AtomicBool::new(false);
+
+/// SHA-256 of the seeds of the keys found so far.
+static SEEDS: Mutex<BTreeSet<[u8; 32]>> = Mutex::new(BTreeSet::new());
+
+/// Turns redaction off for the rest of the process, for `--reveal-secrets`.
+pub fn reveal_secrets() {
+    REVEAL.store(true, Ordering::Relaxed);
+}
+
+/// Registers a key found by this process, so its seed is recognized on its
+/// own as well as within the keypair.
+pub fn register(keypair: &Keypair) {
+    let hash: [u8; 32] = Sha256::digest(keypair.secret_bytes()).into();
+    SEEDS.lock().unwrap_or_else(PoisonError::into_inner).insert(hash);
+}
+
+/// Whether `bytes` are a keypair: a seed followed by its public key.
+fn is_keypair(bytes: &[u8]) -> bool {
+    let Ok(bytes) = <&[u8; 64]>::try_from(bytes) else {
+        return false;
+    };
+    let seed: &[u8; 32] = bytes[..32].try_into().expect("the slice is 32 bytes");
+    SigningKey::from_bytes(seed).verifying_key().as_bytes()[..] == bytes[32..]
+}
+
+fn is_secret(bytes: &[u8]) -> bool {
+    match bytes.len() {
+        32 => {
+            let hash: [u8; 32] = Sha256::digest(bytes).into();
+            SEEDS.lock().unwrap_or_else(PoisonError::into_inner).contains(&hash)
+        }
+        64 => is_keypair(bytes),
+        _ => false,
+    }
+}
+
+/// Whether a run of letters and digits encodes a secret, in hex or base58.
+fn is_secret_token(token: &str) -> bool {
+    let hex = matches!(token.len(), 64 | 128) && token.bytes().all(|byte| byte.is_ascii_hexdigit());
+    if hex && hex::decode(token).is_ok_and(|bytes| is_secret(&bytes)) {
+        return true;
+    }
+    // 32 bytes take at least 32 base58 characters, and 64 bytes at most 88
+    (32..=88).contains(&token.len()) && bs58::decode(token).into_vec().is_ok_and(|bytes| is_secret(&bytes))
+}
+
+/// Redacts JSON arrays of byte values that hold a secret.
+fn scrub_arrays(text: &str) -> String {
+    let mut scrubbed = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        scrubbed.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let inner_end = rest[1..]
+            .find(|c: char| !(c.is_ascii_digit() || c == ',' || c.is_ascii_whitespace()))
+            .map(|end| end + 1);
+        if let Some(end) = inner_end.filter(|&end| rest.as_bytes()[end] == b']') {
+            let bytes: Option<Vec<u8>> = rest[1..end].split(',').map(|value| value.trim().parse::<u8>().ok()).collect();
+            if bytes.is_some_and(|bytes| is_secret(&bytes)) {
+                scrubbed.push_str(REDACTED);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        scrubbed.push('[');
+        rest = &rest[1..];
+    }
+    scrubbed.push_str(rest);
+    scrubbed
+}
+
+/// `text` with every secret key in it redacted, unless `--reveal-secrets`
+/// was passed.
+pub fn scrub(text: &str) -> String {
+    if REVEAL.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    let text = scrub_arrays(text);
+    let mut scrubbed = String::with_capacity(text.len());
+    let mut token_start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (c.is_ascii_alphanumeric(), token_start) {
+            (true, None) => token_start = Some(i),
+            (false, Some(start)) => {
+                let token = &text[start..i];
+                scrubbed.push_str(if is_secret_token(token) { REDACTED } else { token });
+                token_start = None;
+            }
+            _ => {}
+        }
+        if !c.is_ascii_alphanumeric() && i < text.len() {
+            scrubbed.push(c);
+        }
+    }
+    scrubbed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_signer::Signer as _;
+
+    /// `--reveal-secrets` is process-wide, so tests that scrub hold this
+    /// while the reveal test has it switched on.
+    static REVEAL_LOCK: Mutex<()> = Mutex::new(());
+
+    fn scrubbed(text: &str) -> String {
+        let _guard = REVEAL_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        scrub(text)
+    }
+
+    #[test]
+    fn redacts_keypairs_in_json_base58_and_hex() {
+        let keypair = Keypair::new();
+        let json = serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap();
+        let bytes = keypair.to_bytes();
+        let forms = [json.clone(), format!("{bytes:?}"), keypair.to_base58_string(), hex::encode(bytes)];
+        for form in forms {
+            let pubkey = keypair.pubkey();
+            assert_eq!(scrubbed(&format!("found {pubkey} ({form})")), format!("found {pubkey} ({REDACTED})"));
+        }
+        assert_eq!(scrubbed(&format!("{{\"secret\":{json}}}")), format!("{{\"secret\":{REDACTED}}}"));
+    }
+
+    #[test]
+    fn redacts_registered_seeds_only() {
+        let keypair = Keypair::new();
+        let seed = keypair.secret_bytes();
+        let forms =
+            [bs58::encode(seed).into_string(), hex::encode(seed), serde_json::to_string(&seed.to_vec()).unwrap()];
+        for form in &forms {
+            assert_eq!(scrubbed(form), *form, "seeds are only known once registered");
+        }
+        register(&keypair);
+        for form in &forms {
+            assert_eq!(scrubbed(&format!("seed: {form}")), format!("seed: {REDACTED}"));
+        }
+
+        // Any other 32 bytes, such as a hash or an address, are left alone
+        let hash = Sha256::digest(b"doppler");
+        for text in [bs58::encode(hash).into_string(), hex::encode(hash), keypair.pubkey().to_string()] {
+            assert_eq!(scrubbed(&text), text);
+        }
+    }
+
+    #[test]
+    fn leaves_signatures_alone() {
+        let keypair = Keypair::new();
+        register(&keypair);
+        let signature = keypair.sign_message(b"doppler");
+        for text in [
+            signature.to_string(),
+            hex::encode(signature.as_ref()),
+            serde_json::to_string(&signature.as_ref().to_vec()).unwrap(),
+        ] {
+            assert_eq!(scrubbed(&text), text);
+        }
+    }
+
+    #[test]
+    fn reveal_secrets_passes_text_through() {
+        let keypair = Keypair::new();
+        register(&keypair);
+        let text = format!("{} {}", keypair.to_base58_string(), hex::encode(keypair.secret_bytes()));
+        let _guard = REVEAL_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        reveal_secrets();
+        let revealed = scrub(&text);
+        REVEAL.store(false, Ordering::Relaxed);
+        assert_eq!(revealed, text);
+        assert_eq!(scrub(&text), format!("{REDACTED} {REDACTED}"));
+    }
+}
@@ -0,0 +1,289 @@
+//! `repl`: a prompt for steering a batch search while the workers run.
+//!
+//! Patterns added or cancelled at the prompt reach the running batch through
+//! its reload hook, so the search never restarts and keeps the progress it
+//! has made on the patterns that stay.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::io::{self, BufRead as _, Write as _};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use doppler_keygen::batch::{self, BatchHooks, BatchMatch, PatternStats};
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::pattern::{BatchPattern, VanityPattern};
+use doppler_keygen::progress::ProgressSnapshot;
+use doppler_keygen::shutdown::ShutdownToken;
+use solana_keypair::Keypair;
+
+use crate::cli_error::CliError;
+use crate::grind::GrindOptions;
+
+/// How often the running batch picks up changes made at the prompt.
+const RELOAD_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often the figures shown by `status` are refreshed.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+const HELP: &str = "\
+Commands:
+  add KIND:PATTERN:COUNT [PRIORITY]  - Search for a pattern, e.g. add starts-with:ab:2 or add ends-with:xyz:1 5;
+                                       adding a pattern again changes its count and priority
+  cancel N                           - Stop searching for pattern N, as numbered by status
+  status                             - Attempts, rate and each pattern's progress and ETA
+  keys                               - Keys found so far
+  help                               - Show this list
+  quit                               - Stop the search and print the summary (also Ctrl-C or end of input)";
+
+/// A key accepted during the session.
+struct FoundEntry {
+    address: String,
+    pattern: VanityPattern,
+    segment: usize,
+}
+
+/// State shared by the prompt and the running batch.
+struct Session {
+    ignore_case: bool,
+    /// The patterns as last edited at the prompt
+    patterns: Mutex<Vec<BatchPattern>>,
+    /// Set when `patterns` changed since the batch last picked them up
+    changed: AtomicBool,
+    /// The latest progress report, once there is one
+    snapshot: Mutex<Option<ProgressSnapshot>>,
+    keys: Mutex<Vec<FoundEntry>>,
+}
+
+impl Session {
+    fn add(&self, args: &[&str]) -> Result<(), String> {
+        let [entry, rest @ ..] = args else {
+            return Err("add requires a pattern, e.g. add starts-with:ab:2".to_string());
+        };
+        let mut batch_pattern = crate::grind::parse_pattern_entry(entry).map_err(|e| format!("Invalid pattern '{entry}': {e}"))?;
+        match rest {
+            [] => {}
+            [priority] => {
+                batch_pattern.priority = priority.parse::<u32>().map_err(|_| format!("Invalid priority '{priority}'"))?;
+            }
+            _ => return Err("add takes a pattern and an optional priority".to_string()),
+        }
+        batch_pattern.pattern.validate(self.ignore_case)?;
+
+        let mut patterns = self.patterns.lock().unwrap_or_else(PoisonError::into_inner);
+        match patterns.iter_mut().find(|p| p.pattern == batch_pattern.pattern) {
+            Some(existing) => {
+                println!("Updated pattern {}: {} key(s)", batch_pattern.pattern, batch_pattern.count);
+                *existing = batch_pattern;
+            }
+            None => {
+                println!("Added pattern {}: {} key(s)", batch_pattern.pattern, batch_pattern.count);
+                patterns.push(batch_pattern);
+            }
+        }
+        self.changed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn cancel(&self, args: &[&str]) -> Result<(), String> {
+        let [number] = args else {
+            return Err("cancel requires a pattern number, as shown by status".to_string());
+        };
+        let mut patterns = self.patterns.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = number
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| (1..=patterns.len()).contains(&n))
+            .ok_or_else(|| format!("No pattern {number}; status lists {} pattern(s)", patterns.len()))?;
+        let batch_pattern = patterns.remove(index - 1);
+        println!("Cancelled pattern {}", batch_pattern.pattern);
+        self.changed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn print_status(&self, options: &GrindOptions) {
+        let snapshot = self.snapshot.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        let Some(snapshot) = snapshot else {
+            println!("Starting up, no progress yet");
+            return;
+        };
+        println!(
+            "Attempts: {} | {:.0} keys/sec | Found: {} | Elapsed: {}",
+            snapshot.total_attempts,
+            snapshot.rate,
+            snapshot.keys_found,
+            crate::grind::format_eta(snapshot.elapsed.as_secs_f64())
+        );
+        if let Some(history) = crate::grind::format_rate_history(&snapshot.rate_history) {
+            println!("Rate history: {history}");
+        }
+
+        let patterns = self.patterns.lock().unwrap_or_else(PoisonError::into_inner);
+        if patterns.is_empty() {
+            println!("No patterns; add one with add KIND:PATTERN:COUNT");
+        }
+        for (number, batch_pattern) in patterns.iter().enumerate() {
+            // A pattern added since the last report has no progress yet
+            let (found, workers) = snapshot
+                .patterns
+                .iter()
+                .find(|p| p.pattern == batch_pattern.pattern)
+                .map_or((0, 0), |p| (p.found, p.workers));
+            let pattern_stats = PatternStats {
+                pattern: batch_pattern.pattern.clone(),
+                requested: batch_pattern.count,
+                found,
+                removed: false,
+                workers,
+            };
+            let state = if found >= batch_pattern.count {
+                "done".to_string()
+            } else {
+                format!("ETA {}", crate::grind::pattern_eta(&pattern_stats, snapshot.rate, self.ignore_case, options))
+            };
+            let priority = match batch_pattern.priority {
+                0 => String::new(),
+                priority => format!(", priority {priority}"),
+            };
+            let threads = crate::grind::pattern_threads(&pattern_stats);
+            println!(
+                "  {}. {}: {found}/{}{priority}{threads}, {state}",
+                number + 1,
+                batch_pattern.pattern,
+                batch_pattern.count
+            );
+        }
+    }
+
+    fn print_keys(&self) {
+        let keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        if keys.is_empty() {
+            println!("No keys found yet");
+        }
+        for (number, entry) in keys.iter().enumerate() {
+            println!("  {}. {} ({}, segment {})", number + 1, entry.address, entry.pattern, entry.segment);
+        }
+    }
+
+    /// Runs one line typed at the prompt, returning false to quit.
+    fn handle(&self, line: &str, options: &GrindOptions) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(()),
+            ["add", args @ ..] => self.add(args),
+            ["cancel", args @ ..] => self.cancel(args),
+            ["status"] => {
+                self.print_status(options);
+                Ok(())
+            }
+            ["keys"] => {
+                self.print_keys();
+                Ok(())
+            }
+            ["help"] => {
+                println!("{HELP}");
+                Ok(())
+            }
+            ["quit" | "exit"] => return false,
+            [command, ..] => Err(format!("Unknown command '{command}'; type help for the list")),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {e}");
+        }
+        true
+    }
+}
+
+/// Starts a batch search for `patterns`, which may be empty, and reads
+/// commands from stdin until `quit`, the end of input or Ctrl-C. Returns the
+/// keypairs kept, as `grind` does.
+pub fn run(patterns: &[BatchPattern], ignore_case: bool, options: &GrindOptions) -> Result<Vec<Keypair>, CliError> {
+    println!("Doppler Keygen REPL - Mining for 32-bit immediate value compatible vanity keys in the background");
+    crate::grind::print_derivation(options);
+    crate::grind::print_constraints(options);
+    println!("Using {} threads", options.num_threads);
+    if ignore_case {
+        println!("Vanity patterns are matched case-insensitively");
+    }
+
+    let session = Arc::new(Session {
+        ignore_case,
+        patterns: Mutex::new(patterns.to_vec()),
+        changed: AtomicBool::new(false),
+        snapshot: Mutex::new(None),
+        keys: Mutex::new(Vec::new()),
+    });
+    let shutdown = ShutdownToken::new();
+    crate::grind::stop_on_interrupt(&shutdown)?;
+
+    let hooks = {
+        let progress_session = Arc::clone(&session);
+        let match_session = Arc::clone(&session);
+        let reload_session = Arc::clone(&session);
+        let options = options.clone();
+        let progress_options = options.clone();
+
+        BatchHooks {
+            on_progress: Some((
+                STATUS_INTERVAL,
+                Box::new(move |snapshot| {
+                    crate::grind::record_progress(&progress_options, &snapshot);
+                    *progress_session.snapshot.lock().unwrap_or_else(PoisonError::into_inner) = Some(snapshot);
+                }),
+            )),
+            filter: crate::grind::custom_filter(&options),
+            on_match: Some(Box::new(move |batch_match: &BatchMatch| {
+                if !crate::grind::save_batch_match(batch_match, &options) {
+                    return false;
+                }
+                match_session.keys.lock().unwrap_or_else(PoisonError::into_inner).push(FoundEntry {
+                    address: batch_match.key.address.clone(),
+                    pattern: batch_match.pattern.clone(),
+                    segment: batch_match.key.segment,
+                });
+                true
+            })),
+            reload: Some((
+                RELOAD_INTERVAL,
+                Box::new(move || {
+                    reload_session
+                        .changed
+                        .swap(false, Ordering::Relaxed)
+                        .then(|| reload_session.patterns.lock().unwrap_or_else(PoisonError::into_inner).clone())
+                }),
+            )),
+            shutdown: Some(shutdown.clone()),
+        }
+    };
+
+    // The search runs on this thread, so the prompt gets its own. After
+    // Ctrl-C it may still be waiting for a line, which is harmless as the
+    // process exits once the summary is printed.
+    {
+        let session = Arc::clone(&session);
+        let shutdown = shutdown.clone();
+        let options = options.clone();
+        thread::Builder::new()
+            .name("repl".to_string())
+            .spawn(move || {
+                println!("Type help for commands\n");
+                let mut lines = io::stdin().lock().lines();
+                loop {
+                    print!("doppler> ");
+                    let _ = io::stdout().flush();
+                    let Some(Ok(line)) = lines.next() else {
+                        println!();
+                        break;
+                    };
+                    if !session.handle(&line, &options) {
+                        break;
+                    }
+                }
+                shutdown.cancel();
+            })
+            .map_err(DopplerError::SpawnThread)?;
+    }
+
+    let result = batch::vanity_keys_batch(patterns, &options.grind_config(ignore_case), hooks)?;
+    Ok(crate::grind::finish_batch(result, options))
+}
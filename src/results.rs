@@ -0,0 +1,268 @@
+//! The results database: a SQLite catalogue of the keys in an output
+//! directory.
+//!
+//! Every key a grind saves, and every key brought in with `import`, gets a
+//! row holding its JSON record: where its files are, where it came from,
+//! and an analysis of its address, namely the imm32 compatible segments and
+//! any vanity patterns it shows, and the run's tags and metadata. Only
+//! public keys and file names are stored, never secrets.
+
+use rusqlite::{params, Connection, OptionalExtension as _};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cli_error::CliError;
+use crate::codegen::{self, Segment};
+use crate::inspect;
+use crate::tags::KeyTags;
+
+/// Name of the database in the output directory.
+pub const RESULTS_FILE: &str = "results.db";
+
+/// How long to wait for another process to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the results database knows about a key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRecord {
+    pub pubkey: String,
+    /// The matched address, when it is derived from the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Seconds since the Unix epoch
+    pub recorded_at: u64,
+    /// `grind` or `import`
+    pub source: String,
+    /// The file an imported key was read from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<PathBuf>,
+    /// Files holding the secret, relative to the output directory when they
+    /// are inside it
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    /// The segment the grind matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched_segment: Option<usize>,
+    /// The vanity pattern the key was ground for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// The address's imm32 compatible segments
+    #[serde(default)]
+    pub segments: Vec<usize>,
+    /// Notable patterns the address shows, as `inspect` reports them
+    #[serde(default)]
+    pub vanity: Vec<String>,
+    /// Seconds since the Unix epoch the key should be rotated by, from
+    /// `--lifetime`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotate_by: Option<u64>,
+    /// Labels given with `--tag` and `--meta`
+    #[serde(flatten)]
+    pub tags: KeyTags,
+}
+
+impl KeyRecord {
+    /// A record for `pubkey` from `source`, with the analysis of `address`,
+    /// the key itself unless a derived address was matched.
+    pub fn new(pubkey: String, address: &[u8; 32], source: &str) -> KeyRecord {
+        let encoded = bs58::encode(address).into_string();
+        KeyRecord {
+            address: (encoded != pubkey).then(|| encoded.clone()),
+            pubkey,
+            recorded_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            source: source.to_string(),
+            origin: None,
+            files: Vec::new(),
+            matched_segment: None,
+            pattern: None,
+            segments: codegen::segments(address)
+                .iter()
+                .enumerate()
+                .filter(|(_, segment)| matches!(segment, Segment::Imm32(_)))
+                .map(|(segment, _)| segment)
+                .collect(),
+            vanity: inspect::vanity_patterns(&encoded).iter().map(ToString::to_string).collect(),
+            rotate_by: None,
+            tags: KeyTags::default(),
+        }
+    }
+
+    /// The analysis of the address, for display.
+    pub fn analysis(&self) -> String {
+        let list = |items: Vec<String>| if items.is_empty() { "none".to_string() } else { items.join(", ") };
+        format!(
+            "imm32 segments: {}; patterns: {}",
+            list(self.segments.iter().map(ToString::to_string).collect()),
+            list(self.vanity.clone())
+        )
+    }
+}
+
+pub struct ResultsDb {
+    path: PathBuf,
+    dir: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl std::fmt::Debug for ResultsDb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResultsDb").field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+impl ResultsDb {
+    /// Opens the database in `dir`, creating it if needed.
+    pub fn open(dir: &Path) -> Result<ResultsDb, CliError> {
+        let path = dir.join(RESULTS_FILE);
+        let results_error = |e: rusqlite::Error| CliError::Results { path: path.clone(), reason: e.to_string() };
+        let connection = Connection::open(&path).map_err(results_error)?;
+        connection.busy_timeout(BUSY_TIMEOUT).map_err(results_error)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS keys (
+                    pubkey TEXT PRIMARY KEY,
+                    recorded_at INTEGER NOT NULL,
+                    record TEXT NOT NULL
+                )",
+            )
+            .map_err(results_error)?;
+        Ok(ResultsDb { path, dir: dir.to_path_buf(), connection: Mutex::new(connection) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn error(&self, reason: impl ToString) -> CliError {
+        CliError::Results { path: self.path.clone(), reason: reason.to_string() }
+    }
+
+    /// `path` relative to the output directory, if it is inside it.
+    pub fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.dir).map_or_else(|_| path.to_path_buf(), Path::to_path_buf)
+    }
+
+    /// The record of `pubkey`, if there is one.
+    pub fn get(&self, pubkey: &str) -> Result<Option<KeyRecord>, CliError> {
+        let json = self
+            .connection()
+            .query_row("SELECT record FROM keys WHERE pubkey = ?1", params![pubkey], |row| row.get::<_, String>(0))
+            .optional()
+            .map_err(|e| self.error(e))?;
+        json.map(|json| serde_json::from_str(&json).map_err(|e| self.error(format!("record of {pubkey} is unreadable: {e}"))))
+            .transpose()
+    }
+
+    /// Every record, oldest first.
+    pub fn load(&self) -> Result<Vec<KeyRecord>, CliError> {
+        let connection = self.connection();
+        let mut statement = connection
+            .prepare("SELECT pubkey, record FROM keys ORDER BY recorded_at, pubkey")
+            .map_err(|e| self.error(e))?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| self.error(e))?;
+        rows.map(|row| {
+            let (pubkey, json) = row.map_err(|e| self.error(e))?;
+            serde_json::from_str(&json).map_err(|e| self.error(format!("record of {pubkey} is unreadable: {e}")))
+        })
+        .collect()
+    }
+
+    /// Writes `record`, replacing any earlier record of the same key.
+    pub fn save(&self, record: &KeyRecord) -> Result<(), CliError> {
+        let json = serde_json::to_string(record).map_err(|e| self.error(e))?;
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO keys (pubkey, recorded_at, record) VALUES (?1, ?2, ?3)",
+                params![record.pubkey, record.recorded_at as i64, json],
+            )
+            .map(|_| ())
+            .map_err(|e| self.error(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_keypair::Keypair;
+    use solana_signer::Signer as _;
+
+    /// A fresh key's record, recorded at `recorded_at`.
+    fn record(recorded_at: u64) -> KeyRecord {
+        let pubkey = Keypair::new().pubkey();
+        KeyRecord { recorded_at, ..KeyRecord::new(pubkey.to_string(), &pubkey.to_bytes(), "grind") }
+    }
+
+    #[test]
+    fn records_round_trip_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ResultsDb::open(dir.path()).unwrap();
+        let mut newer = record(200);
+        newer.files = vec![db.relative(&dir.path().join("newer.json")), PathBuf::from("/elsewhere/newer.json")];
+        newer.matched_segment = Some(2);
+        newer.pattern = Some("prefix 'ab'".to_string());
+        newer.rotate_by = Some(300);
+        newer.tags.add_tag("launch-q3").unwrap();
+        newer.tags.add_meta("team=infra").unwrap();
+        let older = record(100);
+        db.save(&newer).unwrap();
+        db.save(&older).unwrap();
+
+        let loaded = db.get(&newer.pubkey).unwrap().unwrap();
+        assert_eq!(loaded.files, [PathBuf::from("newer.json"), PathBuf::from("/elsewhere/newer.json")]);
+        assert_eq!((loaded.matched_segment, loaded.rotate_by), (Some(2), Some(300)));
+        assert_eq!(loaded.pattern.as_deref(), Some("prefix 'ab'"));
+        assert_eq!(loaded.tags, newer.tags);
+        assert!(db.get(&Keypair::new().pubkey().to_string()).unwrap().is_none());
+        let pubkeys: Vec<String> = db.load().unwrap().into_iter().map(|record| record.pubkey).collect();
+        assert_eq!(pubkeys, [older.pubkey.clone(), newer.pubkey.clone()]);
+    }
+
+    #[test]
+    fn saving_a_key_again_replaces_its_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ResultsDb::open(dir.path()).unwrap();
+        let mut key = record(100);
+        db.save(&key).unwrap();
+        key.source = "import".to_string();
+        key.origin = Some(PathBuf::from("/old/id.json"));
+        db.save(&key).unwrap();
+        drop(db);
+
+        let db = ResultsDb::open(dir.path()).unwrap();
+        let records = db.load().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].source, "import");
+        assert_eq!(records[0].origin, Some(PathBuf::from("/old/id.json")));
+    }
+
+    #[test]
+    fn a_derived_address_is_analysed_instead_of_the_key() {
+        let pubkey = Keypair::new().pubkey();
+        let own = KeyRecord::new(pubkey.to_string(), &pubkey.to_bytes(), "grind");
+        assert_eq!(own.address, None);
+
+        // Every segment of an all-zero address is an imm32 of 0
+        let derived = KeyRecord::new(pubkey.to_string(), &[0; 32], "grind");
+        assert_eq!(derived.address.as_deref(), Some("11111111111111111111111111111111"));
+        assert_eq!(derived.segments, [0, 1, 2, 3]);
+        assert!(derived.analysis().starts_with("imm32 segments: 0, 1, 2, 3; patterns: "), "{}", derived.analysis());
+    }
+
+    #[test]
+    fn unreadable_records_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ResultsDb::open(dir.path()).unwrap();
+        db.connection().execute("INSERT INTO keys (pubkey, recorded_at, record) VALUES ('Broken1', 0, '{')", []).unwrap();
+        let error = db.get("Broken1").unwrap_err().to_string();
+        assert!(error.contains("record of Broken1 is unreadable"), "{error}");
+        assert!(db.load().is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! `rotate`: replacing an admin key embedded in a program's source.
+//!
+//! The replacement is ground with the same structure as the old key, so the
+//! generated comparison code keeps its shape and only the constants change.
+//! Those are rewritten line by line into a patch for the program's source.
+
+use crate::codegen::{self, Segment};
+
+/// The `.equ` constants `address` generates.
+pub fn constants(address: &[u8; 32]) -> String {
+    let mut constants = String::new();
+    for (segment, value) in codegen::segments(address).iter().enumerate() {
+        constants.push_str(&format!(".equ EXPECTED_ADMIN_KEY_{segment}, {}\n", value.constant()));
+    }
+    constants
+}
+
+/// Every spelling of `address` that generated code uses, paired with its
+/// spelling for `replacement`: the base58 address, and each changed
+/// segment as an `.equ` constant and as a Rust u64 literal.
+fn replacements(address: &[u8; 32], replacement: &[u8; 32]) -> Vec<(String, String)> {
+    let mut pairs = vec![(bs58::encode(address).into_string(), bs58::encode(replacement).into_string())];
+    let u64_literal = |segment: Segment| match segment {
+        Segment::Imm32(value) => format!("0x{:016x}", i64::from(value) as u64),
+        Segment::Wide(value) => format!("0x{value:016x}"),
+    };
+    for (old, new) in codegen::segments(address).into_iter().zip(codegen::segments(replacement)) {
+        if old == new {
+            continue;
+        }
+        pairs.push((old.constant(), new.constant()));
+        if u64_literal(old) != old.constant() {
+            pairs.push((u64_literal(old), u64_literal(new)));
+        }
+    }
+    pairs
+}
+
+/// Replaces whole occurrences of `from` in `line`, i.e. those not followed
+/// by another letter or digit, so a short constant never matches the start
+/// of a longer one.
+fn replace_token(line: &str, from: &str, to: &str) -> String {
+    let mut result = String::new();
+    let mut rest = line;
+    while let Some(index) = rest.find(from) {
+        let end = index + from.len();
+        let whole = !rest[end..].starts_with(|c: char| c.is_ascii_alphanumeric());
+        result.push_str(&rest[..index]);
+        result.push_str(if whole { to } else { from });
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// A unified diff, without context lines, that swaps the constants of
+/// `address` in `source` for those of `replacement`; `None` if `source`
+/// contains none of them. `path` names the file in the diff headers.
+pub fn migration_patch(path: &str, source: &str, address: &[u8; 32], replacement: &[u8; 32]) -> Option<String> {
+    let pairs = replacements(address, replacement);
+    let changes: Vec<(usize, &str, String)> = source
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let updated = pairs.iter().fold(line.to_string(), |line, (from, to)| replace_token(&line, from, to));
+            (updated != line).then_some((index + 1, line, updated))
+        })
+        .collect();
+    if changes.is_empty() {
+        return None;
+    }
+
+    let mut patch = format!("--- {path}\n+++ {path}\n");
+    // Consecutive changed lines share a hunk
+    let mut start = 0;
+    while start < changes.len() {
+        let mut end = start + 1;
+        while end < changes.len() && changes[end].0 == changes[end - 1].0 + 1 {
+            end += 1;
+        }
+        let hunk = &changes[start..end];
+        let range = match hunk.len() {
+            1 => hunk[0].0.to_string(),
+            len => format!("{},{len}", hunk[0].0),
+        };
+        patch.push_str(&format!("@@ -{range} +{range} @@\n"));
+        for (_, line, _) in hunk {
+            patch.push_str(&format!("-{line}\n"));
+        }
+        for (_, _, updated) in hunk {
+            patch.push_str(&format!("+{updated}\n"));
+        }
+        start = end;
+    }
+    Some(patch)
+}
@@ -0,0 +1,170 @@
+//! `--rotate`: streaming runs that file their keys by date.
+//!
+//! Keys found while rotation is on are saved to a subdirectory of the output
+//! directory named after the current UTC day (`2025-06-01`) or hour
+//! (`2025-06-01T13`), so a standing inventory of keys can be handed out or
+//! archived one period at a time. The key index, the audit log and the
+//! public key record stay in the output directory itself, since they cover
+//! every key the directory has ever held.
+//!
+//! With `--keep N`, starting a new period removes all but the N most recent
+//! period directories, shredding every file in them first.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cli_error::CliError;
+use crate::shred;
+
+/// How long each output directory collects keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Hourly,
+    Daily,
+}
+
+impl Period {
+    pub fn parse(value: &str) -> Result<Period, String> {
+        match value {
+            "hourly" => Ok(Period::Hourly),
+            "daily" => Ok(Period::Daily),
+            _ => Err(format!("Unknown rotation period '{value}' (expected hourly or daily)")),
+        }
+    }
+
+    /// Name of the directory for the period holding `unix_seconds`.
+    fn label(self, unix_seconds: u64) -> String {
+        let (year, month, day) = utc_date(unix_seconds);
+        match self {
+            Period::Daily => format!("{year:04}-{month:02}-{day:02}"),
+            Period::Hourly => format!("{year:04}-{month:02}-{day:02}T{:02}", unix_seconds % 86_400 / 3600),
+        }
+    }
+
+    /// Whether `name` is the name of one of this period's directories.
+    fn is_label(self, name: &str) -> bool {
+        let pattern = match self {
+            Period::Daily => "dddd-dd-dd",
+            Period::Hourly => "dddd-dd-ddTdd",
+        };
+        name.len() == pattern.len()
+            && name.bytes().zip(pattern.bytes()).all(|(c, p)| if p == b'd' { c.is_ascii_digit() } else { c == p })
+    }
+}
+
+/// The UTC calendar date of a Unix timestamp as year, month and day.
+pub fn utc_date(unix_seconds: u64) -> (i64, i64, i64) {
+    let days = (unix_seconds / 86_400) as i64;
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Where a rotating run saves its keys.
+#[derive(Debug)]
+pub struct Rotation {
+    period: Period,
+    /// Period directories to keep, the current one included
+    keep: Option<usize>,
+    base: PathBuf,
+    /// Label of the directory keys are being saved to
+    current: Mutex<Option<String>>,
+}
+
+impl Rotation {
+    pub fn new(period: Period, keep: Option<usize>, base: &Path) -> Rotation {
+        Rotation { period, keep, base: base.to_path_buf(), current: Mutex::new(None) }
+    }
+
+    /// The directory for keys found now, created on the first key of each
+    /// period. Starting a period also prunes old ones.
+    pub fn dir(&self) -> Result<PathBuf, CliError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let label = self.period.label(now);
+        let dir = self.base.join(&label);
+
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        if current.as_deref() != Some(label.as_str()) {
+            fs::create_dir_all(&dir).map_err(|source| CliError::CreateOutputDir { path: dir.clone(), source })?;
+            println!("Saving keys to: {}", dir.display());
+            if let Some(keep) = self.keep {
+                self.prune(&label, keep);
+            }
+            *current = Some(label);
+        }
+        Ok(dir)
+    }
+
+    /// Shreds and removes all but the `keep` most recent period directories,
+    /// never the current one. Failures are only reported, since the keys
+    /// found meanwhile still have to be saved.
+    fn prune(&self, current: &str, keep: usize) {
+        let entries = match fs::read_dir(&self.base) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Warning: could not list {} to prune old keys: {e}", self.base.display());
+                return;
+            }
+        };
+        let mut labels: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| self.period.is_label(name) && name != current)
+            .collect();
+        labels.sort_unstable_by(|a, b| b.cmp(a));
+
+        for label in labels.iter().skip(keep.saturating_sub(1)) {
+            let dir = self.base.join(label);
+            match remove_dir(&dir) {
+                Ok(files) => println!("Pruned {} ({files} file(s) shredded)", dir.display()),
+                Err(e) => eprintln!("Warning: could not prune {}: {e}", dir.display()),
+            }
+        }
+    }
+}
+
+/// Shreds every file in `dir` and removes it, returning how many files it
+/// held. A directory with subdirectories is left alone.
+fn remove_dir(dir: &Path) -> Result<usize, CliError> {
+    let shred_error = |source| CliError::Shred { path: dir.to_path_buf(), source };
+    let paths = fs::read_dir(dir)
+        .map_err(shred_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(shred_error)?;
+    for path in &paths {
+        shred::shred_file(path)?;
+    }
+    fs::remove_dir(dir).map_err(shred_error)?;
+    Ok(paths.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_and_labels_follow_the_utc_calendar() {
+        assert_eq!(utc_date(0), (1970, 1, 1));
+        // The last second of a leap day, then the next day
+        assert_eq!(utc_date(1_709_251_199), (2024, 2, 29));
+        assert_eq!(utc_date(1_709_251_200), (2024, 3, 1));
+        assert_eq!(Period::Daily.label(1_709_251_199), "2024-02-29");
+        assert_eq!(Period::Hourly.label(1_709_251_199), "2024-02-29T23");
+        assert!(Period::Hourly.is_label("2024-02-29T23"));
+        assert!(!Period::Daily.is_label("2024-02-29T23"));
+        assert!(!Period::Daily.is_label("2024-2-29"));
+    }
+}
@@ -0,0 +1,119 @@
+//! A minimal Solana JSON-RPC client for the few calls the CLI makes.
+
+use serde_json::{json, Value};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a single RPC request may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a pending signature is polled while waiting for confirmation.
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Lamports in one SOL.
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+
+/// Resolves a cluster moniker (`devnet`, `testnet`, `mainnet-beta`,
+/// `localhost`) to its public RPC URL; anything else is taken as a URL.
+pub fn cluster_url(cluster: &str) -> String {
+    match cluster {
+        "devnet" => "https://api.devnet.solana.com".to_string(),
+        "testnet" => "https://api.testnet.solana.com".to_string(),
+        "mainnet-beta" | "mainnet" => "https://api.mainnet-beta.solana.com".to_string(),
+        "localhost" => "http://127.0.0.1:8899".to_string(),
+        url => url.to_string(),
+    }
+}
+
+#[derive(Debug)]
+pub struct RpcClient {
+    agent: ureq::Agent,
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> RpcClient {
+        RpcClient { agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(), url: url.to_string() }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Calls `method` and returns its `result`, or the error the node
+    /// reported.
+    fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let response: Value = self
+            .agent
+            .post(&self.url)
+            .send_json(json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+            .map_err(|e| match e {
+                ureq::Error::Status(status, response) => {
+                    format!("{method} failed with HTTP {status}: {}", response.into_string().unwrap_or_default().trim())
+                }
+                e => format!("{method} failed: {e}"),
+            })?
+            .into_json()
+            .map_err(|e| format!("{method} returned an unexpected response: {e}"))?;
+
+        if let Some(error) = response.get("error") {
+            let message = error.get("message").and_then(Value::as_str).map_or_else(|| error.to_string(), String::from);
+            return Err(format!("{method} failed: {message}"));
+        }
+        response.get("result").cloned().ok_or_else(|| format!("{method} returned no result"))
+    }
+
+    /// Requests an airdrop of `lamports` to `pubkey`, returning the
+    /// transaction signature.
+    pub fn request_airdrop(&self, pubkey: &str, lamports: u64) -> Result<String, String> {
+        self.call("requestAirdrop", json!([pubkey, lamports]))?
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| "requestAirdrop returned no signature".to_string())
+    }
+
+    /// Waits until `signature` is confirmed, failing if the transaction
+    /// failed or `timeout` passes first.
+    pub fn confirm_signature(&self, signature: &str, timeout: Duration) -> Result<(), String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let result = self.call("getSignatureStatuses", json!([[signature]]))?;
+            if let Some(status) = result.pointer("/value/0").filter(|status| !status.is_null()) {
+                if let Some(error) = status.get("err").filter(|error| !error.is_null()) {
+                    return Err(format!("transaction {signature} failed: {error}"));
+                }
+                let confirmation = status.get("confirmationStatus").and_then(Value::as_str);
+                if matches!(confirmation, Some("confirmed" | "finalized")) {
+                    return Ok(());
+                }
+            }
+
+            if Instant::now() >= deadline {
+                return Err(format!("transaction {signature} was not confirmed within {}s", timeout.as_secs()));
+            }
+            thread::sleep(CONFIRM_POLL_INTERVAL);
+        }
+    }
+
+    /// Returns the account stored at `pubkey`, or `None` if there is none.
+    pub fn get_account_info(&self, pubkey: &str) -> Result<Option<Value>, String> {
+        let result = self.call("getAccountInfo", json!([pubkey, { "encoding": "base64" }]))?;
+        Ok(result.get("value").filter(|value| !value.is_null()).cloned())
+    }
+
+    /// Whether any transaction has ever referenced `pubkey`.
+    pub fn has_history(&self, pubkey: &str) -> Result<bool, String> {
+        let result = self.call("getSignaturesForAddress", json!([pubkey, { "limit": 1 }]))?;
+        Ok(result.as_array().is_some_and(|signatures| !signatures.is_empty()))
+    }
+
+    /// The latest finalized blockhash, for a transaction to be signed.
+    pub fn get_latest_blockhash(&self) -> Result<String, String> {
+        let result = self.call("getLatestBlockhash", json!([{ "commitment": "finalized" }]))?;
+        result
+            .pointer("/value/blockhash")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .ok_or_else(|| "getLatestBlockhash returned no blockhash".to_string())
+    }
+}
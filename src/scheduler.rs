@@ -0,0 +1,374 @@
+//! Execution backends that drive candidate keys through a grind run.
+
+use core::ops::ControlFlow;
+use ed25519_dalek::SigningKey;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use solana_keypair::Keypair;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use crate::derive::Derivation;
+use crate::entropy::Entropy;
+use crate::error::DopplerError;
+use crate::imm32;
+use crate::shutdown::ShutdownToken;
+use crate::stats::StatsCollector;
+
+/// Default number of candidates generated per batch.
+pub const DEFAULT_BATCH_SIZE: usize = 1_024;
+
+/// How many times a panicking worker is restarted before it is retired.
+const MAX_WORKER_RESTARTS: usize = 10;
+
+/// How candidate generation is spread across threads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheduler {
+    /// One dedicated OS thread per worker, each looping until shutdown
+    #[default]
+    Threads,
+    /// Chunks of candidates processed by a work-stealing rayon pool
+    Rayon,
+}
+
+impl Scheduler {
+    pub fn parse(value: &str) -> Result<Scheduler, String> {
+        match value {
+            "threads" => Ok(Scheduler::Threads),
+            "rayon" => Ok(Scheduler::Rayon),
+            _ => Err(format!("Unknown scheduler '{value}' (expected threads or rayon)")),
+        }
+    }
+}
+
+/// How a run's workers are set up.
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    pub scheduler: Scheduler,
+    pub num_threads: usize,
+    /// Candidates generated per batch
+    pub batch_size: usize,
+    /// Match an address derived from each key instead of the key itself
+    pub derivation: Option<Derivation>,
+    /// Where each worker's RNG is seeded from
+    pub entropy: Entropy,
+}
+
+/// A generated secret seed together with its public key and imm32 segment
+/// analysis.
+///
+/// The full `Keypair` is only built on demand, since almost every candidate
+/// is rejected before it is needed.
+pub struct Candidate {
+    pub seed: [u8; Keypair::SECRET_KEY_LENGTH],
+    pub pubkey: [u8; 32],
+    /// The address that is matched: the public key, or the address derived
+    /// from it when the run has a `Derivation`
+    pub address: [u8; 32],
+    /// Bit `n` is set if segment `n` of `address` forms a valid 32-bit
+    /// immediate
+    pub imm32_mask: u8,
+}
+
+impl Candidate {
+    /// Builds the keypair for this candidate's seed.
+    pub fn keypair(&self) -> Keypair {
+        Keypair::new_from_array(self.seed)
+    }
+
+    /// Returns the first segment that forms a valid 32-bit immediate.
+    pub fn imm32_segment(&self) -> Option<usize> {
+        imm32::first_segment(self.imm32_mask)
+    }
+}
+
+/// Per-worker RNG and buffers reused from batch to batch.
+struct BatchBuffers {
+    rng: ChaCha20Rng,
+    seeds: Vec<[u8; Keypair::SECRET_KEY_LENGTH]>,
+    pubkeys: Vec<[u8; 32]>,
+    /// Derived addresses, only filled when the run has a `Derivation`
+    addresses: Vec<[u8; 32]>,
+    masks: Vec<u8>,
+}
+
+impl BatchBuffers {
+    fn new(batch_size: usize, entropy: &Entropy) -> Result<BatchBuffers, DopplerError> {
+        Ok(BatchBuffers {
+            rng: ChaCha20Rng::from_seed(entropy.worker_seed()?),
+            seeds: vec![[0u8; Keypair::SECRET_KEY_LENGTH]; batch_size],
+            pubkeys: vec![[0u8; 32]; batch_size],
+            addresses: vec![[0u8; 32]; batch_size],
+            masks: vec![0u8; batch_size],
+        })
+    }
+
+    /// Buffers for each of `config.num_threads` workers, made before any
+    /// of them starts.
+    fn for_workers(config: &WorkerConfig) -> Result<Vec<BatchBuffers>, DopplerError> {
+        (0..config.num_threads).map(|_| BatchBuffers::new(config.batch_size, &config.entropy)).collect()
+    }
+}
+
+/// Generates candidate keypairs on `config.num_threads` workers until
+/// `shutdown` is cancelled, passing each one to `check` along with the id of
+/// the worker that generated it. With a `derivation`, each candidate's imm32
+/// analysis is of the address derived from its public key.
+///
+/// Candidates are generated `batch_size` at a time: each worker draws secret
+/// seeds from its own ChaCha20 CSPRNG, seeded once from the run's entropy,
+/// and the shutdown token and attempt counters are only touched once per
+/// batch.
+///
+/// With the thread scheduler, `check` returning `ControlFlow::Break` retires
+/// the worker, and the run ends once the token is cancelled or every worker
+/// has retired. The rayon scheduler has no long-lived workers to retire, so
+/// there a break ends the run. A run whose workers have all been retired
+/// after repeated panics fails with `DopplerError::WorkersRetired` rather
+/// than ending as if it were done.
+pub fn run_workers<F>(
+    config: &WorkerConfig,
+    stats: &Arc<StatsCollector>,
+    shutdown: &ShutdownToken,
+    check: F,
+) -> Result<(), DopplerError>
+where
+    F: Fn(usize, Candidate) -> ControlFlow<()> + Send + Sync + 'static,
+{
+    match config.scheduler {
+        Scheduler::Threads => run_threads(config, stats, shutdown, Arc::new(check)),
+        Scheduler::Rayon => run_rayon(config, stats, shutdown, &check),
+    }
+}
+
+/// Generates and checks one batch of candidates, counting them in
+/// `attempts` and stopping early if `check` asks to.
+///
+/// The whole batch is derived into the reused buffers first so the imm32
+/// test can run over every public key in one pass. Nothing is allocated per
+/// candidate.
+fn run_batch<F>(
+    worker: usize,
+    buffers: &mut BatchBuffers,
+    derivation: Option<Derivation>,
+    check: &F,
+    attempts: &mut u64,
+) -> ControlFlow<()>
+where
+    F: Fn(usize, Candidate) -> ControlFlow<()>,
+{
+    for (seed, pubkey) in buffers.seeds.iter_mut().zip(&mut buffers.pubkeys) {
+        buffers.rng.fill_bytes(seed);
+        *pubkey = SigningKey::from_bytes(seed).verifying_key().to_bytes();
+    }
+    let addresses = match derivation {
+        Some(derivation) => {
+            for (address, pubkey) in buffers.addresses.iter_mut().zip(&buffers.pubkeys) {
+                // A key with no derived address can never match, so it gets
+                // a placeholder that fails the imm32 test
+                *address = derivation.derive(pubkey).unwrap_or([0xAA; 32]);
+            }
+            &buffers.addresses
+        }
+        None => &buffers.pubkeys,
+    };
+    imm32::imm32_masks(addresses, &mut buffers.masks);
+
+    for (((&seed, &pubkey), &address), &imm32_mask) in
+        buffers.seeds.iter().zip(&buffers.pubkeys).zip(addresses).zip(&buffers.masks)
+    {
+        *attempts += 1;
+        check(worker, Candidate { seed, pubkey, address, imm32_mask })?;
+    }
+    ControlFlow::Continue(())
+}
+
+fn run_threads<F>(
+    config: &WorkerConfig,
+    stats: &Arc<StatsCollector>,
+    shutdown: &ShutdownToken,
+    check: Arc<F>,
+) -> Result<(), DopplerError>
+where
+    F: Fn(usize, Candidate) -> ControlFlow<()> + Send + Sync + 'static,
+{
+    let derivation = config.derivation;
+    let mut handles = Vec::with_capacity(config.num_threads);
+    for (thread_id, mut buffers) in BatchBuffers::for_workers(config)?.into_iter().enumerate() {
+        let stats = Arc::clone(stats);
+        let worker_shutdown = shutdown.clone();
+        let check = Arc::clone(&check);
+
+        let spawned = thread::Builder::new().name(format!("worker-{thread_id}")).spawn(move || {
+            // Stop once the run has been shut down
+            while !worker_shutdown.is_cancelled() {
+                let mut batch_attempts = 0u64;
+                let mut result = ControlFlow::Continue(());
+                let healthy = run_supervised(thread_id, &mut batch_attempts, |batch_attempts| {
+                    result = run_batch(thread_id, &mut buffers, derivation, &*check, batch_attempts);
+                });
+                stats.record_attempts(thread_id, batch_attempts);
+
+                if result.is_break() || !healthy {
+                    break;
+                }
+            }
+        });
+        match spawned {
+            Ok(handle) => handles.push(handle),
+            Err(e) => {
+                // Stop the workers already running before giving up, so
+                // none outlives the run
+                shutdown.cancel();
+                for handle in handles {
+                    let _ = handle.join();
+                }
+                return Err(DopplerError::SpawnThread(e));
+            }
+        }
+    }
+
+    let mut result = Ok(());
+    for (thread_id, handle) in handles.into_iter().enumerate() {
+        if handle.join().is_err() && result.is_ok() {
+            result = Err(DopplerError::WorkerPanicked(thread_id));
+        }
+    }
+    // Workers only stop on their own once a check has asked them to, which
+    // a finished run pairs with cancelling the token, or once they have been
+    // retired
+    if result.is_ok() && !shutdown.is_cancelled() {
+        result = Err(DopplerError::WorkersRetired);
+    }
+    result
+}
+
+fn run_rayon<F>(
+    config: &WorkerConfig,
+    stats: &StatsCollector,
+    shutdown: &ShutdownToken,
+    check: &F,
+) -> Result<(), DopplerError>
+where
+    F: Fn(usize, Candidate) -> ControlFlow<()> + Send + Sync,
+{
+    let (num_threads, derivation) = (config.num_threads, config.derivation);
+    // One set of buffers per pool thread, kept across rounds; a thread only
+    // ever locks its own
+    let buffers: Vec<_> = BatchBuffers::for_workers(config)?.into_iter().map(Mutex::new).collect();
+    // Pool threads whose batches kept panicking; their share of each round
+    // is skipped
+    let retired: Vec<_> = (0..num_threads).map(|_| AtomicBool::new(false)).collect();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(|thread_id| format!("worker-{thread_id}"))
+        .build()
+        .map_err(|e| DopplerError::ThreadPool(e.to_string()))?;
+
+    pool.install(|| {
+        // Each round hands every pool thread a few batches so that work can
+        // be stolen from threads that fall behind
+        let batches_per_round = num_threads * 4;
+        while !shutdown.is_cancelled() {
+            if retired.iter().all(|retired| retired.load(Ordering::Relaxed)) {
+                return Err(DopplerError::WorkersRetired);
+            }
+            let round = (0..batches_per_round).into_par_iter().try_for_each(|_| {
+                let worker = rayon::current_thread_index().unwrap_or(0);
+                if shutdown.is_cancelled() || retired[worker].load(Ordering::Relaxed) {
+                    return ControlFlow::Continue(());
+                }
+                let mut buffers = buffers[worker].lock().unwrap_or_else(PoisonError::into_inner);
+                let mut batch_attempts = 0u64;
+                let mut result = ControlFlow::Continue(());
+
+                let healthy = run_supervised(worker, &mut batch_attempts, |batch_attempts| {
+                    result = run_batch(worker, &mut buffers, derivation, check, batch_attempts);
+                });
+                if !healthy {
+                    retired[worker].store(true, Ordering::Relaxed);
+                }
+
+                stats.record_attempts(worker, batch_attempts);
+                result
+            });
+            if round.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Runs a batch of work on the current thread, restarting it if it panics.
+///
+/// The attempt counter lives outside the batch so attempts made before a
+/// panic are still reported. A worker that keeps panicking is retired after
+/// `MAX_WORKER_RESTARTS` restarts within one batch, in which case this
+/// returns false, and the remaining workers carry on.
+fn run_supervised<F>(thread_id: usize, local_attempts: &mut u64, mut work: F) -> bool
+where
+    F: FnMut(&mut u64),
+{
+    let mut restarts = 0;
+    while let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| work(local_attempts))) {
+        let cause = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_string());
+
+        if restarts == MAX_WORKER_RESTARTS {
+            eprintln!("Worker {thread_id} panicked: {cause}; giving up after {restarts} restarts");
+            return false;
+        }
+        restarts += 1;
+        eprintln!("Worker {thread_id} panicked: {cause}; restarting ({restarts}/{MAX_WORKER_RESTARTS})");
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    fn config(scheduler: Scheduler) -> WorkerConfig {
+        WorkerConfig { scheduler, num_threads: 2, batch_size: 16, derivation: None, entropy: Entropy::default() }
+    }
+
+    #[test]
+    fn a_run_whose_workers_all_retire_fails() {
+        for scheduler in [Scheduler::Threads, Scheduler::Rayon] {
+            let stats = Arc::new(StatsCollector::new(2));
+            let result = run_workers(&config(scheduler), &stats, &ShutdownToken::new(), |_, _| panic!("broken check"));
+            assert!(matches!(result, Err(DopplerError::WorkersRetired)), "{scheduler:?}: {result:?}");
+        }
+    }
+
+    #[test]
+    fn a_run_stopped_by_its_check_succeeds() {
+        for scheduler in [Scheduler::Threads, Scheduler::Rayon] {
+            let stats = Arc::new(StatsCollector::new(2));
+            let shutdown = ShutdownToken::new();
+            let seen = Arc::new(AtomicU64::new(0));
+            let check = {
+                let (shutdown, seen) = (shutdown.clone(), Arc::clone(&seen));
+                move |_, _| {
+                    if seen.fetch_add(1, Ordering::Relaxed) >= 100 {
+                        shutdown.cancel();
+                        return ControlFlow::Break(());
+                    }
+                    ControlFlow::Continue(())
+                }
+            };
+            run_workers(&config(scheduler), &stats, &shutdown, check).unwrap();
+            assert!(stats.summary().total_attempts > 100, "{scheduler:?}");
+        }
+    }
+}
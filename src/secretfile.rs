@@ -0,0 +1,81 @@
+//! Files holding secrets, such as keypairs.
+//!
+//! They are created readable by their owner only, as `solana-keygen` does,
+//! rather than with the umask's usual 0644 and tightened afterwards, so
+//! there is no moment at which another user could open them.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+/// Creates `path`, which must not exist yet, readable by its owner only.
+pub fn create(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Writes `contents` to a new file at `path` readable by its owner only.
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    create(path)?.write_all(contents)
+}
+
+/// Writes `contents` to `path` readable by its owner only, replacing any
+/// file already there. The new file is written next to it first and
+/// renamed into place, so `path` never holds a partial secret.
+pub fn replace(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    match fs::remove_file(&temporary) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let written = create(&temporary).and_then(|mut file| file.write_all(contents).and_then(|()| file.sync_all()));
+    match written.and_then(|()| fs::rename(&temporary, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&temporary);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn mode(path: &Path) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn write_creates_owner_only_files_and_never_overwrites() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key.json");
+        write(&path, b"[1,2,3]").unwrap();
+        #[cfg(unix)]
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(write(&path, b"[4]").unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"[1,2,3]");
+    }
+
+    #[test]
+    fn replace_swaps_in_an_owner_only_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("id.json");
+        fs::write(&path, b"old").unwrap();
+        replace(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        #[cfg(unix)]
+        assert_eq!(mode(&path), 0o600);
+        assert!(!dir.path().join("id.json.tmp").exists());
+    }
+}
@@ -0,0 +1,242 @@
+//! `grind-seed`: vanity stake and nonce accounts under an existing key.
+//!
+//! Stake and durable nonce accounts can live at an address derived from a
+//! base key and a seed string (`create_with_seed`), so a recognizable
+//! address only takes a search over seeds: one SHA-256 per attempt instead
+//! of a key generation, and nothing secret to store.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use doppler_keygen::derive::{self, MAX_SEED_LEN};
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::imm32;
+use doppler_keygen::pattern::BatchPattern;
+use doppler_keygen::shutdown::ShutdownToken;
+use doppler_keygen::stats::StatsCollector;
+
+use crate::cli_error::CliError;
+
+/// Characters seeds are drawn from, so they can be typed on a command line.
+const SEED_ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Seeds tried per batch; counters are only updated once per batch.
+const SEED_BATCH: usize = 1_024;
+
+/// The kind of account created at the derived address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedAccount {
+    Stake,
+    Nonce,
+}
+
+impl SeedAccount {
+    pub fn parse(value: &str) -> Result<SeedAccount, String> {
+        match value {
+            "stake" => Ok(SeedAccount::Stake),
+            "nonce" => Ok(SeedAccount::Nonce),
+            _ => Err(format!("Unknown account type '{value}' (expected stake or nonce)")),
+        }
+    }
+
+    /// The program that owns the account, which is part of its address.
+    fn owner(&self) -> &'static [u8; 32] {
+        match self {
+            SeedAccount::Stake => &derive::STAKE_PROGRAM_ID,
+            SeedAccount::Nonce => &derive::SYSTEM_PROGRAM_ID,
+        }
+    }
+
+    /// The Solana CLI command that creates the account with `seed`. The
+    /// base keypair signs and funds it.
+    pub fn command(&self, seed: &str) -> String {
+        let subcommand = match self {
+            SeedAccount::Stake => "create-stake-account",
+            SeedAccount::Nonce => "create-nonce-account",
+        };
+        format!("solana {subcommand} <BASE_KEYPAIR> <AMOUNT> --seed {seed}")
+    }
+}
+
+/// What to search for.
+#[derive(Debug, Clone)]
+pub struct SeedSearch {
+    pub base: [u8; 32],
+    pub account: SeedAccount,
+    /// Characters in each generated seed, at most `MAX_SEED_LEN`
+    pub seed_len: usize,
+    /// Patterns and how many seeds to find for each; an empty pattern
+    /// accepts any imm32 compatible address
+    pub patterns: Vec<BatchPattern>,
+    pub ignore_case: bool,
+}
+
+/// A seed whose derived address satisfied the search.
+#[derive(Debug, Clone)]
+pub struct SeedMatch {
+    pub seed: String,
+    /// The base58 derived address
+    pub address: String,
+    /// The first segment that forms a valid 32-bit immediate
+    pub segment: usize,
+    pub pattern_index: usize,
+    /// Which of the pattern's seeds this is, counting from 1
+    pub pattern_number: usize,
+}
+
+/// Searches seeds on `num_threads` workers until every pattern has its
+/// seeds or `shutdown` is cancelled, calling `on_match` for each seed found.
+pub fn run<F>(
+    search: &SeedSearch,
+    num_threads: usize,
+    stats: &Arc<StatsCollector>,
+    shutdown: &ShutdownToken,
+    keys_found: &AtomicUsize,
+    on_match: F,
+) -> Result<Vec<SeedMatch>, CliError>
+where
+    F: Fn(&SeedMatch) + Sync,
+{
+    let total_count: usize = search.patterns.iter().map(|p| p.count).sum();
+    let found_per_pattern = Mutex::new(vec![0usize; search.patterns.len()]);
+    let matches = Mutex::new(Vec::new());
+
+    // Each worker draws from its own stream so none repeats another's
+    // seeds. The streams are seeded up front, where a failing OS RNG can
+    // still be reported
+    let rng_seeds = (0..num_threads)
+        .map(|_| {
+            let mut rng_seed = [0u8; 32];
+            getrandom::getrandom(&mut rng_seed)
+                .map_err(|e| DopplerError::Entropy(format!("OS random number generator failed: {e}")))?;
+            Ok(rng_seed)
+        })
+        .collect::<Result<Vec<_>, CliError>>()?;
+
+    let worker = |thread_id: usize, rng_seed: [u8; 32]| {
+        let mut rng = ChaCha20Rng::from_seed(rng_seed);
+        let base_hasher = Sha256::new().chain_update(search.base);
+        let mut seeds = vec![[0u8; MAX_SEED_LEN]; SEED_BATCH];
+        let mut addresses = vec![[0u8; 32]; SEED_BATCH];
+        let mut masks = vec![0u8; SEED_BATCH];
+
+        while !shutdown.is_cancelled() {
+            for (seed, address) in seeds.iter_mut().zip(&mut addresses) {
+                let seed = &mut seed[..search.seed_len];
+                draw_seed(&mut rng, seed);
+                *address = seed_address(&base_hasher, seed, search.account.owner());
+            }
+            imm32::imm32_masks(&addresses, &mut masks);
+            stats.record_attempts(thread_id, SEED_BATCH as u64);
+
+            for ((seed, address), &mask) in seeds.iter().zip(&addresses).zip(&masks) {
+                let Some(segment) = imm32::first_segment(mask) else {
+                    continue;
+                };
+                let address = bs58::encode(address).into_string();
+
+                let mut found = found_per_pattern.lock().unwrap_or_else(PoisonError::into_inner);
+                let matched = search.patterns.iter().enumerate().find(|(index, batch_pattern)| {
+                    found[*index] < batch_pattern.count
+                        && batch_pattern.pattern.matches_with_case(&address, search.ignore_case)
+                });
+                let Some((pattern_index, _)) = matched else {
+                    continue;
+                };
+                found[pattern_index] += 1;
+                let seed_match = SeedMatch {
+                    seed: String::from_utf8_lossy(&seed[..search.seed_len]).into_owned(),
+                    address,
+                    segment,
+                    pattern_index,
+                    pattern_number: found[pattern_index],
+                };
+                drop(found);
+
+                on_match(&seed_match);
+                matches.lock().unwrap_or_else(PoisonError::into_inner).push(seed_match);
+                if keys_found.fetch_add(1, Ordering::Relaxed) + 1 >= total_count {
+                    shutdown.cancel();
+                }
+            }
+        }
+    };
+
+    thread::scope(|scope| {
+        let handles = rng_seeds
+            .into_iter()
+            .enumerate()
+            .map(|(thread_id, rng_seed)| {
+                thread::Builder::new()
+                    .name(format!("worker-{thread_id}"))
+                    .spawn_scoped(scope, move || worker(thread_id, rng_seed))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                shutdown.cancel();
+                DopplerError::SpawnThread(e)
+            })?;
+
+        let mut result = Ok(());
+        for (thread_id, handle) in handles.into_iter().enumerate() {
+            if handle.join().is_err() && result.is_ok() {
+                shutdown.cancel();
+                result = Err(DopplerError::WorkerPanicked(thread_id));
+            }
+        }
+        result
+    })?;
+
+    Ok(matches.into_inner().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Fills `seed` with random characters from `SEED_ALPHABET`.
+fn draw_seed(rng: &mut ChaCha20Rng, seed: &mut [u8]) {
+    rng.fill_bytes(seed);
+    for byte in seed.iter_mut() {
+        *byte = SEED_ALPHABET[*byte as usize % SEED_ALPHABET.len()];
+    }
+}
+
+/// The address `create_with_seed` gives for `seed`, where `base_hasher`
+/// has already absorbed the base key.
+fn seed_address(base_hasher: &Sha256, seed: &[u8], owner: &[u8; 32]) -> [u8; 32] {
+    base_hasher.clone().chain_update(seed).chain_update(owner).finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_pubkey::Pubkey;
+
+    /// A full search needs about 2^30 hashes per imm32 compatible address,
+    /// so this checks the seeds and addresses the workers compute instead.
+    #[test]
+    fn seed_addresses_match_create_with_seed() {
+        let base = [9; 32];
+        let base_hasher = Sha256::new().chain_update(base);
+        let mut rng = ChaCha20Rng::from_seed([1; 32]);
+
+        for account in [SeedAccount::Stake, SeedAccount::Nonce] {
+            for seed_len in [1, 16, MAX_SEED_LEN] {
+                let mut seed = vec![0; seed_len];
+                draw_seed(&mut rng, &mut seed);
+                let seed = String::from_utf8(seed).unwrap();
+                assert!(seed.bytes().all(|byte| SEED_ALPHABET.contains(&byte)));
+
+                let expected = Pubkey::create_with_seed(
+                    &Pubkey::new_from_array(base),
+                    &seed,
+                    &Pubkey::new_from_array(*account.owner()),
+                )
+                .unwrap();
+                assert_eq!(seed_address(&base_hasher, seed.as_bytes(), account.owner()), expected.to_bytes());
+                assert_eq!(derive::create_with_seed(&base, seed.as_bytes(), account.owner()), expected.to_bytes());
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! `selftest`: statistical checks of the key stream on this machine.
+//!
+//! A sample of candidates is generated the way a grind generates them and
+//! checked for the flaws a broken entropy source or a miscompiled matcher
+//! would show: skewed seed or public key bytes, too many or too few one
+//! bits, segments matching more or less often than theory says, and imm32
+//! masks that disagree with the scalar definition.
+
+use core::ops::ControlFlow;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use doppler_keygen::entropy::Entropy;
+use doppler_keygen::error::DopplerError;
+use doppler_keygen::imm32;
+use doppler_keygen::scheduler::{self, Candidate, Scheduler, WorkerConfig, DEFAULT_BATCH_SIZE};
+use doppler_keygen::shutdown::ShutdownToken;
+use doppler_keygen::stats::StatsCollector;
+
+use crate::cli_error::CliError;
+
+/// Default number of candidates sampled.
+pub const DEFAULT_SAMPLES: u64 = 1_000_000;
+
+/// A statistic further than this many standard deviations from its
+/// expected value fails. Each check fails a sound source about once in
+/// 15,000 runs, so one of the four statistical checks about once in 4,000.
+const MAX_Z: f64 = 4.0;
+
+/// Synthetic keys fed straight to the matcher, with planted matches.
+const PLANTED_KEYS: usize = 4_096;
+
+/// Counts gathered by one worker.
+#[derive(Clone)]
+struct Tally {
+    candidates: u64,
+    seed_bytes: [u64; 256],
+    pubkey_bytes: [u64; 256],
+    seed_ones: u64,
+    /// Segments whose top byte is the sign extension of bit 31
+    sign_bytes: u64,
+    /// Candidates whose imm32 mask disagrees with the scalar test
+    mask_mismatches: u64,
+}
+
+impl Default for Tally {
+    fn default() -> Tally {
+        Tally {
+            candidates: 0,
+            seed_bytes: [0; 256],
+            pubkey_bytes: [0; 256],
+            seed_ones: 0,
+            sign_bytes: 0,
+            mask_mismatches: 0,
+        }
+    }
+}
+
+impl Tally {
+    fn add(&mut self, candidate: &Candidate) {
+        self.candidates += 1;
+        for &byte in &candidate.seed {
+            self.seed_bytes[usize::from(byte)] += 1;
+            self.seed_ones += u64::from(byte.count_ones());
+        }
+        for &byte in &candidate.pubkey {
+            self.pubkey_bytes[usize::from(byte)] += 1;
+        }
+        for segment in candidate.address.chunks(8) {
+            let sign = if segment[3] & 0x80 != 0 { 0xFF } else { 0x00 };
+            self.sign_bytes += u64::from(segment[7] == sign);
+        }
+        if scalar_mask(&candidate.address) != candidate.imm32_mask {
+            self.mask_mismatches += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &Tally) {
+        self.candidates += other.candidates;
+        for (total, count) in self.seed_bytes.iter_mut().zip(&other.seed_bytes) {
+            *total += count;
+        }
+        for (total, count) in self.pubkey_bytes.iter_mut().zip(&other.pubkey_bytes) {
+            *total += count;
+        }
+        self.seed_ones += other.seed_ones;
+        self.sign_bytes += other.sign_bytes;
+        self.mask_mismatches += other.mask_mismatches;
+    }
+}
+
+/// The outcome of one check.
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+fn scalar_mask(address: &[u8; 32]) -> u8 {
+    (0..4).filter(|&segment| imm32::segment_is_imm32_compatible(address, segment)).fold(0, |mask, segment| mask | 1 << segment)
+}
+
+/// Checks a z-score against `MAX_Z`.
+fn z_check(name: &'static str, z: f64, detail: String) -> Check {
+    Check { name, passed: z.abs() <= MAX_Z, detail: format!("{detail}, z = {z:.2}") }
+}
+
+/// Pearson's chi-square test of `counts` against a uniform distribution,
+/// turned into a z-score with the Wilson-Hilferty approximation.
+fn uniformity(name: &'static str, counts: &[u64; 256]) -> Check {
+    let total: u64 = counts.iter().sum();
+    let expected = total as f64 / 256.0;
+    let chi_square: f64 = counts.iter().map(|&count| (count as f64 - expected).powi(2) / expected).sum();
+    let df = 255.0;
+    let z = ((chi_square / df).cbrt() - (1.0 - 2.0 / (9.0 * df))) / (2.0 / (9.0 * df)).sqrt();
+    z_check(name, z, format!("chi-square {chi_square:.1} with 255 degrees of freedom"))
+}
+
+/// A binomial count against its expected probability.
+fn frequency(name: &'static str, hits: u64, trials: u64, probability: f64) -> Check {
+    let expected = trials as f64 * probability;
+    let z = (hits as f64 - expected) / (expected * (1.0 - probability)).sqrt();
+    z_check(name, z, format!("{hits} of {trials}, expected {expected:.0}"))
+}
+
+/// Generates `samples` candidates with `num_threads` workers seeded from
+/// `entropy` and runs every check on them.
+pub fn run(samples: u64, num_threads: usize, entropy: Entropy) -> Result<Vec<Check>, CliError> {
+    let tallies: Arc<Vec<Mutex<Tally>>> = Arc::new((0..num_threads).map(|_| Mutex::new(Tally::default())).collect());
+    let taken = Arc::new(AtomicU64::new(0));
+    let stats = Arc::new(StatsCollector::new(num_threads));
+    let shutdown = ShutdownToken::new();
+
+    let check = {
+        let tallies = Arc::clone(&tallies);
+        let shutdown = shutdown.clone();
+        move |worker: usize, candidate: Candidate| {
+            if taken.fetch_add(1, Ordering::Relaxed) >= samples {
+                shutdown.cancel();
+                return ControlFlow::Break(());
+            }
+            tallies[worker].lock().unwrap_or_else(PoisonError::into_inner).add(&candidate);
+            ControlFlow::Continue(())
+        }
+    };
+    let workers = WorkerConfig {
+        scheduler: Scheduler::Threads,
+        num_threads,
+        batch_size: DEFAULT_BATCH_SIZE,
+        derivation: None,
+        entropy,
+    };
+    scheduler::run_workers(&workers, &stats, &shutdown, check)?;
+
+    let mut tally = Tally::default();
+    for worker in tallies.iter() {
+        tally.merge(&worker.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    let seed_bits = tally.candidates * 256;
+    let ones_z = (tally.seed_ones as f64 - seed_bits as f64 / 2.0) / (seed_bits as f64 / 4.0).sqrt();
+    let mut checks = vec![
+        uniformity("Seed byte distribution", &tally.seed_bytes),
+        z_check("Seed monobit", ones_z, format!("{} one bits of {seed_bits}", tally.seed_ones)),
+        uniformity("Public key byte distribution", &tally.pubkey_bytes),
+        frequency("Segment sign byte frequency", tally.sign_bytes, tally.candidates * 4, 1.0 / 256.0),
+        Check {
+            name: "Imm32 masks of the sample",
+            passed: tally.mask_mismatches == 0,
+            detail: format!("{} of {} disagree with the scalar test", tally.mask_mismatches, tally.candidates),
+        },
+    ];
+    checks.push(planted_matches()?);
+    Ok(checks)
+}
+
+/// Runs the matcher over random keys with imm32 compatible segments planted
+/// in them. Random keys almost never match, so this is the only way to see
+/// the matcher accept anything.
+fn planted_matches() -> Result<Check, CliError> {
+    let mut keys = vec![[0u8; 32]; PLANTED_KEYS];
+    for key in &mut keys {
+        getrandom::getrandom(key).map_err(|e| DopplerError::Entropy(format!("OS random number generator failed: {e}")))?;
+        // The low four bits of the first byte pick the segments to plant
+        let planted = key[0] & 0x0F;
+        for segment in (0..4).filter(|segment| planted & 1 << segment != 0) {
+            let offset = segment * 8;
+            let fill = if key[offset + 3] & 0x80 != 0 { 0xFF } else { 0x00 };
+            key[offset + 4..offset + 8].fill(fill);
+        }
+    }
+    let mut masks = vec![0u8; PLANTED_KEYS];
+    imm32::imm32_masks(&keys, &mut masks);
+
+    let mismatches = keys.iter().zip(&masks).filter(|(key, &mask)| scalar_mask(key) != mask).count();
+    let planted = keys.iter().map(|key| (key[0] & 0x0F).count_ones()).sum::<u32>();
+    Ok(Check {
+        name: "Imm32 masks of planted matches",
+        passed: mismatches == 0,
+        detail: format!("{mismatches} of {PLANTED_KEYS} keys with {planted} planted segments disagree with the scalar test"),
+    })
+}
@@ -0,0 +1,110 @@
+//! Overwrite-then-delete for keypair files.
+//!
+//! Each file is overwritten with random bytes and then zeros, flushed to the
+//! device after every pass, truncated and finally removed. This only helps
+//! where writes land on the same blocks: SSD wear levelling, copy-on-write
+//! and journaling filesystems, snapshots and backups can all keep older
+//! copies of the data, so full-disk encryption remains the real protection.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek as _, SeekFrom, Write as _};
+use std::path::Path;
+
+use crate::cli_error::CliError;
+
+/// Size of the buffer each overwrite pass is written from.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Overwrites `path` with random data and then zeros before removing it.
+pub fn shred_file(path: &Path) -> Result<(), CliError> {
+    let shred_error = |source| CliError::Shred { path: path.to_path_buf(), source };
+
+    let metadata = fs::symlink_metadata(path).map_err(shred_error)?;
+    if !metadata.is_file() {
+        return Err(shred_error(io::Error::new(io::ErrorKind::InvalidInput, "not a regular file")));
+    }
+
+    let mut file = OpenOptions::new().write(true).open(path).map_err(shred_error)?;
+    let len = metadata.len();
+    for random in [true, false] {
+        overwrite(&mut file, len, random).map_err(shred_error)?;
+    }
+    file.set_len(0).and_then(|()| file.sync_all()).map_err(shred_error)?;
+    drop(file);
+
+    fs::remove_file(path).map_err(shred_error)
+}
+
+/// Writes `len` bytes of random data or zeros over the start of `file` and
+/// waits for them to reach the device.
+fn overwrite(file: &mut fs::File, len: u64, random: bool) -> io::Result<()> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = remaining.min(CHUNK_SIZE as u64) as usize;
+        if random {
+            getrandom::getrandom(&mut buffer[..chunk]).map_err(|e| io::Error::other(e.to_string()))?;
+        }
+        file.write_all(&buffer[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shred_error_kind(path: &Path) -> io::ErrorKind {
+        match shred_file(path) {
+            Err(CliError::Shred { path: failed, source }) => {
+                assert_eq!(failed, path);
+                source.kind()
+            }
+            other => panic!("expected a shred error for {}, got {other:?}", path.display()),
+        }
+    }
+
+    #[test]
+    fn shredded_files_are_overwritten_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("id.json");
+        // Spans several overwrite chunks
+        fs::write(&path, vec![0xa5; CHUNK_SIZE * 2 + 7]).unwrap();
+        // A second name for the same data outlives the shredded one, and
+        // shows what was left of it
+        let link = dir.path().join("link.json");
+        fs::hard_link(&path, &link).unwrap();
+        let other = dir.path().join("other.json");
+        fs::write(&other, "kept").unwrap();
+
+        shred_file(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read(&link).unwrap(), b"");
+        assert_eq!(fs::read_to_string(&other).unwrap(), "kept");
+    }
+
+    #[test]
+    fn failures_name_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(shred_error_kind(&dir.path().join("missing.json")), io::ErrorKind::NotFound);
+        assert_eq!(shred_error_kind(dir.path()), io::ErrorKind::InvalidInput);
+        assert!(dir.path().exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_refused_without_touching_their_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("id.json");
+        fs::write(&target, "secret").unwrap();
+        let link = dir.path().join("link.json");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        assert_eq!(shred_error_kind(&link), io::ErrorKind::InvalidInput);
+        assert!(link.exists());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "secret");
+    }
+}
@@ -0,0 +1,51 @@
+//! Cooperative shutdown signal shared by the threads of a grind run.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::Duration;
+
+/// A cloneable token that tells every thread of a run to stop.
+///
+/// Workers poll [`ShutdownToken::is_cancelled`] in their hot loop, while
+/// periodic threads such as the progress reporter sleep in
+/// [`ShutdownToken::wait_timeout`] so they wake immediately on shutdown.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl ShutdownToken {
+    pub fn new() -> ShutdownToken {
+        ShutdownToken::default()
+    }
+
+    /// Signals shutdown and wakes every thread waiting on the token.
+    pub fn cancel(&self) {
+        let _guard = self.inner.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.inner.cancelled.store(true, Ordering::Release);
+        self.inner.condvar.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Sleeps for up to `timeout`, returning true as soon as shutdown is
+    /// signalled or false if the timeout elapsed first.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let guard = self.inner.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let _guard = self
+            .inner
+            .condvar
+            .wait_timeout_while(guard, timeout, |_| !self.is_cancelled())
+            .unwrap_or_else(PoisonError::into_inner);
+        self.is_cancelled()
+    }
+}
@@ -0,0 +1,541 @@
+//! `simulate`: runs the generated comparison code in an sBPF interpreter.
+//!
+//! The assembly `address` and `address-set` print is assembled to sBPF
+//! bytecode, wrapped in a small program that loads a key and reports
+//! whether the check accepted it, and executed against each admin key and
+//! mutants of it with each byte in turn flipped. Every admin must be
+//! accepted and every mutant rejected; anything else is a generator bug,
+//! such as a segment comparison left out, that would otherwise surface only
+//! once the program is deployed. Coverage is the share of the key's bytes
+//! whose mutants are all rejected. Only the instructions the generated code uses
+//! are supported.
+
+use std::collections::HashMap;
+
+use crate::codegen;
+
+/// Where the input region, holding the key, is mapped.
+const INPUT_START: u64 = 0x4_0000_0000;
+
+/// Instructions a run may execute before it is stopped.
+const MAX_INSTRUCTIONS: u64 = 4096;
+
+/// Offsets of the key's segments in the input, as the surrounding program
+/// would define them.
+const OFFSETS: &str = ".equ ADMIN_KEY_0, 0\n.equ ADMIN_KEY_1, 8\n.equ ADMIN_KEY_2, 16\n.equ ADMIN_KEY_3, 24\n";
+
+/// The program around a check starting at its first instruction: it
+/// returns 1 when the check falls through or jumps to `accept`, and 0 when
+/// it jumps to `reject`.
+fn wrap(check: &str, accept: &str, reject: &str) -> String {
+    format!("{OFFSETS}{check}\n{accept}:\n  mov64 r0, 1\n  exit\n{reject}:\n  mov64 r0, 0\n  exit\n")
+}
+
+/// A generated check, as a program the interpreter runs.
+#[derive(Debug, Clone)]
+pub struct Routine {
+    pub name: String,
+    /// The keys the check must accept
+    pub admins: Vec<[u8; 32]>,
+    pub source: String,
+}
+
+/// The routines `address` and `address-set` generate for `keys`: each
+/// key's own check, jump-based and constant-time, and with several keys the
+/// set check both ways.
+pub fn routines(keys: &[[u8; 32]]) -> Vec<Routine> {
+    let mut routines = Vec::new();
+    for key in keys {
+        let pubkey = bs58::encode(key).into_string();
+        let constants = codegen::constants_asm("EXPECTED_ADMIN_KEY", key);
+        for (mode, code) in [("jumps", codegen::address_asm(key)), ("constant time", codegen::constant_time_asm(key))] {
+            routines.push(Routine {
+                name: format!("address {pubkey} ({mode})"),
+                admins: vec![*key],
+                source: wrap(&format!("{constants}{code}"), "is_admin", "abort"),
+            });
+        }
+    }
+    if keys.len() > 1 {
+        for (mode, constant_time) in [("jumps", false), ("constant time", true)] {
+            routines.push(Routine {
+                name: format!("address-set of {} keys ({mode})", keys.len()),
+                admins: keys.to_vec(),
+                source: wrap(&codegen::address_set_asm(keys, constant_time), "is_admin", "not_admin"),
+            });
+        }
+    }
+    routines
+}
+
+/// A register, `r0`-`r10`.
+fn register(operand: &str) -> Option<u8> {
+    operand.strip_prefix('r')?.parse().ok().filter(|&register| register <= 10)
+}
+
+/// A number, or a constant defined with `.equ`, as its 64 bits.
+fn value(operand: &str, constants: &HashMap<String, i128>) -> Result<i128, String> {
+    let (negative, digits) = match operand.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, operand),
+    };
+    let parsed = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(i128::from),
+        None => digits.parse::<u64>().ok().map(i128::from),
+    };
+    match parsed {
+        Some(number) => Ok(if negative { -number } else { number }),
+        None if !negative => constants.get(operand).copied().ok_or_else(|| format!("unknown constant '{operand}'")),
+        None => Err(format!("invalid number '{operand}'")),
+    }
+}
+
+/// An immediate operand: a sign-extended 32-bit value, given either signed
+/// or as its 32 bits.
+fn imm32(operand: &str, constants: &HashMap<String, i128>) -> Result<i32, String> {
+    match value(operand, constants)? {
+        number if (0..=i128::from(u32::MAX)).contains(&number) => Ok(number as u32 as i32),
+        number => i32::try_from(number).map_err(|_| format!("'{operand}' does not fit a 32-bit immediate")),
+    }
+}
+
+/// One 8-byte instruction slot.
+fn encode(opcode: u8, dst: u8, src: u8, offset: i16, imm: i32) -> [u8; 8] {
+    let mut slot = [opcode, src << 4 | dst, 0, 0, 0, 0, 0, 0];
+    slot[2..4].copy_from_slice(&offset.to_le_bytes());
+    slot[4..8].copy_from_slice(&imm.to_le_bytes());
+    slot
+}
+
+/// ALU opcodes with an immediate source; `| 0x08` takes a register.
+fn alu_opcode(mnemonic: &str) -> Option<u8> {
+    Some(match mnemonic {
+        "add64" => 0x07,
+        "sub64" => 0x17,
+        "or64" => 0x47,
+        "and64" => 0x57,
+        "lsh64" => 0x67,
+        "rsh64" => 0x77,
+        "xor64" => 0xa7,
+        "mov64" => 0xb7,
+        _ => return None,
+    })
+}
+
+/// Conditional jump opcodes with an immediate operand; `| 0x08` compares
+/// with a register.
+fn jump_opcode(mnemonic: &str) -> Option<u8> {
+    Some(match mnemonic {
+        "jeq" => 0x15,
+        "jne" => 0x55,
+        _ => return None,
+    })
+}
+
+/// Assembles `source` to sBPF bytecode. Lines hold a label (`name:`), an
+/// `.equ NAME, VALUE` directive or one instruction; `;` starts a comment.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut constants = HashMap::new();
+    let mut labels = HashMap::new();
+    let mut instructions = Vec::new();
+    let mut slot = 0usize;
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        let error = |reason: String| format!("line {}: {reason}: {line}", number + 1);
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            if labels.insert(label.to_string(), slot).is_some() {
+                return Err(error(format!("label '{label}' is defined twice")));
+            }
+        } else if let Some(directive) = line.strip_prefix(".equ") {
+            let (name, operand) = directive.split_once(',').ok_or_else(|| error("expected .equ NAME, VALUE".to_string()))?;
+            constants.insert(name.trim().to_string(), value(operand.trim(), &constants).map_err(error)?);
+        } else {
+            let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let operands: Vec<String> = operands.split(',').map(|operand| operand.trim().to_string()).filter(|o| !o.is_empty()).collect();
+            instructions.push((number + 1, line.to_string(), mnemonic.to_string(), operands, slot));
+            slot += if mnemonic == "lddw" { 2 } else { 1 };
+        }
+    }
+
+    let mut bytecode = Vec::with_capacity(slot * 8);
+    for (number, line, mnemonic, operands, slot) in instructions {
+        let error = |reason: String| format!("line {number}: {reason}: {line}");
+        let operand = |index: usize| operands.get(index).map(String::as_str).ok_or_else(|| error("missing operand".to_string()));
+        let dst = || operand(0).and_then(|operand| register(operand).ok_or_else(|| error(format!("'{operand}' is not a register"))));
+        let target = |index: usize| -> Result<i16, String> {
+            let label = operand(index)?;
+            let target = *labels.get(label).ok_or_else(|| error(format!("unknown label '{label}'")))?;
+            i16::try_from(target as i64 - slot as i64 - 1).map_err(|_| error(format!("label '{label}' is out of reach")))
+        };
+        match mnemonic.as_str() {
+            "exit" => bytecode.extend(encode(0x95, 0, 0, 0, 0)),
+            "ja" => bytecode.extend(encode(0x05, 0, 0, target(0)?, 0)),
+            "lddw" => {
+                let number = value(operand(1)?, &constants).map_err(error)?;
+                let bits = u64::try_from(number)
+                    .or_else(|_| i64::try_from(number).map(|number| number as u64))
+                    .map_err(|_| error(format!("'{}' does not fit 64 bits", operands[1])))?;
+                bytecode.extend(encode(0x18, dst()?, 0, 0, bits as u32 as i32));
+                bytecode.extend(encode(0x00, 0, 0, 0, (bits >> 32) as u32 as i32));
+            }
+            "ldxdw" => {
+                let memory = operand(1)?
+                    .strip_prefix('[')
+                    .and_then(|memory| memory.strip_suffix(']'))
+                    .ok_or_else(|| error("expected [rN+OFFSET]".to_string()))?;
+                let (base, offset) = match memory.find(['+', '-']) {
+                    Some(sign) => {
+                        let offset = value(memory[sign + 1..].trim(), &constants).map_err(error)?;
+                        (memory[..sign].trim(), if &memory[sign..=sign] == "-" { -offset } else { offset })
+                    }
+                    None => (memory.trim(), 0),
+                };
+                let src = register(base).ok_or_else(|| error(format!("'{base}' is not a register")))?;
+                let offset = i16::try_from(offset).map_err(|_| error("offset does not fit 16 bits".to_string()))?;
+                bytecode.extend(encode(0x79, dst()?, src, offset, 0));
+            }
+            mnemonic => {
+                let (opcode, branch) = match (alu_opcode(mnemonic), jump_opcode(mnemonic)) {
+                    (Some(opcode), _) => (opcode, None),
+                    (_, Some(opcode)) => (opcode, Some(target(2)?)),
+                    _ => return Err(error(format!("unsupported instruction '{mnemonic}'"))),
+                };
+                let source = operand(1)?;
+                let encoded = match register(source) {
+                    Some(src) => encode(opcode | 0x08, dst()?, src, branch.unwrap_or(0), 0),
+                    None => encode(opcode, dst()?, 0, branch.unwrap_or(0), imm32(source, &constants).map_err(error)?),
+                };
+                bytecode.extend(encoded);
+            }
+        }
+    }
+    Ok(bytecode)
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Execution {
+    /// Whether the check accepted the key
+    pub accepted: bool,
+    pub instructions: u64,
+}
+
+/// Runs `bytecode` with `r1` pointing at `key` in the input region.
+pub fn run(bytecode: &[u8], key: &[u8; 32]) -> Result<Execution, String> {
+    let mut registers = [0u64; 11];
+    registers[1] = INPUT_START;
+    let mut pc = 0usize;
+    let mut instructions = 0;
+    loop {
+        let slot: &[u8; 8] = bytecode
+            .get(pc * 8..pc * 8 + 8)
+            .and_then(|slot| slot.try_into().ok())
+            .ok_or_else(|| format!("ran past the end of the program at instruction {pc}"))?;
+        instructions += 1;
+        if instructions > MAX_INSTRUCTIONS {
+            return Err(format!("did not exit within {MAX_INSTRUCTIONS} instructions"));
+        }
+        let (opcode, dst, src) = (slot[0], usize::from(slot[1] & 0x0f), usize::from(slot[1] >> 4));
+        // The register fields hold 0-15, but only r0-r10 exist
+        if let Some(register) = [dst, src].into_iter().find(|&register| register >= registers.len()) {
+            return Err(format!("unsupported register r{register} at instruction {pc}"));
+        }
+        let offset = i16::from_le_bytes([slot[2], slot[3]]);
+        let imm = i32::from_le_bytes([slot[4], slot[5], slot[6], slot[7]]);
+        let source = if opcode & 0x08 != 0 { registers[src] } else { i64::from(imm) as u64 };
+        let jump = |pc: usize| pc.checked_add_signed(isize::from(offset) + 1).ok_or_else(|| format!("jump before the start at instruction {pc}"));
+        pc = match opcode {
+            0x95 => return Ok(Execution { accepted: registers[0] != 0, instructions }),
+            0x05 => jump(pc)?,
+            0x15 | 0x1d if registers[dst] == source => jump(pc)?,
+            0x55 | 0x5d if registers[dst] != source => jump(pc)?,
+            0x15 | 0x1d | 0x55 | 0x5d => pc + 1,
+            0x18 => {
+                let high = bytecode.get(pc * 8 + 12..pc * 8 + 16).ok_or_else(|| format!("truncated lddw at instruction {pc}"))?;
+                let high = u32::from_le_bytes(high.try_into().expect("four bytes"));
+                registers[dst] = u64::from(high) << 32 | u64::from(imm as u32);
+                pc + 2
+            }
+            0x79 => {
+                let address = registers[src].wrapping_add_signed(i64::from(offset));
+                let start = address.wrapping_sub(INPUT_START);
+                let bytes = usize::try_from(start)
+                    .ok()
+                    .and_then(|start| key.get(start..start.checked_add(8)?))
+                    .ok_or_else(|| format!("read of 8 bytes at 0x{address:x} is outside the key, at instruction {pc}"))?;
+                registers[dst] = u64::from_le_bytes(bytes.try_into().expect("eight bytes"));
+                pc + 1
+            }
+            _ => {
+                registers[dst] = match opcode & !0x08 {
+                    0x07 => registers[dst].wrapping_add(source),
+                    0x17 => registers[dst].wrapping_sub(source),
+                    0x47 => registers[dst] | source,
+                    0x57 => registers[dst] & source,
+                    0x67 => registers[dst] << (source & 63),
+                    0x77 => registers[dst] >> (source & 63),
+                    0xa7 => registers[dst] ^ source,
+                    0xb7 => source,
+                    _ => return Err(format!("unsupported opcode 0x{opcode:02x} at instruction {pc}")),
+                };
+                pc + 1
+            }
+        };
+    }
+}
+
+/// Flips applied to every byte of a key: each single bit, which an
+/// immediate compared in place catches, and the whole byte, which for the
+/// top four bytes of a segment only the sign extension of an imm32
+/// immediate or the full 64-bit constant catches.
+const FLIPS: [u8; 9] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0xff];
+
+/// Keys one flipped byte away from `key`, which a correct check must
+/// reject, with the byte each one changes.
+pub fn mutants(key: &[u8; 32]) -> Vec<(usize, [u8; 32])> {
+    let mut mutants = Vec::with_capacity(32 * FLIPS.len());
+    for byte in 0..32 {
+        for flip in FLIPS {
+            let mut mutant = *key;
+            mutant[byte] ^= flip;
+            mutants.push((byte, mutant));
+        }
+    }
+    mutants
+}
+
+/// What running one routine against its admins and their mutants showed.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub name: String,
+    pub admins: usize,
+    /// Instructions the check took to accept each admin it accepted
+    pub accepted: Vec<u64>,
+    pub mutants: usize,
+    pub rejected: usize,
+    /// Bytes whose mutants were all rejected, for every admin: the bytes
+    /// the check really compares
+    pub covered: [bool; 32],
+    pub failures: Vec<String>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// How many of the key's bytes the check covers.
+    pub fn coverage(&self) -> usize {
+        self.covered.iter().filter(|&&covered| covered).count()
+    }
+
+    /// The bytes the check does not cover, as ranges with their segments,
+    /// e.g. `24-27 (segment 3)`.
+    pub fn uncovered(&self) -> Vec<String> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for byte in (0..32).filter(|&byte| !self.covered[byte]) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == byte && *end / 8 == byte / 8 => *end = byte,
+                _ => ranges.push((byte, byte)),
+            }
+        }
+        ranges
+            .into_iter()
+            .map(|(start, end)| {
+                if start == end {
+                    format!("{start} (segment {})", start / 8)
+                } else {
+                    format!("{start}-{end} (segment {})", start / 8)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Assembles and runs `routine`.
+pub fn check(routine: &Routine) -> Report {
+    let mut report = Report {
+        name: routine.name.clone(),
+        admins: routine.admins.len(),
+        accepted: Vec::new(),
+        mutants: 0,
+        rejected: 0,
+        covered: [false; 32],
+        failures: Vec::new(),
+    };
+    let bytecode = match assemble(&routine.source) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            report.failures.push(format!("does not assemble: {e}"));
+            return report;
+        }
+    };
+    report.covered = [true; 32];
+    for admin in &routine.admins {
+        let pubkey = bs58::encode(admin).into_string();
+        match run(&bytecode, admin) {
+            Ok(execution) if execution.accepted => report.accepted.push(execution.instructions),
+            Ok(_) => report.failures.push(format!("rejected {pubkey}")),
+            Err(e) => report.failures.push(format!("{pubkey}: {e}")),
+        }
+        // Mutants that got through, and the first error, for each byte
+        let mut missed: [(usize, Option<String>); 32] = Default::default();
+        for (byte, mutant) in mutants(admin) {
+            if routine.admins.contains(&mutant) {
+                continue;
+            }
+            report.mutants += 1;
+            match run(&bytecode, &mutant) {
+                Ok(execution) if !execution.accepted => report.rejected += 1,
+                Ok(_) => missed[byte].0 += 1,
+                Err(e) => {
+                    missed[byte].0 += 1;
+                    missed[byte].1.get_or_insert(e);
+                }
+            }
+        }
+        for (byte, (count, error)) in missed.into_iter().enumerate().filter(|(_, (count, _))| *count > 0) {
+            report.covered[byte] = false;
+            report.failures.push(match error {
+                Some(e) => format!("{pubkey} with byte {byte} flipped: {e}"),
+                None => format!("accepted {count} mutant(s) of {pubkey} with byte {byte} (segment {}) flipped", byte / 8),
+            });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A key with a wide segment 0 and 3, a positive imm32 segment 1 and a
+    /// negative imm32 segment 2.
+    fn key() -> [u8; 32] {
+        let mut key: [u8; 32] = std::array::from_fn(|i| (i as u8).wrapping_mul(37).wrapping_add(11));
+        key[8..16].copy_from_slice(&0x1234_5678_u64.to_le_bytes());
+        key[16..24].copy_from_slice(&(-0x7654_3210_i64).to_le_bytes());
+        key
+    }
+
+    fn program(slots: &[[u8; 8]]) -> Vec<u8> {
+        slots.concat()
+    }
+
+    /// `r0 = 1; exit` then `r0 = 0; exit`, so a jump of 2 rejects.
+    const ACCEPT_REJECT: [[u8; 8]; 4] = [
+        [0xb7, 0x00, 0, 0, 1, 0, 0, 0],
+        [0x95, 0, 0, 0, 0, 0, 0, 0],
+        [0xb7, 0x00, 0, 0, 0, 0, 0, 0],
+        [0x95, 0, 0, 0, 0, 0, 0, 0],
+    ];
+    const ACCEPT_REJECT_ASM: &str = "mov64 r0, 1\nexit\nreject:\nmov64 r0, 0\nexit\n";
+
+    #[test]
+    fn jne_sign_extends_a_negative_imm32() {
+        // ldxdw r2, [r1+16]; jne r2, -0x76543210, +2
+        let mut slots = vec![[0x79, 0x12, 16, 0, 0, 0, 0, 0], [0x55, 0x02, 2, 0, 0xf0, 0xcd, 0xab, 0x89]];
+        slots.extend(ACCEPT_REJECT);
+        let bytecode = program(&slots);
+        assert!(run(&bytecode, &key()).unwrap().accepted);
+
+        // The same low 32 bits with the upper ones clear are a different
+        // 64-bit value
+        let mut zero_extended = key();
+        zero_extended[20..24].fill(0);
+        assert!(!run(&bytecode, &zero_extended).unwrap().accepted);
+
+        // The assembler encodes the immediate the same, signed or as its 32 bits
+        for operand in ["-0x76543210", "0x89abcdf0"] {
+            let source = format!("ldxdw r2, [r1+16]\njne r2, {operand}, reject\n{ACCEPT_REJECT_ASM}");
+            assert_eq!(assemble(&source).unwrap(), bytecode, "{operand}");
+        }
+    }
+
+    #[test]
+    fn lddw_spans_two_slots() {
+        // ldxdw r2, [r1+0]; lddw r3, segment 0; jne r2, r3, +2
+        let segment = u64::from_le_bytes(key()[..8].try_into().unwrap());
+        let (low, high) = ((segment as u32).to_le_bytes(), ((segment >> 32) as u32).to_le_bytes());
+        let mut slots = vec![
+            [0x79, 0x12, 0, 0, 0, 0, 0, 0],
+            [0x18, 0x03, 0, 0, low[0], low[1], low[2], low[3]],
+            [0x00, 0x00, 0, 0, high[0], high[1], high[2], high[3]],
+            [0x5d, 0x32, 2, 0, 0, 0, 0, 0],
+        ];
+        slots.extend(ACCEPT_REJECT);
+        let bytecode = program(&slots);
+        let execution = run(&bytecode, &key()).unwrap();
+        assert!(execution.accepted);
+        // The second slot of lddw is not executed on its own
+        assert_eq!(execution.instructions, 5);
+
+        let mut mutant = key();
+        mutant[7] ^= 0x80;
+        assert!(!run(&bytecode, &mutant).unwrap().accepted);
+
+        // Labels after an lddw count both of its slots
+        let source = format!("ldxdw r2, [r1+0]\nlddw r3, 0x{segment:x}\njne r2, r3, reject\n{ACCEPT_REJECT_ASM}");
+        assert_eq!(assemble(&source).unwrap(), bytecode);
+    }
+
+    #[test]
+    fn registers_above_r10_are_rejected() {
+        // mov64 r11, 1 and mov64 r0, r15
+        for (slot, register) in [([0xb7, 0x0b, 0, 0, 1, 0, 0, 0], 11), ([0xbf, 0xf0, 0, 0, 0, 0, 0, 0], 15)] {
+            let error = run(&program(&[slot, [0x95, 0, 0, 0, 0, 0, 0, 0]]), &key()).unwrap_err();
+            assert_eq!(error, format!("unsupported register r{register} at instruction 0"));
+        }
+    }
+
+    #[test]
+    fn generated_routines_pass() {
+        let mut other = key();
+        other[31] ^= 1;
+        for routine in routines(&[key(), other]) {
+            let report = check(&routine);
+            assert!(report.passed(), "{}: {:?}", routine.name, report.failures);
+            assert_eq!(report.coverage(), 32, "{}", routine.name);
+        }
+    }
+
+    /// The jump-based check of `key()` with segment 3's comparison left out.
+    fn check_without_segment_3() -> Report {
+        let key = key();
+        let code = codegen::address_asm(&key);
+        let segments: Vec<&str> = code.split("\n\n").collect();
+        assert!(segments[3].contains("ADMIN_KEY_3"));
+        let code = [segments[0], segments[1], segments[2]].join("\n\n");
+        let constants = codegen::constants_asm("EXPECTED_ADMIN_KEY", &key);
+        let source = wrap(&format!("{constants}{code}\n"), "is_admin", "abort");
+        check(&Routine { name: "without segment 3".to_string(), admins: vec![key], source })
+    }
+
+    #[test]
+    fn check_fails_when_a_segment_is_left_out() {
+        let report = check_without_segment_3();
+        assert!(!report.passed());
+        assert_eq!(report.accepted.len(), 1);
+        assert_eq!(report.mutants, 288);
+        assert_eq!(report.rejected, 24 * FLIPS.len());
+        let segment_3 = |failure: &String| failure.contains("(segment 3) flipped");
+        assert!(report.failures.iter().all(segment_3), "{:?}", report.failures);
+    }
+
+    #[test]
+    fn reports_the_bytes_a_check_leaves_uncovered() {
+        let report = check_without_segment_3();
+        assert_eq!(report.coverage(), 24);
+        assert_eq!(report.uncovered(), ["24-31 (segment 3)"]);
+
+        let mut covered = [true; 32];
+        for byte in [0, 9, 10, 11, 15, 16] {
+            covered[byte] = false;
+        }
+        let report = Report { covered, ..report };
+        assert_eq!(report.uncovered(), ["0 (segment 0)", "9-11 (segment 1)", "15 (segment 1)", "16 (segment 2)"]);
+    }
+}
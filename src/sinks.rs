@@ -0,0 +1,347 @@
+//! Per-pattern sinks: keys for a batch pattern delivered straight to the
+//! requester's destination.
+//!
+//! Sinks are named tables under `[sinks]` in the config file, and a pattern
+//! picks one with `sink = "NAME"` (or `--sink NAME` after it on the command
+//! line). A sink can:
+//!
+//! - save the pattern's keypair files to its own directory (`dir`)
+//! - encrypt them to the requester's age recipients (`encrypt_to`)
+//! - POST each key to a webhook (`webhook`)
+//! - insert each key into a SQLite table (`sqlite`, `table`)
+//!
+//! Webhooks and tables get the public key, and the keypair only when it is
+//! encrypted to the sink's recipients. The secret never leaves the machine
+//! in the clear.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::json;
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use doppler_keygen::pattern::{BatchPattern, VanityPattern};
+
+use crate::cli_error::CliError;
+use crate::encrypt::AgeRecipients;
+use crate::preflight::{self, OutputPlan};
+use crate::redact;
+use crate::grind::OutputFormat;
+
+/// How long a webhook request may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for another writer to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Table keys are inserted into when a sink names none.
+const DEFAULT_TABLE: &str = "keys";
+
+/// A `[sinks.NAME]` table of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkConfig {
+    /// Directory the pattern's keypair files are saved to instead of the
+    /// output directory
+    pub dir: Option<PathBuf>,
+    /// Comma-separated age recipients the pattern's keypairs are encrypted to
+    pub encrypt_to: Option<String>,
+    /// URL each key is POSTed to as JSON
+    pub webhook: Option<String>,
+    /// SQLite database each key is inserted into
+    pub sqlite: Option<PathBuf>,
+    /// Table of `sqlite` to insert into
+    pub table: Option<String>,
+}
+
+/// An opened sink.
+pub struct Sink {
+    pub name: String,
+    pub dir: Option<PathBuf>,
+    pub encrypt_to: Option<AgeRecipients>,
+    webhook: Option<(ureq::Agent, String)>,
+    database: Option<(Mutex<Connection>, String)>,
+}
+
+/// The sinks a run delivers to, by name.
+pub struct Sinks(BTreeMap<String, Sink>);
+
+impl std::fmt::Debug for Sinks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.0.keys()).finish()
+    }
+}
+
+impl Sinks {
+    /// Opens the sinks in `configs` that `patterns` name, creating their
+    /// directories and tables. Fails if a pattern names a sink that isn't
+    /// configured.
+    pub fn open(configs: &BTreeMap<String, SinkConfig>, patterns: &[BatchPattern]) -> Result<Sinks, CliError> {
+        let mut sinks = BTreeMap::new();
+        for name in patterns.iter().filter_map(|batch_pattern| batch_pattern.sink.as_ref()) {
+            if sinks.contains_key(name) {
+                continue;
+            }
+            let config = configs
+                .get(name)
+                .ok_or_else(|| CliError::Config(format!("Pattern sink '{name}' is not defined under [sinks] in the config file")))?;
+            sinks.insert(name.clone(), Sink::open(name, config)?);
+        }
+        Ok(Sinks(sinks))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Sink> {
+        self.0.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// Names of the sinks that save keypair files themselves.
+    pub fn storing_secrets(&self) -> impl Iterator<Item = &str> {
+        self.0.values().filter(|sink| sink.dir.is_some() || sink.encrypt_to.is_some()).map(|sink| sink.name.as_str())
+    }
+
+    /// Names of the sinks that send keys over the network.
+    pub fn networked(&self) -> impl Iterator<Item = &str> {
+        self.0.values().filter(|sink| sink.webhook.is_some()).map(|sink| sink.name.as_str())
+    }
+}
+
+impl Sink {
+    fn open(name: &str, config: &SinkConfig) -> Result<Sink, CliError> {
+        let sink_error = |reason: String| CliError::Sink { name: name.to_string(), reason };
+        if config.dir.is_none() && config.encrypt_to.is_none() && config.webhook.is_none() && config.sqlite.is_none() {
+            return Err(sink_error("it needs at least one of dir, encrypt_to, webhook or sqlite".to_string()));
+        }
+        if config.table.is_some() && config.sqlite.is_none() {
+            return Err(sink_error("table requires sqlite".to_string()));
+        }
+
+        if let Some(dir) = &config.dir {
+            fs::create_dir_all(dir).map_err(|source| CliError::CreateOutputDir { path: dir.clone(), source })?;
+            preflight::check_output_dir(dir, &OutputPlan { expected_keys: None, files_per_key: 0, no_sync_dirs: false })?;
+        }
+        let encrypt_to = config.encrypt_to.as_deref().map(AgeRecipients::parse).transpose().map_err(sink_error)?;
+        let webhook = config
+            .webhook
+            .as_ref()
+            .map(|url| match url.starts_with("https://") || url.starts_with("http://") {
+                true => Ok((ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(), url.clone())),
+                false => Err(sink_error(format!("webhook '{url}' is not an http:// or https:// URL"))),
+            })
+            .transpose()?;
+        let database = config
+            .sqlite
+            .as_ref()
+            .map(|path| {
+                let table = config.table.clone().unwrap_or_else(|| DEFAULT_TABLE.to_string());
+                open_table(path, &table).map(|connection| (Mutex::new(connection), table)).map_err(sink_error)
+            })
+            .transpose()?;
+
+        Ok(Sink { name: name.to_string(), dir: config.dir.clone(), encrypt_to, webhook, database })
+    }
+
+    /// Sends a saved key to the sink's webhook and table. The keypair is only
+    /// included encrypted to the sink's recipients, and only if `save_secret`.
+    pub fn deliver(
+        &self,
+        keypair: &Keypair,
+        address: &str,
+        segment: usize,
+        pattern: &VanityPattern,
+        save_secret: bool,
+    ) -> Result<(), CliError> {
+        let sink_error = |reason: String| CliError::Sink { name: self.name.clone(), reason };
+        let pubkey = keypair.pubkey().to_string();
+        let encrypted = match (&self.encrypt_to, save_secret) {
+            (Some(recipients), true) => Some(recipients.encrypt(&OutputFormat::Json.encode(keypair))?),
+            _ => None,
+        };
+        let found_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+
+        if let Some((agent, url)) = &self.webhook {
+            let mut body = json!({
+                "event": "key",
+                "sink": self.name,
+                "pubkey": pubkey,
+                "address": address,
+                "pattern": pattern.to_string(),
+                "segment": segment,
+                "found_at": found_at,
+            });
+            if let Some(encrypted) = &encrypted {
+                body["encrypted_keypair"] = json!(BASE64.encode(encrypted));
+            }
+            agent
+                .post(url)
+                .set("Content-Type", "application/json")
+                .send_string(&redact::scrub(&body.to_string()))
+                .map_err(|e| sink_error(format!("webhook {url} failed: {e}")))?;
+        }
+
+        if let Some((connection, table)) = &self.database {
+            connection
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .execute(
+                    &format!(
+                        "INSERT INTO \"{table}\" (pubkey, address, pattern, segment, found_at, encrypted_keypair) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+                    ),
+                    rusqlite::params![pubkey, address, pattern.to_string(), segment as i64, found_at as i64, encrypted],
+                )
+                .map_err(|e| sink_error(format!("insert into {table} failed: {e}")))?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens the SQLite database at `path` and creates `table` in it if needed.
+fn open_table(path: &Path, table: &str) -> Result<Connection, String> {
+    let valid = table.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("invalid table name '{table}' (letters, digits and underscores only)"));
+    }
+    let connection = Connection::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    connection.busy_timeout(BUSY_TIMEOUT).map_err(|e| format!("failed to configure {}: {e}", path.display()))?;
+    connection
+        .execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS \"{table}\" (
+                pubkey TEXT PRIMARY KEY,
+                address TEXT NOT NULL,
+                pattern TEXT NOT NULL,
+                segment INTEGER NOT NULL,
+                found_at INTEGER NOT NULL,
+                encrypted_keypair BLOB
+            )"
+        ))
+        .map_err(|e| format!("failed to create table {table} in {}: {e}", path.display()))?;
+    Ok(connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn pattern(prefix: &str, sink: Option<&str>) -> BatchPattern {
+        BatchPattern {
+            pattern: VanityPattern { prefix: Some(prefix.to_string()), suffix: None },
+            count: 1,
+            found: 0,
+            priority: 0,
+            sink: sink.map(str::to_string),
+        }
+    }
+
+    fn configs(entries: &[(&str, SinkConfig)]) -> BTreeMap<String, SinkConfig> {
+        entries.iter().map(|(name, config)| (name.to_string(), config.clone())).collect()
+    }
+
+    fn open_error(config: SinkConfig) -> String {
+        Sinks::open(&configs(&[("s", config)]), &[pattern("a", Some("s"))]).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn sinks_and_pattern_names_are_read_from_the_config_file() {
+        let config: Config = toml::from_str(
+            r#"
+            patterns = [
+              { prefix = "ali", count = 5, sink = "alice" },
+              { prefix = "bob", count = 2 },
+            ]
+
+            [sinks.alice]
+            dir = "/srv/deliveries/alice"
+            webhook = "https://alice.example/keys"
+
+            [sinks.bob]
+            sqlite = "/srv/deliveries/orders.db"
+            table = "bob_keys"
+            "#,
+        )
+        .unwrap();
+        let patterns = config.patterns.unwrap();
+        assert_eq!(patterns[0].sink.as_deref(), Some("alice"));
+        assert_eq!(patterns[1].sink, None);
+
+        let sinks = config.sinks.unwrap();
+        assert_eq!(sinks["alice"].dir, Some(PathBuf::from("/srv/deliveries/alice")));
+        assert_eq!(sinks["alice"].webhook.as_deref(), Some("https://alice.example/keys"));
+        assert_eq!(sinks["bob"].sqlite, Some(PathBuf::from("/srv/deliveries/orders.db")));
+        assert_eq!(sinks["bob"].table.as_deref(), Some("bob_keys"));
+
+        assert!(toml::from_str::<Config>("[sinks.carol]\npath = \"/srv\"\n").is_err());
+    }
+
+    #[test]
+    fn only_named_sinks_are_opened() {
+        let dir = tempfile::tempdir().unwrap();
+        let team = SinkConfig { dir: Some(dir.path().join("team")), ..SinkConfig::default() };
+        let unused = SinkConfig { dir: Some(dir.path().join("unused")), ..SinkConfig::default() };
+        let patterns = [pattern("a", Some("team")), pattern("b", None)];
+        let sinks = Sinks::open(&configs(&[("team", team), ("unused", unused)]), &patterns).unwrap();
+        assert_eq!(sinks.names().collect::<Vec<_>>(), ["team"]);
+        assert_eq!(sinks.storing_secrets().collect::<Vec<_>>(), ["team"]);
+        assert!(dir.path().join("team").is_dir() && !dir.path().join("unused").exists());
+
+        let error = Sinks::open(&BTreeMap::new(), &[pattern("a", Some("team"))]).unwrap_err().to_string();
+        assert!(error.contains("'team' is not defined under [sinks]"), "{error}");
+    }
+
+    #[test]
+    fn incomplete_sinks_are_refused() {
+        assert!(open_error(SinkConfig::default()).contains("needs at least one of"));
+        let webhook = |url: &str| SinkConfig { webhook: Some(url.to_string()), ..SinkConfig::default() };
+        let table_only = SinkConfig { table: Some("keys".to_string()), ..webhook("https://x.example") };
+        assert!(open_error(table_only).contains("table requires sqlite"));
+        assert!(open_error(webhook("ftp://x.example")).contains("not an http:// or https:// URL"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let bad_table = SinkConfig {
+            sqlite: Some(dir.path().join("keys.db")),
+            table: Some("keys\"; DROP".to_string()),
+            ..SinkConfig::default()
+        };
+        assert!(open_error(bad_table).contains("invalid table name"));
+    }
+
+    #[test]
+    fn keys_are_inserted_into_the_sink_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = dir.path().join("orders.db");
+        let config =
+            SinkConfig { sqlite: Some(database.clone()), table: Some("bob_keys".to_string()), ..SinkConfig::default() };
+        let sinks = Sinks::open(&configs(&[("bob", config)]), &[pattern("b", Some("bob"))]).unwrap();
+
+        let keypair = Keypair::new();
+        let address = keypair.pubkey().to_string();
+        let bob = VanityPattern { prefix: Some("b".to_string()), suffix: None };
+        sinks.get("bob").unwrap().deliver(&keypair, &address, 2, &bob, true).unwrap();
+
+        let connection = Connection::open(&database).unwrap();
+        let (pubkey, segment, encrypted): (String, i64, Option<Vec<u8>>) = connection
+            .query_row("SELECT pubkey, segment, encrypted_keypair FROM bob_keys", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((pubkey, segment), (address, 2));
+        // Without encrypt_to the keypair stays out of the table
+        assert_eq!(encrypted, None);
+    }
+}
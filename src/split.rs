@@ -0,0 +1,201 @@
+//! Shamir secret sharing of found keys.
+//!
+//! With `--split K/N` the 32-byte secret seed of each found key is split into
+//! N shares, any K of which recover it, and each share goes to its own file.
+//! The full secret is never written to disk; `doppler-keygen combine` puts it
+//! back together.
+
+use serde::{Deserialize, Serialize};
+use blahaj::{Share, Sharks};
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli_error::CliError;
+use crate::secretfile;
+
+/// How many shares to split each secret into and how many recover it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitSpec {
+    pub threshold: u8,
+    pub shares: u8,
+}
+
+impl SplitSpec {
+    /// Parses `K/N`, e.g. `3/5`.
+    pub fn parse(value: &str) -> Result<SplitSpec, String> {
+        let invalid = || format!("Invalid split '{value}' (expected THRESHOLD/SHARES, e.g. 3/5)");
+        let (threshold, shares) = value.split_once('/').ok_or_else(invalid)?;
+        let threshold = threshold.parse::<u8>().map_err(|_| invalid())?;
+        let shares = shares.parse::<u8>().map_err(|_| invalid())?;
+
+        if threshold < 2 {
+            return Err(format!("Invalid split '{value}': a threshold below 2 would store the whole secret in every share"));
+        }
+        if threshold > shares {
+            return Err(format!("Invalid split '{value}': the threshold cannot exceed the number of shares"));
+        }
+        Ok(SplitSpec { threshold, shares })
+    }
+}
+
+/// Contents of a share file.
+#[derive(Debug, Serialize, Deserialize)]
+struct ShareFile {
+    /// The base58 public key the share belongs to
+    pubkey: String,
+    threshold: u8,
+    shares: u8,
+    /// Hex encoded share, x coordinate first
+    share: String,
+}
+
+/// Splits the secret of `keypair` and writes one share file per share to
+/// `output_dir`, returning their paths.
+pub fn write_shares(keypair: &Keypair, spec: SplitSpec, output_dir: &Path) -> Result<Vec<PathBuf>, CliError> {
+    let pubkey = keypair.pubkey().to_string();
+    let dealer = Sharks(spec.threshold).dealer(keypair.secret_bytes());
+
+    dealer
+        .take(usize::from(spec.shares))
+        .enumerate()
+        .map(|(index, share)| {
+            let share_file = ShareFile {
+                pubkey: pubkey.clone(),
+                threshold: spec.threshold,
+                shares: spec.shares,
+                share: hex::encode(Vec::from(&share)),
+            };
+            let path = output_dir.join(format!("{pubkey}.share-{}-of-{}.json", index + 1, spec.shares));
+            let contents = serde_json::to_vec_pretty(&share_file).expect("share files always serialize");
+            secretfile::write(&path, &contents).map_err(|source| CliError::WriteKeypair { path: path.clone(), source })?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Recovers a keypair from share files, checking that the shares belong to
+/// the same key and split and that the result matches its public key.
+pub fn combine(paths: &[PathBuf]) -> Result<Keypair, String> {
+    let mut share_files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let share_file: ShareFile =
+            serde_json::from_str(&contents).map_err(|e| format!("{} is not a share file: {e}", path.display()))?;
+        share_files.push(share_file);
+    }
+
+    let Some(first) = share_files.first() else {
+        return Err("No share files given".to_string());
+    };
+    if let Some(other) = share_files.iter().find(|share_file| share_file.pubkey != first.pubkey) {
+        return Err(format!("Shares belong to different keys ({} and {})", first.pubkey, other.pubkey));
+    }
+    // Every share of a split records the same threshold and share count,
+    // and the threshold is what recovery relies on
+    let split = |share_file: &ShareFile| format!("{}/{}", share_file.threshold, share_file.shares);
+    if let Some((other, path)) = share_files
+        .iter()
+        .zip(paths)
+        .find(|(share_file, _)| (share_file.threshold, share_file.shares) != (first.threshold, first.shares))
+    {
+        return Err(format!(
+            "{} is from a {} split of {}, but {} is from a {} split",
+            path.display(),
+            split(other),
+            first.pubkey,
+            paths[0].display(),
+            split(first)
+        ));
+    }
+    SplitSpec::parse(&split(first)).map_err(|e| format!("{}: {e}", paths[0].display()))?;
+
+    let shares = share_files
+        .iter()
+        .zip(paths)
+        .map(|(share_file, path)| {
+            let bytes = hex::decode(&share_file.share).map_err(|e| format!("{}: invalid share: {e}", path.display()))?;
+            Share::try_from(bytes.as_slice()).map_err(|e| format!("{}: invalid share: {e}", path.display()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let secret = Sharks(first.threshold).recover(&shares).map_err(|e| {
+        format!("{e}: {} of {} shares are needed for {}", first.threshold, first.shares, first.pubkey)
+    })?;
+
+    let seed: [u8; 32] = secret
+        .try_into()
+        .map_err(|_| format!("Recovered secret for {} has the wrong length", first.pubkey))?;
+    let keypair = Keypair::new_from_array(seed);
+    if keypair.pubkey().to_string() != first.pubkey {
+        return Err(format!(
+            "Recovered key does not match {}; at least one share is corrupt or from another split",
+            first.pubkey
+        ));
+    }
+    Ok(keypair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_threshold_of_shares_recovers_the_seed() {
+        let seed = [7u8; 32];
+        let dealt: Vec<Share> = Sharks(3).dealer(&seed).take(5).collect();
+        for subset in [[0, 1, 2], [0, 2, 4], [4, 3, 1]] {
+            let shares: Vec<Share> = subset.iter().map(|&index| Share::try_from(Vec::from(&dealt[index]).as_slice()).unwrap()).collect();
+            assert_eq!(Sharks(3).recover(&shares).unwrap(), seed);
+        }
+        assert!(Sharks(3).recover(&dealt[..2]).is_err());
+    }
+
+    #[test]
+    fn share_files_round_trip_above_the_threshold_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = Keypair::new_from_array([42; 32]);
+        let paths = write_shares(&keypair, SplitSpec::parse("3/5").unwrap(), dir.path()).unwrap();
+        assert_eq!(paths.len(), 5);
+        #[cfg(unix)]
+        for path in &paths {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let recovered = combine(&[paths[4].clone(), paths[0].clone(), paths[2].clone()]).unwrap();
+        assert_eq!(recovered.secret_bytes(), keypair.secret_bytes());
+        assert_eq!(recovered.pubkey(), keypair.pubkey());
+        assert!(combine(&paths[..2]).unwrap_err().contains("3 of 5 shares are needed"));
+    }
+
+    #[test]
+    fn shares_from_different_splits_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = Keypair::new_from_array([42; 32]);
+        let three_of_five = write_shares(&keypair, SplitSpec::parse("3/5").unwrap(), dir.path()).unwrap();
+        let two_of_three = write_shares(&keypair, SplitSpec::parse("2/3").unwrap(), dir.path()).unwrap();
+
+        let mixed = [three_of_five[0].clone(), three_of_five[1].clone(), two_of_three[0].clone()];
+        let error = combine(&mixed).unwrap_err();
+        assert!(error.contains("is from a 2/3 split of") && error.contains("is from a 3/5 split"), "{error}");
+
+        // A share file whose count was edited no longer agrees with the rest
+        let contents = fs::read_to_string(&three_of_five[2]).unwrap().replace("\"shares\": 5", "\"shares\": 4");
+        fs::write(&three_of_five[2], contents).unwrap();
+        let error = combine(&three_of_five[..3]).unwrap_err();
+        assert!(error.contains("is from a 3/4 split of"), "{error}");
+    }
+
+    #[test]
+    fn share_files_with_an_impossible_split_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = write_shares(&Keypair::new(), SplitSpec::parse("2/2").unwrap(), dir.path()).unwrap();
+        for path in &paths {
+            let contents = fs::read_to_string(path).unwrap().replace("\"threshold\": 2", "\"threshold\": 1");
+            fs::write(path, contents).unwrap();
+        }
+        let error = combine(&paths).unwrap_err();
+        assert!(error.contains("threshold below 2"), "{error}");
+    }
+}
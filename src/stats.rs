@@ -0,0 +1,250 @@
+//! Attempt accounting and rate statistics for a grind run.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Window rates kept for the history chart and percentiles. Older windows
+/// are dropped, so a stream or daemon running for weeks holds a fixed
+/// amount; at the default progress interval this is over eleven hours.
+const MAX_RATE_WINDOWS: usize = 4_096;
+
+/// Collects attempt counts from the workers of a run.
+///
+/// Each worker owns a counter slot, so no single atomic is shared by every
+/// thread and totals are summed into a `u128` that cannot overflow however
+/// long the run. Rates are measured over windows between successive calls to
+/// [`StatsCollector::sample`] using the monotonic clock.
+#[derive(Debug)]
+pub struct StatsCollector {
+    workers: Vec<AtomicU64>,
+    start: Instant,
+    window: Mutex<Window>,
+}
+
+#[derive(Debug)]
+struct Window {
+    last_total: u128,
+    last_worker_attempts: Vec<u64>,
+    last_time: Instant,
+    /// Rates of the most recent `MAX_RATE_WINDOWS` windows, oldest first
+    rates: VecDeque<f64>,
+}
+
+/// Attempt totals and rates for the window ending at the time of sampling.
+#[derive(Debug, Clone)]
+pub struct RateSample {
+    pub total_attempts: u128,
+    pub rate: f64,
+    /// Rate of each worker over the window, indexed by worker id
+    pub worker_rates: Vec<f64>,
+}
+
+/// Final statistics for a run.
+#[derive(Debug, Clone)]
+pub struct StatsSummary {
+    pub total_attempts: u128,
+    pub elapsed: Duration,
+    pub average_rate: f64,
+    /// 10th, 50th and 90th percentile of the most recent window rates, if
+    /// any windows were sampled.
+    pub rate_percentiles: Option<[f64; 3]>,
+    /// Attempts and average rate of each worker, indexed by worker id
+    pub workers: Vec<WorkerStats>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WorkerStats {
+    pub attempts: u64,
+    pub average_rate: f64,
+}
+
+impl StatsCollector {
+    pub fn new(num_workers: usize) -> StatsCollector {
+        let start = Instant::now();
+        StatsCollector {
+            workers: (0..num_workers).map(|_| AtomicU64::new(0)).collect(),
+            start,
+            window: Mutex::new(Window {
+                last_total: 0,
+                last_worker_attempts: vec![0; num_workers],
+                last_time: start,
+                rates: VecDeque::with_capacity(MAX_RATE_WINDOWS),
+            }),
+        }
+    }
+
+    /// Adds `attempts` to the counter of the given worker.
+    pub fn record_attempts(&self, worker: usize, attempts: u64) {
+        self.workers[worker].fetch_add(attempts, Ordering::Relaxed);
+    }
+
+    fn worker_attempts(&self) -> Vec<u64> {
+        self.workers.iter().map(|attempts| attempts.load(Ordering::Relaxed)).collect()
+    }
+
+    /// Time since the collector was created.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Closes the current rate window and starts a new one.
+    pub fn sample(&self) -> RateSample {
+        let worker_attempts = self.worker_attempts();
+        let total_attempts = worker_attempts.iter().copied().map(u128::from).sum();
+        let now = Instant::now();
+
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        let window_length = now.duration_since(window.last_time);
+        let total_rate = rate(total_attempts - window.last_total, window_length);
+        let worker_rates = worker_attempts
+            .iter()
+            .zip(&window.last_worker_attempts)
+            .map(|(&current, &last)| rate(u128::from(current - last), window_length))
+            .collect();
+        window.last_total = total_attempts;
+        window.last_worker_attempts = worker_attempts;
+        window.last_time = now;
+        if window.rates.len() == MAX_RATE_WINDOWS {
+            window.rates.pop_front();
+        }
+        window.rates.push_back(total_rate);
+
+        RateSample { total_attempts, rate: total_rate, worker_rates }
+    }
+
+    /// The rates of the windows kept, oldest first, averaged down to at
+    /// most `buckets` values so a long run still fits a chart.
+    pub fn rate_history(&self, buckets: usize) -> Vec<f64> {
+        let rates: Vec<f64> = self.window.lock().unwrap_or_else(PoisonError::into_inner).rates.iter().copied().collect();
+        if rates.len() <= buckets {
+            return rates;
+        }
+        (0..buckets)
+            .map(|bucket| {
+                let bucket_rates = &rates[bucket * rates.len() / buckets..(bucket + 1) * rates.len() / buckets];
+                bucket_rates.iter().sum::<f64>() / bucket_rates.len() as f64
+            })
+            .collect()
+    }
+
+    pub fn summary(&self) -> StatsSummary {
+        let worker_attempts = self.worker_attempts();
+        let total_attempts = worker_attempts.iter().copied().map(u128::from).sum();
+        let elapsed = self.start.elapsed();
+
+        let mut rates: Vec<f64> = self.window.lock().unwrap_or_else(PoisonError::into_inner).rates.iter().copied().collect();
+        rates.sort_by(f64::total_cmp);
+        let rate_percentiles = (!rates.is_empty())
+            .then(|| [percentile(&rates, 10), percentile(&rates, 50), percentile(&rates, 90)]);
+
+        StatsSummary {
+            total_attempts,
+            elapsed,
+            average_rate: rate(total_attempts, elapsed),
+            rate_percentiles,
+            workers: worker_attempts
+                .into_iter()
+                .map(|attempts| WorkerStats { attempts, average_rate: rate(u128::from(attempts), elapsed) })
+                .collect(),
+        }
+    }
+}
+
+/// Attempts per second, or zero if no time has elapsed.
+fn rate(attempts: u128, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds > 0.0 {
+        attempts as f64 / seconds
+    } else {
+        0.0
+    }
+}
+
+/// Formats per-worker rates as `#id rate` pairs, e.g. `#0 25012 | #1 24980`.
+pub fn format_worker_rates(rates: impl IntoIterator<Item = f64>) -> String {
+    rates
+        .into_iter()
+        .enumerate()
+        .map(|(worker, rate)| format!("#{worker} {rate:.0}"))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Draws rates as a line of block characters scaled from the lowest rate to
+/// the highest, so a sustained drop stands out, e.g. `▇█▇▇▅▃▃▂`.
+pub fn sparkline(rates: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let min = rates.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = rates.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    rates
+        .iter()
+        .map(|&rate| {
+            if max > min {
+                BLOCKS[((rate - min) / (max - min) * 7.0).round() as usize]
+            } else {
+                BLOCKS[3]
+            }
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of an already sorted, non-empty slice.
+fn percentile(sorted: &[f64], percent: usize) -> f64 {
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A collector whose window rates are `rates`, as if sampled in turn.
+    fn with_rates(rates: impl IntoIterator<Item = f64>) -> StatsCollector {
+        let stats = StatsCollector::new(1);
+        stats.window.lock().unwrap().rates.extend(rates);
+        stats
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let rates: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!([percentile(&rates, 10), percentile(&rates, 50), percentile(&rates, 90)], [1.0, 5.0, 9.0]);
+        assert_eq!(percentile(&rates, 100), 10.0);
+        assert_eq!([percentile(&[7.0], 0), percentile(&[7.0], 90)], [7.0, 7.0]);
+
+        let summary = with_rates([30.0, 10.0, 20.0]).summary();
+        assert_eq!(summary.rate_percentiles, Some([10.0, 20.0, 30.0]));
+        assert!(StatsCollector::new(1).summary().rate_percentiles.is_none());
+    }
+
+    #[test]
+    fn rate_history_averages_windows_into_buckets() {
+        let stats = with_rates((1..=8).map(f64::from));
+        assert_eq!(stats.rate_history(8), [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(stats.rate_history(4), [1.5, 3.5, 5.5, 7.5]);
+        assert_eq!(stats.rate_history(3), [1.5, 4.0, 7.0]);
+    }
+
+    #[test]
+    fn only_the_most_recent_windows_are_kept() {
+        let stats = StatsCollector::new(2);
+        for _ in 0..MAX_RATE_WINDOWS + 10 {
+            stats.record_attempts(1, 5);
+            stats.sample();
+        }
+        assert_eq!(stats.window.lock().unwrap().rates.len(), MAX_RATE_WINDOWS);
+        assert_eq!(stats.rate_history(MAX_RATE_WINDOWS * 2).len(), MAX_RATE_WINDOWS);
+        assert_eq!(stats.summary().total_attempts, 5 * (MAX_RATE_WINDOWS as u128 + 10));
+    }
+
+    #[test]
+    fn sparkline_scales_from_the_lowest_rate_to_the_highest() {
+        assert_eq!(sparkline(&[10.0, 80.0, 45.0, 10.0]), "▁█▅▁");
+        assert_eq!(sparkline(&[5.0, 5.0]), "▄▄");
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(format_worker_rates([25012.4, 24980.0]), "#0 25012 | #1 24980");
+    }
+}
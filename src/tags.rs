@@ -0,0 +1,132 @@
+//! Tags and metadata attached to the keys a search finds.
+//!
+//! `--tag launch-q3 --meta team=infra` labels every key a run saves, so a
+//! key can be traced back to its purpose long after the run. The labels go
+//! into the key's results database record and the run's email manifest,
+//! and into a `<pubkey>.meta.json` sidecar next to the key's files, which
+//! travels with them when they are copied or archived.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli_error::CliError;
+use crate::results::KeyRecord;
+
+/// Extension of the sidecar written next to a key's files.
+pub const SIDECAR_EXTENSION: &str = "meta.json";
+
+/// Whether `name` is usable as a tag or metadata key: letters, digits and
+/// `-_.:/`, so it reads the same in a file name, a CSV cell or a shell.
+fn is_label(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:/".contains(c))
+}
+
+/// The tags and metadata of a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyTags {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: BTreeMap<String, String>,
+}
+
+impl KeyTags {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.meta.is_empty()
+    }
+
+    /// Adds the tag given with `--tag`. Repeating a tag is harmless.
+    pub fn add_tag(&mut self, tag: &str) -> Result<(), String> {
+        if !is_label(tag) {
+            return Err(format!("Invalid tag '{tag}': use letters, digits and -_.:/"));
+        }
+        if !self.tags.iter().any(|existing| existing == tag) {
+            self.tags.push(tag.to_string());
+        }
+        Ok(())
+    }
+
+    /// Adds the `KEY=VALUE` pair given with `--meta`.
+    pub fn add_meta(&mut self, pair: &str) -> Result<(), String> {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid metadata '{pair}': expected KEY=VALUE"))?;
+        if !is_label(key) {
+            return Err(format!("Invalid metadata key '{key}': use letters, digits and -_.:/"));
+        }
+        if self.meta.contains_key(key) {
+            return Err(format!("Metadata key '{key}' is given more than once"));
+        }
+        self.meta.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// The tags and metadata on one line, for display.
+    pub fn describe(&self) -> String {
+        self.tags
+            .iter()
+            .cloned()
+            .chain(self.meta.iter().map(|(key, value)| format!("{key}={value}")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Writes `record` as the sidecar of its key in `dir`, replacing any
+/// earlier one. Returns the sidecar's path.
+pub fn write_sidecar(dir: &Path, record: &KeyRecord) -> Result<PathBuf, CliError> {
+    let path = dir.join(format!("{}.{SIDECAR_EXTENSION}", record.pubkey));
+    let json = serde_json::to_string_pretty(record).expect("key records serialize to JSON");
+    fs::write(&path, format!("{json}\n")).map_err(|source| CliError::Sidecar { path: path.clone(), source })?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_and_metadata_are_checked() {
+        let mut tags = KeyTags::default();
+        assert!(tags.is_empty());
+        tags.add_tag("launch-q3").unwrap();
+        tags.add_tag("launch-q3").unwrap();
+        tags.add_tag("team/infra:v1.2").unwrap();
+        assert!(tags.add_tag("has space").unwrap_err().starts_with("Invalid tag 'has space'"));
+        assert!(tags.add_tag("").is_err());
+
+        tags.add_meta("owner=ops@example.com").unwrap();
+        tags.add_meta("note=a=b").unwrap();
+        assert!(tags.add_meta("owner=someone").unwrap_err().contains("given more than once"));
+        assert!(tags.add_meta("no-value").unwrap_err().contains("expected KEY=VALUE"));
+        assert!(tags.add_meta("bad key=1").unwrap_err().starts_with("Invalid metadata key 'bad key'"));
+
+        assert_eq!(tags.tags, ["launch-q3", "team/infra:v1.2"]);
+        assert_eq!(tags.describe(), "launch-q3, team/infra:v1.2, note=a=b, owner=ops@example.com");
+    }
+
+    #[test]
+    fn sidecars_hold_the_record_with_its_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let pubkey = "11111111111111111111111111111112".to_string();
+        let mut record = KeyRecord::new(pubkey.clone(), &[0; 32], "grind");
+        record.tags.add_tag("launch-q3").unwrap();
+        record.tags.add_meta("team=infra").unwrap();
+
+        let path = write_sidecar(dir.path(), &record).unwrap();
+        assert_eq!(path, dir.path().join(format!("{pubkey}.meta.json")));
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        // The labels sit at the top level, next to the rest of the record
+        assert_eq!(json["tags"], serde_json::json!(["launch-q3"]));
+        assert_eq!(json["meta"]["team"], "infra");
+        let read: KeyRecord = serde_json::from_value(json).unwrap();
+        assert_eq!((read.pubkey, read.tags), (pubkey, record.tags.clone()));
+
+        // Untagged records leave the labels out
+        record.tags = KeyTags::default();
+        let json = fs::read_to_string(write_sidecar(dir.path(), &record).unwrap()).unwrap();
+        assert!(!json.contains("\"tags\"") && !json.contains("\"meta\""), "{json}");
+    }
+}
@@ -0,0 +1,284 @@
+//! `grind --targets`: one search for a whole list of wanted prefixes.
+//!
+//! A list of base58 prefixes or full addresses, possibly millions of them,
+//! is checked in one pass. Each imm32 compatible candidate is checked
+//! against a bloom filter of the entries, so almost every candidate is
+//! rejected with a few bit lookups in memory. Only bloom filter hits go on
+//! to the exact index: the entries sorted into fixed-width records in a
+//! memory-mapped file and binary searched, so the full list lives in the
+//! page cache rather than on the heap.
+
+use memmap2::Mmap;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, RandomState};
+use std::io::{BufRead, BufReader, BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use doppler_keygen::pattern::VanityPattern;
+
+/// Longest base58 encoding of a 32-byte address, which is also the width of
+/// an index record.
+const MAX_ENTRY_LEN: usize = 44;
+
+/// False positive rate the bloom filter is sized for.
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// A fixed-size bloom filter over strings.
+struct Bloom {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+    hashers: (RandomState, RandomState),
+}
+
+impl Bloom {
+    fn new(num_items: usize) -> Bloom {
+        let ln2 = core::f64::consts::LN_2;
+        let num_bits = ((-(num_items.max(1) as f64) * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil() as u64).max(64);
+        let num_hashes = ((num_bits as f64 / num_items.max(1) as f64) * ln2).round().clamp(1.0, 16.0) as u32;
+        Bloom {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+            hashers: (RandomState::new(), RandomState::new()),
+        }
+    }
+
+    /// The bit positions for `item`, by double hashing.
+    fn positions(&self, item: &str) -> impl Iterator<Item = u64> + use<> {
+        let h1 = self.hashers.0.hash_one(item);
+        let h2 = self.hashers.1.hash_one(item) | 1;
+        let num_bits = self.num_bits;
+        (0..u64::from(self.num_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    fn insert(&mut self, item: &str) {
+        for position in self.positions(item) {
+            self.bits[(position / 64) as usize] |= 1 << (position % 64);
+        }
+    }
+
+    fn may_contain(&self, item: &str) -> bool {
+        self.positions(item).all(|position| self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0)
+    }
+}
+
+/// An entry padded with zeros to the record width. No base58 character is
+/// zero, so records sort in the same order as the entries.
+type Record = [u8; MAX_ENTRY_LEN];
+
+fn record(entry: &str) -> Record {
+    let mut record = [0; MAX_ENTRY_LEN];
+    record[..entry.len()].copy_from_slice(entry.as_bytes());
+    record
+}
+
+/// The entries as sorted records in a memory-mapped file.
+struct ExactIndex {
+    records: Mmap,
+    /// Dropped after `records`, so the file is unmapped before it is removed
+    _file: RemoveOnDrop,
+}
+
+/// Removes a file when dropped.
+struct RemoveOnDrop(PathBuf);
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+impl ExactIndex {
+    /// Writes `records` in order to a file in the temporary directory and
+    /// maps it. On Unix the file is removed as soon as it is mapped, so it
+    /// goes away with the process; elsewhere it is removed when the index is
+    /// dropped.
+    fn build(mut records: Vec<Record>) -> Result<ExactIndex, String> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        records.sort_unstable();
+        let path = std::env::temp_dir()
+            .join(format!("doppler-targets-{}-{}.index", process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+        let index_error = |e: std::io::Error| format!("Failed to write the target index {}: {e}", path.display());
+
+        let file = File::options().read(true).write(true).create_new(true).open(&path).map_err(index_error)?;
+        let mut writer = BufWriter::new(&file);
+        records.iter().try_for_each(|record| writer.write_all(record)).map_err(index_error)?;
+        writer.flush().map_err(index_error)?;
+        drop(writer);
+        // SAFETY: the file was just created by this process under a unique
+        // name and is never written again, so the mapping can't change
+        // underneath the slices read from it
+        let mapped = unsafe { Mmap::map(&file) };
+        #[cfg(unix)]
+        let _ = fs::remove_file(&path);
+        let records = mapped.map_err(index_error)?;
+        Ok(ExactIndex { records, _file: RemoveOnDrop(path) })
+    }
+
+    fn len(&self) -> usize {
+        self.records.len() / MAX_ENTRY_LEN
+    }
+
+    fn contains(&self, entry: &str) -> bool {
+        let wanted = record(entry);
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let start = middle * MAX_ENTRY_LEN;
+            match self.records[start..start + MAX_ENTRY_LEN].cmp(&wanted[..]) {
+                core::cmp::Ordering::Less => low = middle + 1,
+                core::cmp::Ordering::Greater => high = middle,
+                core::cmp::Ordering::Equal => return true,
+            }
+        }
+        false
+    }
+}
+
+/// The wanted prefixes, indexed for matching candidate addresses.
+pub struct TargetSet {
+    bloom: Bloom,
+    exact: ExactIndex,
+    /// Distinct prefix lengths, shortest first
+    lengths: Vec<usize>,
+    /// Chance that a random address matches some entry
+    match_probability: f64,
+}
+
+impl TargetSet {
+    /// Loads one base58 prefix or address per line, skipping blank lines
+    /// and `#` comments.
+    pub fn load(path: &Path) -> Result<TargetSet, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+        let mut entries = HashSet::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+            let entry = line.trim();
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+            // Only the alphabet and length are checked: the full prefix
+            // check is too slow for millions of entries, and an impossible
+            // prefix just never matches
+            if entry.len() > MAX_ENTRY_LEN || bs58::decode(entry).into_vec().is_err() {
+                return Err(format!("{}:{}: '{entry}' is not a base58 prefix or address", path.display(), number + 1));
+            }
+            entries.insert(record(entry));
+        }
+        if entries.is_empty() {
+            return Err(format!("{} has no targets", path.display()));
+        }
+        TargetSet::new(entries.into_iter().collect())
+    }
+
+    fn new(records: Vec<Record>) -> Result<TargetSet, String> {
+        let mut bloom = Bloom::new(records.len());
+        let mut lengths = Vec::new();
+        let mut match_probability = 0.0;
+        for record in &records {
+            let length = record.iter().position(|&byte| byte == 0).unwrap_or(MAX_ENTRY_LEN);
+            // Records only ever hold base58 characters
+            let entry = core::str::from_utf8(&record[..length]).unwrap_or_default();
+            bloom.insert(entry);
+            if !lengths.contains(&length) {
+                lengths.push(length);
+            }
+            let pattern = VanityPattern { prefix: Some(entry.to_string()), suffix: None };
+            match_probability += pattern.match_probability(false);
+        }
+        lengths.sort_unstable();
+        Ok(TargetSet { bloom, exact: ExactIndex::build(records)?, lengths, match_probability: match_probability.min(1.0) })
+    }
+
+    pub fn len(&self) -> usize {
+        self.exact.len()
+    }
+
+    /// Returns the shortest entry `address` starts with, if any.
+    pub fn find<'a>(&self, address: &'a str) -> Option<&'a str> {
+        self.lengths
+            .iter()
+            .take_while(|&&length| length <= address.len())
+            .map(|&length| &address[..length])
+            .find(|prefix| self.bloom.may_contain(prefix) && self.exact.contains(prefix))
+    }
+
+    /// Chance that a random address matches some entry, for estimating how
+    /// long a search takes.
+    pub fn match_probability(&self) -> f64 {
+        self.match_probability
+    }
+}
+
+impl fmt::Debug for TargetSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TargetSet").field("len", &self.len()).field("lengths", &self.lengths).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(entries: &[&str]) -> TargetSet {
+        TargetSet::new(entries.iter().map(|entry| record(entry)).collect()).unwrap()
+    }
+
+    const ADDRESS: &str = "DopKey1111111111111111111111111111111111111";
+
+    #[test]
+    fn addresses_match_prefixes_and_full_addresses() {
+        let set = targets(&["abc", "Zz", ADDRESS]);
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.find("abcXYZ1111"), Some("abc"));
+        assert_eq!(set.find("ZzTop"), Some("Zz"));
+        assert_eq!(set.find(ADDRESS), Some(ADDRESS));
+        assert_eq!(set.find("abXYZ"), None);
+        assert_eq!(set.find("ab"), None);
+        // Matching is case-sensitive, like base58
+        assert_eq!(set.find("ABCxyz"), None);
+    }
+
+    #[test]
+    fn the_shortest_matching_entry_is_reported() {
+        let set = targets(&["abcd", "ab", "abc"]);
+        assert_eq!(set.find("abcdef"), Some("ab"));
+        assert_eq!(set.lengths, [2, 3, 4]);
+    }
+
+    #[test]
+    fn the_index_finds_every_entry_of_a_large_list() {
+        let entries: Vec<String> = (0..20_000u32).map(|i| bs58::encode(i.to_be_bytes()).into_string()).collect();
+        let set = TargetSet::new(entries.iter().map(|entry| record(entry)).collect()).unwrap();
+        for entry in &entries {
+            assert!(set.exact.contains(entry), "{entry}");
+        }
+        for i in 20_000..21_000u32 {
+            let entry = bs58::encode(i.to_be_bytes()).into_string();
+            assert!(!set.exact.contains(&entry), "{entry}");
+        }
+    }
+
+    #[test]
+    fn lists_are_checked_when_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wanted.txt");
+
+        fs::write(&path, "# team A\nabc\n\n  Zz  \nabc\n").unwrap();
+        let set = TargetSet::load(&path).unwrap();
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.find("Zzz"), Some("Zz"));
+
+        fs::write(&path, "abc\nab0\n").unwrap();
+        assert!(TargetSet::load(&path).err().unwrap().ends_with("wanted.txt:2: 'ab0' is not a base58 prefix or address"));
+        fs::write(&path, format!("{ADDRESS}11\n")).unwrap();
+        assert!(TargetSet::load(&path).is_err());
+        fs::write(&path, "# nothing yet\n").unwrap();
+        assert!(TargetSet::load(&path).err().unwrap().ends_with("has no targets"));
+    }
+}
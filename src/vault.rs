@@ -0,0 +1,204 @@
+//! HashiCorp Vault sink for found keys.
+//!
+//! With `--vault-path MOUNT/PREFIX` each found keypair is written to Vault's
+//! KV version 2 engine at `MOUNT/PREFIX/<pubkey>` instead of local files.
+//! Authentication follows the Vault CLI: `VAULT_TOKEN`, or an AppRole login
+//! with `VAULT_ROLE_ID` and `VAULT_SECRET_ID`.
+
+use serde::Deserialize;
+use serde_json::json;
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+use std::time::Duration;
+
+use crate::cli_error::CliError;
+use crate::config::env_var;
+
+/// How long a single request to Vault may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A KV v2 location that found keypairs are written to.
+#[derive(Debug)]
+pub struct VaultSink {
+    agent: ureq::Agent,
+    addr: String,
+    mount: String,
+    prefix: String,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    auth: LoginAuth,
+}
+
+#[derive(Deserialize)]
+struct LoginAuth {
+    client_token: String,
+}
+
+impl VaultSink {
+    /// Authenticates against the Vault server at `addr` and returns a sink
+    /// writing under `path`, given as `MOUNT/PREFIX`.
+    pub fn connect(addr: &str, path: &str) -> Result<VaultSink, CliError> {
+        let path = path.trim_matches('/');
+        let (mount, prefix) = path.split_once('/').unwrap_or((path, ""));
+        if mount.is_empty() {
+            return Err(CliError::Vault("--vault-path must name a KV mount, e.g. secret/doppler".to_string()));
+        }
+
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+        let addr = addr.trim_end_matches('/').to_string();
+        let token = match (env_var("VAULT_TOKEN"), env_var("VAULT_ROLE_ID"), env_var("VAULT_SECRET_ID")) {
+            (Some(token), _, _) => token,
+            (None, Some(role_id), Some(secret_id)) => {
+                let response: LoginResponse = agent
+                    .post(&format!("{addr}/v1/auth/approle/login"))
+                    .send_json(json!({ "role_id": role_id, "secret_id": secret_id }))
+                    .map_err(|e| request_error("AppRole login", e))?
+                    .into_json()
+                    .map_err(|e| CliError::Vault(format!("AppRole login returned an unexpected response: {e}")))?;
+                response.auth.client_token
+            }
+            _ => {
+                return Err(CliError::Vault(
+                    "set VAULT_TOKEN, or VAULT_ROLE_ID and VAULT_SECRET_ID for AppRole login".to_string(),
+                ))
+            }
+        };
+
+        Ok(VaultSink { agent, addr, mount: mount.to_string(), prefix: prefix.to_string(), token })
+    }
+
+    /// Writes `keypair` to Vault, refusing to overwrite an existing secret,
+    /// and returns the path it was written to. `segment` is the matched
+    /// imm32 segment, if the key has one.
+    pub fn store(&self, keypair: &Keypair, segment: Option<usize>) -> Result<String, CliError> {
+        let pubkey = keypair.pubkey().to_string();
+        let secret_path = match self.prefix.as_str() {
+            "" => pubkey.clone(),
+            prefix => format!("{prefix}/{pubkey}"),
+        };
+
+        // cas=0 only succeeds if nothing is stored at the path yet
+        self.agent
+            .post(&format!("{}/v1/{}/data/{secret_path}", self.addr, self.mount))
+            .set("X-Vault-Token", &self.token)
+            .send_json(json!({
+                "options": { "cas": 0 },
+                "data": {
+                    "pubkey": pubkey,
+                    "keypair": keypair.to_base58_string(),
+                    "segment": segment,
+                },
+            }))
+            .map_err(|e| request_error(&format!("writing {pubkey}"), e))?;
+
+        Ok(format!("{}/{secret_path}", self.mount))
+    }
+}
+
+/// Describes a failed request, including the errors Vault reported.
+fn request_error(action: &str, error: ureq::Error) -> CliError {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            let errors = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|body| body.get("errors").map(ToString::to_string))
+                .unwrap_or(body);
+            CliError::Vault(format!("{action} failed with HTTP {status}: {errors}"))
+        }
+        e => CliError::Vault(format!("{action} failed: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{self, Response};
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    const TOKEN: &str = "s.test-token";
+
+    type Secrets = Arc<Mutex<BTreeMap<String, serde_json::Value>>>;
+
+    /// Serves a KV v2 engine that takes writes made with `TOKEN` and, like
+    /// Vault, refuses a cas=0 write to a path that already holds a secret.
+    fn kv_server() -> (String, Secrets) {
+        let secrets = Secrets::default();
+        let stored = Arc::clone(&secrets);
+        let addr = http::serve(SocketAddr::from(([127, 0, 0, 1], 0)), "test-vault", move |request| {
+            if request.header("x-vault-token") != Some(TOKEN) {
+                return Response::json(403, &json!({ "errors": ["permission denied"] }));
+            }
+            let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+            let mut secrets = stored.lock().unwrap();
+            if body["options"]["cas"] == 0 && secrets.contains_key(&request.path) {
+                let error = "check-and-set parameter did not match the current version";
+                return Response::json(400, &json!({ "errors": [error] }));
+            }
+            secrets.insert(request.path.clone(), body["data"].clone());
+            Response::json(200, &json!({ "data": { "version": 1 } }))
+        })
+        .unwrap();
+        (format!("http://{addr}"), secrets)
+    }
+
+    fn sink(addr: &str, token: &str) -> VaultSink {
+        VaultSink {
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            addr: addr.to_string(),
+            mount: "secret".to_string(),
+            prefix: "doppler".to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    #[test]
+    fn stored_keypairs_read_back() {
+        let (addr, secrets) = kv_server();
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey().to_string();
+
+        let path = sink(&addr, TOKEN).store(&keypair, Some(3)).unwrap();
+        assert_eq!(path, format!("secret/doppler/{pubkey}"));
+
+        let secrets = secrets.lock().unwrap();
+        let data = &secrets[&format!("/v1/secret/data/doppler/{pubkey}")];
+        assert_eq!(data["pubkey"], pubkey);
+        assert_eq!(data["segment"], 3);
+        let stored = Keypair::from_base58_string(data["keypair"].as_str().unwrap());
+        assert_eq!(stored.to_bytes(), keypair.to_bytes());
+    }
+
+    #[test]
+    fn existing_secrets_are_not_overwritten() {
+        let (addr, _secrets) = kv_server();
+        let sink = sink(&addr, TOKEN);
+        let keypair = Keypair::new();
+        sink.store(&keypair, None).unwrap();
+
+        let error = sink.store(&keypair, None).unwrap_err().to_string();
+        assert!(error.contains("failed with HTTP 400") && error.contains("check-and-set"), "{error}");
+    }
+
+    #[test]
+    fn a_wrong_token_is_refused() {
+        let (addr, secrets) = kv_server();
+        let keypair = Keypair::new();
+
+        let error = sink(&addr, "s.wrong").store(&keypair, None).unwrap_err().to_string();
+        assert!(error.contains(&format!("writing {}", keypair.pubkey())), "{error}");
+        assert!(error.contains("HTTP 403: [\"permission denied\"]"), "{error}");
+        assert!(secrets.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_path_must_name_a_mount() {
+        let error = VaultSink::connect("http://127.0.0.1:8200", "/").unwrap_err().to_string();
+        assert!(error.contains("--vault-path must name a KV mount"), "{error}");
+    }
+}
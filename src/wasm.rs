@@ -0,0 +1,117 @@
+//! A single-threaded grinder for the browser.
+//!
+//! Keys are generated with the page's `crypto.getRandomValues` and never
+//! leave the machine. There are no threads or files here: the page drives
+//! the search in slices, either by calling `step` from its own loop or by
+//! passing a progress callback to `run`, and should do so from a Web Worker
+//! so the UI stays responsive.
+
+use ed25519_dalek::SigningKey;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use wasm_bindgen::prelude::*;
+
+use crate::imm32;
+use crate::pattern::VanityPattern;
+
+/// A matching key found by a `Grinder`.
+#[wasm_bindgen]
+pub struct FoundKey {
+    address: String,
+    segment: usize,
+    keypair_bytes: [u8; 64],
+}
+
+#[wasm_bindgen]
+impl FoundKey {
+    /// The base58 public key.
+    #[wasm_bindgen(getter)]
+    pub fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    /// The first segment (0-3) that forms a valid 32-bit immediate.
+    #[wasm_bindgen(getter)]
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    /// The 64-byte secret key followed by the public key, as used by
+    /// `solana-keygen`.
+    #[wasm_bindgen(getter, js_name = secretKey)]
+    pub fn secret_key(&self) -> Vec<u8> {
+        self.keypair_bytes.to_vec()
+    }
+
+    /// The keypair in `solana-keygen`'s JSON byte array format.
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> String {
+        let bytes: Vec<String> = self.keypair_bytes.iter().map(u8::to_string).collect();
+        format!("[{}]", bytes.join(","))
+    }
+}
+
+/// Searches for keys with an imm32 compatible segment, optionally also
+/// matching a vanity prefix and/or suffix.
+#[wasm_bindgen]
+pub struct Grinder {
+    pattern: VanityPattern,
+    ignore_case: bool,
+    rng: ChaCha20Rng,
+    attempts: u64,
+}
+
+#[wasm_bindgen]
+impl Grinder {
+    #[wasm_bindgen(constructor)]
+    pub fn new(prefix: Option<String>, suffix: Option<String>, ignore_case: bool) -> Result<Grinder, JsError> {
+        let pattern = VanityPattern { prefix, suffix };
+
+        let mut rng_seed = [0u8; 32];
+        getrandom::getrandom(&mut rng_seed).map_err(|e| JsError::new(&format!("No random source available: {e}")))?;
+
+        Ok(Grinder { pattern, ignore_case, rng: ChaCha20Rng::from_seed(rng_seed), attempts: 0 })
+    }
+
+    /// Total candidates tried so far.
+    #[wasm_bindgen(getter)]
+    pub fn attempts(&self) -> f64 {
+        self.attempts as f64
+    }
+
+    /// Tries up to `max_attempts` candidates, returning the first match.
+    pub fn step(&mut self, max_attempts: u32) -> Option<FoundKey> {
+        for _ in 0..max_attempts {
+            self.attempts += 1;
+
+            let mut seed = [0u8; 32];
+            self.rng.fill_bytes(&mut seed);
+            let signing_key = SigningKey::from_bytes(&seed);
+            let pubkey = signing_key.verifying_key().to_bytes();
+
+            let Some(segment) = (0..4).find(|&segment| imm32::segment_is_imm32_compatible(&pubkey, segment)) else {
+                continue;
+            };
+            let address = bs58::encode(pubkey).into_string();
+            if self.pattern.matches_with_case(&address, self.ignore_case) {
+                return Some(FoundKey { address, segment, keypair_bytes: signing_key.to_keypair_bytes() });
+            }
+        }
+        None
+    }
+
+    /// Searches in slices of `batch_size` candidates until a match is found,
+    /// calling `on_progress(attempts)` after each slice. The search stops
+    /// early if the callback returns `false`.
+    pub fn run(&mut self, batch_size: u32, on_progress: &js_sys::Function) -> Result<Option<FoundKey>, JsValue> {
+        loop {
+            if let Some(found) = self.step(batch_size) {
+                return Ok(Some(found));
+            }
+            let keep_going = on_progress.call1(&JsValue::NULL, &JsValue::from_f64(self.attempts()))?;
+            if keep_going == JsValue::FALSE {
+                return Ok(None);
+            }
+        }
+    }
+}
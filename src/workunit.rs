@@ -0,0 +1,366 @@
+//! `work-unit`: grinding work that can be checked after the fact.
+//!
+//! A coordinator issues a unit: a random seed, a range of candidate indices
+//! and the patterns to look for. Candidate `i` is the 32 bytes at position
+//! `32·i` of the ChaCha20 stream keyed by the seed, so anyone holding the
+//! unit can regenerate any candidate without the others.
+//!
+//! The worker grinds the range in chunks of `chunk_len` candidates and
+//! returns a proof: the SHA-256 of each chunk's public keys and the indices
+//! of its matches. The coordinator then regenerates a few chunks of its own
+//! choosing. A chunk whose hash is wrong, or that holds a match the proof
+//! left out, shows the worker claimed attempts it did not make. The chunks
+//! are picked only when the proof is checked, so a worker that skipped a
+//! fraction `f` of its chunks passes `n` samples with probability at most
+//! `(1 - f)^n`.
+//!
+//! Matches are reported by index rather than by secret key: whoever holds
+//! the unit can rebuild them, which also means the unit file is as secret as
+//! the keys it leads to.
+
+use ed25519_dalek::SigningKey;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_keypair::Keypair;
+use solana_signer::Signer as _;
+use std::collections::BTreeSet;
+use zeroize::Zeroizing;
+
+use doppler_keygen::imm32;
+use doppler_keygen::pattern::VanityPattern;
+
+/// Candidates per chunk unless the unit says otherwise.
+pub const DEFAULT_CHUNK_LEN: u64 = 16_384;
+
+/// Chunks regenerated by `verify` unless told otherwise.
+pub const DEFAULT_SAMPLES: usize = 16;
+
+/// A range of candidates to grind.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkUnit {
+    /// The ChaCha20 key the candidates are drawn from, in hex
+    pub seed: String,
+    /// Index of the first candidate
+    pub start: u64,
+    /// Number of candidates
+    pub count: u64,
+    /// Candidates per chunk, the granularity at which work is checked
+    pub chunk_len: u64,
+    /// Patterns a match must meet on top of imm32 compatibility; empty
+    /// accepts any imm32 compatible key
+    #[serde(default)]
+    pub patterns: Vec<VanityPattern>,
+    #[serde(default)]
+    pub ignore_case: bool,
+}
+
+/// A candidate the worker found to match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnitMatch {
+    pub index: u64,
+    pub address: String,
+    /// The first segment that forms a valid 32-bit immediate
+    pub segment: usize,
+}
+
+/// What a worker returns for a unit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proof {
+    /// SHA-256 of the unit, so a proof cannot be passed off for another
+    pub unit: String,
+    /// SHA-256 of each chunk's public keys, in hex
+    pub chunks: Vec<String>,
+    pub matches: Vec<UnitMatch>,
+}
+
+/// The outcome of checking a proof.
+#[derive(Debug)]
+pub struct Verification {
+    /// The chunks that were regenerated
+    pub sampled: Vec<usize>,
+    pub total_chunks: usize,
+    pub matches: usize,
+}
+
+/// Finds the segment of an imm32 mask that counts as a match, if any.
+type SegmentCheck = fn(u8) -> Option<usize>;
+
+/// One regenerated chunk: its hash and matches.
+struct Chunk {
+    hash: [u8; 32],
+    matches: Vec<UnitMatch>,
+}
+
+impl WorkUnit {
+    /// A unit with a fresh random seed.
+    pub fn issue(
+        start: u64,
+        count: u64,
+        chunk_len: u64,
+        patterns: Vec<VanityPattern>,
+        ignore_case: bool,
+    ) -> Result<WorkUnit, String> {
+        if count == 0 || chunk_len == 0 {
+            return Err("A work unit needs at least one candidate per chunk".to_string());
+        }
+        if start.checked_add(count).is_none() {
+            return Err(format!("A work unit of {count} candidates cannot start at {start}"));
+        }
+        for pattern in &patterns {
+            pattern.validate(ignore_case)?;
+        }
+        let mut seed = Zeroizing::new([0u8; 32]);
+        getrandom::getrandom(&mut *seed).map_err(|e| format!("OS random number generator failed: {e}"))?;
+        Ok(WorkUnit { seed: hex::encode(*seed), start, count, chunk_len, patterns, ignore_case })
+    }
+
+    /// Reads and checks a unit file.
+    pub fn from_json(contents: &[u8]) -> Result<WorkUnit, String> {
+        let unit: WorkUnit = serde_json::from_slice(contents).map_err(|e| format!("Not a work unit: {e}"))?;
+        unit.key()?;
+        if unit.count == 0 || unit.chunk_len == 0 || unit.start.checked_add(unit.count).is_none() {
+            return Err("The work unit's range is empty or out of bounds".to_string());
+        }
+        for pattern in &unit.patterns {
+            pattern.validate(unit.ignore_case)?;
+        }
+        Ok(unit)
+    }
+
+    fn key(&self) -> Result<Zeroizing<[u8; 32]>, String> {
+        let bytes = Zeroizing::new(hex::decode(&self.seed).map_err(|_| "The work unit's seed is not hex".to_string())?);
+        let key: [u8; 32] = bytes[..].try_into().map_err(|_| "The work unit's seed is not 32 bytes".to_string())?;
+        Ok(Zeroizing::new(key))
+    }
+
+    /// SHA-256 of the unit's JSON, in hex.
+    pub fn digest(&self) -> String {
+        hex::encode(Sha256::digest(serde_json::to_vec(self).expect("a work unit serializes")))
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.count.div_ceil(self.chunk_len) as usize
+    }
+
+    /// The keypair of candidate `index`.
+    pub fn keypair(&self, index: u64) -> Result<Keypair, String> {
+        let mut rng = ChaCha20Rng::from_seed(*self.key()?);
+        rng.set_word_pos(u128::from(index) * 8);
+        let mut seed = Zeroizing::new([0u8; Keypair::SECRET_KEY_LENGTH]);
+        rng.fill_bytes(&mut *seed);
+        Ok(Keypair::new_from_array(*seed))
+    }
+
+    fn is_match(&self, address: &str) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|pattern| pattern.matches_with_case(address, self.ignore_case))
+    }
+
+    /// Regenerates chunk `chunk` of the unit.
+    fn chunk(&self, key: &[u8; 32], chunk: usize, matching_segment: SegmentCheck) -> Chunk {
+        let first = self.start + chunk as u64 * self.chunk_len;
+        let len = self.chunk_len.min(self.start + self.count - first) as usize;
+        let mut rng = ChaCha20Rng::from_seed(*key);
+        rng.set_word_pos(u128::from(first) * 8);
+
+        let mut seed = Zeroizing::new([0u8; Keypair::SECRET_KEY_LENGTH]);
+        let mut pubkeys = vec![[0u8; 32]; len];
+        for pubkey in &mut pubkeys {
+            rng.fill_bytes(&mut *seed);
+            *pubkey = SigningKey::from_bytes(&seed).verifying_key().to_bytes();
+        }
+        let mut masks = vec![0u8; len];
+        imm32::imm32_masks(&pubkeys, &mut masks);
+
+        let mut hasher = Sha256::new();
+        let mut matches = Vec::new();
+        for (position, (pubkey, &mask)) in pubkeys.iter().zip(&masks).enumerate() {
+            hasher.update(pubkey);
+            let Some(segment) = matching_segment(mask) else {
+                continue;
+            };
+            let address = bs58::encode(pubkey).into_string();
+            if self.is_match(&address) {
+                matches.push(UnitMatch { index: first + position as u64, address, segment });
+            }
+        }
+        Chunk { hash: hasher.finalize().into(), matches }
+    }
+
+    /// Grinds the whole unit on the current rayon pool, calling `on_chunk`
+    /// with the number of chunks done after each one.
+    pub fn run(&self, on_chunk: impl Fn(usize) + Sync) -> Result<Proof, String> {
+        self.run_with(on_chunk, imm32::first_segment)
+    }
+
+    // Tests pass a segment check that accepts far more keys, since real
+    // imm32 matches are too rare to wait for
+    fn run_with(&self, on_chunk: impl Fn(usize) + Sync, matching_segment: SegmentCheck) -> Result<Proof, String> {
+        let key = self.key()?;
+        let done = std::sync::atomic::AtomicUsize::new(0);
+        let chunks: Vec<Chunk> = (0..self.chunk_count())
+            .into_par_iter()
+            .map(|chunk| {
+                let chunk = self.chunk(&key, chunk, matching_segment);
+                on_chunk(done.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1);
+                chunk
+            })
+            .collect();
+
+        Ok(Proof {
+            unit: self.digest(),
+            chunks: chunks.iter().map(|chunk| hex::encode(chunk.hash)).collect(),
+            matches: chunks.into_iter().flat_map(|chunk| chunk.matches).collect(),
+        })
+    }
+
+    /// Checks `proof` against `samples` chunks picked at random, and every
+    /// match it reports.
+    pub fn verify(&self, proof: &Proof, samples: usize) -> Result<Verification, String> {
+        self.verify_with(proof, samples, imm32::first_segment)
+    }
+
+    fn verify_with(&self, proof: &Proof, samples: usize, matching_segment: SegmentCheck) -> Result<Verification, String> {
+        if proof.unit != self.digest() {
+            return Err("The proof is for a different work unit".to_string());
+        }
+        let total_chunks = self.chunk_count();
+        if proof.chunks.len() != total_chunks {
+            return Err(format!("The proof has {} chunk hashes; the unit has {total_chunks} chunks", proof.chunks.len()));
+        }
+
+        let key = self.key()?;
+        let reported: BTreeSet<u64> = proof.matches.iter().map(|unit_match| unit_match.index).collect();
+        for unit_match in &proof.matches {
+            if !(self.start..self.start + self.count).contains(&unit_match.index) {
+                return Err(format!("Match {} is outside the unit", unit_match.index));
+            }
+            let address = self.keypair(unit_match.index)?.pubkey().to_bytes();
+            let mut mask = [0u8];
+            imm32::imm32_masks(&[address], &mut mask);
+            let address = bs58::encode(address).into_string();
+            if address != unit_match.address || matching_segment(mask[0]) != Some(unit_match.segment) || !self.is_match(&address) {
+                return Err(format!("Match {} is not {} or does not match", unit_match.index, unit_match.address));
+            }
+        }
+
+        let sampled = pick_chunks(total_chunks, samples)?;
+        for &chunk in &sampled {
+            let regenerated = self.chunk(&key, chunk, matching_segment);
+            if hex::encode(regenerated.hash) != proof.chunks[chunk] {
+                return Err(format!("Chunk {chunk} does not hash to what the proof claims; its keys were not generated"));
+            }
+            if let Some(missing) = regenerated.matches.iter().find(|unit_match| !reported.contains(&unit_match.index)) {
+                return Err(format!("Chunk {chunk} holds match {} ({}) that the proof leaves out", missing.index, missing.address));
+            }
+        }
+
+        Ok(Verification { sampled, total_chunks, matches: proof.matches.len() })
+    }
+}
+
+/// `samples` distinct chunks out of `total`, or all of them if there are
+/// no more than that, drawn from the OS so the worker cannot predict them.
+fn pick_chunks(total: usize, samples: usize) -> Result<Vec<usize>, String> {
+    if samples >= total {
+        return Ok((0..total).collect());
+    }
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| format!("OS random number generator failed: {e}"))?;
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    let mut picked = BTreeSet::new();
+    while picked.len() < samples {
+        picked.insert((rng.next_u64() % total as u64) as usize);
+    }
+    Ok(picked.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(count: u64) -> WorkUnit {
+        WorkUnit { seed: hex::encode([9u8; 32]), start: 1_000, count, chunk_len: 64, patterns: Vec::new(), ignore_case: false }
+    }
+
+    #[test]
+    fn candidates_come_from_their_place_in_the_stream() {
+        let unit = unit(10);
+        let mut rng = ChaCha20Rng::from_seed([9u8; 32]);
+        rng.set_word_pos(1_003 * 8);
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        assert_eq!(unit.keypair(1_003).unwrap().secret_bytes(), &seed);
+
+        // A chunk regenerated on its own hashes the same keys
+        let key = unit.key().unwrap();
+        let mut hasher = Sha256::new();
+        for index in 1_000..1_010 {
+            hasher.update(unit.keypair(index).unwrap().pubkey().to_bytes());
+        }
+        assert_eq!(unit.chunk(&key, 0, imm32::first_segment).hash, <[u8; 32]>::from(hasher.finalize()));
+    }
+
+    #[test]
+    fn an_honest_proof_passes() {
+        let unit = unit(2 * 64 + 5);
+        let proof = unit.run(|_| {}).unwrap();
+        assert_eq!(proof.chunks.len(), 3);
+        let verification = unit.verify(&proof, DEFAULT_SAMPLES).unwrap();
+        assert_eq!(verification.sampled, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn skipped_and_hidden_work_is_caught() {
+        // Every key ending in 'z' counts, so most chunks hold a match
+        let every_key = |_| Some(0);
+        let mut unit = unit(40 * 64);
+        unit.patterns = vec![VanityPattern { prefix: None, suffix: Some("z".to_string()) }];
+        let proof = unit.run_with(|_| {}, every_key).unwrap();
+        assert!(proof.matches.len() > 20);
+        assert!(proof.matches.iter().all(|unit_match| unit_match.address.ends_with('z')));
+        unit.verify_with(&proof, DEFAULT_SAMPLES, every_key).unwrap();
+
+        // Check every chunk, so the tampered one is always among them
+        let all = unit.chunk_count();
+        let mut skipped = proof.clone();
+        skipped.chunks[1] = hex::encode([0u8; 32]);
+        assert!(unit.verify_with(&skipped, all, every_key).unwrap_err().contains("Chunk 1 does not hash"));
+
+        let mut hidden = proof.clone();
+        let kept = hidden.matches.pop().unwrap();
+        assert!(unit.verify_with(&hidden, all, every_key).unwrap_err().contains(&kept.address));
+
+        let mut forged = proof.clone();
+        forged.matches[0].address = kept.address.clone();
+        assert!(unit.verify_with(&forged, all, every_key).is_err());
+
+        // Real imm32 checks reject the matches the loose check accepted
+        assert!(unit.verify(&proof, DEFAULT_SAMPLES).is_err());
+
+        let mut other = unit.clone();
+        other.start += 1;
+        assert!(other.verify(&proof, DEFAULT_SAMPLES).unwrap_err().contains("different work unit"));
+    }
+
+    #[test]
+    fn samples_are_distinct_chunks() {
+        let picked = pick_chunks(100, 16).unwrap();
+        assert_eq!(picked.len(), 16);
+        assert!(picked.iter().all(|&chunk| chunk < 100));
+        assert_eq!(pick_chunks(3, 16).unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn units_are_checked_when_read() {
+        let json = serde_json::to_vec(&unit(10)).unwrap();
+        assert_eq!(WorkUnit::from_json(&json).unwrap(), unit(10));
+        assert!(WorkUnit::from_json(&serde_json::to_vec(&unit(0)).unwrap()).is_err());
+        let mut short = unit(10);
+        short.seed = "abcd".to_string();
+        assert!(WorkUnit::from_json(&serde_json::to_vec(&short).unwrap()).is_err());
+        assert!(WorkUnit::issue(u64::MAX, 2, 64, Vec::new(), false).is_err());
+    }
+}